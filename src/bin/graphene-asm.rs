@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use graphene_jvm::vm::class::{assemble, disassemble, parse, write};
+
+const USAGE: &str = "usage: graphene-asm [-d] [files]
+
+Assembles each source file into a class file next to it, with the extension
+replaced by `.class`.
+
+options:
+  -d    disassemble class files to standard output instead";
+
+fn main() {
+    let mut disassembling = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("-d") => disassembling = true,
+            Some("-h" | "-help" | "--help") => {
+                println!("{USAGE}");
+                return;
+            }
+            Some(option) if option.starts_with('-') => {
+                eprintln!("error: unknown option: {option}");
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let result = if disassembling {
+            disassemble_file(&path)
+        } else {
+            assemble_file(&path)
+        };
+        if let Err(error) = result {
+            eprintln!("error: {}: {error}", path.display());
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn assemble_file(path: &Path) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let class =
+        assemble(&source).map_err(|error| format!("line {}: {:?}", error.line, error.kind))?;
+    let bytes = write(&class).map_err(|error| format!("{error:?}"))?;
+    std::fs::write(path.with_extension("class"), bytes).map_err(|error| error.to_string())
+}
+
+fn disassemble_file(path: &Path) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|error| error.to_string())?;
+    let class = parse(&bytes).map_err(|error| format!("{error:?}"))?;
+    print!("{}", disassemble(&class));
+    Ok(())
+}
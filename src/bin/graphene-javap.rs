@@ -0,0 +1,1034 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use graphene_jvm::string::JavaStr;
+use graphene_jvm::vm::class::{
+    parse, Class, ClassFlags, ClassTypeSignature, Code, ConstantIdx, ConstantPool, Entry, Field,
+    FieldFlags, FieldType, Instruction, Method, MethodFlags, ReferenceKind, TypeArgument,
+    TypeParameter, TypeSignature,
+};
+
+const USAGE: &str = "usage: graphene-javap [-c] [-l] [-p] [-v] [class files]
+
+options:
+  -c    disassemble the code of each method
+  -l    print line number and local variable tables
+  -p    show private members
+  -v    print additional information, such as the constant pool";
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Options {
+    code: bool,
+    lines: bool,
+    private: bool,
+    verbose: bool,
+}
+
+fn main() {
+    let mut options = Options::default();
+    let mut paths = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("-c") => options.code = true,
+            Some("-l") => options.lines = true,
+            Some("-p" | "-private") => options.private = true,
+            Some("-v" | "-verbose") => options.verbose = true,
+            Some("-h" | "-help" | "--help") => {
+                println!("{USAGE}");
+                return;
+            }
+            Some(option) if option.starts_with('-') => {
+                eprintln!("error: unknown option: {option}");
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    }
+
+    if options.verbose {
+        options.code = true;
+        options.lines = true;
+    }
+
+    let mut out = io::stdout().lock();
+    let mut failed = false;
+    for path in paths {
+        let class = match std::fs::read(&path) {
+            Ok(bytes) => match parse(&bytes) {
+                Ok(class) => class,
+                Err(error) => {
+                    eprintln!("error: failed to parse {}: {error:?}", path.display());
+                    failed = true;
+                    continue;
+                }
+            },
+            Err(error) => {
+                eprintln!("error: failed to read {}: {error}", path.display());
+                failed = true;
+                continue;
+            }
+        };
+
+        let result = print_class(&mut out, &class, &path, options);
+        if let Err(error) = result.and_then(|()| out.flush()) {
+            // The reader, such as `head`, has seen all it wants.
+            if error.kind() == io::ErrorKind::BrokenPipe {
+                break;
+            }
+            eprintln!("error: failed to write {}: {error}", path.display());
+            std::process::exit(1);
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn print_class(
+    out: &mut impl Write,
+    class: &Class,
+    path: &std::path::Path,
+    options: Options,
+) -> io::Result<()> {
+    let constants = class.constants();
+
+    if options.verbose {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        writeln!(out, "Classfile {}", path.display())?;
+        if let Some(source_file) = class.source_file() {
+            writeln!(out, "  Compiled from \"{source_file}\"")?;
+        }
+        writeln!(out, "{}", class_declaration(class))?;
+
+        let (major, minor) = class.version();
+        writeln!(out, "  minor version: {minor}")?;
+        writeln!(out, "  major version: {major}")?;
+        writeln!(
+            out,
+            "  flags: {}",
+            flags_list(class.flags().bits(), &class.flags().to_string())
+        )?;
+
+        let this_class = constant_idx(
+            constants,
+            |entry| matches!(entry, Entry::Class(name) if constants.get(*name).into_utf8() == class.name()),
+        );
+        if let Some(this_class) = this_class {
+            print_commented(
+                out,
+                "  ",
+                &format!("this_class: #{}", this_class.get()),
+                &resolve(constants, this_class),
+            )?;
+        }
+        if let Some(super_name) = class.super_name() {
+            let super_class = constant_idx(
+                constants,
+                |entry| matches!(entry, Entry::Class(name) if constants.get(*name).into_utf8() == super_name),
+            );
+            if let Some(super_class) = super_class {
+                print_commented(
+                    out,
+                    "  ",
+                    &format!("super_class: #{}", super_class.get()),
+                    &resolve(constants, super_class),
+                )?;
+            }
+        } else {
+            writeln!(out, "  super_class: #0")?;
+        }
+        writeln!(
+            out,
+            "  interfaces: {}, fields: {}, methods: {}",
+            class.interfaces().count(),
+            class.fields().len(),
+            class.methods().len()
+        )?;
+
+        writeln!(out, "Constant pool:")?;
+        for (idx, entry) in constants.iter() {
+            print_constant(out, constants, idx, entry)?;
+        }
+        writeln!(out, "{{")?;
+    } else {
+        if let Some(source_file) = class.source_file() {
+            writeln!(out, "Compiled from \"{source_file}\"")?;
+        }
+        writeln!(out, "{} {{", class_declaration(class))?;
+    }
+
+    let mut first = true;
+    let fields = class.fields().iter().filter(|field| {
+        options.private || field.flags() & FieldFlags::PRIVATE != FieldFlags::PRIVATE
+    });
+    for field in fields {
+        if !first && (options.verbose || options.code || options.lines) {
+            writeln!(out)?;
+        }
+        first = false;
+        print_field(out, class, field, options)?;
+    }
+
+    let methods = class.methods().iter().filter(|method| {
+        options.private || method.flags() & MethodFlags::PRIVATE != MethodFlags::PRIVATE
+    });
+    for method in methods {
+        if !first && (options.verbose || options.code || options.lines) {
+            writeln!(out)?;
+        }
+        first = false;
+        print_method(out, class, method, options)?;
+    }
+
+    writeln!(out, "}}")?;
+    if options.verbose {
+        if let Some(signature) = class.signature() {
+            writeln!(out, "Signature: {signature}")?;
+        }
+        if let Some(source_file) = class.source_file() {
+            writeln!(out, "SourceFile: \"{source_file}\"")?;
+        }
+    }
+    Ok(())
+}
+
+fn print_field(
+    out: &mut impl Write,
+    class: &Class,
+    field: &Field,
+    options: Options,
+) -> io::Result<()> {
+    let constants = class.constants();
+
+    let mut declaration = String::from("  ");
+    declaration.push_str(&field_modifiers(field.flags()));
+    if let Some(Ok(signature)) = field.parsed_signature(constants) {
+        declaration.push_str(&type_source(signature.field_type()));
+    } else {
+        declaration.push_str(&field.parsed_descriptor().source_name().to_string());
+    }
+    writeln!(out, "{declaration} {};", field.name(constants))?;
+
+    if options.verbose {
+        writeln!(out, "    descriptor: {}", field.descriptor(constants))?;
+        writeln!(
+            out,
+            "    flags: {}",
+            flags_list(field.flags().bits(), &field.flags().to_string())
+        )?;
+        if let Some(constant_value) = field.constant_value() {
+            writeln!(
+                out,
+                "    ConstantValue: {}",
+                typed_constant(constants, constant_value)
+            )?;
+        }
+        if let Some(signature) = field.signature(constants) {
+            writeln!(out, "    Signature: {signature}")?;
+        }
+    }
+    Ok(())
+}
+
+fn print_method(
+    out: &mut impl Write,
+    class: &Class,
+    method: &Method,
+    options: Options,
+) -> io::Result<()> {
+    let constants = class.constants();
+
+    writeln!(out, "  {};", method_declaration(class, method))?;
+    if options.verbose {
+        writeln!(out, "    descriptor: {}", method.descriptor(constants))?;
+        writeln!(
+            out,
+            "    flags: {}",
+            flags_list(method.flags().bits(), &method.flags().to_string())
+        )?;
+    }
+
+    if let Some(code) = method.bytecode() {
+        if options.code {
+            writeln!(out, "    Code:")?;
+            if options.verbose {
+                let is_static = method.flags() & MethodFlags::STATIC == MethodFlags::STATIC;
+                writeln!(
+                    out,
+                    "      stack={}, locals={}, args_size={}",
+                    code.max_stack(),
+                    code.max_locals(),
+                    method.parsed_descriptor().arg_slots(is_static)
+                )?;
+            }
+            print_code(out, class, code, options)?;
+        }
+
+        if options.lines {
+            print_tables(out, constants, code, options)?;
+        }
+    }
+
+    if options.verbose {
+        let exceptions = method.exceptions(constants).collect::<Vec<_>>();
+        if !exceptions.is_empty() {
+            writeln!(out, "    Exceptions:")?;
+            let exceptions = exceptions
+                .iter()
+                .map(|name| dotted(name))
+                .collect::<Vec<_>>();
+            writeln!(out, "      throws {}", exceptions.join(", "))?;
+        }
+        if let Some(signature) = method.signature(constants) {
+            writeln!(out, "    Signature: {signature}")?;
+        }
+    }
+    Ok(())
+}
+
+fn print_code(
+    out: &mut impl Write,
+    class: &Class,
+    code: &Code,
+    options: Options,
+) -> io::Result<()> {
+    let constants = class.constants();
+    let width = if options.verbose { 10 } else { 8 };
+
+    for (pc, instruction) in code.bytecode() {
+        let target = |offset: i32| pc.wrapping_add_signed(offset);
+        let prefix = format!("{pc:>width$}: ");
+
+        match instruction {
+            Instruction::tableswitch(switch) => {
+                writeln!(
+                    out,
+                    "{prefix}{:<13} {{ // {} to {}",
+                    "tableswitch",
+                    switch.low(),
+                    switch.high()
+                )?;
+                for (i, offset) in switch.offsets().enumerate() {
+                    let key = switch.low() + i as i32;
+                    writeln!(out, "{key:>entry$}: {}", target(offset), entry = width + 14)?;
+                }
+                writeln!(
+                    out,
+                    "{:>entry$}: {}",
+                    "default",
+                    target(switch.default()),
+                    entry = width + 14
+                )?;
+                writeln!(out, "{:>close$}", "}", close = width + 3)?;
+            }
+            Instruction::lookupswitch(switch) => {
+                writeln!(
+                    out,
+                    "{prefix}{:<13} {{ // {}",
+                    "lookupswitch",
+                    switch.pairs().count()
+                )?;
+                for (key, offset) in switch.pairs() {
+                    writeln!(out, "{key:>entry$}: {}", target(offset), entry = width + 14)?;
+                }
+                writeln!(
+                    out,
+                    "{:>entry$}: {}",
+                    "default",
+                    target(switch.default()),
+                    entry = width + 14
+                )?;
+                writeln!(out, "{:>close$}", "}", close = width + 3)?;
+            }
+            instruction => {
+                let (line, comment) = disassemble(class, pc, instruction);
+                if let Some(comment) = comment {
+                    writeln!(
+                        out,
+                        "{}",
+                        format!("{prefix}{line:<34}// {comment}").trim_end()
+                    )?;
+                } else {
+                    writeln!(out, "{prefix}{line}")?;
+                }
+            }
+        }
+    }
+
+    if options.verbose || !code.exception_table().is_empty() {
+        if code.exception_table().is_empty() {
+            return Ok(());
+        }
+        let indent = " ".repeat(width - 4);
+        writeln!(out, "{indent}Exception table:")?;
+        writeln!(out, "{indent}   from    to  target type")?;
+        for handler in code.exception_table() {
+            let catch_type = match handler.catch_type {
+                Some(catch_type) => format!("Class {}", resolve(constants, catch_type)),
+                None => String::from("any"),
+            };
+            writeln!(
+                out,
+                "{:>from$}{:>6}{:>6}   {catch_type}",
+                handler.start_pc,
+                handler.end_pc,
+                handler.handler_pc,
+                from = width + 4
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_tables(
+    out: &mut impl Write,
+    constants: &ConstantPool,
+    code: &Code,
+    options: Options,
+) -> io::Result<()> {
+    let indent = if options.verbose { "      " } else { "    " };
+    if !code.line_numbers().is_empty() {
+        writeln!(out, "{indent}LineNumberTable:")?;
+        for line_number in code.line_numbers() {
+            writeln!(
+                out,
+                "{indent}  line {}: {}",
+                line_number.line, line_number.start_pc
+            )?;
+        }
+    }
+
+    let tables = [
+        ("LocalVariableTable", code.local_variables()),
+        ("LocalVariableTypeTable", code.local_variable_types()),
+    ];
+    for (name, local_variables) in tables {
+        if local_variables.is_empty() || (!options.verbose && name == "LocalVariableTypeTable") {
+            continue;
+        }
+        writeln!(out, "{indent}{name}:")?;
+        writeln!(out, "{indent}  Start  Length  Slot  Name   Signature")?;
+        for local_variable in local_variables {
+            writeln!(
+                out,
+                "{:>start$}{:>8}{:>6}{:>6}   {}",
+                local_variable.start_pc,
+                local_variable.length,
+                local_variable.index,
+                constants.get(local_variable.name).into_utf8().to_string(),
+                constants.get(local_variable.descriptor).into_utf8(),
+                start = indent.len() + 7
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats a single instruction, returning its mnemonic and operands along
+/// with a comment which resolves any constant pool reference.
+fn disassemble(class: &Class, pc: u32, instruction: Instruction) -> (String, Option<String>) {
+    let constants = class.constants();
+    let target = |offset: i32| pc.wrapping_add_signed(offset);
+
+    let with_operand =
+        |name: &str, operand: &dyn std::fmt::Display| format!("{name:<13} {operand}");
+    let with_local = |name: &str, idx: u16| {
+        if idx <= 3 {
+            format!("{name}_{idx}")
+        } else {
+            with_operand(name, &idx)
+        }
+    };
+    let with_constant = |name: &str, idx: ConstantIdx, kind: &str| {
+        let line = with_operand(name, &format_args!("#{}", idx.get()));
+        let comment = format!("{kind} {}", describe_member(class, idx));
+        (line, Some(comment))
+    };
+    let with_class = |name: &str, idx: ConstantIdx| {
+        let line = with_operand(name, &format_args!("#{}", idx.get()));
+        (line, Some(format!("class {}", resolve(constants, idx))))
+    };
+
+    let line = match instruction {
+        Instruction::iconst(-1) => String::from("iconst_m1"),
+        Instruction::iconst(val @ 0..=5) => format!("iconst_{val}"),
+        Instruction::iconst(val) => with_operand("bipush", &val),
+        Instruction::lconst(val) => format!("lconst_{val}"),
+        Instruction::fconst(val) => format!("fconst_{}", val as i32),
+        Instruction::dconst(val) => format!("dconst_{}", val as i32),
+        Instruction::bipush(val) => with_operand("bipush", &val),
+        Instruction::sipush(val) => with_operand("sipush", &val),
+        Instruction::ldc(idx) => {
+            let name = match constants.get(idx) {
+                Entry::Long(_) | Entry::Double(_) => "ldc2_w",
+                _ if idx.get() > u8::MAX as u16 => "ldc_w",
+                _ => "ldc",
+            };
+            let line = with_operand(name, &format_args!("#{}", idx.get()));
+            return (line, Some(typed_constant(constants, idx)));
+        }
+
+        Instruction::iload(idx) => with_local("iload", idx),
+        Instruction::lload(idx) => with_local("lload", idx),
+        Instruction::fload(idx) => with_local("fload", idx),
+        Instruction::dload(idx) => with_local("dload", idx),
+        Instruction::aload(idx) => with_local("aload", idx),
+        Instruction::istore(idx) => with_local("istore", idx),
+        Instruction::lstore(idx) => with_local("lstore", idx),
+        Instruction::fstore(idx) => with_local("fstore", idx),
+        Instruction::dstore(idx) => with_local("dstore", idx),
+        Instruction::astore(idx) => with_local("astore", idx),
+
+        Instruction::fcmp(false) => String::from("fcmpl"),
+        Instruction::fcmp(true) => String::from("fcmpg"),
+        Instruction::dcmp(false) => String::from("dcmpl"),
+        Instruction::dcmp(true) => String::from("dcmpg"),
+        Instruction::if_eq(offset) => with_operand("ifeq", &target(offset as i32)),
+        Instruction::if_ne(offset) => with_operand("ifne", &target(offset as i32)),
+        Instruction::if_lt(offset) => with_operand("iflt", &target(offset as i32)),
+        Instruction::if_ge(offset) => with_operand("ifge", &target(offset as i32)),
+        Instruction::if_gt(offset) => with_operand("ifgt", &target(offset as i32)),
+        Instruction::if_le(offset) => with_operand("ifle", &target(offset as i32)),
+        Instruction::if_icmp_eq(offset) => with_operand("if_icmpeq", &target(offset as i32)),
+        Instruction::if_icmp_ne(offset) => with_operand("if_icmpne", &target(offset as i32)),
+        Instruction::if_icmp_lt(offset) => with_operand("if_icmplt", &target(offset as i32)),
+        Instruction::if_icmp_ge(offset) => with_operand("if_icmpge", &target(offset as i32)),
+        Instruction::if_icmp_gt(offset) => with_operand("if_icmpgt", &target(offset as i32)),
+        Instruction::if_icmp_le(offset) => with_operand("if_icmple", &target(offset as i32)),
+        Instruction::if_acmp_eq(offset) => with_operand("if_acmpeq", &target(offset as i32)),
+        Instruction::if_acmp_ne(offset) => with_operand("if_acmpne", &target(offset as i32)),
+        Instruction::ifnull(offset) => with_operand("ifnull", &target(offset as i32)),
+        Instruction::ifnonnull(offset) => with_operand("ifnonnull", &target(offset as i32)),
+
+        Instruction::goto(offset) if i16::try_from(offset).is_ok() => {
+            with_operand("goto", &target(offset))
+        }
+        Instruction::goto(offset) => with_operand("goto_w", &target(offset)),
+        Instruction::jsr(offset) if i16::try_from(offset).is_ok() => {
+            with_operand("jsr", &target(offset))
+        }
+        Instruction::jsr(offset) => with_operand("jsr_w", &target(offset)),
+        Instruction::ret(idx) => with_operand("ret", &idx),
+        Instruction::ret_void => String::from("return"),
+
+        Instruction::getstatic(idx) => return with_constant("getstatic", idx, "Field"),
+        Instruction::putstatic(idx) => return with_constant("putstatic", idx, "Field"),
+        Instruction::getfield(idx) => return with_constant("getfield", idx, "Field"),
+        Instruction::putfield(idx) => return with_constant("putfield", idx, "Field"),
+        Instruction::invokevirtual(idx) => return with_constant("invokevirtual", idx, "Method"),
+        Instruction::invokespecial(idx) => {
+            let kind = member_kind(constants, idx);
+            return with_constant("invokespecial", idx, kind);
+        }
+        Instruction::invokestatic(idx) => {
+            let kind = member_kind(constants, idx);
+            return with_constant("invokestatic", idx, kind);
+        }
+        Instruction::invokeinterface(idx, count) => {
+            let line = with_operand("invokeinterface", &format_args!("#{},  {count}", idx.get()));
+            let comment = format!("InterfaceMethod {}", describe_member(class, idx));
+            return (line, Some(comment));
+        }
+        Instruction::invokedynamic(idx) => {
+            let line = with_operand("invokedynamic", &format_args!("#{},  0", idx.get()));
+            let comment = format!("InvokeDynamic {}", resolve(constants, idx));
+            return (line, Some(comment));
+        }
+        Instruction::new(idx) => return with_class("new", idx),
+        Instruction::anewarray(idx) => return with_class("anewarray", idx),
+        Instruction::checkcast(idx) => return with_class("checkcast", idx),
+        Instruction::instanceof(idx) => return with_class("instanceof", idx),
+        Instruction::multianewarray(idx, dimensions) => {
+            let line = with_operand(
+                "multianewarray",
+                &format_args!("#{},  {dimensions}", idx.get()),
+            );
+            return (line, Some(format!("class {}", resolve(constants, idx))));
+        }
+        Instruction::newarray(kind) => {
+            let kind = format!("{kind:?}")
+                .to_lowercase()
+                .replace("bool", "boolean");
+            format!("{:<14} {kind}", "newarray")
+        }
+
+        Instruction::iinc(idx, constant) => {
+            with_operand("iinc", &format_args!("{idx}, {constant}"))
+        }
+
+        instruction => format!("{instruction:?}"),
+    };
+    (line, None)
+}
+
+fn print_constant(
+    out: &mut impl Write,
+    constants: &ConstantPool,
+    idx: ConstantIdx,
+    entry: &Entry,
+) -> io::Result<()> {
+    let (kind, args) = match entry {
+        Entry::Utf8(value) => ("Utf8", escape(value)),
+        Entry::Integer(value) => ("Integer", value.to_string()),
+        Entry::Float(value) => ("Float", format!("{value:?}f")),
+        Entry::Long(value) => ("Long", format!("{value}l")),
+        Entry::Double(value) => ("Double", format!("{value:?}d")),
+        Entry::Class(name) => ("Class", format!("#{}", name.get())),
+        Entry::String(value) => ("String", format!("#{}", value.get())),
+        Entry::FieldRef(class, name_type) => {
+            ("Fieldref", format!("#{}.#{}", class.get(), name_type.get()))
+        }
+        Entry::MethodRef(class, name_type) => (
+            "Methodref",
+            format!("#{}.#{}", class.get(), name_type.get()),
+        ),
+        Entry::InterfaceMethodRef(class, name_type) => (
+            "InterfaceMethodref",
+            format!("#{}.#{}", class.get(), name_type.get()),
+        ),
+        Entry::NameType(name, descriptor) => (
+            "NameAndType",
+            format!("#{}:#{}", name.get(), descriptor.get()),
+        ),
+        Entry::MethodHandle(kind, reference) => (
+            "MethodHandle",
+            format!("{}:#{}", reference_kind(*kind).0, reference.get()),
+        ),
+        Entry::MethodType(descriptor) => ("MethodType", format!("#{}", descriptor.get())),
+        Entry::InvokeDynamic(bootstrap_method, name_type) => (
+            "InvokeDynamic",
+            format!("#{bootstrap_method}:#{}", name_type.get()),
+        ),
+        Entry::Dynamic(bootstrap_method, name_type) => (
+            "Dynamic",
+            format!("#{bootstrap_method}:#{}", name_type.get()),
+        ),
+        Entry::Module(name) => ("Module", format!("#{}", name.get())),
+        Entry::Package(name) => ("Package", format!("#{}", name.get())),
+    };
+
+    let idx_str = format!("#{}", idx.get());
+    match entry {
+        Entry::Utf8(_)
+        | Entry::Integer(_)
+        | Entry::Float(_)
+        | Entry::Long(_)
+        | Entry::Double(_) => {
+            writeln!(out, "{idx_str:>5} = {kind:<18} {args}")?;
+        }
+        Entry::MethodType(_) => {
+            writeln!(
+                out,
+                "{idx_str:>5} = {kind:<18} {args:<14} //  {}",
+                resolve(constants, idx)
+            )?;
+        }
+        _ => {
+            writeln!(
+                out,
+                "{idx_str:>5} = {kind:<18} {args:<14} // {}",
+                resolve(constants, idx)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a constant pool entry to a human readable form, following any
+/// references to other entries.
+fn resolve(constants: &ConstantPool, idx: ConstantIdx) -> String {
+    match constants.get(idx) {
+        Entry::Utf8(value) => escape(value),
+        Entry::Integer(value) => value.to_string(),
+        Entry::Float(value) => format!("{value:?}f"),
+        Entry::Long(value) => format!("{value}l"),
+        Entry::Double(value) => format!("{value:?}d"),
+        Entry::Class(name) => quoted(constants.get(*name).into_utf8()),
+        Entry::String(value) => escape(constants.get(*value).into_utf8()),
+        Entry::FieldRef(class, name_type)
+        | Entry::MethodRef(class, name_type)
+        | Entry::InterfaceMethodRef(class, name_type) => {
+            format!(
+                "{}.{}",
+                resolve(constants, *class),
+                resolve(constants, *name_type)
+            )
+        }
+        Entry::NameType(name, descriptor) => format!(
+            "{}:{}",
+            quoted(constants.get(*name).into_utf8()),
+            constants.get(*descriptor).into_utf8()
+        ),
+        Entry::MethodHandle(kind, reference) => {
+            format!(
+                "{} {}",
+                reference_kind(*kind).1,
+                resolve(constants, *reference)
+            )
+        }
+        Entry::MethodType(descriptor) => constants.get(*descriptor).into_utf8().to_string(),
+        Entry::InvokeDynamic(bootstrap_method, name_type)
+        | Entry::Dynamic(bootstrap_method, name_type) => {
+            format!("#{bootstrap_method}:{}", resolve(constants, *name_type))
+        }
+        Entry::Module(name) | Entry::Package(name) => constants.get(*name).into_utf8().to_string(),
+    }
+}
+
+/// Describes a field or method reference, omitting the class when the member
+/// belongs to `class` itself.
+fn describe_member(class: &Class, idx: ConstantIdx) -> String {
+    let constants = class.constants();
+    let (class_idx, name_type) = constants.get(idx).into_ref();
+    let class_name = constants
+        .get(constants.get(class_idx).into_class())
+        .into_utf8();
+    if class_name == class.name() {
+        resolve(constants, name_type)
+    } else {
+        resolve(constants, idx)
+    }
+}
+
+fn member_kind(constants: &ConstantPool, idx: ConstantIdx) -> &'static str {
+    match constants.get(idx) {
+        Entry::InterfaceMethodRef(..) => "InterfaceMethod",
+        _ => "Method",
+    }
+}
+
+/// Formats a loadable constant along with its type, as done for `ldc` and the
+/// `ConstantValue` attribute.
+fn typed_constant(constants: &ConstantPool, idx: ConstantIdx) -> String {
+    let kind = match constants.get(idx) {
+        Entry::Integer(_) => "int",
+        Entry::Float(_) => "float",
+        Entry::Long(_) => "long",
+        Entry::Double(_) => "double",
+        Entry::Class(_) => "class",
+        Entry::String(_) => "String",
+        Entry::MethodHandle(..) => "MethodHandle",
+        Entry::MethodType(_) => "MethodType",
+        _ => "",
+    };
+    format!("{kind} {}", resolve(constants, idx))
+}
+
+fn reference_kind(kind: ReferenceKind) -> (u8, &'static str) {
+    match kind {
+        ReferenceKind::GetField => (1, "REF_getField"),
+        ReferenceKind::GetStatic => (2, "REF_getStatic"),
+        ReferenceKind::PutField => (3, "REF_putField"),
+        ReferenceKind::PutStatic => (4, "REF_putStatic"),
+        ReferenceKind::InvokeVirtual => (5, "REF_invokeVirtual"),
+        ReferenceKind::InvokeStatic => (6, "REF_invokeStatic"),
+        ReferenceKind::InvokeSpecial => (7, "REF_invokeSpecial"),
+        ReferenceKind::NewInvokeSpecial => (8, "REF_newInvokeSpecial"),
+        ReferenceKind::InvokeInterface => (9, "REF_invokeInterface"),
+    }
+}
+
+fn constant_idx<F: Fn(&Entry) -> bool>(constants: &ConstantPool, f: F) -> Option<ConstantIdx> {
+    constants
+        .iter()
+        .find(|(_, entry)| f(entry))
+        .map(|(idx, _)| idx)
+}
+
+fn class_declaration(class: &Class) -> String {
+    let flags = class.flags();
+    let has = |flag: ClassFlags| flags & flag == flag;
+
+    let mut declaration = String::new();
+    if has(ClassFlags::PUBLIC) {
+        declaration.push_str("public ");
+    }
+    if has(ClassFlags::FINAL) {
+        declaration.push_str("final ");
+    }
+    if has(ClassFlags::INTERFACE) {
+        declaration.push_str("interface ");
+    } else {
+        if has(ClassFlags::ABSTRACT) {
+            declaration.push_str("abstract ");
+        }
+        declaration.push_str("class ");
+    }
+    declaration.push_str(&dotted(class.name()));
+
+    let (superclass, interfaces) = if let Some(Ok(signature)) = class.parsed_signature() {
+        declaration.push_str(&type_parameters_source(signature.type_parameters()));
+        let superclass = class_type_source(signature.superclass());
+        let interfaces = signature
+            .interfaces()
+            .iter()
+            .map(class_type_source)
+            .collect::<Vec<_>>();
+        (Some(superclass), interfaces)
+    } else {
+        let superclass = class.super_name().map(dotted);
+        let interfaces = class.interfaces().map(dotted).collect::<Vec<_>>();
+        (superclass, interfaces)
+    };
+
+    if has(ClassFlags::INTERFACE) {
+        if !interfaces.is_empty() {
+            declaration.push_str(" extends ");
+            declaration.push_str(&interfaces.join(", "));
+        }
+    } else {
+        if let Some(superclass) = superclass.filter(|name| name != "java.lang.Object") {
+            declaration.push_str(" extends ");
+            declaration.push_str(&superclass);
+        }
+        if !interfaces.is_empty() {
+            declaration.push_str(" implements ");
+            declaration.push_str(&interfaces.join(", "));
+        }
+    }
+    declaration
+}
+
+fn method_declaration(class: &Class, method: &Method) -> String {
+    let constants = class.constants();
+    let flags = method.flags();
+    let has = |flag: MethodFlags| flags & flag == flag;
+
+    let name = method.name(constants);
+    if name == "<clinit>" {
+        return String::from("static {}");
+    }
+
+    let mut declaration = String::new();
+    for (flag, modifier) in [
+        (MethodFlags::PUBLIC, "public "),
+        (MethodFlags::PRIVATE, "private "),
+        (MethodFlags::PROTECTED, "protected "),
+        (MethodFlags::STATIC, "static "),
+        (MethodFlags::FINAL, "final "),
+        (MethodFlags::SYNCHRONIZED, "synchronized "),
+        (MethodFlags::NATIVE, "native "),
+        (MethodFlags::ABSTRACT, "abstract "),
+        (MethodFlags::STRICT, "strictfp "),
+    ] {
+        if has(flag) {
+            declaration.push_str(modifier);
+        }
+    }
+
+    let (parameters, ret, throws) = if let Some(Ok(signature)) = method.parsed_signature(constants)
+    {
+        let type_parameters = type_parameters_source(signature.type_parameters());
+        if !type_parameters.is_empty() {
+            declaration.push_str(&type_parameters);
+            declaration.push(' ');
+        }
+
+        let parameters = signature.args().iter().map(type_source).collect::<Vec<_>>();
+        let ret = signature.result().map(type_source);
+        let mut throws = signature
+            .throws()
+            .iter()
+            .map(type_source)
+            .collect::<Vec<_>>();
+        if throws.is_empty() {
+            throws = method.exceptions(constants).map(dotted).collect();
+        }
+        (parameters, ret, throws)
+    } else {
+        let descriptor = method.parsed_descriptor();
+        let parameters = descriptor
+            .args()
+            .iter()
+            .map(|arg| arg.source_name().to_string())
+            .collect::<Vec<_>>();
+        let ret = descriptor.result().map(|ret| ret.source_name().to_string());
+        let throws = method.exceptions(constants).map(dotted).collect::<Vec<_>>();
+        (parameters, ret, throws)
+    };
+
+    let mut parameters = parameters;
+    if has(MethodFlags::VARARGS) {
+        if let Some(last) = parameters.last_mut() {
+            if let Some(component) = last.strip_suffix("[]") {
+                *last = format!("{component}...");
+            }
+        }
+    }
+
+    if name == "<init>" {
+        declaration.push_str(&dotted(class.name()));
+    } else {
+        declaration.push_str(ret.as_deref().unwrap_or("void"));
+        declaration.push(' ');
+        declaration.push_str(&name.to_string());
+    }
+    declaration.push('(');
+    declaration.push_str(&parameters.join(", "));
+    declaration.push(')');
+
+    if !throws.is_empty() {
+        declaration.push_str(" throws ");
+        declaration.push_str(&throws.join(", "));
+    }
+    declaration
+}
+
+fn field_modifiers(flags: FieldFlags) -> String {
+    let mut modifiers = String::new();
+    for (flag, modifier) in [
+        (FieldFlags::PUBLIC, "public "),
+        (FieldFlags::PRIVATE, "private "),
+        (FieldFlags::PROTECTED, "protected "),
+        (FieldFlags::STATIC, "static "),
+        (FieldFlags::FINAL, "final "),
+        (FieldFlags::VOLATILE, "volatile "),
+        (FieldFlags::TRANSIENT, "transient "),
+    ] {
+        if flags & flag == flag {
+            modifiers.push_str(modifier);
+        }
+    }
+    modifiers
+}
+
+fn type_parameters_source(type_parameters: &[TypeParameter]) -> String {
+    if type_parameters.is_empty() {
+        return String::new();
+    }
+
+    let type_parameters = type_parameters
+        .iter()
+        .map(|type_parameter| {
+            // An `Object` bound is implied, so it is left out like it would be
+            // in source code.
+            let bounds = type_parameter
+                .class_bound()
+                .into_iter()
+                .chain(type_parameter.interface_bounds())
+                .map(type_source)
+                .filter(|bound| bound != "java.lang.Object")
+                .collect::<Vec<_>>();
+            if bounds.is_empty() {
+                type_parameter.name().to_string()
+            } else {
+                format!("{} extends {}", type_parameter.name(), bounds.join(" & "))
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("<{}>", type_parameters.join(", "))
+}
+
+fn type_source(signature: &TypeSignature) -> String {
+    match signature {
+        TypeSignature::Byte => FieldType::Byte.source_name().to_string(),
+        TypeSignature::Short => FieldType::Short.source_name().to_string(),
+        TypeSignature::Int => FieldType::Int.source_name().to_string(),
+        TypeSignature::Long => FieldType::Long.source_name().to_string(),
+        TypeSignature::Float => FieldType::Float.source_name().to_string(),
+        TypeSignature::Double => FieldType::Double.source_name().to_string(),
+        TypeSignature::Char => FieldType::Char.source_name().to_string(),
+        TypeSignature::Bool => FieldType::Bool.source_name().to_string(),
+        TypeSignature::Class(class) => class_type_source(class),
+        TypeSignature::TypeVariable(name) => name.to_string(),
+        TypeSignature::Array(component) => format!("{}[]", type_source(component)),
+    }
+}
+
+fn class_type_source(signature: &ClassTypeSignature) -> String {
+    let segments = signature
+        .segments()
+        .iter()
+        .map(|segment| {
+            let name = dotted(segment.name());
+            if segment.type_arguments().is_empty() {
+                return name;
+            }
+
+            let type_arguments = segment
+                .type_arguments()
+                .iter()
+                .map(|type_argument| match type_argument {
+                    TypeArgument::Any => String::from("?"),
+                    TypeArgument::Exact(signature) => type_source(signature),
+                    TypeArgument::Extends(signature) => {
+                        format!("? extends {}", type_source(signature))
+                    }
+                    TypeArgument::Super(signature) => format!("? super {}", type_source(signature)),
+                })
+                .collect::<Vec<_>>();
+            format!("{name}<{}>", type_arguments.join(", "))
+        })
+        .collect::<Vec<_>>();
+    segments.join(".")
+}
+
+fn flags_list(bits: u16, names: &str) -> String {
+    let names = names
+        .split(" | ")
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("ACC_{name}"))
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        format!("(0x{bits:04x})")
+    } else {
+        format!("(0x{bits:04x}) {}", names.join(", "))
+    }
+}
+
+fn print_commented(
+    out: &mut impl Write,
+    indent: &str,
+    line: &str,
+    comment: &str,
+) -> io::Result<()> {
+    writeln!(out, "{indent}{line:<40}// {comment}")
+}
+
+/// Converts an internal binary name, such as `java/lang/String`, to the form
+/// used in source code, such as `java.lang.String`.
+fn dotted(name: &JavaStr) -> String {
+    name.to_string().replace('/', ".")
+}
+
+/// Quotes names which are not valid Java identifiers, such as `<init>` and
+/// array class names.
+fn quoted(name: &JavaStr) -> String {
+    let name = escape(name);
+    if name.starts_with('<') || name.starts_with('[') {
+        format!("\"{name}\"")
+    } else {
+        name
+    }
+}
+
+fn escape(value: &JavaStr) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match char::from_u32(c) {
+            Some('\n') => escaped.push_str("\\n"),
+            Some('\t') => escaped.push_str("\\t"),
+            Some('\r') => escaped.push_str("\\r"),
+            Some('\u{8}') => escaped.push_str("\\b"),
+            Some('\u{c}') => escaped.push_str("\\f"),
+            Some('\\') => escaped.push_str("\\\\"),
+            Some('"') => escaped.push_str("\\\""),
+            Some(c) if c.is_ascii() && !c.is_ascii_control() => escaped.push(c),
+            _ if c > 0xFFFF => {
+                let c = c - 0x10000;
+                let high = 0xD800 + (c >> 10);
+                let low = 0xDC00 + (c & 0x3FF);
+                escaped.push_str(&format!("\\u{high:04x}\\u{low:04x}"));
+            }
+            _ => escaped.push_str(&format!("\\u{c:04x}")),
+        }
+    }
+    escaped
+}
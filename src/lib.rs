@@ -1,5 +1,7 @@
 #![feature(debug_closure_helpers)]
+#![cfg_attr(feature = "jni", feature(c_variadic))]
 mod reader;
+mod writer;
 
 pub mod string;
 pub mod vm;
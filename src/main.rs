@@ -1,22 +1,63 @@
 use std::path::PathBuf;
 
 use graphene_jvm::string::from_utf8;
-use graphene_jvm::vm::{execute, ClassManager};
+use graphene_jvm::vm::heap::Collector;
+use graphene_jvm::vm::{execute_with, ClassManager, ExecuteOptions};
 
 fn main() {
-    let (class_manager, main_class) = if std::env::args().len() < 3 {
-        eprintln!("usage: graphene_jvm [class files] [main class]");
+    let mut options = ExecuteOptions::default();
+    let mut generational = false;
+    let mut nursery_size = None;
+    let mut library_path = Vec::new();
+    let args = std::env::args_os()
+        .skip(1)
+        .filter(|arg| {
+            let arg = arg.to_string_lossy();
+            match &*arg {
+                "-Xint" => options.tiered = false,
+                "-XX:+UseGenerationalGC" => generational = true,
+                "-verbose:gc" => options.verbose_gc = true,
+                #[cfg(feature = "jit")]
+                "-XX:-UseJIT" => options.jit = false,
+                _ => {
+                    if let Some(threshold) = arg.strip_prefix("-XX:CompileThreshold=") {
+                        options.compile_threshold =
+                            threshold.parse().expect("invalid compile threshold");
+                    } else if let Some(size) = arg.strip_prefix("-Xmx") {
+                        options.heap_size = parse_size(size).expect("invalid heap size");
+                    } else if let Some(size) = arg.strip_prefix("-Xmn") {
+                        nursery_size = Some(parse_size(size).expect("invalid nursery size"));
+                    } else if let Some(path) = arg.strip_prefix("-Djava.library.path=") {
+                        library_path = std::env::split_paths(path).collect();
+                    } else {
+                        return true;
+                    }
+                }
+            }
+            false
+        })
+        .collect::<Vec<_>>();
+    if generational {
+        options.collector = Collector::Generational {
+            nursery_size: nursery_size.unwrap_or(options.heap_size / 4),
+        };
+    }
+
+    let (class_manager, main_class) = if args.len() < 2 {
+        eprintln!(
+            "usage: graphene_jvm [-Xint] [-Xmx<size>] [-Xmn<size>] [-XX:+UseGenerationalGC] [-verbose:gc] [-Djava.library.path=<dirs>] [-XX:CompileThreshold=n] [class files] [main class]"
+        );
         return;
     } else {
-        let file_count = std::env::args_os().len();
-        let mut stack = std::env::args_os()
-            .skip(1)
-            .take(file_count - 2)
+        let mut stack = args[..args.len() - 1]
+            .iter()
             .map(PathBuf::from)
             .collect::<Vec<_>>();
-        let main_class = std::env::args().last().unwrap();
+        let main_class = args[args.len() - 1].to_str().unwrap().to_owned();
 
         let mut class_manager = ClassManager::new();
+        #[cfg(feature = "jni")]
+        class_manager.set_library_path(library_path);
         while let Some(entry) = stack.pop() {
             if entry.is_dir() {
                 for entry in std::fs::read_dir(entry).unwrap() {
@@ -35,5 +76,19 @@ fn main() {
     let main_class = from_utf8(main_class.as_str());
     println!("{}", main_class);
 
-    execute(&class_manager, &main_class);
+    if let Err(error) = execute_with(&class_manager, &main_class, options) {
+        eprintln!("Exception in thread \"main\" {error}");
+        std::process::exit(1);
+    }
+}
+
+/// Parses a number of bytes, which may end with `k`, `m` or `g`.
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, shift) = match size.as_bytes().last()?.to_ascii_lowercase() {
+        b'k' => (&size[..size.len() - 1], 10),
+        b'm' => (&size[..size.len() - 1], 20),
+        b'g' => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(1 << shift)
 }
@@ -27,7 +27,6 @@ impl Iterator for JavaChars<'_> {
     type Item = u32;
 
     #[inline]
-    #[must_use]
     fn next(&mut self) -> Option<Self::Item> {
         let (slice, code_point) = unsafe { next_code_point(self.slice) }?;
         self.slice = slice;
@@ -37,11 +36,7 @@ impl Iterator for JavaChars<'_> {
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
         let len = self.slice.len();
-        // `(len + 5` can't overflow, because we know that
-        // `slice::Iter` belongs to a slice in memory which has a
-        // maximum length of `isize::MAX` (which is well below
-        // `usize::MAX`).
-        ((len + 5) / 6, Some(len))
+        (len.div_ceil(6), Some(len))
     }
 
     #[inline]
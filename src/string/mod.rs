@@ -1,5 +1,6 @@
 mod iter;
 mod str;
+#[allow(clippy::module_inception)]
 mod string;
 
 pub mod macros;
@@ -234,7 +235,7 @@ unsafe fn next_code_point(bytes: &[u8]) -> Option<(&[u8], u32)> {
     } else {
         // 3-byte characters
         let second = *bytes.get_unchecked(1);
-        let third = *bytes.get_unchecked(1);
+        let third = *bytes.get_unchecked(2);
         Some((
             &bytes[3..],
             ((first as u32 & 0x0F) << 12) | ((second as u32 & 0x3F) << 6) | (third as u32 & 0x3F),
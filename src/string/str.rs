@@ -130,12 +130,7 @@ impl JavaStr {
                     true
                 } else if b & 0xF0 == 0xE0 {
                     // Check whether this is the second part of a surrogate pair
-                    if self.bytes.len() - index > 3 && check_surrogate_index(&self.bytes, index - 3)
-                    {
-                        false
-                    } else {
-                        true
-                    }
+                    !(self.bytes.len() - index > 3 && check_surrogate_index(&self.bytes, index - 3))
                 } else {
                     false
                 }
@@ -222,7 +217,7 @@ impl JavaStr {
     /// Iteration over grapheme clusters may be what you actually want. This
     /// functionality is not provided by this crate.
     #[inline]
-    pub const fn chars(&self) -> JavaChars {
+    pub const fn chars(&self) -> JavaChars<'_> {
         JavaChars { slice: &self.bytes }
     }
 
@@ -89,7 +89,6 @@ impl JavaString {
 
 impl Default for JavaString {
     #[inline]
-    #[must_use]
     fn default() -> Self {
         Self::new()
     }
@@ -97,7 +96,6 @@ impl Default for JavaString {
 
 impl std::borrow::Borrow<JavaStr> for JavaString {
     #[inline]
-    #[must_use]
     fn borrow(&self) -> &JavaStr {
         self
     }
@@ -107,7 +105,6 @@ impl Deref for JavaString {
     type Target = JavaStr;
 
     #[inline]
-    #[must_use]
     fn deref(&self) -> &Self::Target {
         unsafe { JavaStr::from_java_unchecked(&self.vec) }
     }
@@ -115,7 +112,6 @@ impl Deref for JavaString {
 
 impl DerefMut for JavaString {
     #[inline]
-    #[must_use]
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { JavaStr::from_java_unchecked_mut(&mut self.vec) }
     }
@@ -0,0 +1,621 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::vm::class::{Code, ConstantIdx, ConstantPool, Instruction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfgError {
+    /// The jump at the given offset targets an offset which is not the start
+    /// of an instruction.
+    InvalidBranchTarget(u32),
+    /// The range or handler of an exception handler does not lie on
+    /// instruction boundaries.
+    InvalidHandler,
+    /// Execution can continue past the last instruction of the method, or the
+    /// method has no code at all.
+    FallsOffEnd,
+}
+
+/// Identifies a basic block within a [`ControlFlowGraph`]. Blocks are numbered
+/// in the order of their offsets, so the entry block is always
+/// [`BlockId::ENTRY`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub(super) usize);
+
+impl BlockId {
+    /// The block containing the first instruction of the method.
+    pub const ENTRY: Self = Self(0);
+
+    /// Returns the position of the block in [`ControlFlowGraph::blocks`].
+    pub const fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Execution continues with the following instruction, either because
+    /// the block does not end in a jump or because a conditional jump is not
+    /// taken.
+    FallThrough,
+    /// A conditional jump is taken.
+    Branch,
+    Goto,
+    /// One of the targets of a `tableswitch` or `lookupswitch`, including its
+    /// default.
+    Switch,
+    /// A `jsr` to the start of a subroutine.
+    Jsr,
+    /// A `ret` from a subroutine to the instruction following one of the
+    /// `jsr`s which call it.
+    Ret,
+    /// An exception thrown within the block is caught by a handler, which
+    /// catches the given class, or every exception if it is `None`.
+    Exception(Option<ConstantIdx>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: BlockId,
+    pub kind: EdgeKind,
+}
+
+/// A sequence of instructions which is only entered at its first instruction
+/// and only left after its last one, or through an exception.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    start: u32,
+    end: u32,
+    successors: Vec<Edge>,
+    predecessors: Vec<BlockId>,
+}
+
+impl BasicBlock {
+    /// Returns the offset of the first instruction of the block.
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Returns the offset just past the last instruction of the block.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Returns the edges leaving the block. Normal edges come before
+    /// exception edges, which are in the order of the exception table.
+    pub fn successors(&self) -> &[Edge] {
+        &self.successors
+    }
+
+    /// Returns the blocks with an edge to this block, in the order of their
+    /// offsets.
+    pub fn predecessors(&self) -> &[BlockId] {
+        &self.predecessors
+    }
+
+    /// Returns the instructions of the block along with their offsets.
+    pub fn instructions<'a>(&self, code: &'a Code) -> impl Iterator<Item = (u32, Instruction<'a>)> {
+        let mut bytecode = code.bytecode();
+        bytecode.set_pc(self.start);
+        let end = self.end;
+        bytecode.take_while(move |(pc, _)| *pc < end)
+    }
+}
+
+/// The control-flow graph of the bytecode of a method, made up of basic
+/// blocks.
+///
+/// Blocks are split at jump targets, after jumps, returns and `athrow`, and
+/// at the bounds of exception handlers, so that every instruction of a block
+/// is covered by the same handlers. Each block covered by a handler has an
+/// exception edge to it.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+}
+
+/// How an instruction passes on control.
+struct Flow {
+    targets: Vec<(u32, EdgeKind)>,
+    falls_through: bool,
+}
+
+impl ControlFlowGraph {
+    /// Builds the control-flow graph of `code`.
+    ///
+    /// A `ret` is given an edge to the return site of every `jsr` which calls
+    /// a subroutine that the `ret` can be reached from.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CfgError`] if a jump or exception handler does not target
+    /// an instruction, or if execution can fall off the end of the code.
+    pub fn new(code: &Code) -> Result<Self, CfgError> {
+        let instructions: Vec<(u32, Instruction)> = code.bytecode().collect();
+        let length = code.bytecode.len() as u32;
+        let is_instruction = |pc: u32| {
+            instructions
+                .binary_search_by_key(&pc, |(pc, _)| *pc)
+                .is_ok()
+        };
+        if instructions.is_empty() {
+            return Err(CfgError::FallsOffEnd);
+        }
+
+        let mut leaders = BTreeSet::from([0]);
+        let mut flows = Vec::with_capacity(instructions.len());
+        for (i, (pc, instruction)) in instructions.iter().enumerate() {
+            let flow = flow(*pc, instruction);
+            for &(target, _) in &flow.targets {
+                if !is_instruction(target) {
+                    return Err(CfgError::InvalidBranchTarget(*pc));
+                }
+                leaders.insert(target);
+            }
+            if !flow.targets.is_empty() || !flow.falls_through {
+                if let Some((next, _)) = instructions.get(i + 1) {
+                    leaders.insert(*next);
+                }
+            }
+            flows.push(flow);
+        }
+        for handler in code.exception_table() {
+            let (start, end) = (handler.start_pc as u32, handler.end_pc as u32);
+            if start >= end
+                || !is_instruction(start)
+                || !is_instruction(handler.handler_pc as u32)
+                || !(is_instruction(end) || end == length)
+            {
+                return Err(CfgError::InvalidHandler);
+            }
+            leaders.extend([start, handler.handler_pc as u32]);
+            if end < length {
+                leaders.insert(end);
+            }
+        }
+
+        let starts: Vec<u32> = leaders.into_iter().collect();
+        let mut blocks: Vec<BasicBlock> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| BasicBlock {
+                start,
+                end: starts.get(i + 1).copied().unwrap_or(length),
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            })
+            .collect();
+        let block_at = |pc: u32| BlockId(starts.partition_point(|&start| start <= pc) - 1);
+
+        // The last instruction of a block decides where control goes next.
+        let mut last = 0;
+        for block in &mut blocks {
+            while instructions
+                .get(last + 1)
+                .is_some_and(|(pc, _)| *pc < block.end)
+            {
+                last += 1;
+            }
+            let flow = &flows[last];
+            for &(target, kind) in &flow.targets {
+                add_edge(&mut block.successors, block_at(target), kind);
+            }
+            if flow.falls_through {
+                if block.end == length {
+                    return Err(CfgError::FallsOffEnd);
+                }
+                add_edge(
+                    &mut block.successors,
+                    block_at(block.end),
+                    EdgeKind::FallThrough,
+                );
+            }
+            last += 1;
+        }
+
+        // Every subroutine returns to the instructions following the `jsr`s
+        // which call it.
+        let mut return_sites: BTreeMap<BlockId, Vec<BlockId>> = BTreeMap::new();
+        for (i, (_, instruction)) in instructions.iter().enumerate() {
+            if let Instruction::jsr(_) = instruction {
+                let entry = block_at(flows[i].targets[0].0);
+                if let Some((next, _)) = instructions.get(i + 1) {
+                    return_sites.entry(entry).or_default().push(block_at(*next));
+                }
+            }
+        }
+        for (&entry, sites) in &return_sites {
+            for ret in subroutine_rets(&blocks, &instructions, &return_sites, entry) {
+                for &site in sites {
+                    add_edge(&mut blocks[ret.0].successors, site, EdgeKind::Ret);
+                }
+            }
+        }
+
+        for block in &mut blocks {
+            for handler in code.exception_table() {
+                if (handler.start_pc as u32..handler.end_pc as u32).contains(&block.start) {
+                    let target = block_at(handler.handler_pc as u32);
+                    add_edge(
+                        &mut block.successors,
+                        target,
+                        EdgeKind::Exception(handler.catch_type),
+                    );
+                }
+            }
+        }
+
+        for i in 0..blocks.len() {
+            for j in 0..blocks[i].successors.len() {
+                let target = blocks[i].successors[j].target;
+                let predecessors = &mut blocks[target.0].predecessors;
+                if predecessors.last() != Some(&BlockId(i)) {
+                    predecessors.push(BlockId(i));
+                }
+            }
+        }
+
+        Ok(Self { blocks })
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+
+    /// Returns the block containing the instruction at `pc`.
+    pub fn block_at(&self, pc: u32) -> Option<BlockId> {
+        let index = self.blocks.partition_point(|block| block.start <= pc);
+        let block = self.blocks.get(index.checked_sub(1)?)?;
+        (pc < block.end).then_some(BlockId(index - 1))
+    }
+
+    /// Returns the blocks reachable from the entry in reverse postorder, in
+    /// which every block comes before its successors, apart from along back
+    /// edges.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::with_capacity(self.blocks.len());
+        // Each entry holds a block and the index of its next successor.
+        let mut stack = vec![(BlockId::ENTRY, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            let successors = &self.blocks[block.0].successors;
+            if let Some(edge) = successors.get(*next) {
+                *next += 1;
+                if !visited[edge.target.0] {
+                    visited[edge.target.0] = true;
+                    stack.push((edge.target, 0));
+                }
+            } else {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Computes the dominator tree of the graph.
+    pub fn dominators(&self) -> DominatorTree {
+        DominatorTree::new(self)
+    }
+
+    /// Finds the natural loops of the graph, which are formed by the back
+    /// edges to blocks that dominate their source. Back edges to the same
+    /// header form a single loop. Loops are sorted by their header, so an
+    /// outer loop comes before the loops nested in it unless they share a
+    /// header.
+    ///
+    /// Irreducible loops, which can be entered at more than one block, have no
+    /// back edges and are not found.
+    pub fn loops(&self, dominators: &DominatorTree) -> Vec<Loop> {
+        let mut latches: BTreeMap<BlockId, Vec<BlockId>> = BTreeMap::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                if dominators.dominates(edge.target, BlockId(i)) {
+                    latches.entry(edge.target).or_default().push(BlockId(i));
+                }
+            }
+        }
+
+        latches
+            .into_iter()
+            .map(|(header, latches)| {
+                let mut blocks = BTreeSet::from([header]);
+                let mut stack = latches.clone();
+                while let Some(block) = stack.pop() {
+                    if blocks.insert(block) {
+                        stack.extend(
+                            self.blocks[block.0]
+                                .predecessors
+                                .iter()
+                                .filter(|&&predecessor| dominators.is_reachable(predecessor)),
+                        );
+                    }
+                }
+                Loop {
+                    header,
+                    latches,
+                    blocks: blocks.into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the graph in the DOT format of Graphviz, labelling each block
+    /// with its instructions. Exception edges are dashed and labelled with the
+    /// class they catch.
+    pub fn to_dot(&self, code: &Code, constants: &ConstantPool) -> String {
+        let mut out = String::from("digraph cfg {\n");
+        out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for (pc, instruction) in block.instructions(code) {
+                let line = format!("{instruction:?}");
+                write!(label, "{pc}: {}\\l", escape(&line)).unwrap();
+            }
+            writeln!(out, "    b{i} [label=\"{label}\"];").unwrap();
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                let attributes = match edge.kind {
+                    EdgeKind::FallThrough | EdgeKind::Goto => String::new(),
+                    EdgeKind::Branch => " [label=\"branch\"]".to_owned(),
+                    EdgeKind::Switch => " [label=\"switch\"]".to_owned(),
+                    EdgeKind::Jsr => " [label=\"jsr\"]".to_owned(),
+                    EdgeKind::Ret => " [label=\"ret\", style=dotted]".to_owned(),
+                    EdgeKind::Exception(catch_type) => {
+                        let name = match catch_type {
+                            Some(class) => {
+                                let name = constants.get(class).into_class();
+                                constants.get(name).into_utf8().to_string()
+                            }
+                            None => "any".to_owned(),
+                        };
+                        format!(" [label=\"{}\", style=dashed]", escape(&name))
+                    }
+                };
+                writeln!(out, "    b{i} -> b{}{attributes};", edge.target.0).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Adds an edge unless the block already has an edge of the same sort to
+/// `target`.
+fn add_edge(successors: &mut Vec<Edge>, target: BlockId, kind: EdgeKind) {
+    let is_exception = |kind| matches!(kind, EdgeKind::Exception(_));
+    let exists = successors
+        .iter()
+        .any(|edge| edge.target == target && is_exception(edge.kind) == is_exception(kind));
+    if !exists {
+        successors.push(Edge { target, kind });
+    }
+}
+
+fn flow(pc: u32, instruction: &Instruction) -> Flow {
+    let target = |offset: i32| (pc as i64 + offset as i64) as u32;
+    let (targets, falls_through) = match *instruction {
+        Instruction::goto(offset) => (vec![(target(offset), EdgeKind::Goto)], false),
+        Instruction::jsr(offset) => (vec![(target(offset), EdgeKind::Jsr)], false),
+        Instruction::tableswitch(switch) => {
+            let mut targets: Vec<(u32, EdgeKind)> = switch
+                .offsets()
+                .map(|offset| (target(offset), EdgeKind::Switch))
+                .collect();
+            targets.push((target(switch.default()), EdgeKind::Switch));
+            (targets, false)
+        }
+        Instruction::lookupswitch(switch) => {
+            let mut targets: Vec<(u32, EdgeKind)> = switch
+                .pairs()
+                .map(|(_, offset)| (target(offset), EdgeKind::Switch))
+                .collect();
+            targets.push((target(switch.default()), EdgeKind::Switch));
+            (targets, false)
+        }
+        Instruction::ireturn
+        | Instruction::lreturn
+        | Instruction::freturn
+        | Instruction::dreturn
+        | Instruction::areturn
+        | Instruction::ret_void
+        | Instruction::athrow
+        | Instruction::ret(_) => (Vec::new(), false),
+        _ => match instruction.branch_offset() {
+            Some(offset) => (vec![(target(offset as i32), EdgeKind::Branch)], true),
+            None => (Vec::new(), true),
+        },
+    };
+    Flow {
+        targets,
+        falls_through,
+    }
+}
+
+/// Finds the blocks ending in a `ret` which can be reached from the
+/// subroutine starting at `entry`. Calls to other subroutines are stepped
+/// over to their return sites, so their `ret`s are not included.
+fn subroutine_rets(
+    blocks: &[BasicBlock],
+    instructions: &[(u32, Instruction)],
+    return_sites: &BTreeMap<BlockId, Vec<BlockId>>,
+    entry: BlockId,
+) -> Vec<BlockId> {
+    let mut visited = vec![false; blocks.len()];
+    let mut stack = vec![entry];
+    let mut rets = Vec::new();
+    while let Some(block) = stack.pop() {
+        if std::mem::replace(&mut visited[block.0], true) {
+            continue;
+        }
+        let end = blocks[block.0].end;
+        let last = instructions.partition_point(|(pc, _)| *pc < end) - 1;
+        if let (_, Instruction::ret(_)) = instructions[last] {
+            rets.push(block);
+        }
+        for edge in &blocks[block.0].successors {
+            match edge.kind {
+                EdgeKind::Jsr => {
+                    let next = instructions
+                        .get(last + 1)
+                        .map(|(pc, _)| *pc)
+                        .and_then(|pc| blocks.iter().position(|block| block.start == pc));
+                    if let Some(next) = next {
+                        if return_sites.contains_key(&edge.target) {
+                            stack.push(BlockId(next));
+                        }
+                    }
+                }
+                _ => stack.push(edge.target),
+            }
+        }
+    }
+    rets
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The dominator tree of a [`ControlFlowGraph`]. A block dominates another if
+/// every path from the entry to the other block passes through it.
+///
+/// Blocks which cannot be reached from the entry have no dominators.
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    /// The immediate dominator of every reachable block, where the entry is
+    /// its own immediate dominator.
+    idom: Vec<Option<BlockId>>,
+    children: Vec<Vec<BlockId>>,
+}
+
+impl DominatorTree {
+    /// Computes the dominators with the iterative algorithm of Cooper, Harvey
+    /// and Kennedy.
+    fn new(graph: &ControlFlowGraph) -> Self {
+        let order = graph.reverse_postorder();
+        let mut position = vec![usize::MAX; graph.blocks.len()];
+        for (i, block) in order.iter().enumerate() {
+            position[block.0] = i;
+        }
+
+        let mut idom = vec![None; graph.blocks.len()];
+        idom[0] = Some(BlockId::ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut new_idom = None;
+                for &predecessor in &graph.blocks[block.0].predecessors {
+                    if idom[predecessor.0].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => intersect(&idom, &position, predecessor, other),
+                    });
+                }
+                if new_idom.is_some() && idom[block.0] != new_idom {
+                    idom[block.0] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); graph.blocks.len()];
+        for (i, parent) in idom.iter().enumerate().skip(1) {
+            if let Some(parent) = parent {
+                children[parent.0].push(BlockId(i));
+            }
+        }
+        Self { idom, children }
+    }
+
+    /// Returns the closest strict dominator of `block`, or `None` for the
+    /// entry and unreachable blocks.
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0].filter(|_| block != BlockId::ENTRY)
+    }
+
+    /// Returns the blocks immediately dominated by `block`.
+    pub fn children(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block.0]
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.idom[block.0].is_some()
+    }
+
+    /// Returns `true` if `dominator` dominates `block`. Every reachable block
+    /// dominates itself.
+    pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        if !self.is_reachable(block) {
+            return false;
+        }
+        let mut current = block;
+        loop {
+            if current == dominator {
+                return true;
+            }
+            match self.immediate_dominator(current) {
+                Some(parent) => current = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+fn intersect(
+    idom: &[Option<BlockId>],
+    position: &[usize],
+    mut a: BlockId,
+    mut b: BlockId,
+) -> BlockId {
+    while a != b {
+        while position[a.0] > position[b.0] {
+            a = idom[a.0].unwrap();
+        }
+        while position[b.0] > position[a.0] {
+            b = idom[b.0].unwrap();
+        }
+    }
+    a
+}
+
+/// A natural loop, found by [`ControlFlowGraph::loops`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    header: BlockId,
+    latches: Vec<BlockId>,
+    blocks: Vec<BlockId>,
+}
+
+impl Loop {
+    /// Returns the block through which the loop is entered, which dominates
+    /// every block of the loop.
+    pub fn header(&self) -> BlockId {
+        self.header
+    }
+
+    /// Returns the blocks with a back edge to the header.
+    pub fn latches(&self) -> &[BlockId] {
+        &self.latches
+    }
+
+    /// Returns the blocks of the loop, including the header, sorted by their
+    /// offsets.
+    pub fn blocks(&self) -> &[BlockId] {
+        &self.blocks
+    }
+
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.binary_search(&block).is_ok()
+    }
+}
@@ -0,0 +1,648 @@
+use crate::vm::class::{
+    Code, ConstantIdx, ConstantPool, Entry, FieldType, Instruction, MethodDescriptor,
+};
+
+use super::{Analysis, Direction};
+
+/// The value of a local variable or operand stack slot, as far as it is known
+/// by [`ConstantPropagation`].
+///
+/// Values of type `long` and `double` take up two slots, where the second slot
+/// always holds [`Constant::Unknown`].
+#[derive(Debug, Clone, Copy)]
+pub enum Constant {
+    /// The slot may hold different values on different paths, or a value
+    /// which is not a constant.
+    Unknown,
+    Null,
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// The string constant at the given index of the constant pool.
+    String(ConstantIdx),
+}
+
+/// Floating-point constants are compared by their bits, so that `NaN` equals
+/// itself and `0.0` differs from `-0.0`.
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constant::Unknown, Constant::Unknown) | (Constant::Null, Constant::Null) => true,
+            (Constant::Int(a), Constant::Int(b)) => a == b,
+            (Constant::Long(a), Constant::Long(b)) => a == b,
+            (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+            (Constant::Double(a), Constant::Double(b)) => a.to_bits() == b.to_bits(),
+            (Constant::String(a), Constant::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Constant {}
+
+/// The values of the local variables and operand stack before an
+/// instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantFrame {
+    locals: Vec<Constant>,
+    stack: Vec<Constant>,
+}
+
+impl ConstantFrame {
+    pub fn locals(&self) -> &[Constant] {
+        &self.locals
+    }
+
+    /// Returns the values on the operand stack, from the bottom to the top.
+    pub fn stack(&self) -> &[Constant] {
+        &self.stack
+    }
+
+    fn push(&mut self, value: Constant) {
+        let is_wide = matches!(value, Constant::Long(_) | Constant::Double(_));
+        self.stack.push(value);
+        if is_wide {
+            self.stack.push(Constant::Unknown);
+        }
+    }
+
+    fn push_unknown(&mut self, slots: usize) {
+        for _ in 0..slots {
+            self.stack.push(Constant::Unknown);
+        }
+    }
+
+    fn pop(&mut self) -> Constant {
+        self.stack.pop().unwrap_or(Constant::Unknown)
+    }
+
+    /// Pops a value of type `long` or `double`.
+    fn pop_wide(&mut self) -> Constant {
+        self.pop();
+        self.pop()
+    }
+
+    fn pop_slots(&mut self, count: usize) {
+        for _ in 0..count {
+            self.pop();
+        }
+    }
+
+    fn load(&mut self, index: u16, size: usize) {
+        for slot in index as usize..index as usize + size {
+            let value = self.locals.get(slot).copied().unwrap_or(Constant::Unknown);
+            self.stack.push(value);
+        }
+    }
+
+    fn store(&mut self, index: u16, size: usize) {
+        let index = index as usize;
+        let mut values = vec![Constant::Unknown; size];
+        for value in values.iter_mut().rev() {
+            *value = self.pop();
+        }
+        if self.locals.len() < index + size {
+            self.locals.resize(index + size, Constant::Unknown);
+        }
+        // Overwriting the second slot of a wide value invalidates it.
+        if index > 0
+            && matches!(
+                self.locals[index - 1],
+                Constant::Long(_) | Constant::Double(_)
+            )
+        {
+            self.locals[index - 1] = Constant::Unknown;
+        }
+        self.locals[index..index + size].copy_from_slice(&values);
+    }
+
+    /// Pops the given number of slots and pushes them again in the order
+    /// given by `order`, where 0 is the slot which was on top.
+    fn shuffle(&mut self, count: usize, order: &[usize]) {
+        let mut popped = Vec::with_capacity(count);
+        for _ in 0..count {
+            popped.push(self.pop());
+        }
+        for &index in order {
+            self.stack.push(popped[index]);
+        }
+    }
+}
+
+/// Finds the local variables and operand stack slots which hold the same
+/// constant on every path to an instruction, folding arithmetic, conversions
+/// and comparisons of constants.
+///
+/// The values of parameters, fields, array elements and the results of
+/// method calls are unknown. The analysis assumes that the code passes
+/// verification.
+pub struct ConstantPropagation<'a> {
+    constants: &'a ConstantPool,
+    max_locals: usize,
+}
+
+impl<'a> ConstantPropagation<'a> {
+    pub fn new(code: &Code, constants: &'a ConstantPool) -> Self {
+        Self {
+            constants,
+            max_locals: code.max_locals as usize,
+        }
+    }
+
+    fn field_slots(&self, index: ConstantIdx) -> usize {
+        let (_, name_type) = self.constants.get(index).into_ref();
+        let (_, descriptor) = self.constants.get(name_type).into_name_type();
+        FieldType::parse(self.constants.get(descriptor).into_utf8())
+            .map_or(0, |field_type| field_type.slots())
+    }
+
+    /// Returns the number of slots taken by the arguments and the result of a
+    /// method with the descriptor in the given name and type entry.
+    fn invoke_slots(&self, name_type: ConstantIdx) -> (usize, usize) {
+        let (_, descriptor) = self.constants.get(name_type).into_name_type();
+        MethodDescriptor::parse(self.constants.get(descriptor).into_utf8()).map_or(
+            (0, 0),
+            |descriptor| {
+                let result = descriptor.result().map_or(0, FieldType::slots);
+                (descriptor.arg_slots(true), result)
+            },
+        )
+    }
+
+    fn execute(&self, frame: &mut ConstantFrame, instruction: &Instruction) {
+        use Constant::{Double, Float, Int, Long, Null, Unknown};
+
+        match *instruction {
+            // Constants
+            Instruction::nop => {}
+            Instruction::aconst_null => frame.push(Null),
+            Instruction::iconst(value) => frame.push(Int(value)),
+            Instruction::bipush(value) => frame.push(Int(value as i32)),
+            Instruction::sipush(value) => frame.push(Int(value as i32)),
+            Instruction::lconst(value) => frame.push(Long(value)),
+            Instruction::fconst(value) => frame.push(Float(value)),
+            Instruction::dconst(value) => frame.push(Double(value)),
+            Instruction::ldc(index) => match self.constants.get(index) {
+                Entry::Integer(value) => frame.push(Int(*value)),
+                Entry::Float(value) => frame.push(Float(*value)),
+                Entry::Long(value) => frame.push(Long(*value)),
+                Entry::Double(value) => frame.push(Double(*value)),
+                Entry::String(_) => frame.push(Constant::String(index)),
+                Entry::Dynamic(_, name_type) => {
+                    let (_, descriptor) = self.constants.get(*name_type).into_name_type();
+                    let slots = FieldType::parse(self.constants.get(descriptor).into_utf8())
+                        .map_or(1, |field_type| field_type.slots());
+                    frame.push_unknown(slots);
+                }
+                _ => frame.push(Unknown),
+            },
+
+            // Loads and stores
+            Instruction::iload(index) | Instruction::fload(index) | Instruction::aload(index) => {
+                frame.load(index, 1)
+            }
+            Instruction::lload(index) | Instruction::dload(index) => frame.load(index, 2),
+            Instruction::istore(index)
+            | Instruction::fstore(index)
+            | Instruction::astore(index) => frame.store(index, 1),
+            Instruction::lstore(index) | Instruction::dstore(index) => frame.store(index, 2),
+            Instruction::iinc(index, increment) => {
+                if let Some(local) = frame.locals.get_mut(index as usize) {
+                    *local = match *local {
+                        Int(value) => Int(value.wrapping_add(increment as i32)),
+                        _ => Unknown,
+                    };
+                }
+            }
+            Instruction::iaload
+            | Instruction::faload
+            | Instruction::aaload
+            | Instruction::baload
+            | Instruction::caload
+            | Instruction::saload => {
+                frame.pop_slots(2);
+                frame.push(Unknown);
+            }
+            Instruction::laload | Instruction::daload => {
+                frame.pop_slots(2);
+                frame.push_unknown(2);
+            }
+            Instruction::iastore
+            | Instruction::fastore
+            | Instruction::aastore
+            | Instruction::bastore
+            | Instruction::castore
+            | Instruction::sastore => frame.pop_slots(3),
+            Instruction::lastore | Instruction::dastore => frame.pop_slots(4),
+
+            // Stack
+            Instruction::pop => frame.pop_slots(1),
+            Instruction::pop2 => frame.pop_slots(2),
+            Instruction::dup => frame.shuffle(1, &[0, 0]),
+            Instruction::dup_x1 => frame.shuffle(2, &[0, 1, 0]),
+            Instruction::dup_x2 => frame.shuffle(3, &[0, 2, 1, 0]),
+            Instruction::dup2 => frame.shuffle(2, &[1, 0, 1, 0]),
+            Instruction::dup2_x1 => frame.shuffle(3, &[1, 0, 2, 1, 0]),
+            Instruction::dup2_x2 => frame.shuffle(4, &[1, 0, 3, 2, 1, 0]),
+            Instruction::swap => frame.shuffle(2, &[0, 1]),
+
+            // Math
+            Instruction::iadd
+            | Instruction::isub
+            | Instruction::imul
+            | Instruction::idiv
+            | Instruction::irem
+            | Instruction::ishl
+            | Instruction::ishr
+            | Instruction::iushr
+            | Instruction::iand
+            | Instruction::ior
+            | Instruction::ixor => {
+                let b = frame.pop();
+                let a = frame.pop();
+                let result = match (a, b) {
+                    (Int(a), Int(b)) => int_operation(instruction, a, b).map_or(Unknown, Int),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::ladd
+            | Instruction::lsub
+            | Instruction::lmul
+            | Instruction::ldiv
+            | Instruction::lrem
+            | Instruction::land
+            | Instruction::lor
+            | Instruction::lxor => {
+                let b = frame.pop_wide();
+                let a = frame.pop_wide();
+                let result = match (a, b) {
+                    (Long(a), Long(b)) => long_operation(instruction, a, b).map_or(Unknown, Long),
+                    _ => Unknown,
+                };
+                push_wide(frame, result);
+            }
+            Instruction::lshl | Instruction::lshr | Instruction::lushr => {
+                let b = frame.pop();
+                let a = frame.pop_wide();
+                let result = match (a, b) {
+                    (Long(a), Int(b)) => match instruction {
+                        Instruction::lshl => Long(a.wrapping_shl(b as u32)),
+                        Instruction::lshr => Long(a.wrapping_shr(b as u32)),
+                        _ => Long((a as u64).wrapping_shr(b as u32) as i64),
+                    },
+                    _ => Unknown,
+                };
+                push_wide(frame, result);
+            }
+            Instruction::fadd
+            | Instruction::fsub
+            | Instruction::fmul
+            | Instruction::fdiv
+            | Instruction::frem => {
+                let b = frame.pop();
+                let a = frame.pop();
+                let result = match (a, b) {
+                    (Float(a), Float(b)) => Float(match instruction {
+                        Instruction::fadd => a + b,
+                        Instruction::fsub => a - b,
+                        Instruction::fmul => a * b,
+                        Instruction::fdiv => a / b,
+                        _ => a % b,
+                    }),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::dadd
+            | Instruction::dsub
+            | Instruction::dmul
+            | Instruction::ddiv
+            | Instruction::drem => {
+                let b = frame.pop_wide();
+                let a = frame.pop_wide();
+                let result = match (a, b) {
+                    (Double(a), Double(b)) => Double(match instruction {
+                        Instruction::dadd => a + b,
+                        Instruction::dsub => a - b,
+                        Instruction::dmul => a * b,
+                        Instruction::ddiv => a / b,
+                        _ => a % b,
+                    }),
+                    _ => Unknown,
+                };
+                push_wide(frame, result);
+            }
+            Instruction::ineg => {
+                let result = match frame.pop() {
+                    Int(a) => Int(a.wrapping_neg()),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::lneg => {
+                let result = match frame.pop_wide() {
+                    Long(a) => Long(a.wrapping_neg()),
+                    _ => Unknown,
+                };
+                push_wide(frame, result);
+            }
+            Instruction::fneg => {
+                let result = match frame.pop() {
+                    Float(a) => Float(-a),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::dneg => {
+                let result = match frame.pop_wide() {
+                    Double(a) => Double(-a),
+                    _ => Unknown,
+                };
+                push_wide(frame, result);
+            }
+
+            // Conversions
+            Instruction::i2l
+            | Instruction::i2f
+            | Instruction::i2d
+            | Instruction::i2b
+            | Instruction::i2c
+            | Instruction::i2s => {
+                let value = frame.pop();
+                let result = match value {
+                    Int(a) => match instruction {
+                        Instruction::i2l => Long(a as i64),
+                        Instruction::i2f => Float(a as f32),
+                        Instruction::i2d => Double(a as f64),
+                        Instruction::i2b => Int(a as i8 as i32),
+                        Instruction::i2c => Int(a as u16 as i32),
+                        _ => Int(a as i16 as i32),
+                    },
+                    _ => Unknown,
+                };
+                push_converted(frame, instruction, result);
+            }
+            Instruction::l2i | Instruction::l2f | Instruction::l2d => {
+                let result = match frame.pop_wide() {
+                    Long(a) => match instruction {
+                        Instruction::l2i => Int(a as i32),
+                        Instruction::l2f => Float(a as f32),
+                        _ => Double(a as f64),
+                    },
+                    _ => Unknown,
+                };
+                push_converted(frame, instruction, result);
+            }
+            Instruction::f2i | Instruction::f2l | Instruction::f2d => {
+                // Casts in Rust saturate and turn NaN into 0, like in Java.
+                let result = match frame.pop() {
+                    Float(a) => match instruction {
+                        Instruction::f2i => Int(a as i32),
+                        Instruction::f2l => Long(a as i64),
+                        _ => Double(a as f64),
+                    },
+                    _ => Unknown,
+                };
+                push_converted(frame, instruction, result);
+            }
+            Instruction::d2i | Instruction::d2l | Instruction::d2f => {
+                let result = match frame.pop_wide() {
+                    Double(a) => match instruction {
+                        Instruction::d2i => Int(a as i32),
+                        Instruction::d2l => Long(a as i64),
+                        _ => Float(a as f32),
+                    },
+                    _ => Unknown,
+                };
+                push_converted(frame, instruction, result);
+            }
+
+            // Comparisons
+            Instruction::lcmp => {
+                let b = frame.pop_wide();
+                let a = frame.pop_wide();
+                let result = match (a, b) {
+                    (Long(a), Long(b)) => Int(a.cmp(&b) as i32),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::fcmp(greater) => {
+                let b = frame.pop();
+                let a = frame.pop();
+                let result = match (a, b) {
+                    (Float(a), Float(b)) => Int(compare(a.partial_cmp(&b), greater)),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::dcmp(greater) => {
+                let b = frame.pop_wide();
+                let a = frame.pop_wide();
+                let result = match (a, b) {
+                    (Double(a), Double(b)) => Int(compare(a.partial_cmp(&b), greater)),
+                    _ => Unknown,
+                };
+                frame.push(result);
+            }
+            Instruction::if_eq(_)
+            | Instruction::if_ne(_)
+            | Instruction::if_lt(_)
+            | Instruction::if_ge(_)
+            | Instruction::if_gt(_)
+            | Instruction::if_le(_)
+            | Instruction::ifnull(_)
+            | Instruction::ifnonnull(_) => frame.pop_slots(1),
+            Instruction::if_icmp_eq(_)
+            | Instruction::if_icmp_ne(_)
+            | Instruction::if_icmp_lt(_)
+            | Instruction::if_icmp_ge(_)
+            | Instruction::if_icmp_gt(_)
+            | Instruction::if_icmp_le(_)
+            | Instruction::if_acmp_eq(_)
+            | Instruction::if_acmp_ne(_) => frame.pop_slots(2),
+
+            // Control
+            Instruction::goto(_) | Instruction::ret(_) | Instruction::ret_void => {}
+            Instruction::jsr(_) => frame.push(Unknown),
+            Instruction::tableswitch(_)
+            | Instruction::lookupswitch(_)
+            | Instruction::ireturn
+            | Instruction::freturn
+            | Instruction::areturn
+            | Instruction::athrow => frame.pop_slots(1),
+            Instruction::lreturn | Instruction::dreturn => frame.pop_slots(2),
+
+            // References
+            Instruction::getstatic(index) => frame.push_unknown(self.field_slots(index)),
+            Instruction::putstatic(index) => frame.pop_slots(self.field_slots(index)),
+            Instruction::getfield(index) => {
+                frame.pop_slots(1);
+                frame.push_unknown(self.field_slots(index));
+            }
+            Instruction::putfield(index) => frame.pop_slots(self.field_slots(index) + 1),
+            Instruction::invokevirtual(index)
+            | Instruction::invokespecial(index)
+            | Instruction::invokeinterface(index, _) => {
+                let (_, name_type) = self.constants.get(index).into_ref();
+                let (arguments, result) = self.invoke_slots(name_type);
+                frame.pop_slots(arguments + 1);
+                frame.push_unknown(result);
+            }
+            Instruction::invokestatic(index) => {
+                let (_, name_type) = self.constants.get(index).into_ref();
+                let (arguments, result) = self.invoke_slots(name_type);
+                frame.pop_slots(arguments);
+                frame.push_unknown(result);
+            }
+            Instruction::invokedynamic(index) => {
+                let (_, name_type) = self.constants.get(index).into_invoke_dynamic();
+                let (arguments, result) = self.invoke_slots(name_type);
+                frame.pop_slots(arguments);
+                frame.push_unknown(result);
+            }
+            Instruction::new(_) => frame.push(Unknown),
+            Instruction::newarray(_)
+            | Instruction::anewarray(_)
+            | Instruction::arraylength
+            | Instruction::instanceof(_) => {
+                frame.pop_slots(1);
+                frame.push(Unknown);
+            }
+            // A cast leaves the value unchanged, and `null` passes every cast.
+            Instruction::checkcast(_) => {}
+            Instruction::monitorenter | Instruction::monitorexit => frame.pop_slots(1),
+
+            // Extended
+            Instruction::multianewarray(_, dimensions) => {
+                frame.pop_slots(dimensions as usize);
+                frame.push(Unknown);
+            }
+        }
+    }
+}
+
+/// Pushes the result of an operation on `long` or `double` values, which
+/// takes up two slots even if it is unknown.
+fn push_wide(frame: &mut ConstantFrame, result: Constant) {
+    frame.push(result);
+    if result == Constant::Unknown {
+        frame.push(Constant::Unknown);
+    }
+}
+
+/// Pushes the result of a conversion, which takes up two slots if the
+/// instruction converts to `long` or `double`.
+fn push_converted(frame: &mut ConstantFrame, instruction: &Instruction, result: Constant) {
+    let is_wide = matches!(
+        instruction,
+        Instruction::i2l
+            | Instruction::i2d
+            | Instruction::l2d
+            | Instruction::f2l
+            | Instruction::f2d
+            | Instruction::d2l
+    );
+    if is_wide {
+        push_wide(frame, result);
+    } else {
+        frame.push(result);
+    }
+}
+
+/// Folds an `int` operation, returning `None` if it throws an
+/// `ArithmeticException`.
+fn int_operation(instruction: &Instruction, a: i32, b: i32) -> Option<i32> {
+    Some(match instruction {
+        Instruction::iadd => a.wrapping_add(b),
+        Instruction::isub => a.wrapping_sub(b),
+        Instruction::imul => a.wrapping_mul(b),
+        Instruction::idiv => (b != 0).then(|| a.wrapping_div(b))?,
+        Instruction::irem => (b != 0).then(|| a.wrapping_rem(b))?,
+        Instruction::ishl => a.wrapping_shl(b as u32),
+        Instruction::ishr => a.wrapping_shr(b as u32),
+        Instruction::iushr => (a as u32).wrapping_shr(b as u32) as i32,
+        Instruction::iand => a & b,
+        Instruction::ior => a | b,
+        _ => a ^ b,
+    })
+}
+
+/// Folds a `long` operation, returning `None` if it throws an
+/// `ArithmeticException`.
+fn long_operation(instruction: &Instruction, a: i64, b: i64) -> Option<i64> {
+    Some(match instruction {
+        Instruction::ladd => a.wrapping_add(b),
+        Instruction::lsub => a.wrapping_sub(b),
+        Instruction::lmul => a.wrapping_mul(b),
+        Instruction::ldiv => (b != 0).then(|| a.wrapping_div(b))?,
+        Instruction::lrem => (b != 0).then(|| a.wrapping_rem(b))?,
+        Instruction::land => a & b,
+        Instruction::lor => a | b,
+        _ => a ^ b,
+    })
+}
+
+/// Returns the result of `fcmpg` or `dcmpg` if `greater` is set, and of
+/// `fcmpl` or `dcmpl` otherwise.
+fn compare(ordering: Option<std::cmp::Ordering>, greater: bool) -> i32 {
+    match ordering {
+        Some(ordering) => ordering as i32,
+        None if greater => 1,
+        None => -1,
+    }
+}
+
+impl Analysis for ConstantPropagation<'_> {
+    /// The frame before an instruction, or `None` if it has not been reached.
+    type Domain = Option<ConstantFrame>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self) -> Option<ConstantFrame> {
+        None
+    }
+
+    fn boundary(&self) -> Option<ConstantFrame> {
+        Some(ConstantFrame {
+            locals: vec![Constant::Unknown; self.max_locals],
+            stack: Vec::new(),
+        })
+    }
+
+    fn join(&self, state: &mut Option<ConstantFrame>, other: &Option<ConstantFrame>, _: u32) {
+        match (state.as_mut(), other) {
+            (_, None) => {}
+            (None, Some(_)) => *state = other.clone(),
+            (Some(frame), Some(other)) => {
+                if frame.locals.len() < other.locals.len() {
+                    frame.locals.resize(other.locals.len(), Constant::Unknown);
+                }
+                for (value, other) in frame.locals.iter_mut().zip(&other.locals) {
+                    if value != other {
+                        *value = Constant::Unknown;
+                    }
+                }
+                for (value, other) in frame.stack.iter_mut().zip(&other.stack) {
+                    if value != other {
+                        *value = Constant::Unknown;
+                    }
+                }
+            }
+        }
+    }
+
+    fn transfer(&self, state: &mut Option<ConstantFrame>, _: u32, instruction: &Instruction) {
+        if let Some(frame) = state {
+            self.execute(frame, instruction);
+        }
+    }
+
+    fn exception(&self, state: &mut Option<ConstantFrame>, _: Option<ConstantIdx>) {
+        if let Some(frame) = state {
+            frame.stack = vec![Constant::Unknown];
+        }
+    }
+}
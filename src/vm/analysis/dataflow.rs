@@ -0,0 +1,362 @@
+use std::collections::BTreeSet;
+
+use crate::vm::class::{Code, ConstantIdx, Instruction};
+
+use super::{BlockId, ControlFlowGraph, EdgeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// States flow from the entry of the method along the edges of the graph.
+    Forward,
+    /// States flow from the exits of the method against the edges of the
+    /// graph.
+    Backward,
+}
+
+/// A data-flow analysis, which computes a state at every instruction of a
+/// method by applying the effect of each instruction until a fixed point is
+/// reached.
+///
+/// The states must form a lattice of finite height, with [`bottom`] as its
+/// least element, and both [`join`] and [`transfer`] must be monotonic, so
+/// that [`solve`] terminates.
+///
+/// [`bottom`]: Analysis::bottom
+/// [`join`]: Analysis::join
+/// [`transfer`]: Analysis::transfer
+pub trait Analysis {
+    type Domain: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// Returns the state of a block which has not been reached yet.
+    fn bottom(&self) -> Self::Domain;
+
+    /// Returns the state at the entry of the method for a forward analysis,
+    /// or at every return, `athrow` and `ret` without a successor for a
+    /// backward analysis.
+    fn boundary(&self) -> Self::Domain;
+
+    /// Merges `other` into `state`, where two paths meet at the instruction
+    /// at `pc`.
+    fn join(&self, state: &mut Self::Domain, other: &Self::Domain, pc: u32);
+
+    /// Applies the effect of `instruction` at `pc` to `state`. A backward
+    /// analysis is given the state after the instruction and must turn it
+    /// into the state before it.
+    fn transfer(&self, state: &mut Self::Domain, pc: u32, instruction: &Instruction);
+
+    /// Adjusts the state flowing along an exception edge to a handler which
+    /// catches `catch_type`, or every exception if it is `None`. By default,
+    /// the state is left unchanged.
+    fn exception(&self, state: &mut Self::Domain, catch_type: Option<ConstantIdx>) {
+        let _ = (state, catch_type);
+    }
+}
+
+/// The states computed by [`solve`] at the bounds of every block.
+#[derive(Debug, Clone)]
+pub struct DataflowResults<D> {
+    before: Vec<D>,
+    after: Vec<D>,
+    /// For a backward analysis, the states of the handlers of every block,
+    /// which are merged into the state before each of its instructions.
+    exceptional: Vec<D>,
+}
+
+impl<D: Clone> DataflowResults<D> {
+    /// Returns the state before the first instruction of `block`.
+    pub fn before(&self, block: BlockId) -> &D {
+        &self.before[block.index()]
+    }
+
+    /// Returns the state after the last instruction of `block`.
+    pub fn after(&self, block: BlockId) -> &D {
+        &self.after[block.index()]
+    }
+
+    /// Calls `f` with the offset, the instruction, and the states before and
+    /// after it for every instruction of the method, in the order of their
+    /// offsets.
+    pub fn visit<A>(
+        &self,
+        analysis: &A,
+        graph: &ControlFlowGraph,
+        code: &Code,
+        mut f: impl FnMut(u32, &Instruction, &D, &D),
+    ) where
+        A: Analysis<Domain = D>,
+    {
+        for (index, block) in graph.blocks().iter().enumerate() {
+            let instructions: Vec<_> = block.instructions(code).collect();
+            let has_handlers = has_handlers(graph, BlockId(index));
+            match A::DIRECTION {
+                Direction::Forward => {
+                    let mut state = self.before[index].clone();
+                    for (pc, instruction) in &instructions {
+                        let before = state.clone();
+                        analysis.transfer(&mut state, *pc, instruction);
+                        f(*pc, instruction, &before, &state);
+                    }
+                }
+                Direction::Backward => {
+                    let mut states = Vec::with_capacity(instructions.len() + 1);
+                    let mut state = self.after[index].clone();
+                    states.push(state.clone());
+                    for (pc, instruction) in instructions.iter().rev() {
+                        analysis.transfer(&mut state, *pc, instruction);
+                        if has_handlers {
+                            analysis.join(&mut state, &self.exceptional[index], *pc);
+                        }
+                        states.push(state.clone());
+                    }
+                    states.reverse();
+                    for (i, (pc, instruction)) in instructions.iter().enumerate() {
+                        f(*pc, instruction, &states[i], &states[i + 1]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs `analysis` over the blocks of `graph` until the states reach a fixed
+/// point.
+///
+/// The state flowing along an exception edge is the join of the states
+/// before every instruction of the block, since any of them may throw. In a
+/// backward analysis the state of a handler is likewise merged into the state
+/// before every instruction the handler covers.
+///
+/// A forward analysis leaves the states of blocks which cannot be reached
+/// from the entry at [`Analysis::bottom`].
+pub fn solve<A: Analysis>(
+    analysis: &A,
+    graph: &ControlFlowGraph,
+    code: &Code,
+) -> DataflowResults<A::Domain> {
+    let blocks = graph.blocks();
+    let instructions: Vec<Vec<(u32, Instruction)>> = blocks
+        .iter()
+        .map(|block| block.instructions(code).collect())
+        .collect();
+    let mut results = DataflowResults {
+        before: vec![analysis.bottom(); blocks.len()],
+        after: vec![analysis.bottom(); blocks.len()],
+        exceptional: vec![analysis.bottom(); blocks.len()],
+    };
+
+    // Blocks are processed in reverse postorder for a forward analysis and in
+    // postorder for a backward one, so that most states are final before they
+    // are used.
+    let mut order = graph.reverse_postorder();
+    let mut worklist = BTreeSet::new();
+    match A::DIRECTION {
+        Direction::Forward => {
+            results.before[0] = analysis.boundary();
+            worklist.insert((0, BlockId::ENTRY));
+        }
+        Direction::Backward => {
+            let mut reachable = vec![false; blocks.len()];
+            for block in &order {
+                reachable[block.index()] = true;
+            }
+            order.reverse();
+            order.extend(
+                (0..blocks.len())
+                    .map(BlockId)
+                    .filter(|block| !reachable[block.0]),
+            );
+            for (index, block) in blocks.iter().enumerate() {
+                let is_exit = block
+                    .successors()
+                    .iter()
+                    .all(|edge| matches!(edge.kind, EdgeKind::Exception(_)));
+                if is_exit {
+                    results.after[index] = analysis.boundary();
+                }
+            }
+            worklist.extend(order.iter().enumerate().map(|(i, &block)| (i, block)));
+        }
+    }
+    let mut priority = vec![usize::MAX; blocks.len()];
+    let mut visited = vec![false; blocks.len()];
+    for (i, block) in order.iter().enumerate() {
+        priority[block.0] = i;
+    }
+
+    while let Some((_, block)) = worklist.pop_first() {
+        let index = block.0;
+        let first_visit = !visited[index];
+        visited[index] = true;
+        let successors = blocks[index].successors();
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut state = results.before[index].clone();
+                let mut exceptional: Vec<Option<A::Domain>> = vec![None; successors.len()];
+                for (pc, instruction) in &instructions[index] {
+                    for (edge, thrown) in successors.iter().zip(&mut exceptional) {
+                        if let EdgeKind::Exception(catch_type) = edge.kind {
+                            let mut incoming = state.clone();
+                            analysis.exception(&mut incoming, catch_type);
+                            match thrown.as_mut() {
+                                Some(existing) => analysis.join(existing, &incoming, *pc),
+                                None => *thrown = Some(incoming),
+                            }
+                        }
+                    }
+                    analysis.transfer(&mut state, *pc, instruction);
+                }
+
+                for (edge, thrown) in successors.iter().zip(exceptional) {
+                    let incoming = thrown.as_ref().unwrap_or(&state);
+                    let target = edge.target.0;
+                    let mut merged = results.before[target].clone();
+                    analysis.join(&mut merged, incoming, graph.block(edge.target).start());
+                    if merged != results.before[target] || !visited[target] {
+                        results.before[target] = merged;
+                        worklist.insert((priority[target], edge.target));
+                    }
+                }
+                results.after[index] = state;
+            }
+            Direction::Backward => {
+                let has_handlers = has_handlers(graph, block);
+                let mut state = results.after[index].clone();
+                for (pc, instruction) in instructions[index].iter().rev() {
+                    analysis.transfer(&mut state, *pc, instruction);
+                    if has_handlers {
+                        analysis.join(&mut state, &results.exceptional[index], *pc);
+                    }
+                }
+                if state == results.before[index] && !first_visit {
+                    continue;
+                }
+                results.before[index] = state;
+
+                for &predecessor in graph.block(block).predecessors() {
+                    let last_pc = instructions[predecessor.0].last().map_or(0, |(pc, _)| *pc);
+                    let edges = graph.block(predecessor).successors();
+                    let mut changed = false;
+                    for edge in edges.iter().filter(|edge| edge.target == block) {
+                        let (target, incoming) = match edge.kind {
+                            EdgeKind::Exception(catch_type) => {
+                                let mut incoming = results.before[index].clone();
+                                analysis.exception(&mut incoming, catch_type);
+                                (&mut results.exceptional[predecessor.0], incoming)
+                            }
+                            _ => (
+                                &mut results.after[predecessor.0],
+                                results.before[index].clone(),
+                            ),
+                        };
+                        let mut merged = target.clone();
+                        analysis.join(&mut merged, &incoming, last_pc);
+                        if merged != *target {
+                            *target = merged;
+                            changed = true;
+                        }
+                    }
+                    if changed || !visited[predecessor.0] {
+                        worklist.insert((priority[predecessor.0], predecessor));
+                    }
+                }
+            }
+        }
+    }
+
+    results
+}
+
+fn has_handlers(graph: &ControlFlowGraph, block: BlockId) -> bool {
+    graph
+        .block(block)
+        .successors()
+        .iter()
+        .any(|edge| matches!(edge.kind, EdgeKind::Exception(_)))
+}
+
+/// A set of small integers, such as the indices of local variables, stored as
+/// a bit vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    /// Creates an empty set which can hold the integers up to `len`.
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.words
+            .get(value / 64)
+            .is_some_and(|word| word & (1 << (value % 64)) != 0)
+    }
+
+    /// Adds `value` to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is not less than the length the set was created
+    /// with, rounded up to a multiple of 64.
+    pub fn insert(&mut self, value: usize) {
+        self.words[value / 64] |= 1 << (value % 64);
+    }
+
+    pub fn remove(&mut self, value: usize) {
+        if let Some(word) = self.words.get_mut(value / 64) {
+            *word &= !(1 << (value % 64));
+        }
+    }
+
+    /// Adds every element of `other` to the set.
+    pub fn union_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /// Returns the elements of the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(index, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| index * 64 + bit)
+        })
+    }
+}
+
+/// Returns the local variable read by an instruction, along with the number
+/// of slots it takes up.
+pub(super) fn local_read(instruction: &Instruction) -> Option<(u16, usize)> {
+    match *instruction {
+        Instruction::iload(index)
+        | Instruction::fload(index)
+        | Instruction::aload(index)
+        | Instruction::iinc(index, _)
+        | Instruction::ret(index) => Some((index, 1)),
+        Instruction::lload(index) | Instruction::dload(index) => Some((index, 2)),
+        _ => None,
+    }
+}
+
+/// Returns the local variable written by an instruction, along with the
+/// number of slots it takes up.
+pub(super) fn local_write(instruction: &Instruction) -> Option<(u16, usize)> {
+    match *instruction {
+        Instruction::istore(index)
+        | Instruction::fstore(index)
+        | Instruction::astore(index)
+        | Instruction::iinc(index, _) => Some((index, 1)),
+        Instruction::lstore(index) | Instruction::dstore(index) => Some((index, 2)),
+        _ => None,
+    }
+}
@@ -0,0 +1,96 @@
+use crate::vm::class::{Code, Instruction, MethodDescriptor};
+
+use super::dataflow::{local_read, local_write};
+use super::{Analysis, BitSet, BlockId, ControlFlowGraph, DataflowResults, Direction};
+
+/// Finds the local variables which are live at every instruction, that is,
+/// whose current values may still be read before they are overwritten.
+///
+/// A value of type `long` or `double` is live if the lower of its two slots
+/// is.
+pub struct Liveness {
+    max_locals: usize,
+}
+
+impl Liveness {
+    pub fn new(code: &Code) -> Self {
+        let mut max_locals = code.max_locals as usize;
+        for (_, instruction) in code.bytecode() {
+            for (index, size) in local_read(&instruction)
+                .into_iter()
+                .chain(local_write(&instruction))
+            {
+                max_locals = max_locals.max(index as usize + size);
+            }
+        }
+        Self { max_locals }
+    }
+
+    /// Returns the offsets of the stores and `iinc` instructions whose values
+    /// are never read.
+    pub fn dead_stores(
+        &self,
+        graph: &ControlFlowGraph,
+        code: &Code,
+        results: &DataflowResults<BitSet>,
+    ) -> Vec<u32> {
+        let mut dead_stores = Vec::new();
+        results.visit(self, graph, code, |pc, instruction, _, after| {
+            if let Some((index, _)) = local_write(instruction) {
+                if !after.contains(index as usize) {
+                    dead_stores.push(pc);
+                }
+            }
+        });
+        dead_stores
+    }
+
+    /// Returns the indices of the parameters in `descriptor` whose values are
+    /// never read, where the first parameter after any `this` has index 0.
+    pub fn unused_parameters(
+        &self,
+        results: &DataflowResults<BitSet>,
+        descriptor: &MethodDescriptor,
+        is_static: bool,
+    ) -> Vec<usize> {
+        let live = results.before(BlockId::ENTRY);
+        let mut slot = if is_static { 0 } else { 1 };
+        let mut unused = Vec::new();
+        for (index, parameter) in descriptor.args().iter().enumerate() {
+            if !live.contains(slot) {
+                unused.push(index);
+            }
+            slot += parameter.slots();
+        }
+        unused
+    }
+}
+
+impl Analysis for Liveness {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn bottom(&self) -> BitSet {
+        BitSet::new(self.max_locals)
+    }
+
+    fn boundary(&self) -> BitSet {
+        BitSet::new(self.max_locals)
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet, _: u32) {
+        state.union_with(other);
+    }
+
+    fn transfer(&self, state: &mut BitSet, _: u32, instruction: &Instruction) {
+        if let Some((index, size)) = local_write(instruction) {
+            for slot in index as usize..index as usize + size {
+                state.remove(slot);
+            }
+        }
+        if let Some((index, _)) = local_read(instruction) {
+            state.insert(index as usize);
+        }
+    }
+}
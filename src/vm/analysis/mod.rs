@@ -0,0 +1,13 @@
+mod cfg;
+mod constant_propagation;
+mod dataflow;
+mod liveness;
+mod reaching;
+mod stack_types;
+
+pub use cfg::*;
+pub use constant_propagation::*;
+pub use dataflow::*;
+pub use liveness::*;
+pub use reaching::*;
+pub use stack_types::*;
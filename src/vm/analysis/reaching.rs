@@ -0,0 +1,130 @@
+use crate::vm::class::{Code, Instruction, MethodDescriptor};
+
+use super::dataflow::local_write;
+use super::{Analysis, BitSet, Direction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Definition {
+    /// The value held by the local variable with the given index when the
+    /// method is entered, which is `this` or a parameter.
+    Parameter(u16),
+    /// A store or `iinc` at the given offset.
+    Store { pc: u32, local: u16 },
+}
+
+impl Definition {
+    /// Returns the index of the local variable which is defined.
+    pub fn local(&self) -> u16 {
+        match *self {
+            Definition::Parameter(local) | Definition::Store { local, .. } => local,
+        }
+    }
+}
+
+/// Finds the definitions of local variables which reach every instruction,
+/// that is, which may not have been overwritten on some path to it.
+///
+/// The state is a set of indices into [`ReachingDefinitions::definitions`].
+pub struct ReachingDefinitions {
+    /// The parameters in the order of their indices, followed by the stores
+    /// in the order of their offsets.
+    definitions: Vec<Definition>,
+    parameters: usize,
+    /// The definitions of each local variable.
+    by_local: Vec<Vec<usize>>,
+}
+
+impl ReachingDefinitions {
+    pub fn new(code: &Code, descriptor: &MethodDescriptor, is_static: bool) -> Self {
+        let mut definitions = Vec::new();
+        if !is_static {
+            definitions.push(Definition::Parameter(0));
+        }
+        let mut slot = if is_static { 0 } else { 1 };
+        for parameter in descriptor.args() {
+            definitions.push(Definition::Parameter(slot));
+            slot += parameter.slots() as u16;
+        }
+        let parameters = definitions.len();
+
+        for (pc, instruction) in code.bytecode() {
+            if let Some((local, _)) = local_write(&instruction) {
+                definitions.push(Definition::Store { pc, local });
+            }
+        }
+
+        let max_locals = definitions
+            .iter()
+            .map(|definition| definition.local() as usize + 2)
+            .max()
+            .unwrap_or(0);
+        let mut by_local = vec![Vec::new(); max_locals];
+        for (index, definition) in definitions.iter().enumerate() {
+            by_local[definition.local() as usize].push(index);
+        }
+
+        Self {
+            definitions,
+            parameters,
+            by_local,
+        }
+    }
+
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// Returns the definitions in `state` of the local variable `local`.
+    pub fn reaching<'a>(
+        &'a self,
+        state: &'a BitSet,
+        local: u16,
+    ) -> impl Iterator<Item = Definition> + 'a {
+        self.by_local
+            .get(local as usize)
+            .into_iter()
+            .flatten()
+            .filter(|&&index| state.contains(index))
+            .map(|&index| self.definitions[index])
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self) -> BitSet {
+        BitSet::new(self.definitions.len())
+    }
+
+    fn boundary(&self) -> BitSet {
+        let mut state = BitSet::new(self.definitions.len());
+        for index in 0..self.parameters {
+            state.insert(index);
+        }
+        state
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet, _: u32) {
+        state.union_with(other);
+    }
+
+    fn transfer(&self, state: &mut BitSet, pc: u32, instruction: &Instruction) {
+        let Some((local, size)) = local_write(instruction) else {
+            return;
+        };
+        // A wide value also overwrites the definitions of its second slot.
+        for slot in local as usize..local as usize + size {
+            for &index in self.by_local.get(slot).into_iter().flatten() {
+                state.remove(index);
+            }
+        }
+        let stores = &self.definitions[self.parameters..];
+        let index = stores.partition_point(|definition| match *definition {
+            Definition::Store { pc: store, .. } => store < pc,
+            Definition::Parameter(_) => true,
+        });
+        state.insert(self.parameters + index);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::java_str;
+use crate::string::{JavaStr, JavaString};
+use crate::vm::class::stack_map::{self, MethodContext};
+use crate::vm::class::{
+    BuildError, Class, Code, ConstantIdx, ConstantPool, Frame, Instruction, Method, MethodFlags,
+    VerificationType,
+};
+
+use super::{Analysis, Direction};
+
+/// The types known at an instruction by [`StackTypes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeState {
+    /// The instruction has not been reached.
+    Unreached,
+    Frame(Frame),
+    /// The instruction can be reached with an invalid frame, for example
+    /// because an earlier instruction pops more values than are on the stack
+    /// or because paths with stacks of different heights meet.
+    Invalid(BuildError),
+}
+
+impl TypeState {
+    /// Returns the frame, if the state is neither unreached nor invalid.
+    pub fn frame(&self) -> Option<&Frame> {
+        match self {
+            TypeState::Frame(frame) => Some(frame),
+            _ => None,
+        }
+    }
+}
+
+/// Infers the types of the local variables and operand stack at every
+/// instruction of a method, starting from the types of its parameters.
+///
+/// Types are merged like the frames of a `StackMapTable`. A value pushed by
+/// `jsr` has the type [`VerificationType::Top`].
+pub struct StackTypes<'a> {
+    constants: &'a ConstantPool,
+    bytecode: &'a [u8],
+    method: MethodContext<'a>,
+    max_locals: usize,
+}
+
+impl<'a> StackTypes<'a> {
+    /// Creates the analysis for `method` of `class`. The common superclass of
+    /// two classes is taken to be `java/lang/Object` unless another function
+    /// is given with [`StackTypes::common_superclass`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method has no code.
+    pub fn new(class: &'a Class, method: &'a Method) -> Self {
+        let code: &Code = method.bytecode().expect("method has no code");
+        let is_static = method.flags() & MethodFlags::STATIC == MethodFlags::STATIC;
+        let descriptor = method.parsed_descriptor();
+
+        let mut max_locals = (code.max_locals as usize).max(descriptor.arg_slots(is_static));
+        for (_, instruction) in code.bytecode() {
+            if let Some((index, size)) = stack_map::local_access(&instruction) {
+                max_locals = max_locals.max(index as usize + size);
+            }
+        }
+
+        Self {
+            constants: class.constants(),
+            bytecode: &code.bytecode,
+            method: MethodContext {
+                class_name: class.name(),
+                name: method.name(class.constants()),
+                descriptor,
+                is_static,
+                common_superclass: &object,
+            },
+            max_locals,
+        }
+    }
+
+    /// Sets the function which returns the closest common superclass of two
+    /// classes, used where references to different classes are merged.
+    pub fn common_superclass(
+        &mut self,
+        common_superclass: &'a (dyn Fn(&JavaStr, &JavaStr) -> JavaString + 'a),
+    ) -> &mut Self {
+        self.method.common_superclass = common_superclass;
+        self
+    }
+}
+
+fn object(_: &JavaStr, _: &JavaStr) -> JavaString {
+    java_str!("java/lang/Object").to_owned()
+}
+
+impl Analysis for StackTypes<'_> {
+    type Domain = TypeState;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self) -> TypeState {
+        TypeState::Unreached
+    }
+
+    fn boundary(&self) -> TypeState {
+        TypeState::Frame(stack_map::initial_frame(&self.method, self.max_locals))
+    }
+
+    fn join(&self, state: &mut TypeState, other: &TypeState, pc: u32) {
+        match (&mut *state, other) {
+            (_, TypeState::Unreached) | (TypeState::Invalid(_), _) => {}
+            (TypeState::Unreached, _) | (_, TypeState::Invalid(_)) => *state = other.clone(),
+            (TypeState::Frame(frame), TypeState::Frame(other)) => {
+                if let Err(error) = stack_map::merge_frame(frame, other, pc, &self.method) {
+                    *state = TypeState::Invalid(error);
+                }
+            }
+        }
+    }
+
+    fn transfer(&self, state: &mut TypeState, pc: u32, instruction: &Instruction) {
+        if let TypeState::Frame(frame) = state {
+            let result = stack_map::execute(
+                frame,
+                pc,
+                instruction,
+                self.bytecode,
+                self.constants,
+                &self.method,
+            );
+            if let Err(error) = result {
+                *state = TypeState::Invalid(error);
+            }
+        }
+    }
+
+    fn exception(&self, state: &mut TypeState, catch_type: Option<ConstantIdx>) {
+        if let TypeState::Frame(frame) = state {
+            let class = match catch_type {
+                Some(class) => {
+                    let name = self.constants.get(class).into_class();
+                    self.constants.get(name).into_utf8()
+                }
+                None => java_str!("java/lang/Throwable"),
+            };
+            frame.stack = vec![VerificationType::Object(class.to_owned())];
+        }
+    }
+}
@@ -1,283 +1,612 @@
-use super::class::{Bytecode, Code, ConstantPool};
-use super::value::Value;
+//! Call frames and the thread stack holding their locals and operand stacks.
+//!
+//! The locals and operand stacks of all frames of a thread live in one
+//! contiguous [`ThreadStack`]. The locals of a frame start where the arguments
+//! were on the operand stack of its caller, so invoking a method copies
+//! nothing, and are followed by its operand stack.
+//!
+//! A class is verified before any of its code runs, so the type of the value
+//! in a slot is always the one the instruction reading it expects, and
+//! release builds store raw 32-bit slots, with a `long` or `double` split
+//! across two of them. Debug builds also tag every slot with the type of its
+//! value and check it on every access, unless the `jit` feature is enabled,
+//! as machine code reads the slots directly.
+
+use super::convert::{ConvertError, FromJava, JavaHeap, ToJava};
+use super::decode::{DecodedCode, MethodId};
+use super::heap::ObjectRef;
+use super::ir::{self, Optimized};
+use super::monitor::FrameMonitor;
+use super::value::{Value, ValueType};
+
+#[cfg(all(debug_assertions, not(feature = "jit")))]
+pub(super) use tagged::*;
+#[cfg(any(not(debug_assertions), feature = "jit"))]
+pub(super) use untagged::*;
+
+/// The largest number of slots the frames of a thread may use.
+const MAX_STACK_SLOTS: usize = 1 << 20;
 
 #[derive(Debug)]
 pub struct CallFrame<'a> {
-    pub(super) constants: &'a ConstantPool,
-    pub(super) bytecode: Bytecode<'a>,
-
-    pub(super) locals: Locals,
-    pub(super) stack: Stack,
+    /// The index of the class of the method in the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    pub(super) class: usize,
+    /// The index of the method in [`Class::methods`].
+    ///
+    /// [`Class::methods`]: super::class::Class::methods
+    pub(super) method: u32,
+    pub(super) code: &'a DecodedCode,
+    /// The index of the next instruction to execute in `code`.
+    pub(super) pc: usize,
+
+    /// The index of the first local in the [`ThreadStack`].
+    pub(super) base: usize,
+    /// The number of slots on the operand stack.
+    pub(super) depth: usize,
+    /// The state of the frame while it runs in the optimizing tier.
+    pub(super) optimized: Option<Optimized>,
+    /// The monitor of a frame of a `synchronized` method, which it enters
+    /// before its first instruction and exits when it returns.
+    pub(super) monitor: FrameMonitor,
 }
 
 impl<'a> CallFrame<'a> {
-    pub fn new(constants: &'a ConstantPool, code: &'a Code) -> Self {
+    pub fn new(method: MethodId, code: &'a DecodedCode, base: usize) -> Self {
         Self {
-            constants,
-            bytecode: code.bytecode(),
+            class: method.class as usize,
+            method: method.method,
+            code,
+            pc: 0,
+
+            base,
+            depth: 0,
+            optimized: None,
+            monitor: FrameMonitor::Unentered,
+        }
+    }
 
-            locals: Locals::new(code.max_locals as usize),
-            stack: Stack::new(code.max_stack as usize),
+    /// Returns the index in the [`ThreadStack`] after the last slot in use by
+    /// the frame, where the locals of a frame pushed on top of it start.
+    pub(super) fn top(&self) -> usize {
+        if self.optimized.is_some() {
+            self.base + ir::register_count(self.code)
+        } else {
+            self.base + self.code.max_locals as usize + self.depth
         }
     }
+
+    /// Returns whether the slots of the frame extend beyond the largest stack
+    /// a thread may use, which is checked before the frame runs.
+    pub(super) fn overflows(&self) -> bool {
+        self.base + ir::register_count(self.code) > MAX_STACK_SLOTS
+    }
 }
 
-#[derive(Debug)]
-pub struct Stack {
-    stack: Vec<Slot>,
+/// The locals and operand stacks of the frames of a thread.
+#[derive(Debug, Default)]
+pub struct ThreadStack {
+    slots: Vec<Slot>,
 }
 
-impl Stack {
-    pub fn new(n: usize) -> Self {
-        Self {
-            stack: Vec::with_capacity(n),
+impl ThreadStack {
+    pub fn new() -> Self {
+        Self { slots: Vec::new() }
+    }
+
+    /// Returns the slots of the frame running `code` whose locals start at
+    /// `base`: its locals, its operand stack and the scratch registers of the
+    /// optimizing tier.
+    pub(super) fn registers(&mut self, base: usize, code: &DecodedCode) -> &mut [Slot] {
+        let end = base + ir::register_count(code);
+        if end > self.slots.len() {
+            self.grow(end);
         }
+        &mut self.slots[base..end]
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn grow(&mut self, end: usize) {
+        assert!(end <= MAX_STACK_SLOTS, "frame overflows the stack");
+        let len = end.next_power_of_two().min(MAX_STACK_SLOTS);
+        self.slots.resize(len, Slot::default());
+    }
+
+    /// Splits the slots of the frame running `code` whose locals start at
+    /// `base` into its locals and its operand stack, which holds `depth`
+    /// slots.
+    pub(super) fn split<'s>(
+        &'s mut self,
+        base: usize,
+        code: &DecodedCode,
+        depth: &'s mut usize,
+    ) -> (Locals<'s>, Stack<'s>) {
+        let max_locals = code.max_locals as usize;
+        let (locals, stack) = self.registers(base, code).split_at_mut(max_locals);
+        let locals = Locals { slots: locals };
+        let stack = Stack {
+            slots: stack,
+            base: base + max_locals,
+            len: depth,
+        };
+        (locals, stack)
+    }
+
+    /// Returns the locals of `frame` followed by the slots on its operand
+    /// stack.
+    pub(super) fn slots(&self, frame: &CallFrame) -> &[Slot] {
+        let start = frame.base;
+        &self.slots[start..start + frame.code.max_locals as usize + frame.depth]
+    }
+
+    pub(super) fn slots_mut(&mut self, frame: &CallFrame) -> &mut [Slot] {
+        let start = frame.base;
+        &mut self.slots[start..start + frame.code.max_locals as usize + frame.depth]
+    }
+
+    /// Returns the operand stack of `frame`.
+    pub(super) fn stack<'s>(&'s mut self, frame: &'s mut CallFrame) -> Stack<'s> {
+        self.split(frame.base, frame.code, &mut frame.depth).1
+    }
+
+    /// Leaves `value`, which the first frame of the thread returned, in the
+    /// first slot, where every tier leaves the result of a frame with no
+    /// caller.
+    pub(super) fn set_result(&mut self, value: Value) {
+        set(&mut self.slots, 0, value);
+    }
+
+    /// Returns the value of type `value_type` which the first frame of the
+    /// thread returned.
+    pub(super) fn result(&self, value_type: ValueType) -> Value {
+        get(&self.slots, 0, value_type)
     }
+}
+
+/// The operand stack of a frame.
+#[derive(Debug)]
+pub struct Stack<'s> {
+    slots: &'s mut [Slot],
+    /// The index of the bottom of the stack in the [`ThreadStack`].
+    base: usize,
+    /// The number of slots on the stack, which is kept in the frame.
+    len: &'s mut usize,
+}
 
+impl Stack<'_> {
     pub fn push(&mut self, val: Value) {
-        match val {
-            Value::Long(_) | Value::Double(_) => {
-                self.stack.push(Slot::Entry(val));
-                self.stack.push(Slot::Marker);
-            }
-            _ => {
-                self.stack.push(Slot::Entry(val));
-            }
-        }
+        set(self.slots, *self.len, val);
+        *self.len += val.size();
     }
 
     pub fn push_int(&mut self, val: i32) {
-        self.push(Value::Int(val))
+        set_int(self.slots, *self.len, val);
+        *self.len += 1;
     }
 
     pub fn push_long(&mut self, val: i64) {
-        self.push(Value::Long(val))
+        set_long(self.slots, *self.len, val);
+        *self.len += 2;
     }
 
     pub fn push_float(&mut self, val: f32) {
-        self.push(Value::Float(val))
+        set_float(self.slots, *self.len, val);
+        *self.len += 1;
     }
 
     pub fn push_double(&mut self, val: f64) {
-        self.push(Value::Double(val))
+        set_double(self.slots, *self.len, val);
+        *self.len += 2;
     }
 
     pub fn push_ret_addr(&mut self, val: u32) {
-        self.push(Value::RetAddr(val))
-    }
-
-    pub fn pop(&mut self) -> Value {
-        match self.stack.pop() {
-            Some(Slot::Entry(val)) => val,
-            Some(Slot::Marker) => match self.stack.pop() {
-                Some(Slot::Entry(val)) => val,
-                Some(Slot::Marker) => panic!("invalid stack state"),
-                None => panic!("cannot pop from empty stack"),
-            },
-            None => panic!("cannot pop from empty stack"),
-        }
+        set_ret_addr(self.slots, *self.len, val);
+        *self.len += 1;
+    }
+
+    pub fn push_reference(&mut self, val: Option<ObjectRef>) {
+        set_reference(self.slots, *self.len, val);
+        *self.len += 1;
+    }
+
+    /// Pops a value of type `value_type`.
+    pub fn pop(&mut self, value_type: ValueType) -> Value {
+        *self.len -= value_type.size();
+        get(self.slots, *self.len, value_type)
     }
 
     pub fn pop_int(&mut self) -> i32 {
-        match self.pop() {
-            Value::Int(val) => val,
-            _ => panic!("invalid stack state"),
-        }
+        *self.len -= 1;
+        get_int(self.slots, *self.len)
     }
 
     pub fn pop_long(&mut self) -> i64 {
-        match self.pop() {
-            Value::Long(val) => val,
-            _ => panic!("invalid stack state"),
-        }
+        *self.len -= 2;
+        get_long(self.slots, *self.len)
     }
 
     pub fn pop_float(&mut self) -> f32 {
-        match self.pop() {
-            Value::Float(val) => val,
-            _ => panic!("invalid stack state"),
-        }
+        *self.len -= 1;
+        get_float(self.slots, *self.len)
     }
 
     pub fn pop_double(&mut self) -> f64 {
-        match self.pop() {
-            Value::Double(val) => val,
-            _ => panic!("invalid stack state"),
-        }
+        *self.len -= 2;
+        get_double(self.slots, *self.len)
     }
 
     pub fn pop_ret_addr(&mut self) -> u32 {
-        match self.pop() {
-            Value::RetAddr(val) => val,
-            _ => panic!("invalid stack state"),
-        }
+        *self.len -= 1;
+        get_ret_addr(self.slots, *self.len)
+    }
+
+    pub fn pop_reference(&mut self) -> Option<ObjectRef> {
+        *self.len -= 1;
+        get_reference(self.slots, *self.len)
+    }
+
+    /// Pushes a Rust value as the Java value it converts to, such as the
+    /// result of a native method, allocating the objects it needs in `heap`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConvertError`] if the value does not convert, in which
+    /// case nothing is pushed.
+    pub fn push_as<T: ToJava>(
+        &mut self,
+        val: &T,
+        heap: &mut JavaHeap<'_>,
+    ) -> Result<(), ConvertError> {
+        let val = val.to_java(&T::field_type(), heap)?;
+        self.push(val);
+        Ok(())
+    }
+
+    /// Pops a value and converts it to a Rust value, such as an argument of a
+    /// native method, or returns `None` if the Rust type cannot represent it.
+    pub fn pop_as<T: FromJava>(&mut self, heap: &mut JavaHeap<'_>) -> Option<T> {
+        T::from_java(self.pop(T::value_type()), heap)
+    }
+
+    /// Pops a slot holding a reference or a return address, as stored by
+    /// `astore`.
+    pub(super) fn pop_slot(&mut self) -> Slot {
+        *self.len -= 1;
+        let slot = self.slots[*self.len];
+        check_value(slot);
+        slot
+    }
+
+    /// Returns the reference `depth` slots below the top of the stack, such
+    /// as the receiver of a method call beneath its arguments.
+    pub fn peek_reference(&self, depth: usize) -> Option<ObjectRef> {
+        get_reference(self.slots, *self.len - depth - 1)
+    }
+
+    /// Pops the `slots` slots holding the arguments of a call, and returns the
+    /// index of the first in the [`ThreadStack`], where the locals of the
+    /// invoked method start.
+    pub(super) fn pop_arguments(&mut self, slots: usize) -> usize {
+        *self.len -= slots;
+        self.base + *self.len
     }
 
     pub fn inst_pop(&mut self) {
-        match self.stack.pop() {
-            Some(Slot::Entry(_)) => (),
-            Some(Slot::Marker) => {
-                panic!("found category 2 type but expected category 1 type")
-            }
-            None => panic!("cannot pop from empty stack"),
-        }
+        *self.len -= 1;
+        check_value(self.slots[*self.len]);
     }
 
     pub fn inst_pop2(&mut self) {
-        match self.stack.pop() {
-            Some(_) => (),
-            None => panic!("cannot pop from empty stack"),
-        };
-        match self.stack.pop() {
-            Some(Slot::Entry(_)) => (),
-            Some(Slot::Marker) => panic!("invalid stack state"),
-            None => panic!("cannot pop from empty stack"),
-        }
+        *self.len -= 2;
+        check_value(self.slots[*self.len]);
     }
 
     pub fn inst_dup(&mut self) {
-        self.dup_cat1(0);
+        self.dup(1, 0);
     }
 
     pub fn inst_dup_x1(&mut self) {
-        self.dup_cat1(1);
+        self.dup(1, 1);
     }
 
     pub fn inst_dup_x2(&mut self) {
-        self.dup_cat1(2);
+        self.dup(1, 2);
     }
 
     pub fn inst_dup2(&mut self) {
-        self.dup_cat2(0);
+        self.dup(2, 0);
     }
 
     pub fn inst_dup2_x1(&mut self) {
-        self.dup_cat2(1);
+        self.dup(2, 1);
     }
 
     pub fn inst_dup2_x2(&mut self) {
-        self.dup_cat2(2);
+        self.dup(2, 2);
     }
 
     pub fn inst_swap(&mut self) {
-        match self.stack.last_chunk_mut() {
-            Some([Slot::Entry(lower), Slot::Entry(upper)]) => {
-                std::mem::swap(lower, upper);
-            }
-            Some(_) => panic!("invalid stack state"),
-            None => panic!("cannot swap with stack len of {}", self.stack.len()),
-        }
+        let len = *self.len;
+        check_value(self.slots[len - 1]);
+        check_value(self.slots[len - 2]);
+        self.slots.swap(len - 1, len - 2);
     }
 
+    /// Duplicates the top `size` slots and inserts the copy `skip` slots
+    /// below them.
     #[track_caller]
-    fn dup_cat1(&mut self, n: usize) {
-        let entry = match self.stack.last() {
-            Some(entry @ Slot::Entry(_)) => *entry,
-            Some(Slot::Marker) => panic!("invalid stack state"),
-            None => panic!("cannot pop from empty stack"),
-        };
-        self.stack.insert(self.stack.len() - n - 1, entry);
+    fn dup(&mut self, size: usize, skip: usize) {
+        let len = *self.len;
+        check_value(self.slots[len - size]);
+        self.slots.copy_within(len - size - skip..len, len - skip);
+        self.slots.copy_within(len..len + size, len - size - skip);
+        *self.len += size;
     }
+}
 
-    #[track_caller]
-    fn dup_cat2(&mut self, n: usize) {
-        let chunk = match self.stack.last_chunk() {
-            Some(chunk @ [Slot::Entry(_), Slot::Entry(_) | Slot::Marker]) => *chunk,
-            Some(_) => panic!("invalid stack state"),
-            None => panic!("cannot pop from empty stack"),
-        };
-        self.stack.insert(self.stack.len() - n - 2, chunk[0]);
-        self.stack.insert(self.stack.len() - n - 2, chunk[1]);
+/// The locals of a frame.
+#[derive(Debug)]
+pub struct Locals<'s> {
+    slots: &'s mut [Slot],
+}
+
+impl Locals<'_> {
+    pub fn get_int(&self, i: usize) -> i32 {
+        get_int(self.slots, i)
+    }
+
+    pub fn get_long(&self, i: usize) -> i64 {
+        get_long(self.slots, i)
+    }
+
+    pub fn get_float(&self, i: usize) -> f32 {
+        get_float(self.slots, i)
+    }
+
+    pub fn get_double(&self, i: usize) -> f64 {
+        get_double(self.slots, i)
+    }
+
+    pub fn get_ret_addr(&self, i: usize) -> u32 {
+        get_ret_addr(self.slots, i)
+    }
+
+    pub fn get_reference(&self, i: usize) -> Option<ObjectRef> {
+        get_reference(self.slots, i)
+    }
+
+    pub fn set_int(&mut self, i: usize, val: i32) {
+        set_int(self.slots, i, val);
+    }
+
+    pub fn set_long(&mut self, i: usize, val: i64) {
+        set_long(self.slots, i, val);
+    }
+
+    pub fn set_float(&mut self, i: usize, val: f32) {
+        set_float(self.slots, i, val);
+    }
+
+    pub fn set_double(&mut self, i: usize, val: f64) {
+        set_double(self.slots, i, val);
+    }
+
+    pub fn set_ret_addr(&mut self, i: usize, val: u32) {
+        set_ret_addr(self.slots, i, val);
+    }
+
+    pub fn set_reference(&mut self, i: usize, val: Option<ObjectRef>) {
+        set_reference(self.slots, i, val);
+    }
+
+    pub(super) fn set_slot(&mut self, i: usize, slot: Slot) {
+        self.slots[i] = slot;
     }
 }
 
-#[derive(Debug)]
-pub struct Locals {
-    slots: Box<[Slot]>,
+/// Reads the value of type `value_type` at `i`.
+#[inline(always)]
+pub(super) fn get(slots: &[Slot], i: usize, value_type: ValueType) -> Value {
+    match value_type {
+        ValueType::Int => Value::Int(get_int(slots, i)),
+        ValueType::Long => Value::Long(get_long(slots, i)),
+        ValueType::Float => Value::Float(get_float(slots, i)),
+        ValueType::Double => Value::Double(get_double(slots, i)),
+        ValueType::RetAddr => Value::RetAddr(get_ret_addr(slots, i)),
+        ValueType::Reference => Value::Reference(get_reference(slots, i)),
+    }
 }
 
-impl Locals {
-    pub fn new(n: usize) -> Self {
-        Self {
-            slots: vec![Slot::Marker; n].into_boxed_slice(),
-        }
+/// Writes `value` at `i`, taking two slots if it is a `long` or `double`.
+#[inline(always)]
+pub(super) fn set(slots: &mut [Slot], i: usize, value: Value) {
+    match value {
+        Value::Int(val) => set_int(slots, i, val),
+        Value::Long(val) => set_long(slots, i, val),
+        Value::Float(val) => set_float(slots, i, val),
+        Value::Double(val) => set_double(slots, i, val),
+        Value::RetAddr(val) => set_ret_addr(slots, i, val),
+        Value::Reference(val) => set_reference(slots, i, val),
+    }
+}
+
+/// Slots tagged with the type of their value.
+#[cfg(all(debug_assertions, not(feature = "jit")))]
+mod tagged {
+    use crate::vm::heap::ObjectRef;
+    use crate::vm::value::Value;
+
+    #[derive(Debug, Clone, Copy, Default)]
+    pub enum Slot {
+        Entry(Value),
+        /// The second slot of a `long` or `double`, or a slot which has not
+        /// been written.
+        #[default]
+        Marker,
+    }
+
+    #[cold]
+    #[inline(never)]
+    #[track_caller]
+    fn invalid_slot() -> ! {
+        panic!("invalid slot state")
     }
 
-    pub fn get(&self, i: usize) -> Value {
-        match self.slots.get(i) {
-            Some(Slot::Entry(val)) => *val,
-            _ => panic!("invalid local index: {i}"),
+    /// Checks that `slot` starts a value.
+    #[track_caller]
+    pub fn check_value(slot: Slot) {
+        if let Slot::Marker = slot {
+            invalid_slot();
         }
     }
 
-    pub fn get_int(&self, i: usize) -> i32 {
-        match self.get(i) {
-            Value::Int(val) => val,
-            _ => panic!("invalid locals state"),
+    #[track_caller]
+    pub fn get_int(slots: &[Slot], i: usize) -> i32 {
+        match slots[i] {
+            Slot::Entry(Value::Int(val)) => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn get_long(&self, i: usize) -> i64 {
-        match self.get(i) {
-            Value::Long(val) => val,
-            _ => panic!("invalid locals state"),
+    #[track_caller]
+    pub fn get_long(slots: &[Slot], i: usize) -> i64 {
+        match slots[i..i + 2] {
+            [Slot::Entry(Value::Long(val)), Slot::Marker] => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn get_float(&self, i: usize) -> f32 {
-        match self.get(i) {
-            Value::Float(val) => val,
-            _ => panic!("invalid locals state"),
+    #[track_caller]
+    pub fn get_float(slots: &[Slot], i: usize) -> f32 {
+        match slots[i] {
+            Slot::Entry(Value::Float(val)) => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn get_double(&self, i: usize) -> f64 {
-        match self.get(i) {
-            Value::Double(val) => val,
-            _ => panic!("invalid locals state"),
+    #[track_caller]
+    pub fn get_double(slots: &[Slot], i: usize) -> f64 {
+        match slots[i..i + 2] {
+            [Slot::Entry(Value::Double(val)), Slot::Marker] => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn get_ret_addr(&self, i: usize) -> u32 {
-        match self.get(i) {
-            Value::RetAddr(val) => val,
-            _ => panic!("invalid locals state"),
+    #[track_caller]
+    pub fn get_ret_addr(slots: &[Slot], i: usize) -> u32 {
+        match slots[i] {
+            Slot::Entry(Value::RetAddr(val)) => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn set(&mut self, i: usize, val: Value) {
-        match val {
-            Value::Long(_) | Value::Double(_) => {
-                self.slots[i] = Slot::Entry(val);
-                self.slots[i + 1] = Slot::Marker;
-            }
-            _ => self.slots[i] = Slot::Entry(val),
+    #[track_caller]
+    pub fn get_reference(slots: &[Slot], i: usize) -> Option<ObjectRef> {
+        match slots[i] {
+            Slot::Entry(Value::Reference(val)) => val,
+            _ => invalid_slot(),
         }
     }
 
-    pub fn set_int(&mut self, i: usize, val: i32) {
-        self.set(i, Value::Int(val));
+    pub fn set_int(slots: &mut [Slot], i: usize, val: i32) {
+        slots[i] = Slot::Entry(Value::Int(val));
     }
 
-    pub fn set_long(&mut self, i: usize, val: i64) {
-        self.set(i, Value::Long(val));
+    pub fn set_long(slots: &mut [Slot], i: usize, val: i64) {
+        slots[i] = Slot::Entry(Value::Long(val));
+        slots[i + 1] = Slot::Marker;
     }
 
-    pub fn set_float(&mut self, i: usize, val: f32) {
-        self.set(i, Value::Float(val));
+    pub fn set_float(slots: &mut [Slot], i: usize, val: f32) {
+        slots[i] = Slot::Entry(Value::Float(val));
     }
 
-    pub fn set_double(&mut self, i: usize, val: f64) {
-        self.set(i, Value::Double(val));
+    pub fn set_double(slots: &mut [Slot], i: usize, val: f64) {
+        slots[i] = Slot::Entry(Value::Double(val));
+        slots[i + 1] = Slot::Marker;
     }
 
-    pub fn set_ret_addr(&mut self, i: usize, val: u32) {
-        self.set(i, Value::RetAddr(val));
+    pub fn set_ret_addr(slots: &mut [Slot], i: usize, val: u32) {
+        slots[i] = Slot::Entry(Value::RetAddr(val));
+    }
+
+    pub fn set_reference(slots: &mut [Slot], i: usize, val: Option<ObjectRef>) {
+        slots[i] = Slot::Entry(Value::Reference(val));
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Slot {
-    Entry(Value),
-    Marker,
+/// Raw 32-bit slots, with the low half of a `long` or `double` first.
+#[cfg(any(not(debug_assertions), feature = "jit"))]
+mod untagged {
+    use crate::vm::heap::ObjectRef;
+
+    pub type Slot = u32;
+
+    #[inline(always)]
+    pub fn check_value(_slot: Slot) {}
+
+    #[inline(always)]
+    pub fn get_int(slots: &[Slot], i: usize) -> i32 {
+        slots[i] as i32
+    }
+
+    #[inline(always)]
+    pub fn get_long(slots: &[Slot], i: usize) -> i64 {
+        match slots[i..i + 2] {
+            [low, high] => (low as u64 | (high as u64) << 32) as i64,
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_float(slots: &[Slot], i: usize) -> f32 {
+        f32::from_bits(slots[i])
+    }
+
+    #[inline(always)]
+    pub fn get_double(slots: &[Slot], i: usize) -> f64 {
+        f64::from_bits(get_long(slots, i) as u64)
+    }
+
+    #[inline(always)]
+    pub fn get_ret_addr(slots: &[Slot], i: usize) -> u32 {
+        slots[i]
+    }
+
+    #[inline(always)]
+    pub fn get_reference(slots: &[Slot], i: usize) -> Option<ObjectRef> {
+        ObjectRef::from_bits(slots[i])
+    }
+
+    #[inline(always)]
+    pub fn set_int(slots: &mut [Slot], i: usize, val: i32) {
+        slots[i] = val as u32;
+    }
+
+    #[inline(always)]
+    pub fn set_long(slots: &mut [Slot], i: usize, val: i64) {
+        slots[i..i + 2].copy_from_slice(&[val as u32, (val as u64 >> 32) as u32]);
+    }
+
+    #[inline(always)]
+    pub fn set_float(slots: &mut [Slot], i: usize, val: f32) {
+        slots[i] = val.to_bits();
+    }
+
+    #[inline(always)]
+    pub fn set_double(slots: &mut [Slot], i: usize, val: f64) {
+        set_long(slots, i, val.to_bits() as i64);
+    }
+
+    #[inline(always)]
+    pub fn set_ret_addr(slots: &mut [Slot], i: usize, val: u32) {
+        slots[i] = val;
+    }
+
+    #[inline(always)]
+    pub fn set_reference(slots: &mut [Slot], i: usize, val: Option<ObjectRef>) {
+        slots[i] = ObjectRef::to_bits(val);
+    }
 }
@@ -0,0 +1,1731 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Write;
+use std::mem::discriminant;
+
+use crate::reader::Reader;
+use crate::string::{from_utf8, JavaStr, JavaString};
+
+use super::stack_map::{entries, initial_frame, read_stack_map, CommonSuperclass, MethodContext};
+use super::{
+    ArrayKind, AttributeKind, BuildError, Class, ClassBuilder, ClassFlags, Code, CodeBuilder,
+    ConstantIdx, ConstantPool, Entry, FieldFlags, FieldType, Frame, FrameEntry, Instruction, Label,
+    Method, MethodDescriptor, MethodFlags, ReferenceKind, VerificationType,
+};
+
+type Result<T> = std::result::Result<T, AssembleErrorKind>;
+
+/// The mnemonics of instructions with an operand, along with the constructor
+/// of the instruction.
+type Mnemonics<T> = &'static [(&'static str, fn(T) -> Instruction<'static>)];
+
+/// An error in the source given to [`assemble`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssembleError {
+    /// The 1-based number of the line the error was found on.
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssembleErrorKind {
+    UnknownDirective,
+    UnknownInstruction,
+    UnknownFlag,
+    /// A directive appears where it is not allowed, such as a `.field` before
+    /// the `.class` or a `.limit` outside of a method.
+    UnexpectedDirective,
+    UnexpectedToken,
+    UnexpectedEndOfLine,
+    /// A method or switch is not terminated.
+    UnexpectedEndOfFile,
+    InvalidNumber,
+    /// A quoted string is not terminated or contains an invalid escape.
+    InvalidString,
+    InvalidDescriptor,
+    /// A label is used but never defined within the method.
+    UndefinedLabel,
+    DuplicateLabel,
+    /// The source has no `.class` directive.
+    MissingClass,
+    /// Only one of `.limit stack` and `.limit locals` is given.
+    MissingLimit,
+    /// An `invokedynamic` or `dynamic` constant refers to a bootstrap method
+    /// which no `.bootstrap` declares.
+    UndefinedBootstrapMethod,
+    /// The method could not be assembled. The line is the one of its
+    /// `.method` directive.
+    Build(BuildError),
+}
+
+/// Instructions without operands, along with their mnemonics.
+const SIMPLE_INSTRUCTIONS: &[(&str, Instruction<'static>)] = &[
+    ("nop", Instruction::nop),
+    ("aconst_null", Instruction::aconst_null),
+    ("iaload", Instruction::iaload),
+    ("laload", Instruction::laload),
+    ("faload", Instruction::faload),
+    ("daload", Instruction::daload),
+    ("aaload", Instruction::aaload),
+    ("baload", Instruction::baload),
+    ("caload", Instruction::caload),
+    ("saload", Instruction::saload),
+    ("iastore", Instruction::iastore),
+    ("lastore", Instruction::lastore),
+    ("fastore", Instruction::fastore),
+    ("dastore", Instruction::dastore),
+    ("aastore", Instruction::aastore),
+    ("bastore", Instruction::bastore),
+    ("castore", Instruction::castore),
+    ("sastore", Instruction::sastore),
+    ("pop", Instruction::pop),
+    ("pop2", Instruction::pop2),
+    ("dup", Instruction::dup),
+    ("dup_x1", Instruction::dup_x1),
+    ("dup_x2", Instruction::dup_x2),
+    ("dup2", Instruction::dup2),
+    ("dup2_x1", Instruction::dup2_x1),
+    ("dup2_x2", Instruction::dup2_x2),
+    ("swap", Instruction::swap),
+    ("iadd", Instruction::iadd),
+    ("ladd", Instruction::ladd),
+    ("fadd", Instruction::fadd),
+    ("dadd", Instruction::dadd),
+    ("isub", Instruction::isub),
+    ("lsub", Instruction::lsub),
+    ("fsub", Instruction::fsub),
+    ("dsub", Instruction::dsub),
+    ("imul", Instruction::imul),
+    ("lmul", Instruction::lmul),
+    ("fmul", Instruction::fmul),
+    ("dmul", Instruction::dmul),
+    ("idiv", Instruction::idiv),
+    ("ldiv", Instruction::ldiv),
+    ("fdiv", Instruction::fdiv),
+    ("ddiv", Instruction::ddiv),
+    ("irem", Instruction::irem),
+    ("lrem", Instruction::lrem),
+    ("frem", Instruction::frem),
+    ("drem", Instruction::drem),
+    ("ineg", Instruction::ineg),
+    ("lneg", Instruction::lneg),
+    ("fneg", Instruction::fneg),
+    ("dneg", Instruction::dneg),
+    ("ishl", Instruction::ishl),
+    ("lshl", Instruction::lshl),
+    ("ishr", Instruction::ishr),
+    ("lshr", Instruction::lshr),
+    ("iushr", Instruction::iushr),
+    ("lushr", Instruction::lushr),
+    ("iand", Instruction::iand),
+    ("land", Instruction::land),
+    ("ior", Instruction::ior),
+    ("lor", Instruction::lor),
+    ("ixor", Instruction::ixor),
+    ("lxor", Instruction::lxor),
+    ("i2l", Instruction::i2l),
+    ("i2f", Instruction::i2f),
+    ("i2d", Instruction::i2d),
+    ("l2i", Instruction::l2i),
+    ("l2f", Instruction::l2f),
+    ("l2d", Instruction::l2d),
+    ("f2i", Instruction::f2i),
+    ("f2l", Instruction::f2l),
+    ("f2d", Instruction::f2d),
+    ("d2i", Instruction::d2i),
+    ("d2l", Instruction::d2l),
+    ("d2f", Instruction::d2f),
+    ("i2b", Instruction::i2b),
+    ("i2c", Instruction::i2c),
+    ("i2s", Instruction::i2s),
+    ("lcmp", Instruction::lcmp),
+    ("ireturn", Instruction::ireturn),
+    ("lreturn", Instruction::lreturn),
+    ("freturn", Instruction::freturn),
+    ("dreturn", Instruction::dreturn),
+    ("areturn", Instruction::areturn),
+    ("ret_void", Instruction::ret_void),
+    ("arraylength", Instruction::arraylength),
+    ("athrow", Instruction::athrow),
+    ("monitorenter", Instruction::monitorenter),
+    ("monitorexit", Instruction::monitorexit),
+];
+
+/// Instructions which take the index of a local variable.
+const LOCAL_INSTRUCTIONS: Mnemonics<u16> = &[
+    ("iload", Instruction::iload),
+    ("lload", Instruction::lload),
+    ("fload", Instruction::fload),
+    ("dload", Instruction::dload),
+    ("aload", Instruction::aload),
+    ("istore", Instruction::istore),
+    ("lstore", Instruction::lstore),
+    ("fstore", Instruction::fstore),
+    ("dstore", Instruction::dstore),
+    ("astore", Instruction::astore),
+    ("ret", Instruction::ret),
+];
+
+const BRANCH_INSTRUCTIONS: Mnemonics<i16> = &[
+    ("if_eq", Instruction::if_eq),
+    ("if_ne", Instruction::if_ne),
+    ("if_lt", Instruction::if_lt),
+    ("if_ge", Instruction::if_ge),
+    ("if_gt", Instruction::if_gt),
+    ("if_le", Instruction::if_le),
+    ("if_icmp_eq", Instruction::if_icmp_eq),
+    ("if_icmp_ne", Instruction::if_icmp_ne),
+    ("if_icmp_lt", Instruction::if_icmp_lt),
+    ("if_icmp_ge", Instruction::if_icmp_ge),
+    ("if_icmp_gt", Instruction::if_icmp_gt),
+    ("if_icmp_le", Instruction::if_icmp_le),
+    ("if_acmp_eq", Instruction::if_acmp_eq),
+    ("if_acmp_ne", Instruction::if_acmp_ne),
+    ("ifnull", Instruction::ifnull),
+    ("ifnonnull", Instruction::ifnonnull),
+];
+
+/// Instructions which take a class name.
+const CLASS_INSTRUCTIONS: Mnemonics<ConstantIdx> = &[
+    ("new", Instruction::new),
+    ("anewarray", Instruction::anewarray),
+    ("checkcast", Instruction::checkcast),
+    ("instanceof", Instruction::instanceof),
+];
+
+const FIELD_INSTRUCTIONS: Mnemonics<ConstantIdx> = &[
+    ("getstatic", Instruction::getstatic),
+    ("putstatic", Instruction::putstatic),
+    ("getfield", Instruction::getfield),
+    ("putfield", Instruction::putfield),
+];
+
+const METHOD_INSTRUCTIONS: Mnemonics<ConstantIdx> = &[
+    ("invokevirtual", Instruction::invokevirtual),
+    ("invokespecial", Instruction::invokespecial),
+    ("invokestatic", Instruction::invokestatic),
+];
+
+const ARRAY_KINDS: [(ArrayKind, &str); 8] = [
+    (ArrayKind::Bool, "boolean"),
+    (ArrayKind::Char, "char"),
+    (ArrayKind::Float, "float"),
+    (ArrayKind::Double, "double"),
+    (ArrayKind::Byte, "byte"),
+    (ArrayKind::Short, "short"),
+    (ArrayKind::Int, "int"),
+    (ArrayKind::Long, "long"),
+];
+
+const REFERENCE_KINDS: [(ReferenceKind, &str); 9] = [
+    (ReferenceKind::GetField, "getfield"),
+    (ReferenceKind::GetStatic, "getstatic"),
+    (ReferenceKind::PutField, "putfield"),
+    (ReferenceKind::PutStatic, "putstatic"),
+    (ReferenceKind::InvokeVirtual, "invokevirtual"),
+    (ReferenceKind::InvokeStatic, "invokestatic"),
+    (ReferenceKind::InvokeSpecial, "invokespecial"),
+    (ReferenceKind::NewInvokeSpecial, "newinvokespecial"),
+    (ReferenceKind::InvokeInterface, "invokeinterface"),
+];
+
+/// The entries of stack map frames which are not class types, along with their
+/// names.
+const FRAME_TYPES: [(VerificationType, &str); 7] = [
+    (VerificationType::Top, "top"),
+    (VerificationType::Integer, "int"),
+    (VerificationType::Float, "float"),
+    (VerificationType::Long, "long"),
+    (VerificationType::Double, "double"),
+    (VerificationType::Null, "null"),
+    (VerificationType::UninitializedThis, "uninitializedThis"),
+];
+
+/// Words which have a meaning of their own where a name may appear, so names
+/// spelled like them are quoted.
+const KEYWORDS: [&str; 15] = [
+    "all",
+    "is",
+    "signature",
+    "interface",
+    "=",
+    "locals",
+    "stack",
+    "uninitialized",
+    "top",
+    "int",
+    "float",
+    "long",
+    "double",
+    "null",
+    "uninitializedThis",
+];
+
+/// Assembles a class from its textual form, as produced by [`disassemble`].
+///
+/// The source is made up of directives, one per line, in the style of Jasmin.
+/// Comments start with a `;` at the beginning of a word, and names can be
+/// quoted with `"` when they contain whitespace or unusual characters:
+///
+/// ```text
+/// .version 52 0
+/// .class public super Counter
+/// .super java/lang/Object
+///
+/// .field private static count I
+/// .field public static final LIMIT I = int 10
+///
+/// .method public static next ()I
+///     getstatic Counter count I
+///     iconst 1
+///     iadd
+///     dup
+///     putstatic Counter count I
+///     ireturn
+/// .end method
+/// ```
+///
+/// Instructions are written with the names of the [`Instruction`] variants.
+/// Jumps refer to labels, which are defined by a line like `loop:`. Switches
+/// list their targets on the following lines, ending with a `default:` line.
+/// Exception handlers are declared anywhere in a method with `.catch <class>
+/// from <label> to <label> using <label>`, where a class of `all` catches
+/// every exception, and `.line` marks the source line of the following
+/// instructions.
+///
+/// Generic signatures are given by `.signature <signature>` after the
+/// `.class` or within a method, and by `signature <signature>` after the
+/// descriptor of a `.field`. Local variables are named with `.var <index> is
+/// <name> <descriptor> from <label> to <label>`, which adds an entry to the
+/// `LocalVariableTable`, or with `signature <signature>` in place of the
+/// descriptor, which adds one to the `LocalVariableTypeTable`.
+///
+/// Each `.bootstrap <method handle> <arguments>` adds an entry to the
+/// `BootstrapMethods` attribute, where the method handle and arguments are
+/// constants and entries are numbered from 0. These numbers are used by
+/// `invokedynamic <bootstrap method> <name> <descriptor>` and by constants
+/// such as `dynamic <bootstrap method> <name> <descriptor>`.
+///
+/// Unless a method gives both `.limit stack` and `.limit locals`, its limits
+/// and `StackMapTable` are computed as described by [`CodeBuilder`]. With
+/// limits, the code is taken as written, which allows invalid code to be
+/// assembled.
+///
+/// Stack map frames can be written by hand with `.stack`, which gives the
+/// frame of the following instruction as a list of locals and a list of
+/// operand stack entries, such as `.stack locals int java/lang/String stack
+/// long`. An entry is one of `top`, `int`, `float`, `long`, `double`,
+/// `null`, `uninitializedThis`, `uninitialized <label>` for the object
+/// created by the `new` instruction at the label, or the name of a class or
+/// array type. Once a method has a `.stack`, its `StackMapTable` holds
+/// exactly the frames written, whether or not it gives its limits.
+///
+/// # Errors
+///
+/// Returns an [`AssembleError`] describing the first line which could not be
+/// assembled.
+pub fn assemble(source: &str) -> std::result::Result<Class, AssembleError> {
+    assemble_class(source, None)
+}
+
+/// Assembles a class like [`assemble`], where the generated stack map frames
+/// use `common_superclass` to find the closest common superclass of two
+/// classes, which is needed when values of different classes merge. Without
+/// it, their type is widened to `java/lang/Object`.
+///
+/// # Errors
+///
+/// Returns an [`AssembleError`] describing the first line which could not be
+/// assembled.
+pub fn assemble_with(
+    source: &str,
+    common_superclass: impl Fn(&JavaStr, &JavaStr) -> JavaString + 'static,
+) -> std::result::Result<Class, AssembleError> {
+    assemble_class(source, Some(Box::new(common_superclass)))
+}
+
+fn assemble_class(
+    source: &str,
+    common_superclass: Option<Box<CommonSuperclass>>,
+) -> std::result::Result<Class, AssembleError> {
+    let mut assembler = Assembler {
+        lines: source.lines().enumerate(),
+        line: 0,
+        version: None,
+        class: None,
+        common_superclass,
+        bootstrap_methods: 0,
+        bootstrap_uses: HashMap::new(),
+    };
+    assembler.assemble().map_err(|kind| AssembleError {
+        line: assembler.line,
+        kind,
+    })
+}
+
+/// Writes the textual form of a class, which can be turned back into the
+/// class with [`assemble`].
+///
+/// Only the parts of a class which [`assemble`] can express are written, so
+/// attributes other than those it describes are dropped. The limits of
+/// methods are written along with the frames of their `StackMapTable`, so
+/// that the class assembled again has the same frames.
+pub fn disassemble(class: &Class) -> String {
+    let constants = class.constants();
+    let mut out = String::new();
+
+    let (major, minor) = class.version();
+    writeln!(out, ".version {major} {minor}").unwrap();
+    out.push_str(".class ");
+    write_flags(
+        &mut out,
+        class.flags().bits(),
+        ClassFlags::FLAGS.map(|(f, n)| (f.bits(), n)),
+    );
+    write_name(&mut out, class.name());
+    out.push('\n');
+    if let Some(super_name) = class.super_name() {
+        out.push_str(".super ");
+        write_name(&mut out, super_name);
+        out.push('\n');
+    }
+    for interface in class.interfaces() {
+        out.push_str(".implements ");
+        write_name(&mut out, interface);
+        out.push('\n');
+    }
+    if let Some(signature) = class.signature() {
+        out.push_str(".signature ");
+        write_name(&mut out, signature);
+        out.push('\n');
+    }
+    if let Some(source_file) = class.source_file() {
+        out.push_str(".source ");
+        write_quoted(&mut out, source_file);
+        out.push('\n');
+    }
+    for (method_handle, arguments) in bootstrap_methods(class) {
+        out.push_str(".bootstrap ");
+        write_constant(&mut out, method_handle, constants);
+        for argument in arguments {
+            out.push(' ');
+            write_constant(&mut out, argument, constants);
+        }
+        out.push('\n');
+    }
+
+    if !class.fields().is_empty() {
+        out.push('\n');
+    }
+    for field in class.fields() {
+        out.push_str(".field ");
+        write_flags(
+            &mut out,
+            field.flags().bits(),
+            FieldFlags::FLAGS.map(|(f, n)| (f.bits(), n)),
+        );
+        write_name(&mut out, field.name(constants));
+        out.push(' ');
+        write_name(&mut out, field.descriptor(constants));
+        if let Some(signature) = field.signature(constants) {
+            out.push_str(" signature ");
+            write_name(&mut out, signature);
+        }
+        if let Some(value) = field.constant_value() {
+            out.push_str(" = ");
+            write_constant(&mut out, value, constants);
+        }
+        out.push('\n');
+    }
+
+    for method in class.methods() {
+        out.push('\n');
+        write_method(&mut out, class.name(), method, constants);
+    }
+
+    out
+}
+
+struct Assembler<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    /// The number of the line being assembled.
+    line: usize,
+    version: Option<(u16, u16)>,
+    class: Option<ClassBuilder>,
+    common_superclass: Option<Box<CommonSuperclass>>,
+    /// The number of `.bootstrap` directives seen so far.
+    bootstrap_methods: u16,
+    /// The bootstrap methods referred to, along with the line of their first
+    /// use.
+    bootstrap_uses: HashMap<u16, usize>,
+}
+
+/// The state of the method being assembled.
+struct MethodState {
+    code: CodeBuilder,
+    has_code: bool,
+    max_stack: Option<u16>,
+    max_locals: Option<u16>,
+    signature: Option<JavaString>,
+    labels: HashMap<String, LabelState>,
+}
+
+struct LabelState {
+    label: Label,
+    defined: bool,
+    /// The line the label was first used on, if it was used.
+    used_at: Option<usize>,
+}
+
+impl<'a> Assembler<'a> {
+    fn assemble(&mut self) -> Result<Class> {
+        while let Some(mut tokens) = self.next_line()? {
+            match tokens.word()? {
+                ".version" => {
+                    if self.class.is_some() {
+                        return Err(AssembleErrorKind::UnexpectedDirective);
+                    }
+                    self.version = Some((tokens.number()?, tokens.number()?));
+                }
+                ".class" => {
+                    if self.class.is_some() {
+                        return Err(AssembleErrorKind::UnexpectedDirective);
+                    }
+                    let (flags, mut operands) = tokens.split_flags(1)?;
+                    let flags = parse_flags(&flags, ClassFlags::FLAGS.map(|(f, n)| (f.bits(), n)))?;
+                    let mut class = ClassBuilder::new(&operands.name()?);
+                    class.flags(ClassFlags::from_bits(flags)).super_class(None);
+                    if let Some((major, minor)) = self.version {
+                        class.version(major, minor);
+                    }
+                    if let Some(common_superclass) = self.common_superclass.take() {
+                        class.common_superclass(common_superclass);
+                    }
+                    self.class = Some(class);
+                }
+                ".super" => {
+                    let name = tokens.name()?;
+                    self.class()?.super_class(Some(&name));
+                }
+                ".implements" => {
+                    let name = tokens.name()?;
+                    self.class()?.interface(&name);
+                }
+                ".source" => {
+                    let name = tokens.name()?;
+                    self.class()?.source_file(&name);
+                }
+                ".signature" => {
+                    let signature = tokens.name()?;
+                    self.class()?.signature(&signature);
+                }
+                ".bootstrap" => {
+                    let method_handle = self.constant(&mut tokens)?;
+                    let mut arguments = Vec::new();
+                    while !tokens.is_empty() {
+                        arguments.push(self.constant(&mut tokens)?);
+                    }
+                    self.bootstrap_methods =
+                        self.class()?.bootstrap_method(method_handle, &arguments) + 1;
+                }
+                ".field" => {
+                    let value = tokens.split_at_word("=");
+                    let signature = tokens
+                        .split_at_word("signature")
+                        .map(|mut signature| {
+                            let name = signature.name()?;
+                            signature.end()?;
+                            Ok(name)
+                        })
+                        .transpose()?;
+                    let (flags, mut operands) = tokens.split_flags(2)?;
+                    let flags = parse_flags(&flags, FieldFlags::FLAGS.map(|(f, n)| (f.bits(), n)))?;
+                    let flags = FieldFlags::from_bits(flags);
+                    let name = operands.name()?;
+                    let descriptor = operands.field_type()?;
+                    match value {
+                        Some(mut value) => {
+                            let value = self.constant(&mut value)?;
+                            self.class()?
+                                .constant_field(flags, &name, &descriptor, value);
+                        }
+                        None => {
+                            self.class()?.field(flags, &name, &descriptor);
+                        }
+                    }
+                    if let Some(signature) = signature {
+                        self.class()?.field_signature(&signature);
+                    }
+                }
+                ".method" => {
+                    let (flags, mut operands) = tokens.split_flags(2)?;
+                    let flags =
+                        parse_flags(&flags, MethodFlags::FLAGS.map(|(f, n)| (f.bits(), n)))?;
+                    let flags = MethodFlags::from_bits(flags);
+                    let name = operands.name()?;
+                    let descriptor = operands.method_descriptor()?;
+                    self.class()?;
+                    self.method(flags, &name, &descriptor)?;
+                }
+                directive if directive.starts_with('.') => {
+                    return Err(AssembleErrorKind::UnknownDirective)
+                }
+                _ => return Err(AssembleErrorKind::UnexpectedToken),
+            }
+            tokens.end()?;
+        }
+
+        let class = self.class.take().ok_or(AssembleErrorKind::MissingClass)?;
+        let undefined = self
+            .bootstrap_uses
+            .iter()
+            .filter(|(&index, _)| index >= self.bootstrap_methods)
+            .map(|(_, &line)| line)
+            .min();
+        if let Some(line) = undefined {
+            self.line = line;
+            return Err(AssembleErrorKind::UndefinedBootstrapMethod);
+        }
+        Ok(class.build())
+    }
+
+    /// Returns the tokens of the next line which is not blank or a comment.
+    fn next_line(&mut self) -> Result<Option<Tokens<'a>>> {
+        for (number, line) in self.lines.by_ref() {
+            self.line = number + 1;
+            let tokens = tokenize(line)?;
+            if !tokens.is_empty() {
+                return Ok(Some(tokens));
+            }
+        }
+        Ok(None)
+    }
+
+    fn class(&mut self) -> Result<&mut ClassBuilder> {
+        self.class
+            .as_mut()
+            .ok_or(AssembleErrorKind::UnexpectedDirective)
+    }
+
+    fn method(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+    ) -> Result<()> {
+        let start = self.line;
+        let mut method = MethodState {
+            code: CodeBuilder::new(),
+            has_code: false,
+            max_stack: None,
+            max_locals: None,
+            signature: None,
+            labels: HashMap::new(),
+        };
+
+        loop {
+            let line = self.line;
+            let Some(mut tokens) = self.next_line()? else {
+                self.line = line;
+                return Err(AssembleErrorKind::UnexpectedEndOfFile);
+            };
+            let mut word = tokens.word()?;
+            if let Some(label) = word.strip_suffix(':') {
+                method.define(label)?;
+                if tokens.is_empty() {
+                    continue;
+                }
+                word = tokens.word()?;
+            }
+
+            match word {
+                ".end" => {
+                    tokens.keyword("method")?;
+                    tokens.end()?;
+                    break;
+                }
+                ".limit" => {
+                    method.has_code = true;
+                    match tokens.word()? {
+                        "stack" => method.max_stack = Some(tokens.number()?),
+                        "locals" => method.max_locals = Some(tokens.number()?),
+                        _ => return Err(AssembleErrorKind::UnexpectedToken),
+                    }
+                }
+                ".catch" => {
+                    let catch_type = match tokens.next()? {
+                        Token::Word("all") => None,
+                        token => {
+                            let name = token.into_name();
+                            Some(self.class()?.constants().class(&name))
+                        }
+                    };
+                    tokens.keyword("from")?;
+                    let start = method.label(&mut tokens, self.line)?;
+                    tokens.keyword("to")?;
+                    let end = method.label(&mut tokens, self.line)?;
+                    tokens.keyword("using")?;
+                    let handler = method.label(&mut tokens, self.line)?;
+                    method.code.try_catch(start, end, handler, catch_type);
+                }
+                ".line" => {
+                    method.code.line_number(tokens.number()?);
+                }
+                ".signature" => {
+                    method.signature = Some(tokens.name()?);
+                }
+                ".var" => {
+                    method.has_code = true;
+                    let index = tokens.number()?;
+                    tokens.keyword("is")?;
+                    let name = tokens.name()?;
+                    let is_signature = tokens.keyword("signature").is_ok();
+                    let descriptor = tokens.name()?;
+                    tokens.keyword("from")?;
+                    let start = method.label(&mut tokens, self.line)?;
+                    tokens.keyword("to")?;
+                    let end = method.label(&mut tokens, self.line)?;
+                    let constants = self.class()?.constants();
+                    let (name, descriptor) = (constants.utf8(&name), constants.utf8(&descriptor));
+                    if is_signature {
+                        method
+                            .code
+                            .local_variable_type(start, end, index, name, descriptor);
+                    } else {
+                        method
+                            .code
+                            .local_variable(start, end, index, name, descriptor);
+                    }
+                }
+                ".stack" => {
+                    let line = self.line;
+                    let mut locals = Vec::new();
+                    let mut stack = Vec::new();
+                    let mut entries = &mut locals;
+                    if tokens.keyword("locals").is_err() && tokens.keyword("stack").is_ok() {
+                        entries = &mut stack;
+                    }
+                    while !tokens.is_empty() {
+                        if tokens.keyword("stack").is_ok() {
+                            entries = &mut stack;
+                            continue;
+                        }
+                        let entry = match tokens.next()? {
+                            Token::Word("uninitialized") => {
+                                FrameEntry::Uninitialized(method.label(&mut tokens, line)?)
+                            }
+                            Token::Word(word) => {
+                                match FRAME_TYPES.iter().find(|(_, name)| *name == word) {
+                                    Some((verification_type, _)) => {
+                                        FrameEntry::Type(verification_type.clone())
+                                    }
+                                    None => FrameEntry::Type(VerificationType::Object(
+                                        from_utf8(word).into_owned(),
+                                    )),
+                                }
+                            }
+                            Token::Quoted(name) => FrameEntry::Type(VerificationType::Object(name)),
+                        };
+                        entries.push(entry);
+                    }
+                    method.code.frame(locals, stack);
+                }
+                directive if directive.starts_with('.') => {
+                    return Err(AssembleErrorKind::UnexpectedDirective)
+                }
+                mnemonic => {
+                    method.has_code = true;
+                    self.instruction(&mut method, mnemonic, &mut tokens)?;
+                }
+            }
+            tokens.end()?;
+        }
+
+        for label in method.labels.values() {
+            if let (false, Some(used_at)) = (label.defined, label.used_at) {
+                self.line = used_at;
+                return Err(AssembleErrorKind::UndefinedLabel);
+            }
+        }
+
+        let class = self.class()?;
+        if !method.has_code {
+            class.abstract_method(flags, name, descriptor);
+            if let Some(signature) = &method.signature {
+                class.method_signature(signature);
+            }
+            return Ok(());
+        }
+        match (method.max_stack, method.max_locals) {
+            (Some(max_stack), Some(max_locals)) => {
+                method.code.limits(max_stack, max_locals);
+            }
+            (None, None) => {}
+            _ => {
+                self.line = start;
+                return Err(AssembleErrorKind::MissingLimit);
+            }
+        }
+        if let Err(error) = class.method(flags, name, descriptor, method.code) {
+            self.line = start;
+            return Err(AssembleErrorKind::Build(error));
+        }
+        if let Some(signature) = &method.signature {
+            class.method_signature(signature);
+        }
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        method: &mut MethodState,
+        mnemonic: &str,
+        tokens: &mut Tokens,
+    ) -> Result<()> {
+        let line = self.line;
+
+        if let Some((_, instruction)) = find(SIMPLE_INSTRUCTIONS, mnemonic) {
+            method.code.emit(instruction);
+        } else if let Some((_, instruction)) = find(LOCAL_INSTRUCTIONS, mnemonic) {
+            method.code.emit(instruction(tokens.number()?));
+        } else if let Some((_, instruction)) = find(BRANCH_INSTRUCTIONS, mnemonic) {
+            let target = method.label(tokens, line)?;
+            method.code.branch(instruction, target);
+        } else if let Some((_, instruction)) = find(CLASS_INSTRUCTIONS, mnemonic) {
+            let name = tokens.name()?;
+            let class = self.class()?.constants().class(&name);
+            method.code.emit(instruction(class));
+        } else if let Some((_, instruction)) = find(FIELD_INSTRUCTIONS, mnemonic) {
+            let field = self.field_ref(tokens)?;
+            method.code.emit(instruction(field));
+        } else if let Some((_, instruction)) = find(METHOD_INSTRUCTIONS, mnemonic) {
+            let interface = tokens.keyword("interface").is_ok();
+            let reference = self.method_ref(tokens, interface)?;
+            method.code.emit(instruction(reference));
+        } else {
+            let instruction = match mnemonic {
+                "iconst" => Instruction::iconst(tokens.number()?),
+                "lconst" => Instruction::lconst(tokens.number()?),
+                "fconst" => Instruction::fconst(tokens.number()?),
+                "dconst" => Instruction::dconst(tokens.number()?),
+                "bipush" => Instruction::bipush(tokens.number()?),
+                "sipush" => Instruction::sipush(tokens.number()?),
+                "ldc" => Instruction::ldc(self.constant(tokens)?),
+                "fcmp" | "dcmp" => {
+                    let greater = match tokens.word()? {
+                        "l" => false,
+                        "g" => true,
+                        _ => return Err(AssembleErrorKind::UnexpectedToken),
+                    };
+                    if mnemonic == "fcmp" {
+                        Instruction::fcmp(greater)
+                    } else {
+                        Instruction::dcmp(greater)
+                    }
+                }
+                "goto" | "jsr" => {
+                    let target = method.label(tokens, line)?;
+                    if mnemonic == "goto" {
+                        method.code.goto(target);
+                    } else {
+                        method.code.jsr(target);
+                    }
+                    return Ok(());
+                }
+                "tableswitch" => {
+                    let low = tokens.number()?;
+                    tokens.end()?;
+                    let mut targets = Vec::new();
+                    let default = loop {
+                        let mut tokens = self.switch_line()?;
+                        let line = self.line;
+                        if tokens.keyword("default:").is_ok() {
+                            let default = method.label(&mut tokens, line)?;
+                            tokens.end()?;
+                            break default;
+                        }
+                        targets.push(method.label(&mut tokens, line)?);
+                        tokens.end()?;
+                    };
+                    method.code.tableswitch(low, &targets, default);
+                    return Ok(());
+                }
+                "lookupswitch" => {
+                    tokens.end()?;
+                    let mut pairs = Vec::new();
+                    let default = loop {
+                        let mut tokens = self.switch_line()?;
+                        let line = self.line;
+                        let key = tokens.word()?;
+                        let key = key
+                            .strip_suffix(':')
+                            .ok_or(AssembleErrorKind::UnexpectedToken)?;
+                        let target = method.label(&mut tokens, line)?;
+                        tokens.end()?;
+                        if key == "default" {
+                            break target;
+                        }
+                        let key = key.parse().map_err(|_| AssembleErrorKind::InvalidNumber)?;
+                        pairs.push((key, target));
+                    };
+                    method.code.lookupswitch(&pairs, default);
+                    return Ok(());
+                }
+                "invokeinterface" => {
+                    let method = self.method_ref(tokens, true)?;
+                    let constants = self.class()?.constants().constants();
+                    let (_, name_type) = constants.get(method).into_ref();
+                    let (_, descriptor) = constants.get(name_type).into_name_type();
+                    let descriptor = MethodDescriptor::parse(constants.get(descriptor).into_utf8())
+                        .map_err(|_| AssembleErrorKind::InvalidDescriptor)?;
+                    let count = u8::try_from(descriptor.arg_slots(false))
+                        .map_err(|_| AssembleErrorKind::InvalidDescriptor)?;
+                    Instruction::invokeinterface(method, count)
+                }
+                "invokedynamic" => {
+                    let (bootstrap_method, name, descriptor) = self.dynamic(tokens)?;
+                    let call_site = self.class()?.constants().invoke_dynamic(
+                        bootstrap_method,
+                        &name,
+                        &descriptor,
+                    );
+                    Instruction::invokedynamic(call_site)
+                }
+                "newarray" => {
+                    let word = tokens.word()?;
+                    let (kind, _) = ARRAY_KINDS
+                        .into_iter()
+                        .find(|(_, name)| *name == word)
+                        .ok_or(AssembleErrorKind::UnexpectedToken)?;
+                    Instruction::newarray(kind)
+                }
+                "multianewarray" => {
+                    let name = tokens.name()?;
+                    let class = self.class()?.constants().class(&name);
+                    Instruction::multianewarray(class, tokens.number()?)
+                }
+                "iinc" => Instruction::iinc(tokens.number()?, tokens.number()?),
+                _ => return Err(AssembleErrorKind::UnknownInstruction),
+            };
+            method.code.emit(instruction);
+        }
+        Ok(())
+    }
+
+    /// Returns the next line of a switch, which must exist.
+    fn switch_line(&mut self) -> Result<Tokens<'a>> {
+        let line = self.line;
+        match self.next_line()? {
+            Some(tokens) => Ok(tokens),
+            None => {
+                self.line = line;
+                Err(AssembleErrorKind::UnexpectedEndOfFile)
+            }
+        }
+    }
+
+    /// Parses a constant, such as `int 5` or `string "hello"`.
+    fn constant(&mut self, tokens: &mut Tokens) -> Result<ConstantIdx> {
+        let kind = tokens.word()?;
+        let constant = match kind {
+            "int" => self.class()?.constants().integer(tokens.number()?),
+            "long" => self.class()?.constants().long(tokens.number()?),
+            "float" => self.class()?.constants().float(tokens.number()?),
+            "double" => self.class()?.constants().double(tokens.number()?),
+            "string" => {
+                let value = tokens.name()?;
+                self.class()?.constants().string(&value)
+            }
+            "class" => {
+                let name = tokens.name()?;
+                self.class()?.constants().class(&name)
+            }
+            "methodtype" => {
+                let descriptor = tokens.name()?;
+                self.class()?.constants().method_type(&descriptor)
+            }
+            "methodhandle" => {
+                let word = tokens.word()?;
+                let (kind, _) = REFERENCE_KINDS
+                    .into_iter()
+                    .find(|(_, name)| *name == word)
+                    .ok_or(AssembleErrorKind::UnexpectedToken)?;
+                let reference = match kind {
+                    ReferenceKind::GetField
+                    | ReferenceKind::GetStatic
+                    | ReferenceKind::PutField
+                    | ReferenceKind::PutStatic => self.field_ref(tokens)?,
+                    ReferenceKind::InvokeInterface => self.method_ref(tokens, true)?,
+                    _ => {
+                        let interface = tokens.keyword("interface").is_ok();
+                        self.method_ref(tokens, interface)?
+                    }
+                };
+                self.class()?.constants().method_handle(kind, reference)
+            }
+            "dynamic" => {
+                let (bootstrap_method, name, descriptor) = self.dynamic(tokens)?;
+                self.class()?
+                    .constants()
+                    .dynamic(bootstrap_method, &name, &descriptor)
+            }
+            _ => return Err(AssembleErrorKind::UnexpectedToken),
+        };
+        Ok(constant)
+    }
+
+    /// Parses the operands of a dynamic call site or constant, of the form
+    /// `<bootstrap method> <name> <descriptor>`.
+    fn dynamic(&mut self, tokens: &mut Tokens) -> Result<(u16, JavaString, JavaString)> {
+        let bootstrap_method = tokens.number()?;
+        self.bootstrap_uses
+            .entry(bootstrap_method)
+            .or_insert(self.line);
+        Ok((bootstrap_method, tokens.name()?, tokens.name()?))
+    }
+
+    /// Parses a field reference of the form `<class> <name> <descriptor>`.
+    fn field_ref(&mut self, tokens: &mut Tokens) -> Result<ConstantIdx> {
+        let (class, name, descriptor) = (tokens.name()?, tokens.name()?, tokens.name()?);
+        Ok(self
+            .class()?
+            .constants()
+            .field_ref(&class, &name, &descriptor))
+    }
+
+    /// Parses a method reference of the form `<class> <name> <descriptor>`.
+    fn method_ref(&mut self, tokens: &mut Tokens, interface: bool) -> Result<ConstantIdx> {
+        let (class, name, descriptor) = (tokens.name()?, tokens.name()?, tokens.name()?);
+        let constants = self.class()?.constants();
+        if interface {
+            Ok(constants.interface_method_ref(&class, &name, &descriptor))
+        } else {
+            Ok(constants.method_ref(&class, &name, &descriptor))
+        }
+    }
+}
+
+impl MethodState {
+    /// Returns the label named by the next token, which was used on `line`.
+    fn label(&mut self, tokens: &mut Tokens, line: usize) -> Result<Label> {
+        let name = tokens.word()?;
+        let code = &mut self.code;
+        let state = self
+            .labels
+            .entry(name.to_owned())
+            .or_insert_with(|| LabelState {
+                label: code.label(),
+                defined: false,
+                used_at: None,
+            });
+        state.used_at.get_or_insert(line);
+        Ok(state.label)
+    }
+
+    fn define(&mut self, name: &str) -> Result<()> {
+        let code = &mut self.code;
+        let state = self
+            .labels
+            .entry(name.to_owned())
+            .or_insert_with(|| LabelState {
+                label: code.label(),
+                defined: false,
+                used_at: None,
+            });
+        if state.defined {
+            return Err(AssembleErrorKind::DuplicateLabel);
+        }
+        state.defined = true;
+        code.bind(state.label);
+        Ok(())
+    }
+}
+
+enum Token<'a> {
+    Word(&'a str),
+    Quoted(JavaString),
+}
+
+impl Token<'_> {
+    fn into_name(self) -> JavaString {
+        match self {
+            Token::Word(word) => from_utf8(word).into_owned(),
+            Token::Quoted(string) => string,
+        }
+    }
+}
+
+/// The remaining tokens of a line.
+struct Tokens<'a> {
+    tokens: VecDeque<Token<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn next(&mut self) -> Result<Token<'a>> {
+        self.tokens
+            .pop_front()
+            .ok_or(AssembleErrorKind::UnexpectedEndOfLine)
+    }
+
+    fn word(&mut self) -> Result<&'a str> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            Token::Quoted(_) => Err(AssembleErrorKind::UnexpectedToken),
+        }
+    }
+
+    /// Consumes the next token if it is the unquoted `keyword`.
+    fn keyword(&mut self, keyword: &str) -> Result<()> {
+        match self.tokens.front() {
+            Some(Token::Word(word)) if *word == keyword => {
+                self.tokens.pop_front();
+                Ok(())
+            }
+            Some(_) => Err(AssembleErrorKind::UnexpectedToken),
+            None => Err(AssembleErrorKind::UnexpectedEndOfLine),
+        }
+    }
+
+    fn name(&mut self) -> Result<JavaString> {
+        Ok(self.next()?.into_name())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T> {
+        self.word()?
+            .parse()
+            .map_err(|_| AssembleErrorKind::InvalidNumber)
+    }
+
+    fn field_type(&mut self) -> Result<FieldType> {
+        FieldType::parse(&self.name()?).map_err(|_| AssembleErrorKind::InvalidDescriptor)
+    }
+
+    fn method_descriptor(&mut self) -> Result<MethodDescriptor> {
+        MethodDescriptor::parse(&self.name()?).map_err(|_| AssembleErrorKind::InvalidDescriptor)
+    }
+
+    /// Splits the tokens into flags and the last `operands` tokens.
+    fn split_flags(&mut self, operands: usize) -> Result<(Vec<Token<'a>>, Tokens<'a>)> {
+        let count = self
+            .tokens
+            .len()
+            .checked_sub(operands)
+            .ok_or(AssembleErrorKind::UnexpectedEndOfLine)?;
+        let operands = self.tokens.split_off(count);
+        let flags = std::mem::take(&mut self.tokens).into();
+        Ok((flags, Tokens { tokens: operands }))
+    }
+
+    /// Splits off the tokens after the unquoted `word`, if it is present.
+    fn split_at_word(&mut self, word: &str) -> Option<Tokens<'a>> {
+        let position = self
+            .tokens
+            .iter()
+            .position(|token| matches!(token, Token::Word(w) if *w == word))?;
+        let rest = self.tokens.split_off(position + 1);
+        self.tokens.pop_back();
+        Some(Tokens { tokens: rest })
+    }
+
+    fn end(&self) -> Result<()> {
+        if self.tokens.is_empty() {
+            Ok(())
+        } else {
+            Err(AssembleErrorKind::UnexpectedToken)
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Tokens<'_>> {
+    let mut tokens = VecDeque::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() && !rest.starts_with(';') {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let (string, remaining) = parse_quoted(quoted)?;
+            tokens.push_back(Token::Quoted(string));
+            rest = remaining;
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push_back(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(Tokens { tokens })
+}
+
+/// Parses a quoted string up to its closing `"`, returning the string and the
+/// rest of the line.
+fn parse_quoted(quoted: &str) -> Result<(JavaString, &str)> {
+    let mut bytes = Vec::new();
+    let mut chars = quoted.chars();
+    loop {
+        let code_point = match chars.next().ok_or(AssembleErrorKind::InvalidString)? {
+            '"' => {
+                let string = JavaStr::from_java(&bytes)
+                    .map_err(|_| AssembleErrorKind::InvalidString)?
+                    .to_owned();
+                return Ok((string, chars.as_str()));
+            }
+            '\\' => match chars.next() {
+                Some('"') => '"' as u32,
+                Some('\\') => '\\' as u32,
+                Some('n') => '\n' as u32,
+                Some('r') => '\r' as u32,
+                Some('t') => '\t' as u32,
+                // The code point may be a lone surrogate, which Java strings
+                // can hold.
+                Some('u') => {
+                    let rest = chars
+                        .as_str()
+                        .strip_prefix('{')
+                        .ok_or(AssembleErrorKind::InvalidString)?;
+                    let end = rest.find('}').ok_or(AssembleErrorKind::InvalidString)?;
+                    chars = rest[end + 1..].chars();
+                    u32::from_str_radix(&rest[..end], 16)
+                        .ok()
+                        .filter(|&code_point| code_point <= 0x10FFFF)
+                        .ok_or(AssembleErrorKind::InvalidString)?
+                }
+                _ => return Err(AssembleErrorKind::InvalidString),
+            },
+            c => c as u32,
+        };
+        push_code_point(&mut bytes, code_point);
+    }
+}
+
+/// Appends `code_point` to `bytes` in Modified UTF-8, where supplementary
+/// characters are encoded as a surrogate pair.
+fn push_code_point(bytes: &mut Vec<u8>, code_point: u32) {
+    match code_point {
+        0x01..=0x7F => bytes.push(code_point as u8),
+        0x00 | 0x80..=0x7FF => {
+            bytes.push(0xC0 | (code_point >> 6) as u8);
+            bytes.push(0x80 | (code_point & 0x3F) as u8);
+        }
+        0x800..=0xFFFF => {
+            bytes.push(0xE0 | (code_point >> 12) as u8);
+            bytes.push(0x80 | ((code_point >> 6) & 0x3F) as u8);
+            bytes.push(0x80 | (code_point & 0x3F) as u8);
+        }
+        _ => {
+            let code_point = code_point - 0x10000;
+            push_code_point(bytes, 0xD800 | (code_point >> 10));
+            push_code_point(bytes, 0xDC00 | (code_point & 0x3FF));
+        }
+    }
+}
+
+/// Parses flags given by their lowercase names, or as hexadecimal bits such as
+/// `0x8000`.
+fn parse_flags<const N: usize>(tokens: &[Token], names: [(u16, &str); N]) -> Result<u16> {
+    let mut bits = 0;
+    for token in tokens {
+        let Token::Word(word) = token else {
+            return Err(AssembleErrorKind::UnknownFlag);
+        };
+        if let Some(hex) = word.strip_prefix("0x") {
+            bits |= u16::from_str_radix(hex, 16).map_err(|_| AssembleErrorKind::UnknownFlag)?;
+        } else {
+            let (flag, _) = names
+                .into_iter()
+                .find(|(_, name)| name.to_ascii_lowercase() == *word)
+                .ok_or(AssembleErrorKind::UnknownFlag)?;
+            bits |= flag;
+        }
+    }
+    Ok(bits)
+}
+
+fn find<T: Copy>(table: &[(&'static str, T)], mnemonic: &str) -> Option<(&'static str, T)> {
+    table.iter().copied().find(|(name, _)| *name == mnemonic)
+}
+
+/// Finds the entry of `table` whose instruction is the same variant as
+/// `instruction`, where `example` constructs an instruction from the table.
+fn find_variant<T: Copy>(
+    table: &[(&'static str, T)],
+    instruction: &Instruction,
+    example: impl Fn(T) -> Instruction<'static>,
+) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(_, entry)| discriminant(&example(*entry)) == discriminant(instruction))
+        .map(|(name, _)| *name)
+}
+
+fn write_flags<const N: usize>(out: &mut String, bits: u16, names: [(u16, &str); N]) {
+    let mut remaining = bits;
+    for (flag, name) in names {
+        if bits & flag == flag {
+            out.push_str(&name.to_ascii_lowercase());
+            out.push(' ');
+            remaining &= !flag;
+        }
+    }
+    if remaining != 0 {
+        write!(out, "{remaining:#06x} ").unwrap();
+    }
+}
+
+/// Writes a name, quoting it if it would not be read back as a single word.
+fn write_name(out: &mut String, name: &JavaStr) {
+    let plain = !name.is_empty()
+        && !KEYWORDS.iter().any(|keyword| name == *keyword)
+        && !name.as_bytes().starts_with(b";")
+        && !name.as_bytes().starts_with(b"\"")
+        && name
+            .chars()
+            .all(|c| char::from_u32(c).is_some_and(|c| !c.is_whitespace() && !c.is_control()));
+    if plain {
+        write!(out, "{name}").unwrap();
+    } else {
+        write_quoted(out, name);
+    }
+}
+
+fn write_quoted(out: &mut String, string: &JavaStr) {
+    out.push('"');
+    for code_point in string.chars() {
+        match char::from_u32(code_point) {
+            Some('"') => out.push_str("\\\""),
+            Some('\\') => out.push_str("\\\\"),
+            Some('\n') => out.push_str("\\n"),
+            Some('\r') => out.push_str("\\r"),
+            Some('\t') => out.push_str("\\t"),
+            Some(c) if !c.is_control() => out.push(c),
+            _ => write!(out, "\\u{{{code_point:x}}}").unwrap(),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes a constant in the form read by [`Assembler::constant`].
+fn write_constant(out: &mut String, index: ConstantIdx, constants: &ConstantPool) {
+    match *constants.get(index) {
+        Entry::Integer(value) => write!(out, "int {value}").unwrap(),
+        Entry::Float(value) => write!(out, "float {value:?}").unwrap(),
+        Entry::Long(value) => write!(out, "long {value}").unwrap(),
+        Entry::Double(value) => write!(out, "double {value:?}").unwrap(),
+        Entry::String(value) => {
+            out.push_str("string ");
+            write_quoted(out, constants.get(value).into_utf8());
+        }
+        Entry::Class(name) => {
+            out.push_str("class ");
+            write_name(out, constants.get(name).into_utf8());
+        }
+        Entry::MethodType(descriptor) => {
+            out.push_str("methodtype ");
+            write_name(out, constants.get(descriptor).into_utf8());
+        }
+        Entry::MethodHandle(kind, reference) => {
+            let (_, name) = REFERENCE_KINDS
+                .into_iter()
+                .find(|(k, _)| *k == kind)
+                .unwrap();
+            write!(out, "methodhandle {name} ").unwrap();
+            if kind != ReferenceKind::InvokeInterface {
+                write_interface(out, reference, constants);
+            }
+            write_member(out, reference, constants);
+        }
+        Entry::Dynamic(bootstrap_method, name_type) => {
+            write!(out, "dynamic {bootstrap_method} ").unwrap();
+            write_name_type(out, name_type, constants);
+        }
+        _ => write!(out, "invalid {}", index.get()).unwrap(),
+    }
+}
+
+/// Writes the `interface` keyword if `reference` is an interface method.
+fn write_interface(out: &mut String, reference: ConstantIdx, constants: &ConstantPool) {
+    if let Entry::InterfaceMethodRef(..) = constants.get(reference) {
+        out.push_str("interface ");
+    }
+}
+
+/// Writes a field or method reference as `<class> <name> <descriptor>`.
+fn write_member(out: &mut String, reference: ConstantIdx, constants: &ConstantPool) {
+    let (class, name_type) = constants.get(reference).into_ref();
+    write_name(
+        out,
+        constants.get(constants.get(class).into_class()).into_utf8(),
+    );
+    out.push(' ');
+    write_name_type(out, name_type, constants);
+}
+
+fn write_name_type(out: &mut String, name_type: ConstantIdx, constants: &ConstantPool) {
+    let (name, descriptor) = constants.get(name_type).into_name_type();
+    write_name(out, constants.get(name).into_utf8());
+    out.push(' ');
+    write_name(out, constants.get(descriptor).into_utf8());
+}
+
+/// Returns the method handle and arguments of each entry of the
+/// `BootstrapMethods` attribute of `class`, or none if it has no such
+/// attribute or the attribute is malformed.
+fn bootstrap_methods(class: &Class) -> Vec<(ConstantIdx, Vec<ConstantIdx>)> {
+    let constants = class.constants();
+    let Some(bytes) = class
+        .attributes()
+        .iter()
+        .find_map(|attribute| match &attribute.kind {
+            AttributeKind::Raw(bytes)
+                if constants.get(attribute.name).into_utf8() == "BootstrapMethods" =>
+            {
+                Some(bytes)
+            }
+            _ => None,
+        })
+    else {
+        return Vec::new();
+    };
+
+    read_bootstrap_methods(&mut Reader::new(bytes), constants).unwrap_or_default()
+}
+
+fn read_bootstrap_methods(
+    reader: &mut Reader,
+    constants: &ConstantPool,
+) -> Option<Vec<(ConstantIdx, Vec<ConstantIdx>)>> {
+    let index = |reader: &mut Reader| {
+        let index = ConstantIdx::new(reader.read_u16().ok()?)?;
+        constants.try_get(index).map(|_| index)
+    };
+    let mut bootstrap_methods = Vec::new();
+    for _ in 0..reader.read_u16().ok()? {
+        let method_handle = index(reader)?;
+        let arguments = (0..reader.read_u16().ok()?)
+            .map(|_| index(reader))
+            .collect::<Option<_>>()?;
+        bootstrap_methods.push((method_handle, arguments));
+    }
+    Some(bootstrap_methods)
+}
+
+/// Returns the frames of the `StackMapTable` of `code`, or none if it has no
+/// such attribute or the attribute is malformed.
+fn stack_map_frames(
+    class_name: &JavaStr,
+    method: &Method,
+    code: &Code,
+    constants: &ConstantPool,
+) -> Vec<(u16, Frame)> {
+    let Some(stack_map) = code
+        .attributes
+        .iter()
+        .find_map(|attribute| match &attribute.kind {
+            AttributeKind::Raw(bytes)
+                if constants.get(attribute.name).into_utf8() == "StackMapTable" =>
+            {
+                Some(bytes)
+            }
+            _ => None,
+        })
+    else {
+        return Vec::new();
+    };
+    let initial = initial_frame(
+        &MethodContext {
+            class_name,
+            name: method.name(constants),
+            descriptor: method.parsed_descriptor(),
+            is_static: method.flags() & MethodFlags::STATIC == MethodFlags::STATIC,
+            common_superclass: &|_, _| unreachable!("frames are not merged"),
+        },
+        code.max_locals() as usize,
+    );
+    read_stack_map(stack_map, constants, &initial).unwrap_or_default()
+}
+
+/// Writes a `.stack` directive for `frame`.
+fn write_frame(out: &mut String, frame: &Frame, labels: &HashMap<u32, usize>) {
+    out.push_str("    .stack");
+    for (keyword, slots) in [("locals", frame.locals()), ("stack", frame.stack())] {
+        let entries = entries(slots);
+        if entries.is_empty() {
+            continue;
+        }
+        write!(out, " {keyword}").unwrap();
+        for entry in entries {
+            out.push(' ');
+            match entry {
+                VerificationType::Uninitialized(pc) => {
+                    write!(out, "uninitialized L{}", labels[&u32::from(*pc)]).unwrap();
+                }
+                VerificationType::Object(name) => write_name(out, name),
+                _ => {
+                    let (_, name) = FRAME_TYPES.iter().find(|(t, _)| t == entry).unwrap();
+                    out.push_str(name);
+                }
+            }
+        }
+    }
+    out.push('\n');
+}
+
+fn write_method(out: &mut String, class_name: &JavaStr, method: &Method, constants: &ConstantPool) {
+    out.push_str(".method ");
+    write_flags(
+        out,
+        method.flags().bits(),
+        MethodFlags::FLAGS.map(|(f, n)| (f.bits(), n)),
+    );
+    write_name(out, method.name(constants));
+    out.push(' ');
+    write_name(out, method.descriptor(constants));
+    out.push('\n');
+    if let Some(signature) = method.signature(constants) {
+        out.push_str("    .signature ");
+        write_name(out, signature);
+        out.push('\n');
+    }
+
+    if let Some(code) = method.bytecode() {
+        writeln!(out, "    .limit stack {}", code.max_stack()).unwrap();
+        writeln!(out, "    .limit locals {}", code.max_locals()).unwrap();
+
+        let frames = stack_map_frames(class_name, method, code, constants);
+        let mut targets = BTreeSet::new();
+        for (_, frame) in &frames {
+            for entry in frame.locals().iter().chain(frame.stack()) {
+                if let VerificationType::Uninitialized(pc) = entry {
+                    targets.insert(u32::from(*pc));
+                }
+            }
+        }
+        for handler in code.exception_table() {
+            targets.extend([handler.start_pc, handler.end_pc, handler.handler_pc].map(u32::from));
+        }
+        for variable in code
+            .local_variables()
+            .iter()
+            .chain(code.local_variable_types())
+        {
+            let start = u32::from(variable.start_pc);
+            targets.extend([start, start + u32::from(variable.length)]);
+        }
+        for (pc, instruction) in code.bytecode() {
+            let target = |offset: i32| (pc as i32 + offset) as u32;
+            match instruction {
+                Instruction::goto(offset) | Instruction::jsr(offset) => {
+                    targets.insert(target(offset));
+                }
+                Instruction::tableswitch(switch) => {
+                    targets.extend(switch.offsets().map(target));
+                    targets.insert(target(switch.default()));
+                }
+                Instruction::lookupswitch(switch) => {
+                    targets.extend(switch.pairs().map(|(_, offset)| target(offset)));
+                    targets.insert(target(switch.default()));
+                }
+                _ => {
+                    if let Some(offset) = instruction.branch_offset() {
+                        targets.insert(target(offset as i32));
+                    }
+                }
+            }
+        }
+
+        // Labels are numbered in order, so that they do not change when
+        // instructions are encoded differently.
+        let labels: HashMap<u32, usize> = targets
+            .into_iter()
+            .enumerate()
+            .map(|(i, pc)| (pc, i))
+            .collect();
+
+        for handler in code.exception_table() {
+            out.push_str("    .catch ");
+            match handler.catch_type {
+                Some(class) => write_name(
+                    out,
+                    constants.get(constants.get(class).into_class()).into_utf8(),
+                ),
+                None => out.push_str("all"),
+            }
+            writeln!(
+                out,
+                " from L{} to L{} using L{}",
+                labels[&u32::from(handler.start_pc)],
+                labels[&u32::from(handler.end_pc)],
+                labels[&u32::from(handler.handler_pc)]
+            )
+            .unwrap();
+        }
+
+        let tables = [
+            ("", code.local_variables()),
+            ("signature ", code.local_variable_types()),
+        ];
+        for (keyword, variables) in tables {
+            for variable in variables {
+                write!(out, "    .var {} is ", variable.index).unwrap();
+                write_name(out, constants.get(variable.name).into_utf8());
+                write!(out, " {keyword}").unwrap();
+                write_name(out, constants.get(variable.descriptor).into_utf8());
+                let start = u32::from(variable.start_pc);
+                writeln!(
+                    out,
+                    " from L{} to L{}",
+                    labels[&start],
+                    labels[&(start + u32::from(variable.length))]
+                )
+                .unwrap();
+            }
+        }
+
+        for (pc, instruction) in code.bytecode() {
+            if let Some(label) = labels.get(&pc) {
+                writeln!(out, "L{label}:").unwrap();
+            }
+            for line_number in code.line_numbers() {
+                if u32::from(line_number.start_pc) == pc {
+                    writeln!(out, "    .line {}", line_number.line).unwrap();
+                }
+            }
+            for (_, frame) in frames
+                .iter()
+                .filter(|(frame_pc, _)| u32::from(*frame_pc) == pc)
+            {
+                write_frame(out, frame, &labels);
+            }
+            out.push_str("    ");
+            write_instruction(out, &instruction, pc, &labels, constants);
+            out.push('\n');
+        }
+        let end = code.bytecode.len() as u32;
+        if let Some(label) = labels.get(&end) {
+            writeln!(out, "L{label}:").unwrap();
+        }
+    }
+
+    out.push_str(".end method\n");
+}
+
+fn write_instruction(
+    out: &mut String,
+    instruction: &Instruction,
+    pc: u32,
+    labels: &HashMap<u32, usize>,
+    constants: &ConstantPool,
+) {
+    let label = |offset: i32| labels[&((pc as i32 + offset) as u32)];
+
+    if let Some(name) = find_variant(SIMPLE_INSTRUCTIONS, instruction, |i| i) {
+        out.push_str(name);
+        return;
+    }
+    if let Some(offset) = instruction.branch_offset() {
+        let name = find_variant(BRANCH_INSTRUCTIONS, instruction, |i| i(0)).unwrap();
+        write!(out, "{name} L{}", label(offset as i32)).unwrap();
+        return;
+    }
+
+    match *instruction {
+        Instruction::iconst(value) => write!(out, "iconst {value}").unwrap(),
+        Instruction::lconst(value) => write!(out, "lconst {value}").unwrap(),
+        Instruction::fconst(value) => write!(out, "fconst {value:?}").unwrap(),
+        Instruction::dconst(value) => write!(out, "dconst {value:?}").unwrap(),
+        Instruction::bipush(value) => write!(out, "bipush {value}").unwrap(),
+        Instruction::sipush(value) => write!(out, "sipush {value}").unwrap(),
+        Instruction::ldc(index) => {
+            out.push_str("ldc ");
+            write_constant(out, index, constants);
+        }
+        Instruction::iload(index)
+        | Instruction::lload(index)
+        | Instruction::fload(index)
+        | Instruction::dload(index)
+        | Instruction::aload(index)
+        | Instruction::istore(index)
+        | Instruction::lstore(index)
+        | Instruction::fstore(index)
+        | Instruction::dstore(index)
+        | Instruction::astore(index)
+        | Instruction::ret(index) => {
+            let name = find_variant(LOCAL_INSTRUCTIONS, instruction, |i| i(0)).unwrap();
+            write!(out, "{name} {index}").unwrap();
+        }
+        Instruction::fcmp(greater) | Instruction::dcmp(greater) => {
+            let name = if let Instruction::fcmp(_) = instruction {
+                "fcmp"
+            } else {
+                "dcmp"
+            };
+            write!(out, "{name} {}", if greater { "g" } else { "l" }).unwrap();
+        }
+        Instruction::goto(offset) => write!(out, "goto L{}", label(offset)).unwrap(),
+        Instruction::jsr(offset) => write!(out, "jsr L{}", label(offset)).unwrap(),
+        Instruction::tableswitch(switch) => {
+            write!(out, "tableswitch {}", switch.low()).unwrap();
+            for offset in switch.offsets() {
+                write!(out, "\n        L{}", label(offset)).unwrap();
+            }
+            write!(out, "\n        default: L{}", label(switch.default())).unwrap();
+        }
+        Instruction::lookupswitch(switch) => {
+            out.push_str("lookupswitch");
+            for (key, offset) in switch.pairs() {
+                write!(out, "\n        {key}: L{}", label(offset)).unwrap();
+            }
+            write!(out, "\n        default: L{}", label(switch.default())).unwrap();
+        }
+        Instruction::getstatic(index)
+        | Instruction::putstatic(index)
+        | Instruction::getfield(index)
+        | Instruction::putfield(index) => {
+            let name = find_variant(FIELD_INSTRUCTIONS, instruction, |i| i(index)).unwrap();
+            write!(out, "{name} ").unwrap();
+            write_member(out, index, constants);
+        }
+        Instruction::invokevirtual(index)
+        | Instruction::invokespecial(index)
+        | Instruction::invokestatic(index) => {
+            let name = find_variant(METHOD_INSTRUCTIONS, instruction, |i| i(index)).unwrap();
+            write!(out, "{name} ").unwrap();
+            write_interface(out, index, constants);
+            write_member(out, index, constants);
+        }
+        Instruction::invokeinterface(index, _) => {
+            out.push_str("invokeinterface ");
+            write_member(out, index, constants);
+        }
+        Instruction::invokedynamic(index) => {
+            let (bootstrap_method, name_type) = constants.get(index).into_invoke_dynamic();
+            write!(out, "invokedynamic {bootstrap_method} ").unwrap();
+            write_name_type(out, name_type, constants);
+        }
+        Instruction::new(index)
+        | Instruction::anewarray(index)
+        | Instruction::checkcast(index)
+        | Instruction::instanceof(index) => {
+            let name = find_variant(CLASS_INSTRUCTIONS, instruction, |i| i(index)).unwrap();
+            write!(out, "{name} ").unwrap();
+            write_name(
+                out,
+                constants.get(constants.get(index).into_class()).into_utf8(),
+            );
+        }
+        Instruction::newarray(kind) => {
+            let (_, name) = ARRAY_KINDS.into_iter().find(|(k, _)| *k == kind).unwrap();
+            write!(out, "newarray {name}").unwrap();
+        }
+        Instruction::multianewarray(index, dimensions) => {
+            out.push_str("multianewarray ");
+            write_name(
+                out,
+                constants.get(constants.get(index).into_class()).into_utf8(),
+            );
+            write!(out, " {dimensions}").unwrap();
+        }
+        Instruction::iinc(index, value) => write!(out, "iinc {index} {value}").unwrap(),
+        _ => unreachable!("instruction without operands: {instruction:?}"),
+    }
+}
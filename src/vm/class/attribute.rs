@@ -0,0 +1,62 @@
+use super::ConstantIdx;
+
+/// An attribute of a class, field, method or [`Code`], in the order it
+/// appeared in the class file.
+///
+/// The contents of the attributes which graphene understands are stored in a
+/// structured form on their owner, so those attributes only record their
+/// position here. Every other attribute is kept verbatim so that it can be
+/// written back out unchanged.
+///
+/// [`Code`]: super::Code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: ConstantIdx,
+    pub kind: AttributeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeKind {
+    /// The `Code` attribute of a method, stored in [`Method::bytecode`].
+    ///
+    /// [`Method::bytecode`]: super::Method::bytecode
+    Code,
+    /// The `ConstantValue` attribute of a field, stored in
+    /// [`Field::constant_value`].
+    ///
+    /// [`Field::constant_value`]: super::Field::constant_value
+    ConstantValue,
+    /// The `Exceptions` attribute of a method, stored in
+    /// [`Method::exceptions`].
+    ///
+    /// [`Method::exceptions`]: super::Method::exceptions
+    Exceptions,
+    /// The `Signature` attribute of a class, field or method, holding the
+    /// index of the signature string.
+    Signature(ConstantIdx),
+    /// The `SourceFile` attribute of a class, stored in
+    /// [`Class::source_file`].
+    ///
+    /// [`Class::source_file`]: super::Class::source_file
+    SourceFile,
+    /// A `LineNumberTable` attribute holding the given number of entries of
+    /// [`Code::line_numbers`]. Entries are assigned to the attributes in order,
+    /// with the last attribute taking any that remain.
+    ///
+    /// [`Code::line_numbers`]: super::Code::line_numbers
+    LineNumberTable(usize),
+    /// A `LocalVariableTable` attribute holding the given number of entries of
+    /// [`Code::local_variables`], assigned like [`LineNumberTable`].
+    ///
+    /// [`Code::local_variables`]: super::Code::local_variables
+    /// [`LineNumberTable`]: AttributeKind::LineNumberTable
+    LocalVariableTable(usize),
+    /// A `LocalVariableTypeTable` attribute holding the given number of entries
+    /// of [`Code::local_variable_types`], assigned like [`LineNumberTable`].
+    ///
+    /// [`Code::local_variable_types`]: super::Code::local_variable_types
+    /// [`LineNumberTable`]: AttributeKind::LineNumberTable
+    LocalVariableTypeTable(usize),
+    /// An attribute which is not interpreted, along with its contents.
+    Raw(Vec<u8>),
+}
@@ -0,0 +1,1154 @@
+use std::collections::HashMap;
+
+use crate::java_str;
+use crate::string::{JavaStr, JavaString};
+use crate::writer::Writer;
+
+use super::stack_map::{
+    analyze, descriptor_string, initial_frame, slots, write_stack_map, Analysis, CommonSuperclass,
+    Frame, MethodContext, VerificationType,
+};
+use super::{
+    write_instruction, Attribute, AttributeKind, Class, ClassFlags, Code, ConstantIdx,
+    ConstantPool, Entry, ExceptionHandler, Field, FieldFlags, FieldType, Instruction, LineNumber,
+    LocalVariable, LookupSwitch, MemberIndex, Method, MethodDescriptor, MethodFlags, ReferenceKind,
+    TableSwitch,
+};
+
+type Result<T> = std::result::Result<T, BuildError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// A label was used by an instruction or exception handler, but never
+    /// bound to a position.
+    UnboundLabel,
+    /// A jump or exception handler targets an offset which is not the start
+    /// of an instruction.
+    InvalidBranchTarget,
+    /// The bytecode of a method is longer than 65535 bytes.
+    CodeTooLong,
+    /// The operand stack or local variables need more than 65535 slots.
+    TooManySlots,
+    /// The instruction at the given offset pops more values than are on the
+    /// operand stack.
+    StackUnderflow(u16),
+    /// The instruction at the given offset can be reached with operand stacks
+    /// of different heights or incompatible types.
+    InconsistentStack(u16),
+    /// The instruction at the given offset can never be executed. Dead code is
+    /// rejected because no valid stack map frame can be given for it.
+    UnreachableCode(u16),
+    /// Execution can continue past the last instruction of the method.
+    FallsOffEnd,
+    /// The instruction at the given offset is a `jsr` or `ret`, which cannot
+    /// be described by a `StackMapTable`.
+    Subroutine(u16),
+    /// A member reference in the constant pool has a malformed descriptor.
+    InvalidDescriptor,
+    /// A stack map frame given with [`CodeBuilder::frame`] is at the given
+    /// offset, where another frame was given or no instruction starts.
+    InvalidFrame(u16),
+    /// A local variable given with [`CodeBuilder::local_variable`] or
+    /// [`CodeBuilder::local_variable_type`] ends before it starts.
+    InvalidLocalVariable,
+}
+
+/// Builds a constant pool, reusing existing entries instead of adding
+/// duplicates.
+///
+/// # Panics
+///
+/// The methods which add entries panic if the constant pool is full.
+pub struct ConstantPoolBuilder {
+    constants: ConstantPool,
+    indices: HashMap<Entry, ConstantIdx>,
+}
+
+impl ConstantPoolBuilder {
+    pub fn new() -> Self {
+        Self {
+            constants: ConstantPool::new(0),
+            indices: HashMap::new(),
+        }
+    }
+
+    pub fn constants(&self) -> &ConstantPool {
+        &self.constants
+    }
+
+    pub fn build(self) -> ConstantPool {
+        self.constants
+    }
+
+    /// Returns the index of `entry`, adding it to the constant pool if it is
+    /// not already present.
+    pub fn add(&mut self, entry: Entry) -> ConstantIdx {
+        if let Some(&index) = self.indices.get(&entry) {
+            return index;
+        }
+
+        let index = u16::try_from(self.constants.len() + 1)
+            .ok()
+            .and_then(ConstantIdx::new)
+            .filter(|index| {
+                let size = match entry {
+                    Entry::Long(_) | Entry::Double(_) => 2,
+                    _ => 1,
+                };
+                index.get() as usize + size <= u16::MAX as usize
+            })
+            .expect("constant pool is full");
+        self.constants.add(entry.clone());
+        self.indices.insert(entry, index);
+        index
+    }
+
+    pub fn utf8(&mut self, value: &JavaStr) -> ConstantIdx {
+        self.add(Entry::Utf8(value.to_owned()))
+    }
+
+    pub fn integer(&mut self, value: i32) -> ConstantIdx {
+        self.add(Entry::Integer(value))
+    }
+
+    pub fn float(&mut self, value: f32) -> ConstantIdx {
+        self.add(Entry::Float(value))
+    }
+
+    pub fn long(&mut self, value: i64) -> ConstantIdx {
+        self.add(Entry::Long(value))
+    }
+
+    pub fn double(&mut self, value: f64) -> ConstantIdx {
+        self.add(Entry::Double(value))
+    }
+
+    /// Adds a class reference. Array classes are named by their descriptor,
+    /// such as `[Ljava/lang/String;`.
+    pub fn class(&mut self, name: &JavaStr) -> ConstantIdx {
+        let name = self.utf8(name);
+        self.add(Entry::Class(name))
+    }
+
+    pub fn string(&mut self, value: &JavaStr) -> ConstantIdx {
+        let value = self.utf8(value);
+        self.add(Entry::String(value))
+    }
+
+    pub fn name_type(&mut self, name: &JavaStr, descriptor: &JavaStr) -> ConstantIdx {
+        let name = self.utf8(name);
+        let descriptor = self.utf8(descriptor);
+        self.add(Entry::NameType(name, descriptor))
+    }
+
+    pub fn field_ref(
+        &mut self,
+        class: &JavaStr,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> ConstantIdx {
+        let class = self.class(class);
+        let name_type = self.name_type(name, descriptor);
+        self.add(Entry::FieldRef(class, name_type))
+    }
+
+    pub fn method_ref(
+        &mut self,
+        class: &JavaStr,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> ConstantIdx {
+        let class = self.class(class);
+        let name_type = self.name_type(name, descriptor);
+        self.add(Entry::MethodRef(class, name_type))
+    }
+
+    pub fn interface_method_ref(
+        &mut self,
+        class: &JavaStr,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> ConstantIdx {
+        let class = self.class(class);
+        let name_type = self.name_type(name, descriptor);
+        self.add(Entry::InterfaceMethodRef(class, name_type))
+    }
+
+    pub fn method_handle(&mut self, kind: ReferenceKind, reference: ConstantIdx) -> ConstantIdx {
+        self.add(Entry::MethodHandle(kind, reference))
+    }
+
+    pub fn method_type(&mut self, descriptor: &JavaStr) -> ConstantIdx {
+        let descriptor = self.utf8(descriptor);
+        self.add(Entry::MethodType(descriptor))
+    }
+
+    /// Adds a call site for `invokedynamic`, which is linked by the entry at
+    /// `bootstrap_method` of the `BootstrapMethods` attribute.
+    pub fn invoke_dynamic(
+        &mut self,
+        bootstrap_method: u16,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> ConstantIdx {
+        let name_type = self.name_type(name, descriptor);
+        self.add(Entry::InvokeDynamic(bootstrap_method, name_type))
+    }
+
+    /// Adds a dynamically-computed constant, which is produced by the entry
+    /// at `bootstrap_method` of the `BootstrapMethods` attribute.
+    pub fn dynamic(
+        &mut self,
+        bootstrap_method: u16,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> ConstantIdx {
+        let name_type = self.name_type(name, descriptor);
+        self.add(Entry::Dynamic(bootstrap_method, name_type))
+    }
+}
+
+impl Default for ConstantPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Continues building an existing constant pool, such as the one of a parsed
+/// class. Existing entries keep their indices.
+impl From<ConstantPool> for ConstantPoolBuilder {
+    fn from(constants: ConstantPool) -> Self {
+        let mut indices = HashMap::new();
+        for (index, entry) in constants.iter() {
+            indices.entry(entry.clone()).or_insert(index);
+        }
+        Self { constants, indices }
+    }
+}
+
+/// A position within the bytecode of a [`CodeBuilder`], used as the target of
+/// jumps and the bounds of exception handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+/// An entry of a stack map frame given with [`CodeBuilder::frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameEntry {
+    Type(VerificationType),
+    /// An object created by the `new` instruction at the label, whose
+    /// constructor has not been called yet.
+    Uninitialized(Label),
+}
+
+enum Item {
+    Instruction(Instruction<'static>),
+    Branch(fn(i16) -> Instruction<'static>, Label),
+    Goto(Label),
+    Jsr(Label),
+    TableSwitch {
+        low: i32,
+        targets: Vec<Label>,
+        default: Label,
+    },
+    LookupSwitch {
+        pairs: Vec<(i32, Label)>,
+        default: Label,
+    },
+    Bind(Label),
+    LineNumber(u16),
+    Frame {
+        locals: Vec<FrameEntry>,
+        stack: Vec<FrameEntry>,
+    },
+}
+
+struct Handler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: Option<ConstantIdx>,
+}
+
+struct Variable {
+    start: Label,
+    end: Label,
+    index: u16,
+    name: ConstantIdx,
+    descriptor: ConstantIdx,
+}
+
+/// Assembles the bytecode of a method from [`Instruction`]s.
+///
+/// Jumps refer to [`Label`]s rather than offsets. When the method is added to
+/// a [`ClassBuilder`], the offsets of jumps and the padding of switches are
+/// computed, jumps which do not fit in 16 bits are widened, and `max_stack`,
+/// `max_locals` and the `StackMapTable` attribute are derived from the code.
+#[derive(Default)]
+pub struct CodeBuilder {
+    items: Vec<Item>,
+    label_count: usize,
+    handlers: Vec<Handler>,
+    local_variables: Vec<Variable>,
+    local_variable_types: Vec<Variable>,
+    limits: Option<(u16, u16)>,
+}
+
+impl CodeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new label, which must later be bound with [`bind`].
+    ///
+    /// [`bind`]: Self::bind
+    pub fn label(&mut self) -> Label {
+        let label = Label(self.label_count);
+        self.label_count += 1;
+        label
+    }
+
+    /// Binds `label` to the position of the next instruction.
+    pub fn bind(&mut self, label: Label) -> &mut Self {
+        self.items.push(Item::Bind(label));
+        self
+    }
+
+    /// Appends an instruction. Constants which have no dedicated opcode, such
+    /// as `iconst(100_000)`, are loaded from the constant pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instruction` is a jump or switch, which must be added with
+    /// [`branch`], [`goto`], [`tableswitch`] or [`lookupswitch`] instead.
+    ///
+    /// [`branch`]: Self::branch
+    /// [`goto`]: Self::goto
+    /// [`tableswitch`]: Self::tableswitch
+    /// [`lookupswitch`]: Self::lookupswitch
+    pub fn emit(&mut self, instruction: Instruction<'static>) -> &mut Self {
+        match instruction {
+            Instruction::goto(_)
+            | Instruction::jsr(_)
+            | Instruction::tableswitch(_)
+            | Instruction::lookupswitch(_) => panic!("jumps must target a label: {instruction:?}"),
+            _ if inverse_branch(&instruction).is_some() => {
+                panic!("jumps must target a label: {instruction:?}")
+            }
+            _ => self.items.push(Item::Instruction(instruction)),
+        }
+        self
+    }
+
+    /// Appends a conditional jump to `target`, where `instruction` is the
+    /// constructor of the jump, such as `Instruction::if_icmp_lt`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instruction` does not construct a conditional jump.
+    pub fn branch(
+        &mut self,
+        instruction: fn(i16) -> Instruction<'static>,
+        target: Label,
+    ) -> &mut Self {
+        let example = instruction(0);
+        assert!(
+            inverse_branch(&example).is_some(),
+            "not a conditional jump: {example:?}"
+        );
+        self.items.push(Item::Branch(instruction, target));
+        self
+    }
+
+    /// Appends an unconditional jump to `target`.
+    pub fn goto(&mut self, target: Label) -> &mut Self {
+        self.items.push(Item::Goto(target));
+        self
+    }
+
+    /// Appends a jump to the subroutine at `target`.
+    ///
+    /// Subroutines cannot be described by stack map frames, so the method
+    /// can only be added if its [`limits`] are given.
+    ///
+    /// [`limits`]: Self::limits
+    pub fn jsr(&mut self, target: Label) -> &mut Self {
+        self.items.push(Item::Jsr(target));
+        self
+    }
+
+    /// Appends a `tableswitch` which jumps to `targets[key - low]`, or to
+    /// `default` if the key is out of range.
+    pub fn tableswitch(&mut self, low: i32, targets: &[Label], default: Label) -> &mut Self {
+        self.items.push(Item::TableSwitch {
+            low,
+            targets: targets.to_vec(),
+            default,
+        });
+        self
+    }
+
+    /// Appends a `lookupswitch` which jumps to the label paired with the key,
+    /// or to `default` if no key matches. The pairs are sorted by key.
+    pub fn lookupswitch(&mut self, pairs: &[(i32, Label)], default: Label) -> &mut Self {
+        let mut pairs = pairs.to_vec();
+        pairs.sort_by_key(|(key, _)| *key);
+        self.items.push(Item::LookupSwitch { pairs, default });
+        self
+    }
+
+    /// Adds an exception handler at `handler` for the instructions between
+    /// `start` and `end`. A `catch_type` of `None` catches every exception.
+    ///
+    /// Handlers are searched in the order they are added.
+    pub fn try_catch(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<ConstantIdx>,
+    ) -> &mut Self {
+        self.handlers.push(Handler {
+            start,
+            end,
+            handler,
+            catch_type,
+        });
+        self
+    }
+
+    /// Marks the following instructions as belonging to `line` of the source
+    /// file.
+    pub fn line_number(&mut self, line: u16) -> &mut Self {
+        self.items.push(Item::LineNumber(line));
+        self
+    }
+
+    /// Adds an entry to the `LocalVariableTable`, naming the local variable
+    /// at `index` between `start` and `end`. The `name` and `descriptor` are
+    /// `Utf8` constants.
+    pub fn local_variable(
+        &mut self,
+        start: Label,
+        end: Label,
+        index: u16,
+        name: ConstantIdx,
+        descriptor: ConstantIdx,
+    ) -> &mut Self {
+        self.local_variables.push(Variable {
+            start,
+            end,
+            index,
+            name,
+            descriptor,
+        });
+        self
+    }
+
+    /// Adds an entry to the `LocalVariableTypeTable`, which gives the generic
+    /// `signature` of the local variable at `index` between `start` and
+    /// `end`, like [`local_variable`].
+    ///
+    /// [`local_variable`]: Self::local_variable
+    pub fn local_variable_type(
+        &mut self,
+        start: Label,
+        end: Label,
+        index: u16,
+        name: ConstantIdx,
+        signature: ConstantIdx,
+    ) -> &mut Self {
+        self.local_variable_types.push(Variable {
+            start,
+            end,
+            index,
+            name,
+            descriptor: signature,
+        });
+        self
+    }
+
+    /// Gives the stack map frame of the next instruction, with entries as in
+    /// a `StackMapTable`, where `long` and `double` take up a single entry.
+    ///
+    /// Once a frame is given, no frames are generated for the method: the
+    /// `StackMapTable` holds exactly the frames given, even if they are wrong.
+    pub fn frame(&mut self, locals: Vec<FrameEntry>, stack: Vec<FrameEntry>) -> &mut Self {
+        self.items.push(Item::Frame { locals, stack });
+        self
+    }
+
+    /// Uses the given `max_stack` and `max_locals` instead of computing them.
+    ///
+    /// The code is then taken as it is: it is not analyzed, and it may be
+    /// invalid. No `StackMapTable` is generated for it, so it only has the
+    /// frames given with [`frame`]. This is meant for hand-written code which
+    /// the verifier should reject.
+    ///
+    /// [`frame`]: Self::frame
+    pub fn limits(&mut self, max_stack: u16, max_locals: u16) -> &mut Self {
+        self.limits = Some((max_stack, max_locals));
+        self
+    }
+}
+
+/// Builds a [`Class`] without going through a class file. Methods are
+/// assembled from a [`CodeBuilder`] as they are added.
+pub struct ClassBuilder {
+    constants: ConstantPoolBuilder,
+    major_version: u16,
+    minor_version: u16,
+    flags: ClassFlags,
+    name: JavaString,
+    this_class: ConstantIdx,
+    super_class: Option<ConstantIdx>,
+    interfaces: Vec<ConstantIdx>,
+    fields: Vec<Field>,
+    methods: Vec<Method>,
+    signature: Option<ConstantIdx>,
+    source_file: Option<ConstantIdx>,
+    bootstrap_methods: Vec<(ConstantIdx, Vec<ConstantIdx>)>,
+    common_superclass: Box<CommonSuperclass>,
+}
+
+impl ClassBuilder {
+    /// Creates a public class named `name` which extends `java/lang/Object`,
+    /// using version 52.0 of the class file format.
+    pub fn new(name: &JavaStr) -> Self {
+        let mut constants = ConstantPoolBuilder::new();
+        let this_class = constants.class(name);
+        let super_class = constants.class(java_str!("java/lang/Object"));
+        Self {
+            constants,
+            major_version: 52,
+            minor_version: 0,
+            flags: ClassFlags::PUBLIC | ClassFlags::SUPER,
+            name: name.to_owned(),
+            this_class,
+            super_class: Some(super_class),
+            interfaces: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            signature: None,
+            source_file: None,
+            bootstrap_methods: Vec::new(),
+            common_superclass: Box::new(|_, _| java_str!("java/lang/Object").to_owned()),
+        }
+    }
+
+    /// Gives access to the constant pool, to create the operands of
+    /// instructions.
+    pub fn constants(&mut self) -> &mut ConstantPoolBuilder {
+        &mut self.constants
+    }
+
+    pub fn version(&mut self, major: u16, minor: u16) -> &mut Self {
+        self.major_version = major;
+        self.minor_version = minor;
+        self
+    }
+
+    pub fn flags(&mut self, flags: ClassFlags) -> &mut Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets the superclass. Only `java/lang/Object` itself has no superclass.
+    pub fn super_class(&mut self, name: Option<&JavaStr>) -> &mut Self {
+        self.super_class = name.map(|name| self.constants.class(name));
+        self
+    }
+
+    pub fn interface(&mut self, name: &JavaStr) -> &mut Self {
+        let interface = self.constants.class(name);
+        self.interfaces.push(interface);
+        self
+    }
+
+    /// Adds a `SourceFile` attribute to the class.
+    pub fn source_file(&mut self, name: &JavaStr) -> &mut Self {
+        self.source_file = Some(self.constants.utf8(name));
+        self
+    }
+
+    /// Adds a `Signature` attribute to the class, giving its generic
+    /// signature.
+    pub fn signature(&mut self, signature: &JavaStr) -> &mut Self {
+        self.signature = Some(self.constants.utf8(signature));
+        self
+    }
+
+    /// Adds a `Signature` attribute to the field added last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no field has been added.
+    pub fn field_signature(&mut self, signature: &JavaStr) -> &mut Self {
+        let (signature, attribute) = self.signature_attribute(signature);
+        let field = self.fields.last_mut().expect("a field has been added");
+        field.signature = Some(signature);
+        field.attributes.push(attribute);
+        self
+    }
+
+    /// Adds a `Signature` attribute to the method added last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no method has been added.
+    pub fn method_signature(&mut self, signature: &JavaStr) -> &mut Self {
+        let (signature, attribute) = self.signature_attribute(signature);
+        let method = self.methods.last_mut().expect("a method has been added");
+        method.signature = Some(signature);
+        method.attributes.push(attribute);
+        self
+    }
+
+    /// Adds an entry to the `BootstrapMethods` attribute, which calls the
+    /// `MethodHandle` constant `method_handle` with the constants of
+    /// `arguments`. Returns the index of the entry, which is referred to by
+    /// [`ConstantPoolBuilder::invoke_dynamic`] and
+    /// [`ConstantPoolBuilder::dynamic`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the attribute is full.
+    pub fn bootstrap_method(
+        &mut self,
+        method_handle: ConstantIdx,
+        arguments: &[ConstantIdx],
+    ) -> u16 {
+        let index =
+            u16::try_from(self.bootstrap_methods.len()).expect("too many bootstrap methods");
+        self.bootstrap_methods
+            .push((method_handle, arguments.to_vec()));
+        index
+    }
+
+    /// Sets the function used to find the closest common superclass of two
+    /// classes when computing stack map frames. By default, this is always
+    /// `java/lang/Object`, which is only correct if the values are not used as
+    /// a more specific type after control flow merges.
+    pub fn common_superclass(
+        &mut self,
+        common_superclass: impl Fn(&JavaStr, &JavaStr) -> JavaString + 'static,
+    ) -> &mut Self {
+        self.common_superclass = Box::new(common_superclass);
+        self
+    }
+
+    pub fn field(
+        &mut self,
+        flags: FieldFlags,
+        name: &JavaStr,
+        descriptor: &FieldType,
+    ) -> &mut Self {
+        let name = self.constants.utf8(name);
+        let descriptor_idx = self.constants.utf8(&descriptor_string(descriptor));
+        self.fields.push(Field {
+            name,
+            descriptor: descriptor_idx,
+            parsed_descriptor: descriptor.clone(),
+            flags,
+            signature: None,
+            constant_value: None,
+            attributes: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds a field with a `ConstantValue` attribute, which initializes a
+    /// static field to the constant at `value`.
+    pub fn constant_field(
+        &mut self,
+        flags: FieldFlags,
+        name: &JavaStr,
+        descriptor: &FieldType,
+        value: ConstantIdx,
+    ) -> &mut Self {
+        self.field(flags, name, descriptor);
+        let attribute = Attribute {
+            name: self.constants.utf8(java_str!("ConstantValue")),
+            kind: AttributeKind::ConstantValue,
+        };
+        let field = self.fields.last_mut().unwrap();
+        field.constant_value = Some(value);
+        field.attributes.push(attribute);
+        self
+    }
+
+    /// Adds a method without code, such as an `abstract` or `native` method.
+    pub fn abstract_method(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+    ) -> &mut Self {
+        let method = self.declare_method(flags, name, descriptor, None);
+        self.methods.push(method);
+        self
+    }
+
+    /// Assembles `code` and adds it as a method.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`BuildError`] if the code cannot be assembled, or if no
+    /// valid stack map frames can be computed for it.
+    pub fn method(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+        code: CodeBuilder,
+    ) -> Result<&mut Self> {
+        let code = self.assemble(flags, name, descriptor, code)?;
+        let method = self.declare_method(flags, name, descriptor, Some(code));
+        self.methods.push(method);
+        Ok(self)
+    }
+
+    pub fn build(mut self) -> Class {
+        let mut attributes = Vec::new();
+        if let Some(signature) = self.signature {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("Signature")),
+                kind: AttributeKind::Signature(signature),
+            });
+        }
+        if self.source_file.is_some() {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("SourceFile")),
+                kind: AttributeKind::SourceFile,
+            });
+        }
+        if !self.bootstrap_methods.is_empty() {
+            let mut writer = Writer::new();
+            writer.write_u16(self.bootstrap_methods.len() as u16);
+            for (method_handle, arguments) in &self.bootstrap_methods {
+                writer.write_u16(method_handle.get());
+                writer.write_u16(arguments.len() as u16);
+                for argument in arguments {
+                    writer.write_u16(argument.get());
+                }
+            }
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("BootstrapMethods")),
+                kind: AttributeKind::Raw(writer.into_inner()),
+            });
+        }
+
+        let constants = self.constants.build();
+        let members = MemberIndex::new(&constants, &self.fields, &self.methods);
+        Class {
+            minor_version: self.minor_version,
+            major_version: self.major_version,
+            constants,
+            flags: self.flags,
+            this_class: self.this_class,
+            super_class: self.super_class,
+            interfaces: self.interfaces,
+            fields: self.fields,
+            methods: self.methods,
+            signature: self.signature,
+            source_file: self.source_file,
+            attributes,
+            members,
+        }
+    }
+
+    fn signature_attribute(&mut self, signature: &JavaStr) -> (ConstantIdx, Attribute) {
+        let signature = self.constants.utf8(signature);
+        let attribute = Attribute {
+            name: self.constants.utf8(java_str!("Signature")),
+            kind: AttributeKind::Signature(signature),
+        };
+        (signature, attribute)
+    }
+
+    fn declare_method(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+        code: Option<Code>,
+    ) -> Method {
+        let name = self.constants.utf8(name);
+        let descriptor_idx = self.constants.utf8(&descriptor_string(descriptor));
+        let mut attributes = Vec::new();
+        if code.is_some() {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("Code")),
+                kind: AttributeKind::Code,
+            });
+        }
+        Method {
+            name,
+            descriptor: descriptor_idx,
+            parsed_descriptor: descriptor.clone(),
+            flags,
+            signature: None,
+            exceptions: Vec::new(),
+            code,
+            attributes,
+        }
+    }
+
+    fn assemble(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+        mut code: CodeBuilder,
+    ) -> Result<Code> {
+        // Constants without a dedicated opcode are loaded with `ldc`.
+        for item in &mut code.items {
+            if let Item::Instruction(instruction) = item {
+                if let Some(constant) = self.lower_constant(instruction) {
+                    *instruction = Instruction::ldc(constant);
+                }
+            }
+        }
+
+        let constants = self.constants.constants();
+        let mut wide = vec![false; code.items.len()];
+        let (positions, labels) = loop {
+            let (positions, labels) = layout(&code, &wide, constants)?;
+
+            let mut changed = false;
+            for (i, item) in code.items.iter().enumerate() {
+                if let Item::Branch(_, target) | Item::Goto(target) | Item::Jsr(target) = item {
+                    let offset = labels[target.0] as i64 - positions[i] as i64;
+                    if !wide[i] && i16::try_from(offset).is_err() {
+                        wide[i] = true;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break (positions, labels);
+            }
+        };
+
+        let mut writer = Writer::new();
+        let mut line_numbers = Vec::new();
+        for (i, item) in code.items.iter().enumerate() {
+            let pc = positions[i];
+            let offset_to = |label: &Label| labels[label.0] as i32 - pc as i32;
+            match item {
+                Item::Instruction(instruction) => {
+                    write_instruction(&mut writer, instruction, pc, constants)
+                }
+                Item::Branch(instruction, target) => {
+                    if wide[i] {
+                        // Jump over a `goto_w` to the target when the inverse
+                        // condition holds.
+                        let inverse = inverse_branch(&instruction(0)).unwrap();
+                        write_instruction(&mut writer, &inverse(8), pc, constants);
+                        writer.write_u8(0xC8);
+                        writer.write_i32(offset_to(target) - 3);
+                    } else {
+                        let offset = offset_to(target) as i16;
+                        write_instruction(&mut writer, &instruction(offset), pc, constants);
+                    }
+                }
+                Item::Goto(target) => {
+                    if wide[i] {
+                        writer.write_u8(0xC8);
+                        writer.write_i32(offset_to(target));
+                    } else {
+                        let goto = Instruction::goto(offset_to(target));
+                        write_instruction(&mut writer, &goto, pc, constants);
+                    }
+                }
+                Item::Jsr(target) => {
+                    if wide[i] {
+                        writer.write_u8(0xC9);
+                        writer.write_i32(offset_to(target));
+                    } else {
+                        let jsr = Instruction::jsr(offset_to(target));
+                        write_instruction(&mut writer, &jsr, pc, constants);
+                    }
+                }
+                Item::TableSwitch {
+                    low,
+                    targets,
+                    default,
+                } => {
+                    let mut offsets = Writer::new();
+                    for target in targets {
+                        offsets.write_i32(offset_to(target));
+                    }
+                    let offsets = offsets.into_inner();
+                    let high = low + targets.len() as i32 - 1;
+                    let switch = TableSwitch::new(offset_to(default), *low, high, &offsets);
+                    write_instruction(
+                        &mut writer,
+                        &Instruction::tableswitch(switch),
+                        pc,
+                        constants,
+                    );
+                }
+                Item::LookupSwitch { pairs, default } => {
+                    let mut pair_bytes = Writer::new();
+                    for (key, target) in pairs {
+                        pair_bytes.write_i32(*key);
+                        pair_bytes.write_i32(offset_to(target));
+                    }
+                    let pair_bytes = pair_bytes.into_inner();
+                    let switch = LookupSwitch::new(offset_to(default), &pair_bytes);
+                    let instruction = Instruction::lookupswitch(switch);
+                    write_instruction(&mut writer, &instruction, pc, constants);
+                }
+                Item::Bind(_) | Item::Frame { .. } => {}
+                Item::LineNumber(line) => line_numbers.push(LineNumber {
+                    start_pc: pc as u16,
+                    line: *line,
+                }),
+            }
+        }
+        let bytecode = writer.into_inner();
+        if bytecode.len() > u16::MAX as usize {
+            return Err(BuildError::CodeTooLong);
+        }
+
+        let exception_table: Vec<ExceptionHandler> = code
+            .handlers
+            .iter()
+            .map(|handler| ExceptionHandler {
+                start_pc: labels[handler.start.0] as u16,
+                end_pc: labels[handler.end.0] as u16,
+                handler_pc: labels[handler.handler.0] as u16,
+                catch_type: handler.catch_type,
+            })
+            .collect();
+
+        let method = MethodContext {
+            class_name: &self.name,
+            name,
+            descriptor,
+            is_static: flags & MethodFlags::STATIC == MethodFlags::STATIC,
+            common_superclass: &*self.common_superclass,
+        };
+        let mut analysis = match code.limits {
+            Some((max_stack, max_locals)) => Analysis {
+                max_stack,
+                max_locals,
+                initial: initial_frame(&method, max_locals as usize),
+                frames: Vec::new(),
+            },
+            None => analyze(&bytecode, &exception_table, constants, &method)?,
+        };
+        let given_frames = given_frames(&code, &positions, &labels, &bytecode)?;
+        let has_given_frames = given_frames.is_some();
+        if let Some(frames) = given_frames {
+            analysis.frames = frames;
+        }
+        // Stack map frames were introduced in version 50 of the class file
+        // format, and are required from version 51 onwards. Frames given by
+        // hand are written as they are.
+        let stack_map = ((self.major_version >= 50 || has_given_frames)
+            && !analysis.frames.is_empty())
+        .then(|| write_stack_map(&analysis, &mut self.constants));
+        let (max_stack, max_locals) = (analysis.max_stack, analysis.max_locals);
+
+        let variables = |variables: &[Variable]| {
+            variables
+                .iter()
+                .map(|variable| {
+                    let (start, end) = (labels[variable.start.0], labels[variable.end.0]);
+                    let length = end
+                        .checked_sub(start)
+                        .ok_or(BuildError::InvalidLocalVariable)?;
+                    Ok(LocalVariable {
+                        start_pc: start as u16,
+                        length: length as u16,
+                        name: variable.name,
+                        descriptor: variable.descriptor,
+                        index: variable.index,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let local_variables = variables(&code.local_variables)?;
+        let local_variable_types = variables(&code.local_variable_types)?;
+
+        let mut attributes = Vec::new();
+        if !line_numbers.is_empty() {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("LineNumberTable")),
+                kind: AttributeKind::LineNumberTable(line_numbers.len()),
+            });
+        }
+        if !local_variables.is_empty() {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("LocalVariableTable")),
+                kind: AttributeKind::LocalVariableTable(local_variables.len()),
+            });
+        }
+        if !local_variable_types.is_empty() {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("LocalVariableTypeTable")),
+                kind: AttributeKind::LocalVariableTypeTable(local_variable_types.len()),
+            });
+        }
+        if let Some(stack_map) = stack_map {
+            attributes.push(Attribute {
+                name: self.constants.utf8(java_str!("StackMapTable")),
+                kind: AttributeKind::Raw(stack_map),
+            });
+        }
+
+        Ok(Code {
+            max_stack,
+            max_locals,
+            bytecode,
+            exception_table,
+            line_numbers,
+            local_variables,
+            local_variable_types,
+            attributes,
+        })
+    }
+
+    /// Adds the value of a constant instruction to the constant pool if it has
+    /// no dedicated opcode.
+    fn lower_constant(&mut self, instruction: &Instruction) -> Option<ConstantIdx> {
+        match *instruction {
+            Instruction::iconst(value) if i16::try_from(value).is_err() => {
+                Some(self.constants.integer(value))
+            }
+            Instruction::lconst(value) if !matches!(value, 0 | 1) => {
+                Some(self.constants.long(value))
+            }
+            Instruction::fconst(value)
+                if !(value.to_bits() == 0 || value == 1.0 || value == 2.0) =>
+            {
+                Some(self.constants.float(value))
+            }
+            Instruction::dconst(value) if !(value.to_bits() == 0 || value == 1.0) => {
+                Some(self.constants.double(value))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the frames given with [`CodeBuilder::frame`] by their offset, or
+/// `None` if no frames were given.
+fn given_frames(
+    code: &CodeBuilder,
+    positions: &[usize],
+    labels: &[usize],
+    bytecode: &[u8],
+) -> Result<Option<Vec<(u16, Frame)>>> {
+    let starts = super::Bytecode::new(bytecode)
+        .map(|(pc, _)| pc as usize)
+        .collect::<std::collections::HashSet<_>>();
+    let resolve = |entries: &[FrameEntry]| {
+        slots(entries.iter().map(|entry| match entry {
+            FrameEntry::Type(verification_type) => verification_type.clone(),
+            FrameEntry::Uninitialized(label) => {
+                VerificationType::Uninitialized(labels[label.0] as u16)
+            }
+        }))
+    };
+
+    let mut frames = Vec::new();
+    for (i, item) in code.items.iter().enumerate() {
+        if let Item::Frame { locals, stack } = item {
+            let pc = positions[i];
+            if !starts.contains(&pc) {
+                return Err(BuildError::InvalidFrame(pc as u16));
+            }
+            let frame = Frame {
+                locals: resolve(locals),
+                stack: resolve(stack),
+            };
+            frames.push((pc as u16, frame));
+        }
+    }
+    if frames.is_empty() {
+        return Ok(None);
+    }
+    frames.sort_by_key(|(pc, _)| *pc);
+    if let Some(pair) = frames.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(BuildError::InvalidFrame(pair[0].0));
+    }
+    Ok(Some(frames))
+}
+
+/// Computes the offset of every item and label of `code`. Jumps marked in
+/// `wide` use their 32-bit form.
+fn layout(
+    code: &CodeBuilder,
+    wide: &[bool],
+    constants: &ConstantPool,
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let mut positions = Vec::with_capacity(code.items.len());
+    let mut labels = vec![None; code.label_count];
+    let mut pc = 0;
+    for (i, item) in code.items.iter().enumerate() {
+        positions.push(pc);
+        let padding = (4 - (pc + 1) % 4) % 4;
+        pc += match item {
+            Item::Instruction(instruction) => {
+                let mut writer = Writer::new();
+                write_instruction(&mut writer, instruction, pc, constants);
+                writer.len()
+            }
+            // A widened conditional jump is followed by a `goto_w`.
+            Item::Branch(..) => {
+                if wide[i] {
+                    8
+                } else {
+                    3
+                }
+            }
+            Item::Goto(_) | Item::Jsr(_) => {
+                if wide[i] {
+                    5
+                } else {
+                    3
+                }
+            }
+            Item::TableSwitch { targets, .. } => 1 + padding + 12 + 4 * targets.len(),
+            Item::LookupSwitch { pairs, .. } => 1 + padding + 8 + 8 * pairs.len(),
+            Item::Bind(label) => {
+                labels[label.0] = Some(pc);
+                0
+            }
+            Item::LineNumber(_) | Item::Frame { .. } => 0,
+        };
+    }
+
+    let labels = labels
+        .into_iter()
+        .collect::<Option<Vec<usize>>>()
+        .ok_or(BuildError::UnboundLabel)?;
+    Ok((positions, labels))
+}
+
+/// Returns the constructor of the jump with the opposite condition, or `None`
+/// if `instruction` is not a conditional jump.
+fn inverse_branch(instruction: &Instruction) -> Option<fn(i16) -> Instruction<'static>> {
+    let inverse: fn(i16) -> Instruction<'static> = match instruction {
+        Instruction::if_eq(_) => Instruction::if_ne,
+        Instruction::if_ne(_) => Instruction::if_eq,
+        Instruction::if_lt(_) => Instruction::if_ge,
+        Instruction::if_ge(_) => Instruction::if_lt,
+        Instruction::if_gt(_) => Instruction::if_le,
+        Instruction::if_le(_) => Instruction::if_gt,
+        Instruction::if_icmp_eq(_) => Instruction::if_icmp_ne,
+        Instruction::if_icmp_ne(_) => Instruction::if_icmp_eq,
+        Instruction::if_icmp_lt(_) => Instruction::if_icmp_ge,
+        Instruction::if_icmp_ge(_) => Instruction::if_icmp_lt,
+        Instruction::if_icmp_gt(_) => Instruction::if_icmp_le,
+        Instruction::if_icmp_le(_) => Instruction::if_icmp_gt,
+        Instruction::if_acmp_eq(_) => Instruction::if_acmp_ne,
+        Instruction::if_acmp_ne(_) => Instruction::if_acmp_eq,
+        Instruction::ifnull(_) => Instruction::ifnonnull,
+        Instruction::ifnonnull(_) => Instruction::ifnull,
+        _ => return None,
+    };
+    Some(inverse)
+}
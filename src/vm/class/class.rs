@@ -1,21 +1,41 @@
-use crate::string::JavaStr;
+use std::collections::HashMap;
 
-use super::{ConstantIdx, ConstantPool};
+use crate::string::{JavaStr, JavaString};
+
+use super::{Attribute, ClassSignature, ConstantIdx, ConstantPool, ParseError};
 use super::{Field, Method};
 
+#[derive(PartialEq)]
 pub struct Class {
+    pub(super) minor_version: u16,
+    pub(super) major_version: u16,
     pub(super) constants: ConstantPool,
+    pub(super) flags: ClassFlags,
     pub(super) this_class: ConstantIdx,
     pub(super) super_class: Option<ConstantIdx>,
+    pub(super) interfaces: Vec<ConstantIdx>,
     pub(super) fields: Vec<Field>,
     pub(super) methods: Vec<Method>,
+    pub(super) signature: Option<ConstantIdx>,
+    pub(super) source_file: Option<ConstantIdx>,
+    pub(super) attributes: Vec<Attribute>,
+    pub(super) members: MemberIndex,
 }
 
 impl Class {
+    /// Returns the `(major, minor)` version of the class file.
+    pub fn version(&self) -> (u16, u16) {
+        (self.major_version, self.minor_version)
+    }
+
     pub fn constants(&self) -> &ConstantPool {
         &self.constants
     }
 
+    pub fn flags(&self) -> ClassFlags {
+        self.flags
+    }
+
     pub fn name(&self) -> &JavaStr {
         let name_idx = self.constants.get(self.this_class).into_class();
         self.constants.get(name_idx).into_utf8()
@@ -30,21 +50,104 @@ impl Class {
         }
     }
 
-    pub fn get_field(&self, name: &JavaStr) -> &Field {
-        self.fields
+    /// Returns the names of the interfaces directly implemented by the class.
+    pub fn interfaces(&self) -> impl Iterator<Item = &JavaStr> {
+        self.interfaces.iter().map(|&class| {
+            let name = self.constants.get(class).into_class();
+            self.constants.get(name).into_utf8()
+        })
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn methods(&self) -> &[Method] {
+        &self.methods
+    }
+
+    /// Returns the generic signature of the class, if it has a `Signature`
+    /// attribute.
+    pub fn signature(&self) -> Option<&JavaStr> {
+        self.signature
+            .map(|signature| self.constants.get(signature).into_utf8())
+    }
+
+    /// Parses the generic signature of the class, if it has a `Signature`
+    /// attribute. The signature is only metadata, so a malformed one does not
+    /// keep the class from being parsed, and is only reported here.
+    pub fn parsed_signature(&self) -> Option<Result<ClassSignature, ParseError>> {
+        self.signature().map(ClassSignature::parse)
+    }
+
+    /// Returns the name of the source file the class was compiled from, if it
+    /// has a `SourceFile` attribute.
+    pub fn source_file(&self) -> Option<&JavaStr> {
+        self.source_file
+            .map(|source_file| self.constants.get(source_file).into_utf8())
+    }
+
+    /// Returns the attributes of the class, in the order they appear in the
+    /// class file.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Returns the field with the given name and descriptor, if the class
+    /// declares one.
+    pub fn get_field(&self, name: &JavaStr, descriptor: &JavaStr) -> Option<&Field> {
+        self.field_index(name, descriptor)
+            .map(|index| &self.fields[index])
+    }
+
+    /// Returns the method with the given name and descriptor, if the class
+    /// declares one.
+    pub fn get_method(&self, name: &JavaStr, descriptor: &JavaStr) -> Option<&Method> {
+        self.method_index(name, descriptor)
+            .map(|index| &self.methods[index])
+    }
+
+    /// Returns the index in [`Class::fields`] of the field with the given name
+    /// and descriptor.
+    pub fn field_index(&self, name: &JavaStr, descriptor: &JavaStr) -> Option<usize> {
+        let candidates = self.members.fields.get(name)?;
+        candidates
             .iter()
-            .find(|field| field.name(&self.constants) == name)
-            .unwrap()
+            .map(|&index| index as usize)
+            .find(|&index| self.fields[index].descriptor(&self.constants) == descriptor)
     }
 
-    pub fn get_method(&self, name: &JavaStr, descriptor: &JavaStr) -> &Method {
-        self.methods
+    /// Returns the index in [`Class::methods`] of the method with the given
+    /// name and descriptor.
+    pub fn method_index(&self, name: &JavaStr, descriptor: &JavaStr) -> Option<usize> {
+        let candidates = self.members.methods.get(name)?;
+        candidates
             .iter()
-            .find(|method| {
-                method.name(&self.constants) == name
-                    && method.descriptor(&self.constants) == descriptor
-            })
-            .unwrap()
+            .map(|&index| index as usize)
+            .find(|&index| self.methods[index].descriptor(&self.constants) == descriptor)
+    }
+}
+
+/// The fields and methods of a class indexed by name. Members sharing a name,
+/// such as overloaded methods, are told apart by their descriptors.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct MemberIndex {
+    fields: HashMap<JavaString, Vec<u16>>,
+    methods: HashMap<JavaString, Vec<u16>>,
+}
+
+impl MemberIndex {
+    pub(super) fn new(constants: &ConstantPool, fields: &[Field], methods: &[Method]) -> Self {
+        let mut index = Self::default();
+        for (i, field) in fields.iter().enumerate() {
+            let name = field.name(constants).to_owned();
+            index.fields.entry(name).or_default().push(i as u16);
+        }
+        for (i, method) in methods.iter().enumerate() {
+            let name = method.name(constants).to_owned();
+            index.methods.entry(name).or_default().push(i as u16);
+        }
+        index
     }
 }
 
@@ -52,6 +155,7 @@ impl std::fmt::Debug for Class {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Class")
             .field("constants", &self.constants)
+            .field("flags", &self.flags)
             .field("this_class", {
                 let class = self.constants.get(self.this_class).into_class();
                 &self.constants.get(class).into_utf8()
@@ -64,6 +168,10 @@ impl std::fmt::Debug for Class {
                     f.write_str("None")
                 }
             })
+            .field_with("interfaces", |f| {
+                f.debug_list().entries(self.interfaces()).finish()
+            })
+            .field("signature", &self.signature())
             .field_with("fields", |f| {
                 let mut debug_list = f.debug_list();
                 for field in self.fields.iter() {
@@ -71,6 +179,8 @@ impl std::fmt::Debug for Class {
                         f.debug_struct("Field")
                             .field("name", &field.name(&self.constants))
                             .field("descriptor", &field.descriptor(&self.constants))
+                            .field_with("flags", |f| std::fmt::Display::fmt(&field.flags, f))
+                            .field("signature", &field.signature(&self.constants))
                             .finish()
                     });
                 }
@@ -84,6 +194,7 @@ impl std::fmt::Debug for Class {
                             .field("name", &method.name(&self.constants))
                             .field("descriptor", &method.descriptor(&self.constants))
                             .field_with("flags", |f| std::fmt::Display::fmt(&method.flags, f))
+                            .field("signature", &method.signature(&self.constants))
                             .field_with("code", |f| {
                                 if let Some(code) = &method.code {
                                     code.fmt(f)
@@ -99,3 +210,106 @@ impl std::fmt::Debug for Class {
             .finish()
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ClassFlags(u16);
+
+impl ClassFlags {
+    /// Declared public; may be accessed from outside its
+    /// package.
+    pub const PUBLIC: Self = Self(0x0001);
+    /// Declared final; no subclasses allowed.
+    pub const FINAL: Self = Self(0x0010);
+    /// Treat superclass methods specially when invoked by
+    /// the invokespecial instruction.
+    pub const SUPER: Self = Self(0x0020);
+    /// Is an interface, not a class.
+    pub const INTERFACE: Self = Self(0x0200);
+    /// Declared abstract; must not be instantiated.
+    pub const ABSTRACT: Self = Self(0x0400);
+    /// Declared synthetic; not present in the source code.
+    pub const SYNTHETIC: Self = Self(0x1000);
+    /// Declared as an annotation type.
+    pub const ANNOTATION: Self = Self(0x2000);
+    /// Declared as an enum type.
+    pub const ENUM: Self = Self(0x4000);
+
+    /// Used for constructing a value during parsing. We do
+    /// check bits here because unknown bits are to be
+    /// ignored according to the specification.
+    pub(super) const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw bits of the flags, as stored in the
+    /// class file.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub(super) const FLAGS: [(ClassFlags, &'static str); 8] = [
+        (ClassFlags::PUBLIC, "PUBLIC"),
+        (ClassFlags::FINAL, "FINAL"),
+        (ClassFlags::SUPER, "SUPER"),
+        (ClassFlags::INTERFACE, "INTERFACE"),
+        (ClassFlags::ABSTRACT, "ABSTRACT"),
+        (ClassFlags::SYNTHETIC, "SYNTHETIC"),
+        (ClassFlags::ANNOTATION, "ANNOTATION"),
+        (ClassFlags::ENUM, "ENUM"),
+    ];
+}
+
+impl std::ops::BitAnd<ClassFlags> for ClassFlags {
+    type Output = ClassFlags;
+
+    fn bitand(self, rhs: ClassFlags) -> Self::Output {
+        ClassFlags(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::BitAnd<ClassFlags> for &ClassFlags {
+    type Output = ClassFlags;
+
+    fn bitand(self, rhs: ClassFlags) -> Self::Output {
+        ClassFlags(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::BitOr<ClassFlags> for ClassFlags {
+    type Output = ClassFlags;
+
+    fn bitor(self, rhs: ClassFlags) -> Self::Output {
+        ClassFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOr<ClassFlags> for &ClassFlags {
+    type Output = ClassFlags;
+
+    fn bitor(self, rhs: ClassFlags) -> Self::Output {
+        ClassFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::fmt::Debug for ClassFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ClassFlags({})", self)
+    }
+}
+
+impl std::fmt::Display for ClassFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for (flag, name) in Self::FLAGS {
+            if self & flag == flag {
+                if !first {
+                    write!(f, " | {name}")?;
+                } else {
+                    write!(f, "{name}")?;
+                    first = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use crate::string::{JavaStr, JavaString};
 
 /// The runtime constant pool for the JVM. This represents the various strings,
 /// names, and other constants referred to within the class.
+#[derive(PartialEq)]
 pub struct ConstantPool {
     entries: Vec<Slot>,
 }
@@ -25,6 +26,11 @@ impl ConstantPool {
         self.entries.len()
     }
 
+    /// Returns `true` if the constant pool contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     /// Adds an entry to the constant pool. This method should only be used
     /// during construction of the constant pool.
     pub fn add(&mut self, entry: Entry) {
@@ -41,12 +47,38 @@ impl ConstantPool {
     /// indexing and the irregular sizes of the JVM's `long` and `double`
     /// constants.
     pub fn get(&self, index: ConstantIdx) -> &Entry {
+        match self.try_get(index) {
+            Some(entry) => entry,
+            None => panic!("invalid constant pool index: {:?}", index.0),
+        }
+    }
+
+    /// Gets an entry from the constant pool, or returns `None` if `index` is
+    /// out of bounds or refers to the empty slot following a `long` or
+    /// `double` constant.
+    pub fn try_get(&self, index: ConstantIdx) -> Option<&Entry> {
         let raw_index = index.0.get() - 1;
         match self.entries.get(raw_index as usize) {
-            Some(Slot::Entry(entry)) => entry,
-            _ => panic!("invalid constant pool index: {:?}", index.0),
+            Some(Slot::Entry(entry)) => Some(entry),
+            _ => None,
         }
     }
+
+    /// Returns an iterator over the entries of the constant pool along with
+    /// their indices. The empty slots following [`Entry::Long`] and
+    /// [`Entry::Double`] are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = (ConstantIdx, &Entry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Entry(entry) => {
+                    let index = NonZeroU16::new(index as u16 + 1).unwrap();
+                    Some((ConstantIdx(index), entry))
+                }
+                Slot::Marker => None,
+            })
+    }
 }
 
 impl std::fmt::Debug for ConstantPool {
@@ -73,21 +105,39 @@ impl std::fmt::Debug for ConstantPool {
 
 /// An index into the constant pool.
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ConstantIdx(pub(super) NonZeroU16);
 
+impl ConstantIdx {
+    /// Creates an index from its 1-based raw value, returning `None` if `index`
+    /// is zero.
+    pub const fn new(index: u16) -> Option<Self> {
+        match NonZeroU16::new(index) {
+            Some(index) => Some(Self(index)),
+            None => None,
+        }
+    }
+
+    /// Returns the 1-based raw value of the index, as stored in the class file.
+    pub const fn get(self) -> u16 {
+        self.0.get()
+    }
+}
+
 impl std::fmt::Debug for ConstantIdx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "ConstantIdx({})", self.0)
     }
 }
 
+#[derive(PartialEq)]
 enum Slot {
     Entry(Entry),
     Marker,
 }
 
 /// An entry within the constant pool of a class.
+#[derive(Clone)]
 pub enum Entry {
     /// A constant string value. This is used by a number of other entries
     /// within the constant pool. This uses a modified UTF-8 encoding
@@ -122,6 +172,13 @@ pub enum Entry {
     /// and return types of the call, and optionally, a sequence of additional
     /// constants called *static arguments* to the bootstrap method.
     InvokeDynamic(u16, ConstantIdx),
+    /// Like [`Entry::InvokeDynamic`], but describes a dynamically-computed
+    /// constant rather than a call site.
+    Dynamic(u16, ConstantIdx),
+    /// A module, as referenced by the `Module` attribute of `module-info`.
+    Module(ConstantIdx),
+    /// A package exported or opened by a module.
+    Package(ConstantIdx),
 }
 
 impl Entry {
@@ -210,6 +267,84 @@ impl Entry {
             entry => panic!("invalid entry: {entry:?}"),
         }
     }
+
+    pub fn into_dynamic(&self) -> (u16, ConstantIdx) {
+        match self {
+            Self::Dynamic(bootstrap_method, name_type) => (*bootstrap_method, *name_type),
+            entry => panic!("invalid entry: {entry:?}"),
+        }
+    }
+
+    pub fn into_module(&self) -> ConstantIdx {
+        match self {
+            Self::Module(name) => *name,
+            entry => panic!("invalid entry: {entry:?}"),
+        }
+    }
+
+    pub fn into_package(&self) -> ConstantIdx {
+        match self {
+            Self::Package(name) => *name,
+            entry => panic!("invalid entry: {entry:?}"),
+        }
+    }
+}
+
+/// Floating point constants are compared by their bits, so that an entry is
+/// always equal to itself, even when it holds a NaN. This also makes entries
+/// usable as keys when deduplicating the constant pool.
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Utf8(a), Self::Utf8(b)) => a == b,
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::Long(a), Self::Long(b)) => a == b,
+            (Self::Double(a), Self::Double(b)) => a.to_bits() == b.to_bits(),
+            (Self::Class(a), Self::Class(b))
+            | (Self::String(a), Self::String(b))
+            | (Self::MethodType(a), Self::MethodType(b))
+            | (Self::Module(a), Self::Module(b))
+            | (Self::Package(a), Self::Package(b)) => a == b,
+            (Self::FieldRef(a, b), Self::FieldRef(c, d))
+            | (Self::MethodRef(a, b), Self::MethodRef(c, d))
+            | (Self::InterfaceMethodRef(a, b), Self::InterfaceMethodRef(c, d))
+            | (Self::NameType(a, b), Self::NameType(c, d)) => a == c && b == d,
+            (Self::MethodHandle(a, b), Self::MethodHandle(c, d)) => a == c && b == d,
+            (Self::InvokeDynamic(a, b), Self::InvokeDynamic(c, d))
+            | (Self::Dynamic(a, b), Self::Dynamic(c, d)) => a == c && b == d,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Entry {}
+
+impl std::hash::Hash for Entry {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Utf8(string) => string.hash(state),
+            Self::Integer(value) => value.hash(state),
+            Self::Float(value) => value.to_bits().hash(state),
+            Self::Long(value) => value.hash(state),
+            Self::Double(value) => value.to_bits().hash(state),
+            Self::Class(index)
+            | Self::String(index)
+            | Self::MethodType(index)
+            | Self::Module(index)
+            | Self::Package(index) => index.hash(state),
+            Self::FieldRef(a, b)
+            | Self::MethodRef(a, b)
+            | Self::InterfaceMethodRef(a, b)
+            | Self::NameType(a, b) => (a, b).hash(state),
+            Self::MethodHandle(kind, reference) => (kind, reference).hash(state),
+            Self::InvokeDynamic(bootstrap_method, name_type)
+            | Self::Dynamic(bootstrap_method, name_type) => {
+                (bootstrap_method, name_type).hash(state)
+            }
+        }
+    }
 }
 
 impl std::fmt::Debug for Entry {
@@ -245,12 +380,17 @@ impl std::fmt::Debug for Entry {
                 "InvokeDynamic({}, {})",
                 bootstrap_method, name_type_idx.0
             ),
+            Self::Dynamic(bootstrap_method, name_type_idx) => {
+                write!(f, "Dynamic({}, {})", bootstrap_method, name_type_idx.0)
+            }
+            Self::Module(name_idx) => write!(f, "Module({})", name_idx.0),
+            Self::Package(name_idx) => write!(f, "Package({})", name_idx.0),
         }
     }
 }
 
 /// Denotes the *kind* of a method handle, characterizing its bytecode behavior.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ReferenceKind {
     GetField,
     GetStatic,
@@ -1,6 +1,8 @@
 use std::fmt::Write;
 
-use crate::string::JavaString;
+use crate::string::{JavaStr, JavaString};
+
+use super::ParseError;
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct MethodDescriptor {
@@ -9,6 +11,40 @@ pub struct MethodDescriptor {
 }
 
 impl MethodDescriptor {
+    /// Creates a method descriptor from its parameter types and return type. A
+    /// return type of `None` denotes a `void` method.
+    #[inline]
+    #[must_use]
+    pub fn new(parameters: Vec<FieldType>, ret: Option<FieldType>) -> Self {
+        Self { parameters, ret }
+    }
+
+    /// Appends a parameter, returning the modified descriptor.
+    #[inline]
+    #[must_use]
+    pub fn with_arg(mut self, parameter: FieldType) -> Self {
+        self.parameters.push(parameter);
+        self
+    }
+
+    /// Sets the return type, returning the modified descriptor.
+    #[inline]
+    #[must_use]
+    pub fn with_result(mut self, ret: Option<FieldType>) -> Self {
+        self.ret = ret;
+        self
+    }
+
+    /// Returns the number of local variable slots taken up by the arguments of
+    /// the method, including the slot of the `this` reference unless
+    /// `is_static` is set. The flag is needed as a descriptor alone does not
+    /// say whether the method has a receiver.
+    #[must_use]
+    pub fn arg_slots(&self, is_static: bool) -> usize {
+        let receiver = if is_static { 0 } else { 1 };
+        receiver + self.parameters.iter().map(FieldType::slots).sum::<usize>()
+    }
+
     #[inline]
     #[must_use]
     pub fn args(&self) -> &[FieldType] {
@@ -60,6 +96,127 @@ pub enum FieldType {
     Array(Box<FieldType>),
 }
 
+impl FieldType {
+    /// Creates a reference type to the class with the internal binary `name`,
+    /// such as `java/lang/String`.
+    #[inline]
+    #[must_use]
+    pub fn class(name: &JavaStr) -> Self {
+        Self::Class(name.to_owned())
+    }
+
+    /// Creates an array type whose components are of type `component`.
+    #[inline]
+    #[must_use]
+    pub fn array(component: FieldType) -> Self {
+        Self::Array(Box::new(component))
+    }
+
+    /// Returns the number of local variable or operand stack slots that a value
+    /// of this type occupies. This is two for `long` and `double`, and one for
+    /// everything else.
+    #[inline]
+    #[must_use]
+    pub fn slots(&self) -> usize {
+        match self {
+            Self::Long | Self::Double => 2,
+            _ => 1,
+        }
+    }
+
+    /// Returns a value which displays the type as it would be written in Java
+    /// source code, such as `int[][]` or `java.lang.String`.
+    #[inline]
+    #[must_use]
+    pub fn source_name(&self) -> SourceName<'_> {
+        SourceName(self)
+    }
+
+    /// Parses a type written in Java source syntax, such as `int[][]` or
+    /// `java.lang.String`. Class names must be fully qualified.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidDescriptor`] if `name` is not a valid
+    /// primitive, class or array type.
+    pub fn from_source_name(name: &JavaStr) -> Result<Self, ParseError> {
+        let mut bytes = name.as_ref();
+
+        let mut dimensions = 0;
+        while let Some(rest) = bytes.strip_suffix(b"[]") {
+            bytes = rest;
+            dimensions += 1;
+        }
+
+        let mut field_type = match bytes {
+            b"byte" => Self::Byte,
+            b"short" => Self::Short,
+            b"int" => Self::Int,
+            b"long" => Self::Long,
+            b"float" => Self::Float,
+            b"double" => Self::Double,
+            b"char" => Self::Char,
+            b"boolean" => Self::Bool,
+            b"void" | [] | [b'.', ..] | [.., b'.'] => return Err(ParseError::InvalidDescriptor),
+            bytes => {
+                if bytes.windows(2).any(|window| window == b"..")
+                    || bytes.iter().any(|b| matches!(b, b'/' | b';' | b'[' | b']'))
+                {
+                    return Err(ParseError::InvalidDescriptor);
+                }
+
+                let internal = bytes
+                    .iter()
+                    .map(|&b| if b == b'.' { b'/' } else { b })
+                    .collect();
+
+                // SAFETY: Only ASCII bytes were replaced with other ASCII
+                // bytes, so the string is still valid Modified UTF-8.
+                Self::Class(unsafe { JavaString::from_java_unchecked(internal) })
+            }
+        };
+
+        for _ in 0..dimensions {
+            field_type = Self::array(field_type);
+        }
+        Ok(field_type)
+    }
+}
+
+/// Displays a [`FieldType`] in Java source syntax.
+///
+/// This struct is created by the [`source_name`] method on [`FieldType`].
+///
+/// [`source_name`]: FieldType::source_name
+#[derive(Clone, Copy)]
+pub struct SourceName<'a>(&'a FieldType);
+
+impl std::fmt::Display for SourceName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            FieldType::Byte => f.write_str("byte"),
+            FieldType::Short => f.write_str("short"),
+            FieldType::Int => f.write_str("int"),
+            FieldType::Long => f.write_str("long"),
+            FieldType::Float => f.write_str("float"),
+            FieldType::Double => f.write_str("double"),
+            FieldType::Char => f.write_str("char"),
+            FieldType::Bool => f.write_str("boolean"),
+            FieldType::Class(name) => {
+                for c in name.chars() {
+                    match char::from_u32(c) {
+                        Some('/') => f.write_char('.')?,
+                        Some(c) => f.write_char(c)?,
+                        None => f.write_char(char::REPLACEMENT_CHARACTER)?,
+                    }
+                }
+                Ok(())
+            }
+            FieldType::Array(component) => write!(f, "{}[]", component.source_name()),
+        }
+    }
+}
+
 impl std::fmt::Debug for FieldType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_char('"')?;
@@ -80,7 +237,112 @@ impl std::fmt::Display for FieldType {
             Self::Char => f.write_char('C'),
             Self::Bool => f.write_char('Z'),
             Self::Class(string) => write!(f, "L{string};"),
-            Self::Array(kind) => write!(f, "[{kind:?}"),
+            Self::Array(kind) => write!(f, "[{kind}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java_str;
+
+    #[test]
+    fn descriptors_are_built_and_displayed() {
+        let descriptor = MethodDescriptor::new(vec![FieldType::Int], None)
+            .with_arg(FieldType::array(FieldType::class(java_str!(
+                "java/lang/String"
+            ))))
+            .with_arg(FieldType::array(FieldType::array(FieldType::Double)))
+            .with_result(Some(FieldType::Long));
+        assert_eq!(descriptor.to_string(), "(I[Ljava/lang/String;[[D)J");
+        assert_eq!(
+            MethodDescriptor::parse(java_str!("(I[Ljava/lang/String;[[D)J")),
+            Ok(descriptor)
+        );
+        let void = MethodDescriptor::new(Vec::new(), None);
+        assert_eq!(void.to_string(), "()V");
+        assert_eq!(void.result(), None);
+    }
+
+    #[test]
+    fn longs_and_doubles_take_two_slots() {
+        assert_eq!(FieldType::Long.slots(), 2);
+        assert_eq!(FieldType::Double.slots(), 2);
+        assert_eq!(FieldType::Int.slots(), 1);
+        assert_eq!(FieldType::Bool.slots(), 1);
+        assert_eq!(FieldType::class(java_str!("A")).slots(), 1);
+        // An array is a reference, whatever its components.
+        assert_eq!(FieldType::array(FieldType::Long).slots(), 1);
+
+        let descriptor = MethodDescriptor::parse(java_str!("(JIDLA;[J)V")).unwrap();
+        assert_eq!(descriptor.arg_slots(true), 7);
+        assert_eq!(descriptor.arg_slots(false), 8);
+        let empty = MethodDescriptor::parse(java_str!("()I")).unwrap();
+        assert_eq!(empty.arg_slots(true), 0);
+        assert_eq!(empty.arg_slots(false), 1);
+    }
+
+    #[test]
+    fn nested_arrays_display_their_dimensions() {
+        let nested = FieldType::array(FieldType::array(FieldType::array(FieldType::class(
+            java_str!("java/lang/Object"),
+        ))));
+        assert_eq!(nested.to_string(), "[[[Ljava/lang/Object;");
+        assert_eq!(nested.source_name().to_string(), "java.lang.Object[][][]");
+        assert_eq!(
+            FieldType::parse(java_str!("[[[Ljava/lang/Object;")),
+            Ok(nested)
+        );
+        let ints = FieldType::array(FieldType::array(FieldType::Int));
+        assert_eq!(ints.to_string(), "[[I");
+        assert_eq!(ints.source_name().to_string(), "int[][]");
+    }
+
+    #[test]
+    fn source_names_round_trip() {
+        for name in [
+            "byte",
+            "short",
+            "int",
+            "long",
+            "float",
+            "double",
+            "char",
+            "boolean",
+            "java.lang.String",
+            "int[][]",
+            "java.util.Map[]",
+        ] {
+            let field_type = FieldType::from_source_name(crate::string::from_utf8(name).as_ref());
+            assert_eq!(field_type.unwrap().source_name().to_string(), name);
+        }
+        assert_eq!(
+            FieldType::from_source_name(java_str!("java.lang.String[]")),
+            Ok(FieldType::array(FieldType::class(java_str!(
+                "java/lang/String"
+            ))))
+        );
+    }
+
+    #[test]
+    fn invalid_source_names_are_rejected() {
+        for name in [
+            "void",
+            "",
+            "[]",
+            ".A",
+            "A.",
+            "a..b",
+            "java/lang/String",
+            "int[",
+            "A;",
+        ] {
+            assert_eq!(
+                FieldType::from_source_name(crate::string::from_utf8(name).as_ref()),
+                Err(ParseError::InvalidDescriptor),
+                "{name}"
+            );
         }
     }
 }
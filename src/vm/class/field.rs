@@ -1,11 +1,16 @@
 use crate::string::JavaStr;
 
-use super::{ConstantIdx, ConstantPool, FieldType};
+use super::{Attribute, ConstantIdx, ConstantPool, FieldSignature, FieldType, ParseError};
 
+#[derive(PartialEq)]
 pub struct Field {
     pub(super) name: ConstantIdx,
     pub(super) descriptor: ConstantIdx,
     pub(super) parsed_descriptor: FieldType,
+    pub(super) flags: FieldFlags,
+    pub(super) signature: Option<ConstantIdx>,
+    pub(super) constant_value: Option<ConstantIdx>,
+    pub(super) attributes: Vec<Attribute>,
 }
 
 impl Field {
@@ -20,6 +25,38 @@ impl Field {
     pub fn parsed_descriptor(&self) -> &FieldType {
         &self.parsed_descriptor
     }
+
+    pub fn flags(&self) -> FieldFlags {
+        self.flags
+    }
+
+    /// Returns the index of the constant used to initialize the field, if it
+    /// has a `ConstantValue` attribute.
+    pub fn constant_value(&self) -> Option<ConstantIdx> {
+        self.constant_value
+    }
+
+    /// Returns the generic signature of the field, if it has a `Signature`
+    /// attribute.
+    pub fn signature<'a>(&self, constant_pool: &'a ConstantPool) -> Option<&'a JavaStr> {
+        self.signature
+            .map(|signature| constant_pool.get(signature).into_utf8())
+    }
+
+    /// Parses the generic signature of the field, if it has a `Signature`
+    /// attribute. A malformed signature is only reported here.
+    pub fn parsed_signature(
+        &self,
+        constant_pool: &ConstantPool,
+    ) -> Option<Result<FieldSignature, ParseError>> {
+        self.signature(constant_pool).map(FieldSignature::parse)
+    }
+
+    /// Returns the attributes of the field, in the order they appear in the
+    /// class file.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
 }
 
 impl std::fmt::Debug for Field {
@@ -27,6 +64,117 @@ impl std::fmt::Debug for Field {
         f.debug_struct("Field")
             .field("name", &self.name)
             .field("descriptor", &self.parsed_descriptor)
+            .field("flags", &self.flags)
+            .field("signature", &self.signature)
             .finish()
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FieldFlags(u16);
+
+impl FieldFlags {
+    /// Declared public; may be accessed from outside its
+    /// package.
+    pub const PUBLIC: Self = Self(0x0001);
+    /// Declared private; usable only within the defining
+    /// class.
+    pub const PRIVATE: Self = Self(0x0002);
+    /// Declared protected; may be accessed within
+    /// subclasses.
+    pub const PROTECTED: Self = Self(0x0004);
+    /// Declared static
+    pub const STATIC: Self = Self(0x0008);
+    /// Declared final; never directly assigned to after
+    /// object construction.
+    pub const FINAL: Self = Self(0x0010);
+    /// Declared volatile; cannot be cached.
+    pub const VOLATILE: Self = Self(0x0040);
+    /// Declared transient; not written or read by a
+    /// persistent object manager.
+    pub const TRANSIENT: Self = Self(0x0080);
+    /// Declared synthetic; not present in the source code.
+    pub const SYNTHETIC: Self = Self(0x1000);
+    /// Declared as an element of an enum.
+    pub const ENUM: Self = Self(0x4000);
+
+    /// Used for constructing a value during parsing. We do
+    /// check bits here because unknown bits are to be
+    /// ignored according to the specification.
+    pub(super) const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw bits of the flags, as stored in the
+    /// class file.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub(super) const FLAGS: [(FieldFlags, &'static str); 9] = [
+        (FieldFlags::PUBLIC, "PUBLIC"),
+        (FieldFlags::PRIVATE, "PRIVATE"),
+        (FieldFlags::PROTECTED, "PROTECTED"),
+        (FieldFlags::STATIC, "STATIC"),
+        (FieldFlags::FINAL, "FINAL"),
+        (FieldFlags::VOLATILE, "VOLATILE"),
+        (FieldFlags::TRANSIENT, "TRANSIENT"),
+        (FieldFlags::SYNTHETIC, "SYNTHETIC"),
+        (FieldFlags::ENUM, "ENUM"),
+    ];
+}
+
+impl std::ops::BitAnd<FieldFlags> for FieldFlags {
+    type Output = FieldFlags;
+
+    fn bitand(self, rhs: FieldFlags) -> Self::Output {
+        FieldFlags(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::BitAnd<FieldFlags> for &FieldFlags {
+    type Output = FieldFlags;
+
+    fn bitand(self, rhs: FieldFlags) -> Self::Output {
+        FieldFlags(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::BitOr<FieldFlags> for FieldFlags {
+    type Output = FieldFlags;
+
+    fn bitor(self, rhs: FieldFlags) -> Self::Output {
+        FieldFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOr<FieldFlags> for &FieldFlags {
+    type Output = FieldFlags;
+
+    fn bitor(self, rhs: FieldFlags) -> Self::Output {
+        FieldFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::fmt::Debug for FieldFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FieldFlags({})", self)
+    }
+}
+
+impl std::fmt::Display for FieldFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for (flag, name) in Self::FLAGS {
+            if self & flag == flag {
+                if !first {
+                    write!(f, " | {name}")?;
+                } else {
+                    write!(f, "{name}")?;
+                    first = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::reader::Reader;
 
-use super::{parse_instruction, ConstantIdx};
+use super::{parse_instruction, ConstantIdx, ParseError};
 
 /// An array of bytes which make up Java's bytecode. This gives utilities to
 /// iterate over each bytecode and set the program counter.
@@ -24,26 +24,50 @@ impl<'a> Bytecode<'a> {
     pub fn set_pc(&mut self, pc: u32) {
         self.pc = pc;
     }
-}
-
-impl<'a> Iterator for Bytecode<'a> {
-    type Item = (u32, Instruction<'a>);
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Parses the instruction at the program counter and moves past it, or
+    /// returns `None` at the end of the bytecode.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidInstruction`] if the opcode is unknown or
+    /// an operand byte which must be zero is not, and
+    /// [`ParseError::UnexpectedEndOfFile`] if the bytecode ends within the
+    /// instruction.
+    pub fn try_next(&mut self) -> Option<Result<(u32, Instruction<'a>), ParseError>> {
         if self.pc < self.slice.len() as u32 {
             let pc = self.pc as usize;
 
             let mut reader = Reader::new(&self.slice[pc..]);
-            let instruction = parse_instruction(&mut reader, pc).unwrap();
+            let instruction = match parse_instruction(&mut reader, pc) {
+                Ok(instruction) => instruction,
+                Err(error) => return Some(Err(error)),
+            };
             self.pc = (self.slice.len() - reader.remaining()) as u32;
 
-            Some((pc as u32, instruction))
+            Some(Ok((pc as u32, instruction)))
         } else {
             None
         }
     }
 }
 
+/// Iterates over the instructions of bytecode which is known to be valid,
+/// such as that of a class which has been loaded.
+///
+/// # Panics
+///
+/// Panics if an instruction cannot be parsed. Use [`Bytecode::try_next`] to
+/// read bytecode which may be malformed.
+impl<'a> Iterator for Bytecode<'a> {
+    type Item = (u32, Instruction<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next()
+            .map(|result| result.expect("invalid instruction"))
+    }
+}
+
 impl std::fmt::Debug for Bytecode<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut list = f.debug_list();
@@ -238,6 +262,32 @@ pub enum Instruction<'a> {
     iinc(u16, i16),
 }
 
+impl Instruction<'_> {
+    /// Returns the offset of a conditional jump, or `None` if the instruction
+    /// is not a conditional jump.
+    pub fn branch_offset(&self) -> Option<i16> {
+        match *self {
+            Instruction::if_eq(offset)
+            | Instruction::if_ne(offset)
+            | Instruction::if_lt(offset)
+            | Instruction::if_ge(offset)
+            | Instruction::if_gt(offset)
+            | Instruction::if_le(offset)
+            | Instruction::if_icmp_eq(offset)
+            | Instruction::if_icmp_ne(offset)
+            | Instruction::if_icmp_lt(offset)
+            | Instruction::if_icmp_ge(offset)
+            | Instruction::if_icmp_gt(offset)
+            | Instruction::if_icmp_le(offset)
+            | Instruction::if_acmp_eq(offset)
+            | Instruction::if_acmp_ne(offset)
+            | Instruction::ifnull(offset)
+            | Instruction::ifnonnull(offset) => Some(offset),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArrayKind {
     Bool,
@@ -258,19 +308,31 @@ pub struct LookupSwitch<'a> {
 
 impl<'a> LookupSwitch<'a> {
     pub fn new(default: i32, pairs: &'a [u8]) -> Self {
-        assert!(pairs.len() % (4 * 2) == 0);
+        assert!(pairs.len().is_multiple_of(4 * 2));
         Self { default, pairs }
     }
 
-    pub fn lookup(&self, key: i32) -> i32 {
-        let mut reader = Reader::new(self.pairs);
-
-        while !reader.is_empty() {
-            let to_match = reader.read_u32().unwrap() as i32;
-            let offset = reader.read_u32().unwrap() as i32;
+    /// Returns the offset jumped to when no key matches.
+    pub fn default(&self) -> i32 {
+        self.default
+    }
 
-            if to_match == key {
-                return offset;
+    /// Returns the offset jumped to for `key`. The pairs of a `lookupswitch`
+    /// are sorted by their keys, so they are binary searched.
+    pub fn lookup(&self, key: i32) -> i32 {
+        let pair = |index: usize| {
+            let mut reader = Reader::new(&self.pairs[index * 8..]);
+            (reader.read_i32().unwrap(), reader.read_i32().unwrap())
+        };
+
+        let (mut low, mut high) = (0, self.pairs.len() / 8);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let (to_match, offset) = pair(middle);
+            match to_match.cmp(&key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return offset,
             }
         }
 
@@ -284,10 +346,7 @@ impl<'a> LookupSwitch<'a> {
             if reader.is_empty() {
                 None
             } else {
-                Some((
-                    reader.read_u32().unwrap() as i32,
-                    reader.read_u32().unwrap() as i32,
-                ))
+                Some((reader.read_i32().unwrap(), reader.read_i32().unwrap()))
             }
         })
     }
@@ -320,7 +379,7 @@ pub struct TableSwitch<'a> {
 
 impl<'a> TableSwitch<'a> {
     pub fn new(default: i32, low: i32, high: i32, offsets: &'a [u8]) -> Self {
-        assert!(offsets.len() % 4 == 0);
+        assert!(offsets.len().is_multiple_of(4));
         Self {
             default,
             low,
@@ -329,6 +388,19 @@ impl<'a> TableSwitch<'a> {
         }
     }
 
+    /// Returns the offset jumped to when the key is out of range.
+    pub fn default(&self) -> i32 {
+        self.default
+    }
+
+    pub fn low(&self) -> i32 {
+        self.low
+    }
+
+    pub fn high(&self) -> i32 {
+        self.high
+    }
+
     pub fn lookup(&self, key: i32) -> i32 {
         if key < self.low || self.high < key {
             return self.default;
@@ -338,12 +410,12 @@ impl<'a> TableSwitch<'a> {
 
         let mut reader = Reader::new(self.offsets);
         reader.skip(position as usize * 4).unwrap();
-        reader.read_u32().unwrap() as i32
+        reader.read_i32().unwrap()
     }
 
     pub fn offsets(&self) -> impl Iterator<Item = i32> + 'a {
         let mut reader = Reader::new(self.offsets);
-        std::iter::from_fn(move || reader.read_u32().map(|value| value as i32).ok())
+        std::iter::from_fn(move || reader.read_i32().ok())
     }
 
     fn fmt(&self, f: &mut std::fmt::Formatter, pc: u32) -> std::fmt::Result {
@@ -1,13 +1,19 @@
 use crate::string::JavaStr;
 
-use super::{Bytecode, ConstantIdx, ConstantPool, MethodDescriptor};
+use super::{
+    Attribute, Bytecode, ConstantIdx, ConstantPool, MethodDescriptor, MethodSignature, ParseError,
+};
 
+#[derive(PartialEq)]
 pub struct Method {
     pub(super) name: ConstantIdx,
     pub(super) descriptor: ConstantIdx,
     pub(super) parsed_descriptor: MethodDescriptor,
     pub(super) flags: MethodFlags,
+    pub(super) signature: Option<ConstantIdx>,
+    pub(super) exceptions: Vec<ConstantIdx>,
     pub(super) code: Option<Code>,
+    pub(super) attributes: Vec<Attribute>,
 }
 
 impl Method {
@@ -27,9 +33,43 @@ impl Method {
         self.flags
     }
 
+    /// Returns the generic signature of the method, if it has a `Signature`
+    /// attribute.
+    pub fn signature<'a>(&self, constant_pool: &'a ConstantPool) -> Option<&'a JavaStr> {
+        self.signature
+            .map(|signature| constant_pool.get(signature).into_utf8())
+    }
+
+    /// Parses the generic signature of the method, if it has a `Signature`
+    /// attribute. A malformed signature is only reported here.
+    pub fn parsed_signature(
+        &self,
+        constant_pool: &ConstantPool,
+    ) -> Option<Result<MethodSignature, ParseError>> {
+        self.signature(constant_pool).map(MethodSignature::parse)
+    }
+
+    /// Returns the names of the checked exceptions the method is declared to
+    /// throw, as listed in its `Exceptions` attribute.
+    pub fn exceptions<'a>(
+        &'a self,
+        constant_pool: &'a ConstantPool,
+    ) -> impl Iterator<Item = &'a JavaStr> + 'a {
+        self.exceptions.iter().map(|&class| {
+            let name = constant_pool.get(class).into_class();
+            constant_pool.get(name).into_utf8()
+        })
+    }
+
     pub fn bytecode(&self) -> Option<&Code> {
         self.code.as_ref()
     }
+
+    /// Returns the attributes of the method, in the order they appear in the
+    /// class file.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
 }
 
 impl std::fmt::Debug for Method {
@@ -38,6 +78,7 @@ impl std::fmt::Debug for Method {
             .field("name", &self.name)
             .field("descriptor", &self.parsed_descriptor)
             .field("flags", &self.flags)
+            .field("signature", &self.signature)
             .field_with("code", |f| {
                 if let Some(code) = &self.code {
                     code.fmt(f)
@@ -91,7 +132,13 @@ impl MethodFlags {
         Self(bits)
     }
 
-    const FLAGS: [(MethodFlags, &'static str); 12] = [
+    /// Returns the raw bits of the flags, as stored in the
+    /// class file.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub(super) const FLAGS: [(MethodFlags, &'static str); 12] = [
         (MethodFlags::PUBLIC, "PUBLIC"),
         (MethodFlags::PRIVATE, "PRIVATE"),
         (MethodFlags::PROTECTED, "PROTECTED"),
@@ -162,10 +209,16 @@ impl std::fmt::Display for MethodFlags {
     }
 }
 
+#[derive(PartialEq)]
 pub struct Code {
     pub max_stack: u16,
     pub max_locals: u16,
     pub bytecode: Vec<u8>,
+    pub exception_table: Vec<ExceptionHandler>,
+    pub line_numbers: Vec<LineNumber>,
+    pub local_variables: Vec<LocalVariable>,
+    pub local_variable_types: Vec<LocalVariable>,
+    pub attributes: Vec<Attribute>,
 }
 
 impl Code {
@@ -177,9 +230,40 @@ impl Code {
         self.max_locals
     }
 
-    pub fn bytecode(&self) -> Bytecode {
+    pub fn bytecode(&self) -> Bytecode<'_> {
         Bytecode::new(self.bytecode.as_slice())
     }
+
+    pub fn exception_table(&self) -> &[ExceptionHandler] {
+        &self.exception_table
+    }
+
+    /// Returns the entries of the `LineNumberTable` attributes of the code.
+    pub fn line_numbers(&self) -> &[LineNumber] {
+        &self.line_numbers
+    }
+
+    /// Returns the entries of the `LocalVariableTable` attributes of the code.
+    pub fn local_variables(&self) -> &[LocalVariable] {
+        &self.local_variables
+    }
+
+    /// Returns the entries of the `LocalVariableTypeTable` attributes of the
+    /// code. The descriptor of each entry is a field signature rather than a
+    /// field descriptor.
+    pub fn local_variable_types(&self) -> &[LocalVariable] {
+        &self.local_variable_types
+    }
+
+    /// Returns the source line which the instruction at `pc` belongs to, if
+    /// the code has line number information.
+    pub fn line_number(&self, pc: u16) -> Option<u16> {
+        self.line_numbers
+            .iter()
+            .filter(|entry| entry.start_pc <= pc)
+            .max_by_key(|entry| entry.start_pc)
+            .map(|entry| entry.line)
+    }
 }
 
 impl std::fmt::Debug for Code {
@@ -188,6 +272,38 @@ impl std::fmt::Debug for Code {
             .field("max_stack", &self.max_stack)
             .field("max_locals", &self.max_locals)
             .field("bytecode", &self.bytecode())
+            .field("exception_table", &self.exception_table)
             .finish()
     }
 }
+
+/// An entry of the exception table of a [`Code`] attribute. The handler is
+/// active for instructions in the range `start_pc..end_pc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionHandler {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// The class of exceptions caught by the handler. `None` catches every
+    /// exception, and is used to implement `finally`.
+    pub catch_type: Option<ConstantIdx>,
+}
+
+/// Maps the start of a range of bytecode to a line in the original source
+/// file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineNumber {
+    pub start_pc: u16,
+    pub line: u16,
+}
+
+/// Describes a local variable which holds a value for the instructions in the
+/// range `start_pc..start_pc + length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: ConstantIdx,
+    pub descriptor: ConstantIdx,
+    pub index: u16,
+}
@@ -1,3 +1,7 @@
+mod assembly;
+mod attribute;
+mod builder;
+#[allow(clippy::module_inception)]
 mod class;
 mod constant_pool;
 mod descriptor;
@@ -5,7 +9,13 @@ mod field;
 mod instruction;
 mod method;
 mod parse;
+mod signature;
+pub(crate) mod stack_map;
+mod write;
 
+pub use assembly::*;
+pub use attribute::*;
+pub use builder::*;
 pub use class::*;
 pub use constant_pool::*;
 pub use descriptor::*;
@@ -13,3 +23,6 @@ pub use field::*;
 pub use instruction::*;
 pub use method::*;
 pub use parse::*;
+pub use signature::*;
+pub use stack_map::{Frame, VerificationType};
+pub use write::*;
@@ -2,11 +2,14 @@ use std::num::NonZeroU16;
 
 use crate::java_str;
 use crate::reader::{Reader, ReaderError};
-use crate::string::{EncodingError, JavaChars, JavaStr};
+use crate::string::{EncodingError, JavaChars, JavaStr, JavaString};
 
 use super::{
-    ArrayKind, Class, Code, ConstantIdx, ConstantPool, Entry, Field, FieldType, Instruction,
-    LookupSwitch, Method, MethodDescriptor, MethodFlags, ReferenceKind, TableSwitch,
+    ArrayKind, Attribute, AttributeKind, Class, ClassFlags, ClassSignature, ClassTypeSignature,
+    Code, ConstantIdx, ConstantPool, Entry, ExceptionHandler, Field, FieldFlags, FieldSignature,
+    FieldType, Instruction, LineNumber, LocalVariable, LookupSwitch, MemberIndex, Method,
+    MethodDescriptor, MethodFlags, MethodSignature, ReferenceKind, SimpleClassTypeSignature,
+    TableSwitch, TypeArgument, TypeParameter, TypeSignature,
 };
 
 type Result<T> = std::result::Result<T, ParseError>;
@@ -21,6 +24,13 @@ pub enum ParseError {
     InvalidConstantIdx,
     InvalidArrayType,
     InvalidDescriptor,
+    InvalidSignature,
+    /// A jump in a `Code` attribute targets an offset which is not the start
+    /// of an instruction.
+    InvalidBranchTarget,
+    /// A `Code` attribute holds an unknown opcode, or an operand byte which
+    /// must be zero is not.
+    InvalidInstruction,
 }
 
 impl From<EncodingError> for ParseError {
@@ -64,14 +74,14 @@ pub fn parse(slice: &[u8]) -> Result<Class> {
     }
 
     // File Version
-    let _minor = reader.read_u16()?;
-    let _major = reader.read_u16()?;
+    let minor_version = reader.read_u16()?;
+    let major_version = reader.read_u16()?;
 
     // Constant Pool
     let constants = parse_constant_pool(&mut reader)?;
 
     // Access Flags
-    let _access_flags = reader.read_u16()?;
+    let flags = ClassFlags::from_bits(reader.read_u16()?);
 
     // Class Name
     let this_class = ConstantIdx::try_from(reader.read_u16()?)?;
@@ -83,7 +93,7 @@ pub fn parse(slice: &[u8]) -> Result<Class> {
     let interface_count = reader.read_u16()? as usize;
     let mut interfaces = Vec::with_capacity(interface_count);
     for _ in 0..interface_count {
-        interfaces.push(reader.read_u16()?);
+        interfaces.push(ConstantIdx::try_from(reader.read_u16()?)?);
     }
 
     // Field
@@ -100,17 +110,46 @@ pub fn parse(slice: &[u8]) -> Result<Class> {
         methods.push(parse_method(&mut reader, &constants)?);
     }
 
-    let attribute_count = reader.read_u16()?;
+    let mut signature = None;
+    let mut source_file = None;
+    let attribute_count = reader.read_u16()? as usize;
+    let mut attributes = Vec::with_capacity(attribute_count);
     for _ in 0..attribute_count {
-        let _ = parse_attribute(&mut reader)?;
+        let (name_idx, slice) = parse_attribute(&mut reader)?;
+
+        let name = constants.get(name_idx).into_utf8();
+        let kind = if name == java_str!("Signature") {
+            let signature_idx = parse_signature_idx(slice)?;
+            signature = Some(signature_idx);
+            AttributeKind::Signature(signature_idx)
+        } else if name == java_str!("SourceFile") {
+            let mut reader = Reader::new(slice);
+            source_file = Some(ConstantIdx::try_from(reader.read_u16()?)?);
+            AttributeKind::SourceFile
+        } else {
+            AttributeKind::Raw(slice.to_owned())
+        };
+        attributes.push(Attribute {
+            name: name_idx,
+            kind,
+        });
     }
 
+    let members = MemberIndex::new(&constants, &fields, &methods);
     Ok(Class {
+        minor_version,
+        major_version,
         constants,
+        flags,
         this_class,
         super_class,
+        interfaces,
         fields,
         methods,
+        signature,
+        source_file,
+        attributes,
+        members,
     })
 }
 
@@ -187,6 +226,19 @@ fn parse_constant_pool(reader: &mut Reader) -> Result<ConstantPool> {
                 let name_type_idx = ConstantIdx::try_from(reader.read_u16()?)?;
                 Entry::InvokeDynamic(bootstrap_method_attr_idx, name_type_idx)
             }
+            17 => {
+                let bootstrap_method_attr_idx = reader.read_u16()?;
+                let name_type_idx = ConstantIdx::try_from(reader.read_u16()?)?;
+                Entry::Dynamic(bootstrap_method_attr_idx, name_type_idx)
+            }
+            19 => {
+                let name_idx = ConstantIdx::try_from(reader.read_u16()?)?;
+                Entry::Module(name_idx)
+            }
+            20 => {
+                let name_idx = ConstantIdx::try_from(reader.read_u16()?)?;
+                Entry::Package(name_idx)
+            }
             _ => return Err(ParseError::InvalidConstantTag),
         };
         constants.add(entry);
@@ -196,21 +248,45 @@ fn parse_constant_pool(reader: &mut Reader) -> Result<ConstantPool> {
 }
 
 fn parse_field(reader: &mut Reader, constants: &ConstantPool) -> Result<Field> {
-    let _access_flags = reader.read_u16()?;
+    let flags = FieldFlags::from_bits(reader.read_u16()?);
     let name = ConstantIdx::try_from(reader.read_u16()?)?;
     let descriptor = ConstantIdx::try_from(reader.read_u16()?)?;
     let descriptor_str = constants.get(descriptor).into_utf8();
-    let parsed_descriptor = parse_field_descriptor(&mut descriptor_str.chars())?;
+    let parsed_descriptor = FieldType::parse(descriptor_str)?;
 
-    let attribute_count = reader.read_u16()?;
+    let mut signature = None;
+    let mut constant_value = None;
+    let attribute_count = reader.read_u16()? as usize;
+    let mut attributes = Vec::with_capacity(attribute_count);
     for _ in 0..attribute_count {
-        let _ = parse_attribute(reader)?;
+        let (name_idx, slice) = parse_attribute(reader)?;
+
+        let name = constants.get(name_idx).into_utf8();
+        let kind = if name == java_str!("Signature") {
+            let signature_idx = parse_signature_idx(slice)?;
+            signature = Some(signature_idx);
+            AttributeKind::Signature(signature_idx)
+        } else if name == java_str!("ConstantValue") {
+            let mut reader = Reader::new(slice);
+            constant_value = Some(ConstantIdx::try_from(reader.read_u16()?)?);
+            AttributeKind::ConstantValue
+        } else {
+            AttributeKind::Raw(slice.to_owned())
+        };
+        attributes.push(Attribute {
+            name: name_idx,
+            kind,
+        });
     }
 
     Ok(Field {
         name,
         descriptor,
         parsed_descriptor,
+        flags,
+        signature,
+        constant_value,
+        attributes,
     })
 }
 
@@ -219,40 +295,38 @@ fn parse_method(reader: &mut Reader, constants: &ConstantPool) -> Result<Method>
     let name = ConstantIdx::try_from(reader.read_u16()?)?;
     let descriptor = ConstantIdx::try_from(reader.read_u16()?)?;
     let descriptor_str = constants.get(descriptor).into_utf8();
-    let parsed_descriptor = parse_method_descriptor(&mut descriptor_str.chars())?;
+    let parsed_descriptor = MethodDescriptor::parse(descriptor_str)?;
 
     let mut code = None;
-    let attribute_count = reader.read_u16()?;
+    let mut signature = None;
+    let mut exceptions = Vec::new();
+    let attribute_count = reader.read_u16()? as usize;
+    let mut attributes = Vec::with_capacity(attribute_count);
     for _ in 0..attribute_count {
-        let (name, slice) = parse_attribute(reader)?;
-
-        if constants.get(name).into_utf8() == java_str!("Code") {
+        let (name_idx, slice) = parse_attribute(reader)?;
+
+        let name = constants.get(name_idx).into_utf8();
+        let kind = if name == java_str!("Code") {
+            code = Some(parse_code(slice, constants)?);
+            AttributeKind::Code
+        } else if name == java_str!("Signature") {
+            let signature_idx = parse_signature_idx(slice)?;
+            signature = Some(signature_idx);
+            AttributeKind::Signature(signature_idx)
+        } else if name == java_str!("Exceptions") {
             let mut reader = Reader::new(slice);
-
-            let max_stack = reader.read_u16()?;
-            let max_locals = reader.read_u16()?;
-            let code_len = reader.read_u32()?;
-            let code_bytes = reader.read_slice(code_len as usize)?;
-
-            let exception_table_len = reader.read_u16()?;
-            for _ in 0..exception_table_len {
-                let _start_pc = reader.read_u16()?;
-                let _end_pc = reader.read_u16()?;
-                let _handler_pc = reader.read_u16()?;
-                let _catch_type = reader.read_u16()?;
+            let exception_count = reader.read_u16()?;
+            for _ in 0..exception_count {
+                exceptions.push(ConstantIdx::try_from(reader.read_u16()?)?);
             }
-
-            let attribute_count = reader.read_u16()?;
-            for _ in 0..attribute_count {
-                let _ = parse_attribute(&mut reader)?;
-            }
-
-            code = Some(Code {
-                max_stack,
-                max_locals,
-                bytecode: code_bytes.to_owned(),
-            });
-        }
+            AttributeKind::Exceptions
+        } else {
+            AttributeKind::Raw(slice.to_owned())
+        };
+        attributes.push(Attribute {
+            name: name_idx,
+            kind,
+        });
     }
 
     Ok(Method {
@@ -260,11 +334,141 @@ fn parse_method(reader: &mut Reader, constants: &ConstantPool) -> Result<Method>
         descriptor,
         parsed_descriptor,
         flags,
+        signature,
+        exceptions,
 
         code,
+        attributes,
     })
 }
 
+fn parse_code(slice: &[u8], constants: &ConstantPool) -> Result<Code> {
+    let mut reader = Reader::new(slice);
+
+    let max_stack = reader.read_u16()?;
+    let max_locals = reader.read_u16()?;
+    let code_len = reader.read_u32()?;
+    let code_bytes = reader.read_slice(code_len as usize)?;
+
+    let exception_table_len = reader.read_u16()?;
+    let mut exception_table = Vec::with_capacity(exception_table_len as usize);
+    for _ in 0..exception_table_len {
+        let start_pc = reader.read_u16()?;
+        let end_pc = reader.read_u16()?;
+        let handler_pc = reader.read_u16()?;
+        let catch_type = NonZeroU16::new(reader.read_u16()?).map(ConstantIdx);
+        exception_table.push(ExceptionHandler {
+            start_pc,
+            end_pc,
+            handler_pc,
+            catch_type,
+        });
+    }
+
+    let mut line_numbers = Vec::new();
+    let mut local_variables = Vec::new();
+    let mut local_variable_types = Vec::new();
+    let attribute_count = reader.read_u16()? as usize;
+    let mut attributes = Vec::with_capacity(attribute_count);
+    for _ in 0..attribute_count {
+        let (name_idx, slice) = parse_attribute(&mut reader)?;
+
+        let name = constants.get(name_idx).into_utf8();
+        let kind = if name == java_str!("LineNumberTable") {
+            let mut reader = Reader::new(slice);
+            let line_number_count = reader.read_u16()?;
+            for _ in 0..line_number_count {
+                let start_pc = reader.read_u16()?;
+                let line = reader.read_u16()?;
+                line_numbers.push(LineNumber { start_pc, line });
+            }
+            AttributeKind::LineNumberTable(line_number_count as usize)
+        } else if name == java_str!("LocalVariableTable") {
+            let count = parse_local_variable_table(slice, &mut local_variables)?;
+            AttributeKind::LocalVariableTable(count)
+        } else if name == java_str!("LocalVariableTypeTable") {
+            let count = parse_local_variable_table(slice, &mut local_variable_types)?;
+            AttributeKind::LocalVariableTypeTable(count)
+        } else {
+            AttributeKind::Raw(slice.to_owned())
+        };
+        attributes.push(Attribute {
+            name: name_idx,
+            kind,
+        });
+    }
+
+    Ok(Code {
+        max_stack,
+        max_locals,
+        bytecode: code_bytes.to_owned(),
+        exception_table,
+        line_numbers,
+        local_variables,
+        local_variable_types,
+        attributes,
+    })
+}
+
+/// Parses a `LocalVariableTable` or `LocalVariableTypeTable` attribute into
+/// `local_variables`, returning the number of entries it contained.
+fn parse_local_variable_table(
+    slice: &[u8],
+    local_variables: &mut Vec<LocalVariable>,
+) -> Result<usize> {
+    let mut reader = Reader::new(slice);
+    let local_variable_count = reader.read_u16()?;
+    for _ in 0..local_variable_count {
+        let start_pc = reader.read_u16()?;
+        let length = reader.read_u16()?;
+        let name = ConstantIdx::try_from(reader.read_u16()?)?;
+        let descriptor = ConstantIdx::try_from(reader.read_u16()?)?;
+        let index = reader.read_u16()?;
+        local_variables.push(LocalVariable {
+            start_pc,
+            length,
+            name,
+            descriptor,
+            index,
+        });
+    }
+    Ok(local_variable_count as usize)
+}
+
+impl MethodDescriptor {
+    /// Parses a method descriptor, such as `(I[Ljava/lang/String;)V`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidDescriptor`] if `descriptor` is not a
+    /// valid method descriptor.
+    pub fn parse(descriptor: &JavaStr) -> Result<Self> {
+        let mut chars = descriptor.chars();
+        let method_descriptor = parse_method_descriptor(&mut chars)?;
+        if chars.next().is_some() {
+            return Err(ParseError::InvalidDescriptor);
+        }
+        Ok(method_descriptor)
+    }
+}
+
+impl FieldType {
+    /// Parses a field descriptor, such as `[Ljava/lang/String;`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidDescriptor`] if `descriptor` is not a
+    /// valid field descriptor.
+    pub fn parse(descriptor: &JavaStr) -> Result<Self> {
+        let mut chars = descriptor.chars();
+        let field_type = parse_field_descriptor(&mut chars)?;
+        if chars.next().is_some() {
+            return Err(ParseError::InvalidDescriptor);
+        }
+        Ok(field_type)
+    }
+}
+
 fn parse_method_descriptor(chars: &mut JavaChars) -> Result<MethodDescriptor> {
     let Some('(') = chars.next().and_then(char::from_u32) else {
         return Err(ParseError::InvalidDescriptor);
@@ -280,6 +484,7 @@ fn parse_method_descriptor(chars: &mut JavaChars) -> Result<MethodDescriptor> {
     }
 
     let ret = if let Some('V') = chars.clone().next().and_then(char::from_u32) {
+        chars.next();
         None
     } else {
         Some(parse_field_descriptor(chars)?)
@@ -298,12 +503,17 @@ fn parse_field_descriptor(chars: &mut JavaChars) -> Result<FieldType> {
         Some('J') => FieldType::Long,
         Some('L') => {
             let remaining = chars.as_str();
-            while let Some(c) = chars.next().and_then(char::from_u32) {
-                if c == ';' {
-                    break;
+            loop {
+                match chars.next().and_then(char::from_u32) {
+                    Some(';') => break,
+                    Some('.' | '[') | None => return Err(ParseError::InvalidDescriptor),
+                    Some(_) => (),
                 }
             }
             let name = &remaining[..remaining.len() - chars.as_str().len() - 1];
+            if name.is_empty() {
+                return Err(ParseError::InvalidDescriptor);
+            }
             FieldType::Class(name.to_owned())
         }
         Some('S') => FieldType::Short,
@@ -314,6 +524,296 @@ fn parse_field_descriptor(chars: &mut JavaChars) -> Result<FieldType> {
     Ok(field_type)
 }
 
+fn parse_signature_idx(slice: &[u8]) -> Result<ConstantIdx> {
+    let mut reader = Reader::new(slice);
+    ConstantIdx::try_from(reader.read_u16()?)
+}
+
+impl ClassSignature {
+    /// Parses a class signature, such as
+    /// `<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidSignature`] if `signature` is not a valid
+    /// class signature.
+    pub fn parse(signature: &JavaStr) -> Result<Self> {
+        let mut chars = signature.chars();
+
+        let type_parameters = parse_type_parameters(&mut chars)?;
+        let superclass = parse_class_type_signature(&mut chars)?;
+        let mut interfaces = Vec::new();
+        while peek_char(&chars).is_some() {
+            interfaces.push(parse_class_type_signature(&mut chars)?);
+        }
+
+        Ok(ClassSignature {
+            type_parameters,
+            superclass,
+            interfaces,
+        })
+    }
+}
+
+impl MethodSignature {
+    /// Parses a method signature, such as:
+    ///
+    /// `<T:Ljava/lang/Object;>(Ljava/util/List<+TT;>;)TT;^Ljava/io/IOException;
+    /// `
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidSignature`] if `signature` is not a valid
+    /// method signature.
+    pub fn parse(signature: &JavaStr) -> Result<Self> {
+        let mut chars = signature.chars();
+
+        let type_parameters = parse_type_parameters(&mut chars)?;
+
+        let Some('(') = next_char(&mut chars) else {
+            return Err(ParseError::InvalidSignature);
+        };
+        let mut parameters = Vec::new();
+        loop {
+            if let Some(')') = peek_char(&chars) {
+                chars.next();
+                break;
+            }
+            parameters.push(parse_type_signature(&mut chars)?);
+        }
+
+        let ret = if let Some('V') = peek_char(&chars) {
+            chars.next();
+            None
+        } else {
+            Some(parse_type_signature(&mut chars)?)
+        };
+
+        let mut throws = Vec::new();
+        while let Some(c) = next_char(&mut chars) {
+            if c != '^' {
+                return Err(ParseError::InvalidSignature);
+            }
+            match parse_type_signature(&mut chars)? {
+                throw @ (TypeSignature::Class(_) | TypeSignature::TypeVariable(_)) => {
+                    throws.push(throw);
+                }
+                _ => return Err(ParseError::InvalidSignature),
+            }
+        }
+
+        Ok(MethodSignature {
+            type_parameters,
+            parameters,
+            ret,
+            throws,
+        })
+    }
+}
+
+impl FieldSignature {
+    /// Parses a field signature, such as
+    /// `Ljava/util/List<Ljava/lang/String;>;`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::InvalidSignature`] if `signature` is not a valid
+    /// field signature.
+    pub fn parse(signature: &JavaStr) -> Result<Self> {
+        let mut chars = signature.chars();
+        let field_type = parse_reference_type_signature(&mut chars)?;
+        if chars.next().is_some() {
+            return Err(ParseError::InvalidSignature);
+        }
+        Ok(FieldSignature(field_type))
+    }
+}
+
+fn peek_char(chars: &JavaChars) -> Option<char> {
+    chars.clone().next().and_then(char::from_u32)
+}
+
+fn next_char(chars: &mut JavaChars) -> Option<char> {
+    chars.next().and_then(char::from_u32)
+}
+
+fn parse_type_parameters(chars: &mut JavaChars) -> Result<Vec<TypeParameter>> {
+    let mut type_parameters = Vec::new();
+    if let Some('<') = peek_char(chars) {
+        chars.next();
+    } else {
+        return Ok(type_parameters);
+    }
+
+    loop {
+        if let Some('>') = peek_char(chars) {
+            chars.next();
+            break;
+        }
+
+        let name = parse_identifier(chars)?;
+        let Some(':') = next_char(chars) else {
+            return Err(ParseError::InvalidSignature);
+        };
+        let class_bound = if let Some('L' | 'T' | '[') = peek_char(chars) {
+            Some(parse_reference_type_signature(chars)?)
+        } else {
+            None
+        };
+        let mut interface_bounds = Vec::new();
+        while let Some(':') = peek_char(chars) {
+            chars.next();
+            interface_bounds.push(parse_reference_type_signature(chars)?);
+        }
+
+        type_parameters.push(TypeParameter {
+            name,
+            class_bound,
+            interface_bounds,
+        });
+    }
+
+    if type_parameters.is_empty() {
+        return Err(ParseError::InvalidSignature);
+    }
+    Ok(type_parameters)
+}
+
+fn parse_reference_type_signature(chars: &mut JavaChars) -> Result<TypeSignature> {
+    let signature = parse_type_signature(chars)?;
+    if signature.is_reference() {
+        Ok(signature)
+    } else {
+        Err(ParseError::InvalidSignature)
+    }
+}
+
+fn parse_type_signature(chars: &mut JavaChars) -> Result<TypeSignature> {
+    let signature = match peek_char(chars) {
+        Some('B') => TypeSignature::Byte,
+        Some('C') => TypeSignature::Char,
+        Some('D') => TypeSignature::Double,
+        Some('F') => TypeSignature::Float,
+        Some('I') => TypeSignature::Int,
+        Some('J') => TypeSignature::Long,
+        Some('S') => TypeSignature::Short,
+        Some('Z') => TypeSignature::Bool,
+        Some('L') => return Ok(TypeSignature::Class(parse_class_type_signature(chars)?)),
+        Some('T') => {
+            chars.next();
+            let name = parse_identifier(chars)?;
+            let Some(';') = next_char(chars) else {
+                return Err(ParseError::InvalidSignature);
+            };
+            return Ok(TypeSignature::TypeVariable(name));
+        }
+        Some('[') => {
+            chars.next();
+            return Ok(TypeSignature::Array(Box::new(parse_type_signature(chars)?)));
+        }
+        _ => return Err(ParseError::InvalidSignature),
+    };
+    chars.next();
+    Ok(signature)
+}
+
+fn parse_class_type_signature(chars: &mut JavaChars) -> Result<ClassTypeSignature> {
+    let Some('L') = next_char(chars) else {
+        return Err(ParseError::InvalidSignature);
+    };
+
+    // The outermost class is qualified by its package, so its name is made up
+    // of identifiers separated by slashes.
+    let remaining = chars.as_str();
+    loop {
+        parse_identifier(chars)?;
+        if let Some('/') = peek_char(chars) {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    let name = remaining[..remaining.len() - chars.as_str().len()].to_owned();
+    let type_arguments = parse_type_arguments(chars)?;
+
+    let mut segments = vec![SimpleClassTypeSignature {
+        name,
+        type_arguments,
+    }];
+    loop {
+        match next_char(chars) {
+            Some(';') => break,
+            Some('.') => {
+                let name = parse_identifier(chars)?;
+                let type_arguments = parse_type_arguments(chars)?;
+                segments.push(SimpleClassTypeSignature {
+                    name,
+                    type_arguments,
+                });
+            }
+            _ => return Err(ParseError::InvalidSignature),
+        }
+    }
+
+    Ok(ClassTypeSignature { segments })
+}
+
+fn parse_type_arguments(chars: &mut JavaChars) -> Result<Vec<TypeArgument>> {
+    let mut type_arguments = Vec::new();
+    if let Some('<') = peek_char(chars) {
+        chars.next();
+    } else {
+        return Ok(type_arguments);
+    }
+
+    loop {
+        let type_argument = match peek_char(chars) {
+            Some('>') => {
+                chars.next();
+                break;
+            }
+            Some('*') => {
+                chars.next();
+                TypeArgument::Any
+            }
+            Some('+') => {
+                chars.next();
+                TypeArgument::Extends(parse_reference_type_signature(chars)?)
+            }
+            Some('-') => {
+                chars.next();
+                TypeArgument::Super(parse_reference_type_signature(chars)?)
+            }
+            _ => TypeArgument::Exact(parse_reference_type_signature(chars)?),
+        };
+        type_arguments.push(type_argument);
+    }
+
+    if type_arguments.is_empty() {
+        return Err(ParseError::InvalidSignature);
+    }
+    Ok(type_arguments)
+}
+
+fn parse_identifier(chars: &mut JavaChars) -> Result<JavaString> {
+    let remaining = chars.as_str();
+    while let Some(c) = chars.clone().next() {
+        if matches!(
+            char::from_u32(c),
+            Some('.' | ';' | '[' | '/' | '<' | '>' | ':')
+        ) {
+            break;
+        }
+        chars.next();
+    }
+
+    let identifier = &remaining[..remaining.len() - chars.as_str().len()];
+    if identifier.is_empty() {
+        return Err(ParseError::InvalidSignature);
+    }
+    Ok(identifier.to_owned())
+}
+
 fn parse_attribute<'a>(reader: &mut Reader<'a>) -> Result<(ConstantIdx, &'a [u8])> {
     let name = ConstantIdx::try_from(reader.read_u16()?)?;
     let length = reader.read_u32()?;
@@ -325,7 +825,7 @@ pub(super) fn parse_instruction<'a>(
     reader: &mut Reader<'a>,
     current_offset: usize,
 ) -> Result<Instruction<'a>> {
-    let instruction = match reader.read_u8().unwrap() {
+    let instruction = match reader.read_u8()? {
         // Constants
         0x00 => Instruction::nop,
         0x01 => Instruction::aconst_null,
@@ -346,8 +846,8 @@ pub(super) fn parse_instruction<'a>(
         0x10 => Instruction::bipush(reader.read_u8()? as i8),
         0x11 => Instruction::sipush(reader.read_i16()?),
         0x12 => Instruction::ldc(ConstantIdx::try_from(reader.read_u8()? as u16)?),
-        0x13 => Instruction::ldc(ConstantIdx::try_from(reader.read_u16()? as u16)?),
-        0x14 => Instruction::ldc(ConstantIdx::try_from(reader.read_u16()? as u16)?),
+        0x13 => Instruction::ldc(ConstantIdx::try_from(reader.read_u16()?)?),
+        0x14 => Instruction::ldc(ConstantIdx::try_from(reader.read_u16()?)?),
 
         // Loads
         0x15 => Instruction::iload(reader.read_u8()? as u16),
@@ -467,7 +967,7 @@ pub(super) fn parse_instruction<'a>(
         0x81 => Instruction::lor,
         0x82 => Instruction::ixor,
         0x83 => Instruction::lxor,
-        0x84 => Instruction::iinc(reader.read_u8()? as u16, reader.read_u8()? as i16),
+        0x84 => Instruction::iinc(reader.read_u8()? as u16, reader.read_u8()? as i8 as i16),
 
         // Conversions
         0x85 => Instruction::i2l,
@@ -513,7 +1013,7 @@ pub(super) fn parse_instruction<'a>(
         0xA8 => Instruction::jsr(reader.read_i16()? as i32),
         0xA9 => Instruction::ret(reader.read_u8()? as u16),
         0xAA => {
-            reader.skip(4 - (current_offset + 1) % 4)?;
+            reader.skip((4 - (current_offset + 1) % 4) % 4)?;
 
             let default = reader.read_u32()? as i32;
             let low = reader.read_u32()? as i32;
@@ -525,7 +1025,7 @@ pub(super) fn parse_instruction<'a>(
             Instruction::tableswitch(TableSwitch::new(default, low, high, slice))
         }
         0xAB => {
-            reader.skip(4 - (current_offset + 1) % 4)?;
+            reader.skip((4 - (current_offset + 1) % 4) % 4)?;
 
             let default = reader.read_u32()? as i32;
             let pair_count = reader.read_u32()?;
@@ -551,13 +1051,17 @@ pub(super) fn parse_instruction<'a>(
         0xB9 => {
             let index = ConstantIdx::try_from(reader.read_u16()?)?;
             let count = reader.read_u8()?;
-            assert_eq!(&[0x00], reader.read_slice(1)?);
+            if reader.read_u8()? != 0 {
+                return Err(ParseError::InvalidInstruction);
+            }
 
             Instruction::invokeinterface(index, count)
         }
         0xBA => {
             let index = ConstantIdx::try_from(reader.read_u16()?)?;
-            assert_eq!(&[0x00, 0x00], reader.read_slice(2)?);
+            if reader.read_u16()? != 0 {
+                return Err(ParseError::InvalidInstruction);
+            }
 
             Instruction::invokedynamic(index)
         }
@@ -592,7 +1096,7 @@ pub(super) fn parse_instruction<'a>(
                 // Other
                 0xA9 => Instruction::ret(index),
                 0x84 => Instruction::iinc(index, reader.read_i16()?),
-                opcode => panic!("invalid opcode: {opcode:04X}"),
+                _ => return Err(ParseError::InvalidInstruction),
             }
         }
         0xC5 => Instruction::multianewarray(
@@ -604,7 +1108,7 @@ pub(super) fn parse_instruction<'a>(
         0xC8 => Instruction::goto(reader.read_u32()? as i32),
         0xC9 => Instruction::jsr(reader.read_u32()? as i32),
 
-        opcode => panic!("invalid opcode: {opcode:04X}"),
+        _ => return Err(ParseError::InvalidInstruction),
     };
     Ok(instruction)
 }
@@ -637,3 +1141,183 @@ impl TryFrom<u8> for ArrayKind {
         Ok(kind)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a class file for the class `A` without a superclass, whose
+    /// `Signature` attribute holds `signature`.
+    fn class_with_signature(signature: &str) -> Vec<u8> {
+        let mut bytes = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 5];
+        for utf8 in ["A", "Signature", signature] {
+            bytes.push(1);
+            bytes.extend_from_slice(&(utf8.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(utf8.as_bytes());
+            if utf8 == "A" {
+                bytes.extend_from_slice(&[7, 0, 1]);
+            }
+        }
+        // Flags, this class, superclass, interfaces, fields and methods
+        bytes.extend_from_slice(&[0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 1, 0, 3, 0, 0, 0, 2, 0, 4]);
+        bytes
+    }
+
+    #[test]
+    fn malformed_signature_is_reported_lazily() {
+        let class = parse(&class_with_signature("<T:>Ljava/lang/Object")).unwrap();
+        assert_eq!(class.signature().unwrap(), "<T:>Ljava/lang/Object");
+        assert_eq!(
+            class.parsed_signature(),
+            Some(Err(ParseError::InvalidSignature))
+        );
+    }
+
+    #[test]
+    fn signature_is_parsed() {
+        let signature = "<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;";
+        let class = parse(&class_with_signature(signature)).unwrap();
+        let parsed = class.parsed_signature().unwrap().unwrap();
+        assert_eq!(parsed.type_parameters().len(), 1);
+        assert_eq!(parsed.interfaces().len(), 1);
+        assert_eq!(parsed.to_string(), signature);
+    }
+
+    #[test]
+    fn method_signatures_are_parsed() {
+        let signature = "<T:Ljava/lang/Object;E:Ljava/lang/Exception;>\
+            (Ljava/util/List<*>;Ljava/util/Map<-TT;+[I>.Entry<TT;>;[TT;J)TT;\
+            ^Ljava/io/IOException;^TE;";
+        let parsed =
+            MethodSignature::parse(JavaStr::from_java(signature.as_bytes()).unwrap()).unwrap();
+        assert_eq!(parsed.to_string(), signature);
+
+        let [t, e] = parsed.type_parameters() else {
+            panic!("two type parameters: {parsed:?}");
+        };
+        assert_eq!(t.name(), "T");
+        assert!(t.interface_bounds().is_empty());
+        assert_eq!(e.name(), "E");
+
+        let [list, entry, array, long] = parsed.args() else {
+            panic!("four arguments: {parsed:?}");
+        };
+        let TypeSignature::Class(list) = list else {
+            panic!("a class type: {list:?}");
+        };
+        assert_eq!(list.segments()[0].type_arguments(), [TypeArgument::Any]);
+        let TypeSignature::Class(entry) = entry else {
+            panic!("a class type: {entry:?}");
+        };
+        assert_eq!(entry.binary_name(), "java/util/Map$Entry");
+        let [map, inner] = entry.segments() else {
+            panic!("two segments: {entry:?}");
+        };
+        let variable = TypeSignature::TypeVariable(java_str!("T").to_owned());
+        assert_eq!(
+            map.type_arguments(),
+            [
+                TypeArgument::Super(variable.clone()),
+                TypeArgument::Extends(TypeSignature::Array(Box::new(TypeSignature::Int))),
+            ]
+        );
+        assert_eq!(inner.name(), "Entry");
+        assert_eq!(
+            inner.type_arguments(),
+            [TypeArgument::Exact(variable.clone())]
+        );
+        assert_eq!(array, &TypeSignature::Array(Box::new(variable.clone())));
+        assert_eq!(long, &TypeSignature::Long);
+        assert_eq!(parsed.result(), Some(&variable));
+
+        let [io, thrown] = parsed.throws() else {
+            panic!("two exceptions: {parsed:?}");
+        };
+        assert!(
+            matches!(io, TypeSignature::Class(io) if io.binary_name() == "java/io/IOException")
+        );
+        assert!(matches!(thrown, TypeSignature::TypeVariable(name) if name == "E"));
+    }
+
+    #[test]
+    fn void_method_signatures_have_no_result() {
+        let parsed = MethodSignature::parse(java_str!("()V")).unwrap();
+        assert!(parsed.type_parameters().is_empty());
+        assert!(parsed.args().is_empty());
+        assert_eq!(parsed.result(), None);
+        assert!(parsed.throws().is_empty());
+        assert_eq!(parsed.to_string(), "()V");
+    }
+
+    #[test]
+    fn invalid_method_signatures_are_rejected() {
+        for signature in [
+            "",
+            "(",
+            "(I",
+            "()",
+            "(V)V",
+            "()VV",
+            "()V^",
+            "()V^[Ljava/lang/Exception;",
+            "()V^I",
+            "<>()V",
+            "<T>()V",
+            "(Ljava/util/List<>;)V",
+            "(Ljava/util/List<TT>;)V",
+        ] {
+            assert_eq!(
+                MethodSignature::parse(JavaStr::from_java(signature.as_bytes()).unwrap()),
+                Err(ParseError::InvalidSignature),
+                "{signature}"
+            );
+        }
+    }
+
+    #[test]
+    fn field_signatures_are_parsed() {
+        for signature in [
+            "TT;",
+            "[TT;",
+            "[[I",
+            "Ljava/util/List<Ljava/lang/String;>;",
+            "Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;",
+            "Lp/Outer$Nested<+Ljava/lang/Number;>;",
+            "Ljava/lang/Class<*>;",
+        ] {
+            let parsed =
+                FieldSignature::parse(JavaStr::from_java(signature.as_bytes()).unwrap()).unwrap();
+            assert!(parsed.field_type().is_reference());
+            assert_eq!(parsed.to_string(), signature);
+        }
+
+        let parsed = FieldSignature::parse(java_str!("Lp/Outer<TT;>.Inner;")).unwrap();
+        let TypeSignature::Class(class) = parsed.field_type() else {
+            panic!("a class type: {parsed:?}");
+        };
+        assert_eq!(class.binary_name(), "p/Outer$Inner");
+        assert!(class.segments()[1].type_arguments().is_empty());
+    }
+
+    #[test]
+    fn invalid_field_signatures_are_rejected() {
+        for signature in [
+            "",
+            "I",
+            "V",
+            "T;",
+            "TT",
+            "Ljava/lang/String",
+            "Ljava/lang/String;I",
+            "TT;TU;",
+            "Ljava/util/List<I>;",
+        ] {
+            assert_eq!(
+                FieldSignature::parse(JavaStr::from_java(signature.as_bytes()).unwrap()),
+                Err(ParseError::InvalidSignature),
+                "{signature}"
+            );
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::fmt::Write;
+
+use crate::string::JavaString;
+
+/// The generic signature of a class, as stored in the `Signature` attribute of
+/// a class file. It records the type parameters of the class along with the
+/// parameterized types of its superclass and superinterfaces.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub(super) type_parameters: Vec<TypeParameter>,
+    pub(super) superclass: ClassTypeSignature,
+    pub(super) interfaces: Vec<ClassTypeSignature>,
+}
+
+impl ClassSignature {
+    #[inline]
+    #[must_use]
+    pub fn type_parameters(&self) -> &[TypeParameter] {
+        &self.type_parameters
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn superclass(&self) -> &ClassTypeSignature {
+        &self.superclass
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn interfaces(&self) -> &[ClassTypeSignature] {
+        &self.interfaces
+    }
+}
+
+impl std::fmt::Debug for ClassSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for ClassSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_type_parameters(&self.type_parameters, f)?;
+        std::fmt::Display::fmt(&self.superclass, f)?;
+        for interface in &self.interfaces {
+            std::fmt::Display::fmt(interface, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The generic signature of a method, as stored in the `Signature` attribute
+/// of a method. Unlike a [`MethodDescriptor`], this preserves type arguments,
+/// type variables and the declared exceptions.
+///
+/// [`MethodDescriptor`]: super::MethodDescriptor
+#[derive(Clone, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub(super) type_parameters: Vec<TypeParameter>,
+    pub(super) parameters: Vec<TypeSignature>,
+    pub(super) ret: Option<TypeSignature>,
+    pub(super) throws: Vec<TypeSignature>,
+}
+
+impl MethodSignature {
+    #[inline]
+    #[must_use]
+    pub fn type_parameters(&self) -> &[TypeParameter] {
+        &self.type_parameters
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn args(&self) -> &[TypeSignature] {
+        &self.parameters
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn result(&self) -> Option<&TypeSignature> {
+        self.ret.as_ref()
+    }
+
+    /// Returns the exception types declared by the method. Each of these is
+    /// either a [`TypeSignature::Class`] or a [`TypeSignature::TypeVariable`].
+    #[inline]
+    #[must_use]
+    pub fn throws(&self) -> &[TypeSignature] {
+        &self.throws
+    }
+}
+
+impl std::fmt::Debug for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_type_parameters(&self.type_parameters, f)?;
+
+        f.write_char('(')?;
+        for parameter in &self.parameters {
+            std::fmt::Display::fmt(parameter, f)?;
+        }
+        f.write_char(')')?;
+
+        if let Some(ret) = &self.ret {
+            std::fmt::Display::fmt(ret, f)?;
+        } else {
+            f.write_char('V')?;
+        }
+
+        for throws in &self.throws {
+            write!(f, "^{throws}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The generic signature of a field, as stored in the `Signature` attribute of
+/// a field. This is always a reference type: a class type, a type variable or
+/// an array type.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FieldSignature(pub(super) TypeSignature);
+
+impl FieldSignature {
+    #[inline]
+    #[must_use]
+    pub fn field_type(&self) -> &TypeSignature {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for FieldSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl std::fmt::Display for FieldSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// A formal type parameter of a generic class or method, such as
+/// `T:Ljava/lang/Object;` or `K::Ljava/lang/Comparable<TK;>;`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub(super) name: JavaString,
+    pub(super) class_bound: Option<TypeSignature>,
+    pub(super) interface_bounds: Vec<TypeSignature>,
+}
+
+impl TypeParameter {
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &JavaString {
+        &self.name
+    }
+
+    /// Returns the class bound of the type parameter. This is `None` when the
+    /// first bound of the parameter is an interface.
+    #[inline]
+    #[must_use]
+    pub fn class_bound(&self) -> Option<&TypeSignature> {
+        self.class_bound.as_ref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn interface_bounds(&self) -> &[TypeSignature] {
+        &self.interface_bounds
+    }
+}
+
+impl std::fmt::Debug for TypeParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.name)?;
+        if let Some(class_bound) = &self.class_bound {
+            std::fmt::Display::fmt(class_bound, f)?;
+        }
+        for interface_bound in &self.interface_bounds {
+            write!(f, ":{interface_bound}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A type as it appears in a generic signature. This is the generic
+/// counterpart of [`FieldType`].
+///
+/// [`FieldType`]: super::FieldType
+#[derive(Clone, PartialEq, Eq)]
+pub enum TypeSignature {
+    Byte,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Char,
+    Bool,
+    Class(ClassTypeSignature),
+    TypeVariable(JavaString),
+    Array(Box<TypeSignature>),
+}
+
+impl TypeSignature {
+    /// Returns `true` if this is a class type, type variable or array type.
+    #[inline]
+    #[must_use]
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            Self::Class(_) | Self::TypeVariable(_) | Self::Array(_)
+        )
+    }
+}
+
+impl std::fmt::Debug for TypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for TypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte => f.write_char('B'),
+            Self::Short => f.write_char('S'),
+            Self::Int => f.write_char('I'),
+            Self::Long => f.write_char('J'),
+            Self::Float => f.write_char('F'),
+            Self::Double => f.write_char('D'),
+            Self::Char => f.write_char('C'),
+            Self::Bool => f.write_char('Z'),
+            Self::Class(class) => std::fmt::Display::fmt(class, f),
+            Self::TypeVariable(name) => write!(f, "T{name};"),
+            Self::Array(component) => write!(f, "[{component}"),
+        }
+    }
+}
+
+/// A possibly parameterized class type, such as
+/// `Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;`.
+///
+/// The first segment holds the fully qualified name of the outermost class,
+/// and each subsequent segment holds the simple name of a nested class.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClassTypeSignature {
+    pub(super) segments: Vec<SimpleClassTypeSignature>,
+}
+
+impl ClassTypeSignature {
+    #[inline]
+    #[must_use]
+    pub fn segments(&self) -> &[SimpleClassTypeSignature] {
+        &self.segments
+    }
+
+    /// Returns the binary name of the erased class, such as
+    /// `java/util/Map$Entry`.
+    #[must_use]
+    pub fn binary_name(&self) -> JavaString {
+        let mut bytes = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i != 0 {
+                bytes.push(b'$');
+            }
+            bytes.extend_from_slice(segment.name.as_ref());
+        }
+
+        // SAFETY: Joining valid Modified UTF-8 strings with an ASCII character
+        // results in a valid Modified UTF-8 string.
+        unsafe { JavaString::from_java_unchecked(bytes) }
+    }
+}
+
+impl std::fmt::Debug for ClassTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char('L')?;
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i != 0 {
+                f.write_char('.')?;
+            }
+            std::fmt::Display::fmt(segment, f)?;
+        }
+        f.write_char(';')
+    }
+}
+
+/// A single class name within a [`ClassTypeSignature`], along with its type
+/// arguments.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature {
+    pub(super) name: JavaString,
+    pub(super) type_arguments: Vec<TypeArgument>,
+}
+
+impl SimpleClassTypeSignature {
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &JavaString {
+        &self.name
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn type_arguments(&self) -> &[TypeArgument] {
+        &self.type_arguments
+    }
+}
+
+impl std::fmt::Debug for SimpleClassTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for SimpleClassTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.name, f)?;
+        if !self.type_arguments.is_empty() {
+            f.write_char('<')?;
+            for type_argument in &self.type_arguments {
+                std::fmt::Display::fmt(type_argument, f)?;
+            }
+            f.write_char('>')?;
+        }
+        Ok(())
+    }
+}
+
+/// A type argument of a parameterized class type.
+#[derive(Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    /// The unbounded wildcard, `*`.
+    Any,
+    /// A concrete type argument, such as `Ljava/lang/String;`.
+    Exact(TypeSignature),
+    /// An upper bounded wildcard, such as `+Ljava/lang/Number;`.
+    Extends(TypeSignature),
+    /// A lower bounded wildcard, such as `-Ljava/lang/Integer;`.
+    Super(TypeSignature),
+}
+
+impl std::fmt::Debug for TypeArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl std::fmt::Display for TypeArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => f.write_char('*'),
+            Self::Exact(signature) => std::fmt::Display::fmt(signature, f),
+            Self::Extends(signature) => write!(f, "+{signature}"),
+            Self::Super(signature) => write!(f, "-{signature}"),
+        }
+    }
+}
+
+fn fmt_type_parameters(
+    type_parameters: &[TypeParameter],
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    if !type_parameters.is_empty() {
+        f.write_char('<')?;
+        for type_parameter in type_parameters {
+            std::fmt::Display::fmt(type_parameter, f)?;
+        }
+        f.write_char('>')?;
+    }
+    Ok(())
+}
@@ -0,0 +1,1068 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::java_str;
+use crate::reader::Reader;
+use crate::string::{self, JavaStr, JavaString};
+use crate::writer::Writer;
+
+use super::{
+    ArrayKind, BuildError, Bytecode, ConstantIdx, ConstantPool, ConstantPoolBuilder, Entry,
+    ExceptionHandler, FieldType, Instruction, MethodDescriptor,
+};
+
+type Result<T> = std::result::Result<T, BuildError>;
+
+/// Returns the closest common superclass of two classes.
+pub(crate) type CommonSuperclass = dyn Fn(&JavaStr, &JavaStr) -> JavaString;
+
+/// The type of a local variable or operand stack slot, as described by a
+/// `StackMapTable` attribute.
+///
+/// Values of type `long` and `double` take up two slots, where the second slot
+/// always holds [`VerificationType::Top`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    /// An object created by the `new` instruction at the given offset whose
+    /// constructor has not been called yet.
+    Uninitialized(u16),
+    /// An instance of the class or array type with the given binary name, such
+    /// as `java/lang/String` or `[I`.
+    Object(JavaString),
+}
+
+impl VerificationType {
+    /// Returns the type of values of `field_type` on the operand stack.
+    pub fn from_field_type(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Byte
+            | FieldType::Short
+            | FieldType::Int
+            | FieldType::Char
+            | FieldType::Bool => Self::Integer,
+            FieldType::Long => Self::Long,
+            FieldType::Float => Self::Float,
+            FieldType::Double => Self::Double,
+            FieldType::Class(name) => Self::Object(name.clone()),
+            FieldType::Array(_) => Self::Object(descriptor_string(field_type)),
+        }
+    }
+
+    fn object(name: &JavaStr) -> Self {
+        Self::Object(name.to_owned())
+    }
+
+    /// Returns `true` for `long` and `double`, which take up two slots.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Self::Long | Self::Double)
+    }
+}
+
+/// The types of the local variables and operand stack before an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub(crate) locals: Vec<VerificationType>,
+    pub(crate) stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    /// Returns the types of the local variables.
+    pub fn locals(&self) -> &[VerificationType] {
+        &self.locals
+    }
+
+    /// Returns the types of the operand stack, from the bottom to the top.
+    pub fn stack(&self) -> &[VerificationType] {
+        &self.stack
+    }
+}
+
+/// The result of inferring the types of every instruction in a method.
+pub(super) struct Analysis {
+    pub(super) max_stack: u16,
+    pub(super) max_locals: u16,
+    pub(super) initial: Frame,
+    /// The frames which must be recorded in the `StackMapTable`, sorted by
+    /// their offset.
+    pub(super) frames: Vec<(u16, Frame)>,
+}
+
+/// Describes the method whose code is being analyzed.
+pub(crate) struct MethodContext<'a> {
+    pub(crate) class_name: &'a JavaStr,
+    pub(crate) name: &'a JavaStr,
+    pub(crate) descriptor: &'a MethodDescriptor,
+    pub(crate) is_static: bool,
+    pub(crate) common_superclass: &'a (dyn Fn(&JavaStr, &JavaStr) -> JavaString + 'a),
+}
+
+/// Infers the types of the locals and operand stack at every instruction of
+/// `bytecode`, from which `max_stack`, `max_locals` and the frames of the
+/// `StackMapTable` attribute are derived.
+pub(super) fn analyze(
+    bytecode: &[u8],
+    exception_table: &[ExceptionHandler],
+    constants: &ConstantPool,
+    method: &MethodContext,
+) -> Result<Analysis> {
+    let instructions: Vec<(u32, Instruction)> = Bytecode::new(bytecode).collect();
+    let indices: HashMap<u32, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(index, (pc, _))| (*pc, index))
+        .collect();
+    let index_of = |pc: i64| {
+        u32::try_from(pc)
+            .ok()
+            .and_then(|pc| indices.get(&pc).copied())
+            .ok_or(BuildError::InvalidBranchTarget)
+    };
+
+    let mut max_locals = method.descriptor.arg_slots(method.is_static);
+    for (_, instruction) in &instructions {
+        if let Some((index, size)) = local_access(instruction) {
+            max_locals = max_locals.max(index as usize + size);
+        }
+    }
+
+    let initial = initial_frame(method, max_locals);
+
+    // Offsets which need an explicit frame: targets of jumps, exception
+    // handlers and instructions following an unconditional jump.
+    let mut frame_points = BTreeSet::new();
+    for handler in exception_table {
+        frame_points.insert(index_of(handler.handler_pc as i64)?);
+    }
+    for (index, (pc, instruction)) in instructions.iter().enumerate() {
+        let (targets, falls_through) = successors(*pc, instruction);
+        for target in targets {
+            frame_points.insert(index_of(target)?);
+        }
+        if !falls_through && index + 1 < instructions.len() {
+            frame_points.insert(index + 1);
+        }
+    }
+
+    let mut states: Vec<Option<Frame>> = vec![None; instructions.len()];
+    let mut worklist = vec![0];
+    let mut max_stack = 0;
+    states[0] = Some(initial.clone());
+
+    while let Some(index) = worklist.pop() {
+        let (pc, instruction) = &instructions[index];
+        let frame = states[index].clone().unwrap();
+
+        let mut interpreter = Interpreter {
+            frame: frame.clone(),
+            pc: *pc,
+            constants,
+            method,
+            bytecode,
+            max_stack,
+        };
+        interpreter.execute(instruction)?;
+        max_stack = interpreter.max_stack;
+        let out = interpreter.frame;
+
+        let mut merge_into = |target: usize, incoming: Frame| -> Result<()> {
+            let pc = instructions[target].0;
+            let changed = match &mut states[target] {
+                Some(existing) => merge_frame(existing, &incoming, pc, method)?,
+                state @ None => {
+                    *state = Some(incoming);
+                    true
+                }
+            };
+            if changed && !worklist.contains(&target) {
+                worklist.push(target);
+            }
+            Ok(())
+        };
+
+        for handler in exception_table {
+            if (handler.start_pc as u32..handler.end_pc as u32).contains(pc) {
+                let catch_type = match handler.catch_type {
+                    Some(catch_type) => class_name(constants, catch_type).to_owned(),
+                    None => java_str!("java/lang/Throwable").to_owned(),
+                };
+                let target = index_of(handler.handler_pc as i64)?;
+                for locals in [&frame.locals, &out.locals] {
+                    let incoming = Frame {
+                        locals: locals.clone(),
+                        stack: vec![VerificationType::Object(catch_type.clone())],
+                    };
+                    merge_into(target, incoming)?;
+                }
+                max_stack = max_stack.max(1);
+            }
+        }
+
+        let (targets, falls_through) = successors(*pc, instruction);
+        for target in targets {
+            merge_into(index_of(target)?, out.clone())?;
+        }
+        if falls_through {
+            if index + 1 == instructions.len() {
+                return Err(BuildError::FallsOffEnd);
+            }
+            merge_into(index + 1, out)?;
+        }
+    }
+
+    let mut frames = Vec::with_capacity(frame_points.len());
+    for index in frame_points {
+        let pc = instructions[index].0 as u16;
+        match states[index].take() {
+            Some(frame) => frames.push((pc, frame)),
+            None => return Err(BuildError::UnreachableCode(pc)),
+        }
+    }
+
+    Ok(Analysis {
+        max_stack: u16::try_from(max_stack).or(Err(BuildError::TooManySlots))?,
+        max_locals: u16::try_from(max_locals).or(Err(BuildError::TooManySlots))?,
+        initial,
+        frames,
+    })
+}
+
+/// Encodes the contents of a `StackMapTable` attribute, using the most compact
+/// frame type available for each frame.
+pub(super) fn write_stack_map(analysis: &Analysis, constants: &mut ConstantPoolBuilder) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.write_u16(analysis.frames.len() as u16);
+
+    let mut previous_locals = entries(&analysis.initial.locals);
+    let mut previous_pc = None;
+    for (pc, frame) in &analysis.frames {
+        let offset_delta = match previous_pc {
+            Some(previous_pc) => pc - previous_pc - 1,
+            None => *pc,
+        };
+        previous_pc = Some(*pc);
+
+        let locals = entries(&frame.locals);
+        let stack = entries(&frame.stack);
+
+        if locals == previous_locals && stack.is_empty() {
+            if offset_delta < 64 {
+                writer.write_u8(offset_delta as u8);
+            } else {
+                writer.write_u8(251);
+                writer.write_u16(offset_delta);
+            }
+        } else if locals == previous_locals && stack.len() == 1 {
+            if offset_delta < 64 {
+                writer.write_u8(64 + offset_delta as u8);
+            } else {
+                writer.write_u8(247);
+                writer.write_u16(offset_delta);
+            }
+            write_verification_type(&mut writer, stack[0], constants);
+        } else if stack.is_empty()
+            && locals.len() < previous_locals.len()
+            && previous_locals.len() - locals.len() <= 3
+            && previous_locals.starts_with(&locals)
+        {
+            writer.write_u8(251 - (previous_locals.len() - locals.len()) as u8);
+            writer.write_u16(offset_delta);
+        } else if stack.is_empty()
+            && locals.len() > previous_locals.len()
+            && locals.len() - previous_locals.len() <= 3
+            && locals.starts_with(&previous_locals)
+        {
+            writer.write_u8(251 + (locals.len() - previous_locals.len()) as u8);
+            writer.write_u16(offset_delta);
+            for local in &locals[previous_locals.len()..] {
+                write_verification_type(&mut writer, local, constants);
+            }
+        } else {
+            writer.write_u8(255);
+            writer.write_u16(offset_delta);
+            writer.write_u16(locals.len() as u16);
+            for local in &locals {
+                write_verification_type(&mut writer, local, constants);
+            }
+            writer.write_u16(stack.len() as u16);
+            for entry in &stack {
+                write_verification_type(&mut writer, entry, constants);
+            }
+        }
+
+        previous_locals = locals;
+    }
+
+    writer.into_inner()
+}
+
+/// Converts a list of slots into the entries of a stack map frame, where
+/// `long` and `double` values take up a single entry. Trailing `Top` entries
+/// are dropped, as they are implied.
+pub(super) fn entries(slots: &[VerificationType]) -> Vec<&VerificationType> {
+    let mut entries = Vec::with_capacity(slots.len());
+    let mut i = 0;
+    while i < slots.len() {
+        entries.push(&slots[i]);
+        i += if slots[i].is_wide() { 2 } else { 1 };
+    }
+    while entries.last() == Some(&&VerificationType::Top) {
+        entries.pop();
+    }
+    entries
+}
+
+/// Converts the entries of a stack map frame back into slots, where `long`
+/// and `double` values are followed by a `Top` slot.
+pub(super) fn slots(entries: impl IntoIterator<Item = VerificationType>) -> Vec<VerificationType> {
+    let mut slots = Vec::new();
+    for entry in entries {
+        let is_wide = entry.is_wide();
+        slots.push(entry);
+        if is_wide {
+            slots.push(VerificationType::Top);
+        }
+    }
+    slots
+}
+
+/// Decodes the contents of a `StackMapTable` attribute into the frames it
+/// describes, sorted by their offset, where `initial` is the implicit frame
+/// at the start of the method. Returns `None` if the attribute is malformed.
+pub(super) fn read_stack_map(
+    bytes: &[u8],
+    constants: &ConstantPool,
+    initial: &Frame,
+) -> Option<Vec<(u16, Frame)>> {
+    let mut reader = Reader::new(bytes);
+    let count = reader.read_u16().ok()?;
+    let mut frames = Vec::with_capacity(count as usize);
+
+    let mut locals = entries(&initial.locals)
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut previous_pc = None;
+    for _ in 0..count {
+        let frame_type = reader.read_u8().ok()?;
+        let (offset_delta, stack) = match frame_type {
+            0..=63 => (frame_type as u16, Vec::new()),
+            64..=127 => {
+                let stack = read_verification_type(&mut reader, constants)?;
+                (frame_type as u16 - 64, vec![stack])
+            }
+            247 => {
+                let offset_delta = reader.read_u16().ok()?;
+                let stack = read_verification_type(&mut reader, constants)?;
+                (offset_delta, vec![stack])
+            }
+            248..=250 => {
+                let offset_delta = reader.read_u16().ok()?;
+                let len = locals.len().checked_sub(251 - frame_type as usize)?;
+                locals.truncate(len);
+                (offset_delta, Vec::new())
+            }
+            251 => (reader.read_u16().ok()?, Vec::new()),
+            252..=254 => {
+                let offset_delta = reader.read_u16().ok()?;
+                for _ in 251..frame_type {
+                    locals.push(read_verification_type(&mut reader, constants)?);
+                }
+                (offset_delta, Vec::new())
+            }
+            255 => {
+                let offset_delta = reader.read_u16().ok()?;
+                let local_count = reader.read_u16().ok()?;
+                locals = (0..local_count)
+                    .map(|_| read_verification_type(&mut reader, constants))
+                    .collect::<Option<_>>()?;
+                let stack_count = reader.read_u16().ok()?;
+                let stack = (0..stack_count)
+                    .map(|_| read_verification_type(&mut reader, constants))
+                    .collect::<Option<_>>()?;
+                (offset_delta, stack)
+            }
+            _ => return None,
+        };
+        let pc = match previous_pc {
+            Some(previous_pc) => offset_delta.checked_add(previous_pc)?.checked_add(1)?,
+            None => offset_delta,
+        };
+        previous_pc = Some(pc);
+        frames.push((
+            pc,
+            Frame {
+                locals: slots(locals.iter().cloned()),
+                stack: slots(stack),
+            },
+        ));
+    }
+
+    reader.is_empty().then_some(frames)
+}
+
+fn read_verification_type(
+    reader: &mut Reader,
+    constants: &ConstantPool,
+) -> Option<VerificationType> {
+    let verification_type = match reader.read_u8().ok()? {
+        0 => VerificationType::Top,
+        1 => VerificationType::Integer,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 => {
+            let index = ConstantIdx::new(reader.read_u16().ok()?)?;
+            let Some(Entry::Class(name)) = constants.try_get(index) else {
+                return None;
+            };
+            let Some(Entry::Utf8(name)) = constants.try_get(*name) else {
+                return None;
+            };
+            VerificationType::Object(name.clone())
+        }
+        8 => VerificationType::Uninitialized(reader.read_u16().ok()?),
+        _ => return None,
+    };
+    Some(verification_type)
+}
+
+fn write_verification_type(
+    writer: &mut Writer,
+    verification_type: &VerificationType,
+    constants: &mut ConstantPoolBuilder,
+) {
+    match verification_type {
+        VerificationType::Top => writer.write_u8(0),
+        VerificationType::Integer => writer.write_u8(1),
+        VerificationType::Float => writer.write_u8(2),
+        VerificationType::Double => writer.write_u8(3),
+        VerificationType::Long => writer.write_u8(4),
+        VerificationType::Null => writer.write_u8(5),
+        VerificationType::UninitializedThis => writer.write_u8(6),
+        VerificationType::Object(name) => {
+            writer.write_u8(7);
+            writer.write_u16(constants.class(name).get());
+        }
+        VerificationType::Uninitialized(offset) => {
+            writer.write_u8(8);
+            writer.write_u16(*offset);
+        }
+    }
+}
+
+pub(crate) fn initial_frame(method: &MethodContext, max_locals: usize) -> Frame {
+    let mut locals = Vec::with_capacity(max_locals);
+    if !method.is_static {
+        if method.name == java_str!("<init>") && method.class_name != java_str!("java/lang/Object")
+        {
+            locals.push(VerificationType::UninitializedThis);
+        } else {
+            locals.push(VerificationType::object(method.class_name));
+        }
+    }
+    for parameter in method.descriptor.args() {
+        let verification_type = VerificationType::from_field_type(parameter);
+        let is_wide = verification_type.is_wide();
+        locals.push(verification_type);
+        if is_wide {
+            locals.push(VerificationType::Top);
+        }
+    }
+    locals.resize(max_locals, VerificationType::Top);
+    Frame {
+        locals,
+        stack: Vec::new(),
+    }
+}
+
+/// Merges `incoming` into `existing`, returning `true` if `existing` changed.
+pub(crate) fn merge_frame(
+    existing: &mut Frame,
+    incoming: &Frame,
+    pc: u32,
+    method: &MethodContext,
+) -> Result<bool> {
+    if existing.stack.len() != incoming.stack.len() {
+        return Err(BuildError::InconsistentStack(pc as u16));
+    }
+
+    let mut changed = false;
+    for (existing, incoming) in existing.locals.iter_mut().zip(&incoming.locals) {
+        let merged = merge_type(existing, incoming, method);
+        if merged != *existing {
+            *existing = merged;
+            changed = true;
+        }
+    }
+    for (existing, incoming) in existing.stack.iter_mut().zip(&incoming.stack) {
+        let merged = merge_type(existing, incoming, method);
+        if merged == VerificationType::Top && *existing != VerificationType::Top {
+            return Err(BuildError::InconsistentStack(pc as u16));
+        }
+        if merged != *existing {
+            *existing = merged;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+fn merge_type(
+    a: &VerificationType,
+    b: &VerificationType,
+    method: &MethodContext,
+) -> VerificationType {
+    match (a, b) {
+        (a, b) if a == b => a.clone(),
+        (VerificationType::Null, VerificationType::Object(name))
+        | (VerificationType::Object(name), VerificationType::Null) => {
+            VerificationType::Object(name.clone())
+        }
+        (VerificationType::Object(a), VerificationType::Object(b)) => {
+            if a.as_bytes()[0] == b'[' || b.as_bytes()[0] == b'[' {
+                VerificationType::object(java_str!("java/lang/Object"))
+            } else {
+                VerificationType::Object((method.common_superclass)(a, b))
+            }
+        }
+        _ => VerificationType::Top,
+    }
+}
+
+/// Returns the offsets an instruction may jump to, and whether execution may
+/// continue with the following instruction.
+fn successors(pc: u32, instruction: &Instruction) -> (Vec<i64>, bool) {
+    let pc = pc as i64;
+    match *instruction {
+        Instruction::if_eq(offset)
+        | Instruction::if_ne(offset)
+        | Instruction::if_lt(offset)
+        | Instruction::if_ge(offset)
+        | Instruction::if_gt(offset)
+        | Instruction::if_le(offset)
+        | Instruction::if_icmp_eq(offset)
+        | Instruction::if_icmp_ne(offset)
+        | Instruction::if_icmp_lt(offset)
+        | Instruction::if_icmp_ge(offset)
+        | Instruction::if_icmp_gt(offset)
+        | Instruction::if_icmp_le(offset)
+        | Instruction::if_acmp_eq(offset)
+        | Instruction::if_acmp_ne(offset)
+        | Instruction::ifnull(offset)
+        | Instruction::ifnonnull(offset) => (vec![pc + offset as i64], true),
+        Instruction::goto(offset) => (vec![pc + offset as i64], false),
+        Instruction::tableswitch(switch) => {
+            let mut targets: Vec<i64> = switch.offsets().map(|offset| pc + offset as i64).collect();
+            targets.push(pc + switch.default() as i64);
+            (targets, false)
+        }
+        Instruction::lookupswitch(switch) => {
+            let mut targets: Vec<i64> = switch
+                .pairs()
+                .map(|(_, offset)| pc + offset as i64)
+                .collect();
+            targets.push(pc + switch.default() as i64);
+            (targets, false)
+        }
+        Instruction::ireturn
+        | Instruction::lreturn
+        | Instruction::freturn
+        | Instruction::dreturn
+        | Instruction::areturn
+        | Instruction::ret_void
+        | Instruction::athrow
+        | Instruction::jsr(_)
+        | Instruction::ret(_) => (Vec::new(), false),
+        _ => (Vec::new(), true),
+    }
+}
+
+/// Returns the local variable accessed by an instruction, along with the
+/// number of slots it takes up.
+pub(crate) fn local_access(instruction: &Instruction) -> Option<(u16, usize)> {
+    match *instruction {
+        Instruction::iload(index)
+        | Instruction::fload(index)
+        | Instruction::aload(index)
+        | Instruction::istore(index)
+        | Instruction::fstore(index)
+        | Instruction::astore(index)
+        | Instruction::iinc(index, _)
+        | Instruction::ret(index) => Some((index, 1)),
+        Instruction::lload(index)
+        | Instruction::dload(index)
+        | Instruction::lstore(index)
+        | Instruction::dstore(index) => Some((index, 2)),
+        _ => None,
+    }
+}
+
+/// Applies the effect of `instruction` at `pc` to the types in `frame`.
+///
+/// Unlike the analysis for the `StackMapTable`, this allows subroutines. The
+/// return address pushed by `jsr` has no verification type, so it is given
+/// [`VerificationType::Top`].
+pub(crate) fn execute(
+    frame: &mut Frame,
+    pc: u32,
+    instruction: &Instruction,
+    bytecode: &[u8],
+    constants: &ConstantPool,
+    method: &MethodContext,
+) -> Result<()> {
+    let mut interpreter = Interpreter {
+        frame: std::mem::replace(
+            frame,
+            Frame {
+                locals: Vec::new(),
+                stack: Vec::new(),
+            },
+        ),
+        pc,
+        constants,
+        method,
+        bytecode,
+        max_stack: 0,
+    };
+    let result = match instruction {
+        Instruction::jsr(_) => {
+            interpreter.push_slot(VerificationType::Top);
+            Ok(())
+        }
+        Instruction::ret(_) => Ok(()),
+        _ => interpreter.execute(instruction),
+    };
+    *frame = interpreter.frame;
+    result
+}
+
+struct Interpreter<'a> {
+    frame: Frame,
+    pc: u32,
+    constants: &'a ConstantPool,
+    method: &'a MethodContext<'a>,
+    bytecode: &'a [u8],
+    max_stack: usize,
+}
+
+impl Interpreter<'_> {
+    fn push(&mut self, verification_type: VerificationType) {
+        let is_wide = verification_type.is_wide();
+        self.frame.stack.push(verification_type);
+        if is_wide {
+            self.frame.stack.push(VerificationType::Top);
+        }
+        self.max_stack = self.max_stack.max(self.frame.stack.len());
+    }
+
+    fn push_slot(&mut self, verification_type: VerificationType) {
+        self.frame.stack.push(verification_type);
+        self.max_stack = self.max_stack.max(self.frame.stack.len());
+    }
+
+    fn pop(&mut self) -> Result<VerificationType> {
+        self.frame
+            .stack
+            .pop()
+            .ok_or(BuildError::StackUnderflow(self.pc as u16))
+    }
+
+    fn pop_slots(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            self.pop()?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, index: u16) {
+        let verification_type = self.frame.locals[index as usize].clone();
+        self.push(verification_type);
+    }
+
+    fn store(&mut self, index: u16, verification_type: VerificationType) {
+        let index = index as usize;
+        if index > 0 && self.frame.locals[index - 1].is_wide() {
+            self.frame.locals[index - 1] = VerificationType::Top;
+        }
+        if verification_type.is_wide() {
+            self.frame.locals[index + 1] = VerificationType::Top;
+        }
+        self.frame.locals[index] = verification_type;
+    }
+
+    /// Pops `slots` slots and pushes a value of type `result`.
+    fn operation(&mut self, slots: usize, result: VerificationType) -> Result<()> {
+        self.pop_slots(slots)?;
+        self.push(result);
+        Ok(())
+    }
+
+    fn field_type(&self, index: ConstantIdx) -> Result<FieldType> {
+        let (_, name_type) = self.constants.get(index).into_ref();
+        let (_, descriptor) = self.constants.get(name_type).into_name_type();
+        FieldType::parse(self.constants.get(descriptor).into_utf8())
+            .or(Err(BuildError::InvalidDescriptor))
+    }
+
+    fn invoke(&mut self, name_type: ConstantIdx, has_receiver: bool) -> Result<()> {
+        let (name, descriptor) = self.constants.get(name_type).into_name_type();
+        let descriptor = MethodDescriptor::parse(self.constants.get(descriptor).into_utf8())
+            .or(Err(BuildError::InvalidDescriptor))?;
+        self.pop_slots(descriptor.arg_slots(true))?;
+
+        if has_receiver {
+            let receiver = self.pop()?;
+            if self.constants.get(name).into_utf8() == java_str!("<init>") {
+                let initialized = match receiver {
+                    VerificationType::UninitializedThis => {
+                        VerificationType::object(self.method.class_name)
+                    }
+                    VerificationType::Uninitialized(pc) => {
+                        let mut bytecode = Bytecode::new(self.bytecode);
+                        bytecode.set_pc(pc as u32);
+                        match bytecode.next() {
+                            Some((_, Instruction::new(class))) => {
+                                VerificationType::object(class_name(self.constants, class))
+                            }
+                            _ => return Err(BuildError::InvalidBranchTarget),
+                        }
+                    }
+                    _ => receiver.clone(),
+                };
+                for slot in self.frame.locals.iter_mut().chain(&mut self.frame.stack) {
+                    if *slot == receiver {
+                        *slot = initialized.clone();
+                    }
+                }
+            }
+        }
+
+        if let Some(result) = descriptor.result() {
+            self.push(VerificationType::from_field_type(result));
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<()> {
+        use VerificationType::{Double, Float, Integer, Long, Null};
+
+        match *instruction {
+            // Constants
+            Instruction::nop => {}
+            Instruction::aconst_null => self.push(Null),
+            Instruction::iconst(_) | Instruction::bipush(_) | Instruction::sipush(_) => {
+                self.push(Integer)
+            }
+            Instruction::lconst(_) => self.push(Long),
+            Instruction::fconst(_) => self.push(Float),
+            Instruction::dconst(_) => self.push(Double),
+            Instruction::ldc(index) => {
+                let verification_type = match self.constants.get(index) {
+                    Entry::Integer(_) => Integer,
+                    Entry::Float(_) => Float,
+                    Entry::Long(_) => Long,
+                    Entry::Double(_) => Double,
+                    Entry::String(_) => VerificationType::object(java_str!("java/lang/String")),
+                    Entry::Class(_) => VerificationType::object(java_str!("java/lang/Class")),
+                    Entry::MethodType(_) => {
+                        VerificationType::object(java_str!("java/lang/invoke/MethodType"))
+                    }
+                    Entry::MethodHandle(..) => {
+                        VerificationType::object(java_str!("java/lang/invoke/MethodHandle"))
+                    }
+                    Entry::Dynamic(_, name_type) => {
+                        let (_, descriptor) = self.constants.get(*name_type).into_name_type();
+                        let field_type =
+                            FieldType::parse(self.constants.get(descriptor).into_utf8())
+                                .or(Err(BuildError::InvalidDescriptor))?;
+                        VerificationType::from_field_type(&field_type)
+                    }
+                    _ => return Err(BuildError::InvalidDescriptor),
+                };
+                self.push(verification_type);
+            }
+
+            // Loads
+            Instruction::iload(index)
+            | Instruction::lload(index)
+            | Instruction::fload(index)
+            | Instruction::dload(index)
+            | Instruction::aload(index) => self.load(index),
+            Instruction::iaload
+            | Instruction::baload
+            | Instruction::caload
+            | Instruction::saload => self.operation(2, Integer)?,
+            Instruction::laload => self.operation(2, Long)?,
+            Instruction::faload => self.operation(2, Float)?,
+            Instruction::daload => self.operation(2, Double)?,
+            Instruction::aaload => {
+                self.pop()?;
+                let component = match self.pop()? {
+                    VerificationType::Object(array) if array.as_bytes()[0] == b'[' => {
+                        let component = &array.as_bytes()[1..];
+                        let component = if component[0] == b'L' {
+                            &component[1..component.len() - 1]
+                        } else {
+                            component
+                        };
+                        // SAFETY: Removing ASCII characters from the ends of a
+                        // valid Modified UTF-8 string leaves a valid string.
+                        let component = unsafe { JavaStr::from_java_unchecked(component) };
+                        VerificationType::object(component)
+                    }
+                    Null => Null,
+                    _ => VerificationType::object(java_str!("java/lang/Object")),
+                };
+                self.push(component);
+            }
+
+            // Stores
+            Instruction::istore(index)
+            | Instruction::fstore(index)
+            | Instruction::astore(index) => {
+                let verification_type = self.pop()?;
+                self.store(index, verification_type);
+            }
+            Instruction::lstore(index) | Instruction::dstore(index) => {
+                self.pop()?;
+                let verification_type = self.pop()?;
+                self.store(index, verification_type);
+            }
+            Instruction::iastore
+            | Instruction::fastore
+            | Instruction::aastore
+            | Instruction::bastore
+            | Instruction::castore
+            | Instruction::sastore => self.pop_slots(3)?,
+            Instruction::lastore | Instruction::dastore => self.pop_slots(4)?,
+
+            // Stack
+            Instruction::pop => self.pop_slots(1)?,
+            Instruction::pop2 => self.pop_slots(2)?,
+            Instruction::dup => {
+                let a = self.pop()?;
+                self.push_slot(a.clone());
+                self.push_slot(a);
+            }
+            Instruction::dup_x1 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                for slot in [a.clone(), b, a] {
+                    self.push_slot(slot);
+                }
+            }
+            Instruction::dup_x2 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                let c = self.pop()?;
+                for slot in [a.clone(), c, b, a] {
+                    self.push_slot(slot);
+                }
+            }
+            Instruction::dup2 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                for slot in [b.clone(), a.clone(), b, a] {
+                    self.push_slot(slot);
+                }
+            }
+            Instruction::dup2_x1 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                let c = self.pop()?;
+                for slot in [b.clone(), a.clone(), c, b, a] {
+                    self.push_slot(slot);
+                }
+            }
+            Instruction::dup2_x2 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                let c = self.pop()?;
+                let d = self.pop()?;
+                for slot in [b.clone(), a.clone(), d, c, b, a] {
+                    self.push_slot(slot);
+                }
+            }
+            Instruction::swap => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push_slot(a);
+                self.push_slot(b);
+            }
+
+            // Math
+            Instruction::iadd
+            | Instruction::isub
+            | Instruction::imul
+            | Instruction::idiv
+            | Instruction::irem
+            | Instruction::ishl
+            | Instruction::ishr
+            | Instruction::iushr
+            | Instruction::iand
+            | Instruction::ior
+            | Instruction::ixor => self.operation(2, Integer)?,
+            Instruction::ladd
+            | Instruction::lsub
+            | Instruction::lmul
+            | Instruction::ldiv
+            | Instruction::lrem
+            | Instruction::land
+            | Instruction::lor
+            | Instruction::lxor => self.operation(4, Long)?,
+            Instruction::lshl | Instruction::lshr | Instruction::lushr => {
+                self.operation(3, Long)?
+            }
+            Instruction::fadd
+            | Instruction::fsub
+            | Instruction::fmul
+            | Instruction::fdiv
+            | Instruction::frem => self.operation(2, Float)?,
+            Instruction::dadd
+            | Instruction::dsub
+            | Instruction::dmul
+            | Instruction::ddiv
+            | Instruction::drem => self.operation(4, Double)?,
+            Instruction::ineg => self.operation(1, Integer)?,
+            Instruction::lneg => self.operation(2, Long)?,
+            Instruction::fneg => self.operation(1, Float)?,
+            Instruction::dneg => self.operation(2, Double)?,
+            Instruction::iinc(..) => {}
+
+            // Conversions
+            Instruction::i2l => self.operation(1, Long)?,
+            Instruction::i2f => self.operation(1, Float)?,
+            Instruction::i2d => self.operation(1, Double)?,
+            Instruction::l2i => self.operation(2, Integer)?,
+            Instruction::l2f => self.operation(2, Float)?,
+            Instruction::l2d => self.operation(2, Double)?,
+            Instruction::f2i => self.operation(1, Integer)?,
+            Instruction::f2l => self.operation(1, Long)?,
+            Instruction::f2d => self.operation(1, Double)?,
+            Instruction::d2i => self.operation(2, Integer)?,
+            Instruction::d2l => self.operation(2, Long)?,
+            Instruction::d2f => self.operation(2, Float)?,
+            Instruction::i2b | Instruction::i2c | Instruction::i2s => self.operation(1, Integer)?,
+
+            // Comparisons
+            Instruction::lcmp | Instruction::dcmp(_) => self.operation(4, Integer)?,
+            Instruction::fcmp(_) => self.operation(2, Integer)?,
+            Instruction::if_eq(_)
+            | Instruction::if_ne(_)
+            | Instruction::if_lt(_)
+            | Instruction::if_ge(_)
+            | Instruction::if_gt(_)
+            | Instruction::if_le(_)
+            | Instruction::ifnull(_)
+            | Instruction::ifnonnull(_) => self.pop_slots(1)?,
+            Instruction::if_icmp_eq(_)
+            | Instruction::if_icmp_ne(_)
+            | Instruction::if_icmp_lt(_)
+            | Instruction::if_icmp_ge(_)
+            | Instruction::if_icmp_gt(_)
+            | Instruction::if_icmp_le(_)
+            | Instruction::if_acmp_eq(_)
+            | Instruction::if_acmp_ne(_) => self.pop_slots(2)?,
+
+            // Control
+            Instruction::goto(_) | Instruction::ret_void => {}
+            Instruction::jsr(_) | Instruction::ret(_) => {
+                return Err(BuildError::Subroutine(self.pc as u16))
+            }
+            Instruction::tableswitch(_)
+            | Instruction::lookupswitch(_)
+            | Instruction::ireturn
+            | Instruction::freturn
+            | Instruction::areturn
+            | Instruction::athrow => self.pop_slots(1)?,
+            Instruction::lreturn | Instruction::dreturn => self.pop_slots(2)?,
+
+            // References
+            Instruction::getstatic(index) => {
+                let field_type = self.field_type(index)?;
+                self.push(VerificationType::from_field_type(&field_type));
+            }
+            Instruction::putstatic(index) => {
+                let field_type = self.field_type(index)?;
+                self.pop_slots(field_type.slots())?;
+            }
+            Instruction::getfield(index) => {
+                let field_type = self.field_type(index)?;
+                self.operation(1, VerificationType::from_field_type(&field_type))?;
+            }
+            Instruction::putfield(index) => {
+                let field_type = self.field_type(index)?;
+                self.pop_slots(field_type.slots() + 1)?;
+            }
+            Instruction::invokevirtual(index)
+            | Instruction::invokespecial(index)
+            | Instruction::invokeinterface(index, _) => {
+                let (_, name_type) = self.constants.get(index).into_ref();
+                self.invoke(name_type, true)?;
+            }
+            Instruction::invokestatic(index) => {
+                let (_, name_type) = self.constants.get(index).into_ref();
+                self.invoke(name_type, false)?;
+            }
+            Instruction::invokedynamic(index) => {
+                let (_, name_type) = self.constants.get(index).into_invoke_dynamic();
+                self.invoke(name_type, false)?;
+            }
+            Instruction::new(_) => self.push(VerificationType::Uninitialized(self.pc as u16)),
+            Instruction::newarray(kind) => {
+                let descriptor = match kind {
+                    ArrayKind::Bool => java_str!("[Z"),
+                    ArrayKind::Char => java_str!("[C"),
+                    ArrayKind::Float => java_str!("[F"),
+                    ArrayKind::Double => java_str!("[D"),
+                    ArrayKind::Byte => java_str!("[B"),
+                    ArrayKind::Short => java_str!("[S"),
+                    ArrayKind::Int => java_str!("[I"),
+                    ArrayKind::Long => java_str!("[J"),
+                };
+                self.operation(1, VerificationType::object(descriptor))?;
+            }
+            Instruction::anewarray(index) => {
+                let component = class_name(self.constants, index);
+                let component = if component.as_bytes()[0] == b'[' {
+                    FieldType::parse(component).or(Err(BuildError::InvalidDescriptor))?
+                } else {
+                    FieldType::class(component)
+                };
+                let array = FieldType::array(component);
+                self.operation(1, VerificationType::from_field_type(&array))?;
+            }
+            Instruction::arraylength | Instruction::instanceof(_) => self.operation(1, Integer)?,
+            Instruction::checkcast(index) => {
+                let class = class_name(self.constants, index);
+                self.operation(1, VerificationType::object(class))?;
+            }
+            Instruction::monitorenter | Instruction::monitorexit => self.pop_slots(1)?,
+
+            // Extended
+            Instruction::multianewarray(index, dimensions) => {
+                let class = class_name(self.constants, index);
+                self.operation(dimensions as usize, VerificationType::object(class))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn class_name(constants: &ConstantPool, class: ConstantIdx) -> &JavaStr {
+    constants.get(constants.get(class).into_class()).into_utf8()
+}
+
+/// Returns the descriptor of a field or method type as a string. This is also
+/// how array classes are named.
+pub(super) fn descriptor_string(descriptor: &impl std::fmt::Display) -> JavaString {
+    string::from_utf8(&descriptor.to_string()).into_owned()
+}
@@ -0,0 +1,715 @@
+use crate::writer::Writer;
+
+use super::{
+    ArrayKind, Attribute, AttributeKind, Class, Code, ConstantIdx, ConstantPool, Entry, Field,
+    Instruction, LineNumber, LocalVariable, Method, ReferenceKind,
+};
+
+type Result<T> = std::result::Result<T, WriteError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteError {
+    /// A table, such as the constant pool or the fields of a class, has more
+    /// entries than its `u16` count can describe.
+    TooManyEntries,
+    /// An attribute or the bytecode of a method is longer than its `u32`
+    /// length can describe.
+    AttributeTooLong,
+    /// A string in the constant pool is longer than 65535 bytes.
+    StringTooLong,
+    /// An attribute which is stored in a structured form is listed, but its
+    /// owner has no value for it, such as a `Code` attribute on a method
+    /// without bytecode.
+    MissingAttribute,
+}
+
+impl From<ReferenceKind> for u8 {
+    fn from(kind: ReferenceKind) -> Self {
+        match kind {
+            ReferenceKind::GetField => 1,
+            ReferenceKind::GetStatic => 2,
+            ReferenceKind::PutField => 3,
+            ReferenceKind::PutStatic => 4,
+            ReferenceKind::InvokeVirtual => 5,
+            ReferenceKind::InvokeStatic => 6,
+            ReferenceKind::InvokeSpecial => 7,
+            ReferenceKind::NewInvokeSpecial => 8,
+            ReferenceKind::InvokeInterface => 9,
+        }
+    }
+}
+
+impl From<ArrayKind> for u8 {
+    fn from(kind: ArrayKind) -> Self {
+        match kind {
+            ArrayKind::Bool => 4,
+            ArrayKind::Char => 5,
+            ArrayKind::Float => 6,
+            ArrayKind::Double => 7,
+            ArrayKind::Byte => 8,
+            ArrayKind::Short => 9,
+            ArrayKind::Int => 10,
+            ArrayKind::Long => 11,
+        }
+    }
+}
+
+/// Serializes a class back into the class file format.
+///
+/// Attributes are written in the order given by [`Class::attributes`] and its
+/// counterparts on fields, methods and [`Code`], so a class which has not been
+/// modified since it was parsed is written out byte for byte. The contents of
+/// the attributes graphene understands are taken from their structured form,
+/// which means that changes to, for example, [`Code::bytecode`] are reflected
+/// in the output. Only attributes which are listed are written.
+///
+/// # Errors
+///
+/// Returns a [`WriteError`] if the class cannot be represented in the class
+/// file format.
+pub fn write(class: &Class) -> Result<Vec<u8>> {
+    let mut writer = Writer::new();
+
+    // Magic Number
+    writer.write_u32(0xCAFE_BABE);
+
+    // File Version
+    writer.write_u16(class.minor_version);
+    writer.write_u16(class.major_version);
+
+    // Constant Pool
+    write_constant_pool(&mut writer, &class.constants)?;
+
+    // Access Flags
+    writer.write_u16(class.flags.bits());
+
+    // Class Name
+    writer.write_u16(class.this_class.get());
+
+    // Super Class Name
+    writer.write_u16(class.super_class.map_or(0, |super_class| super_class.get()));
+
+    // Interfaces
+    writer.write_u16(count(class.interfaces.len())?);
+    for interface in &class.interfaces {
+        writer.write_u16(interface.get());
+    }
+
+    // Fields
+    writer.write_u16(count(class.fields.len())?);
+    for field in &class.fields {
+        write_field(&mut writer, field)?;
+    }
+
+    // Methods
+    writer.write_u16(count(class.methods.len())?);
+    for method in &class.methods {
+        write_method(&mut writer, method)?;
+    }
+
+    writer.write_u16(count(class.attributes.len())?);
+    for attribute in &class.attributes {
+        write_attribute(&mut writer, attribute, |writer| match &attribute.kind {
+            AttributeKind::Signature(signature) => {
+                writer.write_u16(signature.get());
+                Ok(())
+            }
+            AttributeKind::SourceFile => {
+                let source_file = class.source_file.ok_or(WriteError::MissingAttribute)?;
+                writer.write_u16(source_file.get());
+                Ok(())
+            }
+            AttributeKind::Raw(bytes) => {
+                writer.write_slice(bytes);
+                Ok(())
+            }
+            _ => Err(WriteError::MissingAttribute),
+        })?;
+    }
+
+    Ok(writer.into_inner())
+}
+
+fn write_constant_pool(writer: &mut Writer, constants: &ConstantPool) -> Result<()> {
+    writer.write_u16(count(constants.len() + 1)?);
+    for (_, entry) in constants.iter() {
+        match entry {
+            Entry::Utf8(string) => {
+                writer.write_u8(1);
+                let length = u16::try_from(string.len()).or(Err(WriteError::StringTooLong))?;
+                writer.write_u16(length);
+                writer.write_slice(string.as_bytes());
+            }
+            Entry::Integer(value) => {
+                writer.write_u8(3);
+                writer.write_i32(*value);
+            }
+            Entry::Float(value) => {
+                writer.write_u8(4);
+                writer.write_u32(value.to_bits());
+            }
+            Entry::Long(value) => {
+                writer.write_u8(5);
+                writer.write_u32((*value as u64 >> 32) as u32);
+                writer.write_u32(*value as u32);
+            }
+            Entry::Double(value) => {
+                let bits = value.to_bits();
+                writer.write_u8(6);
+                writer.write_u32((bits >> 32) as u32);
+                writer.write_u32(bits as u32);
+            }
+            Entry::Class(name_idx) => {
+                writer.write_u8(7);
+                writer.write_u16(name_idx.get());
+            }
+            Entry::String(string_idx) => {
+                writer.write_u8(8);
+                writer.write_u16(string_idx.get());
+            }
+            Entry::FieldRef(class_idx, name_type_idx) => {
+                writer.write_u8(9);
+                writer.write_u16(class_idx.get());
+                writer.write_u16(name_type_idx.get());
+            }
+            Entry::MethodRef(class_idx, name_type_idx) => {
+                writer.write_u8(10);
+                writer.write_u16(class_idx.get());
+                writer.write_u16(name_type_idx.get());
+            }
+            Entry::InterfaceMethodRef(class_idx, name_type_idx) => {
+                writer.write_u8(11);
+                writer.write_u16(class_idx.get());
+                writer.write_u16(name_type_idx.get());
+            }
+            Entry::NameType(name_idx, descriptor_idx) => {
+                writer.write_u8(12);
+                writer.write_u16(name_idx.get());
+                writer.write_u16(descriptor_idx.get());
+            }
+            Entry::MethodHandle(ref_kind, ref_idx) => {
+                writer.write_u8(15);
+                writer.write_u8(u8::from(*ref_kind));
+                writer.write_u16(ref_idx.get());
+            }
+            Entry::MethodType(descriptor_idx) => {
+                writer.write_u8(16);
+                writer.write_u16(descriptor_idx.get());
+            }
+            Entry::Dynamic(bootstrap_method_attr_idx, name_type_idx) => {
+                writer.write_u8(17);
+                writer.write_u16(*bootstrap_method_attr_idx);
+                writer.write_u16(name_type_idx.get());
+            }
+            Entry::InvokeDynamic(bootstrap_method_attr_idx, name_type_idx) => {
+                writer.write_u8(18);
+                writer.write_u16(*bootstrap_method_attr_idx);
+                writer.write_u16(name_type_idx.get());
+            }
+            Entry::Module(name_idx) => {
+                writer.write_u8(19);
+                writer.write_u16(name_idx.get());
+            }
+            Entry::Package(name_idx) => {
+                writer.write_u8(20);
+                writer.write_u16(name_idx.get());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_field(writer: &mut Writer, field: &Field) -> Result<()> {
+    writer.write_u16(field.flags.bits());
+    writer.write_u16(field.name.get());
+    writer.write_u16(field.descriptor.get());
+
+    writer.write_u16(count(field.attributes.len())?);
+    for attribute in &field.attributes {
+        write_attribute(writer, attribute, |writer| match &attribute.kind {
+            AttributeKind::Signature(signature) => {
+                writer.write_u16(signature.get());
+                Ok(())
+            }
+            AttributeKind::ConstantValue => {
+                let value = field.constant_value.ok_or(WriteError::MissingAttribute)?;
+                writer.write_u16(value.get());
+                Ok(())
+            }
+            AttributeKind::Raw(bytes) => {
+                writer.write_slice(bytes);
+                Ok(())
+            }
+            _ => Err(WriteError::MissingAttribute),
+        })?;
+    }
+    Ok(())
+}
+
+fn write_method(writer: &mut Writer, method: &Method) -> Result<()> {
+    writer.write_u16(method.flags.bits());
+    writer.write_u16(method.name.get());
+    writer.write_u16(method.descriptor.get());
+
+    writer.write_u16(count(method.attributes.len())?);
+    for attribute in &method.attributes {
+        write_attribute(writer, attribute, |writer| match &attribute.kind {
+            AttributeKind::Code => {
+                let code = method.code.as_ref().ok_or(WriteError::MissingAttribute)?;
+                write_code(writer, code)
+            }
+            AttributeKind::Signature(signature) => {
+                writer.write_u16(signature.get());
+                Ok(())
+            }
+            AttributeKind::Exceptions => {
+                writer.write_u16(count(method.exceptions.len())?);
+                for exception in &method.exceptions {
+                    writer.write_u16(exception.get());
+                }
+                Ok(())
+            }
+            AttributeKind::Raw(bytes) => {
+                writer.write_slice(bytes);
+                Ok(())
+            }
+            _ => Err(WriteError::MissingAttribute),
+        })?;
+    }
+    Ok(())
+}
+
+fn write_code(writer: &mut Writer, code: &Code) -> Result<()> {
+    writer.write_u16(code.max_stack);
+    writer.write_u16(code.max_locals);
+    let code_len = u32::try_from(code.bytecode.len()).or(Err(WriteError::AttributeTooLong))?;
+    writer.write_u32(code_len);
+    writer.write_slice(&code.bytecode);
+
+    writer.write_u16(count(code.exception_table.len())?);
+    for handler in &code.exception_table {
+        writer.write_u16(handler.start_pc);
+        writer.write_u16(handler.end_pc);
+        writer.write_u16(handler.handler_pc);
+        writer.write_u16(handler.catch_type.map_or(0, |catch_type| catch_type.get()));
+    }
+
+    // The entries of the tables are split between their attributes in order,
+    // with the last attribute of each kind taking whatever remains.
+    let mut line_numbers = TableSplitter::new(&code.attributes, &code.line_numbers, |kind| {
+        matches!(kind, AttributeKind::LineNumberTable(_))
+    });
+    let mut local_variables = TableSplitter::new(&code.attributes, &code.local_variables, |kind| {
+        matches!(kind, AttributeKind::LocalVariableTable(_))
+    });
+    let mut local_variable_types =
+        TableSplitter::new(&code.attributes, &code.local_variable_types, |kind| {
+            matches!(kind, AttributeKind::LocalVariableTypeTable(_))
+        });
+
+    writer.write_u16(count(code.attributes.len())?);
+    for attribute in &code.attributes {
+        write_attribute(writer, attribute, |writer| match &attribute.kind {
+            AttributeKind::LineNumberTable(entry_count) => {
+                let entries = line_numbers.take(*entry_count);
+                writer.write_u16(count(entries.len())?);
+                for &LineNumber { start_pc, line } in entries {
+                    writer.write_u16(start_pc);
+                    writer.write_u16(line);
+                }
+                Ok(())
+            }
+            AttributeKind::LocalVariableTable(entry_count) => {
+                write_local_variable_table(writer, local_variables.take(*entry_count))
+            }
+            AttributeKind::LocalVariableTypeTable(entry_count) => {
+                write_local_variable_table(writer, local_variable_types.take(*entry_count))
+            }
+            AttributeKind::Raw(bytes) => {
+                writer.write_slice(bytes);
+                Ok(())
+            }
+            _ => Err(WriteError::MissingAttribute),
+        })?;
+    }
+    Ok(())
+}
+
+fn write_local_variable_table(writer: &mut Writer, entries: &[LocalVariable]) -> Result<()> {
+    writer.write_u16(count(entries.len())?);
+    for local_variable in entries {
+        writer.write_u16(local_variable.start_pc);
+        writer.write_u16(local_variable.length);
+        writer.write_u16(local_variable.name.get());
+        writer.write_u16(local_variable.descriptor.get());
+        writer.write_u16(local_variable.index);
+    }
+    Ok(())
+}
+
+/// Writes the header of `attribute`, followed by the contents produced by
+/// `write_contents`.
+fn write_attribute(
+    writer: &mut Writer,
+    attribute: &Attribute,
+    write_contents: impl FnOnce(&mut Writer) -> Result<()>,
+) -> Result<()> {
+    let mut contents = Writer::new();
+    write_contents(&mut contents)?;
+
+    writer.write_u16(attribute.name.get());
+    let length = u32::try_from(contents.len()).or(Err(WriteError::AttributeTooLong))?;
+    writer.write_u32(length);
+    writer.write_slice(&contents.into_inner());
+    Ok(())
+}
+
+/// Splits the entries of a table such as [`Code::line_numbers`] between the
+/// attributes they were parsed from.
+struct TableSplitter<'a, T> {
+    entries: &'a [T],
+    remaining_attributes: usize,
+}
+
+impl<'a, T> TableSplitter<'a, T> {
+    fn new(
+        attributes: &[Attribute],
+        entries: &'a [T],
+        is_table: impl Fn(&AttributeKind) -> bool,
+    ) -> Self {
+        let remaining_attributes = attributes
+            .iter()
+            .filter(|attribute| is_table(&attribute.kind))
+            .count();
+        Self {
+            entries,
+            remaining_attributes,
+        }
+    }
+
+    fn take(&mut self, entry_count: usize) -> &'a [T] {
+        self.remaining_attributes -= 1;
+        let entry_count = if self.remaining_attributes == 0 {
+            self.entries.len()
+        } else {
+            entry_count.min(self.entries.len())
+        };
+        let (entries, rest) = self.entries.split_at(entry_count);
+        self.entries = rest;
+        entries
+    }
+}
+
+fn count(len: usize) -> Result<u16> {
+    u16::try_from(len).or(Err(WriteError::TooManyEntries))
+}
+
+/// Encodes `instruction`, which is located at `pc`, choosing the shortest
+/// encoding available. This is the counterpart of [`parse_instruction`].
+///
+/// `constants` is used to pick between `ldc`, `ldc_w` and `ldc2_w`.
+///
+/// # Panics
+///
+/// Panics if a constant instruction holds a value which has no dedicated
+/// opcode, such as `lconst(2)`, as those must be loaded from the constant
+/// pool instead.
+///
+/// [`parse_instruction`]: super::parse_instruction
+pub(super) fn write_instruction(
+    writer: &mut Writer,
+    instruction: &Instruction,
+    pc: usize,
+    constants: &ConstantPool,
+) {
+    match *instruction {
+        // Constants
+        Instruction::nop => writer.write_u8(0x00),
+        Instruction::aconst_null => writer.write_u8(0x01),
+        Instruction::iconst(value @ -1..=5) => writer.write_u8((0x03 + value) as u8),
+        Instruction::iconst(value) => {
+            if let Ok(value) = i8::try_from(value) {
+                write_instruction(writer, &Instruction::bipush(value), pc, constants);
+            } else if let Ok(value) = i16::try_from(value) {
+                write_instruction(writer, &Instruction::sipush(value), pc, constants);
+            } else {
+                panic!("invalid constant instruction: {instruction:?}");
+            }
+        }
+        Instruction::lconst(value @ 0..=1) => writer.write_u8(0x09 + value as u8),
+        Instruction::fconst(value) if value == 0.0 && value.is_sign_positive() => {
+            writer.write_u8(0x0B)
+        }
+        Instruction::fconst(1.0) => writer.write_u8(0x0C),
+        Instruction::fconst(2.0) => writer.write_u8(0x0D),
+        Instruction::dconst(value) if value == 0.0 && value.is_sign_positive() => {
+            writer.write_u8(0x0E)
+        }
+        Instruction::dconst(1.0) => writer.write_u8(0x0F),
+        Instruction::lconst(_) | Instruction::fconst(_) | Instruction::dconst(_) => {
+            panic!("invalid constant instruction: {instruction:?}")
+        }
+        Instruction::bipush(value) => {
+            writer.write_u8(0x10);
+            writer.write_u8(value as u8);
+        }
+        Instruction::sipush(value) => {
+            writer.write_u8(0x11);
+            writer.write_u16(value as u16);
+        }
+        Instruction::ldc(index) => match constants.get(index) {
+            Entry::Long(_) | Entry::Double(_) => write_indexed(writer, 0x14, index),
+            _ => {
+                if let Ok(index) = u8::try_from(index.get()) {
+                    writer.write_u8(0x12);
+                    writer.write_u8(index);
+                } else {
+                    write_indexed(writer, 0x13, index);
+                }
+            }
+        },
+
+        // Loads
+        Instruction::iload(index) => write_local(writer, 0x15, Some(0x1A), index),
+        Instruction::lload(index) => write_local(writer, 0x16, Some(0x1E), index),
+        Instruction::fload(index) => write_local(writer, 0x17, Some(0x22), index),
+        Instruction::dload(index) => write_local(writer, 0x18, Some(0x26), index),
+        Instruction::aload(index) => write_local(writer, 0x19, Some(0x2A), index),
+        Instruction::iaload => writer.write_u8(0x2E),
+        Instruction::laload => writer.write_u8(0x2F),
+        Instruction::faload => writer.write_u8(0x30),
+        Instruction::daload => writer.write_u8(0x31),
+        Instruction::aaload => writer.write_u8(0x32),
+        Instruction::baload => writer.write_u8(0x33),
+        Instruction::caload => writer.write_u8(0x34),
+        Instruction::saload => writer.write_u8(0x35),
+
+        // Stores
+        Instruction::istore(index) => write_local(writer, 0x36, Some(0x3B), index),
+        Instruction::lstore(index) => write_local(writer, 0x37, Some(0x3F), index),
+        Instruction::fstore(index) => write_local(writer, 0x38, Some(0x43), index),
+        Instruction::dstore(index) => write_local(writer, 0x39, Some(0x47), index),
+        Instruction::astore(index) => write_local(writer, 0x3A, Some(0x4B), index),
+        Instruction::iastore => writer.write_u8(0x4F),
+        Instruction::lastore => writer.write_u8(0x50),
+        Instruction::fastore => writer.write_u8(0x51),
+        Instruction::dastore => writer.write_u8(0x52),
+        Instruction::aastore => writer.write_u8(0x53),
+        Instruction::bastore => writer.write_u8(0x54),
+        Instruction::castore => writer.write_u8(0x55),
+        Instruction::sastore => writer.write_u8(0x56),
+
+        // Stack
+        Instruction::pop => writer.write_u8(0x57),
+        Instruction::pop2 => writer.write_u8(0x58),
+        Instruction::dup => writer.write_u8(0x59),
+        Instruction::dup_x1 => writer.write_u8(0x5A),
+        Instruction::dup_x2 => writer.write_u8(0x5B),
+        Instruction::dup2 => writer.write_u8(0x5C),
+        Instruction::dup2_x1 => writer.write_u8(0x5D),
+        Instruction::dup2_x2 => writer.write_u8(0x5E),
+        Instruction::swap => writer.write_u8(0x5F),
+
+        // Math
+        Instruction::iadd => writer.write_u8(0x60),
+        Instruction::ladd => writer.write_u8(0x61),
+        Instruction::fadd => writer.write_u8(0x62),
+        Instruction::dadd => writer.write_u8(0x63),
+        Instruction::isub => writer.write_u8(0x64),
+        Instruction::lsub => writer.write_u8(0x65),
+        Instruction::fsub => writer.write_u8(0x66),
+        Instruction::dsub => writer.write_u8(0x67),
+        Instruction::imul => writer.write_u8(0x68),
+        Instruction::lmul => writer.write_u8(0x69),
+        Instruction::fmul => writer.write_u8(0x6A),
+        Instruction::dmul => writer.write_u8(0x6B),
+        Instruction::idiv => writer.write_u8(0x6C),
+        Instruction::ldiv => writer.write_u8(0x6D),
+        Instruction::fdiv => writer.write_u8(0x6E),
+        Instruction::ddiv => writer.write_u8(0x6F),
+        Instruction::irem => writer.write_u8(0x70),
+        Instruction::lrem => writer.write_u8(0x71),
+        Instruction::frem => writer.write_u8(0x72),
+        Instruction::drem => writer.write_u8(0x73),
+        Instruction::ineg => writer.write_u8(0x74),
+        Instruction::lneg => writer.write_u8(0x75),
+        Instruction::fneg => writer.write_u8(0x76),
+        Instruction::dneg => writer.write_u8(0x77),
+        Instruction::ishl => writer.write_u8(0x78),
+        Instruction::lshl => writer.write_u8(0x79),
+        Instruction::ishr => writer.write_u8(0x7A),
+        Instruction::lshr => writer.write_u8(0x7B),
+        Instruction::iushr => writer.write_u8(0x7C),
+        Instruction::lushr => writer.write_u8(0x7D),
+        Instruction::iand => writer.write_u8(0x7E),
+        Instruction::land => writer.write_u8(0x7F),
+        Instruction::ior => writer.write_u8(0x80),
+        Instruction::lor => writer.write_u8(0x81),
+        Instruction::ixor => writer.write_u8(0x82),
+        Instruction::lxor => writer.write_u8(0x83),
+        Instruction::iinc(index, value) => {
+            if let (Ok(index), Ok(value)) = (u8::try_from(index), i8::try_from(value)) {
+                writer.write_u8(0x84);
+                writer.write_u8(index);
+                writer.write_u8(value as u8);
+            } else {
+                writer.write_u8(0xC4);
+                writer.write_u8(0x84);
+                writer.write_u16(index);
+                writer.write_u16(value as u16);
+            }
+        }
+
+        // Conversions
+        Instruction::i2l => writer.write_u8(0x85),
+        Instruction::i2f => writer.write_u8(0x86),
+        Instruction::i2d => writer.write_u8(0x87),
+        Instruction::l2i => writer.write_u8(0x88),
+        Instruction::l2f => writer.write_u8(0x89),
+        Instruction::l2d => writer.write_u8(0x8A),
+        Instruction::f2i => writer.write_u8(0x8B),
+        Instruction::f2l => writer.write_u8(0x8C),
+        Instruction::f2d => writer.write_u8(0x8D),
+        Instruction::d2i => writer.write_u8(0x8E),
+        Instruction::d2l => writer.write_u8(0x8F),
+        Instruction::d2f => writer.write_u8(0x90),
+        Instruction::i2b => writer.write_u8(0x91),
+        Instruction::i2c => writer.write_u8(0x92),
+        Instruction::i2s => writer.write_u8(0x93),
+
+        // Comparisons
+        Instruction::lcmp => writer.write_u8(0x94),
+        Instruction::fcmp(greater) => writer.write_u8(if greater { 0x96 } else { 0x95 }),
+        Instruction::dcmp(greater) => writer.write_u8(if greater { 0x98 } else { 0x97 }),
+        Instruction::if_eq(offset) => write_branch(writer, 0x99, offset),
+        Instruction::if_ne(offset) => write_branch(writer, 0x9A, offset),
+        Instruction::if_lt(offset) => write_branch(writer, 0x9B, offset),
+        Instruction::if_ge(offset) => write_branch(writer, 0x9C, offset),
+        Instruction::if_gt(offset) => write_branch(writer, 0x9D, offset),
+        Instruction::if_le(offset) => write_branch(writer, 0x9E, offset),
+        Instruction::if_icmp_eq(offset) => write_branch(writer, 0x9F, offset),
+        Instruction::if_icmp_ne(offset) => write_branch(writer, 0xA0, offset),
+        Instruction::if_icmp_lt(offset) => write_branch(writer, 0xA1, offset),
+        Instruction::if_icmp_ge(offset) => write_branch(writer, 0xA2, offset),
+        Instruction::if_icmp_gt(offset) => write_branch(writer, 0xA3, offset),
+        Instruction::if_icmp_le(offset) => write_branch(writer, 0xA4, offset),
+        Instruction::if_acmp_eq(offset) => write_branch(writer, 0xA5, offset),
+        Instruction::if_acmp_ne(offset) => write_branch(writer, 0xA6, offset),
+
+        // Control
+        Instruction::goto(offset) => match i16::try_from(offset) {
+            Ok(offset) => write_branch(writer, 0xA7, offset),
+            Err(_) => {
+                writer.write_u8(0xC8);
+                writer.write_i32(offset);
+            }
+        },
+        Instruction::jsr(offset) => match i16::try_from(offset) {
+            Ok(offset) => write_branch(writer, 0xA8, offset),
+            Err(_) => {
+                writer.write_u8(0xC9);
+                writer.write_i32(offset);
+            }
+        },
+        Instruction::ret(index) => write_local(writer, 0xA9, None, index),
+        Instruction::tableswitch(switch) => {
+            writer.write_u8(0xAA);
+            write_switch_padding(writer, pc);
+            writer.write_i32(switch.default());
+            writer.write_i32(switch.low());
+            writer.write_i32(switch.high());
+            for offset in switch.offsets() {
+                writer.write_i32(offset);
+            }
+        }
+        Instruction::lookupswitch(switch) => {
+            writer.write_u8(0xAB);
+            write_switch_padding(writer, pc);
+            writer.write_i32(switch.default());
+            writer.write_i32(switch.pairs().count() as i32);
+            for (key, offset) in switch.pairs() {
+                writer.write_i32(key);
+                writer.write_i32(offset);
+            }
+        }
+        Instruction::ireturn => writer.write_u8(0xAC),
+        Instruction::lreturn => writer.write_u8(0xAD),
+        Instruction::freturn => writer.write_u8(0xAE),
+        Instruction::dreturn => writer.write_u8(0xAF),
+        Instruction::areturn => writer.write_u8(0xB0),
+        Instruction::ret_void => writer.write_u8(0xB1),
+
+        // References
+        Instruction::getstatic(index) => write_indexed(writer, 0xB2, index),
+        Instruction::putstatic(index) => write_indexed(writer, 0xB3, index),
+        Instruction::getfield(index) => write_indexed(writer, 0xB4, index),
+        Instruction::putfield(index) => write_indexed(writer, 0xB5, index),
+        Instruction::invokevirtual(index) => write_indexed(writer, 0xB6, index),
+        Instruction::invokespecial(index) => write_indexed(writer, 0xB7, index),
+        Instruction::invokestatic(index) => write_indexed(writer, 0xB8, index),
+        Instruction::invokeinterface(index, count) => {
+            write_indexed(writer, 0xB9, index);
+            writer.write_u8(count);
+            writer.write_u8(0);
+        }
+        Instruction::invokedynamic(index) => {
+            write_indexed(writer, 0xBA, index);
+            writer.write_u16(0);
+        }
+        Instruction::new(index) => write_indexed(writer, 0xBB, index),
+        Instruction::newarray(kind) => {
+            writer.write_u8(0xBC);
+            writer.write_u8(u8::from(kind));
+        }
+        Instruction::anewarray(index) => write_indexed(writer, 0xBD, index),
+        Instruction::arraylength => writer.write_u8(0xBE),
+        Instruction::athrow => writer.write_u8(0xBF),
+        Instruction::checkcast(index) => write_indexed(writer, 0xC0, index),
+        Instruction::instanceof(index) => write_indexed(writer, 0xC1, index),
+        Instruction::monitorenter => writer.write_u8(0xC2),
+        Instruction::monitorexit => writer.write_u8(0xC3),
+
+        // Extended
+        Instruction::multianewarray(index, dimensions) => {
+            write_indexed(writer, 0xC5, index);
+            writer.write_u8(dimensions);
+        }
+        Instruction::ifnull(offset) => write_branch(writer, 0xC6, offset),
+        Instruction::ifnonnull(offset) => write_branch(writer, 0xC7, offset),
+    }
+}
+
+fn write_indexed(writer: &mut Writer, opcode: u8, index: ConstantIdx) {
+    writer.write_u8(opcode);
+    writer.write_u16(index.get());
+}
+
+fn write_branch(writer: &mut Writer, opcode: u8, offset: i16) {
+    writer.write_u8(opcode);
+    writer.write_u16(offset as u16);
+}
+
+/// Writes an instruction operating on a local variable, using the `_0` to `_3`
+/// forms starting at `short_opcode` where possible, and `wide` where the index
+/// does not fit in a byte.
+fn write_local(writer: &mut Writer, opcode: u8, short_opcode: Option<u8>, index: u16) {
+    match (short_opcode, u8::try_from(index)) {
+        (Some(short_opcode), Ok(index @ 0..=3)) => writer.write_u8(short_opcode + index),
+        (_, Ok(index)) => {
+            writer.write_u8(opcode);
+            writer.write_u8(index);
+        }
+        (_, Err(_)) => {
+            writer.write_u8(0xC4);
+            writer.write_u8(opcode);
+            writer.write_u16(index);
+        }
+    }
+}
+
+/// Pads a switch instruction at `pc` so that its operands are 4-byte aligned.
+fn write_switch_padding(writer: &mut Writer, pc: usize) {
+    for _ in 0..(4 - (pc + 1) % 4) % 4 {
+        writer.write_u8(0);
+    }
+}
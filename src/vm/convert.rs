@@ -0,0 +1,661 @@
+//! Conversions between Rust values and the [`Value`]s of Java types, which
+//! the host passes to and receives from [`Vm::call`], and which native
+//! methods pop from and push to the operand stack.
+//!
+//! Each Rust type stands for a Java type, from which the descriptor of the
+//! method called is derived and checked against the one it was declared
+//! with. Integers narrower than `int` are passed as `int` values, as in the
+//! operand stack, and are truncated when they come back. An object reference
+//! converts from any class or array type, as the host does not know the class
+//! of the objects it holds, and `null` only converts to an [`Option`].
+//!
+//! Strings and vectors or slices convert to new `java.lang.String` objects
+//! and arrays, which are allocated in a [`JavaHeap`], and are copied back out
+//! of them. A string holds its characters as JDK 9 and later do, in a
+//! `byte[]` field `value` along with a `byte` field `coder`, which is 0 if
+//! the bytes are Latin-1 characters and 1 if they are pairs of bytes holding
+//! UTF-16 code units in the byte order of the machine.
+//!
+//! [`Vm::call`]: super::Vm::call
+
+use super::class::FieldType;
+use super::heap::{AllocError, Heap, ObjectRef};
+use super::value::{Value, ValueType};
+use super::{ClassManager, LinkageError};
+use crate::java_str;
+
+/// The `coder` of a string whose bytes are Latin-1 characters.
+const LATIN1: i32 = 0;
+
+/// The `coder` of a string whose bytes hold UTF-16 code units.
+const UTF16: i32 = 1;
+
+/// Why a Rust value could not be converted to a Java value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvertError {
+    /// The Java type cannot represent the value.
+    Unrepresentable,
+    /// A string or array could not be allocated.
+    Alloc(AllocError),
+    /// `java.lang.String`, or the class of an array, could not be found or
+    /// linked.
+    Linkage(LinkageError),
+}
+
+impl From<AllocError> for ConvertError {
+    fn from(error: AllocError) -> Self {
+        Self::Alloc(error)
+    }
+}
+
+impl From<LinkageError> for ConvertError {
+    fn from(error: LinkageError) -> Self {
+        Self::Linkage(error)
+    }
+}
+
+/// The heap values are converted in, along with the classes of its objects.
+///
+/// Conversions to Java allocate the strings and arrays they create without
+/// collecting the heap, and fail with [`AllocError::Full`] if they do not
+/// fit. The references which the Rust values converted from Java hold are
+/// recorded, so that the virtual machine can pin them when it returns the
+/// values to the host.
+#[derive(Debug)]
+pub struct JavaHeap<'h> {
+    classes: &'h ClassManager,
+    heap: &'h mut Heap,
+    held: Vec<ObjectRef>,
+}
+
+impl<'h> JavaHeap<'h> {
+    pub fn new(classes: &'h ClassManager, heap: &'h mut Heap) -> Self {
+        Self {
+            classes,
+            heap,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn classes(&self) -> &ClassManager {
+        self.classes
+    }
+
+    pub fn heap(&mut self) -> &mut Heap {
+        self.heap
+    }
+
+    /// Records that a Rust value converted from Java holds `object`.
+    pub fn hold(&mut self, object: ObjectRef) {
+        self.held.push(object);
+    }
+
+    /// Returns the references the converted values hold.
+    pub(super) fn into_held(self) -> Vec<ObjectRef> {
+        self.held
+    }
+
+    /// Allocates an array of type `array` holding `elements`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConvertError::Linkage`] if no class loaded names the type,
+    /// and [`ConvertError::Alloc`] if the array does not fit.
+    pub fn alloc_array(
+        &mut self,
+        array: &FieldType,
+        elements: Vec<Value>,
+    ) -> Result<ObjectRef, ConvertError> {
+        let class = self.classes.array_class(array)?;
+        self.classes.link(class)?;
+        Ok(self.heap.alloc(class as u32, elements.into_boxed_slice())?)
+    }
+
+    /// Returns the elements of `array`, or `None` if it is not an array whose
+    /// components are of a type `accepts` returns `true` for.
+    fn elements(
+        &self,
+        array: ObjectRef,
+        accepts: impl FnOnce(&FieldType) -> bool,
+    ) -> Option<&[Value]> {
+        let object = self.heap.get(array);
+        let component = self.classes.classes[object.class as usize]
+            .component
+            .as_ref()?;
+        accepts(component).then_some(&object.fields)
+    }
+
+    /// Allocates a `java.lang.String` holding the characters of `string`.
+    fn alloc_string(&mut self, string: &str) -> Result<ObjectRef, ConvertError> {
+        let units: Vec<u16> = string.encode_utf16().collect();
+        self.alloc_string_units(&units)
+    }
+
+    /// Allocates a `java.lang.String` holding the UTF-16 code units `units`.
+    pub(super) fn alloc_string_units(&mut self, units: &[u16]) -> Result<ObjectRef, ConvertError> {
+        let fields = StringFields::new(self.classes)?;
+        let (coder, bytes): (_, Vec<_>) = if units.iter().all(|&unit| unit <= 0xFF) {
+            (LATIN1, units.iter().map(|&unit| unit as u8).collect())
+        } else {
+            (
+                UTF16,
+                units.iter().flat_map(|unit| unit.to_ne_bytes()).collect(),
+            )
+        };
+        let bytes = bytes
+            .into_iter()
+            .map(|byte| Value::Int(byte as i8 as i32))
+            .collect();
+        let value = self.alloc_array(&FieldType::array(FieldType::Byte), bytes)?;
+        let object = self.classes.instantiate(self.heap, fields.class as u32)?;
+        self.heap
+            .set_field(object, fields.value, Value::Reference(Some(value)));
+        self.heap.set_field(object, fields.coder, Value::Int(coder));
+        Ok(object)
+    }
+
+    /// Returns the characters of `string`, or `None` if it is not a
+    /// `java.lang.String` or holds an unpaired surrogate.
+    fn read_string(&self, string: ObjectRef) -> Option<String> {
+        let units = self.string_units(string)?;
+        char::decode_utf16(units).collect::<Result<_, _>>().ok()
+    }
+
+    /// Returns the UTF-16 code units of `string`, or `None` if it is not a
+    /// `java.lang.String`.
+    pub(super) fn string_units(&self, string: ObjectRef) -> Option<Vec<u16>> {
+        let fields = StringFields::new(self.classes).ok()?;
+        let object = self.heap.get(string);
+        if object.class as usize != fields.class {
+            return None;
+        }
+        let (Value::Reference(Some(value)), Value::Int(coder)) =
+            (object.fields[fields.value], object.fields[fields.coder])
+        else {
+            return None;
+        };
+        let bytes = self.elements(value, |component| *component == FieldType::Byte)?;
+        let bytes = bytes.iter().map(|byte| match byte {
+            Value::Int(byte) => *byte as u8,
+            _ => unreachable!("a byte array holds ints"),
+        });
+        match coder {
+            LATIN1 => Some(bytes.map(u16::from).collect()),
+            UTF16 => {
+                let bytes: Vec<u8> = bytes.collect();
+                let units =
+                    (bytes.chunks_exact(2)).map(|pair| u16::from_ne_bytes([pair[0], pair[1]]));
+                Some(units.collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Where the fields of `java.lang.String` are in its objects.
+struct StringFields {
+    class: usize,
+    value: usize,
+    coder: usize,
+}
+
+impl StringFields {
+    /// Finds the fields, linking `java.lang.String`.
+    fn new(classes: &ClassManager) -> Result<Self, LinkageError> {
+        let class = classes.find(java_str!("java/lang/String"))?;
+        Ok(Self {
+            class,
+            value: classes.field_slot(class, java_str!("value"), java_str!("[B"))?,
+            coder: classes.field_slot(class, java_str!("coder"), java_str!("B"))?,
+        })
+    }
+}
+
+/// A Rust type which stands for a Java type.
+pub trait JavaType {
+    /// Returns the Java type which the descriptors derived from the Rust type
+    /// name.
+    fn field_type() -> FieldType;
+
+    /// Returns whether values of `field_type` convert to and from the Rust
+    /// type, which only those of [`JavaType::field_type`] do by default.
+    fn accepts(field_type: &FieldType) -> bool {
+        *field_type == Self::field_type()
+    }
+
+    /// Returns how values of the type are stored in stack slots.
+    fn value_type() -> ValueType {
+        ValueType::from(&Self::field_type())
+    }
+}
+
+/// A Rust type which converts to a Java value.
+pub trait ToJava: JavaType {
+    /// Converts the value to one of `field_type`, which the Rust type
+    /// accepts, allocating the objects it needs in `heap`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConvertError::Unrepresentable`] if the Java type cannot
+    /// represent the value, and the other errors if the objects cannot be
+    /// allocated.
+    fn to_java(
+        &self,
+        field_type: &FieldType,
+        heap: &mut JavaHeap<'_>,
+    ) -> Result<Value, ConvertError>;
+}
+
+/// A Rust type which a Java value converts to.
+pub trait FromJava: JavaType + Sized {
+    /// Converts a value of a type the Rust type accepts, whose objects are in
+    /// `heap`, or returns `None` if the Rust type cannot represent it.
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self>;
+}
+
+/// A Rust type which stands for Java references, and is passed as `null`
+/// when wrapped in an [`Option`].
+pub trait JavaReference: JavaType {}
+
+macro_rules! int_type {
+    ($($rust:ty => $java:ident,)*) => {
+        $(
+            impl JavaType for $rust {
+                fn field_type() -> FieldType {
+                    FieldType::$java
+                }
+            }
+
+            impl ToJava for $rust {
+                fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+                    Ok(Value::Int((*self).into()))
+                }
+            }
+
+            impl FromJava for $rust {
+                fn from_java(value: Value, _: &mut JavaHeap<'_>) -> Option<Self> {
+                    match value {
+                        Value::Int(value) => Some(value as $rust),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+int_type! {
+    i32 => Int,
+    i16 => Short,
+    i8 => Byte,
+    u16 => Char,
+}
+
+impl JavaType for i64 {
+    fn field_type() -> FieldType {
+        FieldType::Long
+    }
+}
+
+impl ToJava for i64 {
+    fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+        Ok(Value::Long(*self))
+    }
+}
+
+impl FromJava for i64 {
+    fn from_java(value: Value, _: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl JavaType for f32 {
+    fn field_type() -> FieldType {
+        FieldType::Float
+    }
+}
+
+impl ToJava for f32 {
+    fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+        Ok(Value::Float(*self))
+    }
+}
+
+impl FromJava for f32 {
+    fn from_java(value: Value, _: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl JavaType for f64 {
+    fn field_type() -> FieldType {
+        FieldType::Double
+    }
+}
+
+impl ToJava for f64 {
+    fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+        Ok(Value::Double(*self))
+    }
+}
+
+impl FromJava for f64 {
+    fn from_java(value: Value, _: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Double(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl JavaType for bool {
+    fn field_type() -> FieldType {
+        FieldType::Bool
+    }
+}
+
+impl ToJava for bool {
+    fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+        Ok(Value::Int(*self as i32))
+    }
+}
+
+impl FromJava for bool {
+    /// Converts an `int`, of which only the lowest bit is kept, as when it is
+    /// stored in a `boolean` field.
+    fn from_java(value: Value, _: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Int(value) => Some(value & 1 != 0),
+            _ => None,
+        }
+    }
+}
+
+impl JavaType for char {
+    fn field_type() -> FieldType {
+        FieldType::Char
+    }
+}
+
+impl ToJava for char {
+    /// Converts a character of the Basic Multilingual Plane, as a Java `char`
+    /// cannot hold the others.
+    fn to_java(&self, _: &FieldType, _: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+        match u16::try_from(*self as u32) {
+            Ok(c) => Ok(Value::Int(c.into())),
+            Err(_) => Err(ConvertError::Unrepresentable),
+        }
+    }
+}
+
+impl FromJava for char {
+    /// Converts a `char` which is not a surrogate.
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        u16::from_java(value, heap).and_then(|c| char::from_u32(c.into()))
+    }
+}
+
+impl JavaType for ObjectRef {
+    fn field_type() -> FieldType {
+        FieldType::class(java_str!("java/lang/Object"))
+    }
+
+    fn accepts(field_type: &FieldType) -> bool {
+        matches!(field_type, FieldType::Class(_) | FieldType::Array(_))
+    }
+}
+
+impl JavaReference for ObjectRef {}
+
+impl ToJava for ObjectRef {
+    /// Converts a reference to an object of `field_type`, such as the element
+    /// of an array of that type.
+    fn to_java(
+        &self,
+        field_type: &FieldType,
+        heap: &mut JavaHeap<'_>,
+    ) -> Result<Value, ConvertError> {
+        let value = Value::Reference(Some(*self));
+        if !heap.classes.accepts(heap.heap, field_type, value) {
+            return Err(ConvertError::Unrepresentable);
+        }
+        Ok(value)
+    }
+}
+
+impl FromJava for ObjectRef {
+    /// Converts a reference which is not `null`.
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        let Value::Reference(Some(object)) = value else {
+            return None;
+        };
+        heap.hold(object);
+        Some(object)
+    }
+}
+
+macro_rules! string_type {
+    ($($rust:ty),*) => {
+        $(
+            impl JavaType for $rust {
+                fn field_type() -> FieldType {
+                    FieldType::class(java_str!("java/lang/String"))
+                }
+            }
+
+            impl JavaReference for $rust {}
+
+            impl ToJava for $rust {
+                fn to_java(&self, _: &FieldType, heap: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+                    Ok(Value::Reference(Some(heap.alloc_string(self)?)))
+                }
+            }
+        )*
+    };
+}
+
+string_type!(&str, String);
+
+impl FromJava for String {
+    /// Converts a string which is not `null` and whose surrogates are paired.
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Reference(Some(string)) => heap.read_string(string),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! array_type {
+    ($($rust:ty),*) => {
+        $(
+            impl<T: JavaType> JavaType for $rust {
+                fn field_type() -> FieldType {
+                    FieldType::array(T::field_type())
+                }
+
+                fn accepts(field_type: &FieldType) -> bool {
+                    matches!(field_type, FieldType::Array(component) if T::accepts(component))
+                }
+            }
+
+            impl<T: JavaType> JavaReference for $rust {}
+
+            impl<T: ToJava> ToJava for $rust {
+                fn to_java(&self, field_type: &FieldType, heap: &mut JavaHeap<'_>) -> Result<Value, ConvertError> {
+                    let FieldType::Array(component) = field_type else {
+                        return Err(ConvertError::Unrepresentable);
+                    };
+                    let elements = (self.iter())
+                        .map(|element| element.to_java(component, heap))
+                        .collect::<Result<_, _>>()?;
+                    Ok(Value::Reference(Some(heap.alloc_array(field_type, elements)?)))
+                }
+            }
+        )*
+    };
+}
+
+array_type!(&[T], Vec<T>);
+
+impl<T: FromJava> FromJava for Vec<T> {
+    /// Converts an array which is not `null` and whose elements all convert.
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        let Value::Reference(Some(array)) = value else {
+            return None;
+        };
+        let elements = heap.elements(array, T::accepts)?.to_vec();
+        elements
+            .into_iter()
+            .map(|element| T::from_java(element, heap))
+            .collect()
+    }
+}
+
+impl<T: JavaReference> JavaType for Option<T> {
+    fn field_type() -> FieldType {
+        T::field_type()
+    }
+
+    fn accepts(field_type: &FieldType) -> bool {
+        T::accepts(field_type)
+    }
+}
+
+impl<T: JavaReference + ToJava> ToJava for Option<T> {
+    fn to_java(
+        &self,
+        field_type: &FieldType,
+        heap: &mut JavaHeap<'_>,
+    ) -> Result<Value, ConvertError> {
+        match self {
+            Some(value) => value.to_java(field_type, heap),
+            None => Ok(Value::Reference(None)),
+        }
+    }
+}
+
+impl<T: JavaReference + FromJava> FromJava for Option<T> {
+    fn from_java(value: Value, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        match value {
+            Value::Reference(None) => Some(None),
+            value => T::from_java(value, heap).map(Some),
+        }
+    }
+}
+
+/// The Rust types of the arguments of a method, as a tuple.
+pub trait ToJavaArgs {
+    /// Returns the parameter types which the descriptors derived from the
+    /// Rust types name.
+    fn parameter_types() -> Vec<FieldType>;
+
+    /// Returns whether arguments of the Rust types can be passed to
+    /// parameters of the given types.
+    fn accepts(parameters: &[FieldType]) -> bool;
+
+    /// Converts the arguments to the types of `parameters`, which the Rust
+    /// types accept, allocating the objects they need in `heap`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first argument which does not convert.
+    fn to_java(
+        &self,
+        parameters: &[FieldType],
+        heap: &mut JavaHeap<'_>,
+    ) -> Result<Vec<Value>, ConvertError>;
+}
+
+macro_rules! args_tuple {
+    ($($arg:ident),*) => {
+        impl<$($arg: ToJava),*> ToJavaArgs for ($($arg,)*) {
+            fn parameter_types() -> Vec<FieldType> {
+                vec![$($arg::field_type()),*]
+            }
+
+            #[allow(unused_variables, unused_mut)]
+            fn accepts(parameters: &[FieldType]) -> bool {
+                let mut parameters = parameters.iter();
+                $(
+                    if !parameters.next().is_some_and($arg::accepts) {
+                        return false;
+                    }
+                )*
+                parameters.next().is_none()
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn to_java(
+                &self,
+                parameters: &[FieldType],
+                heap: &mut JavaHeap<'_>,
+            ) -> Result<Vec<Value>, ConvertError> {
+                let ($($arg,)*) = self;
+                let mut parameters = parameters.iter();
+                Ok(vec![$(
+                    $arg.to_java(parameters.next().expect("an argument has a parameter"), heap)?
+                ),*])
+            }
+        }
+    };
+}
+
+args_tuple!();
+args_tuple!(A);
+args_tuple!(A, B);
+args_tuple!(A, B, C);
+args_tuple!(A, B, C, D);
+args_tuple!(A, B, C, D, E);
+args_tuple!(A, B, C, D, E, F);
+args_tuple!(A, B, C, D, E, F, G);
+args_tuple!(A, B, C, D, E, F, G, H);
+
+/// The Rust type of the result of a method, which is `()` for `void`.
+pub trait FromJavaResult: Sized {
+    /// Returns the return type which the descriptors derived from the Rust
+    /// type name, or `None` for `void`.
+    fn result_type() -> Option<FieldType>;
+
+    /// Returns whether a result of the given type, or of `void` if it is
+    /// `None`, converts to the Rust type.
+    fn accepts_result(result: Option<&FieldType>) -> bool;
+
+    /// Converts the result, whose objects are in `heap`, or returns `None` if
+    /// the Rust type cannot represent it.
+    fn from_result(result: Option<Value>, heap: &mut JavaHeap<'_>) -> Option<Self>;
+}
+
+impl FromJavaResult for () {
+    fn result_type() -> Option<FieldType> {
+        None
+    }
+
+    fn accepts_result(result: Option<&FieldType>) -> bool {
+        result.is_none()
+    }
+
+    fn from_result(result: Option<Value>, _: &mut JavaHeap<'_>) -> Option<Self> {
+        result.is_none().then_some(())
+    }
+}
+
+impl<T: FromJava> FromJavaResult for T {
+    fn result_type() -> Option<FieldType> {
+        Some(T::field_type())
+    }
+
+    fn accepts_result(result: Option<&FieldType>) -> bool {
+        result.is_some_and(T::accepts)
+    }
+
+    fn from_result(result: Option<Value>, heap: &mut JavaHeap<'_>) -> Option<Self> {
+        result.and_then(|result| T::from_java(result, heap))
+    }
+}
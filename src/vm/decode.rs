@@ -0,0 +1,665 @@
+use std::cell::{Cell, OnceCell};
+
+use super::class::{ArrayKind, Code, ConstantIdx, Instruction, ParseError};
+use super::gc::ReferenceMap;
+use super::ir::Profile;
+#[cfg(feature = "jit")]
+use super::jit::NativeCode;
+use super::native::NativeMethod;
+use super::value::ValueType;
+
+/// The code of a method decoded once into an array of instructions, so that
+/// the interpreter does not parse bytes while executing.
+///
+/// Instructions are addressed by their index in the array instead of their
+/// offset in the bytecode. Branch targets are resolved to indices, and the
+/// return addresses pushed by `jsr` are indices as well.
+///
+/// Instructions which refer to the constant pool are quickened the first time
+/// they are executed: they are replaced in place by a variant holding what
+/// the constant resolved to.
+#[derive(Debug)]
+pub struct DecodedCode {
+    ops: Box<[Cell<Op>]>,
+    table_switches: Box<[TableSwitch]>,
+    lookup_switches: Box<[LookupSwitch]>,
+    /// The indices of the `invokevirtual` and `invokeinterface` instructions,
+    /// in ascending order, and the inline cache of each.
+    call_sites: Box<[u32]>,
+    inline_caches: Box<[Cell<Option<InlineCache>>]>,
+    pub(super) max_stack: u16,
+    pub(super) max_locals: u16,
+    /// Whether the method is `synchronized`, so that a call enters the monitor
+    /// of the receiver, or of the class if the method is static.
+    pub(super) synchronized: bool,
+    pub(super) profile: Profile,
+    /// The slots holding references before each instruction, inferred when
+    /// the garbage collector first scans a frame running the method.
+    pub(super) references: OnceCell<ReferenceMap>,
+    #[cfg(feature = "jit")]
+    pub(super) native: NativeCode,
+}
+
+impl DecodedCode {
+    /// Decodes the bytecode of `code`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an instruction cannot be parsed, as described by
+    /// [`Bytecode::try_next`], and [`ParseError::InvalidBranchTarget`] if a
+    /// jump targets an offset which is not the start of an instruction.
+    ///
+    /// [`Bytecode::try_next`]: super::class::Bytecode::try_next
+    pub fn new(code: &Code) -> Result<Self, ParseError> {
+        let mut bytecode = code.bytecode();
+        let instructions: Vec<(u32, Instruction)> =
+            std::iter::from_fn(|| bytecode.try_next()).collect::<Result<_, _>>()?;
+        let index_of = |pc: u32, offset: i32| {
+            let target = pc.wrapping_add_signed(offset);
+            instructions
+                .binary_search_by_key(&target, |(pc, _)| *pc)
+                .map(|index| index as u32)
+                .or(Err(ParseError::InvalidBranchTarget))
+        };
+
+        let mut ops = Vec::with_capacity(instructions.len());
+        let mut table_switches = Vec::new();
+        let mut lookup_switches = Vec::new();
+        let mut call_sites = Vec::new();
+        for &(pc, instruction) in &instructions {
+            if let Instruction::invokevirtual(_) | Instruction::invokeinterface(..) = instruction {
+                call_sites.push(ops.len() as u32);
+            }
+            let branch = |offset: i16| index_of(pc, offset as i32);
+            let op = match instruction {
+                // Constant
+                Instruction::nop => Op::nop,
+                Instruction::aconst_null => Op::aconst_null,
+                Instruction::iconst(val) => Op::iconst(val),
+                Instruction::lconst(val) => Op::lconst(val),
+                Instruction::fconst(val) => Op::fconst(val),
+                Instruction::dconst(val) => Op::dconst(val),
+                Instruction::bipush(val) => Op::iconst(val as i32),
+                Instruction::sipush(val) => Op::iconst(val as i32),
+                Instruction::ldc(idx) => Op::ldc(idx),
+
+                // Load
+                Instruction::iload(idx) => Op::iload(idx),
+                Instruction::lload(idx) => Op::lload(idx),
+                Instruction::fload(idx) => Op::fload(idx),
+                Instruction::dload(idx) => Op::dload(idx),
+                Instruction::aload(idx) => Op::aload(idx),
+                Instruction::iaload => Op::iaload,
+                Instruction::laload => Op::laload,
+                Instruction::faload => Op::faload,
+                Instruction::daload => Op::daload,
+                Instruction::aaload => Op::aaload,
+                Instruction::baload => Op::baload,
+                Instruction::caload => Op::caload,
+                Instruction::saload => Op::saload,
+
+                // Store
+                Instruction::istore(idx) => Op::istore(idx),
+                Instruction::lstore(idx) => Op::lstore(idx),
+                Instruction::fstore(idx) => Op::fstore(idx),
+                Instruction::dstore(idx) => Op::dstore(idx),
+                Instruction::astore(idx) => Op::astore(idx),
+                Instruction::iastore => Op::iastore,
+                Instruction::lastore => Op::lastore,
+                Instruction::fastore => Op::fastore,
+                Instruction::dastore => Op::dastore,
+                Instruction::aastore => Op::aastore,
+                Instruction::bastore => Op::bastore,
+                Instruction::castore => Op::castore,
+                Instruction::sastore => Op::sastore,
+
+                // Stack
+                Instruction::pop => Op::pop,
+                Instruction::pop2 => Op::pop2,
+                Instruction::dup => Op::dup,
+                Instruction::dup_x1 => Op::dup_x1,
+                Instruction::dup_x2 => Op::dup_x2,
+                Instruction::dup2 => Op::dup2,
+                Instruction::dup2_x1 => Op::dup2_x1,
+                Instruction::dup2_x2 => Op::dup2_x2,
+                Instruction::swap => Op::swap,
+
+                // Math
+                Instruction::iadd => Op::iadd,
+                Instruction::ladd => Op::ladd,
+                Instruction::fadd => Op::fadd,
+                Instruction::dadd => Op::dadd,
+                Instruction::isub => Op::isub,
+                Instruction::lsub => Op::lsub,
+                Instruction::fsub => Op::fsub,
+                Instruction::dsub => Op::dsub,
+                Instruction::imul => Op::imul,
+                Instruction::lmul => Op::lmul,
+                Instruction::fmul => Op::fmul,
+                Instruction::dmul => Op::dmul,
+                Instruction::idiv => Op::idiv,
+                Instruction::ldiv => Op::ldiv,
+                Instruction::fdiv => Op::fdiv,
+                Instruction::ddiv => Op::ddiv,
+                Instruction::irem => Op::irem,
+                Instruction::lrem => Op::lrem,
+                Instruction::frem => Op::frem,
+                Instruction::drem => Op::drem,
+                Instruction::ineg => Op::ineg,
+                Instruction::lneg => Op::lneg,
+                Instruction::fneg => Op::fneg,
+                Instruction::dneg => Op::dneg,
+                Instruction::ishl => Op::ishl,
+                Instruction::lshl => Op::lshl,
+                Instruction::ishr => Op::ishr,
+                Instruction::lshr => Op::lshr,
+                Instruction::iushr => Op::iushr,
+                Instruction::lushr => Op::lushr,
+                Instruction::iand => Op::iand,
+                Instruction::land => Op::land,
+                Instruction::ior => Op::ior,
+                Instruction::lor => Op::lor,
+                Instruction::ixor => Op::ixor,
+                Instruction::lxor => Op::lxor,
+
+                // Conversion
+                Instruction::i2l => Op::i2l,
+                Instruction::i2f => Op::i2f,
+                Instruction::i2d => Op::i2d,
+                Instruction::l2i => Op::l2i,
+                Instruction::l2f => Op::l2f,
+                Instruction::l2d => Op::l2d,
+                Instruction::f2i => Op::f2i,
+                Instruction::f2l => Op::f2l,
+                Instruction::f2d => Op::f2d,
+                Instruction::d2i => Op::d2i,
+                Instruction::d2l => Op::d2l,
+                Instruction::d2f => Op::d2f,
+                Instruction::i2b => Op::i2b,
+                Instruction::i2c => Op::i2c,
+                Instruction::i2s => Op::i2s,
+
+                // Comparison
+                Instruction::lcmp => Op::lcmp,
+                Instruction::fcmp(greater_if_nan) => Op::fcmp(greater_if_nan),
+                Instruction::dcmp(greater_if_nan) => Op::dcmp(greater_if_nan),
+                Instruction::if_eq(offset) => Op::if_eq(branch(offset)?),
+                Instruction::if_ne(offset) => Op::if_ne(branch(offset)?),
+                Instruction::if_lt(offset) => Op::if_lt(branch(offset)?),
+                Instruction::if_ge(offset) => Op::if_ge(branch(offset)?),
+                Instruction::if_gt(offset) => Op::if_gt(branch(offset)?),
+                Instruction::if_le(offset) => Op::if_le(branch(offset)?),
+                Instruction::if_icmp_eq(offset) => Op::if_icmp_eq(branch(offset)?),
+                Instruction::if_icmp_ne(offset) => Op::if_icmp_ne(branch(offset)?),
+                Instruction::if_icmp_lt(offset) => Op::if_icmp_lt(branch(offset)?),
+                Instruction::if_icmp_ge(offset) => Op::if_icmp_ge(branch(offset)?),
+                Instruction::if_icmp_gt(offset) => Op::if_icmp_gt(branch(offset)?),
+                Instruction::if_icmp_le(offset) => Op::if_icmp_le(branch(offset)?),
+                Instruction::if_acmp_eq(offset) => Op::if_acmp_eq(branch(offset)?),
+                Instruction::if_acmp_ne(offset) => Op::if_acmp_ne(branch(offset)?),
+
+                // Control
+                Instruction::goto(offset) => Op::goto(index_of(pc, offset)?),
+                Instruction::jsr(offset) => Op::jsr(index_of(pc, offset)?),
+                Instruction::ret(idx) => Op::ret(idx),
+                Instruction::tableswitch(switch) => {
+                    let targets = switch
+                        .offsets()
+                        .map(|offset| index_of(pc, offset))
+                        .collect::<Result<_, _>>()?;
+                    table_switches.push(TableSwitch {
+                        low: switch.low(),
+                        default: index_of(pc, switch.default())?,
+                        targets,
+                    });
+                    Op::tableswitch(table_switches.len() as u32 - 1)
+                }
+                Instruction::lookupswitch(switch) => {
+                    let mut pairs = switch
+                        .pairs()
+                        .map(|(key, offset)| index_of(pc, offset).map(|target| (key, target)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    pairs.sort_unstable_by_key(|(key, _)| *key);
+                    lookup_switches.push(LookupSwitch {
+                        default: index_of(pc, switch.default())?,
+                        keys: pairs.iter().map(|(key, _)| *key).collect(),
+                        targets: pairs.iter().map(|(_, target)| *target).collect(),
+                    });
+                    Op::lookupswitch(lookup_switches.len() as u32 - 1)
+                }
+                Instruction::ireturn => Op::ireturn,
+                Instruction::lreturn => Op::lreturn,
+                Instruction::freturn => Op::freturn,
+                Instruction::dreturn => Op::dreturn,
+                Instruction::areturn => Op::areturn,
+                Instruction::ret_void => Op::ret_void,
+
+                // Reference
+                Instruction::getstatic(idx) => Op::getstatic(idx),
+                Instruction::putstatic(idx) => Op::putstatic(idx),
+                Instruction::getfield(idx) => Op::getfield(idx),
+                Instruction::putfield(idx) => Op::putfield(idx),
+                Instruction::invokevirtual(idx) => Op::invokevirtual(idx),
+                Instruction::invokespecial(idx) => Op::invokespecial(idx),
+                Instruction::invokestatic(idx) => Op::invokestatic(idx),
+                Instruction::invokeinterface(idx, count) => Op::invokeinterface(idx, count),
+                Instruction::invokedynamic(idx) => Op::invokedynamic(idx),
+                Instruction::new(idx) => Op::new(idx),
+                Instruction::newarray(kind) => Op::newarray(kind),
+                Instruction::anewarray(idx) => Op::anewarray(idx),
+                Instruction::arraylength => Op::arraylength,
+                Instruction::athrow => Op::athrow,
+                Instruction::checkcast(idx) => Op::checkcast(idx),
+                Instruction::instanceof(idx) => Op::instanceof(idx),
+                Instruction::monitorenter => Op::monitorenter,
+                Instruction::monitorexit => Op::monitorexit,
+
+                // Extended
+                Instruction::multianewarray(idx, dimensions) => Op::multianewarray(idx, dimensions),
+                Instruction::ifnonnull(offset) => Op::ifnonnull(branch(offset)?),
+                Instruction::ifnull(offset) => Op::ifnull(branch(offset)?),
+                Instruction::iinc(idx, constant) => Op::iinc(idx, constant),
+            };
+            ops.push(Cell::new(op));
+        }
+
+        Ok(Self {
+            ops: ops.into_boxed_slice(),
+            table_switches: table_switches.into_boxed_slice(),
+            lookup_switches: lookup_switches.into_boxed_slice(),
+            inline_caches: vec![Cell::new(None); call_sites.len()].into_boxed_slice(),
+            call_sites: call_sites.into_boxed_slice(),
+            max_stack: code.max_stack,
+            max_locals: code.max_locals,
+            synchronized: false,
+            profile: Profile::default(),
+            references: OnceCell::new(),
+            #[cfg(feature = "jit")]
+            native: NativeCode::default(),
+        })
+    }
+
+    /// Returns the number of instructions.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns `true` if there are no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Returns the instruction at `index`, as it currently is after any
+    /// quickening.
+    pub fn get(&self, index: usize) -> Option<Op> {
+        self.ops.get(index).map(Cell::get)
+    }
+
+    /// Replaces the instruction at `index` with its quickened form.
+    pub(super) fn quicken(&self, index: usize, op: Op) {
+        self.ops[index].set(op);
+    }
+
+    pub fn table_switch(&self, index: u32) -> &TableSwitch {
+        &self.table_switches[index as usize]
+    }
+
+    pub fn lookup_switch(&self, index: u32) -> &LookupSwitch {
+        &self.lookup_switches[index as usize]
+    }
+
+    /// Returns the index of the inline cache of the call instruction at
+    /// `index`.
+    pub(super) fn call_site(&self, index: usize) -> u16 {
+        self.call_sites.binary_search(&(index as u32)).unwrap() as u16
+    }
+
+    pub(super) fn inline_cache(&self, cache: u16) -> &Cell<Option<InlineCache>> {
+        &self.inline_caches[cache as usize]
+    }
+}
+
+/// An instruction as executed by the interpreter. This mirrors
+/// [`Instruction`], except that `bipush` and `sipush` become `iconst`, jumps
+/// hold the index of their target and switches hold the index of their table
+/// in the [`DecodedCode`].
+///
+/// An `ldc` is quickened into the matching constant instruction, while the
+/// variants under `Quick` replace instructions whose constant has been
+/// resolved.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    // Constant
+    nop,
+    aconst_null,
+    iconst(i32),
+    lconst(i64),
+    fconst(f32),
+    dconst(f64),
+    ldc(ConstantIdx),
+
+    // Load
+    iload(u16),
+    lload(u16),
+    fload(u16),
+    dload(u16),
+    aload(u16),
+    iaload,
+    laload,
+    faload,
+    daload,
+    aaload,
+    baload,
+    caload,
+    saload,
+
+    // Store
+    istore(u16),
+    lstore(u16),
+    fstore(u16),
+    dstore(u16),
+    astore(u16),
+    iastore,
+    lastore,
+    fastore,
+    dastore,
+    aastore,
+    bastore,
+    castore,
+    sastore,
+
+    // Stack
+    pop,
+    pop2,
+    dup,
+    dup_x1,
+    dup_x2,
+    dup2,
+    dup2_x1,
+    dup2_x2,
+    swap,
+
+    // Math
+    iadd,
+    ladd,
+    fadd,
+    dadd,
+    isub,
+    lsub,
+    fsub,
+    dsub,
+    imul,
+    lmul,
+    fmul,
+    dmul,
+    idiv,
+    ldiv,
+    fdiv,
+    ddiv,
+    irem,
+    lrem,
+    frem,
+    drem,
+    ineg,
+    lneg,
+    fneg,
+    dneg,
+    ishl,
+    lshl,
+    ishr,
+    lshr,
+    iushr,
+    lushr,
+    iand,
+    land,
+    ior,
+    lor,
+    ixor,
+    lxor,
+
+    // Conversion
+    i2l,
+    i2f,
+    i2d,
+    l2i,
+    l2f,
+    l2d,
+    f2i,
+    f2l,
+    f2d,
+    d2i,
+    d2l,
+    d2f,
+    i2b,
+    i2c,
+    i2s,
+
+    // Comparison
+    lcmp,
+    fcmp(bool),
+    dcmp(bool),
+    if_eq(u32),
+    if_ne(u32),
+    if_lt(u32),
+    if_ge(u32),
+    if_gt(u32),
+    if_le(u32),
+    if_icmp_eq(u32),
+    if_icmp_ne(u32),
+    if_icmp_lt(u32),
+    if_icmp_ge(u32),
+    if_icmp_gt(u32),
+    if_icmp_le(u32),
+    if_acmp_eq(u32),
+    if_acmp_ne(u32),
+
+    // Control
+    goto(u32),
+    jsr(u32),
+    ret(u16),
+    tableswitch(u32),
+    lookupswitch(u32),
+    ireturn,
+    lreturn,
+    freturn,
+    dreturn,
+    areturn,
+    ret_void,
+
+    // Reference
+    getstatic(ConstantIdx),
+    putstatic(ConstantIdx),
+    getfield(ConstantIdx),
+    putfield(ConstantIdx),
+    invokevirtual(ConstantIdx),
+    invokespecial(ConstantIdx),
+    invokestatic(ConstantIdx),
+    invokeinterface(ConstantIdx, u8),
+    invokedynamic(ConstantIdx),
+    new(ConstantIdx),
+    newarray(ArrayKind),
+    anewarray(ConstantIdx),
+    arraylength,
+    athrow,
+    checkcast(ConstantIdx),
+    instanceof(ConstantIdx),
+    monitorenter,
+    monitorexit,
+
+    // Extended
+    multianewarray(ConstantIdx, u8),
+    ifnonnull(u32),
+    ifnull(u32),
+    iinc(u16, i16),
+
+    // Quick
+    /// An `ldc` of a class, holding the index of the class in the
+    /// [`ClassManager`], which pushes its `java.lang.Class` object.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    ldc_class_quick(u32),
+    getstatic_quick(FieldId),
+    /// A `putstatic` holding the type of the field, which tells the
+    /// interpreter how many slots to pop.
+    putstatic_quick(FieldId, ValueType),
+    /// A `getfield` holding the index of the field in the object.
+    getfield_quick(u32),
+    /// A `putfield` holding the index of the field in the object and its type.
+    putfield_quick(u32, ValueType),
+    invokevirtual_quick(VirtualCall),
+    /// A call which needs no dispatch, that is an `invokespecial` or an
+    /// `invokevirtual` of a private or final method.
+    invokespecial_quick(MethodId),
+    invokestatic_quick(MethodId),
+    invokeinterface_quick(InterfaceCall),
+    /// A call of a native method implemented by the virtual machine which
+    /// needs no dispatch, whose arguments are on the operand stack.
+    invokenative(NativeMethod),
+    /// A `new` holding the index of the class in the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    new_quick(u32),
+    /// A `newarray` or `anewarray` holding the index of the array class in
+    /// the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    newarray_quick(u32),
+    /// A `checkcast` holding the index of the class in the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    checkcast_quick(u32),
+    /// An `instanceof` holding the index of the class in the
+    /// [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    instanceof_quick(u32),
+}
+
+/// A resolved `invokevirtual`, dispatched through the virtual method table of
+/// the class of the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualCall {
+    /// The index of the method in the virtual method table.
+    pub(super) index: u32,
+    /// The number of stack slots taken by the arguments, including the
+    /// receiver.
+    pub(super) slots: u16,
+    pub(super) cache: u16,
+}
+
+/// A resolved `invokeinterface`, dispatched through the interface method table
+/// of the class of the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceCall {
+    /// The index of the interface in the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    pub(super) interface: u32,
+    /// The index of the method in [`Class::methods`] of the interface.
+    ///
+    /// [`Class::methods`]: super::class::Class::methods
+    pub(super) method: u16,
+    /// The number of stack slots taken by the arguments, including the
+    /// receiver.
+    pub(super) slots: u16,
+    pub(super) cache: u16,
+}
+
+/// The method a call site last dispatched to, along with the class of the
+/// receiver it was selected for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InlineCache {
+    pub(super) class: u32,
+    pub(super) target: MethodId,
+}
+
+/// A resolved method, identified by the index of its class in the
+/// [`ClassManager`] and its index in [`Class::methods`].
+///
+/// [`ClassManager`]: super::ClassManager
+/// [`Class::methods`]: super::class::Class::methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MethodId {
+    pub(super) class: u32,
+    pub(super) method: u32,
+}
+
+/// A resolved static field, identified by the index of its class in the
+/// [`ClassManager`] and its index in [`Class::fields`].
+///
+/// [`ClassManager`]: super::ClassManager
+/// [`Class::fields`]: super::class::Class::fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldId {
+    pub(super) class: u32,
+    pub(super) field: u32,
+}
+
+#[derive(Debug)]
+pub struct TableSwitch {
+    low: i32,
+    default: u32,
+    targets: Box<[u32]>,
+}
+
+impl TableSwitch {
+    /// Returns the index of the instruction jumped to for `key`.
+    pub fn target(&self, key: i32) -> u32 {
+        let position = (key as i64 - self.low as i64) as usize;
+        self.targets.get(position).copied().unwrap_or(self.default)
+    }
+
+    /// Returns the index of every instruction the switch may jump to,
+    /// including the default.
+    pub fn targets(&self) -> impl Iterator<Item = u32> + '_ {
+        self.targets.iter().copied().chain([self.default])
+    }
+
+    /// Returns each key which does not jump to the default, with the index of
+    /// the instruction jumped to.
+    pub fn cases(&self) -> impl Iterator<Item = (i32, u32)> + '_ {
+        let keys = (0..).map(|i| self.low.wrapping_add(i));
+        keys.zip(self.targets.iter().copied())
+    }
+
+    /// Returns the index of the instruction jumped to for keys out of range.
+    pub fn default_target(&self) -> u32 {
+        self.default
+    }
+}
+
+/// A `lookupswitch` whose keys are sorted, so that they can be binary
+/// searched.
+#[derive(Debug)]
+pub struct LookupSwitch {
+    default: u32,
+    keys: Box<[i32]>,
+    targets: Box<[u32]>,
+}
+
+impl LookupSwitch {
+    /// Returns the index of the instruction jumped to for `key`.
+    pub fn target(&self, key: i32) -> u32 {
+        match self.keys.binary_search(&key) {
+            Ok(position) => self.targets[position],
+            Err(_) => self.default,
+        }
+    }
+
+    /// Returns the index of every instruction the switch may jump to,
+    /// including the default.
+    pub fn targets(&self) -> impl Iterator<Item = u32> + '_ {
+        self.targets.iter().copied().chain([self.default])
+    }
+
+    /// Returns each key with the index of the instruction jumped to.
+    pub fn cases(&self) -> impl Iterator<Item = (i32, u32)> + '_ {
+        self.keys.iter().copied().zip(self.targets.iter().copied())
+    }
+
+    /// Returns the index of the instruction jumped to for keys not listed.
+    pub fn default_target(&self) -> u32 {
+        self.default
+    }
+}
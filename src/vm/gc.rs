@@ -0,0 +1,349 @@
+//! The roots of the garbage collector: the references held by static fields,
+//! by the locals and operand stacks of frames, by the monitors frames entered,
+//! by the threads and by the host.
+//!
+//! Slots do not record the type of their values in release builds, so the
+//! slots of a frame which hold references are found from the types inferred
+//! for the bytecode of its method before the instruction the frame stopped
+//! at. The tiers above the bytecode interpreter leave the slots of a frame as
+//! the interpreter would wherever the collector may run, which is when an
+//! object is allocated and while a frame calls a method.
+//!
+//! The finalizers of unreachable objects and the enqueueing of cleared
+//! references run on the finalizer thread, so that they never run while a
+//! thread of the program holds monitors they need. The objects stay pending
+//! in the heap, where they are roots, until the finalizer thread has returned
+//! from the calls before and pushes the calls for them.
+
+use super::analysis::{solve, BitSet, ControlFlowGraph, StackTypes};
+use super::call_frame::{get_reference, set_reference, CallFrame, ThreadStack};
+use super::class::VerificationType;
+use super::decode::MethodId;
+use super::heap::{Heap, ObjectRef};
+use super::monitor::{FrameMonitor, LockTarget};
+use super::scheduler::{JavaThread, Scheduler};
+use super::value::Value;
+use super::{ClassManager, LinkageError};
+
+/// The slots of a frame which hold references before each instruction of a
+/// method.
+#[derive(Debug)]
+pub struct ReferenceMap {
+    /// Whether slot `i` holds a reference before the instruction at `pc`, at
+    /// index `pc * width + i`, where the operand stack starts after the
+    /// locals.
+    bits: BitSet,
+    width: usize,
+}
+
+impl ReferenceMap {
+    /// Infers the types of the slots of the method of `frame`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the control flow of the method is invalid, which cannot be
+    /// the case for a method which has run.
+    fn new(classes: &ClassManager, frame: &CallFrame) -> Self {
+        let class = &classes.classes[frame.class].class;
+        let method = &class.methods()[frame.method as usize];
+        let code = method.bytecode().expect("method which has run has code");
+        let locals = frame.code.max_locals as usize;
+        let width = locals + frame.code.max_stack as usize;
+
+        let offsets: Vec<u32> = code.bytecode().map(|(offset, _)| offset).collect();
+        let analysis = StackTypes::new(class, method);
+        let graph =
+            ControlFlowGraph::new(code).expect("method which has run has valid control flow");
+        let results = solve(&analysis, &graph, code);
+
+        let mut bits = BitSet::new(frame.code.len() * width);
+        results.visit(&analysis, &graph, code, |offset, _, before, _| {
+            let Some(types) = before.frame() else {
+                return;
+            };
+            let start = offsets.binary_search(&offset).unwrap() * width;
+            let slots = (types.locals().iter().take(locals).enumerate())
+                .chain((types.stack().iter().enumerate()).map(|(i, t)| (locals + i, t)));
+            for (slot, verification_type) in slots {
+                if is_reference(verification_type) {
+                    bits.insert(start + slot);
+                }
+            }
+        });
+        Self { bits, width }
+    }
+
+    /// Returns whether slot `slot` holds a reference before the instruction at
+    /// `pc`.
+    fn contains(&self, pc: usize, slot: usize) -> bool {
+        self.bits.contains(pc * self.width + slot)
+    }
+}
+
+fn is_reference(verification_type: &VerificationType) -> bool {
+    matches!(
+        verification_type,
+        VerificationType::Null
+            | VerificationType::UninitializedThis
+            | VerificationType::Uninitialized(_)
+            | VerificationType::Object(_)
+    )
+}
+
+/// Why the heap is collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cause {
+    /// An object did not fit, which only needs a young collection if the heap
+    /// is generational.
+    Allocation,
+    /// The program called `System.gc()`, which collects the whole heap.
+    SystemGc,
+    /// The host pinned a reference to the nursery, which a young collection
+    /// moves out of it.
+    Pin,
+}
+
+/// Where a root was found, which is updated if the object it references is
+/// moved.
+enum Root {
+    Static {
+        class: usize,
+        field: usize,
+    },
+    Mirror(usize),
+    Slot {
+        thread: usize,
+        frame: usize,
+        slot: usize,
+    },
+    Monitor {
+        thread: usize,
+        frame: usize,
+    },
+    Thread(usize),
+    Pinned(usize),
+    #[cfg(feature = "jni")]
+    Global(usize),
+    #[cfg(feature = "jni")]
+    Local(usize),
+}
+
+/// Frees the objects which cannot be reached from the static fields or the
+/// `java.lang.Class` objects of the loaded classes, from the references
+/// `pinned` by the host, from the local and global references of native
+/// methods or from the threads and their frames, each of which stopped before
+/// the instruction at its `pc` with `depth` slots on its operand stack, and
+/// moves the references to the objects which are copied. Logs the collection
+/// to the standard error if `verbose` is set.
+///
+/// Then lets the finalizer thread run if there are objects to finalize or
+/// references to enqueue.
+pub(super) fn collect(
+    classes: &ClassManager,
+    heap: &mut Heap,
+    scheduler: &mut Scheduler,
+    pinned: &mut [ObjectRef],
+    cause: Cause,
+    verbose: bool,
+) {
+    // A young collection only needs the roots referencing the nursery.
+    let young = cause != Cause::SystemGc && heap.can_collect_young();
+    let is_root = |object: ObjectRef| !young || object.is_young();
+
+    let mut roots: Vec<ObjectRef> = Vec::new();
+    let mut locations = Vec::new();
+    for (index, class) in classes.classes.iter().enumerate() {
+        if let Some(mirror) = class.mirror.get().filter(|&mirror| is_root(mirror)) {
+            roots.push(mirror);
+            locations.push(Root::Mirror(index));
+        }
+        if young && !heap.has_young_statics(index) {
+            continue;
+        }
+        for (field, value) in class.statics.iter().enumerate() {
+            if let Some(Value::Reference(Some(object))) = value.get() {
+                if is_root(object) {
+                    roots.push(object);
+                    locations.push(Root::Static {
+                        class: index,
+                        field,
+                    });
+                }
+            }
+        }
+    }
+    for (index, &object) in pinned.iter().enumerate() {
+        if is_root(object) {
+            roots.push(object);
+            locations.push(Root::Pinned(index));
+        }
+    }
+    #[cfg(feature = "jni")]
+    for (index, &object) in classes.natives.globals.borrow().iter().enumerate() {
+        if let Some(object) = object.filter(|&object| is_root(object)) {
+            roots.push(object);
+            locations.push(Root::Global(index));
+        }
+    }
+    #[cfg(feature = "jni")]
+    for (index, &object) in classes.natives.locals.borrow().iter().enumerate() {
+        if let Some(object) = object.filter(|&object| is_root(object)) {
+            roots.push(object);
+            locations.push(Root::Local(index));
+        }
+    }
+    for (index, thread) in scheduler.threads.iter().enumerate() {
+        if let Some(object) = thread.object.filter(|&object| is_root(object)) {
+            roots.push(object);
+            locations.push(Root::Thread(index));
+        }
+        for (frame_index, frame) in thread.call_stack.iter().enumerate() {
+            if let FrameMonitor::Entered(LockTarget::Object(object)) = frame.monitor {
+                if is_root(object) {
+                    roots.push(object);
+                    locations.push(Root::Monitor {
+                        thread: index,
+                        frame: frame_index,
+                    });
+                }
+            }
+            let map = frame
+                .code
+                .references
+                .get_or_init(|| ReferenceMap::new(classes, frame));
+            let slots = thread.stack.slots(frame);
+            for slot in 0..slots.len() {
+                if !map.contains(frame.pc, slot) {
+                    continue;
+                }
+                if let Some(object) = get_reference(slots, slot).filter(|&object| is_root(object)) {
+                    roots.push(object);
+                    locations.push(Root::Slot {
+                        thread: index,
+                        frame: frame_index,
+                        slot,
+                    });
+                }
+            }
+        }
+    }
+
+    let collection = if young {
+        heap.collect_young(&mut roots)
+    } else {
+        heap.collect(&mut roots, classes)
+    };
+    for (location, object) in locations.into_iter().zip(roots) {
+        match location {
+            Root::Static { class, field } => {
+                let statics = &classes.classes[class].statics;
+                statics[field].set(Some(Value::Reference(Some(object))));
+            }
+            Root::Mirror(class) => classes.classes[class].mirror.set(Some(object)),
+            Root::Slot {
+                thread,
+                frame,
+                slot,
+            } => {
+                let thread = &mut scheduler.threads[thread];
+                let slots = thread.stack.slots_mut(&thread.call_stack[frame]);
+                set_reference(slots, slot, Some(object));
+            }
+            Root::Monitor { thread, frame } => {
+                let frame = &mut scheduler.threads[thread].call_stack[frame];
+                frame.monitor = FrameMonitor::Entered(LockTarget::Object(object));
+            }
+            Root::Thread(thread) => scheduler.threads[thread].object = Some(object),
+            Root::Pinned(index) => pinned[index] = object,
+            #[cfg(feature = "jni")]
+            Root::Global(index) => classes.natives.globals.borrow_mut()[index] = Some(object),
+            #[cfg(feature = "jni")]
+            Root::Local(index) => classes.natives.locals.borrow_mut()[index] = Some(object),
+        }
+    }
+
+    if verbose {
+        let stats = heap.stats();
+        let number = stats.young_collections + stats.full_collections - 1;
+        let kind = if collection.young { "Young" } else { "Full" };
+        let cause = match cause {
+            Cause::Allocation => "Allocation Failure",
+            Cause::SystemGc => "System.gc()",
+            Cause::Pin => "Pinned Reference",
+        };
+        eprintln!(
+            "[gc] GC({number}) Pause {kind} ({cause}) {}K->{}K({}K) {:.3}ms, {}K promoted",
+            collection.used_before >> 10,
+            collection.used_after >> 10,
+            heap.capacity() >> 10,
+            collection.pause.as_secs_f64() * 1000.0,
+            collection.promoted >> 10,
+        );
+    }
+
+    if heap.has_pending() {
+        scheduler.wake_finalizer();
+    }
+}
+
+/// Pushes on the finalizer thread `thread`, which has no frame left, the calls
+/// which finalize the objects found unreachable and enqueue the references
+/// cleared since it last did, and returns whether there were any.
+///
+/// # Errors
+///
+/// Returns a [`LinkageError`] if a method to call has no code.
+pub(super) fn push_pending<'a>(
+    classes: &'a ClassManager,
+    heap: &mut Heap,
+    thread: &mut JavaThread<'a>,
+) -> Result<bool, LinkageError> {
+    // The calls run in the order the objects were found, so the first one is
+    // pushed last.
+    let pending = heap.take_pending();
+    let (thread, call_stack) = (&mut thread.stack, &mut thread.call_stack);
+    for &reference in pending.enqueue.iter().rev() {
+        let class = heap.get(reference).class;
+        if let Some(method) = classes.linked(class).enqueue {
+            push_call(classes, thread, call_stack, method, reference)?;
+        }
+    }
+    for &object in pending.finalize.iter().rev() {
+        let class = heap.get(object).class;
+        let method = classes
+            .linked(class)
+            .finalizer
+            .expect("only objects with a finalizer are registered");
+        push_call(classes, thread, call_stack, method, object)?;
+    }
+    Ok(!call_stack.is_empty())
+}
+
+/// Pushes a frame calling `method` on `receiver` with no other arguments.
+fn push_call<'a>(
+    classes: &'a ClassManager,
+    thread: &mut ThreadStack,
+    call_stack: &mut Vec<CallFrame<'a>>,
+    method: MethodId,
+    receiver: ObjectRef,
+) -> Result<(), LinkageError> {
+    let code = classes.method_code(method)?;
+    let base = call_stack.last().map_or(0, CallFrame::top);
+    set_reference(thread.registers(base, code), 0, Some(receiver));
+    call_stack.push(CallFrame::new(method, code, base));
+    Ok(())
+}
+
+/// Logs the statistics of the collections of `heap` to the standard error.
+pub(super) fn log_stats(heap: &Heap) {
+    let stats = heap.stats();
+    eprintln!(
+        "[gc] {} young and {} full collections, {:.3}ms total pause, {:.3}ms max, {}K promoted, {}K freed",
+        stats.young_collections,
+        stats.full_collections,
+        stats.total_pause.as_secs_f64() * 1000.0,
+        stats.max_pause.as_secs_f64() * 1000.0,
+        stats.bytes_promoted >> 10,
+        stats.bytes_freed >> 10,
+    );
+}
@@ -0,0 +1,862 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::mem;
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+
+use super::analysis::BitSet;
+use super::monitor::LockWord;
+use super::value::Value;
+
+/// The bit set in references to objects in the nursery.
+const YOUNG: u32 = 1 << 31;
+
+/// The number of objects of the old generation covered by each card of the
+/// card table, as a power of two.
+const CARD_SHIFT: u32 = 6;
+
+/// A reference to an object on the [`Heap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectRef(NonZeroU32);
+
+impl ObjectRef {
+    fn old(index: usize) -> Self {
+        Self(NonZeroU32::new(index as u32 + 1).unwrap())
+    }
+
+    fn young(index: usize) -> Self {
+        Self(NonZeroU32::new(index as u32 | YOUNG).unwrap())
+    }
+
+    /// Returns whether the object is in the nursery of a generational heap.
+    pub fn is_young(self) -> bool {
+        self.0.get() & YOUNG != 0
+    }
+
+    /// Returns the index of an object of the old generation.
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+
+    /// Returns the index of an object in the nursery.
+    fn young_index(self) -> usize {
+        (self.0.get() & !YOUNG) as usize
+    }
+
+    /// Returns the bits of a reference in an untagged stack slot, which are
+    /// zero for `null`.
+    #[cfg(any(not(debug_assertions), feature = "jit"))]
+    pub(super) fn to_bits(object: Option<ObjectRef>) -> u32 {
+        object.map_or(0, |object| object.0.get())
+    }
+
+    #[cfg(any(not(debug_assertions), feature = "jit"))]
+    pub(super) fn from_bits(bits: u32) -> Option<ObjectRef> {
+        NonZeroU32::new(bits).map(ObjectRef)
+    }
+}
+
+#[derive(Debug)]
+pub struct Object {
+    /// The index of the class of the object in the [`ClassManager`].
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    pub(super) class: u32,
+    /// The state of the monitor of the object.
+    pub(super) lock: LockWord,
+    /// The instance fields of the object, laid out with the fields of
+    /// superclasses first.
+    pub(super) fields: Box<[Value]>,
+}
+
+impl Object {
+    pub fn fields(&self) -> &[Value] {
+        &self.fields
+    }
+
+    /// Returns the number of bytes the object takes on the heap.
+    fn size(&self) -> usize {
+        object_size(self.fields.len())
+    }
+}
+
+fn object_size(fields: usize) -> usize {
+    mem::size_of::<Object>() + fields * mem::size_of::<Value>()
+}
+
+fn is_young(value: Value) -> bool {
+    matches!(value, Value::Reference(Some(object)) if object.is_young())
+}
+
+/// Why an object could not be allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// The object does not fit until the heap is collected.
+    Full,
+    /// The object does not fit even though the heap was collected since the
+    /// last allocation.
+    OutOfMemory,
+}
+
+/// The garbage collector freeing the objects of a [`Heap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collector {
+    /// A single space of objects, collected by marking and sweeping.
+    #[default]
+    MarkSweep,
+    /// A nursery taking `nursery_size` bytes of the heap, from which the
+    /// objects surviving a collection are copied to an old generation
+    /// collected by marking and sweeping.
+    Generational { nursery_size: usize },
+}
+
+/// How strongly a `java.lang.ref.Reference` holds its referent, which decides
+/// when the collector clears it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    /// Cleared once the referent is only softly reachable and a collection
+    /// could not make room for an allocation otherwise.
+    Soft,
+    /// Cleared once the referent is only weakly reachable.
+    Weak,
+    /// Cleared once the referent is unreachable and has been finalized.
+    Phantom,
+}
+
+/// The kind of the references of a class and the index of their `referent`
+/// field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferenceType {
+    pub kind: ReferenceKind,
+    pub referent: u32,
+}
+
+/// Tells the collector which classes extend `java.lang.ref.Reference`.
+pub trait ReferenceClasses {
+    /// Returns the type of the references of the class at index `class` in
+    /// the [`ClassManager`], or `None` if it does not extend `Reference`.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    fn reference_type(&self, class: u32) -> Option<ReferenceType>;
+}
+
+/// The objects found by the collector which the program must act on.
+#[derive(Debug, Default)]
+pub struct Pending {
+    /// Unreachable objects registered with [`Heap::register_finalizer`],
+    /// which are kept until their finalizer has run.
+    pub finalize: Vec<ObjectRef>,
+    /// References whose referent was cleared, to be enqueued.
+    pub enqueue: Vec<ObjectRef>,
+}
+
+/// A collection of the heap.
+#[derive(Debug, Clone, Copy)]
+pub struct Collection {
+    /// Whether only the nursery was collected.
+    pub young: bool,
+    /// The number of bytes taken by objects before and after the collection.
+    pub used_before: usize,
+    pub used_after: usize,
+    /// The number of bytes copied from the nursery to the old generation.
+    pub promoted: usize,
+    pub pause: Duration,
+}
+
+/// Statistics on the collections of a heap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcStats {
+    pub young_collections: u32,
+    pub full_collections: u32,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+    /// The number of bytes copied from the nursery to the old generation.
+    pub bytes_promoted: u64,
+    pub bytes_freed: u64,
+}
+
+/// The objects allocated since the last collection of a generational heap.
+#[derive(Debug)]
+struct Nursery {
+    /// The objects, in the order they were allocated.
+    objects: Vec<Object>,
+    /// The number of bytes taken by the objects, which is bumped by every
+    /// allocation.
+    used: usize,
+    capacity: usize,
+    /// Whether the objects of the old generation covered by each card may
+    /// reference objects in the nursery.
+    cards: Vec<bool>,
+    /// Whether the static fields of the class at each index in the
+    /// [`ClassManager`] may reference objects in the nursery.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    static_cards: Vec<bool>,
+}
+
+/// Marks the card at `index`, growing the table to cover it.
+fn mark_card(cards: &mut Vec<bool>, index: usize) {
+    if index >= cards.len() {
+        cards.resize(index + 1, false);
+    }
+    cards[index] = true;
+}
+
+/// The objects allocated by a program, which are freed by a mark-sweep
+/// collector once they can no longer be reached from the roots.
+///
+/// With the [`Collector::Generational`] collector, objects are allocated in a
+/// nursery, and the objects in it which survive a collection are copied to
+/// the old generation. A young collection only traces the nursery, from the
+/// roots and from the objects of the old generation which a card table
+/// records as having been written a reference to the nursery.
+#[derive(Debug)]
+pub struct Heap {
+    /// The objects of the old generation, or `None` for entries freed by a
+    /// collection, which are reused by later allocations.
+    objects: Vec<Option<Object>>,
+    /// The indices of the freed entries of `objects`.
+    free: Vec<u32>,
+    /// The number of bytes taken by the objects of the old generation.
+    used: usize,
+    capacity: usize,
+    nursery: Option<Nursery>,
+    /// Whether the heap was collected since the last allocation.
+    collected: bool,
+    /// Whether an object did not fit in the old generation since the last
+    /// full collection, which a young collection cannot make room for.
+    needs_full: bool,
+    /// Whether the next full collection clears soft references, and whether
+    /// the last one did.
+    clear_soft: bool,
+    soft_cleared: bool,
+    /// The objects whose finalizer runs once they become unreachable.
+    finalizable: Vec<ObjectRef>,
+    /// The values of the host attached to objects, which are dropped along
+    /// with them.
+    payloads: HashMap<ObjectRef, Box<dyn Any>>,
+    pending: Pending,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    /// The capacity of a heap created by [`Heap::new`], in bytes.
+    pub const DEFAULT_CAPACITY: usize = 256 << 20;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a heap which holds at most `capacity` bytes of objects.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_collector(capacity, Collector::MarkSweep)
+    }
+
+    /// Creates a heap which holds at most `capacity` bytes of objects,
+    /// including those in the nursery of a generational collector.
+    pub fn with_collector(capacity: usize, collector: Collector) -> Self {
+        let nursery = match collector {
+            Collector::MarkSweep => None,
+            Collector::Generational { nursery_size } => Some(Nursery {
+                objects: Vec::new(),
+                used: 0,
+                capacity: nursery_size.min(capacity),
+                cards: Vec::new(),
+                static_cards: Vec::new(),
+            }),
+        };
+        Self {
+            objects: Vec::new(),
+            free: Vec::new(),
+            used: 0,
+            capacity,
+            nursery,
+            collected: false,
+            needs_full: false,
+            clear_soft: false,
+            soft_cleared: false,
+            finalizable: Vec::new(),
+            payloads: HashMap::new(),
+            pending: Pending::default(),
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn collector(&self) -> Collector {
+        match &self.nursery {
+            Some(nursery) => Collector::Generational {
+                nursery_size: nursery.capacity,
+            },
+            None => Collector::MarkSweep,
+        }
+    }
+
+    /// Returns the number of live objects.
+    pub fn len(&self) -> usize {
+        let young = self
+            .nursery
+            .as_ref()
+            .map_or(0, |nursery| nursery.objects.len());
+        self.objects.len() - self.free.len() + young
+    }
+
+    /// Returns `true` if there are no live objects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes taken by the live objects.
+    pub fn used(&self) -> usize {
+        self.used + self.nursery.as_ref().map_or(0, |nursery| nursery.used)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    /// Returns the number of bytes the old generation may take.
+    fn old_capacity(&self) -> usize {
+        self.capacity - self.nursery.as_ref().map_or(0, |nursery| nursery.capacity)
+    }
+
+    /// Returns the error for an allocation which does not fit. Soft
+    /// references are only cleared once a full collection could not make
+    /// room without clearing them.
+    fn full(&mut self) -> AllocError {
+        if !self.collected {
+            return AllocError::Full;
+        }
+        if !self.soft_cleared {
+            self.clear_soft = true;
+            self.needs_full = true;
+            return AllocError::Full;
+        }
+        AllocError::OutOfMemory
+    }
+
+    /// Allocates an object of the class at index `class` in the
+    /// [`ClassManager`]. Objects which fit go to the nursery, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AllocError::Full`] if the object does not fit, in which case
+    /// the heap should be collected before allocating again, and
+    /// [`AllocError::OutOfMemory`] if it still does not fit after a full
+    /// collection.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    pub fn alloc(&mut self, class: u32, fields: Box<[Value]>) -> Result<ObjectRef, AllocError> {
+        let size = object_size(fields.len());
+        let old_full = self.used > self.old_capacity();
+        if let Some(nursery) = &mut self.nursery {
+            if size <= nursery.capacity {
+                if old_full || nursery.used + size > nursery.capacity {
+                    return Err(self.full());
+                }
+                nursery.used += size;
+                nursery.objects.push(Object {
+                    class,
+                    lock: LockWord::UNLOCKED,
+                    fields,
+                });
+                self.collected = false;
+                return Ok(ObjectRef::young(nursery.objects.len() - 1));
+            }
+        }
+        if self.used + size > self.old_capacity() {
+            self.needs_full = true;
+            return Err(self.full());
+        }
+        self.collected = false;
+        Ok(self.insert(Object {
+            class,
+            lock: LockWord::UNLOCKED,
+            fields,
+        }))
+    }
+
+    /// Allocates an array of the class at index `class` in the
+    /// [`ClassManager`] with `length` elements holding `value`, which are the
+    /// fields of the object.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`Heap::alloc`], and [`AllocError::OutOfMemory`]
+    /// before creating the elements if the array is larger than the heap.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    pub fn alloc_array(
+        &mut self,
+        class: u32,
+        length: usize,
+        value: Value,
+    ) -> Result<ObjectRef, AllocError> {
+        if object_size(length) > self.capacity {
+            return Err(AllocError::OutOfMemory);
+        }
+        self.alloc(class, vec![value; length].into_boxed_slice())
+    }
+
+    /// Adds an object to the old generation.
+    fn insert(&mut self, object: Object) -> ObjectRef {
+        self.used += object.size();
+        let object = Some(object);
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = object;
+                ObjectRef::old(index as usize)
+            }
+            None => {
+                self.objects.push(object);
+                ObjectRef::old(self.objects.len() - 1)
+            }
+        }
+    }
+
+    /// Returns an object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object was freed, which cannot happen to an object
+    /// reachable by the program.
+    pub fn get(&self, object: ObjectRef) -> &Object {
+        if object.is_young() {
+            return &self.nursery.as_ref().expect("heap has a nursery").objects
+                [object.young_index()];
+        }
+        self.objects[object.index()]
+            .as_ref()
+            .expect("object was freed")
+    }
+
+    /// Returns an object to modify. References must be written to its fields
+    /// with [`Heap::set_field`] instead, which maintains the card table.
+    pub fn get_mut(&mut self, object: ObjectRef) -> &mut Object {
+        if object.is_young() {
+            return &mut self.nursery.as_mut().expect("heap has a nursery").objects
+                [object.young_index()];
+        }
+        self.objects[object.index()]
+            .as_mut()
+            .expect("object was freed")
+    }
+
+    /// Writes a field of an object, marking the card of an object of the old
+    /// generation which is written a reference to the nursery.
+    #[inline]
+    pub fn set_field(&mut self, object: ObjectRef, index: usize, value: Value) {
+        if is_young(value) && !object.is_young() {
+            let nursery = self.nursery.as_mut().expect("heap has a nursery");
+            mark_card(&mut nursery.cards, object.index() >> CARD_SHIFT);
+        }
+        self.get_mut(object).fields[index] = value;
+    }
+
+    /// Records that `value` was written to a static field of the class at
+    /// index `class` in the [`ClassManager`], which makes the static fields of
+    /// the class roots of the next young collection if it is a reference to
+    /// the nursery.
+    ///
+    /// [`ClassManager`]: super::ClassManager
+    #[inline]
+    pub fn record_static(&mut self, class: u32, value: Value) {
+        if is_young(value) {
+            let nursery = self.nursery.as_mut().expect("heap has a nursery");
+            mark_card(&mut nursery.static_cards, class as usize);
+        }
+    }
+
+    /// Runs the finalizer of `object` once it becomes unreachable, by
+    /// returning it from [`Heap::take_pending`] after the collection which
+    /// finds it unreachable. The finalizer runs once, even if the object is
+    /// reachable again after it.
+    pub fn register_finalizer(&mut self, object: ObjectRef) {
+        self.finalizable.push(object);
+    }
+
+    /// Attaches a value of the host to `object`, dropping the one it had. The
+    /// value is dropped once the object is freed.
+    pub fn set_payload(&mut self, object: ObjectRef, payload: Box<dyn Any>) {
+        self.payloads.insert(object, payload);
+    }
+
+    /// Returns the value of the host attached to `object`, if any.
+    pub fn payload(&self, object: ObjectRef) -> Option<&dyn Any> {
+        self.payloads.get(&object).map(Box::as_ref)
+    }
+
+    pub fn payload_mut(&mut self, object: ObjectRef) -> Option<&mut dyn Any> {
+        self.payloads.get_mut(&object).map(Box::as_mut)
+    }
+
+    /// Returns the objects found by the collections since the last call,
+    /// which are no longer roots once taken.
+    pub fn take_pending(&mut self) -> Pending {
+        mem::take(&mut self.pending)
+    }
+
+    /// Returns whether the collections found objects which
+    /// [`Heap::take_pending`] has not returned yet.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.finalize.is_empty() || !self.pending.enqueue.is_empty()
+    }
+
+    /// Returns whether the static fields of the class at index `class` may
+    /// reference objects in the nursery.
+    pub fn has_young_statics(&self, class: usize) -> bool {
+        self.nursery
+            .as_ref()
+            .is_some_and(|nursery| nursery.static_cards.get(class).copied().unwrap_or(false))
+    }
+
+    /// Returns whether a young collection can make room for the allocation
+    /// which failed, which requires the old generation to have room for every
+    /// object in the nursery.
+    pub fn can_collect_young(&self) -> bool {
+        self.nursery.as_ref().is_some_and(|nursery| {
+            !self.needs_full && self.used + nursery.used <= self.old_capacity()
+        })
+    }
+
+    /// Copies the objects in the nursery which can be reached from `roots` to
+    /// the old generation, updating `roots` to their new location, and
+    /// empties the nursery. The roots must include every reference to the
+    /// nursery outside the heap, such as the static fields of the classes
+    /// for which [`Heap::has_young_statics`] returns `true`.
+    ///
+    /// References are not cleared, but objects registered for finalization
+    /// which cannot be reached are kept for their finalizer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the heap has no nursery.
+    pub fn collect_young(&mut self, roots: &mut [ObjectRef]) -> Collection {
+        assert!(self.nursery.is_some(), "heap has no nursery");
+        let start = Instant::now();
+        let used_before = self.used();
+        let promoted = self.evacuate(roots);
+        self.record(true, used_before, promoted, start)
+    }
+
+    /// Frees every object which cannot be reached from `roots` through the
+    /// fields of objects, then copies the surviving objects in the nursery,
+    /// if any, to the old generation, updating `roots` to their new location.
+    ///
+    /// The referents of references, as told by `classes`, are not traced.
+    /// Weak references, and soft references if memory is short, whose
+    /// referent cannot be reached otherwise are cleared. Unreachable objects
+    /// registered for finalization are then kept along with the objects they
+    /// reach, and phantom references whose referent is still unreachable are
+    /// cleared. Cleared references are pending enqueueing.
+    pub fn collect(
+        &mut self,
+        roots: &mut [ObjectRef],
+        classes: &impl ReferenceClasses,
+    ) -> Collection {
+        let start = Instant::now();
+        let used_before = self.used();
+        let clear_soft = mem::take(&mut self.clear_soft);
+        let young = self
+            .nursery
+            .as_ref()
+            .map_or(0, |nursery| nursery.objects.len());
+        let mut marker = Marker {
+            marked: BitSet::new(self.objects.len()),
+            young_marked: BitSet::new(young),
+            worklist: Vec::new(),
+            discovered: Vec::new(),
+        };
+        let pending = self.pending.finalize.iter().chain(&self.pending.enqueue);
+        for &root in roots.iter().chain(pending) {
+            marker.mark(root);
+        }
+        marker.trace(self, classes, false);
+
+        if !clear_soft {
+            let mut index = 0;
+            while let Some(&(reference, reference_type)) = marker.discovered.get(index) {
+                index += 1;
+                if reference_type.kind == ReferenceKind::Soft {
+                    if let Some(referent) = self.referent(reference, reference_type) {
+                        marker.mark(referent);
+                        marker.trace(self, classes, false);
+                    }
+                }
+            }
+        }
+        self.clear_references(&marker, |kind| kind != ReferenceKind::Phantom);
+
+        let finalizable = mem::take(&mut self.finalizable);
+        for object in finalizable {
+            if marker.is_marked(object) {
+                self.finalizable.push(object);
+            } else {
+                marker.mark(object);
+                self.pending.finalize.push(object);
+            }
+        }
+        // References only reachable from objects being finalized keep their
+        // referent, as they were not found when references were cleared.
+        marker.trace(self, classes, true);
+        self.clear_references(&marker, |kind| kind == ReferenceKind::Phantom);
+
+        for (index, entry) in self.objects.iter_mut().enumerate() {
+            if entry.is_some() && !marker.marked.contains(index) {
+                self.used -= entry.take().unwrap().size();
+                self.free.push(index as u32);
+            }
+        }
+        self.payloads
+            .retain(|&object, _| object.is_young() || marker.is_marked(object));
+        // Unreachable objects in the nursery are not copied, as only
+        // reachable objects reference them.
+        let promoted = self.evacuate(roots);
+        self.collected = true;
+        self.needs_full = false;
+        self.soft_cleared = clear_soft;
+        self.record(false, used_before, promoted, start)
+    }
+
+    fn referent(&self, reference: ObjectRef, reference_type: ReferenceType) -> Option<ObjectRef> {
+        match self.get(reference).fields[reference_type.referent as usize] {
+            Value::Reference(referent) => referent,
+            _ => None,
+        }
+    }
+
+    /// Clears the references of the kinds selected by `kinds` whose referent
+    /// is not marked, and makes them pending enqueueing.
+    fn clear_references(&mut self, marker: &Marker, kinds: impl Fn(ReferenceKind) -> bool) {
+        for &(reference, reference_type) in &marker.discovered {
+            if !kinds(reference_type.kind) {
+                continue;
+            }
+            let Some(referent) = self.referent(reference, reference_type) else {
+                continue;
+            };
+            if !marker.is_marked(referent) {
+                self.get_mut(reference).fields[reference_type.referent as usize] =
+                    Value::Reference(None);
+                self.pending.enqueue.push(reference);
+            }
+        }
+    }
+
+    /// Copies the objects in the nursery which can be reached from `roots` or
+    /// from the objects of the old generation on marked cards to the old
+    /// generation, and returns the number of bytes copied.
+    fn evacuate(&mut self, roots: &mut [ObjectRef]) -> usize {
+        let Some(nursery) = &mut self.nursery else {
+            return 0;
+        };
+        let mut cards = mem::take(&mut nursery.cards);
+        let mut evacuation = Evacuation {
+            forwarded: vec![None; nursery.objects.len()],
+            young: mem::take(&mut nursery.objects),
+            copied: Vec::new(),
+            bytes: 0,
+        };
+        nursery.static_cards.fill(false);
+        nursery.used = 0;
+
+        let mut pending = mem::take(&mut self.pending);
+        let pending_roots = pending.finalize.iter_mut().chain(&mut pending.enqueue);
+        for root in roots.iter_mut().chain(pending_roots) {
+            if root.is_young() {
+                *root = evacuation.forward(self, *root);
+            }
+        }
+        self.pending = pending;
+        for (card, _) in cards.iter().enumerate().filter(|(_, &marked)| marked) {
+            let start = card << CARD_SHIFT;
+            let end = (start + (1 << CARD_SHIFT)).min(self.objects.len());
+            for index in start..end {
+                self.forward_fields(index, &mut evacuation);
+            }
+        }
+        while let Some(object) = evacuation.copied.pop() {
+            self.forward_fields(object.index(), &mut evacuation);
+        }
+
+        // Objects registered for finalization which were not copied are
+        // unreachable, and are copied for their finalizer.
+        let finalizable = mem::take(&mut self.finalizable);
+        for object in finalizable {
+            if !object.is_young() {
+                self.finalizable.push(object);
+                continue;
+            }
+            let reachable = evacuation.forwarded[object.young_index()].is_some();
+            let copy = evacuation.forward(self, object);
+            if reachable {
+                self.finalizable.push(copy);
+            } else {
+                self.pending.finalize.push(copy);
+            }
+        }
+        while let Some(object) = evacuation.copied.pop() {
+            self.forward_fields(object.index(), &mut evacuation);
+        }
+
+        // The payloads of the objects which were not copied are dropped.
+        let young: Vec<ObjectRef> = (self.payloads.keys().copied())
+            .filter(|object| object.is_young())
+            .collect();
+        for object in young {
+            let payload = self.payloads.remove(&object).unwrap();
+            if let Some(copy) = evacuation.forwarded[object.young_index()] {
+                self.payloads.insert(copy, payload);
+            }
+        }
+
+        let nursery = self.nursery.as_mut().unwrap();
+        cards.fill(false);
+        nursery.cards = cards;
+        evacuation.young.clear();
+        nursery.objects = evacuation.young;
+        evacuation.bytes
+    }
+
+    /// Updates the references to the nursery in the fields of the object at
+    /// `index` in the old generation, copying the objects they reference.
+    fn forward_fields(&mut self, index: usize, evacuation: &mut Evacuation) {
+        let len = self.objects[index]
+            .as_ref()
+            .map_or(0, |object| object.fields.len());
+        for field in 0..len {
+            let value = self.objects[index].as_ref().unwrap().fields[field];
+            if let Value::Reference(Some(object)) = value {
+                if object.is_young() {
+                    let copy = evacuation.forward(self, object);
+                    self.objects[index].as_mut().unwrap().fields[field] =
+                        Value::Reference(Some(copy));
+                }
+            }
+        }
+    }
+
+    fn record(
+        &mut self,
+        young: bool,
+        used_before: usize,
+        promoted: usize,
+        start: Instant,
+    ) -> Collection {
+        let collection = Collection {
+            young,
+            used_before,
+            used_after: self.used(),
+            promoted,
+            pause: start.elapsed(),
+        };
+        let stats = &mut self.stats;
+        if young {
+            stats.young_collections += 1;
+        } else {
+            stats.full_collections += 1;
+        }
+        stats.total_pause += collection.pause;
+        stats.max_pause = stats.max_pause.max(collection.pause);
+        stats.bytes_promoted += promoted as u64;
+        stats.bytes_freed += (used_before - collection.used_after) as u64;
+        collection
+    }
+}
+
+/// The state of the marking of the reachable objects of a full collection.
+struct Marker {
+    marked: BitSet,
+    young_marked: BitSet,
+    /// The marked objects whose fields have not been traced yet.
+    worklist: Vec<ObjectRef>,
+    /// The marked references, whose referent was not traced.
+    discovered: Vec<(ObjectRef, ReferenceType)>,
+}
+
+impl Marker {
+    fn is_marked(&self, object: ObjectRef) -> bool {
+        if object.is_young() {
+            self.young_marked.contains(object.young_index())
+        } else {
+            self.marked.contains(object.index())
+        }
+    }
+
+    fn mark(&mut self, object: ObjectRef) {
+        let (marked, index) = if object.is_young() {
+            (&mut self.young_marked, object.young_index())
+        } else {
+            (&mut self.marked, object.index())
+        };
+        if !marked.contains(index) {
+            marked.insert(index);
+            self.worklist.push(object);
+        }
+    }
+
+    /// Marks every object reachable from the objects on the worklist. The
+    /// referents of references are only traced if `referents` is set.
+    fn trace(&mut self, heap: &Heap, classes: &impl ReferenceClasses, referents: bool) {
+        while let Some(marked) = self.worklist.pop() {
+            let object = heap.get(marked);
+            let mut skipped = None;
+            if let Some(reference_type) = classes.reference_type(object.class) {
+                if !referents {
+                    self.discovered.push((marked, reference_type));
+                    skipped = Some(reference_type.referent as usize);
+                }
+            }
+            for (index, field) in object.fields.iter().enumerate() {
+                if let Value::Reference(Some(object)) = *field {
+                    if skipped != Some(index) {
+                        self.mark(object);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The state of a copy of the reachable objects in the nursery to the old
+/// generation.
+struct Evacuation {
+    /// The objects in the nursery, where those already copied are left empty.
+    young: Vec<Object>,
+    /// The copy of each object in the nursery, if it was copied.
+    forwarded: Vec<Option<ObjectRef>>,
+    /// The copies whose fields have not been updated yet.
+    copied: Vec<ObjectRef>,
+    bytes: usize,
+}
+
+impl Evacuation {
+    /// Returns the copy of an object in the nursery, copying it first if it
+    /// has not been copied yet.
+    fn forward(&mut self, heap: &mut Heap, object: ObjectRef) -> ObjectRef {
+        let index = object.young_index();
+        if let Some(copy) = self.forwarded[index] {
+            return copy;
+        }
+        let empty = Object {
+            class: 0,
+            lock: LockWord::UNLOCKED,
+            fields: Box::default(),
+        };
+        let object = mem::replace(&mut self.young[index], empty);
+        self.bytes += object.size();
+        let copy = heap.insert(object);
+        self.forwarded[index] = Some(copy);
+        self.copied.push(copy);
+        copy
+    }
+}
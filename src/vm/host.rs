@@ -0,0 +1,229 @@
+//! Classes defined by the host, whose methods are Rust closures.
+//!
+//! A host class is a final class extending `java.lang.Object` whose methods,
+//! constructors included, are native methods the closures implement. Java
+//! code compiled against a class of the same name creates its instances with
+//! `new`, calls their methods and passes them around as any other object. A
+//! constructor usually attaches a payload to the new object, a Rust value
+//! which the other methods use and which is dropped once the collector frees
+//! the object.
+//!
+//! The closures run on the thread which called them without giving up its
+//! time slice, and cannot call Java methods in turn.
+
+use std::any::Any;
+
+use super::call_frame::Stack;
+use super::class::{Class, ClassBuilder, ClassFlags, MethodDescriptor, MethodFlags};
+use super::convert::{FromJava, JavaHeap};
+use super::heap::{Heap, ObjectRef};
+use super::value::{Value, ValueType};
+use super::{ClassManager, ExecuteError, JavaException};
+use crate::java_str;
+use crate::string::{from_utf8, JavaStr};
+
+/// A class whose methods are implemented by the host, which is defined with
+/// [`ClassManager::define`].
+///
+/// [`ClassManager::define`]: super::ClassManager::define
+pub struct HostClass {
+    builder: ClassBuilder,
+    /// The closure of each method, in the order they were added.
+    methods: Vec<HostMethod>,
+}
+
+impl HostClass {
+    /// Creates a public final class named `name` which extends
+    /// `java/lang/Object` and has no methods.
+    pub fn new(name: &JavaStr) -> Self {
+        let mut builder = ClassBuilder::new(name);
+        builder.flags(ClassFlags::PUBLIC | ClassFlags::FINAL | ClassFlags::SUPER);
+        Self {
+            builder,
+            methods: Vec::new(),
+        }
+    }
+
+    /// Adds a native method implemented by `f`, which returns the result of
+    /// the method, or `None` if it returns `void`. The method is static if
+    /// `flags` say so.
+    ///
+    /// A call throws a `NullPointerException` if `f` returns `None` for a
+    /// method with a result, so that `f` can use `?` on arguments which are
+    /// null.
+    pub fn method(
+        &mut self,
+        flags: MethodFlags,
+        name: &JavaStr,
+        descriptor: &MethodDescriptor,
+        f: impl Fn(&mut HostCall<'_>) -> Option<Value> + 'static,
+    ) -> &mut Self {
+        self.builder
+            .abstract_method(flags | MethodFlags::NATIVE, name, descriptor);
+        self.methods.push(HostMethod(Box::new(f)));
+        self
+    }
+
+    /// Adds a public constructor implemented by `f`, which finds the new
+    /// object as the receiver of the call.
+    pub fn constructor(
+        &mut self,
+        descriptor: &MethodDescriptor,
+        f: impl Fn(&mut HostCall<'_>) + 'static,
+    ) -> &mut Self {
+        self.method(
+            MethodFlags::PUBLIC,
+            java_str!("<init>"),
+            descriptor,
+            move |call| {
+                f(call);
+                None
+            },
+        )
+    }
+
+    pub(super) fn build(self) -> (Class, Vec<HostMethod>) {
+        (self.builder.build(), self.methods)
+    }
+}
+
+impl std::fmt::Debug for HostClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostClass")
+            .field("methods", &self.methods.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The closure implementing a method of a [`HostClass`].
+pub(super) struct HostMethod(Box<HostFn>);
+
+type HostFn = dyn Fn(&mut HostCall<'_>) -> Option<Value>;
+
+impl std::fmt::Debug for HostMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HostMethod")
+    }
+}
+
+impl HostMethod {
+    /// Runs the method, whose receiver unless it `is_static` and whose
+    /// arguments of the types in `descriptor` are on top of `stack`, and
+    /// pushes its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JavaException::NullPointer`] if the receiver is null, and
+    /// the errors of [`HostMethod::call`].
+    pub(super) fn invoke(
+        &self,
+        descriptor: &MethodDescriptor,
+        is_static: bool,
+        stack: &mut Stack,
+        classes: &ClassManager,
+        heap: &mut Heap,
+    ) -> Result<(), ExecuteError> {
+        let mut args: Vec<Value> = (descriptor.args().iter().rev())
+            .map(|parameter| stack.pop(ValueType::from(parameter)))
+            .collect();
+        args.reverse();
+        let this = if is_static {
+            None
+        } else {
+            Some(stack.pop_reference().ok_or(JavaException::NullPointer)?)
+        };
+        if let Some(result) = self.call(descriptor, classes, heap, this, &args)? {
+            stack.push(result);
+        }
+        Ok(())
+    }
+
+    /// Runs the method with `this` as its receiver and `args` as its
+    /// arguments, and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JavaException::NullPointer`] if the closure returns no value
+    /// for a method with a result, as it does when an argument is null and
+    /// [`HostCall::arg`] cannot convert it, and
+    /// [`ExecuteError::HostResultMismatch`] if it returns a value of another
+    /// type than the descriptor.
+    pub(super) fn call(
+        &self,
+        descriptor: &MethodDescriptor,
+        classes: &ClassManager,
+        heap: &mut Heap,
+        this: Option<ObjectRef>,
+        args: &[Value],
+    ) -> Result<Option<Value>, ExecuteError> {
+        let result = (self.0)(&mut HostCall {
+            classes,
+            heap,
+            this,
+            args,
+        });
+        let expected = descriptor.result().map(ValueType::from);
+        match result {
+            _ if result.map(|value| value.value_type()) == expected => Ok(result),
+            None => Err(JavaException::NullPointer.into()),
+            Some(_) => Err(ExecuteError::HostResultMismatch {
+                descriptor: from_utf8(&descriptor.to_string()).into_owned(),
+            }),
+        }
+    }
+}
+
+/// A call of a method of a [`HostClass`], which gives its closure the
+/// receiver, the arguments and the heap.
+#[derive(Debug)]
+pub struct HostCall<'c> {
+    classes: &'c ClassManager,
+    heap: &'c mut Heap,
+    this: Option<ObjectRef>,
+    args: &'c [Value],
+}
+
+impl HostCall<'_> {
+    /// Returns the receiver of the call, or `None` for a static method.
+    pub fn this(&self) -> Option<ObjectRef> {
+        self.this
+    }
+
+    pub fn args(&self) -> &[Value] {
+        self.args
+    }
+
+    /// Converts the argument at `index`, such as a string or an array copied
+    /// out of the heap, or returns `None` if the Rust type cannot represent
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`.
+    pub fn arg<T: FromJava>(&mut self, index: usize) -> Option<T> {
+        T::from_java(
+            self.args[index],
+            &mut JavaHeap::new(self.classes, self.heap),
+        )
+    }
+
+    pub fn heap(&mut self) -> &mut Heap {
+        self.heap
+    }
+
+    /// Returns the payload of the receiver, if it has one of type `T`.
+    pub fn payload<T: Any>(&mut self) -> Option<&mut T> {
+        let this = self.this?;
+        self.heap.payload_mut(this)?.downcast_mut()
+    }
+
+    /// Attaches `payload` to the receiver, dropping the one it had.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method is static.
+    pub fn set_payload<T: Any>(&mut self, payload: T) {
+        let this = self.this.expect("method has a receiver");
+        self.heap.set_payload(this, Box::new(payload));
+    }
+}
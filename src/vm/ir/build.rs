@@ -0,0 +1,603 @@
+use crate::vm::class::{Entry, Instruction};
+use crate::vm::decode::{DecodedCode, MethodId, Op};
+use crate::vm::value::Value;
+use crate::vm::{ClassManager, Resolved};
+
+use super::{
+    register_count, Call, CallKind, CompareOp, Cond, Deopt, DeoptReason, FloatOp, Function, Inst,
+    IntOp, LongOp, Reg, Safepoint, UnaryOp,
+};
+
+/// A function being compiled, whose jumps still hold the index of the bytecode
+/// instruction they jump to.
+#[derive(Debug)]
+pub(super) struct Draft {
+    pub(super) insts: Vec<Inst>,
+    /// The index of the bytecode instruction each instruction was translated
+    /// from.
+    pub(super) origins: Vec<u32>,
+    /// Whether each bytecode instruction starts a block.
+    pub(super) leaders: Vec<bool>,
+    pub(super) locals: u16,
+    pub(super) registers: usize,
+}
+
+impl Draft {
+    /// Returns the index of the first instruction translated from the
+    /// bytecode instruction at `pc` or from one after it, for each `pc`.
+    pub(super) fn starts(&self) -> Vec<u32> {
+        let mut starts = vec![self.insts.len() as u32; self.leaders.len() + 1];
+        for (index, &origin) in self.origins.iter().enumerate().rev() {
+            starts[origin as usize] = index as u32;
+        }
+        for pc in (0..self.leaders.len()).rev() {
+            starts[pc] = starts[pc].min(starts[pc + 1]);
+        }
+        starts
+    }
+
+    /// Removes the `Nop`s and makes jumps hold the index of the instruction
+    /// they jump to.
+    pub(super) fn finish(self) -> Function {
+        let starts = self.starts();
+        let mut new_index = Vec::with_capacity(self.insts.len() + 1);
+        let mut count = 0;
+        for inst in &self.insts {
+            new_index.push(count);
+            if !matches!(inst, Inst::Nop) {
+                count += 1;
+            }
+        }
+        new_index.push(count);
+
+        let insts = self
+            .insts
+            .into_iter()
+            .filter(|inst| !matches!(inst, Inst::Nop))
+            .map(|mut inst| {
+                if let Some(target) = inst.target_mut() {
+                    *target = new_index[starts[*target as usize] as usize];
+                }
+                inst
+            })
+            .collect();
+        let entries = self
+            .leaders
+            .iter()
+            .enumerate()
+            .map(|(pc, &leader)| match leader {
+                true => new_index[starts[pc] as usize],
+                false => u32::MAX,
+            })
+            .collect();
+        Function {
+            insts,
+            entries,
+            locals: self.locals,
+        }
+    }
+}
+
+/// Translates the code of a method into the intermediate representation, or
+/// returns `None` if it uses subroutines, which are not supported.
+///
+/// Instructions which have not been quickened have never run, so they are
+/// assumed not to run and are translated into a [`Deopt`], as are those the
+/// optimizing tier does not implement. With `speculate`, virtual calls are
+/// assumed to only see the receiver class held by their inline cache.
+pub(super) fn build(
+    classes: &ClassManager,
+    method: MethodId,
+    code: &DecodedCode,
+    speculate: bool,
+) -> Option<Draft> {
+    let class = &classes.classes[method.class as usize].class;
+    let bytecode = class.methods()[method.method as usize].bytecode()?;
+    let instructions: Vec<Instruction> = bytecode.bytecode().map(|(_, inst)| inst).collect();
+
+    let mut builder = Builder {
+        classes,
+        class: method.class as usize,
+        code,
+        speculate,
+        locals: code.max_locals,
+        insts: Vec::new(),
+        targets: Vec::new(),
+        falls_through: true,
+    };
+
+    // The depth of the operand stack before each instruction, found by
+    // following the jumps from the start of the method. Instructions which
+    // are only reached through exception handlers are not translated.
+    let mut depths: Vec<Option<u16>> = vec![None; code.len()];
+    let mut translated: Vec<Vec<Inst>> = vec![Vec::new(); code.len()];
+    let mut leaders = vec![false; code.len()];
+    // The targets of backward jumps, which start with a poll.
+    let mut loops = vec![false; code.len()];
+    let mut worklist = vec![(0, 0)];
+    leaders[0] = true;
+    while let Some((pc, depth)) = worklist.pop() {
+        match depths[pc] {
+            Some(known) if known == depth => continue,
+            Some(_) => return None,
+            None => depths[pc] = Some(depth),
+        }
+        let op = code.get(pc)?;
+        let depth = builder.translate(pc as u32, op, &instructions[pc], depth)?;
+        translated[pc] = std::mem::take(&mut builder.insts);
+
+        for &target in &builder.targets {
+            leaders[target as usize] = true;
+            loops[target as usize] |= target as usize <= pc;
+            worklist.push((target as usize, depth));
+        }
+        let ends_block = !builder.targets.is_empty() || !builder.falls_through;
+        if pc + 1 < code.len() {
+            leaders[pc + 1] |= ends_block;
+            if builder.falls_through {
+                worklist.push((pc + 1, depth));
+            }
+        }
+    }
+
+    // A frame can only enter the function at an instruction which was
+    // translated, and not after one which leaves it for good.
+    for (leader, depth) in leaders.iter_mut().zip(&depths) {
+        *leader &= depth.is_some();
+    }
+    let mut draft = Draft {
+        insts: Vec::new(),
+        origins: Vec::new(),
+        leaders,
+        locals: code.max_locals,
+        registers: register_count(code),
+    };
+    for (pc, mut insts) in translated.into_iter().enumerate() {
+        if let (true, Some(depth)) = (loops[pc], depths[pc]) {
+            let safepoint = Safepoint {
+                pc: pc as u32,
+                depth,
+            };
+            insts.insert(0, Inst::Poll(safepoint));
+        }
+        draft
+            .origins
+            .extend(std::iter::repeat_n(pc as u32, insts.len()));
+        draft.insts.extend(insts);
+    }
+    Some(draft)
+}
+
+struct Builder<'a> {
+    classes: &'a ClassManager,
+    class: usize,
+    code: &'a DecodedCode,
+    speculate: bool,
+    locals: u16,
+    /// The translation of the current instruction.
+    insts: Vec<Inst>,
+    /// The instructions the current instruction jumps to.
+    targets: Vec<u32>,
+    /// Whether execution continues with the next instruction.
+    falls_through: bool,
+}
+
+impl Builder<'_> {
+    /// Returns the register of the stack slot at `depth`.
+    fn slot(&self, depth: u16) -> Reg {
+        self.locals + depth
+    }
+
+    /// Translates `op` at `pc`, which sees `depth` slots on the operand stack,
+    /// and returns the depth after it.
+    fn translate(&mut self, pc: u32, op: Op, instruction: &Instruction, depth: u16) -> Option<u16> {
+        self.targets.clear();
+        self.falls_through = true;
+        let d = depth;
+        let locals = self.locals;
+        let s = |n: u16| locals + n;
+
+        let depth = match op {
+            // Constant
+            Op::nop => d,
+            Op::aconst_null => self.constant(s(d), Value::Reference(None), d),
+            Op::iconst(val) => self.constant(s(d), Value::Int(val), d),
+            Op::lconst(val) => self.constant(s(d), Value::Long(val), d),
+            Op::fconst(val) => self.constant(s(d), Value::Float(val), d),
+            Op::dconst(val) => self.constant(s(d), Value::Double(val), d),
+
+            // Load and store
+            Op::iload(idx) | Op::fload(idx) | Op::aload(idx) => {
+                self.insts.push(Inst::Move(s(d), idx));
+                d + 1
+            }
+            Op::lload(idx) | Op::dload(idx) => {
+                self.insts.push(Inst::Move(s(d), idx));
+                self.insts.push(Inst::Move(s(d + 1), idx + 1));
+                d + 2
+            }
+            Op::istore(idx) | Op::fstore(idx) | Op::astore(idx) => {
+                self.insts.push(Inst::Move(idx, s(d - 1)));
+                d - 1
+            }
+            Op::lstore(idx) | Op::dstore(idx) => {
+                self.insts.push(Inst::Move(idx, s(d - 2)));
+                self.insts.push(Inst::Move(idx + 1, s(d - 1)));
+                d - 2
+            }
+            Op::iinc(idx, constant) => {
+                self.insts
+                    .push(Inst::IntImm(IntOp::Add, idx, idx, constant as i32));
+                d
+            }
+
+            // Stack
+            Op::pop => d - 1,
+            Op::pop2 => d - 2,
+            Op::dup => self.dup(d, 1, 0),
+            Op::dup_x1 => self.dup(d, 1, 1),
+            Op::dup_x2 => self.dup(d, 1, 2),
+            Op::dup2 => self.dup(d, 2, 0),
+            Op::dup2_x1 => self.dup(d, 2, 1),
+            Op::dup2_x2 => self.dup(d, 2, 2),
+            Op::swap => {
+                // The scratch registers follow the deepest stack slot.
+                let scratch = self.slot(self.code.max_stack);
+                self.insts.push(Inst::Move(scratch, s(d - 1)));
+                self.insts.push(Inst::Move(s(d - 1), s(d - 2)));
+                self.insts.push(Inst::Move(s(d - 2), scratch));
+                d
+            }
+
+            // Math
+            Op::iadd => self.int(IntOp::Add, d),
+            Op::isub => self.int(IntOp::Sub, d),
+            Op::imul => self.int(IntOp::Mul, d),
+            Op::idiv => self.int(IntOp::Div, d),
+            Op::irem => self.int(IntOp::Rem, d),
+            Op::ishl => self.int(IntOp::Shl, d),
+            Op::ishr => self.int(IntOp::Shr, d),
+            Op::iushr => self.int(IntOp::Ushr, d),
+            Op::iand => self.int(IntOp::And, d),
+            Op::ior => self.int(IntOp::Or, d),
+            Op::ixor => self.int(IntOp::Xor, d),
+            Op::ladd => self.long(LongOp::Add, d),
+            Op::lsub => self.long(LongOp::Sub, d),
+            Op::lmul => self.long(LongOp::Mul, d),
+            Op::ldiv => self.long(LongOp::Div, d),
+            Op::lrem => self.long(LongOp::Rem, d),
+            Op::lshl => self.long(LongOp::Shl, d),
+            Op::lshr => self.long(LongOp::Shr, d),
+            Op::lushr => self.long(LongOp::Ushr, d),
+            Op::land => self.long(LongOp::And, d),
+            Op::lor => self.long(LongOp::Or, d),
+            Op::lxor => self.long(LongOp::Xor, d),
+            Op::fadd => self.float(FloatOp::Add, d),
+            Op::fsub => self.float(FloatOp::Sub, d),
+            Op::fmul => self.float(FloatOp::Mul, d),
+            Op::fdiv => self.float(FloatOp::Div, d),
+            Op::frem => self.float(FloatOp::Rem, d),
+            Op::dadd => self.double(FloatOp::Add, d),
+            Op::dsub => self.double(FloatOp::Sub, d),
+            Op::dmul => self.double(FloatOp::Mul, d),
+            Op::ddiv => self.double(FloatOp::Div, d),
+            Op::drem => self.double(FloatOp::Rem, d),
+
+            // Negation and conversion
+            Op::ineg => self.unary(UnaryOp::INeg, d, 1),
+            Op::lneg => self.unary(UnaryOp::LNeg, d, 2),
+            Op::fneg => self.unary(UnaryOp::FNeg, d, 1),
+            Op::dneg => self.unary(UnaryOp::DNeg, d, 2),
+            Op::i2l => self.unary(UnaryOp::I2L, d, 1),
+            Op::i2f => self.unary(UnaryOp::I2F, d, 1),
+            Op::i2d => self.unary(UnaryOp::I2D, d, 1),
+            Op::l2i => self.unary(UnaryOp::L2I, d, 2),
+            Op::l2f => self.unary(UnaryOp::L2F, d, 2),
+            Op::l2d => self.unary(UnaryOp::L2D, d, 2),
+            Op::f2i => self.unary(UnaryOp::F2I, d, 1),
+            Op::f2l => self.unary(UnaryOp::F2L, d, 1),
+            Op::f2d => self.unary(UnaryOp::F2D, d, 1),
+            Op::d2i => self.unary(UnaryOp::D2I, d, 2),
+            Op::d2l => self.unary(UnaryOp::D2L, d, 2),
+            Op::d2f => self.unary(UnaryOp::D2F, d, 2),
+            Op::i2b => self.unary(UnaryOp::I2B, d, 1),
+            Op::i2c => self.unary(UnaryOp::I2C, d, 1),
+            Op::i2s => self.unary(UnaryOp::I2S, d, 1),
+
+            // Comparison
+            Op::lcmp => self.compare(CompareOp::Long, d, 2),
+            Op::fcmp(greater_if_nan) => self.compare(CompareOp::Float(greater_if_nan), d, 1),
+            Op::dcmp(greater_if_nan) => self.compare(CompareOp::Double(greater_if_nan), d, 2),
+            Op::if_eq(target) => self.if_imm(Cond::Eq, d, target),
+            Op::if_ne(target) => self.if_imm(Cond::Ne, d, target),
+            Op::if_lt(target) => self.if_imm(Cond::Lt, d, target),
+            Op::if_ge(target) => self.if_imm(Cond::Ge, d, target),
+            Op::if_gt(target) => self.if_imm(Cond::Gt, d, target),
+            Op::if_le(target) => self.if_imm(Cond::Le, d, target),
+            Op::if_icmp_eq(target) => self.if_icmp(Cond::Eq, d, target),
+            Op::if_icmp_ne(target) => self.if_icmp(Cond::Ne, d, target),
+            Op::if_icmp_lt(target) => self.if_icmp(Cond::Lt, d, target),
+            Op::if_icmp_ge(target) => self.if_icmp(Cond::Ge, d, target),
+            Op::if_icmp_gt(target) => self.if_icmp(Cond::Gt, d, target),
+            Op::if_icmp_le(target) => self.if_icmp(Cond::Le, d, target),
+            Op::if_acmp_eq(target) | Op::if_acmp_ne(target) => {
+                let equal = matches!(op, Op::if_acmp_eq(_));
+                self.insts
+                    .push(Inst::IfRef(equal, s(d - 2), s(d - 1), target));
+                self.targets.push(target);
+                d - 2
+            }
+            Op::ifnull(target) | Op::ifnonnull(target) => {
+                let null = matches!(op, Op::ifnull(_));
+                self.insts.push(Inst::IfNull(null, s(d - 1), target));
+                self.targets.push(target);
+                d - 1
+            }
+
+            // Control
+            Op::goto(target) => {
+                self.insts.push(Inst::Goto(target));
+                self.targets.push(target);
+                self.falls_through = false;
+                d
+            }
+            Op::jsr(_) | Op::ret(_) => return None,
+            Op::tableswitch(switch) => {
+                self.insts.push(Inst::TableSwitch(s(d - 1), switch));
+                self.targets
+                    .extend(self.code.table_switch(switch).targets());
+                self.falls_through = false;
+                d - 1
+            }
+            Op::lookupswitch(switch) => {
+                self.insts.push(Inst::LookupSwitch(s(d - 1), switch));
+                self.targets
+                    .extend(self.code.lookup_switch(switch).targets());
+                self.falls_through = false;
+                d - 1
+            }
+            Op::ireturn | Op::freturn | Op::areturn => self.ret(pc, s(d - 1), 1, d),
+            Op::lreturn | Op::dreturn => self.ret(pc, s(d - 2), 2, d),
+            Op::ret_void => self.ret(pc, s(d), 0, d),
+
+            // Quick
+            Op::getstatic_quick(field) => {
+                let size = self.field_size(field.class, field.field);
+                self.insts.push(Inst::GetStatic(s(d), field));
+                d + size
+            }
+            Op::putstatic_quick(field, value_type) => {
+                let size = value_type.size() as u16;
+                self.insts
+                    .push(Inst::PutStatic(field, s(d - size), value_type));
+                d - size
+            }
+            Op::getfield_quick(index) => {
+                let size = self.instance_field_size(instruction)?;
+                self.insts.push(Inst::GetField(s(d - 1), s(d - 1), index));
+                d - 1 + size
+            }
+            Op::putfield_quick(index, value_type) => {
+                let size = value_type.size() as u16;
+                self.insts.push(Inst::PutField(
+                    s(d - 1 - size),
+                    index,
+                    s(d - size),
+                    value_type,
+                ));
+                d - 1 - size
+            }
+            Op::new_quick(class) => {
+                let safepoint = Safepoint { pc, depth: d };
+                self.insts.push(Inst::New(s(d), class, safepoint));
+                d + 1
+            }
+            Op::checkcast_quick(class) => {
+                self.insts.push(Inst::CheckCast(s(d - 1), class));
+                d
+            }
+            Op::instanceof_quick(class) => {
+                self.insts.push(Inst::InstanceOf(s(d - 1), s(d - 1), class));
+                d
+            }
+            Op::invokestatic_quick(method) => {
+                let descriptor = self.classes.method_info(method).parsed_descriptor();
+                let slots = descriptor.arg_slots(true) as u16;
+                self.call(CallKind::Static(method), pc, d, slots, method)
+            }
+            Op::invokespecial_quick(method) => {
+                let descriptor = self.classes.method_info(method).parsed_descriptor();
+                let slots = descriptor.arg_slots(false) as u16;
+                self.call(CallKind::Special(method), pc, d, slots, method)
+            }
+            Op::invokevirtual_quick(call) => {
+                let cache = self.code.inline_cache(call.cache).get();
+                let kind = match cache {
+                    Some(cache) if self.speculate => CallKind::Guarded(cache),
+                    _ => CallKind::Virtual(call),
+                };
+                let method = self.resolved_method(instruction)?;
+                self.call(kind, pc, d, call.slots, method)
+            }
+            Op::invokeinterface_quick(call) => {
+                let cache = self.code.inline_cache(call.cache).get();
+                let kind = match cache {
+                    Some(cache) if self.speculate => CallKind::Guarded(cache),
+                    _ => CallKind::Interface(call),
+                };
+                let method = self.resolved_method(instruction)?;
+                self.call(kind, pc, d, call.slots, method)
+            }
+
+            Op::ldc(idx) => {
+                let constants = self.classes.classes[self.class].class.constants();
+                let value = match constants.get(idx) {
+                    Entry::Integer(val) => Value::Int(*val),
+                    Entry::Long(val) => Value::Long(*val),
+                    Entry::Float(val) => Value::Float(*val),
+                    Entry::Double(val) => Value::Double(*val),
+                    _ => return Some(self.deopt(pc, d, DeoptReason::Unsupported)),
+                };
+                self.constant(s(d), value, d)
+            }
+
+            // Instructions which have never run
+            Op::getstatic(_)
+            | Op::putstatic(_)
+            | Op::getfield(_)
+            | Op::putfield(_)
+            | Op::invokevirtual(_)
+            | Op::invokespecial(_)
+            | Op::invokestatic(_)
+            | Op::invokeinterface(..)
+            | Op::new(_)
+            | Op::checkcast(_)
+            | Op::instanceof(_) => self.deopt(pc, d, DeoptReason::Unreached),
+
+            // Instructions which are not implemented
+            _ => self.deopt(pc, d, DeoptReason::Unsupported),
+        };
+        Some(depth)
+    }
+
+    fn deopt(&mut self, pc: u32, depth: u16, reason: DeoptReason) -> u16 {
+        self.insts.push(Inst::Deopt(Deopt { pc, depth, reason }));
+        self.falls_through = false;
+        depth
+    }
+
+    fn constant(&mut self, dst: Reg, value: Value, depth: u16) -> u16 {
+        self.insts.push(Inst::Const(dst, value));
+        depth + value.size() as u16
+    }
+
+    /// Duplicates the top `size` slots of the stack beneath the `skip` slots
+    /// below them.
+    fn dup(&mut self, depth: u16, size: u16, skip: u16) -> u16 {
+        let base = depth - size - skip;
+        for i in 0..size {
+            self.insts
+                .push(Inst::Move(self.slot(depth + i), self.slot(base + skip + i)));
+        }
+        if skip > 0 {
+            for i in (0..skip).rev() {
+                self.insts
+                    .push(Inst::Move(self.slot(base + size + i), self.slot(base + i)));
+            }
+            for i in 0..size {
+                self.insts
+                    .push(Inst::Move(self.slot(base + i), self.slot(depth + i)));
+            }
+        }
+        depth + size
+    }
+
+    fn int(&mut self, op: IntOp, depth: u16) -> u16 {
+        let (lhs, rhs) = (self.slot(depth - 2), self.slot(depth - 1));
+        self.insts.push(Inst::Int(op, lhs, lhs, rhs));
+        depth - 1
+    }
+
+    fn long(&mut self, op: LongOp, depth: u16) -> u16 {
+        let rhs_size = if op.is_shift() { 1 } else { 2 };
+        let lhs = self.slot(depth - rhs_size - 2);
+        let rhs = self.slot(depth - rhs_size);
+        self.insts.push(Inst::Long(op, lhs, lhs, rhs));
+        depth - rhs_size
+    }
+
+    fn float(&mut self, op: FloatOp, depth: u16) -> u16 {
+        let (lhs, rhs) = (self.slot(depth - 2), self.slot(depth - 1));
+        self.insts.push(Inst::Float(op, lhs, lhs, rhs));
+        depth - 1
+    }
+
+    fn double(&mut self, op: FloatOp, depth: u16) -> u16 {
+        let (lhs, rhs) = (self.slot(depth - 4), self.slot(depth - 2));
+        self.insts.push(Inst::Double(op, lhs, lhs, rhs));
+        depth - 2
+    }
+
+    fn unary(&mut self, op: UnaryOp, depth: u16, size: u16) -> u16 {
+        let reg = self.slot(depth - size);
+        self.insts.push(Inst::Unary(op, reg, reg));
+        depth - size + if op.is_wide() { 2 } else { 1 }
+    }
+
+    fn compare(&mut self, op: CompareOp, depth: u16, size: u16) -> u16 {
+        let (lhs, rhs) = (self.slot(depth - 2 * size), self.slot(depth - size));
+        self.insts.push(Inst::Compare(op, lhs, lhs, rhs));
+        depth - 2 * size + 1
+    }
+
+    fn if_imm(&mut self, cond: Cond, depth: u16, target: u32) -> u16 {
+        self.insts
+            .push(Inst::IfImm(cond, self.slot(depth - 1), 0, target));
+        self.targets.push(target);
+        depth - 1
+    }
+
+    fn if_icmp(&mut self, cond: Cond, depth: u16, target: u32) -> u16 {
+        let (lhs, rhs) = (self.slot(depth - 2), self.slot(depth - 1));
+        self.insts.push(Inst::If(cond, lhs, rhs, target));
+        self.targets.push(target);
+        depth - 2
+    }
+
+    /// Returns the value taking `slots` registers from `reg`. A `synchronized`
+    /// method returns through the interpreter, which exits its monitor.
+    fn ret(&mut self, pc: u32, reg: Reg, slots: u16, depth: u16) -> u16 {
+        if self.code.synchronized {
+            return self.deopt(pc, depth, DeoptReason::Unsupported);
+        }
+        self.insts.push(Inst::Return(reg, slots));
+        self.falls_through = false;
+        depth
+    }
+
+    fn call(&mut self, kind: CallKind, pc: u32, depth: u16, slots: u16, method: MethodId) -> u16 {
+        let descriptor = self.classes.method_info(method).parsed_descriptor();
+        let result = descriptor
+            .result()
+            .map_or(0, |result| result.slots() as u16);
+        let base = depth - slots;
+        self.insts.push(Inst::Call(Call {
+            kind,
+            base: self.slot(base),
+            slots,
+            returns: result > 0,
+            pc,
+        }));
+        base + result
+    }
+
+    fn field_size(&self, class: u32, field: u32) -> u16 {
+        let class = &self.classes.classes[class as usize].class;
+        class.fields()[field as usize].parsed_descriptor().slots() as u16
+    }
+
+    /// Returns the number of slots taken by the field a quickened `getfield`
+    /// or `putfield` accesses, whose reference has already been resolved.
+    fn instance_field_size(&self, instruction: &Instruction) -> Option<u16> {
+        let (Instruction::getfield(idx) | Instruction::putfield(idx)) = instruction else {
+            return None;
+        };
+        match self.classes.resolve(self.class, *idx).ok()? {
+            Resolved::Field(field) => Some(self.field_size(field.class, field.field)),
+            _ => None,
+        }
+    }
+
+    /// Returns the method a quickened `invokevirtual` or `invokeinterface`
+    /// resolved to.
+    fn resolved_method(&self, instruction: &Instruction) -> Option<MethodId> {
+        let (Instruction::invokevirtual(idx) | Instruction::invokeinterface(idx, _)) = instruction
+        else {
+            return None;
+        };
+        match self.classes.resolve(self.class, *idx).ok()? {
+            Resolved::Method(method) => Some(method),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::rc::Rc;
+
+use crate::vm::call_frame::{
+    get, get_double, get_float, get_int, get_long, get_reference, set, set_double, set_float,
+    set_int, set_long, set_reference, CallFrame, Slot, ThreadStack,
+};
+use crate::vm::decode::{DecodedCode, InlineCache, MethodId};
+use crate::vm::heap::{AllocError, Heap, ObjectRef};
+use crate::vm::{ClassManager, ExecuteError, JavaException, LinkageError};
+
+use super::{Call, CallKind, Deopt, DeoptReason, Function, Inst, Reg, Safepoint};
+
+/// How a frame left the optimizing tier.
+enum Exit {
+    /// Returns the value taking a number of registers from a register.
+    Return(Reg, u16),
+    Call(MethodId, Call),
+    Deopt(Deopt),
+    /// Collects the heap, which did not have room for an object.
+    Collect(Safepoint),
+    /// Lets the other threads run, as the time slice is used up.
+    Yield(Safepoint),
+}
+
+/// What has to happen before the frame which left the optimizing tier, or
+/// the frame it called, runs again.
+pub(in crate::vm) enum Stop {
+    Continue,
+    /// Collects the heap, as the frame stopped at an allocation.
+    Collect,
+    /// Calls the native method of a library, whose receiver and arguments
+    /// the frame left on top of its operand stack.
+    #[cfg(feature = "jni")]
+    Jni(MethodId),
+}
+
+/// Runs the frame on top of `call_stack`, which must be in the optimizing
+/// tier, until it returns, calls a method, deoptimizes or uses up the time
+/// slice `budget`.
+pub(in crate::vm) fn run<'a>(
+    classes: &'a ClassManager,
+    heap: &mut Heap,
+    thread: &mut ThreadStack,
+    call_stack: &mut Vec<CallFrame<'a>>,
+    budget: &mut i32,
+) -> Result<Stop, ExecuteError> {
+    let frame = call_stack.last_mut().unwrap();
+    let optimized = frame
+        .optimized
+        .as_mut()
+        .expect("frame runs in the optimizing tier");
+    let registers = thread.registers(frame.base, frame.code);
+
+    let function = Rc::clone(&optimized.function);
+    let exit = execute(
+        classes,
+        heap,
+        &function,
+        frame.code,
+        registers,
+        &mut optimized.pc,
+        budget,
+    )?;
+    match exit {
+        Exit::Return(reg, slots) => {
+            // The result moves to where the arguments were, which is the top
+            // of the operand stack of the caller.
+            let (reg, slots) = (reg as usize, slots as usize);
+            registers.copy_within(reg..reg + slots, 0);
+            call_stack.pop();
+            if let Some(caller) = call_stack.last_mut() {
+                caller.depth += slots;
+            }
+        }
+        Exit::Call(method, call) => {
+            // The frame of the callee starts at the arguments, and its result
+            // is pushed where they were, which is the register of the result.
+            frame.depth = (call.base - function.locals) as usize;
+            frame.pc = call.pc as usize + 1;
+            // The native methods of libraries take the arguments from the
+            // operand stack, and leave their result where they were, as if
+            // they returned.
+            #[cfg(feature = "jni")]
+            if classes.is_jni(method) {
+                frame.depth += call.slots as usize;
+                return Ok(Stop::Jni(method));
+            }
+            let code = classes.method_code(method)?;
+            let base = frame.base + call.base as usize;
+            call_stack.push(CallFrame::new(method, code, base));
+        }
+        Exit::Deopt(deopt) => {
+            frame.depth = deopt.depth as usize;
+            frame.pc = deopt.pc as usize;
+            frame.optimized = None;
+            frame.code.profile.invalidate(deopt.reason);
+        }
+        Exit::Collect(safepoint) => {
+            frame.depth = safepoint.depth as usize;
+            frame.pc = safepoint.pc as usize;
+            return Ok(Stop::Collect);
+        }
+        Exit::Yield(safepoint) => {
+            frame.depth = safepoint.depth as usize;
+            frame.pc = safepoint.pc as usize;
+        }
+    }
+    Ok(Stop::Continue)
+}
+
+/// Runs the instructions of `function` from `pc` until the frame leaves the
+/// optimizing tier, leaving `pc` after the last instruction executed.
+fn execute(
+    classes: &ClassManager,
+    heap: &mut Heap,
+    function: &Function,
+    code: &DecodedCode,
+    registers: &mut [Slot],
+    pc: &mut usize,
+    budget: &mut i32,
+) -> Result<Exit, ExecuteError> {
+    let insts = function.insts();
+    let regs = registers;
+    let mut next = *pc;
+    let exit = loop {
+        let inst = insts[next];
+        next += 1;
+        match inst {
+            Inst::Nop => (),
+            Inst::Move(dst, src) => regs[dst as usize] = regs[src as usize],
+            Inst::Const(dst, value) => set(regs, dst as usize, value),
+            Inst::Int(op, dst, lhs, rhs) => {
+                let result = op.apply(int(regs, lhs), int(regs, rhs));
+                set_int(regs, dst as usize, result.ok_or_else(divide_by_zero)?);
+            }
+            Inst::IntImm(op, dst, lhs, rhs) => {
+                let result = op.apply(int(regs, lhs), rhs);
+                set_int(regs, dst as usize, result.ok_or_else(divide_by_zero)?);
+            }
+            Inst::Long(op, dst, lhs, rhs) => {
+                let rhs = if op.is_shift() {
+                    int(regs, rhs) as i64
+                } else {
+                    long(regs, rhs)
+                };
+                let result = op.apply(long(regs, lhs), rhs);
+                set_long(regs, dst as usize, result.ok_or_else(divide_by_zero)?);
+            }
+            Inst::Float(op, dst, lhs, rhs) => {
+                let result = op.apply_float(float(regs, lhs), float(regs, rhs));
+                set_float(regs, dst as usize, result);
+            }
+            Inst::Double(op, dst, lhs, rhs) => {
+                let result = op.apply_double(double(regs, lhs), double(regs, rhs));
+                set_double(regs, dst as usize, result);
+            }
+            Inst::Unary(op, dst, src) => {
+                let operand = get(regs, src as usize, op.operand_type());
+                let result = op
+                    .apply(operand)
+                    .expect("operand has the type of the operation");
+                set(regs, dst as usize, result);
+            }
+            Inst::Compare(op, dst, lhs, rhs) => {
+                let value_type = op.operand_type();
+                let (lhs, rhs) = (
+                    get(regs, lhs as usize, value_type),
+                    get(regs, rhs as usize, value_type),
+                );
+                let result = op
+                    .apply(lhs, rhs)
+                    .expect("operands have the type of the operation");
+                set_int(regs, dst as usize, result);
+            }
+            Inst::If(cond, lhs, rhs, target) => {
+                if cond.test(int(regs, lhs), int(regs, rhs)) {
+                    next = target as usize;
+                }
+            }
+            Inst::IfImm(cond, lhs, rhs, target) => {
+                if cond.test(int(regs, lhs), rhs) {
+                    next = target as usize;
+                }
+            }
+            Inst::IfRef(equal, lhs, rhs, target) => {
+                if (reference(regs, lhs) == reference(regs, rhs)) == equal {
+                    next = target as usize;
+                }
+            }
+            Inst::IfNull(null, reg, target) => {
+                if reference(regs, reg).is_none() == null {
+                    next = target as usize;
+                }
+            }
+            Inst::Goto(target) => next = target as usize,
+            Inst::TableSwitch(reg, switch) => {
+                let target = code.table_switch(switch).target(int(regs, reg));
+                next = function.entries[target as usize] as usize;
+            }
+            Inst::LookupSwitch(reg, switch) => {
+                let target = code.lookup_switch(switch).target(int(regs, reg));
+                next = function.entries[target as usize] as usize;
+            }
+            Inst::Return(reg, slots) => break Exit::Return(reg, slots),
+            Inst::GetStatic(dst, field) => {
+                let statics = &classes.classes[field.class as usize].statics;
+                let value = statics[field.field as usize].get();
+                set(
+                    regs,
+                    dst as usize,
+                    value.ok_or_else(ExecuteError::string_constant)?,
+                );
+            }
+            Inst::PutStatic(field, reg, value_type) => {
+                let statics = &classes.classes[field.class as usize].statics;
+                let value = get(regs, reg as usize, value_type);
+                statics[field.field as usize].set(Some(value));
+                heap.record_static(field.class, value);
+            }
+            Inst::GetField(dst, object, index) => {
+                let Some(object) = reference(regs, object) else {
+                    return Err(null_pointer());
+                };
+                set(regs, dst as usize, heap.get(object).fields[index as usize]);
+            }
+            Inst::PutField(object, index, reg, value_type) => {
+                let Some(object) = reference(regs, object) else {
+                    return Err(null_pointer());
+                };
+                heap.set_field(object, index as usize, get(regs, reg as usize, value_type));
+            }
+            Inst::CheckCast(object, class) => {
+                if let Some(object) = reference(regs, object) {
+                    let object_class = heap.get(object).class as usize;
+                    if !classes.is_subclass(object_class, class as usize) {
+                        return Err(class_cast());
+                    }
+                }
+            }
+            Inst::InstanceOf(dst, object, class) => {
+                let is_instance = reference(regs, object).is_some_and(|object| {
+                    let object_class = heap.get(object).class as usize;
+                    classes.is_subclass(object_class, class as usize)
+                });
+                set_int(regs, dst as usize, is_instance as i32);
+            }
+            Inst::New(dst, class, safepoint) => {
+                match classes.instantiate(heap, class) {
+                    Ok(object) => set_reference(regs, dst as usize, Some(object)),
+                    // The instruction runs again once the heap has been
+                    // collected.
+                    Err(AllocError::Full) => {
+                        next -= 1;
+                        break Exit::Collect(safepoint);
+                    }
+                    Err(AllocError::OutOfMemory) => return Err(ExecuteError::OutOfMemory),
+                }
+            }
+            Inst::Poll(safepoint) => {
+                *budget -= 1;
+                // The poll runs again once the thread is resumed.
+                if *budget <= 0 {
+                    next -= 1;
+                    break Exit::Yield(safepoint);
+                }
+            }
+            Inst::Call(call) => {
+                let receiver = || match reference(regs, call.base) {
+                    Some(receiver) => Ok(heap.get(receiver).class),
+                    None => Err(null_pointer()),
+                };
+                let method = match call.kind {
+                    CallKind::Static(method) => method,
+                    CallKind::Special(method) => {
+                        receiver()?;
+                        method
+                    }
+                    CallKind::Virtual(virtual_call) => {
+                        let class = receiver()?;
+                        let cache = code.inline_cache(virtual_call.cache);
+                        match cache.get() {
+                            Some(cached) if cached.class == class => cached.target,
+                            _ => {
+                                let target =
+                                    classes.linked(class).vtable[virtual_call.index as usize];
+                                cache.set(Some(InlineCache { class, target }));
+                                target
+                            }
+                        }
+                    }
+                    CallKind::Interface(interface_call) => {
+                        let class = receiver()?;
+                        let cache = code.inline_cache(interface_call.cache);
+                        match cache.get() {
+                            Some(cached) if cached.class == class => cached.target,
+                            _ => {
+                                let itables = &classes.linked(class).itables;
+                                let itable = itables.get(&interface_call.interface);
+                                let Some(target) = itable
+                                    .and_then(|itable| itable[interface_call.method as usize])
+                                else {
+                                    return Err(LinkageError::IncompatibleClassChange.into());
+                                };
+                                cache.set(Some(InlineCache { class, target }));
+                                target
+                            }
+                        }
+                    }
+                    CallKind::Guarded(cache) => {
+                        if receiver()? != cache.class {
+                            let depth = call.base + call.slots - function.locals;
+                            break Exit::Deopt(Deopt {
+                                pc: call.pc,
+                                depth,
+                                reason: DeoptReason::Speculation,
+                            });
+                        }
+                        cache.target
+                    }
+                };
+                break Exit::Call(method, call);
+            }
+            Inst::Deopt(deopt) => break Exit::Deopt(deopt),
+        }
+    };
+    *pc = next;
+    Ok(exit)
+}
+
+#[inline(always)]
+fn int(regs: &[Slot], reg: Reg) -> i32 {
+    get_int(regs, reg as usize)
+}
+
+#[inline(always)]
+fn long(regs: &[Slot], reg: Reg) -> i64 {
+    get_long(regs, reg as usize)
+}
+
+#[inline(always)]
+fn float(regs: &[Slot], reg: Reg) -> f32 {
+    get_float(regs, reg as usize)
+}
+
+#[inline(always)]
+fn double(regs: &[Slot], reg: Reg) -> f64 {
+    get_double(regs, reg as usize)
+}
+
+#[inline(always)]
+fn reference(regs: &[Slot], reg: Reg) -> Option<ObjectRef> {
+    get_reference(regs, reg as usize)
+}
+
+#[cold]
+#[inline(never)]
+fn divide_by_zero() -> ExecuteError {
+    JavaException::DivideByZero.into()
+}
+
+#[cold]
+#[inline(never)]
+fn null_pointer() -> ExecuteError {
+    JavaException::NullPointer.into()
+}
+
+#[cold]
+#[inline(never)]
+fn class_cast() -> ExecuteError {
+    JavaException::ClassCast.into()
+}
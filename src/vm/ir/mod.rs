@@ -0,0 +1,579 @@
+//! The optimizing tier, which runs hot methods translated from stack-based
+//! bytecode into a register-based intermediate representation.
+//!
+//! Every local and every operand stack slot of a method is given a fixed
+//! register: the locals come first, followed by the stack, so that stack slot
+//! `n` lives in register `max_locals + n`. The registers are the slots of the
+//! frame in the [`ThreadStack`], laid out just as the bytecode interpreter
+//! uses them, which makes moving a frame between the tiers free: a frame can
+//! enter the optimizing tier at the start of any block, such as at the head of
+//! a hot loop, and falls back to the bytecode interpreter when an assumption
+//! made while compiling fails.
+//!
+//! [`ThreadStack`]: super::call_frame::ThreadStack
+
+mod build;
+mod interpret;
+mod optimize;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::call_frame::CallFrame;
+use super::decode::{DecodedCode, FieldId, InlineCache, InterfaceCall, MethodId, VirtualCall};
+use super::value::{Value, ValueType};
+use super::ClassManager;
+
+pub(super) use interpret::{run, Stop};
+
+/// The number of times a method is compiled before it is left to the bytecode
+/// interpreter for good.
+const MAX_COMPILATIONS: u8 = 8;
+
+/// The largest method, in instructions, which is compiled.
+const MAX_METHOD_SIZE: usize = 8000;
+
+/// A register, indexing the locals of a frame running in the optimizing tier.
+pub type Reg = u16;
+
+/// Returns the number of registers of a frame running `code` in the optimizing
+/// tier: one per local and stack slot, and two scratch registers used to
+/// shuffle the stack.
+pub(super) fn register_count(code: &DecodedCode) -> usize {
+    code.max_locals as usize + code.max_stack as usize + 2
+}
+
+/// An instruction of the intermediate representation. Instructions write their
+/// result to their first register; a `long` or `double` takes the next
+/// register as well, as on the operand stack.
+///
+/// Jumps hold the index of the instruction they jump to, while switches jump
+/// through the tables of the [`DecodedCode`] of the method, and so hold the
+/// index of bytecode instructions.
+#[derive(Debug, Clone, Copy)]
+pub enum Inst {
+    Nop,
+    /// Copies a register as is, which may be the second half of a `long` or
+    /// `double`.
+    Move(Reg, Reg),
+    Const(Reg, Value),
+    Int(IntOp, Reg, Reg, Reg),
+    IntImm(IntOp, Reg, Reg, i32),
+    /// A `long` operation. The right operand of a shift is an `int`.
+    Long(LongOp, Reg, Reg, Reg),
+    Float(FloatOp, Reg, Reg, Reg),
+    Double(FloatOp, Reg, Reg, Reg),
+    Unary(UnaryOp, Reg, Reg),
+    Compare(CompareOp, Reg, Reg, Reg),
+    If(Cond, Reg, Reg, u32),
+    IfImm(Cond, Reg, i32, u32),
+    /// Jumps if two references are equal, or unequal if the flag is `false`.
+    IfRef(bool, Reg, Reg, u32),
+    /// Jumps if a reference is `null`, or not `null` if the flag is `false`.
+    IfNull(bool, Reg, u32),
+    Goto(u32),
+    TableSwitch(Reg, u32),
+    LookupSwitch(Reg, u32),
+    /// Returns the value taking a number of registers from a register, which
+    /// is zero for a `void` method.
+    Return(Reg, u16),
+    GetStatic(Reg, FieldId),
+    PutStatic(FieldId, Reg, ValueType),
+    /// Reads the field at an index of the object in the second register.
+    GetField(Reg, Reg, u32),
+    /// Writes the third register to the field at an index of the object in
+    /// the first register.
+    PutField(Reg, u32, Reg, ValueType),
+    /// Checks that the object in a register, unless it is `null`, is an
+    /// instance of a class.
+    CheckCast(Reg, u32),
+    /// Writes whether the object in the second register is an instance of a
+    /// class.
+    InstanceOf(Reg, Reg, u32),
+    /// Allocates an object of a class, which may collect the heap first.
+    New(Reg, u32, Safepoint),
+    /// Lets the other threads run once the time slice is used up, which is
+    /// counted down at the start of every loop.
+    Poll(Safepoint),
+    Call(Call),
+    /// Leaves the optimizing tier, resuming the bytecode interpreter before
+    /// an instruction.
+    Deopt(Deopt),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Ushr,
+    And,
+    Or,
+    Xor,
+}
+
+impl IntOp {
+    fn is_commutative(self) -> bool {
+        matches!(
+            self,
+            Self::Add | Self::Mul | Self::And | Self::Or | Self::Xor
+        )
+    }
+
+    /// Applies the operation, or returns `None` for a division by zero.
+    fn apply(self, lhs: i32, rhs: i32) -> Option<i32> {
+        Some(match self {
+            Self::Add => lhs.wrapping_add(rhs),
+            Self::Sub => lhs.wrapping_sub(rhs),
+            Self::Mul => lhs.wrapping_mul(rhs),
+            Self::Div => lhs.checked_div(rhs).or((rhs != 0).then_some(lhs))?,
+            Self::Rem => lhs.checked_rem(rhs).or((rhs != 0).then_some(0))?,
+            Self::Shl => lhs.wrapping_shl(rhs as u32),
+            Self::Shr => lhs.wrapping_shr(rhs as u32),
+            Self::Ushr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+            Self::And => lhs & rhs,
+            Self::Or => lhs | rhs,
+            Self::Xor => lhs ^ rhs,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Ushr,
+    And,
+    Or,
+    Xor,
+}
+
+impl LongOp {
+    fn is_shift(self) -> bool {
+        matches!(self, Self::Shl | Self::Shr | Self::Ushr)
+    }
+
+    /// Applies the operation, where `rhs` is the shift distance for shifts,
+    /// or returns `None` for a division by zero.
+    fn apply(self, lhs: i64, rhs: i64) -> Option<i64> {
+        Some(match self {
+            Self::Add => lhs.wrapping_add(rhs),
+            Self::Sub => lhs.wrapping_sub(rhs),
+            Self::Mul => lhs.wrapping_mul(rhs),
+            Self::Div => lhs.checked_div(rhs).or((rhs != 0).then_some(lhs))?,
+            Self::Rem => lhs.checked_rem(rhs).or((rhs != 0).then_some(0))?,
+            Self::Shl => lhs.wrapping_shl(rhs as u32),
+            Self::Shr => lhs.wrapping_shr(rhs as u32),
+            Self::Ushr => (lhs as u64).wrapping_shr(rhs as u32) as i64,
+            Self::And => lhs & rhs,
+            Self::Or => lhs | rhs,
+            Self::Xor => lhs ^ rhs,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl FloatOp {
+    fn apply_float(self, lhs: f32, rhs: f32) -> f32 {
+        match self {
+            Self::Add => lhs + rhs,
+            Self::Sub => lhs - rhs,
+            Self::Mul => lhs * rhs,
+            Self::Div => lhs / rhs,
+            Self::Rem => lhs % rhs,
+        }
+    }
+
+    fn apply_double(self, lhs: f64, rhs: f64) -> f64 {
+        match self {
+            Self::Add => lhs + rhs,
+            Self::Sub => lhs - rhs,
+            Self::Mul => lhs * rhs,
+            Self::Div => lhs / rhs,
+            Self::Rem => lhs % rhs,
+        }
+    }
+}
+
+/// A negation or a conversion between primitive types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    INeg,
+    LNeg,
+    FNeg,
+    DNeg,
+    I2L,
+    I2F,
+    I2D,
+    L2I,
+    L2F,
+    L2D,
+    F2I,
+    F2L,
+    F2D,
+    D2I,
+    D2L,
+    D2F,
+    I2B,
+    I2C,
+    I2S,
+}
+
+impl UnaryOp {
+    fn operand_type(self) -> ValueType {
+        match self {
+            Self::INeg | Self::I2L | Self::I2F | Self::I2D | Self::I2B | Self::I2C | Self::I2S => {
+                ValueType::Int
+            }
+            Self::LNeg | Self::L2I | Self::L2F | Self::L2D => ValueType::Long,
+            Self::FNeg | Self::F2I | Self::F2L | Self::F2D => ValueType::Float,
+            Self::DNeg | Self::D2I | Self::D2L | Self::D2F => ValueType::Double,
+        }
+    }
+
+    /// Returns whether the result is a `long` or `double`.
+    fn is_wide(self) -> bool {
+        matches!(
+            self,
+            Self::LNeg
+                | Self::DNeg
+                | Self::I2L
+                | Self::I2D
+                | Self::L2D
+                | Self::F2L
+                | Self::F2D
+                | Self::D2L
+        )
+    }
+
+    /// Applies the operation, or returns `None` if `value` has the wrong
+    /// type.
+    fn apply(self, value: Value) -> Option<Value> {
+        Some(match (self, value) {
+            (Self::INeg, Value::Int(val)) => Value::Int(val.wrapping_neg()),
+            (Self::LNeg, Value::Long(val)) => Value::Long(val.wrapping_neg()),
+            (Self::FNeg, Value::Float(val)) => Value::Float(-val),
+            (Self::DNeg, Value::Double(val)) => Value::Double(-val),
+            (Self::I2L, Value::Int(val)) => Value::Long(val as i64),
+            (Self::I2F, Value::Int(val)) => Value::Float(val as f32),
+            (Self::I2D, Value::Int(val)) => Value::Double(val as f64),
+            (Self::L2I, Value::Long(val)) => Value::Int(val as i32),
+            (Self::L2F, Value::Long(val)) => Value::Float(val as f32),
+            (Self::L2D, Value::Long(val)) => Value::Double(val as f64),
+            (Self::F2I, Value::Float(val)) => Value::Int(val as i32),
+            (Self::F2L, Value::Float(val)) => Value::Long(val as i64),
+            (Self::F2D, Value::Float(val)) => Value::Double(val as f64),
+            (Self::D2I, Value::Double(val)) => Value::Int(val as i32),
+            (Self::D2L, Value::Double(val)) => Value::Long(val as i64),
+            (Self::D2F, Value::Double(val)) => Value::Float(val as f32),
+            (Self::I2B, Value::Int(val)) => Value::Int(val as i8 as i32),
+            (Self::I2C, Value::Int(val)) => Value::Int(val as u16 as i32),
+            (Self::I2S, Value::Int(val)) => Value::Int(val as i16 as i32),
+            _ => return None,
+        })
+    }
+}
+
+/// A comparison of two `long`, `float` or `double` values producing `-1`, `0`
+/// or `1`. The flag of floating-point comparisons is whether `NaN` compares
+/// greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Long,
+    Float(bool),
+    Double(bool),
+}
+
+impl CompareOp {
+    fn operand_type(self) -> ValueType {
+        match self {
+            Self::Long => ValueType::Long,
+            Self::Float(_) => ValueType::Float,
+            Self::Double(_) => ValueType::Double,
+        }
+    }
+
+    /// Compares two values, or returns `None` if they have the wrong type.
+    fn apply(self, lhs: Value, rhs: Value) -> Option<i32> {
+        let (ordering, greater_if_nan) = match (self, lhs, rhs) {
+            (Self::Long, Value::Long(lhs), Value::Long(rhs)) => (Some(lhs.cmp(&rhs)), false),
+            (Self::Float(greater), Value::Float(lhs), Value::Float(rhs)) => {
+                (lhs.partial_cmp(&rhs), greater)
+            }
+            (Self::Double(greater), Value::Double(lhs), Value::Double(rhs)) => {
+                (lhs.partial_cmp(&rhs), greater)
+            }
+            _ => return None,
+        };
+        Some(match ordering {
+            Some(std::cmp::Ordering::Greater) => 1,
+            Some(std::cmp::Ordering::Equal) => 0,
+            Some(std::cmp::Ordering::Less) => -1,
+            None if greater_if_nan => 1,
+            None => -1,
+        })
+    }
+}
+
+/// The condition of an `int` comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+impl Cond {
+    fn test(self, lhs: i32, rhs: i32) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Ge => lhs >= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Le => lhs <= rhs,
+        }
+    }
+
+    /// Returns the condition with its operands swapped.
+    fn swap(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Ge => Self::Le,
+            Self::Gt => Self::Lt,
+            Self::Le => Self::Ge,
+            cond => cond,
+        }
+    }
+}
+
+/// A method call, taking its arguments from consecutive registers starting at
+/// `base` and writing its result, if any, to `base`. The frame of the callee
+/// starts at `base`, so the call overwrites every register from there on.
+#[derive(Debug, Clone, Copy)]
+pub struct Call {
+    pub(super) kind: CallKind,
+    pub(super) base: Reg,
+    pub(super) slots: u16,
+    pub(super) returns: bool,
+    /// The index of the bytecode instruction making the call, where the
+    /// bytecode interpreter resumes if a speculation fails.
+    pub(super) pc: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CallKind {
+    Static(MethodId),
+    /// A call to an instance method which needs no dispatch.
+    Special(MethodId),
+    Virtual(VirtualCall),
+    Interface(InterfaceCall),
+    /// A virtual or interface call which is assumed to always have a receiver
+    /// of the class its inline cache held when the method was compiled.
+    Guarded(InlineCache),
+}
+
+/// The state of the bytecode interpreter which an instruction corresponds to,
+/// before the instruction at `pc` with `depth` slots on the operand stack. The
+/// registers of the locals and the operand stack hold the same values there,
+/// so the garbage collector finds the references among them by the types of
+/// the slots of the bytecode.
+#[derive(Debug, Clone, Copy)]
+pub struct Safepoint {
+    pub(super) pc: u32,
+    pub(super) depth: u16,
+}
+
+/// A return to the bytecode interpreter before the instruction at `pc`, with
+/// `depth` slots on the operand stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Deopt {
+    pub(super) pc: u32,
+    pub(super) depth: u16,
+    pub(super) reason: DeoptReason,
+}
+
+/// Why a function returns to the bytecode interpreter, which decides whether
+/// the method is compiled again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeoptReason {
+    /// The instruction is not implemented by the optimizing tier, so the
+    /// function remains valid.
+    Unsupported,
+    /// The instruction had not run when the method was compiled, so the method
+    /// is compiled again once it is hot to include it.
+    Unreached,
+    /// A virtual call saw a receiver of another class than the one it was
+    /// compiled for, so the method is compiled again without speculating.
+    Speculation,
+}
+
+/// A method compiled by the optimizing tier.
+#[derive(Debug)]
+pub struct Function {
+    insts: Box<[Inst]>,
+    /// The index in `insts` at which each bytecode instruction starting a block
+    /// begins, or `u32::MAX` for the other instructions.
+    entries: Box<[u32]>,
+    /// The number of locals, which is the register holding the bottom of the
+    /// operand stack.
+    locals: u16,
+}
+
+impl Function {
+    pub fn insts(&self) -> &[Inst] {
+        &self.insts
+    }
+
+    /// Returns the index of the instruction at which a frame stopped before
+    /// the bytecode instruction at `pc` enters the function, if `pc` starts a
+    /// block.
+    fn entry(&self, pc: usize) -> Option<usize> {
+        match self.entries.get(pc) {
+            Some(&entry) if entry != u32::MAX => Some(entry as usize),
+            _ => None,
+        }
+    }
+}
+
+/// How hot a method is, and the function it was compiled to.
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// The number of invocations and backward jumps since the method was last
+    /// compiled or deoptimized.
+    counter: Cell<u32>,
+    function: RefCell<Option<Rc<Function>>>,
+    compilations: Cell<u8>,
+    /// Whether a speculation failed in a previous compilation, in which case
+    /// the method is compiled without speculating.
+    deoptimized: Cell<bool>,
+    /// Whether the method cannot be compiled.
+    disabled: Cell<bool>,
+}
+
+impl Profile {
+    /// Counts an invocation or a backward jump, and returns `true` if the
+    /// method is hot and may enter the optimizing tier. A threshold of zero is
+    /// never reached.
+    #[inline(always)]
+    pub(super) fn count(&self, threshold: u32) -> bool {
+        let counter = self.counter.get().saturating_add(1);
+        self.counter.set(counter);
+        counter >= threshold && threshold != 0 && !self.disabled.get()
+    }
+
+    /// Returns whether the method is hot and has not failed to compile.
+    pub(super) fn is_hot(&self, threshold: u32) -> bool {
+        self.counter.get() >= threshold && !self.disabled.get()
+    }
+
+    /// Marks the method as one which cannot be compiled.
+    pub(super) fn disable(&self) {
+        self.disabled.set(true);
+    }
+
+    /// Discards the compiled function after a deoptimization, so that the
+    /// method is compiled again once it is hot.
+    pub(super) fn invalidate(&self, reason: DeoptReason) {
+        if reason == DeoptReason::Unsupported {
+            return;
+        }
+        self.function.take();
+        self.counter.set(0);
+        if reason == DeoptReason::Speculation {
+            self.deoptimized.set(true);
+        }
+    }
+}
+
+/// The state of a frame running in the optimizing tier.
+#[derive(Debug)]
+pub struct Optimized {
+    function: Rc<Function>,
+    /// The index of the next instruction to execute.
+    pc: usize,
+}
+
+/// Counts an invocation of the method of `frame` if it has just been entered,
+/// compiles the method if it is hot, and moves the frame to the optimizing
+/// tier if the method is compiled and the frame stopped at the start of a
+/// block. Returns whether the frame runs in the optimizing tier.
+pub(super) fn enter(classes: &ClassManager, frame: &mut CallFrame, threshold: u32) -> bool {
+    if frame.optimized.is_some() {
+        return true;
+    }
+    let profile = &frame.code.profile;
+    if frame.pc == 0 {
+        profile.count(threshold);
+    }
+    if !profile.is_hot(threshold) {
+        return false;
+    }
+
+    let function = profile.function.borrow().clone();
+    let function = match function {
+        Some(function) => function,
+        None => match compile(classes, frame) {
+            Some(function) => function,
+            None => {
+                profile.disable();
+                return false;
+            }
+        },
+    };
+    let Some(entry) = function.entry(frame.pc) else {
+        return false;
+    };
+    // The bytecode interpreter runs the instructions the function leaves it
+    // to, without entering the function again first.
+    if let Inst::Deopt(deopt) = function.insts[entry] {
+        if deopt.pc as usize == frame.pc {
+            return false;
+        }
+    }
+    frame.optimized = Some(Optimized {
+        function,
+        pc: entry,
+    });
+    true
+}
+
+/// Compiles the method of `frame`, or returns `None` if it cannot be compiled.
+fn compile(classes: &ClassManager, frame: &CallFrame) -> Option<Rc<Function>> {
+    let profile = &frame.code.profile;
+    if profile.compilations.get() == MAX_COMPILATIONS || frame.code.len() > MAX_METHOD_SIZE {
+        return None;
+    }
+    profile.compilations.set(profile.compilations.get() + 1);
+
+    let method = MethodId {
+        class: frame.class as u32,
+        method: frame.method,
+    };
+    let speculate = !profile.deoptimized.get();
+    let mut function = build::build(classes, method, frame.code, speculate)?;
+    optimize::optimize(&mut function, frame.code);
+    let function = Rc::new(function.finish());
+    profile.function.replace(Some(function.clone()));
+    Some(function)
+}
@@ -0,0 +1,526 @@
+use std::ops::Range;
+
+use crate::vm::analysis::BitSet;
+use crate::vm::decode::DecodedCode;
+use crate::vm::value::Value;
+
+use super::build::Draft;
+use super::{Call, Inst, IntOp, LongOp, Reg};
+
+/// Optimizes a translated function with constant folding and copy
+/// propagation within each block, then removes the instructions whose results
+/// are never used and writes results directly to the register they are moved
+/// to.
+///
+/// Values are only propagated within a block, so that a frame entering the
+/// function at the start of a block, with its registers copied from the
+/// bytecode interpreter, sees what the optimized code expects.
+pub(super) fn optimize(draft: &mut Draft, code: &DecodedCode) {
+    let blocks = blocks(draft);
+    propagate(draft, code, &blocks);
+    loop {
+        let live = liveness(draft, code);
+        let removed = eliminate_dead_code(draft, &live);
+        let coalesced = coalesce_moves(draft, &live, &blocks);
+        if !removed && !coalesced {
+            break;
+        }
+    }
+}
+
+/// Returns the block of each instruction, counted from the start of the
+/// function.
+fn blocks(draft: &Draft) -> Vec<u32> {
+    let mut block = 0;
+    let mut previous = None;
+    draft
+        .origins
+        .iter()
+        .map(|&origin| {
+            if previous != Some(origin) && draft.leaders[origin as usize] {
+                block += 1;
+            }
+            previous = Some(origin);
+            block
+        })
+        .collect()
+}
+
+/// What is known about the value of a register within a block.
+///
+/// A `long` or `double` is only known if the facts about both of its
+/// registers agree, as a register may be overwritten without the other.
+#[derive(Debug, Clone, Copy)]
+enum Known {
+    Unknown,
+    Copy(Reg),
+    Const(Value),
+    /// The second half of a `long` or `double` constant.
+    High(Value),
+}
+
+impl Known {
+    /// Returns whether the register holds the second half of `value`.
+    fn is_high(self, value: Value) -> bool {
+        match (self, value) {
+            (Known::High(Value::Long(high)), Value::Long(val)) => high == val,
+            (Known::High(Value::Double(high)), Value::Double(val)) => {
+                high.to_bits() == val.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Propagates constants and copies forward through each block, folding the
+/// instructions whose operands are constant.
+fn propagate(draft: &mut Draft, code: &DecodedCode, blocks: &[u32]) {
+    let mut known = vec![Known::Unknown; draft.registers];
+    let mut touched: Vec<Reg> = Vec::new();
+    for index in 0..draft.insts.len() {
+        if index == 0 || blocks[index] != blocks[index - 1] {
+            for reg in touched.drain(..) {
+                known[reg as usize] = Known::Unknown;
+            }
+        }
+
+        let inst = &mut draft.insts[index];
+        inst.map_uses(|reg, wide| match known[reg as usize] {
+            Known::Copy(source) if !wide => source,
+            Known::Copy(source) => match known[reg as usize + 1] {
+                Known::Copy(next) if next == source + 1 => source,
+                _ => reg,
+            },
+            _ => reg,
+        });
+        *inst = fold(*inst, &known, code);
+
+        let Some(clobbered) = inst.clobbered(draft.locals, draft.registers as Reg) else {
+            continue;
+        };
+        for reg in &touched {
+            let value = &mut known[*reg as usize];
+            match *value {
+                Known::Copy(source) if clobbered.contains(&source) => *value = Known::Unknown,
+                _ => (),
+            }
+        }
+        for reg in clobbered {
+            known[reg as usize] = Known::Unknown;
+        }
+        let (dst, fact) = match *inst {
+            Inst::Move(dst, source) => match known[source as usize] {
+                Known::High(value) => (dst, Known::High(value)),
+                _ => (dst, Known::Copy(source)),
+            },
+            Inst::Const(dst, value) => {
+                if value.size() == 2 {
+                    known[dst as usize + 1] = Known::High(value);
+                    touched.push(dst + 1);
+                }
+                (dst, Known::Const(value))
+            }
+            _ => continue,
+        };
+        known[dst as usize] = fact;
+        touched.push(dst);
+    }
+}
+
+/// Returns the instruction computing the same result as `inst` given what is
+/// known about its operands.
+fn fold(inst: Inst, known: &[Known], code: &DecodedCode) -> Inst {
+    // A `long` or `double` is only constant if its second half is as well.
+    let constant = |reg: Reg| match known[reg as usize] {
+        Known::Const(value) if value.size() == 1 || known[reg as usize + 1].is_high(value) => {
+            Some(value)
+        }
+        _ => None,
+    };
+    let int = |reg: Reg| match constant(reg) {
+        Some(Value::Int(val)) => Some(val),
+        _ => None,
+    };
+    let jump = |taken: bool, target: u32| if taken { Inst::Goto(target) } else { Inst::Nop };
+
+    match inst {
+        Inst::Move(dst, source) if dst == source => Inst::Nop,
+        Inst::Move(dst, source) => match known[source as usize] {
+            Known::High(value) if known[dst as usize].is_high(value) => Inst::Nop,
+            _ => match constant(source) {
+                Some(value) => Inst::Const(dst, value),
+                None => inst,
+            },
+        },
+        Inst::Int(op, dst, lhs, rhs) => match (int(lhs), int(rhs)) {
+            (Some(lhs), Some(rhs)) => match op.apply(lhs, rhs) {
+                Some(result) => Inst::Const(dst, Value::Int(result)),
+                None => inst,
+            },
+            (_, Some(rhs)) => Inst::IntImm(op, dst, lhs, rhs),
+            (Some(lhs), _) if op.is_commutative() => Inst::IntImm(op, dst, rhs, lhs),
+            _ => inst,
+        },
+        Inst::IntImm(op, dst, lhs, rhs) => match int(lhs).and_then(|lhs| op.apply(lhs, rhs)) {
+            Some(result) => Inst::Const(dst, Value::Int(result)),
+            None => inst,
+        },
+        Inst::Long(op, dst, lhs, rhs) => {
+            let rhs = match (op.is_shift(), constant(rhs)) {
+                (true, Some(Value::Int(rhs))) => Some(rhs as i64),
+                (false, Some(Value::Long(rhs))) => Some(rhs),
+                _ => None,
+            };
+            match (constant(lhs), rhs) {
+                (Some(Value::Long(lhs)), Some(rhs)) => match op.apply(lhs, rhs) {
+                    Some(result) => Inst::Const(dst, Value::Long(result)),
+                    None => inst,
+                },
+                _ => inst,
+            }
+        }
+        Inst::Float(op, dst, lhs, rhs) => match (constant(lhs), constant(rhs)) {
+            (Some(Value::Float(lhs)), Some(Value::Float(rhs))) => {
+                Inst::Const(dst, Value::Float(op.apply_float(lhs, rhs)))
+            }
+            _ => inst,
+        },
+        Inst::Double(op, dst, lhs, rhs) => match (constant(lhs), constant(rhs)) {
+            (Some(Value::Double(lhs)), Some(Value::Double(rhs))) => {
+                Inst::Const(dst, Value::Double(op.apply_double(lhs, rhs)))
+            }
+            _ => inst,
+        },
+        Inst::Unary(op, dst, source) => match constant(source).and_then(|val| op.apply(val)) {
+            Some(result) => Inst::Const(dst, result),
+            None => inst,
+        },
+        Inst::Compare(op, dst, lhs, rhs) => {
+            match constant(lhs)
+                .zip(constant(rhs))
+                .and_then(|(lhs, rhs)| op.apply(lhs, rhs))
+            {
+                Some(result) => Inst::Const(dst, Value::Int(result)),
+                None => inst,
+            }
+        }
+        Inst::If(cond, lhs, rhs, target) => match (int(lhs), int(rhs)) {
+            (Some(lhs), Some(rhs)) => jump(cond.test(lhs, rhs), target),
+            (_, Some(rhs)) => Inst::IfImm(cond, lhs, rhs, target),
+            (Some(lhs), _) => Inst::IfImm(cond.swap(), rhs, lhs, target),
+            _ => inst,
+        },
+        Inst::IfImm(cond, lhs, rhs, target) => match int(lhs) {
+            Some(lhs) => jump(cond.test(lhs, rhs), target),
+            None => inst,
+        },
+        Inst::IfNull(null, reg, target) => match constant(reg) {
+            Some(Value::Reference(None)) => jump(null, target),
+            _ => inst,
+        },
+        Inst::TableSwitch(reg, switch) => match int(reg) {
+            Some(key) => Inst::Goto(code.table_switch(switch).target(key)),
+            None => inst,
+        },
+        Inst::LookupSwitch(reg, switch) => match int(reg) {
+            Some(key) => Inst::Goto(code.lookup_switch(switch).target(key)),
+            None => inst,
+        },
+        _ => inst,
+    }
+}
+
+/// Returns the registers live after each instruction.
+fn liveness(draft: &Draft, code: &DecodedCode) -> Vec<BitSet> {
+    let starts = draft.starts();
+    let count = draft.insts.len();
+    let mut live_out = vec![BitSet::new(draft.registers); count];
+    let mut live_in = vec![BitSet::new(draft.registers); count + 1];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..count).rev() {
+            let inst = &draft.insts[index];
+            let mut out = BitSet::new(draft.registers);
+            if !inst.is_terminator() {
+                out.union_with(&live_in[index + 1]);
+            }
+            inst.for_each_target(code, |target| {
+                out.union_with(&live_in[starts[target as usize] as usize]);
+            });
+
+            let mut before = out.clone();
+            if let Some(dst) = inst.dst() {
+                before.remove(dst as usize);
+                if inst.writes_next() {
+                    before.remove(dst as usize + 1);
+                }
+            }
+            inst.for_each_use(draft.locals, |reg| before.insert(reg as usize));
+            live_out[index] = out;
+            if before != live_in[index] {
+                live_in[index] = before;
+                changed = true;
+            }
+        }
+    }
+    live_out
+}
+
+/// Removes the instructions without side effects whose result is unused.
+fn eliminate_dead_code(draft: &mut Draft, live: &[BitSet]) -> bool {
+    let mut changed = false;
+    for (inst, live) in draft.insts.iter_mut().zip(live) {
+        let Some(dst) = inst.dst() else {
+            continue;
+        };
+        let next_live = inst.may_write_next() && live.contains(dst as usize + 1);
+        if inst.is_removable() && !live.contains(dst as usize) && !next_live {
+            *inst = Inst::Nop;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Makes the instruction computing the value a `Move` copies write it to the
+/// destination of the `Move` directly, when the value is not used elsewhere.
+fn coalesce_moves(draft: &mut Draft, live: &[BitSet], blocks: &[u32]) -> bool {
+    let mut changed = false;
+    let mut previous: Option<usize> = None;
+    for index in 0..draft.insts.len() {
+        let inst = draft.insts[index];
+        if matches!(inst, Inst::Nop) {
+            continue;
+        }
+        let def = previous.filter(|&previous| blocks[previous] == blocks[index]);
+        previous = Some(index);
+
+        let (Inst::Move(dst, source), Some(def)) = (inst, def) else {
+            continue;
+        };
+        let defining = &mut draft.insts[def];
+        if live[index].contains(source as usize)
+            || defining.dst() != Some(source)
+            || defining.may_write_next()
+            || matches!(defining, Inst::Call(_))
+        {
+            continue;
+        }
+        defining.set_dst(dst);
+        draft.insts[index] = Inst::Nop;
+        previous = Some(def);
+        changed = true;
+    }
+    changed
+}
+
+impl Inst {
+    /// Returns the register the instruction writes its result to.
+    fn dst(&self) -> Option<Reg> {
+        match *self {
+            Inst::Move(dst, _)
+            | Inst::Const(dst, _)
+            | Inst::Int(_, dst, _, _)
+            | Inst::IntImm(_, dst, _, _)
+            | Inst::Long(_, dst, _, _)
+            | Inst::Float(_, dst, _, _)
+            | Inst::Double(_, dst, _, _)
+            | Inst::Unary(_, dst, _)
+            | Inst::Compare(_, dst, _, _)
+            | Inst::GetStatic(dst, _)
+            | Inst::GetField(dst, _, _)
+            | Inst::InstanceOf(dst, _, _)
+            | Inst::New(dst, ..) => Some(dst),
+            Inst::Call(Call {
+                base,
+                returns: true,
+                ..
+            }) => Some(base),
+            _ => None,
+        }
+    }
+
+    fn set_dst(&mut self, reg: Reg) {
+        match self {
+            Inst::Move(dst, _)
+            | Inst::Const(dst, _)
+            | Inst::Int(_, dst, _, _)
+            | Inst::IntImm(_, dst, _, _)
+            | Inst::Long(_, dst, _, _)
+            | Inst::Float(_, dst, _, _)
+            | Inst::Double(_, dst, _, _)
+            | Inst::Unary(_, dst, _)
+            | Inst::Compare(_, dst, _, _)
+            | Inst::GetStatic(dst, _)
+            | Inst::GetField(dst, _, _)
+            | Inst::InstanceOf(dst, _, _)
+            | Inst::New(dst, ..) => *dst = reg,
+            _ => unreachable!("instruction has no result"),
+        }
+    }
+
+    /// Returns whether the result is a `long` or `double`, which also writes
+    /// the register after the destination.
+    fn writes_next(&self) -> bool {
+        match *self {
+            Inst::Const(_, value) => value.size() == 2,
+            Inst::Unary(op, _, _) => op.is_wide(),
+            Inst::Long(..) | Inst::Double(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the result may be a `long` or `double`, including the
+    /// instructions whose result type is not known.
+    fn may_write_next(&self) -> bool {
+        self.writes_next()
+            || matches!(
+                self,
+                Inst::GetStatic(..) | Inst::GetField(..) | Inst::Call(_)
+            )
+    }
+
+    /// Returns the registers the instruction may write: those of its result,
+    /// or for a call, every register from the start of the frame of the
+    /// callee. An allocation may move objects, and only updates the
+    /// references in the locals and the operand stack, so it clobbers every
+    /// register after them as well, and so does a poll, as other threads may
+    /// collect the heap.
+    fn clobbered(&self, locals: Reg, registers: Reg) -> Option<Range<Reg>> {
+        match *self {
+            Inst::Call(call) => Some(call.base..registers),
+            Inst::New(dst, _, safepoint) => Some(dst.min(locals + safepoint.depth)..registers),
+            Inst::Poll(safepoint) => Some(locals + safepoint.depth..registers),
+            _ => {
+                let dst = self.dst()?;
+                Some(dst..dst + if self.may_write_next() { 2 } else { 1 })
+            }
+        }
+    }
+
+    /// Returns whether the instruction can be removed if its result is unused,
+    /// which is not the case if it can fail.
+    fn is_removable(&self) -> bool {
+        match *self {
+            Inst::Int(op, ..) | Inst::IntImm(op, ..) => !matches!(op, IntOp::Div | IntOp::Rem),
+            Inst::Long(op, ..) => !matches!(op, LongOp::Div | LongOp::Rem),
+            Inst::Move(..)
+            | Inst::Const(..)
+            | Inst::Float(..)
+            | Inst::Double(..)
+            | Inst::Unary(..)
+            | Inst::Compare(..)
+            | Inst::GetStatic(..)
+            | Inst::InstanceOf(..) => true,
+            _ => false,
+        }
+    }
+
+    fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Inst::Goto(_)
+                | Inst::TableSwitch(..)
+                | Inst::LookupSwitch(..)
+                | Inst::Return(..)
+                | Inst::Deopt(_)
+        )
+    }
+
+    pub(super) fn target_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            Inst::If(.., target)
+            | Inst::IfImm(.., target)
+            | Inst::IfRef(.., target)
+            | Inst::IfNull(.., target)
+            | Inst::Goto(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Calls `f` with the index of every bytecode instruction the instruction
+    /// may jump to.
+    fn for_each_target(&self, code: &DecodedCode, mut f: impl FnMut(u32)) {
+        match *self {
+            Inst::If(.., target)
+            | Inst::IfImm(.., target)
+            | Inst::IfRef(.., target)
+            | Inst::IfNull(.., target)
+            | Inst::Goto(target) => f(target),
+            Inst::TableSwitch(_, switch) => code.table_switch(switch).targets().for_each(f),
+            Inst::LookupSwitch(_, switch) => code.lookup_switch(switch).targets().for_each(f),
+            _ => (),
+        }
+    }
+
+    /// Calls `f` with every register the instruction reads, which is both
+    /// registers of a `long` or `double`. A [`Deopt`] reads the locals and
+    /// the operand stack, where `locals` is the number of locals, and so do
+    /// calls, allocations and polls, where the garbage collector may look for
+    /// references.
+    ///
+    /// [`Deopt`]: Inst::Deopt
+    fn for_each_use(&self, locals: u16, mut f: impl FnMut(Reg)) {
+        match *self {
+            Inst::Call(call) => (0..call.base + call.slots).for_each(f),
+            Inst::New(_, _, safepoint) | Inst::Poll(safepoint) => {
+                (0..locals + safepoint.depth).for_each(f)
+            }
+            Inst::Deopt(deopt) => (0..locals + deopt.depth).for_each(f),
+            mut inst => inst.map_uses(|reg, wide| {
+                f(reg);
+                if wide {
+                    f(reg + 1);
+                }
+                reg
+            }),
+        }
+    }
+
+    /// Replaces every register the instruction reads as a value with the
+    /// result of `f`, which is told whether the value is a `long` or
+    /// `double`. The registers read by calls and [`Deopt`]s are fixed.
+    ///
+    /// [`Deopt`]: Inst::Deopt
+    fn map_uses(&mut self, mut f: impl FnMut(Reg, bool) -> Reg) {
+        match self {
+            Inst::Move(_, source)
+            | Inst::GetField(_, source, _)
+            | Inst::InstanceOf(_, source, _)
+            | Inst::CheckCast(source, _) => *source = f(*source, false),
+            Inst::Unary(op, _, source) => *source = f(*source, op.operand_type().size() == 2),
+            Inst::IntImm(_, _, lhs, _) | Inst::IfImm(_, lhs, _, _) | Inst::IfNull(_, lhs, _) => {
+                *lhs = f(*lhs, false)
+            }
+            Inst::Int(_, _, lhs, rhs)
+            | Inst::Float(_, _, lhs, rhs)
+            | Inst::If(_, lhs, rhs, _)
+            | Inst::IfRef(_, lhs, rhs, _) => {
+                *lhs = f(*lhs, false);
+                *rhs = f(*rhs, false);
+            }
+            Inst::Long(op, _, lhs, rhs) => {
+                *lhs = f(*lhs, true);
+                *rhs = f(*rhs, !op.is_shift());
+            }
+            Inst::Double(_, _, lhs, rhs) => {
+                *lhs = f(*lhs, true);
+                *rhs = f(*rhs, true);
+            }
+            Inst::Compare(op, _, lhs, rhs) => {
+                let wide = op.operand_type().size() == 2;
+                *lhs = f(*lhs, wide);
+                *rhs = f(*rhs, wide);
+            }
+            Inst::PutField(object, _, reg, value_type) => {
+                *object = f(*object, false);
+                *reg = f(*reg, value_type.size() == 2);
+            }
+            Inst::TableSwitch(reg, _) | Inst::LookupSwitch(reg, _) => *reg = f(*reg, false),
+            Inst::PutStatic(_, reg, value_type) => *reg = f(*reg, value_type.size() == 2),
+            Inst::Return(reg, slots) if *slots > 0 => *reg = f(*reg, *slots == 2),
+            _ => (),
+        }
+    }
+}
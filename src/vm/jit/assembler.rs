@@ -0,0 +1,440 @@
+//! A minimal x86-64 assembler, emitting the few instructions the compiler
+//! uses. Memory operands are always a base register and a displacement.
+
+/// A general purpose register. Only the registers which need no REX prefix
+/// are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Gpr {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rbp = 5,
+    Rsi = 6,
+    Rdi = 7,
+}
+
+/// An SSE register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Xmm(pub(super) u8);
+
+/// A memory operand at a displacement from a base register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Mem {
+    pub(super) base: Gpr,
+    pub(super) disp: i32,
+}
+
+/// The register or memory operand of an instruction, encoded in its ModRM
+/// byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Operand {
+    Reg(Gpr),
+    Xmm(Xmm),
+    Mem(Mem),
+}
+
+impl From<Gpr> for Operand {
+    fn from(reg: Gpr) -> Self {
+        Operand::Reg(reg)
+    }
+}
+
+impl From<Xmm> for Operand {
+    fn from(reg: Xmm) -> Self {
+        Operand::Xmm(reg)
+    }
+}
+
+impl From<Mem> for Operand {
+    fn from(mem: Mem) -> Self {
+        Operand::Mem(mem)
+    }
+}
+
+/// A condition code, as encoded in conditional jumps and `setcc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cond {
+    /// Below, for unsigned and floating-point comparisons.
+    B = 0x2,
+    E = 0x4,
+    Ne = 0x5,
+    /// Below or equal, for unsigned and floating-point comparisons.
+    Be = 0x6,
+    /// Above, for unsigned and floating-point comparisons.
+    A = 0x7,
+    /// Parity, set by an unordered floating-point comparison.
+    P = 0xa,
+    L = 0xc,
+    Ge = 0xd,
+    Le = 0xe,
+    G = 0xf,
+}
+
+/// An arithmetic or logic instruction with a register destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Alu {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+impl Alu {
+    /// Returns the opcode taking a register destination and a register or
+    /// memory source.
+    fn opcode(self) -> u8 {
+        match self {
+            Alu::Add => 0x03,
+            Alu::Or => 0x0b,
+            Alu::And => 0x23,
+            Alu::Sub => 0x2b,
+            Alu::Xor => 0x33,
+            Alu::Cmp => 0x3b,
+        }
+    }
+
+    /// Returns the opcode extension of the form taking an immediate.
+    fn extension(self) -> u8 {
+        match self {
+            Alu::Add => 0,
+            Alu::Or => 1,
+            Alu::And => 4,
+            Alu::Sub => 5,
+            Alu::Xor => 6,
+            Alu::Cmp => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Shift {
+    Shl = 4,
+    Shr = 5,
+    Sar = 7,
+}
+
+/// A scalar SSE arithmetic instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FloatOp {
+    Add = 0x58,
+    Mul = 0x59,
+    Sub = 0x5c,
+    Div = 0x5e,
+}
+
+/// A position in the code, which jumps may refer to before it is bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Label(usize);
+
+#[derive(Debug, Default)]
+pub(super) struct Assembler {
+    code: Vec<u8>,
+    /// The offset each label is bound to.
+    labels: Vec<Option<usize>>,
+    /// The offsets of the 32-bit displacements of jumps, and the label they
+    /// jump to.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the offset of the next instruction.
+    pub(super) fn offset(&self) -> usize {
+        self.code.len()
+    }
+
+    pub(super) fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds `label` to the offset of the next instruction.
+    pub(super) fn bind(&mut self, label: Label) {
+        debug_assert!(self.labels[label.0].is_none(), "label bound twice");
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    /// Returns the code, with the displacements of jumps resolved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a jump refers to a label which was never bound.
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        for (position, label) in self.fixups {
+            let target = self.labels[label.0].expect("jump to an unbound label");
+            let displacement = target as i64 - (position as i64 + 4);
+            self.code[position..position + 4].copy_from_slice(&(displacement as i32).to_le_bytes());
+        }
+        self.code
+    }
+
+    /// Emits an instruction with a ModRM byte, where `reg` is the register or
+    /// opcode extension in its `reg` field and `wide` selects 64-bit operands.
+    fn emit(&mut self, prefix: Option<u8>, wide: bool, opcode: &[u8], reg: u8, rm: Operand) {
+        self.code.extend(prefix);
+        if wide {
+            self.code.push(0x48);
+        }
+        self.code.extend_from_slice(opcode);
+        let reg = (reg & 7) << 3;
+        match rm {
+            Operand::Reg(rm) => self.code.push(0xc0 | reg | rm as u8),
+            Operand::Xmm(rm) => self.code.push(0xc0 | reg | rm.0),
+            Operand::Mem(Mem { base, disp }) => {
+                // A base of `rbp` needs a displacement even if it is zero.
+                if disp == 0 && base != Gpr::Rbp {
+                    self.code.push(reg | base as u8);
+                } else if let Ok(disp) = i8::try_from(disp) {
+                    self.code.push(0x40 | reg | base as u8);
+                    self.code.push(disp as u8);
+                } else {
+                    self.code.push(0x80 | reg | base as u8);
+                    self.code.extend_from_slice(&disp.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    pub(super) fn mov_load(&mut self, wide: bool, dst: Gpr, src: Mem) {
+        self.emit(None, wide, &[0x8b], dst as u8, src.into());
+    }
+
+    pub(super) fn mov_reg(&mut self, wide: bool, dst: Gpr, src: Gpr) {
+        self.emit(None, wide, &[0x8b], dst as u8, src.into());
+    }
+
+    pub(super) fn mov_store(&mut self, wide: bool, dst: Mem, src: Gpr) {
+        self.emit(None, wide, &[0x89], src as u8, dst.into());
+    }
+
+    /// Stores a 32-bit immediate, sign-extended if `wide`.
+    pub(super) fn mov_store_imm(&mut self, wide: bool, dst: Mem, imm: i32) {
+        self.emit(None, wide, &[0xc7], 0, dst.into());
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    pub(super) fn mov_imm32(&mut self, dst: Gpr, imm: i32) {
+        self.code.push(0xb8 + dst as u8);
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    pub(super) fn mov_imm64(&mut self, dst: Gpr, imm: i64) {
+        self.code.extend_from_slice(&[0x48, 0xb8 + dst as u8]);
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    pub(super) fn alu(&mut self, wide: bool, op: Alu, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, wide, &[op.opcode()], dst as u8, src.into());
+    }
+
+    pub(super) fn alu_imm(&mut self, wide: bool, op: Alu, dst: impl Into<Operand>, imm: i32) {
+        self.emit(None, wide, &[0x81], op.extension(), dst.into());
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    pub(super) fn imul(&mut self, wide: bool, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, wide, &[0x0f, 0xaf], dst as u8, src.into());
+    }
+
+    /// Shifts `dst` by the count in `cl`.
+    pub(super) fn shift(&mut self, wide: bool, shift: Shift, dst: impl Into<Operand>) {
+        self.emit(None, wide, &[0xd3], shift as u8, dst.into());
+    }
+
+    pub(super) fn neg(&mut self, wide: bool, dst: impl Into<Operand>) {
+        self.emit(None, wide, &[0xf7], 3, dst.into());
+    }
+
+    /// Divides `rdx:rax` by `src`, leaving the quotient in `rax` and the
+    /// remainder in `rdx`.
+    pub(super) fn idiv(&mut self, wide: bool, src: impl Into<Operand>) {
+        self.emit(None, wide, &[0xf7], 7, src.into());
+    }
+
+    /// Sign-extends `rax` into `rdx`, that is `cdq` or `cqo`.
+    pub(super) fn sign_extend_rax(&mut self, wide: bool) {
+        if wide {
+            self.code.push(0x48);
+        }
+        self.code.push(0x99);
+    }
+
+    pub(super) fn test(&mut self, wide: bool, lhs: impl Into<Operand>, rhs: Gpr) {
+        self.emit(None, wide, &[0x85], rhs as u8, lhs.into());
+    }
+
+    /// Sign-extends a 32-bit value into a 64-bit register.
+    pub(super) fn movsxd(&mut self, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, true, &[0x63], dst as u8, src.into());
+    }
+
+    /// Sign-extends a byte into a 32-bit register.
+    pub(super) fn movsx8(&mut self, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, false, &[0x0f, 0xbe], dst as u8, src.into());
+    }
+
+    /// Sign-extends a 16-bit value into a 32-bit register.
+    pub(super) fn movsx16(&mut self, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, false, &[0x0f, 0xbf], dst as u8, src.into());
+    }
+
+    /// Zero-extends a byte into a 32-bit register.
+    pub(super) fn movzx8(&mut self, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, false, &[0x0f, 0xb6], dst as u8, src.into());
+    }
+
+    /// Zero-extends a 16-bit value into a 32-bit register.
+    pub(super) fn movzx16(&mut self, dst: Gpr, src: impl Into<Operand>) {
+        self.emit(None, false, &[0x0f, 0xb7], dst as u8, src.into());
+    }
+
+    /// Sets the low byte of `dst` to whether `cond` holds.
+    pub(super) fn setcc(&mut self, cond: Cond, dst: Gpr) {
+        self.emit(None, false, &[0x0f, 0x90 + cond as u8], 0, dst.into());
+    }
+
+    /// Loads a `float`, or a `double` if `double`.
+    pub(super) fn float_load(&mut self, double: bool, dst: Xmm, src: Mem) {
+        self.emit(
+            Some(float_prefix(double)),
+            false,
+            &[0x0f, 0x10],
+            dst.0,
+            src.into(),
+        );
+    }
+
+    pub(super) fn float_store(&mut self, double: bool, dst: Mem, src: Xmm) {
+        self.emit(
+            Some(float_prefix(double)),
+            false,
+            &[0x0f, 0x11],
+            src.0,
+            dst.into(),
+        );
+    }
+
+    pub(super) fn float_op(
+        &mut self,
+        double: bool,
+        op: FloatOp,
+        dst: Xmm,
+        src: impl Into<Operand>,
+    ) {
+        self.emit(
+            Some(float_prefix(double)),
+            false,
+            &[0x0f, op as u8],
+            dst.0,
+            src.into(),
+        );
+    }
+
+    /// Compares two floating-point values, setting the flags like an
+    /// unsigned comparison, or the parity flag if either is `NaN`.
+    pub(super) fn ucomis(&mut self, double: bool, lhs: Xmm, rhs: impl Into<Operand>) {
+        let prefix = double.then_some(0x66);
+        self.emit(prefix, false, &[0x0f, 0x2e], lhs.0, rhs.into());
+    }
+
+    /// Converts a 32-bit integer, or a 64-bit one if `wide`, to a `float`, or
+    /// a `double` if `double`.
+    pub(super) fn int_to_float(
+        &mut self,
+        double: bool,
+        wide: bool,
+        dst: Xmm,
+        src: impl Into<Operand>,
+    ) {
+        self.emit(
+            Some(float_prefix(double)),
+            wide,
+            &[0x0f, 0x2a],
+            dst.0,
+            src.into(),
+        );
+    }
+
+    /// Converts a `float`, or a `double` if `double`, to a 32-bit integer, or
+    /// a 64-bit one if `wide`, rounding toward zero. `NaN` and values out of
+    /// range give the smallest integer.
+    pub(super) fn float_to_int(
+        &mut self,
+        double: bool,
+        wide: bool,
+        dst: Gpr,
+        src: impl Into<Operand>,
+    ) {
+        self.emit(
+            Some(float_prefix(double)),
+            wide,
+            &[0x0f, 0x2c],
+            dst as u8,
+            src.into(),
+        );
+    }
+
+    /// Converts a `float` to a `double`, or a `double` to a `float` if
+    /// `double`.
+    pub(super) fn float_to_float(&mut self, double: bool, dst: Xmm, src: impl Into<Operand>) {
+        self.emit(
+            Some(float_prefix(double)),
+            false,
+            &[0x0f, 0x5a],
+            dst.0,
+            src.into(),
+        );
+    }
+
+    pub(super) fn xorps(&mut self, dst: Xmm, src: Xmm) {
+        self.emit(None, false, &[0x0f, 0x57], dst.0, src.into());
+    }
+
+    pub(super) fn push(&mut self, reg: Gpr) {
+        self.code.push(0x50 + reg as u8);
+    }
+
+    pub(super) fn pop(&mut self, reg: Gpr) {
+        self.code.push(0x58 + reg as u8);
+    }
+
+    pub(super) fn ret(&mut self) {
+        self.code.push(0xc3);
+    }
+
+    /// Jumps to the address in `target`.
+    pub(super) fn jmp_reg(&mut self, target: Gpr) {
+        self.emit(None, false, &[0xff], 4, target.into());
+    }
+
+    pub(super) fn jmp(&mut self, label: Label) {
+        self.code.push(0xe9);
+        self.fixup(label);
+    }
+
+    pub(super) fn jcc(&mut self, cond: Cond, label: Label) {
+        self.code.extend_from_slice(&[0x0f, 0x80 + cond as u8]);
+        self.fixup(label);
+    }
+
+    fn fixup(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.code.extend_from_slice(&[0; 4]);
+    }
+}
+
+/// Returns the prefix selecting the scalar `double` or `float` form of an SSE
+/// instruction.
+fn float_prefix(double: bool) -> u8 {
+    if double {
+        0xf2
+    } else {
+        0xf3
+    }
+}
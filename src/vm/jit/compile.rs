@@ -0,0 +1,933 @@
+use std::cell::Cell;
+use std::mem::offset_of;
+
+use crate::vm::class::{Entry, Instruction, MethodFlags};
+use crate::vm::decode::{DecodedCode, FieldId, MethodId, Op};
+use crate::vm::{ClassManager, Resolved};
+
+use super::assembler::{Alu, Assembler, Cond, FloatOp, Gpr, Label, Mem, Shift, Xmm};
+use super::{Compiled, ExecutableMemory, ExitState, EXIT_CALL, EXIT_FALLBACK, EXIT_RETURN};
+
+const XMM0: Xmm = Xmm(0);
+const XMM1: Xmm = Xmm(1);
+
+/// The register holding the address of the first slot of the frame.
+const SLOTS: Gpr = Gpr::Rbx;
+/// The register holding the address of the [`ExitState`].
+const EXIT: Gpr = Gpr::Rbp;
+
+/// Compiles the code of a method, or returns `None` if it uses stacks of
+/// different heights at the same instruction, accesses a local or stack slot
+/// outside the frame, or the code cannot be mapped.
+///
+/// Every instruction is translated on its own, loading its operands from the
+/// slots of the frame and storing its result back, so that the code can be
+/// entered before any instruction. Instructions which are not implemented,
+/// and those which have not been quickened, leave the code for the bytecode
+/// interpreter.
+pub(super) fn compile(
+    classes: &ClassManager,
+    method: MethodId,
+    code: &DecodedCode,
+) -> Option<Compiled> {
+    let class = &classes.classes[method.class as usize].class;
+    let bytecode = class.methods()[method.method as usize].bytecode()?;
+    let instructions: Vec<Instruction> = bytecode.bytecode().map(|(_, inst)| inst).collect();
+    let mut compiler = Compiler::new(classes, method.class as usize, code, &instructions);
+
+    // The depth of the operand stack before each instruction, found by
+    // following the jumps from the start of the method. The code is
+    // translated once to find them, and thrown away. Instructions which are
+    // only reached through exception handlers are left to the interpreter.
+    let mut depths: Vec<Option<i32>> = vec![None; code.len()];
+    let mut worklist = vec![(0, 0)];
+    while let Some((pc, depth)) = worklist.pop() {
+        match depths[pc] {
+            Some(known) if known == depth => continue,
+            Some(_) => return None,
+            None => depths[pc] = Some(depth),
+        }
+        let Some(depth) = compiler.translate(pc, code.get(pc)?, depth) else {
+            continue;
+        };
+        if !(0..=i32::from(code.max_stack)).contains(&depth) {
+            return None;
+        }
+        for &target in &compiler.targets {
+            worklist.push((target as usize, depth));
+        }
+        if compiler.falls_through && pc + 1 < code.len() {
+            worklist.push((pc + 1, depth));
+        }
+    }
+
+    compiler.reset();
+    compiler.prologue();
+    let mut entries = vec![u32::MAX; code.len()].into_boxed_slice();
+    for (pc, depth) in depths.into_iter().enumerate() {
+        let Some(depth) = depth else {
+            continue;
+        };
+        compiler.asm.bind(compiler.labels[pc]);
+        entries[pc] = compiler.asm.offset() as u32;
+        compiler.translate(pc, code.get(pc)?, depth);
+    }
+    compiler.epilogue();
+    if compiler.out_of_bounds.get() {
+        return None;
+    }
+
+    let memory = ExecutableMemory::new(&compiler.asm.finish())?;
+    Some(Compiled {
+        memory,
+        entries,
+        unquickened: compiler.unquickened.into_boxed_slice(),
+    })
+}
+
+struct Compiler<'a> {
+    classes: &'a ClassManager,
+    class: usize,
+    code: &'a DecodedCode,
+    instructions: &'a [Instruction<'a>],
+    locals: u16,
+    /// Whether any instruction translated so far accesses a local or stack
+    /// slot outside the frame.
+    out_of_bounds: Cell<bool>,
+    asm: Assembler,
+    /// The label of the code of each instruction.
+    labels: Vec<Label>,
+    /// The exits to the interpreter before each instruction, with the depth of
+    /// the operand stack, created when first jumped to.
+    fallbacks: Vec<Option<(Label, i32)>>,
+    /// The code counting down the time slice before each backward jump, with
+    /// the target of the jump and the depth of the operand stack there.
+    polls: Vec<(Label, u32, i32)>,
+    epilogue: Label,
+    /// The instructions the current instruction jumps to.
+    targets: Vec<u32>,
+    /// Whether execution continues with the next instruction, either in the
+    /// code or in the interpreter.
+    falls_through: bool,
+    /// Whether each instruction leaves the code because it has not been
+    /// quickened.
+    unquickened: Vec<bool>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(
+        classes: &'a ClassManager,
+        class: usize,
+        code: &'a DecodedCode,
+        instructions: &'a [Instruction<'a>],
+    ) -> Self {
+        let mut asm = Assembler::new();
+        let labels = (0..code.len()).map(|_| asm.new_label()).collect();
+        let epilogue = asm.new_label();
+        Self {
+            classes,
+            class,
+            code,
+            instructions,
+            locals: code.max_locals,
+            out_of_bounds: Cell::new(false),
+            asm,
+            labels,
+            fallbacks: vec![None; code.len()],
+            polls: Vec::new(),
+            epilogue,
+            targets: Vec::new(),
+            falls_through: true,
+            unquickened: vec![false; code.len()],
+        }
+    }
+
+    /// Discards the code translated so far.
+    fn reset(&mut self) {
+        let out_of_bounds = self.out_of_bounds.get();
+        *self = Self::new(self.classes, self.class, self.code, self.instructions);
+        self.out_of_bounds.set(out_of_bounds);
+    }
+
+    /// Emits the code the method is called through, which takes the address of
+    /// the slots of the frame, the address to start at and the address of the
+    /// [`ExitState`].
+    fn prologue(&mut self) {
+        self.asm.push(Gpr::Rbp);
+        self.asm.push(Gpr::Rbx);
+        self.asm.mov_reg(true, SLOTS, Gpr::Rdi);
+        self.asm.mov_reg(true, EXIT, Gpr::Rdx);
+        self.asm.jmp_reg(Gpr::Rsi);
+    }
+
+    /// Emits the polls of the backward jumps, the exits to the interpreter and
+    /// the code returning from the method, which expects the exit code in
+    /// `eax`.
+    fn epilogue(&mut self) {
+        let budget = exit_field(offset_of!(ExitState, budget));
+        for (label, target, depth) in std::mem::take(&mut self.polls) {
+            self.asm.bind(label);
+            self.asm.alu_imm(false, Alu::Sub, budget, 1);
+            let fallback = self.fallback_label(target as usize, depth);
+            self.asm.jcc(Cond::Le, fallback);
+            self.asm.jmp(self.labels[target as usize]);
+        }
+        for (pc, fallback) in std::mem::take(&mut self.fallbacks).into_iter().enumerate() {
+            let Some((label, depth)) = fallback else {
+                continue;
+            };
+            self.asm.bind(label);
+            self.asm
+                .mov_store_imm(false, exit_field(offset_of!(ExitState, pc)), pc as i32);
+            self.asm
+                .mov_store_imm(false, exit_field(offset_of!(ExitState, depth)), depth);
+            self.asm.mov_imm32(Gpr::Rax, EXIT_FALLBACK as i32);
+            self.asm.jmp(self.epilogue);
+        }
+        self.asm.bind(self.epilogue);
+        self.asm.pop(Gpr::Rbx);
+        self.asm.pop(Gpr::Rbp);
+        self.asm.ret();
+    }
+
+    /// Returns the local at `index`, noting if it lies outside the frame.
+    fn local(&self, index: u16) -> Mem {
+        if index >= self.locals {
+            self.out_of_bounds.set(true);
+        }
+        frame_slot(i32::from(index))
+    }
+
+    /// Returns the local taking two slots at `index`.
+    fn local_wide(&self, index: u16) -> Mem {
+        if u32::from(index) + 1 >= u32::from(self.locals) {
+            self.out_of_bounds.set(true);
+        }
+        frame_slot(i32::from(index))
+    }
+
+    /// Returns the slot of the operand stack at `depth`, noting if it lies
+    /// outside the frame.
+    fn slot(&self, depth: i32) -> Mem {
+        if !(0..i32::from(self.code.max_stack)).contains(&depth) {
+            self.out_of_bounds.set(true);
+        }
+        frame_slot(i32::from(self.locals) + depth)
+    }
+
+    /// Translates `op` at `pc`, which sees `depth` slots on the operand stack,
+    /// and returns the depth after it, or `None` if it is not known.
+    fn translate(&mut self, pc: usize, op: Op, depth: i32) -> Option<i32> {
+        self.targets.clear();
+        self.falls_through = true;
+        let d = depth;
+
+        let depth = match op {
+            // Constant
+            Op::nop => d,
+            Op::aconst_null => self.constant(d, 0),
+            Op::iconst(val) => self.constant(d, val),
+            Op::fconst(val) => self.constant(d, val.to_bits() as i32),
+            Op::lconst(val) => self.constant_wide(d, val),
+            Op::dconst(val) => self.constant_wide(d, val.to_bits() as i64),
+            Op::ldc(idx) => {
+                let constants = self.classes.classes[self.class].class.constants();
+                match constants.get(idx) {
+                    Entry::Integer(val) => self.constant(d, *val),
+                    Entry::Float(val) => self.constant(d, val.to_bits() as i32),
+                    Entry::Long(val) => self.constant_wide(d, *val),
+                    Entry::Double(val) => self.constant_wide(d, val.to_bits() as i64),
+                    _ => self.fallback(pc, d, d + 1),
+                }
+            }
+
+            // Load and store
+            Op::iload(idx) | Op::fload(idx) | Op::aload(idx) => {
+                self.copy(false, self.slot(d), self.local(idx));
+                d + 1
+            }
+            Op::lload(idx) | Op::dload(idx) => {
+                self.copy(true, self.slot(d), self.local_wide(idx));
+                d + 2
+            }
+            Op::istore(idx) | Op::fstore(idx) | Op::astore(idx) => {
+                self.copy(false, self.local(idx), self.slot(d - 1));
+                d - 1
+            }
+            Op::lstore(idx) | Op::dstore(idx) => {
+                self.copy(true, self.local_wide(idx), self.slot(d - 2));
+                d - 2
+            }
+            Op::iinc(idx, constant) => {
+                self.asm
+                    .alu_imm(false, Alu::Add, self.local(idx), constant as i32);
+                d
+            }
+            Op::iaload | Op::faload | Op::aaload | Op::baload | Op::caload | Op::saload => {
+                self.fallback(pc, d, d - 1)
+            }
+            Op::laload | Op::daload => self.fallback(pc, d, d),
+            Op::iastore | Op::fastore | Op::aastore | Op::bastore | Op::castore | Op::sastore => {
+                self.fallback(pc, d, d - 3)
+            }
+            Op::lastore | Op::dastore => self.fallback(pc, d, d - 4),
+
+            // Stack
+            Op::pop => d - 1,
+            Op::pop2 => d - 2,
+            Op::dup => self.dup(d, 1, 0),
+            Op::dup_x1 => self.dup(d, 1, 1),
+            Op::dup_x2 => self.dup(d, 1, 2),
+            Op::dup2 => self.dup(d, 2, 0),
+            Op::dup2_x1 => self.dup(d, 2, 1),
+            Op::dup2_x2 => self.dup(d, 2, 2),
+            Op::swap => {
+                let (lower, upper) = (self.slot(d - 2), self.slot(d - 1));
+                self.asm.mov_load(false, Gpr::Rax, upper);
+                self.asm.mov_load(false, Gpr::Rcx, lower);
+                self.asm.mov_store(false, upper, Gpr::Rcx);
+                self.asm.mov_store(false, lower, Gpr::Rax);
+                d
+            }
+
+            // Math
+            Op::iadd => self.int(Alu::Add, d),
+            Op::isub => self.int(Alu::Sub, d),
+            Op::iand => self.int(Alu::And, d),
+            Op::ior => self.int(Alu::Or, d),
+            Op::ixor => self.int(Alu::Xor, d),
+            Op::ladd => self.long(Alu::Add, d),
+            Op::lsub => self.long(Alu::Sub, d),
+            Op::land => self.long(Alu::And, d),
+            Op::lor => self.long(Alu::Or, d),
+            Op::lxor => self.long(Alu::Xor, d),
+            Op::imul | Op::lmul => {
+                let wide = matches!(op, Op::lmul);
+                let size = if wide { 2 } else { 1 };
+                let (lhs, rhs) = (self.slot(d - 2 * size), self.slot(d - size));
+                self.asm.mov_load(wide, Gpr::Rax, lhs);
+                self.asm.imul(wide, Gpr::Rax, rhs);
+                self.asm.mov_store(wide, lhs, Gpr::Rax);
+                d - size
+            }
+            Op::idiv => self.divide(pc, d, false, false),
+            Op::irem => self.divide(pc, d, false, true),
+            Op::ldiv => self.divide(pc, d, true, false),
+            Op::lrem => self.divide(pc, d, true, true),
+            Op::ishl => self.shift(Shift::Shl, false, d),
+            Op::ishr => self.shift(Shift::Sar, false, d),
+            Op::iushr => self.shift(Shift::Shr, false, d),
+            Op::lshl => self.shift(Shift::Shl, true, d),
+            Op::lshr => self.shift(Shift::Sar, true, d),
+            Op::lushr => self.shift(Shift::Shr, true, d),
+            Op::fadd => self.float(FloatOp::Add, false, d),
+            Op::fsub => self.float(FloatOp::Sub, false, d),
+            Op::fmul => self.float(FloatOp::Mul, false, d),
+            Op::fdiv => self.float(FloatOp::Div, false, d),
+            Op::dadd => self.float(FloatOp::Add, true, d),
+            Op::dsub => self.float(FloatOp::Sub, true, d),
+            Op::dmul => self.float(FloatOp::Mul, true, d),
+            Op::ddiv => self.float(FloatOp::Div, true, d),
+            Op::frem => self.fallback(pc, d, d - 1),
+            Op::drem => self.fallback(pc, d, d - 2),
+            Op::ineg => {
+                self.asm.neg(false, self.slot(d - 1));
+                d
+            }
+            Op::lneg => {
+                self.asm.neg(true, self.slot(d - 2));
+                d
+            }
+            // Negating a floating-point value flips its sign bit, which is in
+            // the high half of a `double`.
+            Op::fneg | Op::dneg => {
+                self.asm
+                    .alu_imm(false, Alu::Xor, self.slot(d - 1), i32::MIN);
+                d
+            }
+
+            // Conversion
+            Op::i2l => {
+                let slot = self.slot(d - 1);
+                self.asm.movsxd(Gpr::Rax, slot);
+                self.asm.mov_store(true, slot, Gpr::Rax);
+                d + 1
+            }
+            Op::i2f => self.int_to_float(false, false, d),
+            Op::i2d => self.int_to_float(true, false, d),
+            // The low half of a `long` is its value as an `int`.
+            Op::l2i => d - 1,
+            Op::l2f => self.int_to_float(false, true, d),
+            Op::l2d => self.int_to_float(true, true, d),
+            Op::f2i => self.float_to_int(false, false, d),
+            Op::f2l => self.float_to_int(false, true, d),
+            Op::d2i => self.float_to_int(true, false, d),
+            Op::d2l => self.float_to_int(true, true, d),
+            Op::f2d | Op::d2f => {
+                let double = matches!(op, Op::d2f);
+                let slot = self.slot(if double { d - 2 } else { d - 1 });
+                self.asm.float_to_float(double, XMM0, slot);
+                self.asm.float_store(!double, slot, XMM0);
+                if double {
+                    d - 1
+                } else {
+                    d + 1
+                }
+            }
+            Op::i2b | Op::i2c | Op::i2s => {
+                let slot = self.slot(d - 1);
+                match op {
+                    Op::i2b => self.asm.movsx8(Gpr::Rax, slot),
+                    Op::i2c => self.asm.movzx16(Gpr::Rax, slot),
+                    _ => self.asm.movsx16(Gpr::Rax, slot),
+                }
+                self.asm.mov_store(false, slot, Gpr::Rax);
+                d
+            }
+
+            // Comparison
+            Op::lcmp => {
+                let (lhs, rhs) = (self.slot(d - 4), self.slot(d - 2));
+                self.asm.mov_load(true, Gpr::Rax, lhs);
+                self.asm.alu(true, Alu::Cmp, Gpr::Rax, rhs);
+                self.sign(Cond::G, Cond::L, lhs);
+                d - 3
+            }
+            Op::fcmp(greater_if_nan) => self.float_compare(false, greater_if_nan, d),
+            Op::dcmp(greater_if_nan) => self.float_compare(true, greater_if_nan, d),
+            Op::if_eq(target) => self.if_zero(pc, Cond::E, d, target),
+            Op::if_ne(target) => self.if_zero(pc, Cond::Ne, d, target),
+            Op::if_lt(target) => self.if_zero(pc, Cond::L, d, target),
+            Op::if_ge(target) => self.if_zero(pc, Cond::Ge, d, target),
+            Op::if_gt(target) => self.if_zero(pc, Cond::G, d, target),
+            Op::if_le(target) => self.if_zero(pc, Cond::Le, d, target),
+            Op::ifnull(target) => self.if_zero(pc, Cond::E, d, target),
+            Op::ifnonnull(target) => self.if_zero(pc, Cond::Ne, d, target),
+            Op::if_icmp_eq(target) | Op::if_acmp_eq(target) => self.if_cmp(pc, Cond::E, d, target),
+            Op::if_icmp_ne(target) | Op::if_acmp_ne(target) => self.if_cmp(pc, Cond::Ne, d, target),
+            Op::if_icmp_lt(target) => self.if_cmp(pc, Cond::L, d, target),
+            Op::if_icmp_ge(target) => self.if_cmp(pc, Cond::Ge, d, target),
+            Op::if_icmp_gt(target) => self.if_cmp(pc, Cond::G, d, target),
+            Op::if_icmp_le(target) => self.if_cmp(pc, Cond::Le, d, target),
+
+            // Control
+            Op::goto(target) => {
+                let label = self.jump_label(pc, target, d);
+                self.asm.jmp(label);
+                self.targets.push(target);
+                self.falls_through = false;
+                d
+            }
+            Op::tableswitch(switch) => {
+                let switch = self.code.table_switch(switch);
+                self.switch(pc, switch.cases(), switch.default_target(), d)
+            }
+            Op::lookupswitch(switch) => {
+                let switch = self.code.lookup_switch(switch);
+                self.switch(pc, switch.cases(), switch.default_target(), d)
+            }
+            Op::ireturn | Op::freturn | Op::areturn => return self.ret(pc, d, 1),
+            Op::lreturn | Op::dreturn => return self.ret(pc, d, 2),
+            Op::ret_void => return self.ret(pc, d, 0),
+
+            // Quick
+            Op::getstatic_quick(field) => {
+                let size = self.field_size(field);
+                self.fallback(pc, d, d + size)
+            }
+            Op::putstatic_quick(_, value_type) => {
+                self.fallback(pc, d, d - value_type.size() as i32)
+            }
+            Op::getfield_quick(_) => {
+                let size = self.instance_field_size(pc)?;
+                self.fallback(pc, d, d - 1 + size)
+            }
+            Op::putfield_quick(_, value_type) => {
+                self.fallback(pc, d, d - 1 - value_type.size() as i32)
+            }
+            Op::invokestatic_quick(method) => {
+                let descriptor = self.classes.method_info(method).parsed_descriptor();
+                let slots = descriptor.arg_slots(true) as i32;
+                let result = descriptor.result().map_or(0, |result| result.slots());
+                self.call(pc, d - slots, method);
+                d - slots + result as i32
+            }
+            Op::invokespecial_quick(method) => {
+                let descriptor = self.classes.method_info(method).parsed_descriptor();
+                let slots = descriptor.arg_slots(false) as i32;
+                let result = descriptor.result().map_or(0, |result| result.slots());
+                self.fallback(pc, d, d - slots + result as i32)
+            }
+            Op::invokevirtual_quick(call) => {
+                let result = self.result_size(pc)?;
+                self.fallback(pc, d, d - i32::from(call.slots) + result)
+            }
+            Op::invokeinterface_quick(call) => {
+                let result = self.result_size(pc)?;
+                self.fallback(pc, d, d - i32::from(call.slots) + result)
+            }
+            Op::new_quick(_) | Op::ldc_class_quick(_) => self.fallback(pc, d, d + 1),
+            Op::invokenative(_) => {
+                let after = self.native_depth(pc, d)?;
+                self.fallback(pc, d, after)
+            }
+            Op::newarray(_)
+            | Op::anewarray(_)
+            | Op::newarray_quick(_)
+            | Op::arraylength
+            | Op::checkcast(_)
+            | Op::instanceof(_)
+            | Op::checkcast_quick(_)
+            | Op::instanceof_quick(_) => self.fallback(pc, d, d),
+            Op::monitorenter | Op::monitorexit => self.fallback(pc, d, d - 1),
+            Op::multianewarray(_, dimensions) => self.fallback(pc, d, d + 1 - dimensions as i32),
+
+            // Instructions which have never run, whose effect on the stack is
+            // not known until they are resolved
+            Op::getstatic(_)
+            | Op::putstatic(_)
+            | Op::getfield(_)
+            | Op::putfield(_)
+            | Op::invokevirtual(_)
+            | Op::invokespecial(_)
+            | Op::invokestatic(_)
+            | Op::invokeinterface(..)
+            | Op::new(_) => {
+                self.unquickened[pc] = true;
+                self.fallback(pc, d, d);
+                return None;
+            }
+
+            // Instructions which do not continue with the next one, or whose
+            // effect on the stack is not known
+            Op::jsr(_) | Op::ret(_) | Op::athrow | Op::invokedynamic(_) => {
+                self.fallback(pc, d, d);
+                return None;
+            }
+        };
+        Some(depth)
+    }
+
+    fn copy(&mut self, wide: bool, dst: Mem, src: Mem) {
+        self.asm.mov_load(wide, Gpr::Rax, src);
+        self.asm.mov_store(wide, dst, Gpr::Rax);
+    }
+
+    fn constant(&mut self, depth: i32, val: i32) -> i32 {
+        self.asm.mov_store_imm(false, self.slot(depth), val);
+        depth + 1
+    }
+
+    fn constant_wide(&mut self, depth: i32, val: i64) -> i32 {
+        self.asm.mov_store_imm(false, self.slot(depth), val as i32);
+        self.asm
+            .mov_store_imm(false, self.slot(depth + 1), (val >> 32) as i32);
+        depth + 2
+    }
+
+    /// Returns the label the instruction at `pc` jumps to for `target`, which
+    /// sees `depth` slots on the operand stack. A backward jump goes through a
+    /// poll, which leaves the code for the interpreter at `target` once the
+    /// time slice is used up.
+    fn jump_label(&mut self, pc: usize, target: u32, depth: i32) -> Label {
+        if target as usize > pc {
+            return self.labels[target as usize];
+        }
+        let label = self.asm.new_label();
+        self.polls.push((label, target, depth));
+        label
+    }
+
+    /// Leaves the code for the interpreter to run the instruction at `pc`, and
+    /// returns the depth of the stack after the interpreter runs it.
+    fn fallback(&mut self, pc: usize, depth: i32, after: i32) -> i32 {
+        let label = self.fallback_label(pc, depth);
+        self.asm.jmp(label);
+        after
+    }
+
+    /// Returns the label of the exit to the interpreter before the instruction
+    /// at `pc`.
+    fn fallback_label(&mut self, pc: usize, depth: i32) -> Label {
+        match self.fallbacks[pc] {
+            Some((label, _)) => label,
+            None => {
+                let label = self.asm.new_label();
+                self.fallbacks[pc] = Some((label, depth));
+                label
+            }
+        }
+    }
+
+    /// Duplicates the top `size` slots of the stack beneath the `skip` slots
+    /// below them.
+    fn dup(&mut self, depth: i32, size: i32, skip: i32) -> i32 {
+        let base = depth - size - skip;
+        for i in 0..size {
+            self.copy(false, self.slot(depth + i), self.slot(base + skip + i));
+        }
+        if skip > 0 {
+            for i in (0..skip).rev() {
+                self.copy(false, self.slot(base + size + i), self.slot(base + i));
+            }
+            for i in 0..size {
+                self.copy(false, self.slot(base + i), self.slot(depth + i));
+            }
+        }
+        depth + size
+    }
+
+    fn int(&mut self, op: Alu, depth: i32) -> i32 {
+        let (lhs, rhs) = (self.slot(depth - 2), self.slot(depth - 1));
+        self.asm.mov_load(false, Gpr::Rax, lhs);
+        self.asm.alu(false, op, Gpr::Rax, rhs);
+        self.asm.mov_store(false, lhs, Gpr::Rax);
+        depth - 1
+    }
+
+    fn long(&mut self, op: Alu, depth: i32) -> i32 {
+        let (lhs, rhs) = (self.slot(depth - 4), self.slot(depth - 2));
+        self.asm.mov_load(true, Gpr::Rax, lhs);
+        self.asm.alu(true, op, Gpr::Rax, rhs);
+        self.asm.mov_store(true, lhs, Gpr::Rax);
+        depth - 2
+    }
+
+    /// Divides two `int`s, or two `long`s if `wide`, leaving a division by
+    /// zero to the interpreter.
+    fn divide(&mut self, pc: usize, depth: i32, wide: bool, remainder: bool) -> i32 {
+        let size = if wide { 2 } else { 1 };
+        let (lhs, rhs) = (self.slot(depth - 2 * size), self.slot(depth - size));
+        let by_minus_one = self.asm.new_label();
+        let done = self.asm.new_label();
+
+        self.asm.mov_load(wide, Gpr::Rcx, rhs);
+        self.asm.test(wide, Gpr::Rcx, Gpr::Rcx);
+        let by_zero = self.fallback_label(pc, depth);
+        self.asm.jcc(Cond::E, by_zero);
+
+        // Dividing the smallest integer by -1 overflows, which traps, so
+        // division by -1 is a negation.
+        self.asm.mov_load(wide, Gpr::Rax, lhs);
+        self.asm.alu_imm(wide, Alu::Cmp, Gpr::Rcx, -1);
+        self.asm.jcc(Cond::E, by_minus_one);
+        self.asm.sign_extend_rax(wide);
+        self.asm.idiv(wide, Gpr::Rcx);
+        let result = if remainder { Gpr::Rdx } else { Gpr::Rax };
+        self.asm.mov_store(wide, lhs, result);
+        self.asm.jmp(done);
+
+        self.asm.bind(by_minus_one);
+        if remainder {
+            self.asm.alu(false, Alu::Xor, Gpr::Rax, Gpr::Rax);
+        } else {
+            self.asm.neg(wide, Gpr::Rax);
+        }
+        self.asm.mov_store(wide, lhs, Gpr::Rax);
+        self.asm.bind(done);
+        depth - size
+    }
+
+    /// Shifts an `int`, or a `long` if `wide`, by an `int`. The processor
+    /// masks the distance like Java does.
+    fn shift(&mut self, shift: Shift, wide: bool, depth: i32) -> i32 {
+        let value = self.slot(depth - if wide { 3 } else { 2 });
+        self.asm.mov_load(false, Gpr::Rcx, self.slot(depth - 1));
+        self.asm.shift(wide, shift, value);
+        depth - 1
+    }
+
+    fn float(&mut self, op: FloatOp, double: bool, depth: i32) -> i32 {
+        let size = if double { 2 } else { 1 };
+        let (lhs, rhs) = (self.slot(depth - 2 * size), self.slot(depth - size));
+        self.asm.float_load(double, XMM0, lhs);
+        self.asm.float_op(double, op, XMM0, rhs);
+        self.asm.float_store(double, lhs, XMM0);
+        depth - size
+    }
+
+    /// Converts the `int`, or `long` if `wide`, on top of the stack to a
+    /// `float`, or a `double` if `double`.
+    fn int_to_float(&mut self, double: bool, wide: bool, depth: i32) -> i32 {
+        let (size, result) = (if wide { 2 } else { 1 }, if double { 2 } else { 1 });
+        let slot = self.slot(depth - size);
+        self.asm.int_to_float(double, wide, XMM0, slot);
+        self.asm.float_store(double, slot, XMM0);
+        depth - size + result
+    }
+
+    /// Converts the `float`, or `double` if `double`, on top of the stack to
+    /// an `int`, or a `long` if `wide`.
+    ///
+    /// The processor gives the smallest integer for `NaN` and values out of
+    /// range, while Java gives zero for `NaN` and the largest integer for
+    /// large positive values.
+    fn float_to_int(&mut self, double: bool, wide: bool, depth: i32) -> i32 {
+        let (size, result) = (if double { 2 } else { 1 }, if wide { 2 } else { 1 });
+        let slot = self.slot(depth - size);
+        let (nan, done) = (self.asm.new_label(), self.asm.new_label());
+
+        self.asm.float_to_int(double, wide, Gpr::Rax, slot);
+        if wide {
+            self.asm.mov_imm64(Gpr::Rcx, i64::MIN);
+            self.asm.alu(true, Alu::Cmp, Gpr::Rax, Gpr::Rcx);
+        } else {
+            self.asm.alu_imm(false, Alu::Cmp, Gpr::Rax, i32::MIN);
+        }
+        self.asm.jcc(Cond::Ne, done);
+        self.asm.float_load(double, XMM0, slot);
+        self.asm.ucomis(double, XMM0, XMM0);
+        self.asm.jcc(Cond::P, nan);
+        self.asm.xorps(XMM1, XMM1);
+        self.asm.ucomis(double, XMM0, XMM1);
+        self.asm.jcc(Cond::Be, done);
+        if wide {
+            self.asm.mov_imm64(Gpr::Rax, i64::MAX);
+        } else {
+            self.asm.mov_imm32(Gpr::Rax, i32::MAX);
+        }
+        self.asm.jmp(done);
+        self.asm.bind(nan);
+        self.asm.alu(false, Alu::Xor, Gpr::Rax, Gpr::Rax);
+        self.asm.bind(done);
+        self.asm.mov_store(wide, slot, Gpr::Rax);
+        depth - size + result
+    }
+
+    /// Stores `1` to `dst` if `greater` holds, `-1` if `less` holds, and `0`
+    /// otherwise.
+    fn sign(&mut self, greater: Cond, less: Cond, dst: Mem) {
+        self.asm.setcc(greater, Gpr::Rax);
+        self.asm.movzx8(Gpr::Rax, Gpr::Rax);
+        self.asm.setcc(less, Gpr::Rcx);
+        self.asm.movzx8(Gpr::Rcx, Gpr::Rcx);
+        self.asm.alu(false, Alu::Sub, Gpr::Rax, Gpr::Rcx);
+        self.asm.mov_store(false, dst, Gpr::Rax);
+    }
+
+    fn float_compare(&mut self, double: bool, greater_if_nan: bool, depth: i32) -> i32 {
+        let size = if double { 2 } else { 1 };
+        let (lhs, rhs) = (self.slot(depth - 2 * size), self.slot(depth - size));
+        let (nan, done) = (self.asm.new_label(), self.asm.new_label());
+        self.asm.float_load(double, XMM0, lhs);
+        self.asm.ucomis(double, XMM0, rhs);
+        self.asm.jcc(Cond::P, nan);
+        self.sign(Cond::A, Cond::B, lhs);
+        self.asm.jmp(done);
+        self.asm.bind(nan);
+        let val = if greater_if_nan { 1 } else { -1 };
+        self.asm.mov_store_imm(false, lhs, val);
+        self.asm.bind(done);
+        depth - 2 * size + 1
+    }
+
+    /// Jumps if comparing the `int` or reference on top of the stack to zero
+    /// satisfies `cond`.
+    fn if_zero(&mut self, pc: usize, cond: Cond, depth: i32, target: u32) -> i32 {
+        self.asm.alu_imm(false, Alu::Cmp, self.slot(depth - 1), 0);
+        let label = self.jump_label(pc, target, depth - 1);
+        self.asm.jcc(cond, label);
+        self.targets.push(target);
+        depth - 1
+    }
+
+    fn if_cmp(&mut self, pc: usize, cond: Cond, depth: i32, target: u32) -> i32 {
+        self.asm.mov_load(false, Gpr::Rax, self.slot(depth - 2));
+        self.asm
+            .alu(false, Alu::Cmp, Gpr::Rax, self.slot(depth - 1));
+        let label = self.jump_label(pc, target, depth - 2);
+        self.asm.jcc(cond, label);
+        self.targets.push(target);
+        depth - 2
+    }
+
+    /// Jumps to the target of the key on top of the stack, comparing it to
+    /// each key in turn.
+    fn switch(
+        &mut self,
+        pc: usize,
+        cases: impl Iterator<Item = (i32, u32)>,
+        default: u32,
+        depth: i32,
+    ) -> i32 {
+        self.asm.mov_load(false, Gpr::Rax, self.slot(depth - 1));
+        for (key, target) in cases {
+            self.asm.alu_imm(false, Alu::Cmp, Gpr::Rax, key);
+            let label = self.jump_label(pc, target, depth - 1);
+            self.asm.jcc(Cond::E, label);
+            self.targets.push(target);
+        }
+        let label = self.jump_label(pc, default, depth - 1);
+        self.asm.jmp(label);
+        self.targets.push(default);
+        self.falls_through = false;
+        depth - 1
+    }
+
+    /// Returns the value taking `size` slots on top of the stack, moving it to
+    /// the first slot of the frame. A `synchronized` method returns through
+    /// the interpreter, which exits its monitor.
+    fn ret(&mut self, pc: usize, depth: i32, size: i32) -> Option<i32> {
+        if self.code.synchronized {
+            let label = self.fallback_label(pc, depth);
+            self.asm.jmp(label);
+            self.falls_through = false;
+            return None;
+        }
+        if size > 0 {
+            self.copy(size == 2, frame_slot(0), self.slot(depth - size));
+        }
+        let field = exit_field(offset_of!(ExitState, depth));
+        self.asm.mov_store_imm(false, field, size);
+        self.asm.mov_imm32(Gpr::Rax, EXIT_RETURN as i32);
+        self.asm.jmp(self.epilogue);
+        self.falls_through = false;
+        None
+    }
+
+    /// Leaves the code to call `method`, whose arguments start at `base` on
+    /// the stack, and resume after the call at `pc`.
+    fn call(&mut self, pc: usize, base: i32, method: MethodId) {
+        if base < 0 {
+            self.out_of_bounds.set(true);
+        }
+        let fields = [
+            (offset_of!(ExitState, pc), pc as i32 + 1),
+            (offset_of!(ExitState, depth), base),
+            (offset_of!(ExitState, class), method.class as i32),
+            (offset_of!(ExitState, method), method.method as i32),
+        ];
+        for (offset, val) in fields {
+            self.asm.mov_store_imm(false, exit_field(offset), val);
+        }
+        self.asm.mov_imm32(Gpr::Rax, EXIT_CALL as i32);
+        self.asm.jmp(self.epilogue);
+    }
+
+    fn field_size(&self, field: FieldId) -> i32 {
+        let class = &self.classes.classes[field.class as usize].class;
+        class.fields()[field.field as usize]
+            .parsed_descriptor()
+            .slots() as i32
+    }
+
+    /// Returns the number of slots taken by the field a quickened `getfield`
+    /// at `pc` accesses.
+    fn instance_field_size(&self, pc: usize) -> Option<i32> {
+        let Instruction::getfield(idx) = self.instructions[pc] else {
+            return None;
+        };
+        match self.classes.resolve(self.class, idx).ok()? {
+            Resolved::Field(field) => Some(self.field_size(field)),
+            _ => None,
+        }
+    }
+
+    /// Returns the depth of the operand stack after the native method the
+    /// `invokenative` at `pc` calls, which sees `depth` slots before it.
+    fn native_depth(&self, pc: usize, depth: i32) -> Option<i32> {
+        let (Instruction::invokestatic(idx)
+        | Instruction::invokespecial(idx)
+        | Instruction::invokevirtual(idx)) = self.instructions[pc]
+        else {
+            return None;
+        };
+        let Resolved::Method(method) = self.classes.resolve(self.class, idx).ok()? else {
+            return None;
+        };
+        let info = self.classes.method_info(method);
+        let is_static = info.flags() & MethodFlags::STATIC == MethodFlags::STATIC;
+        let descriptor = info.parsed_descriptor();
+        let result = descriptor.result().map_or(0, |result| result.slots());
+        Some(depth - descriptor.arg_slots(is_static) as i32 + result as i32)
+    }
+
+    /// Returns the number of slots taken by the result of the method a
+    /// quickened `invokevirtual` or `invokeinterface` at `pc` calls.
+    fn result_size(&self, pc: usize) -> Option<i32> {
+        let (Instruction::invokevirtual(idx) | Instruction::invokeinterface(idx, _)) =
+            self.instructions[pc]
+        else {
+            return None;
+        };
+        match self.classes.resolve(self.class, idx).ok()? {
+            Resolved::Method(method) => {
+                let descriptor = self.classes.method_info(method).parsed_descriptor();
+                Some(descriptor.result().map_or(0, |result| result.slots()) as i32)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the slot of the frame at `index`, counting the locals first.
+fn frame_slot(index: i32) -> Mem {
+    Mem {
+        base: SLOTS,
+        disp: 4 * index,
+    }
+}
+
+fn exit_field(offset: usize) -> Mem {
+    Mem {
+        base: EXIT,
+        disp: offset as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::class::{assemble, write};
+
+    /// Loads the assembled `source` without verifying it, and compiles its
+    /// first method.
+    fn compile_unverified(source: &str) -> Option<Compiled> {
+        let mut classes = ClassManager::new();
+        classes
+            .load(&write(&assemble(source).unwrap()).unwrap())
+            .unwrap();
+        let code = classes.classes[0].code[0].as_ref().unwrap();
+        compile(
+            &classes,
+            MethodId {
+                class: 0,
+                method: 0,
+            },
+            code,
+        )
+    }
+
+    #[test]
+    fn code_accessing_slots_outside_the_frame_is_rejected() {
+        let class = |body: &str| {
+            format!(
+                ".class public Test\n.super java/lang/Object\n\
+                 .method public static run ()I\n{body}\n.end method\n"
+            )
+        };
+        let valid = "
+    .limit stack 2
+    .limit locals 1
+    iconst 5
+    istore 0
+    iload 0
+    ireturn";
+        assert!(compile_unverified(&class(valid)).is_some());
+
+        let bodies = [
+            // A local beyond `max_locals`
+            ".limit stack 1\n.limit locals 1\niconst 5\nistore 60000\niconst 0\nireturn",
+            // A `long` whose second slot is beyond `max_locals`
+            ".limit stack 2\n.limit locals 1\nlconst 5\nlstore 0\niconst 0\nireturn",
+            // A stack beyond `max_stack`
+            ".limit stack 1\n.limit locals 0\niconst 1\niconst 2\niadd\nireturn",
+            // A stack below its bottom
+            ".limit stack 1\n.limit locals 0\niadd\nireturn",
+            ".limit stack 1\n.limit locals 0\npop\niconst 0\nireturn",
+        ];
+        for body in bodies {
+            assert!(compile_unverified(&class(body)).is_none(), "{body}");
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! A baseline compiler translating hot methods into x86-64 machine code,
+//! enabled by the `jit` feature.
+//!
+//! The machine code works on the slots of the frame in the [`ThreadStack`]
+//! just as the bytecode interpreter does: each instruction loads its operands
+//! from the slots and stores its result back. A frame can therefore enter the
+//! machine code before any instruction and leave it after any instruction
+//! without translating its state. Calls and returns leave the machine code,
+//! and so do the instructions the compiler does not implement, which the
+//! bytecode interpreter runs instead.
+//!
+//! [`ThreadStack`]: super::call_frame::ThreadStack
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the `jit` feature is only supported on x86-64 Linux");
+
+mod assembler;
+mod compile;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use super::call_frame::{CallFrame, Slot, ThreadStack};
+use super::decode::MethodId;
+use super::ir::DeoptReason;
+use super::ClassManager;
+
+/// The number of times a method is compiled before it is left to the bytecode
+/// interpreter for good.
+const MAX_COMPILATIONS: u8 = 8;
+
+/// The largest method, in instructions, which is compiled.
+const MAX_METHOD_SIZE: usize = 8000;
+
+/// The codes the machine code returns with, telling why it stopped.
+const EXIT_RETURN: u32 = 0;
+const EXIT_CALL: u32 = 1;
+const EXIT_FALLBACK: u32 = 2;
+
+/// The machine code a method was compiled to.
+#[derive(Debug, Default)]
+pub struct NativeCode {
+    compiled: RefCell<Option<Rc<Compiled>>>,
+    compilations: Cell<u8>,
+}
+
+#[derive(Debug)]
+struct Compiled {
+    memory: ExecutableMemory,
+    /// The offset of the code of each instruction, or `u32::MAX` for
+    /// instructions which the code cannot be entered at.
+    entries: Box<[u32]>,
+    /// Whether each instruction leaves the code because it had not been
+    /// quickened when the method was compiled.
+    unquickened: Box<[bool]>,
+}
+
+impl Compiled {
+    fn entry(&self, pc: usize) -> Option<u32> {
+        self.entries
+            .get(pc)
+            .copied()
+            .filter(|&entry| entry != u32::MAX)
+    }
+}
+
+/// Where the machine code stopped, written by the code before it returns.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct ExitState {
+    /// The index of the instruction to continue at.
+    pc: u32,
+    /// The depth of the operand stack, or the number of slots taken by the
+    /// result when returning.
+    depth: u32,
+    /// The method called.
+    class: u32,
+    method: u32,
+    /// What is left of the time slice of the thread, which the code counts
+    /// down at backward jumps.
+    budget: i32,
+}
+
+/// How a frame left the machine code.
+#[derive(Debug)]
+pub(super) enum Exit {
+    /// Returns the value taking a number of slots, which the code moved to
+    /// the first slot of the frame.
+    Return(usize),
+    /// Calls a method, whose arguments are on top of the operand stack.
+    Call(MethodId),
+    /// Stops at an instruction the bytecode interpreter must run, or at the
+    /// target of a backward jump once the time slice is used up.
+    Fallback,
+}
+
+/// Counts an invocation of the method of `frame` if it has just been entered,
+/// compiles the method if it is hot, and returns whether the frame can enter
+/// the machine code at the instruction it stopped at.
+pub(super) fn enter(classes: &ClassManager, frame: &CallFrame, threshold: u32) -> bool {
+    let profile = &frame.code.profile;
+    if frame.pc == 0 {
+        profile.count(threshold);
+    }
+    if !profile.is_hot(threshold) {
+        return false;
+    }
+
+    let compiled = frame.code.native.compiled.borrow().clone();
+    let compiled = match compiled {
+        Some(compiled) => compiled,
+        None => match compile(classes, frame) {
+            Some(compiled) => compiled,
+            None => {
+                profile.disable();
+                return false;
+            }
+        },
+    };
+    compiled.entry(frame.pc).is_some()
+}
+
+/// Compiles the method of `frame`, or returns `None` if it cannot be compiled.
+fn compile(classes: &ClassManager, frame: &CallFrame) -> Option<Rc<Compiled>> {
+    let native = &frame.code.native;
+    if native.compilations.get() == MAX_COMPILATIONS || frame.code.len() > MAX_METHOD_SIZE {
+        return None;
+    }
+    native.compilations.set(native.compilations.get() + 1);
+
+    let method = MethodId {
+        class: frame.class as u32,
+        method: frame.method,
+    };
+    let compiled = Rc::new(compile::compile(classes, method, frame.code)?);
+    native.compiled.replace(Some(Rc::clone(&compiled)));
+    Some(compiled)
+}
+
+/// Runs `frame`, which [`enter`] allowed into the machine code, until it
+/// returns, calls a method, stops at an instruction the code does not
+/// implement or uses up the time slice `budget`.
+pub(super) fn run(frame: &mut CallFrame, thread: &mut ThreadStack, budget: &mut i32) -> Exit {
+    let native = &frame.code.native;
+    let compiled = native
+        .compiled
+        .borrow()
+        .clone()
+        .expect("method is compiled");
+    let entry = compiled.entry(frame.pc).expect("frame stopped at an entry");
+    let slots = thread.registers(frame.base, frame.code);
+
+    let mut state = ExitState {
+        budget: *budget,
+        ..ExitState::default()
+    };
+    // SAFETY: The code was compiled from the code of the method of the frame,
+    // and `compile` rejects code accessing a local beyond `max_locals` or a
+    // stack slot beyond `max_stack`, so it only accesses the locals and
+    // operand stack of the frame, which lie within `slots`. The class was
+    // verified before it ran, so each slot holds a value of the type read.
+    let exit = unsafe { compiled.memory.call(slots.as_mut_ptr(), entry, &mut state) };
+    *budget = state.budget;
+    if exit == EXIT_RETURN {
+        return Exit::Return(state.depth as usize);
+    }
+    frame.pc = state.pc as usize;
+    frame.depth = state.depth as usize;
+    if exit == EXIT_CALL {
+        return Exit::Call(MethodId {
+            class: state.class,
+            method: state.method,
+        });
+    }
+    // Once the interpreter has quickened the instruction, the method is
+    // compiled again, so that the instruction stays in the machine code. The
+    // code only stops at the target of a jump when the time slice is used up.
+    if state.budget > 0 && compiled.unquickened[frame.pc] {
+        native.compiled.take();
+        frame.code.profile.invalidate(DeoptReason::Unreached);
+    }
+    Exit::Fallback
+}
+
+/// Memory holding machine code, which can be executed but not written.
+#[derive(Debug)]
+struct ExecutableMemory {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+impl ExecutableMemory {
+    /// Maps a copy of `code`, or returns `None` if the memory cannot be
+    /// mapped.
+    fn new(code: &[u8]) -> Option<Self> {
+        let len = code.len().max(1);
+        // SAFETY: A new private mapping is created, and written only within
+        // its length before it is made executable.
+        unsafe {
+            let ptr = mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr as isize == -1 {
+                return None;
+            }
+            let memory = Self {
+                ptr: NonNull::new(ptr.cast())?,
+                len,
+            };
+            ptr::copy_nonoverlapping(code.as_ptr(), memory.ptr.as_ptr(), code.len());
+            if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(memory)
+        }
+    }
+
+    /// Runs the code from `entry` on `slots`, and returns the exit code.
+    ///
+    /// # Safety
+    ///
+    /// The code must have been compiled for the frame whose slots start at
+    /// `slots`, and `entry` must be the offset of one of its instructions.
+    unsafe fn call(&self, slots: *mut Slot, entry: u32, state: &mut ExitState) -> u32 {
+        type Function = unsafe extern "C" fn(*mut Slot, *const u8, *mut ExitState) -> u32;
+        let function = std::mem::transmute::<*mut u8, Function>(self.ptr.as_ptr());
+        function(slots, self.ptr.as_ptr().add(entry as usize), state)
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: The mapping was created by `new` and is no longer used.
+        unsafe {
+            munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
@@ -0,0 +1,1983 @@
+//! The functions of the `JNIEnv` interface, which native methods call, and of
+//! the `JavaVM` interface, which gives them a `JNIEnv`.
+//!
+//! The functions are called by native code with the arguments the JNI
+//! specification requires of it, such as valid references and
+//! nul-terminated strings, and do not check them. Those which are not
+//! supported return zero and leave an error pending, which ends the program
+//! once the native method returns, as no exception handler can clear it.
+
+use std::cell::Cell;
+use std::ffi::{c_char, c_void, CStr, VaList};
+use std::ops::Range;
+
+use crate::java_str;
+use crate::string::JavaStr;
+use crate::vm::class::{ClassFlags, FieldFlags, FieldType, MethodFlags};
+use crate::vm::convert::{ConvertError, JavaHeap};
+use crate::vm::decode::{FieldId, MethodId};
+use crate::vm::gc::Cause;
+use crate::vm::heap::{AllocError, Heap, ObjectRef};
+use crate::vm::link::Linked;
+use crate::vm::monitor::LockTarget;
+use crate::vm::value::Value;
+use crate::vm::{ClassManager, ExecuteError, JavaException, Vm};
+
+/// The versions of the JNI which native libraries may ask for.
+const VERSIONS: [i32; 5] = [
+    0x0001_0001,
+    0x0001_0002,
+    0x0001_0004,
+    0x0001_0006,
+    0x0001_0008,
+];
+
+const JNI_OK: i32 = 0;
+const JNI_ERR: i32 = -1;
+const JNI_EDETACHED: i32 = -2;
+const JNI_EVERSION: i32 = -3;
+
+/// The modes of releasing the elements of an array: copying them back
+/// without freeing them, or freeing them without copying them back.
+const JNI_COMMIT: i32 = 1;
+const JNI_ABORT: i32 = 2;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(pointer: *mut c_void);
+}
+
+/// The kinds of references, in the two lowest bits of their handles, above
+/// which is the index of the local or global reference, or of the class.
+const LOCAL: usize = 1;
+const GLOBAL: usize = 2;
+const CLASS: usize = 3;
+
+pub(super) fn is_supported(version: i32) -> bool {
+    VERSIONS.contains(&version)
+}
+
+/// Returns the handle of the class at `class`, which native methods use as a
+/// `jclass`.
+pub(super) fn class_handle(class: usize) -> usize {
+    class << 2 | CLASS
+}
+
+thread_local! {
+    /// The environment of the native method which is running, if any, which
+    /// `GetEnv` returns.
+    static CURRENT: Cell<*mut c_void> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+/// What the native code running in an environment has access to.
+pub(super) enum Access<'e, 'a> {
+    /// Only the classes, as `JNI_OnLoad` has when the host loads its library.
+    Classes,
+    /// The heap, as `JNI_OnLoad` has when the program loads its library.
+    Heap(&'e mut Heap),
+    /// The virtual machine, as a native method has, which runs on the thread
+    /// with the id given unless the host called it.
+    Vm(&'e mut Vm<'a>, Option<u32>),
+}
+
+/// The environment of a native method, which it is passed as a `JNIEnv *`.
+#[repr(C)]
+pub(super) struct Env<'e, 'a> {
+    /// The function table, which a `JNIEnv` starts with.
+    functions: &'static FunctionTable<233>,
+    classes: &'a ClassManager,
+    access: Access<'e, 'a>,
+    /// The number of local references of the native methods running below,
+    /// after which those of this one are.
+    locals: usize,
+    /// The exception thrown by native code, which is pending until it
+    /// returns or clears it.
+    exception: Option<JavaException>,
+    /// The first error native code ran into, which it cannot clear: a feature
+    /// which is not supported, or a Java method it called failing.
+    error: Option<ExecuteError>,
+}
+
+impl<'e, 'a> Env<'e, 'a> {
+    pub(super) fn new(classes: &'a ClassManager, access: Access<'e, 'a>) -> Self {
+        Self {
+            functions: &FUNCTIONS,
+            classes,
+            access,
+            locals: classes.natives.locals.borrow().len(),
+            exception: None,
+            error: None,
+        }
+    }
+
+    /// Returns the environment a native method was passed.
+    ///
+    /// # Safety
+    ///
+    /// `env` must point to an environment which is running a native method.
+    unsafe fn from_ptr<'p>(env: *mut c_void) -> &'p mut Env<'p, 'p> {
+        &mut *env.cast()
+    }
+
+    /// Runs `f`, which calls native code with the pointer to the environment
+    /// it is passed, as the environment of the current thread.
+    pub(super) fn enter<R>(&mut self, f: impl FnOnce(*mut c_void) -> R) -> R {
+        let env = (self as *mut Self).cast();
+        let previous = CURRENT.replace(env);
+        let result = f(env);
+        CURRENT.set(previous);
+        result
+    }
+
+    /// Returns the error native code left pending when it returned: the
+    /// error it ran into, or else the exception it threw.
+    pub(super) fn error(&mut self) -> Option<ExecuteError> {
+        (self.error.take()).or_else(|| self.exception.take().map(ExecuteError::Exception))
+    }
+
+    fn throw(&mut self, class: &str, message: Option<String>) {
+        self.exception = Some(JavaException::Native {
+            class: crate::string::from_utf8(class).into_owned(),
+            message,
+        });
+    }
+
+    /// Records that native code ran into `error`, unless it ran into another
+    /// one before.
+    #[cold]
+    #[inline(never)]
+    fn fail(&mut self, error: ExecuteError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Records that native code used `feature`, which is not supported.
+    #[cold]
+    #[inline(never)]
+    fn unsupported(&mut self, feature: &str) {
+        self.fail(ExecuteError::Unsupported(feature.to_owned()));
+    }
+
+    /// Returns the heap, unless native code has no access to it.
+    fn heap_if_any(&mut self) -> Option<&mut Heap> {
+        match &mut self.access {
+            Access::Classes => None,
+            Access::Heap(heap) => Some(heap),
+            Access::Vm(vm, _) => Some(&mut vm.heap),
+        }
+    }
+
+    fn heap(&mut self) -> Option<&mut Heap> {
+        if let Access::Classes = self.access {
+            self.unsupported("accessing objects in JNI_OnLoad");
+        }
+        self.heap_if_any()
+    }
+
+    /// Returns the virtual machine and the id of the thread running the
+    /// native method, unless `JNI_OnLoad` is running, which cannot use
+    /// `feature`.
+    fn vm(&mut self, feature: &str) -> Option<(&mut Vm<'a>, Option<u32>)> {
+        if !matches!(self.access, Access::Vm(..)) {
+            self.unsupported(&format!("{feature} in JNI_OnLoad"));
+            return None;
+        }
+        let Access::Vm(vm, thread) = &mut self.access else {
+            unreachable!("the access was checked");
+        };
+        Some((vm, *thread))
+    }
+
+    /// Returns the virtual machine and the id of the thread running the
+    /// native method, unless it has no thread, which `feature` needs.
+    fn thread(&mut self, feature: &str) -> Option<(&mut Vm<'a>, u32)> {
+        if !matches!(self.access, Access::Vm(_, Some(_))) {
+            self.unsupported(&format!("{feature} without a thread"));
+            return None;
+        }
+        let Access::Vm(vm, Some(thread)) = &mut self.access else {
+            unreachable!("the access was checked");
+        };
+        Some((vm, *thread))
+    }
+
+    /// Allocates an object with `alloc`, collecting the heap if it does not
+    /// fit, and fully collecting it if it still does not, or throws. The heap
+    /// cannot be collected while `JNI_OnLoad` runs.
+    fn alloc(
+        &mut self,
+        mut alloc: impl FnMut(&mut Heap) -> Result<ObjectRef, ConvertError>,
+    ) -> Option<ObjectRef> {
+        let mut causes = [Cause::Allocation, Cause::SystemGc].into_iter();
+        loop {
+            match alloc(self.heap()?) {
+                Ok(object) => return Some(object),
+                Err(ConvertError::Alloc(AllocError::Full)) => {
+                    if let (Access::Vm(vm, _), Some(cause)) = (&mut self.access, causes.next()) {
+                        vm.collect(cause);
+                        continue;
+                    }
+                }
+                Err(ConvertError::Alloc(AllocError::OutOfMemory)) => (),
+                Err(ConvertError::Linkage(error)) => {
+                    self.throw("java/lang/LinkageError", Some(error.to_string()));
+                    return None;
+                }
+                Err(ConvertError::Unrepresentable) => unreachable!("objects hold their values"),
+            }
+            let message = Some(String::from("Java heap space"));
+            self.throw("java/lang/OutOfMemoryError", message);
+            return None;
+        }
+    }
+
+    /// Links the class at `class`, or throws a `LinkageError`.
+    fn link(&mut self, class: usize) -> Option<&'a Linked> {
+        match self.classes.link(class) {
+            Ok(linked) => Some(linked),
+            Err(error) => {
+                self.throw("java/lang/LinkageError", Some(error.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Initializes the class at `class`, which runs Java code, unless
+    /// `JNI_OnLoad` is running.
+    fn initialize(&mut self, class: usize) -> Option<()> {
+        if self.classes.classes[class].initialized.get() {
+            return Some(());
+        }
+        let (vm, thread) = self.vm("initializing classes")?;
+        if let Err(error) = vm.initialize_from_native(class, thread) {
+            self.fail(error);
+            return None;
+        }
+        Some(())
+    }
+
+    /// Creates a local reference to `object`, or returns `null`.
+    pub(super) fn new_local(&mut self, object: Option<ObjectRef>) -> usize {
+        if object.is_none() {
+            return 0;
+        }
+        let mut locals = self.classes.natives.locals.borrow_mut();
+        locals.push(object);
+        (locals.len() - 1) << 2 | LOCAL
+    }
+
+    /// Returns the object a local or global reference holds, or `None` for
+    /// a class reference, which is not supported.
+    pub(super) fn object(&mut self, handle: usize) -> Option<ObjectRef> {
+        match handle & 3 {
+            _ if handle == 0 => None,
+            LOCAL => self.classes.natives.locals.borrow()[handle >> 2],
+            GLOBAL => self.classes.natives.globals.borrow()[handle >> 2],
+            _ => {
+                self.unsupported("objects of java.lang.Class");
+                None
+            }
+        }
+    }
+
+    /// Returns the object a reference which is not `null` holds, or throws a
+    /// `NullPointerException`.
+    fn non_null(&mut self, handle: usize) -> Option<ObjectRef> {
+        let object = self.object(handle);
+        if object.is_none() && handle & 3 != CLASS {
+            self.exception = Some(JavaException::NullPointer);
+        }
+        object
+    }
+
+    /// Returns the index of the class of a class reference.
+    fn class(&self, handle: usize) -> usize {
+        assert_eq!(handle & 3, CLASS, "reference is not a class");
+        handle >> 2
+    }
+
+    /// Returns the index of the class of a reference which is not `null`, or
+    /// `None` if its object cannot be accessed.
+    fn class_of(&mut self, handle: usize) -> Option<usize> {
+        let object = self.object(handle)?;
+        Some(self.heap()?.get(object).class as usize)
+    }
+
+    fn field(&mut self, object: usize, field: usize) -> Option<Value> {
+        let object = self.object(object)?;
+        Some(self.heap()?.get(object).fields()[field - 1])
+    }
+
+    fn set_field(&mut self, object: usize, field: usize, value: Value) {
+        if let Some(object) = self.object(object) {
+            if let Some(heap) = self.heap() {
+                heap.set_field(object, field - 1, value);
+            }
+        }
+    }
+
+    fn static_field(&mut self, field: usize) -> Option<Value> {
+        let field = static_field_id(field);
+        let statics = &self.classes.classes[field.class as usize].statics;
+        let value = statics[field.field as usize].get();
+        if value.is_none() {
+            self.unsupported("String constants");
+        }
+        value
+    }
+
+    fn set_static_field(&mut self, field: usize, value: Value) {
+        let field = static_field_id(field);
+        let statics = &self.classes.classes[field.class as usize].statics;
+        statics[field.field as usize].set(Some(value));
+        if let Some(heap) = self.heap_if_any() {
+            heap.record_static(field.class, value);
+        }
+    }
+
+    /// Allocates an object of the class at `class`, initializing the class
+    /// first, or throws.
+    fn instantiate(&mut self, class: usize) -> Option<ObjectRef> {
+        let classes = self.classes;
+        let loaded = &classes.classes[class].class;
+        if (loaded.flags() & (ClassFlags::INTERFACE | ClassFlags::ABSTRACT)).bits() != 0 {
+            let name = loaded.name().to_string();
+            self.throw("java/lang/InstantiationException", Some(name));
+            return None;
+        }
+        self.link(class)?;
+        self.initialize(class)?;
+        self.alloc(|heap| Ok(classes.instantiate(heap, class as u32)?))
+    }
+
+    /// Allocates an array of `length` elements of the type `component`, or
+    /// throws.
+    fn new_array(&mut self, component: FieldType, length: i32) -> Option<ObjectRef> {
+        let classes = self.classes;
+        let Ok(length) = usize::try_from(length) else {
+            self.exception = Some(JavaException::NegativeArraySize(length));
+            return None;
+        };
+        let array = FieldType::array(component);
+        self.alloc(|heap| {
+            let class = classes.array_class(&array)?;
+            classes.link(class)?;
+            Ok(classes.new_array(heap, class as u32, length)?)
+        })
+    }
+
+    /// Allocates a `java.lang.String` holding the UTF-16 code units `units`,
+    /// or throws.
+    fn new_string(&mut self, units: &[u16]) -> Option<ObjectRef> {
+        let classes = self.classes;
+        self.alloc(|heap| JavaHeap::new(classes, heap).alloc_string_units(units))
+    }
+
+    /// Returns the UTF-16 code units of a string, or `None` if it is `null`,
+    /// which throws, or not a `java.lang.String`.
+    fn string_units(&mut self, string: usize) -> Option<Vec<u16>> {
+        let classes = self.classes;
+        let string = self.non_null(string)?;
+        JavaHeap::new(classes, self.heap()?).string_units(string)
+    }
+
+    fn array_length(&mut self, array: usize) -> Option<usize> {
+        let array = self.non_null(array)?;
+        Some(self.heap()?.get(array).fields().len())
+    }
+
+    /// Returns the array `array` and the range of `length` of its elements
+    /// from `start`, or throws if they are out of its bounds.
+    fn region(
+        &mut self,
+        array: usize,
+        start: i32,
+        length: i32,
+    ) -> Option<(ObjectRef, Range<usize>)> {
+        let array = self.non_null(array)?;
+        let len = self.heap()?.get(array).fields().len();
+        let Some(range) = bounds(start, length, len) else {
+            let index = if start < 0 || length < 0 {
+                start.min(length)
+            } else {
+                start.max(len as i32)
+            };
+            let length = len;
+            self.exception = Some(JavaException::ArrayIndexOutOfBounds { index, length });
+            return None;
+        };
+        Some((array, range))
+    }
+
+    /// Returns the elements of a primitive array.
+    fn elements<T: Primitive>(&mut self, array: usize) -> Option<Vec<T>> {
+        let array = self.non_null(array)?;
+        let elements = self.heap()?.get(array).fields();
+        Some(
+            elements
+                .iter()
+                .map(|&element| T::from_value(element))
+                .collect(),
+        )
+    }
+
+    /// Writes `elements` to a primitive array from `start`, or throws if
+    /// they are out of its bounds.
+    fn set_elements<T: Primitive + Copy>(&mut self, array: usize, start: i32, elements: &[T]) {
+        let Some((array, range)) = self.region(array, start, elements.len() as i32) else {
+            return;
+        };
+        if let Some(heap) = self.heap() {
+            for (index, &element) in range.zip(elements) {
+                heap.set_field(array, index, element.into_value());
+            }
+        }
+    }
+
+    /// Copies `values` to memory which native code releases, and tells it
+    /// through `is_copy`, unless it is null, or throws an `OutOfMemoryError`
+    /// if none can be allocated.
+    ///
+    /// # Safety
+    ///
+    /// `is_copy` must be null or point to a `jboolean`.
+    unsafe fn copy_out<T: Copy>(&mut self, values: &[T], is_copy: *mut u8) -> *mut T {
+        let copy = malloc(std::mem::size_of_val(values).max(1)).cast::<T>();
+        if copy.is_null() {
+            self.throw("java/lang/OutOfMemoryError", None);
+            return copy;
+        }
+        std::ptr::copy_nonoverlapping(values.as_ptr(), copy, values.len());
+        if !is_copy.is_null() {
+            *is_copy = 1;
+        }
+        copy
+    }
+
+    /// Converts `bits`, which native code passed or returned as a value of
+    /// `field_type`, to a value. Values narrower than a register leave its
+    /// upper bits undefined.
+    pub(super) fn value(&mut self, field_type: &FieldType, bits: u64) -> Value {
+        match field_type {
+            FieldType::Bool => Value::Int((bits as u8 != 0).into()),
+            FieldType::Byte => Value::Int((bits as i8).into()),
+            FieldType::Char => Value::Int((bits as u16).into()),
+            FieldType::Short => Value::Int((bits as i16).into()),
+            FieldType::Int => Value::Int(bits as i32),
+            FieldType::Long => Value::Long(bits as i64),
+            FieldType::Float => Value::Float(f32::from_bits(bits as u32)),
+            FieldType::Double => Value::Double(f64::from_bits(bits)),
+            FieldType::Class(_) | FieldType::Array(_) => {
+                Value::Reference(self.object(bits as usize))
+            }
+        }
+    }
+
+    /// Returns the method the class of `object` selects for `method`, as the
+    /// call of an instance method does, or throws if it selects none.
+    fn select(&mut self, object: ObjectRef, method: MethodId) -> Option<MethodId> {
+        let classes = self.classes;
+        let class = self.heap()?.get(object).class as usize;
+        let info = classes.method_info(method);
+        let constants = classes.classes[method.class as usize].class.constants();
+        let selected = if info.flags() & MethodFlags::PRIVATE == MethodFlags::PRIVATE
+            || info.name(constants) == java_str!("<init>")
+        {
+            Some(method)
+        } else if classes.is_interface(method.class as usize) {
+            let itable = self.link(class)?.itables.get(&method.class);
+            itable.and_then(|itable| itable[method.method as usize])
+        } else {
+            match self.link(method.class as usize)?.vtable_indices[method.method as usize] {
+                Some(index) => self.link(class)?.vtable.get(index as usize).copied(),
+                None => Some(method),
+            }
+        };
+        if selected.is_none() {
+            let (_, name, _) = classes.signature(method);
+            self.throw(
+                "java/lang/IncompatibleClassChangeError",
+                Some(name.to_string()),
+            );
+        }
+        selected
+    }
+
+    /// Calls `method` with the arguments `next_arg` returns the bits of, in
+    /// order, on the object `object` unless it is a static method, and
+    /// returns its result. Returns `None` if it has none, or if it fails or
+    /// cannot be called.
+    fn call(
+        &mut self,
+        object: usize,
+        method: usize,
+        dispatch: Dispatch,
+        mut next_arg: impl FnMut(&FieldType) -> u64,
+    ) -> Option<Value> {
+        let classes = self.classes;
+        let method = method_id(method);
+        let descriptor = classes.method_info(method).parsed_descriptor();
+        let args: Vec<Value> = (descriptor.args().iter())
+            .map(|parameter| {
+                let bits = next_arg(parameter);
+                self.value(parameter, bits)
+            })
+            .collect();
+        let receiver = match dispatch {
+            Dispatch::Static => None,
+            _ => Some(self.non_null(object)?),
+        };
+        let method = match (dispatch, receiver) {
+            (Dispatch::Virtual, Some(receiver)) => self.select(receiver, method)?,
+            _ => method,
+        };
+        self.call_java(method, receiver, &args)
+    }
+
+    /// Runs `method` with `receiver` and `args` as its arguments, and returns
+    /// its result. Returns `None` if it has none, or if it fails, which native
+    /// code cannot recover from.
+    fn call_java(
+        &mut self,
+        method: MethodId,
+        receiver: Option<ObjectRef>,
+        args: &[Value],
+    ) -> Option<Value> {
+        // A thread whose call failed may have been left in any state.
+        if self.error.is_some() {
+            return None;
+        }
+        let (vm, thread) = self.vm("calling Java methods")?;
+        match vm.call_from_native(method, receiver, args, thread) {
+            Ok(result) => result,
+            Err(error) => {
+                self.fail(error);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Env<'_, '_> {
+    /// Deletes the local references of the native method, which has returned.
+    fn drop(&mut self) {
+        self.classes
+            .natives
+            .locals
+            .borrow_mut()
+            .truncate(self.locals);
+    }
+}
+
+/// Returns the field a `jfieldID` returned by `GetStaticFieldID` stands for.
+fn static_field_id(field: usize) -> FieldId {
+    let field = field - 1;
+    FieldId {
+        class: (field >> 32) as u32,
+        field: field as u32,
+    }
+}
+
+/// Reads a modified UTF-8 string passed by native code.
+unsafe fn java_str<'s>(string: *const c_char) -> Option<&'s JavaStr> {
+    JavaStr::from_java(CStr::from_ptr(string).to_bytes()).ok()
+}
+
+unsafe fn lossy(string: *const c_char) -> String {
+    CStr::from_ptr(string).to_string_lossy().into_owned()
+}
+
+unsafe extern "C" fn get_version(_: *mut c_void) -> i32 {
+    0x0001_0008
+}
+
+unsafe extern "C" fn find_class(env: *mut c_void, name: *const c_char) -> usize {
+    let env = Env::from_ptr(env);
+    match java_str(name).and_then(|name| env.classes.position(name)) {
+        Some(class) => class_handle(class),
+        None => {
+            env.throw("java/lang/NoClassDefFoundError", Some(lossy(name)));
+            0
+        }
+    }
+}
+
+unsafe extern "C" fn get_superclass(env: *mut c_void, class: usize) -> usize {
+    let env = Env::from_ptr(env);
+    let class = env.class(class);
+    if env.classes.is_interface(class) {
+        return 0;
+    }
+    let super_name = env.classes.classes[class].class.super_name();
+    super_name
+        .and_then(|name| env.classes.position(name))
+        .map_or(0, class_handle)
+}
+
+unsafe extern "C" fn is_assignable_from(env: *mut c_void, class: usize, target: usize) -> u8 {
+    let env = Env::from_ptr(env);
+    env.classes
+        .is_subclass(env.class(class), env.class(target))
+        .into()
+}
+
+unsafe extern "C" fn throw(env: *mut c_void, object: usize) -> i32 {
+    let env = Env::from_ptr(env);
+    let Some(class) = env.class_of(object) else {
+        return JNI_ERR;
+    };
+    env.exception = Some(JavaException::Native {
+        class: env.classes.classes[class].class.name().to_owned(),
+        message: None,
+    });
+    JNI_OK
+}
+
+unsafe extern "C" fn throw_new(env: *mut c_void, class: usize, message: *const c_char) -> i32 {
+    let env = Env::from_ptr(env);
+    let class = env.class(class);
+    env.exception = Some(JavaException::Native {
+        class: env.classes.classes[class].class.name().to_owned(),
+        message: (!message.is_null()).then(|| lossy(message)),
+    });
+    JNI_OK
+}
+
+unsafe extern "C" fn exception_describe(env: *mut c_void) {
+    if let Some(exception) = Env::from_ptr(env).exception.take() {
+        eprintln!("{exception}");
+    }
+}
+
+unsafe extern "C" fn exception_clear(env: *mut c_void) {
+    Env::from_ptr(env).exception = None;
+}
+
+/// Returns whether an exception is pending, or an error, which native code
+/// should return on as well.
+unsafe extern "C" fn exception_check(env: *mut c_void) -> u8 {
+    let env = Env::from_ptr(env);
+    (env.exception.is_some() || env.error.is_some()).into()
+}
+
+unsafe extern "C" fn fatal_error(_: *mut c_void, message: *const c_char) -> ! {
+    eprintln!("FATAL ERROR in native method: {}", lossy(message));
+    std::process::abort()
+}
+
+unsafe extern "C" fn new_global_ref(env: *mut c_void, handle: usize) -> usize {
+    let env = Env::from_ptr(env);
+    if handle & 3 == CLASS {
+        return handle;
+    }
+    let Some(object) = env.object(handle) else {
+        return 0;
+    };
+    let mut globals = env.classes.natives.globals.borrow_mut();
+    let index = match globals.iter().position(Option::is_none) {
+        Some(index) => {
+            globals[index] = Some(object);
+            index
+        }
+        None => {
+            globals.push(Some(object));
+            globals.len() - 1
+        }
+    };
+    index << 2 | GLOBAL
+}
+
+unsafe extern "C" fn delete_global_ref(env: *mut c_void, handle: usize) {
+    if handle & 3 == GLOBAL {
+        Env::from_ptr(env).classes.natives.globals.borrow_mut()[handle >> 2] = None;
+    }
+}
+
+unsafe extern "C" fn delete_local_ref(env: *mut c_void, handle: usize) {
+    if handle & 3 == LOCAL {
+        let locals = &Env::from_ptr(env).classes.natives.locals;
+        locals.borrow_mut()[handle >> 2] = None;
+    }
+}
+
+unsafe extern "C" fn new_local_ref(env: *mut c_void, handle: usize) -> usize {
+    let env = Env::from_ptr(env);
+    if handle & 3 == CLASS {
+        return handle;
+    }
+    let object = env.object(handle);
+    env.new_local(object)
+}
+
+unsafe extern "C" fn ensure_local_capacity(_: *mut c_void, _: i32) -> i32 {
+    JNI_OK
+}
+
+unsafe extern "C" fn is_same_object(env: *mut c_void, a: usize, b: usize) -> u8 {
+    let env = Env::from_ptr(env);
+    if a & 3 == CLASS || b & 3 == CLASS {
+        return (a == b).into();
+    }
+    (env.object(a) == env.object(b)).into()
+}
+
+unsafe extern "C" fn get_object_ref_type(env: *mut c_void, handle: usize) -> i32 {
+    let env = Env::from_ptr(env);
+    match handle & 3 {
+        _ if handle == 0 => 0,
+        LOCAL if env.object(handle).is_some() => 1,
+        GLOBAL if env.object(handle).is_some() => 2,
+        CLASS => 2,
+        _ => 0,
+    }
+}
+
+unsafe extern "C" fn alloc_object(env: *mut c_void, class: usize) -> usize {
+    let env = Env::from_ptr(env);
+    let object = env.instantiate(env.class(class));
+    env.new_local(object)
+}
+
+unsafe extern "C" fn get_object_class(env: *mut c_void, object: usize) -> usize {
+    Env::from_ptr(env).class_of(object).map_or(0, class_handle)
+}
+
+unsafe extern "C" fn is_instance_of(env: *mut c_void, object: usize, class: usize) -> u8 {
+    let env = Env::from_ptr(env);
+    if env.object(object).is_none() {
+        return 1;
+    }
+    let target = env.class(class);
+    let class = env.class_of(object);
+    class
+        .is_some_and(|class| env.classes.is_subclass(class, target))
+        .into()
+}
+
+unsafe extern "C" fn get_field_id(
+    env: *mut c_void,
+    class: usize,
+    name: *const c_char,
+    descriptor: *const c_char,
+) -> usize {
+    let env = Env::from_ptr(env);
+    let classes = env.classes;
+    let field = java_str(name)
+        .zip(java_str(descriptor))
+        .and_then(|(name, descriptor)| {
+            (classes.resolve_field(env.class(class), name, descriptor)).unwrap_or(None)
+        });
+    // Static fields have no slot in objects.
+    let slot = field.and_then(|field| {
+        let linked = classes.link(field.class as usize).ok()?;
+        linked.field_slots[field.field as usize]
+    });
+    match slot {
+        Some(slot) => slot as usize + 1,
+        None => {
+            env.throw("java/lang/NoSuchFieldError", Some(lossy(name)));
+            0
+        }
+    }
+}
+
+unsafe extern "C" fn get_static_field_id(
+    env: *mut c_void,
+    class: usize,
+    name: *const c_char,
+    descriptor: *const c_char,
+) -> usize {
+    let env = Env::from_ptr(env);
+    let classes = env.classes;
+    let class = env.class(class);
+    let field = java_str(name)
+        .zip(java_str(descriptor))
+        .and_then(|(name, descriptor)| {
+            (classes.resolve_field(class, name, descriptor)).unwrap_or(None)
+        })
+        .filter(|field| {
+            let info = &classes.classes[field.class as usize].class.fields()[field.field as usize];
+            info.flags() & FieldFlags::STATIC == FieldFlags::STATIC
+        });
+    let Some(field) = field else {
+        env.throw("java/lang/NoSuchFieldError", Some(lossy(name)));
+        return 0;
+    };
+    match env.initialize(class) {
+        Some(()) => ((field.class as usize) << 32 | field.field as usize) + 1,
+        None => 0,
+    }
+}
+
+/// A primitive type of the JNI, as native methods pass and receive it.
+trait Primitive {
+    fn from_value(value: Value) -> Self;
+    fn into_value(self) -> Value;
+}
+
+macro_rules! int_primitive {
+    ($($rust:ty,)*) => {
+        $(
+            impl Primitive for $rust {
+                fn from_value(value: Value) -> Self {
+                    match value {
+                        Value::Int(value) => value as $rust,
+                        _ => unreachable!("field holds an int"),
+                    }
+                }
+
+                fn into_value(self) -> Value {
+                    Value::Int(self.into())
+                }
+            }
+        )*
+    };
+}
+
+int_primitive! {
+    i8,
+    u16,
+    i16,
+    i32,
+}
+
+/// A `jboolean`, of which only the lowest bit is stored.
+impl Primitive for u8 {
+    fn from_value(value: Value) -> Self {
+        i8::from_value(value) as u8
+    }
+
+    fn into_value(self) -> Value {
+        Value::Int((self & 1).into())
+    }
+}
+
+impl Primitive for i64 {
+    fn from_value(value: Value) -> Self {
+        match value {
+            Value::Long(value) => value,
+            _ => unreachable!("field holds a long"),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Long(self)
+    }
+}
+
+impl Primitive for f32 {
+    fn from_value(value: Value) -> Self {
+        match value {
+            Value::Float(value) => value,
+            _ => unreachable!("field holds a float"),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl Primitive for f64 {
+    fn from_value(value: Value) -> Self {
+        match value {
+            Value::Double(value) => value,
+            _ => unreachable!("field holds a double"),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Double(self)
+    }
+}
+
+macro_rules! field_accessors {
+    ($($rust:ty => $get:ident, $set:ident, $get_static:ident, $set_static:ident;)*) => {
+        $(
+            unsafe extern "C" fn $get(env: *mut c_void, object: usize, field: usize) -> $rust {
+                (Env::from_ptr(env).field(object, field)).map_or(0 as $rust, <$rust>::from_value)
+            }
+
+            unsafe extern "C" fn $set(env: *mut c_void, object: usize, field: usize, value: $rust) {
+                Env::from_ptr(env).set_field(object, field, value.into_value());
+            }
+
+            unsafe extern "C" fn $get_static(env: *mut c_void, _: usize, field: usize) -> $rust {
+                (Env::from_ptr(env).static_field(field)).map_or(0 as $rust, <$rust>::from_value)
+            }
+
+            unsafe extern "C" fn $set_static(env: *mut c_void, _: usize, field: usize, value: $rust) {
+                Env::from_ptr(env).set_static_field(field, value.into_value());
+            }
+        )*
+    };
+}
+
+field_accessors! {
+    u8 => get_boolean_field, set_boolean_field, get_static_boolean_field, set_static_boolean_field;
+    i8 => get_byte_field, set_byte_field, get_static_byte_field, set_static_byte_field;
+    u16 => get_char_field, set_char_field, get_static_char_field, set_static_char_field;
+    i16 => get_short_field, set_short_field, get_static_short_field, set_static_short_field;
+    i32 => get_int_field, set_int_field, get_static_int_field, set_static_int_field;
+    i64 => get_long_field, set_long_field, get_static_long_field, set_static_long_field;
+    f32 => get_float_field, set_float_field, get_static_float_field, set_static_float_field;
+    f64 => get_double_field, set_double_field, get_static_double_field, set_static_double_field;
+}
+
+unsafe extern "C" fn get_object_field(env: *mut c_void, object: usize, field: usize) -> usize {
+    let env = Env::from_ptr(env);
+    match env.field(object, field) {
+        Some(Value::Reference(object)) => env.new_local(object),
+        None => 0,
+        _ => unreachable!("field holds a reference"),
+    }
+}
+
+unsafe extern "C" fn set_object_field(env: *mut c_void, object: usize, field: usize, value: usize) {
+    let env = Env::from_ptr(env);
+    let value = Value::Reference(env.object(value));
+    env.set_field(object, field, value);
+}
+
+unsafe extern "C" fn get_static_object_field(env: *mut c_void, _: usize, field: usize) -> usize {
+    let env = Env::from_ptr(env);
+    match env.static_field(field) {
+        Some(Value::Reference(object)) => env.new_local(object),
+        None => 0,
+        _ => unreachable!("field holds a reference"),
+    }
+}
+
+unsafe extern "C" fn set_static_object_field(
+    env: *mut c_void,
+    _: usize,
+    field: usize,
+    value: usize,
+) {
+    let env = Env::from_ptr(env);
+    let value = Value::Reference(env.object(value));
+    env.set_static_field(field, value);
+}
+
+/// How a method native code calls is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dispatch {
+    /// By the class of the receiver, as `invokevirtual` selects it.
+    Virtual,
+    /// The method given, called on the receiver, as `invokespecial` calls it.
+    Nonvirtual,
+    /// The static method given.
+    Static,
+}
+
+/// Returns the `jmethodID` of `method`.
+fn method_handle(method: MethodId) -> usize {
+    ((method.class as usize) << 32 | method.method as usize) + 1
+}
+
+/// Returns the method a `jmethodID` stands for.
+fn method_id(method: usize) -> MethodId {
+    let method = method - 1;
+    MethodId {
+        class: (method >> 32) as u32,
+        method: method as u32,
+    }
+}
+
+/// Looks up the method `name` with `descriptor` in `class`, which must be
+/// static or not as `is_static` says, and returns its `jmethodID` once the
+/// class is initialized, or throws a `NoSuchMethodError`.
+unsafe fn get_method(
+    env: &mut Env,
+    class: usize,
+    name: *const c_char,
+    descriptor: *const c_char,
+    is_static: bool,
+) -> usize {
+    let classes = env.classes;
+    let class = env.class(class);
+    let method = java_str(name)
+        .zip(java_str(descriptor))
+        .and_then(|(name, descriptor)| {
+            (classes.resolve_method(class, name, descriptor)).unwrap_or(None)
+        })
+        .filter(|&method| {
+            let flags = classes.method_info(method).flags();
+            (flags & MethodFlags::STATIC == MethodFlags::STATIC) == is_static
+        });
+    let Some(method) = method else {
+        env.throw("java/lang/NoSuchMethodError", Some(lossy(name)));
+        return 0;
+    };
+    match env.initialize(class) {
+        Some(()) => method_handle(method),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn get_method_id(
+    env: *mut c_void,
+    class: usize,
+    name: *const c_char,
+    descriptor: *const c_char,
+) -> usize {
+    get_method(Env::from_ptr(env), class, name, descriptor, false)
+}
+
+unsafe extern "C" fn get_static_method_id(
+    env: *mut c_void,
+    class: usize,
+    name: *const c_char,
+    descriptor: *const c_char,
+) -> usize {
+    get_method(Env::from_ptr(env), class, name, descriptor, true)
+}
+
+/// A type native code receives the results of Java methods as.
+trait CallResult {
+    fn from_result(env: &mut Env, result: Option<Value>) -> Self;
+}
+
+impl<T: Primitive + Default> CallResult for T {
+    fn from_result(_: &mut Env, result: Option<Value>) -> Self {
+        result.map_or_else(T::default, T::from_value)
+    }
+}
+
+/// A reference, which is returned as a new local reference.
+impl CallResult for usize {
+    fn from_result(env: &mut Env, result: Option<Value>) -> Self {
+        match result {
+            Some(Value::Reference(object)) => env.new_local(object),
+            _ => 0,
+        }
+    }
+}
+
+impl CallResult for () {
+    fn from_result(_: &mut Env, _: Option<Value>) -> Self {}
+}
+
+/// Reads the next argument, of the type `parameter`, from the arguments of a
+/// variadic function, in which those narrower than an `int` are promoted to
+/// an `int` and `float`s to a `double`.
+unsafe fn va_arg(args: &mut VaList, parameter: &FieldType) -> u64 {
+    match parameter {
+        FieldType::Long => args.next_arg::<i64>() as u64,
+        FieldType::Float => (args.next_arg::<f64>() as f32).to_bits().into(),
+        FieldType::Double => args.next_arg::<f64>().to_bits(),
+        FieldType::Class(_) | FieldType::Array(_) => args.next_arg::<usize>() as u64,
+        _ => args.next_arg::<i32>() as u64,
+    }
+}
+
+/// Returns a function reading the next argument from an array of `jvalue`s,
+/// each of which holds it in its lowest bytes.
+unsafe fn jvalues(args: *const u64) -> impl FnMut(&FieldType) -> u64 {
+    let mut next = args;
+    move |_| {
+        let bits = *next;
+        next = next.add(1);
+        bits
+    }
+}
+
+unsafe extern "C" fn call_method<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    method: usize,
+    mut args: ...
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Virtual, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_method_v<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    method: usize,
+    mut args: VaList,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Virtual, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_method_a<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    method: usize,
+    args: *const u64,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Virtual, jvalues(args));
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_nonvirtual_method<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    _: usize,
+    method: usize,
+    mut args: ...
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Nonvirtual, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_nonvirtual_method_v<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    _: usize,
+    method: usize,
+    mut args: VaList,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Nonvirtual, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_nonvirtual_method_a<R: CallResult>(
+    env: *mut c_void,
+    object: usize,
+    _: usize,
+    method: usize,
+    args: *const u64,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(object, method, Dispatch::Nonvirtual, jvalues(args));
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_static_method<R: CallResult>(
+    env: *mut c_void,
+    _: usize,
+    method: usize,
+    mut args: ...
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(0, method, Dispatch::Static, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_static_method_v<R: CallResult>(
+    env: *mut c_void,
+    _: usize,
+    method: usize,
+    mut args: VaList,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(0, method, Dispatch::Static, |parameter| {
+        va_arg(&mut args, parameter)
+    });
+    R::from_result(env, result)
+}
+
+unsafe extern "C" fn call_static_method_a<R: CallResult>(
+    env: *mut c_void,
+    _: usize,
+    method: usize,
+    args: *const u64,
+) -> R {
+    let env = Env::from_ptr(env);
+    let result = env.call(0, method, Dispatch::Static, jvalues(args));
+    R::from_result(env, result)
+}
+
+/// Allocates an object of `class` and runs the constructor `method` on it
+/// with the arguments `next_arg` returns, and returns a local reference to
+/// it, or `null` if either fails.
+fn new_object_with(
+    env: &mut Env,
+    class: usize,
+    method: usize,
+    next_arg: impl FnMut(&FieldType) -> u64,
+) -> usize {
+    let Some(object) = env.instantiate(env.class(class)) else {
+        return 0;
+    };
+    let object = env.new_local(Some(object));
+    env.call(object, method, Dispatch::Nonvirtual, next_arg);
+    if env.exception.is_some() || env.error.is_some() {
+        return 0;
+    }
+    object
+}
+
+unsafe extern "C" fn new_object(
+    env: *mut c_void,
+    class: usize,
+    method: usize,
+    mut args: ...
+) -> usize {
+    new_object_with(Env::from_ptr(env), class, method, |parameter| {
+        va_arg(&mut args, parameter)
+    })
+}
+
+unsafe extern "C" fn new_object_v(
+    env: *mut c_void,
+    class: usize,
+    method: usize,
+    mut args: VaList,
+) -> usize {
+    new_object_with(Env::from_ptr(env), class, method, |parameter| {
+        va_arg(&mut args, parameter)
+    })
+}
+
+unsafe extern "C" fn new_object_a(
+    env: *mut c_void,
+    class: usize,
+    method: usize,
+    args: *const u64,
+) -> usize {
+    new_object_with(Env::from_ptr(env), class, method, jvalues(args))
+}
+
+/// Returns the UTF-16 code units of a modified UTF-8 string.
+fn utf16_units(string: &JavaStr) -> Vec<u16> {
+    let mut units = Vec::with_capacity(string.len());
+    for c in string.chars() {
+        match u16::try_from(c) {
+            Ok(unit) => units.push(unit),
+            Err(_) => {
+                let c = c - 0x1_0000;
+                units.extend([0xD800 | (c >> 10) as u16, 0xDC00 | (c & 0x3FF) as u16]);
+            }
+        }
+    }
+    units
+}
+
+/// Encodes UTF-16 code units in modified UTF-8, which encodes `NUL` in two
+/// bytes and each surrogate on its own.
+fn modified_utf8(units: &[u16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(units.len());
+    for &unit in units {
+        match unit {
+            0x01..=0x7F => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                bytes.extend([0xC0 | (unit >> 6) as u8, 0x80 | (unit & 0x3F) as u8]);
+            }
+            _ => bytes.extend([
+                0xE0 | (unit >> 12) as u8,
+                0x80 | (unit >> 6 & 0x3F) as u8,
+                0x80 | (unit & 0x3F) as u8,
+            ]),
+        }
+    }
+    bytes
+}
+
+unsafe extern "C" fn new_string(env: *mut c_void, chars: *const u16, length: i32) -> usize {
+    let env = Env::from_ptr(env);
+    let units = std::slice::from_raw_parts(chars, length as usize);
+    let string = env.new_string(units);
+    env.new_local(string)
+}
+
+/// Creates a string from modified UTF-8, or from UTF-8 if it is not valid
+/// modified UTF-8, replacing the invalid sequences of that as well.
+unsafe extern "C" fn new_string_utf(env: *mut c_void, bytes: *const c_char) -> usize {
+    let env = Env::from_ptr(env);
+    let units = match java_str(bytes) {
+        Some(string) => utf16_units(string),
+        None => lossy(bytes).encode_utf16().collect(),
+    };
+    let string = env.new_string(&units);
+    env.new_local(string)
+}
+
+unsafe extern "C" fn get_string_length(env: *mut c_void, string: usize) -> i32 {
+    let units = Env::from_ptr(env).string_units(string);
+    units.map_or(0, |units| units.len() as i32)
+}
+
+unsafe extern "C" fn get_string_utf_length(env: *mut c_void, string: usize) -> i32 {
+    let units = Env::from_ptr(env).string_units(string);
+    units.map_or(0, |units| modified_utf8(&units).len() as i32)
+}
+
+unsafe extern "C" fn get_string_chars(
+    env: *mut c_void,
+    string: usize,
+    is_copy: *mut u8,
+) -> *mut u16 {
+    let env = Env::from_ptr(env);
+    match env.string_units(string) {
+        Some(units) => env.copy_out(&units, is_copy),
+        None => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn get_string_utf_chars(
+    env: *mut c_void,
+    string: usize,
+    is_copy: *mut u8,
+) -> *mut c_char {
+    let env = Env::from_ptr(env);
+    let Some(units) = env.string_units(string) else {
+        return std::ptr::null_mut();
+    };
+    let mut bytes = modified_utf8(&units);
+    bytes.push(0);
+    env.copy_out(&bytes, is_copy).cast()
+}
+
+/// Frees the characters of a string copied out for native code.
+unsafe extern "C" fn release_string_chars(_: *mut c_void, _: usize, chars: *mut c_void) {
+    free(chars);
+}
+
+/// Returns the range of `length` elements from `start` among `len`, or
+/// `None` if it is out of their bounds.
+fn bounds(start: i32, length: i32, len: usize) -> Option<Range<usize>> {
+    let start = usize::try_from(start).ok()?;
+    let end = start.checked_add(usize::try_from(length).ok()?)?;
+    (end <= len).then_some(start..end)
+}
+
+unsafe extern "C" fn get_string_region(
+    env: *mut c_void,
+    string: usize,
+    start: i32,
+    length: i32,
+    buffer: *mut u16,
+) {
+    let env = Env::from_ptr(env);
+    let Some(units) = env.string_units(string) else {
+        return;
+    };
+    match bounds(start, length, units.len()) {
+        Some(range) => {
+            std::ptr::copy_nonoverlapping(units[range].as_ptr(), buffer, length as usize);
+        }
+        None => env.throw("java/lang/StringIndexOutOfBoundsException", None),
+    }
+}
+
+unsafe extern "C" fn get_string_utf_region(
+    env: *mut c_void,
+    string: usize,
+    start: i32,
+    length: i32,
+    buffer: *mut c_char,
+) {
+    let env = Env::from_ptr(env);
+    let Some(units) = env.string_units(string) else {
+        return;
+    };
+    match bounds(start, length, units.len()) {
+        Some(range) => {
+            let mut bytes = modified_utf8(&units[range]);
+            bytes.push(0);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.cast(), bytes.len());
+        }
+        None => env.throw("java/lang/StringIndexOutOfBoundsException", None),
+    }
+}
+
+unsafe extern "C" fn get_array_length(env: *mut c_void, array: usize) -> i32 {
+    let length = Env::from_ptr(env).array_length(array);
+    length.map_or(0, |length| length as i32)
+}
+
+unsafe extern "C" fn new_object_array(
+    env: *mut c_void,
+    length: i32,
+    class: usize,
+    initial: usize,
+) -> usize {
+    let env = Env::from_ptr(env);
+    let component = &env.classes.classes[env.class(class)];
+    let component = match &component.component {
+        Some(_) => FieldType::parse(component.class.name())
+            .expect("array classes are named by their descriptor"),
+        None => FieldType::class(component.class.name()),
+    };
+    let Some(array) = env.new_array(component, length) else {
+        return 0;
+    };
+    // The initial element is read once the array is allocated, which may
+    // have collected the heap.
+    let initial = Value::Reference(env.object(initial));
+    if let Some(heap) = env.heap() {
+        for index in 0..length as usize {
+            heap.set_field(array, index, initial);
+        }
+    }
+    env.new_local(Some(array))
+}
+
+unsafe extern "C" fn get_object_array_element(env: *mut c_void, array: usize, index: i32) -> usize {
+    let env = Env::from_ptr(env);
+    let Some((array, range)) = env.region(array, index, 1) else {
+        return 0;
+    };
+    let element = env.heap().map(|heap| heap.get(array).fields()[range.start]);
+    match element {
+        Some(Value::Reference(object)) => env.new_local(object),
+        None => 0,
+        _ => unreachable!("array holds references"),
+    }
+}
+
+unsafe extern "C" fn set_object_array_element(
+    env: *mut c_void,
+    array: usize,
+    index: i32,
+    value: usize,
+) {
+    let env = Env::from_ptr(env);
+    let classes = env.classes;
+    let Some((array, range)) = env.region(array, index, 1) else {
+        return;
+    };
+    let value = env.object(value);
+    let Some(heap) = env.heap() else {
+        return;
+    };
+    let component = classes.component_class(heap.get(array).class as usize);
+    let class = value.map(|value| heap.get(value).class as usize);
+    if let Some((class, component)) = class.zip(component) {
+        if !classes.is_subclass(class, component) {
+            env.exception = Some(JavaException::ArrayStore);
+            return;
+        }
+    }
+    heap.set_field(array, range.start, Value::Reference(value));
+}
+
+macro_rules! array_functions {
+    ($(
+        $rust:ty => $component:ident, $new:ident, $get_elements:ident, $release_elements:ident,
+        $get_region:ident, $set_region:ident;
+    )*) => {
+        $(
+            unsafe extern "C" fn $new(env: *mut c_void, length: i32) -> usize {
+                let env = Env::from_ptr(env);
+                let array = env.new_array(FieldType::$component, length);
+                env.new_local(array)
+            }
+
+            unsafe extern "C" fn $get_elements(
+                env: *mut c_void,
+                array: usize,
+                is_copy: *mut u8,
+            ) -> *mut $rust {
+                let env = Env::from_ptr(env);
+                match env.elements::<$rust>(array) {
+                    Some(elements) => env.copy_out(&elements, is_copy),
+                    None => std::ptr::null_mut(),
+                }
+            }
+
+            unsafe extern "C" fn $release_elements(
+                env: *mut c_void,
+                array: usize,
+                elements: *mut $rust,
+                mode: i32,
+            ) {
+                let env = Env::from_ptr(env);
+                if mode != JNI_ABORT {
+                    if let Some(length) = env.array_length(array) {
+                        let elements = std::slice::from_raw_parts(elements, length);
+                        env.set_elements(array, 0, elements);
+                    }
+                }
+                if mode != JNI_COMMIT {
+                    free(elements.cast());
+                }
+            }
+
+            unsafe extern "C" fn $get_region(
+                env: *mut c_void,
+                array: usize,
+                start: i32,
+                length: i32,
+                buffer: *mut $rust,
+            ) {
+                let env = Env::from_ptr(env);
+                let Some((array, range)) = env.region(array, start, length) else {
+                    return;
+                };
+                let Some(heap) = env.heap() else {
+                    return;
+                };
+                let elements = &heap.get(array).fields()[range];
+                for (index, &element) in elements.iter().enumerate() {
+                    *buffer.add(index) = <$rust>::from_value(element);
+                }
+            }
+
+            unsafe extern "C" fn $set_region(
+                env: *mut c_void,
+                array: usize,
+                start: i32,
+                length: i32,
+                buffer: *const $rust,
+            ) {
+                let elements = std::slice::from_raw_parts(buffer, length.max(0) as usize);
+                Env::from_ptr(env).set_elements(array, start, elements);
+            }
+        )*
+    };
+}
+
+array_functions! {
+    u8 => Bool, new_boolean_array, get_boolean_array_elements, release_boolean_array_elements,
+        get_boolean_array_region, set_boolean_array_region;
+    i8 => Byte, new_byte_array, get_byte_array_elements, release_byte_array_elements,
+        get_byte_array_region, set_byte_array_region;
+    u16 => Char, new_char_array, get_char_array_elements, release_char_array_elements,
+        get_char_array_region, set_char_array_region;
+    i16 => Short, new_short_array, get_short_array_elements, release_short_array_elements,
+        get_short_array_region, set_short_array_region;
+    i32 => Int, new_int_array, get_int_array_elements, release_int_array_elements,
+        get_int_array_region, set_int_array_region;
+    i64 => Long, new_long_array, get_long_array_elements, release_long_array_elements,
+        get_long_array_region, set_long_array_region;
+    f32 => Float, new_float_array, get_float_array_elements, release_float_array_elements,
+        get_float_array_region, set_float_array_region;
+    f64 => Double, new_double_array, get_double_array_elements, release_double_array_elements,
+        get_double_array_region, set_double_array_region;
+}
+
+unsafe extern "C" fn monitor_enter(env: *mut c_void, object: usize) -> i32 {
+    let env = Env::from_ptr(env);
+    let Some(object) = env.non_null(object) else {
+        return JNI_ERR;
+    };
+    let Some((vm, _)) = env.thread("entering monitors") else {
+        return JNI_ERR;
+    };
+    match vm.enter_from_native(object) {
+        Ok(()) => JNI_OK,
+        Err(error) => {
+            env.fail(error);
+            JNI_ERR
+        }
+    }
+}
+
+unsafe extern "C" fn monitor_exit(env: *mut c_void, object: usize) -> i32 {
+    let env = Env::from_ptr(env);
+    let classes = env.classes;
+    let Some(object) = env.non_null(object) else {
+        return JNI_ERR;
+    };
+    let Some((vm, _)) = env.thread("exiting monitors") else {
+        return JNI_ERR;
+    };
+    let target = LockTarget::Object(object);
+    match vm.scheduler.exit(classes, &mut vm.heap, target) {
+        Ok(()) => JNI_OK,
+        Err(exception) => {
+            env.exception = Some(exception);
+            JNI_ERR
+        }
+    }
+}
+
+/// A native method registered by `RegisterNatives`.
+#[repr(C)]
+struct NativeMethodEntry {
+    name: *const c_char,
+    signature: *const c_char,
+    function: *mut c_void,
+}
+
+unsafe extern "C" fn register_natives(
+    env: *mut c_void,
+    class: usize,
+    methods: *const NativeMethodEntry,
+    count: i32,
+) -> i32 {
+    let env = Env::from_ptr(env);
+    let classes = env.classes;
+    let class = env.class(class);
+    let loaded = &classes.classes[class].class;
+    for entry in std::slice::from_raw_parts(methods, count as usize) {
+        let method = java_str(entry.name)
+            .zip(java_str(entry.signature))
+            .and_then(|(name, descriptor)| loaded.method_index(name, descriptor))
+            .map(|method| MethodId {
+                class: class as u32,
+                method: method as u32,
+            })
+            .filter(|&method| {
+                classes.method_info(method).flags() & MethodFlags::NATIVE == MethodFlags::NATIVE
+            });
+        match method {
+            Some(method) => classes.register_native(method, entry.function),
+            None => {
+                env.throw("java/lang/NoSuchMethodError", Some(lossy(entry.name)));
+                return JNI_ERR;
+            }
+        }
+    }
+    JNI_OK
+}
+
+unsafe extern "C" fn unregister_natives(env: *mut c_void, class: usize) -> i32 {
+    let env = Env::from_ptr(env);
+    let class = env.class(class) as u32;
+    let mut functions = env.classes.natives.functions.borrow_mut();
+    functions.retain(|method, _| method.class != class);
+    JNI_OK
+}
+
+unsafe extern "C" fn get_java_vm(_: *mut c_void, vm: *mut *mut JavaVm) -> i32 {
+    *vm = JavaVm::get();
+    JNI_OK
+}
+
+/// A table of the functions of an interface, in the order of its slots.
+#[repr(C)]
+struct FunctionTable<const N: usize>([*const c_void; N]);
+
+// SAFETY: The table only holds pointers to functions.
+unsafe impl<const N: usize> Sync for FunctionTable<N> {}
+
+/// Builds a function table from the functions named after its slots. Those
+/// of the `JNIEnv` which are not supported take the environment first, as
+/// they all do, and record that they were called.
+macro_rules! function_table {
+    (@slot $slot:ident = null) => {
+        std::ptr::null()
+    };
+    (@slot $slot:ident = $function:ident $(::<$generic:ty>)?) => {
+        $function $(::<$generic>)? as *const c_void
+    };
+    (@slot $slot:ident) => {{
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn $slot(env: *mut c_void) -> usize {
+            Env::from_ptr(env).unsupported(concat!("the JNI function ", stringify!($slot)));
+            0
+        }
+        $slot as *const c_void
+    }};
+    ($($slot:ident $(= $function:ident $(::<$generic:ty>)?)?,)*) => {
+        FunctionTable([$(function_table!(@slot $slot $(= $function $(::<$generic>)?)?)),*])
+    };
+}
+
+/// The `JNIEnv` functions of version 1.8.
+static FUNCTIONS: FunctionTable<233> = function_table! {
+    reserved0 = null,
+    reserved1 = null,
+    reserved2 = null,
+    reserved3 = null,
+    GetVersion = get_version,
+    DefineClass,
+    FindClass = find_class,
+    FromReflectedMethod,
+    FromReflectedField,
+    ToReflectedMethod,
+    GetSuperclass = get_superclass,
+    IsAssignableFrom = is_assignable_from,
+    ToReflectedField,
+    Throw = throw,
+    ThrowNew = throw_new,
+    ExceptionOccurred,
+    ExceptionDescribe = exception_describe,
+    ExceptionClear = exception_clear,
+    FatalError = fatal_error,
+    PushLocalFrame,
+    PopLocalFrame,
+    NewGlobalRef = new_global_ref,
+    DeleteGlobalRef = delete_global_ref,
+    DeleteLocalRef = delete_local_ref,
+    IsSameObject = is_same_object,
+    NewLocalRef = new_local_ref,
+    EnsureLocalCapacity = ensure_local_capacity,
+    AllocObject = alloc_object,
+    NewObject = new_object,
+    NewObjectV = new_object_v,
+    NewObjectA = new_object_a,
+    GetObjectClass = get_object_class,
+    IsInstanceOf = is_instance_of,
+    GetMethodID = get_method_id,
+    CallObjectMethod = call_method::<usize>,
+    CallObjectMethodV = call_method_v::<usize>,
+    CallObjectMethodA = call_method_a::<usize>,
+    CallBooleanMethod = call_method::<u8>,
+    CallBooleanMethodV = call_method_v::<u8>,
+    CallBooleanMethodA = call_method_a::<u8>,
+    CallByteMethod = call_method::<i8>,
+    CallByteMethodV = call_method_v::<i8>,
+    CallByteMethodA = call_method_a::<i8>,
+    CallCharMethod = call_method::<u16>,
+    CallCharMethodV = call_method_v::<u16>,
+    CallCharMethodA = call_method_a::<u16>,
+    CallShortMethod = call_method::<i16>,
+    CallShortMethodV = call_method_v::<i16>,
+    CallShortMethodA = call_method_a::<i16>,
+    CallIntMethod = call_method::<i32>,
+    CallIntMethodV = call_method_v::<i32>,
+    CallIntMethodA = call_method_a::<i32>,
+    CallLongMethod = call_method::<i64>,
+    CallLongMethodV = call_method_v::<i64>,
+    CallLongMethodA = call_method_a::<i64>,
+    CallFloatMethod = call_method::<f32>,
+    CallFloatMethodV = call_method_v::<f32>,
+    CallFloatMethodA = call_method_a::<f32>,
+    CallDoubleMethod = call_method::<f64>,
+    CallDoubleMethodV = call_method_v::<f64>,
+    CallDoubleMethodA = call_method_a::<f64>,
+    CallVoidMethod = call_method::<()>,
+    CallVoidMethodV = call_method_v::<()>,
+    CallVoidMethodA = call_method_a::<()>,
+    CallNonvirtualObjectMethod = call_nonvirtual_method::<usize>,
+    CallNonvirtualObjectMethodV = call_nonvirtual_method_v::<usize>,
+    CallNonvirtualObjectMethodA = call_nonvirtual_method_a::<usize>,
+    CallNonvirtualBooleanMethod = call_nonvirtual_method::<u8>,
+    CallNonvirtualBooleanMethodV = call_nonvirtual_method_v::<u8>,
+    CallNonvirtualBooleanMethodA = call_nonvirtual_method_a::<u8>,
+    CallNonvirtualByteMethod = call_nonvirtual_method::<i8>,
+    CallNonvirtualByteMethodV = call_nonvirtual_method_v::<i8>,
+    CallNonvirtualByteMethodA = call_nonvirtual_method_a::<i8>,
+    CallNonvirtualCharMethod = call_nonvirtual_method::<u16>,
+    CallNonvirtualCharMethodV = call_nonvirtual_method_v::<u16>,
+    CallNonvirtualCharMethodA = call_nonvirtual_method_a::<u16>,
+    CallNonvirtualShortMethod = call_nonvirtual_method::<i16>,
+    CallNonvirtualShortMethodV = call_nonvirtual_method_v::<i16>,
+    CallNonvirtualShortMethodA = call_nonvirtual_method_a::<i16>,
+    CallNonvirtualIntMethod = call_nonvirtual_method::<i32>,
+    CallNonvirtualIntMethodV = call_nonvirtual_method_v::<i32>,
+    CallNonvirtualIntMethodA = call_nonvirtual_method_a::<i32>,
+    CallNonvirtualLongMethod = call_nonvirtual_method::<i64>,
+    CallNonvirtualLongMethodV = call_nonvirtual_method_v::<i64>,
+    CallNonvirtualLongMethodA = call_nonvirtual_method_a::<i64>,
+    CallNonvirtualFloatMethod = call_nonvirtual_method::<f32>,
+    CallNonvirtualFloatMethodV = call_nonvirtual_method_v::<f32>,
+    CallNonvirtualFloatMethodA = call_nonvirtual_method_a::<f32>,
+    CallNonvirtualDoubleMethod = call_nonvirtual_method::<f64>,
+    CallNonvirtualDoubleMethodV = call_nonvirtual_method_v::<f64>,
+    CallNonvirtualDoubleMethodA = call_nonvirtual_method_a::<f64>,
+    CallNonvirtualVoidMethod = call_nonvirtual_method::<()>,
+    CallNonvirtualVoidMethodV = call_nonvirtual_method_v::<()>,
+    CallNonvirtualVoidMethodA = call_nonvirtual_method_a::<()>,
+    GetFieldID = get_field_id,
+    GetObjectField = get_object_field,
+    GetBooleanField = get_boolean_field,
+    GetByteField = get_byte_field,
+    GetCharField = get_char_field,
+    GetShortField = get_short_field,
+    GetIntField = get_int_field,
+    GetLongField = get_long_field,
+    GetFloatField = get_float_field,
+    GetDoubleField = get_double_field,
+    SetObjectField = set_object_field,
+    SetBooleanField = set_boolean_field,
+    SetByteField = set_byte_field,
+    SetCharField = set_char_field,
+    SetShortField = set_short_field,
+    SetIntField = set_int_field,
+    SetLongField = set_long_field,
+    SetFloatField = set_float_field,
+    SetDoubleField = set_double_field,
+    GetStaticMethodID = get_static_method_id,
+    CallStaticObjectMethod = call_static_method::<usize>,
+    CallStaticObjectMethodV = call_static_method_v::<usize>,
+    CallStaticObjectMethodA = call_static_method_a::<usize>,
+    CallStaticBooleanMethod = call_static_method::<u8>,
+    CallStaticBooleanMethodV = call_static_method_v::<u8>,
+    CallStaticBooleanMethodA = call_static_method_a::<u8>,
+    CallStaticByteMethod = call_static_method::<i8>,
+    CallStaticByteMethodV = call_static_method_v::<i8>,
+    CallStaticByteMethodA = call_static_method_a::<i8>,
+    CallStaticCharMethod = call_static_method::<u16>,
+    CallStaticCharMethodV = call_static_method_v::<u16>,
+    CallStaticCharMethodA = call_static_method_a::<u16>,
+    CallStaticShortMethod = call_static_method::<i16>,
+    CallStaticShortMethodV = call_static_method_v::<i16>,
+    CallStaticShortMethodA = call_static_method_a::<i16>,
+    CallStaticIntMethod = call_static_method::<i32>,
+    CallStaticIntMethodV = call_static_method_v::<i32>,
+    CallStaticIntMethodA = call_static_method_a::<i32>,
+    CallStaticLongMethod = call_static_method::<i64>,
+    CallStaticLongMethodV = call_static_method_v::<i64>,
+    CallStaticLongMethodA = call_static_method_a::<i64>,
+    CallStaticFloatMethod = call_static_method::<f32>,
+    CallStaticFloatMethodV = call_static_method_v::<f32>,
+    CallStaticFloatMethodA = call_static_method_a::<f32>,
+    CallStaticDoubleMethod = call_static_method::<f64>,
+    CallStaticDoubleMethodV = call_static_method_v::<f64>,
+    CallStaticDoubleMethodA = call_static_method_a::<f64>,
+    CallStaticVoidMethod = call_static_method::<()>,
+    CallStaticVoidMethodV = call_static_method_v::<()>,
+    CallStaticVoidMethodA = call_static_method_a::<()>,
+    GetStaticFieldID = get_static_field_id,
+    GetStaticObjectField = get_static_object_field,
+    GetStaticBooleanField = get_static_boolean_field,
+    GetStaticByteField = get_static_byte_field,
+    GetStaticCharField = get_static_char_field,
+    GetStaticShortField = get_static_short_field,
+    GetStaticIntField = get_static_int_field,
+    GetStaticLongField = get_static_long_field,
+    GetStaticFloatField = get_static_float_field,
+    GetStaticDoubleField = get_static_double_field,
+    SetStaticObjectField = set_static_object_field,
+    SetStaticBooleanField = set_static_boolean_field,
+    SetStaticByteField = set_static_byte_field,
+    SetStaticCharField = set_static_char_field,
+    SetStaticShortField = set_static_short_field,
+    SetStaticIntField = set_static_int_field,
+    SetStaticLongField = set_static_long_field,
+    SetStaticFloatField = set_static_float_field,
+    SetStaticDoubleField = set_static_double_field,
+    NewString = new_string,
+    GetStringLength = get_string_length,
+    GetStringChars = get_string_chars,
+    ReleaseStringChars = release_string_chars,
+    NewStringUTF = new_string_utf,
+    GetStringUTFLength = get_string_utf_length,
+    GetStringUTFChars = get_string_utf_chars,
+    ReleaseStringUTFChars = release_string_chars,
+    GetArrayLength = get_array_length,
+    NewObjectArray = new_object_array,
+    GetObjectArrayElement = get_object_array_element,
+    SetObjectArrayElement = set_object_array_element,
+    NewBooleanArray = new_boolean_array,
+    NewByteArray = new_byte_array,
+    NewCharArray = new_char_array,
+    NewShortArray = new_short_array,
+    NewIntArray = new_int_array,
+    NewLongArray = new_long_array,
+    NewFloatArray = new_float_array,
+    NewDoubleArray = new_double_array,
+    GetBooleanArrayElements = get_boolean_array_elements,
+    GetByteArrayElements = get_byte_array_elements,
+    GetCharArrayElements = get_char_array_elements,
+    GetShortArrayElements = get_short_array_elements,
+    GetIntArrayElements = get_int_array_elements,
+    GetLongArrayElements = get_long_array_elements,
+    GetFloatArrayElements = get_float_array_elements,
+    GetDoubleArrayElements = get_double_array_elements,
+    ReleaseBooleanArrayElements = release_boolean_array_elements,
+    ReleaseByteArrayElements = release_byte_array_elements,
+    ReleaseCharArrayElements = release_char_array_elements,
+    ReleaseShortArrayElements = release_short_array_elements,
+    ReleaseIntArrayElements = release_int_array_elements,
+    ReleaseLongArrayElements = release_long_array_elements,
+    ReleaseFloatArrayElements = release_float_array_elements,
+    ReleaseDoubleArrayElements = release_double_array_elements,
+    GetBooleanArrayRegion = get_boolean_array_region,
+    GetByteArrayRegion = get_byte_array_region,
+    GetCharArrayRegion = get_char_array_region,
+    GetShortArrayRegion = get_short_array_region,
+    GetIntArrayRegion = get_int_array_region,
+    GetLongArrayRegion = get_long_array_region,
+    GetFloatArrayRegion = get_float_array_region,
+    GetDoubleArrayRegion = get_double_array_region,
+    SetBooleanArrayRegion = set_boolean_array_region,
+    SetByteArrayRegion = set_byte_array_region,
+    SetCharArrayRegion = set_char_array_region,
+    SetShortArrayRegion = set_short_array_region,
+    SetIntArrayRegion = set_int_array_region,
+    SetLongArrayRegion = set_long_array_region,
+    SetFloatArrayRegion = set_float_array_region,
+    SetDoubleArrayRegion = set_double_array_region,
+    RegisterNatives = register_natives,
+    UnregisterNatives = unregister_natives,
+    MonitorEnter = monitor_enter,
+    MonitorExit = monitor_exit,
+    GetJavaVM = get_java_vm,
+    GetStringRegion = get_string_region,
+    GetStringUTFRegion = get_string_utf_region,
+    GetPrimitiveArrayCritical,
+    ReleasePrimitiveArrayCritical,
+    GetStringCritical,
+    ReleaseStringCritical,
+    NewWeakGlobalRef,
+    DeleteWeakGlobalRef,
+    ExceptionCheck = exception_check,
+    NewDirectByteBuffer,
+    GetDirectBufferAddress,
+    GetDirectBufferCapacity,
+    GetObjectRefType = get_object_ref_type,
+};
+
+/// The `JavaVM` of the virtual machine, which all native libraries share.
+#[repr(C)]
+pub(super) struct JavaVm {
+    functions: &'static FunctionTable<8>,
+}
+
+static VM: JavaVm = JavaVm {
+    functions: &INVOKE_FUNCTIONS,
+};
+
+impl JavaVm {
+    pub(super) fn get() -> *mut JavaVm {
+        (&VM as *const JavaVm).cast_mut()
+    }
+}
+
+/// The `JavaVM` functions, which only give the threads running native
+/// methods their environment. Other threads cannot be attached, as each
+/// Java thread runs on the thread of the host.
+static INVOKE_FUNCTIONS: FunctionTable<8> = function_table! {
+    reserved0 = null,
+    reserved1 = null,
+    reserved2 = null,
+    DestroyJavaVM = destroy_java_vm,
+    AttachCurrentThread = attach_current_thread,
+    DetachCurrentThread = detach_current_thread,
+    GetEnv = get_env,
+    AttachCurrentThreadAsDaemon = attach_current_thread,
+};
+
+unsafe extern "C" fn destroy_java_vm(_: *mut JavaVm) -> i32 {
+    JNI_ERR
+}
+
+unsafe extern "C" fn attach_current_thread(
+    _: *mut JavaVm,
+    env: *mut *mut c_void,
+    _: *mut c_void,
+) -> i32 {
+    let current = CURRENT.get();
+    if current.is_null() {
+        return JNI_ERR;
+    }
+    *env = current;
+    JNI_OK
+}
+
+unsafe extern "C" fn detach_current_thread(_: *mut JavaVm) -> i32 {
+    // A thread running a native method has Java frames.
+    if CURRENT.get().is_null() {
+        JNI_OK
+    } else {
+        JNI_ERR
+    }
+}
+
+unsafe extern "C" fn get_env(_: *mut JavaVm, env: *mut *mut c_void, version: i32) -> i32 {
+    *env = std::ptr::null_mut();
+    if !is_supported(version) {
+        return JNI_EVERSION;
+    }
+    let current = CURRENT.get();
+    if current.is_null() {
+        return JNI_EDETACHED;
+    }
+    *env = current;
+    JNI_OK
+}
@@ -0,0 +1,719 @@
+//! Native methods implemented by shared libraries through the Java Native
+//! Interface, enabled by the `jni` feature.
+//!
+//! A library loaded with [`ClassManager::load_library`], or by the program
+//! with `System.load` or `System.loadLibrary`, has its `JNI_OnLoad` run, which
+//! may register native methods with `RegisterNatives`. The other
+//! native methods are looked up in the loaded libraries the first time they
+//! are called, under the short name `Java_<class>_<method>` and then under the
+//! long name which appends the mangled argument types, as overloads need.
+//!
+//! Native methods are called with a `JNIEnv` whose function table is
+//! implemented in [`env`]. References passed to them are local references,
+//! valid until they return, or global references, which are roots of the
+//! collector until deleted, as local references are while they are valid.
+//! Since classes have no objects, a `jclass` is a handle of its own, which
+//! stays valid for good.
+//!
+//! A native method runs once the frame calling it has stopped, so that it may
+//! call Java methods, which run on its thread above that frame while the other
+//! threads keep running, and allocate objects, collecting the heap if they do
+//! not fit. A native method the host calls has no thread, and the methods it
+//! calls run on threads of their own, as those the host calls do. Calling a
+//! function which is not supported, or a Java method which fails, leaves an
+//! error pending, which native code cannot clear and which is reported once
+//! the native method returns. Since exceptions cannot be caught, one left
+//! pending by a native method ends the program as
+//! [`JavaException::Native`].
+//!
+//! Native methods are called with their integer arguments and references in
+//! general-purpose registers and on the stack and their floating-point
+//! arguments in vector registers, as the System V calling convention of
+//! x86-64 assigns them independently of each other, so that no code has to
+//! be generated for each signature. This limits them to 12 integer arguments
+//! and 8 floating-point ones.
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the `jni` feature is only supported on x86-64 Linux");
+
+mod env;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+
+use super::call_frame::{self, CallFrame, Stack, ThreadStack};
+use super::class::FieldType;
+use super::convert::JavaHeap;
+use super::decode::MethodId;
+use super::heap::{Heap, ObjectRef};
+use super::monitor::LockTarget;
+use super::native::NativeMethod;
+use super::value::{Value, ValueType};
+use super::{run, ClassManager, ExecuteError, JavaException, LinkageError, MethodFlags, Vm};
+use crate::string::JavaStr;
+
+use env::{Access, Env, JavaVm};
+
+const RTLD_NOW: i32 = 2;
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: i32) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *mut c_char;
+    fn dlclose(handle: *mut c_void) -> i32;
+}
+
+/// The number of integer arguments passed in registers after the `JNIEnv`
+/// and the receiver or class, and on the stack after them.
+const REGISTER_INTS: usize = 4;
+const STACK_INTS: usize = 8;
+/// The number of floating-point arguments, all passed in registers.
+const FLOATS: usize = 8;
+
+/// A native method as called by the virtual machine, with the integer and
+/// floating-point arguments in the order the calling convention assigns
+/// them. Natives returning a floating-point value are called as
+/// [`FloatFunction`] instead.
+type IntFunction = unsafe extern "C" fn(
+    *mut c_void,
+    usize,
+    i64,
+    i64,
+    i64,
+    i64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+) -> i64;
+type FloatFunction = unsafe extern "C" fn(
+    *mut c_void,
+    usize,
+    i64,
+    i64,
+    i64,
+    i64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+) -> f64;
+
+/// A library could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The library could not be opened, for the reason given by the dynamic
+    /// linker.
+    Open(String),
+    /// `JNI_OnLoad` asked for a version of the JNI which is not supported.
+    UnsupportedVersion(i32),
+    /// `System.loadLibrary` found no file for the library in the library
+    /// path.
+    NotFound(String),
+    /// `JNI_OnLoad` threw an exception or used a feature which is not
+    /// supported.
+    OnLoad(ExecuteError),
+}
+
+impl LoadError {
+    /// Returns the message of the `UnsatisfiedLinkError` the program throws
+    /// for the error, unless `JNI_OnLoad` failed.
+    fn message(&self) -> String {
+        match self {
+            Self::Open(reason) => reason.clone(),
+            Self::UnsupportedVersion(version) => format!("unsupported JNI version {version:#x}"),
+            Self::NotFound(name) => format!("no {name} in java.library.path"),
+            Self::OnLoad(error) => error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OnLoad(error) => write!(f, "JNI_OnLoad failed: {error}"),
+            _ => write!(f, "java.lang.UnsatisfiedLinkError: {}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// The program fails to load a library with an `UnsatisfiedLinkError`, or
+/// with the error `JNI_OnLoad` failed with.
+impl From<LoadError> for ExecuteError {
+    fn from(error: LoadError) -> Self {
+        if let LoadError::OnLoad(error) = error {
+            return error;
+        }
+        ExecuteError::Exception(JavaException::Native {
+            class: crate::java_str!("java/lang/UnsatisfiedLinkError").to_owned(),
+            message: Some(error.message()),
+        })
+    }
+}
+
+/// A shared library opened by the dynamic linker, and the canonical path it
+/// was loaded from.
+#[derive(Debug)]
+struct Library {
+    handle: *mut c_void,
+    path: PathBuf,
+}
+
+impl Library {
+    fn symbol(&self, name: &str) -> Option<*mut c_void> {
+        let name = CString::new(name).ok()?;
+        // SAFETY: The library is open and the name is nul-terminated.
+        let symbol = unsafe { dlsym(self.handle, name.as_ptr()) };
+        (!symbol.is_null()).then_some(symbol)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        // SAFETY: The library was opened by `dlopen`, and none of its
+        // functions run once the class manager is dropped.
+        unsafe {
+            dlclose(self.handle);
+        }
+    }
+}
+
+/// The native libraries loaded by a class manager and the references
+/// native methods keep to objects.
+#[derive(Debug, Default)]
+pub(super) struct Natives {
+    libraries: RefCell<Vec<Library>>,
+    /// The function of each native method registered or called so far.
+    functions: RefCell<HashMap<MethodId, *mut c_void>>,
+    /// The objects held by global references, or `None` for those deleted,
+    /// which are reused by later ones.
+    pub(super) globals: RefCell<Vec<Option<ObjectRef>>>,
+    /// The objects held by the local references of the native methods which
+    /// are running, innermost last, or `None` for those deleted.
+    pub(super) locals: RefCell<Vec<Option<ObjectRef>>>,
+    /// The directories `System.loadLibrary` looks for libraries in.
+    library_path: Vec<PathBuf>,
+}
+
+/// Returns the name of the file holding the library `name`, as
+/// `System.mapLibraryName` does.
+pub fn map_library_name(name: &str) -> String {
+    format!("lib{name}.so")
+}
+
+impl ClassManager {
+    /// Loads the native library at `path`, as `System.load` does, and runs
+    /// its `JNI_OnLoad` function, if any. A library loaded before is not
+    /// loaded again.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LoadError`] if the library cannot be opened or if its
+    /// `JNI_OnLoad` fails.
+    ///
+    /// # Safety
+    ///
+    /// Loading the library runs its initializers and `JNI_OnLoad`, and its
+    /// native methods run whenever the program calls them, none of which the
+    /// virtual machine can check. They must be sound to run, and must call the
+    /// functions of the `JNIEnv` as the JNI specification requires, with
+    /// valid references, field IDs and strings.
+    pub unsafe fn load_library(&self, path: &Path) -> Result<(), LoadError> {
+        self.open_library(path, None)
+    }
+
+    /// Sets the directories `System.loadLibrary` looks for libraries in,
+    /// which the property `java.library.path` lists in Java. There are none
+    /// by default.
+    pub fn set_library_path(&mut self, directories: Vec<PathBuf>) {
+        self.natives.library_path = directories;
+    }
+
+    /// Loads the native library at `path` and runs its `JNI_OnLoad`, which
+    /// may access objects in `heap` if given.
+    ///
+    /// # Safety
+    ///
+    /// See [`ClassManager::load_library`].
+    unsafe fn open_library(&self, path: &Path, heap: Option<&mut Heap>) -> Result<(), LoadError> {
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let libraries = self.natives.libraries.borrow();
+        if libraries.iter().any(|library| library.path == canonical) {
+            return Ok(());
+        }
+        drop(libraries);
+        let Ok(name) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+            return Err(LoadError::Open(format!("{}: invalid path", path.display())));
+        };
+        // SAFETY: The path is nul-terminated, and the message of `dlerror` is
+        // copied before any other call to the dynamic linker. The caller
+        // vouches for the initializers of the library.
+        let handle = unsafe { dlopen(name.as_ptr(), RTLD_NOW) };
+        if handle.is_null() {
+            let reason = unsafe { CStr::from_ptr(dlerror()) };
+            return Err(LoadError::Open(reason.to_string_lossy().into_owned()));
+        }
+        let library = Library {
+            handle,
+            path: canonical,
+        };
+        let on_load = library.symbol("JNI_OnLoad");
+        // The library stays loaded if `JNI_OnLoad` fails, as it may have
+        // registered native methods.
+        self.natives.libraries.borrow_mut().push(library);
+
+        if let Some(on_load) = on_load {
+            type OnLoad = unsafe extern "C" fn(*mut JavaVm, *mut c_void) -> i32;
+            // SAFETY: `JNI_OnLoad` has this signature, and the caller vouches
+            // for what it does.
+            let on_load = unsafe { std::mem::transmute::<*mut c_void, OnLoad>(on_load) };
+            let access = heap.map_or(Access::Classes, Access::Heap);
+            let mut env = Env::new(self, access);
+            let version = env.enter(|_| unsafe { on_load(JavaVm::get(), std::ptr::null_mut()) });
+            if let Some(error) = env.error() {
+                return Err(LoadError::OnLoad(error));
+            }
+            if !env::is_supported(version) {
+                return Err(LoadError::UnsupportedVersion(version));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `System.load` or `System.loadLibrary`, whose argument is on top
+    /// of `stack`. The first loads the library at an absolute path, and the
+    /// second the file [`map_library_name`] names in the first directory of
+    /// the library path which has it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JavaException::NullPointer`] if the argument is null, the
+    /// `UnsatisfiedLinkError` of a [`LoadError`] if the library cannot be
+    /// found or opened, and the error `JNI_OnLoad` fails with.
+    pub(super) fn invoke_load(
+        &self,
+        native: NativeMethod,
+        stack: &mut Stack,
+        heap: &mut Heap,
+    ) -> Result<(), ExecuteError> {
+        let name: Option<String> = stack
+            .pop_as(&mut JavaHeap::new(self, heap))
+            .expect("argument is a string");
+        let name = name.ok_or(JavaException::NullPointer)?;
+        let path = match native {
+            NativeMethod::SystemLoad if Path::new(&name).is_absolute() => PathBuf::from(name),
+            NativeMethod::SystemLoad => {
+                let reason = format!("Expecting an absolute path of the library: {name}");
+                return Err(LoadError::Open(reason).into());
+            }
+            _ => {
+                let file = map_library_name(&name);
+                (self.natives.library_path.iter())
+                    .map(|directory| directory.join(&file))
+                    .find(|path| path.is_file())
+                    .ok_or(LoadError::NotFound(name))?
+            }
+        };
+        // SAFETY: The program asked for the library, and trusts it as much
+        // as its own native methods, as any virtual machine does.
+        unsafe { self.open_library(&path, Some(heap)) }?;
+        Ok(())
+    }
+
+    /// Registers `function` as the implementation of the native method
+    /// `method`.
+    fn register_native(&self, method: MethodId, function: *mut c_void) {
+        self.natives.functions.borrow_mut().insert(method, function);
+    }
+
+    /// Returns the function implementing the native method `method`, looking
+    /// it up in the loaded libraries if it was not registered.
+    fn native_function(&self, method: MethodId) -> Option<*mut c_void> {
+        if let Some(&function) = self.natives.functions.borrow().get(&method) {
+            return Some(function);
+        }
+        let (class, name, descriptor) = self.signature(method);
+        let short = short_name(&class, &name);
+        let long = long_name(&class, &name, &descriptor);
+        let libraries = self.natives.libraries.borrow();
+        let function = (libraries.iter())
+            .find_map(|library| library.symbol(&short).or_else(|| library.symbol(&long)))?;
+        self.register_native(method, function);
+        Some(function)
+    }
+
+    /// Returns whether `method` is a native method which a library may
+    /// implement. Since no frame can be pushed for it, the calls which select
+    /// it at runtime stop their frame for [`Vm::invoke_jni`] to run it.
+    pub(super) fn is_jni(&self, method: MethodId) -> bool {
+        let loaded = &self.classes[method.class as usize];
+        loaded.code[method.method as usize].is_none()
+            && loaded.host.is_empty()
+            && self.method_info(method).flags() & MethodFlags::NATIVE == MethodFlags::NATIVE
+    }
+}
+
+impl<'a> Vm<'a> {
+    /// Runs the native method `method` of a library for the running thread,
+    /// whose frame stopped with the receiver and arguments on top of its
+    /// operand stack, and pushes its result there.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors [`Vm::call_jni`] does, and
+    /// [`JavaException::NullPointer`] if the receiver is null.
+    pub(super) fn invoke_jni(&mut self, method: MethodId) -> Result<(), ExecuteError> {
+        let info = self.classes.method_info(method);
+        let thread = self.scheduler.current();
+        let id = thread.id;
+        let frame = thread.call_stack.last_mut().expect("thread has a frame");
+        let mut stack = thread.stack.stack(frame);
+        let mut args: Vec<Value> = (info.parsed_descriptor().args().iter().rev())
+            .map(|parameter| stack.pop(ValueType::from(parameter)))
+            .collect();
+        args.reverse();
+        let this = if info.flags() & MethodFlags::STATIC == MethodFlags::STATIC {
+            None
+        } else {
+            Some(stack.pop_reference().ok_or(JavaException::NullPointer)?)
+        };
+        if let Some(result) = self.call_jni(method, this, &args, Some(id))? {
+            let thread = self.scheduler.current();
+            let frame = thread.call_stack.last_mut().expect("thread has a frame");
+            thread.stack.stack(frame).push(result);
+        }
+        Ok(())
+    }
+
+    /// Runs the native method `method` of a library with `this` as its
+    /// receiver and `args` as its arguments on the thread with the id
+    /// `thread`, or for the host, and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::UnsatisfiedLink`] if no library implements
+    /// the method, or if it has too many arguments to be called, and the
+    /// error the method left pending, if any.
+    pub(super) fn call_jni(
+        &mut self,
+        method: MethodId,
+        this: Option<ObjectRef>,
+        args: &[Value],
+        thread: Option<u32>,
+    ) -> Result<Option<Value>, ExecuteError> {
+        let classes = self.classes;
+        let descriptor = classes.method_info(method).parsed_descriptor();
+        let (ints, floats) =
+            (descriptor.args().iter()).partition::<Vec<_>, _>(|parameter| !is_float(parameter));
+        let function = classes
+            .native_function(method)
+            .filter(|_| ints.len() <= REGISTER_INTS + STACK_INTS && floats.len() <= FLOATS);
+        let Some(function) = function else {
+            let (class, name, descriptor) = classes.signature(method);
+            return Err(LinkageError::UnsatisfiedLink {
+                class,
+                name,
+                descriptor,
+            }
+            .into());
+        };
+
+        let mut env = Env::new(classes, Access::Vm(self, thread));
+        let receiver = match this {
+            Some(this) => env.new_local(Some(this)),
+            None => env::class_handle(method.class as usize),
+        };
+        let mut int_args = [0i64; REGISTER_INTS + STACK_INTS];
+        let mut float_args = [0f64; FLOATS];
+        let (mut next_int, mut next_float) = (0, 0);
+        for &arg in args {
+            match arg {
+                Value::Int(value) => {
+                    int_args[next_int] = value.into();
+                    next_int += 1;
+                }
+                Value::Long(value) => {
+                    int_args[next_int] = value;
+                    next_int += 1;
+                }
+                Value::Reference(object) => {
+                    int_args[next_int] = env.new_local(object) as i64;
+                    next_int += 1;
+                }
+                // A `float` is read from the low half of the register.
+                Value::Float(value) => {
+                    float_args[next_float] = f64::from_bits(value.to_bits().into());
+                    next_float += 1;
+                }
+                Value::Double(value) => {
+                    float_args[next_float] = value;
+                    next_float += 1;
+                }
+                Value::RetAddr(_) => unreachable!("arguments are not return addresses"),
+            }
+        }
+
+        let [i0, i1, i2, i3, i4, i5, i6, i7, i8, i9, i10, i11] = int_args;
+        let [f0, f1, f2, f3, f4, f5, f6, f7] = float_args;
+        let result = descriptor.result();
+        let bits = env.enter(|env| {
+            // SAFETY: The function is the native method, which takes its
+            // integer and floating-point arguments in the registers and stack
+            // slots they are passed in, and ignores the others.
+            unsafe {
+                if result.is_some_and(is_float) {
+                    let function = std::mem::transmute::<*mut c_void, FloatFunction>(function);
+                    function(
+                        env, receiver, i0, i1, i2, i3, f0, f1, f2, f3, f4, f5, f6, f7, i4, i5, i6,
+                        i7, i8, i9, i10, i11,
+                    )
+                    .to_bits()
+                } else {
+                    let function = std::mem::transmute::<*mut c_void, IntFunction>(function);
+                    function(
+                        env, receiver, i0, i1, i2, i3, f0, f1, f2, f3, f4, f5, f6, f7, i4, i5, i6,
+                        i7, i8, i9, i10, i11,
+                    ) as u64
+                }
+            }
+        });
+
+        if let Some(error) = env.error() {
+            return Err(error);
+        }
+        Ok(result.map(|result| env.value(result, bits)))
+    }
+
+    /// Runs `method` for a native method running on the thread with the id
+    /// `thread`, or for the host, with `receiver` and `args` as its
+    /// arguments, and returns its result. The method runs on the thread above
+    /// the frame which called the native method, or on a thread of its own
+    /// for the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the method fails with, and the errors of
+    /// [`Vm::run_native`].
+    pub(super) fn call_from_native(
+        &mut self,
+        method: MethodId,
+        receiver: Option<ObjectRef>,
+        args: &[Value],
+        thread: Option<u32>,
+    ) -> Result<Option<Value>, ExecuteError> {
+        let classes = self.classes;
+        if thread.is_none() {
+            // The references are pinned, as the host pins the arguments it
+            // passes, while the thread is started.
+            let mut values = Vec::with_capacity(args.len() + 1);
+            values.push(Value::Reference(receiver));
+            values.extend_from_slice(args);
+            self.pin_values(&mut values);
+            let Value::Reference(receiver) = values[0] else {
+                unreachable!("the receiver is a reference");
+            };
+            let result = self.invoke(method, receiver, &values[1..]);
+            for value in values {
+                if let Value::Reference(Some(object)) = value {
+                    self.release(object);
+                }
+            }
+            let result = result?;
+            if let Some(Value::Reference(Some(object))) = result {
+                self.release(object);
+            }
+            return Ok(result);
+        }
+
+        let descriptor = classes.method_info(method).parsed_descriptor();
+        let host = &classes.classes[method.class as usize].host;
+        if let Some(host) = host.get(method.method as usize) {
+            return host.call(descriptor, classes, &mut self.heap, receiver, args);
+        }
+        if classes.is_jni(method) {
+            return self.call_jni(method, receiver, args, thread);
+        }
+        let code = classes.method_code(method)?;
+        let thread = self.scheduler.current();
+        let frames = thread.call_stack.len();
+        // The frame is pushed on top of the operand stack of the frame which
+        // called the native method, as the frames it calls are.
+        let caller = thread.call_stack.last().expect("thread has a frame");
+        let base = caller.base + caller.code.max_locals as usize + caller.depth;
+        let frame = CallFrame::new(method, code, base);
+        if frame.overflows() {
+            return Err(JavaException::StackOverflow.into());
+        }
+        let registers = thread.stack.registers(base, code);
+        let receiver = receiver.map(|receiver| Value::Reference(Some(receiver)));
+        let mut slot = 0;
+        for &arg in receiver.iter().chain(args) {
+            call_frame::set(registers, slot, arg);
+            slot += arg.size();
+        }
+        thread.call_stack.push(frame);
+        classes.initialize(method.class as usize, &mut thread.call_stack)?;
+        self.run_native(frames)?;
+
+        let Some(result) = descriptor.result() else {
+            return Ok(None);
+        };
+        let thread = self.scheduler.current();
+        let caller = thread.call_stack.last_mut().expect("thread has a frame");
+        Ok(Some(
+            thread.stack.stack(caller).pop(ValueType::from(result)),
+        ))
+    }
+
+    /// Runs the threads until the running thread, which runs a native method,
+    /// is back to its first `frames` frames, the others having returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the program fails with meanwhile, and
+    /// [`ExecuteError::Unsupported`] if only daemon threads are left first,
+    /// as the program cannot end while a native method runs.
+    fn run_native(&mut self, frames: usize) -> Result<(), ExecuteError> {
+        let id = self.scheduler.await_frames(frames);
+        let result = run(self, Some(id));
+        self.scheduler.stop_awaiting(id);
+        result?;
+        let thread = self.scheduler.current();
+        if thread.id != id || thread.call_stack.len() != frames {
+            let feature = "ending the program while a native method runs";
+            return Err(ExecuteError::Unsupported(feature.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Enters the monitor of `object` for the running thread, which runs a
+    /// native method, blocking it until the thread owning it exits it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`Vm::run_native`].
+    pub(super) fn enter_from_native(&mut self, object: ObjectRef) -> Result<(), ExecuteError> {
+        let target = LockTarget::Object(object);
+        if self.scheduler.enter(self.classes, &mut self.heap, target) {
+            let frames = self.scheduler.current().call_stack.len();
+            self.run_native(frames)?;
+        }
+        Ok(())
+    }
+
+    /// Initializes the class at `class` for a native method running on the
+    /// thread with the id `thread`, or on a thread of its own for the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the initialization fails with, and the errors of
+    /// [`Vm::run_native`].
+    pub(super) fn initialize_from_native(
+        &mut self,
+        class: usize,
+        thread: Option<u32>,
+    ) -> Result<(), ExecuteError> {
+        let classes = self.classes;
+        if classes.classes[class].initialized.get() {
+            return Ok(());
+        }
+        if thread.is_some() {
+            let thread = self.scheduler.current();
+            let frames = thread.call_stack.len();
+            classes.initialize(class, &mut thread.call_stack)?;
+            if thread.call_stack.len() > frames {
+                self.run_native(frames)?;
+            }
+        } else {
+            let mut call_stack = Vec::new();
+            classes.initialize(class, &mut call_stack)?;
+            if !call_stack.is_empty() {
+                let id = self.scheduler.start(ThreadStack::new(), call_stack, None);
+                run(self, Some(id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_float(field_type: &FieldType) -> bool {
+    matches!(field_type, FieldType::Float | FieldType::Double)
+}
+
+/// Returns the name a library exports the native method `name` of `class`
+/// under, unless it is overloaded.
+fn short_name(class: &JavaStr, name: &JavaStr) -> String {
+    let mut symbol = String::from("Java_");
+    mangle(&mut symbol, class);
+    symbol.push('_');
+    mangle(&mut symbol, name);
+    symbol
+}
+
+/// Returns the name a library exports the native method `name` of `class`
+/// under, followed by its argument types.
+fn long_name(class: &JavaStr, name: &JavaStr, descriptor: &JavaStr) -> String {
+    let mut symbol = short_name(class, name);
+    symbol.push_str("__");
+    let args = (descriptor.as_bytes().iter())
+        .position(|&b| b == b')')
+        .and_then(|end| descriptor.get(1..end))
+        .expect("method descriptor has arguments");
+    mangle(&mut symbol, args);
+    symbol
+}
+
+/// Appends `name` to `symbol`, escaping the characters which cannot appear
+/// in C identifiers or which would be ambiguous.
+fn mangle(symbol: &mut String, name: &JavaStr) {
+    for c in name.chars() {
+        match char::from_u32(c) {
+            Some('/') => symbol.push('_'),
+            Some('_') => symbol.push_str("_1"),
+            Some(';') => symbol.push_str("_2"),
+            Some('[') => symbol.push_str("_3"),
+            Some(c) if c.is_ascii_alphanumeric() => symbol.push(c),
+            _ => {
+                let mut units = [0; 2];
+                let units = match char::from_u32(c) {
+                    Some(c) => c.encode_utf16(&mut units),
+                    None => {
+                        units[0] = c as u16;
+                        &mut units[..1]
+                    }
+                };
+                for unit in units {
+                    symbol.push_str(&format!("_0{unit:04x}"));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use crate::java_str;
+use crate::string::JavaStr;
+
+use super::class::{FieldFlags, MethodFlags};
+use super::decode::MethodId;
+use super::heap::{ReferenceKind, ReferenceType};
+use super::value::Value;
+use super::{default_value, ClassManager, LinkageError};
+
+/// The layout of the objects of a class and the tables used to dispatch calls
+/// on them. These are built the first time the class is instantiated or one
+/// of its instance members is referenced.
+#[derive(Debug)]
+pub(super) struct Linked {
+    /// The initial values of the instance fields of an object of the class.
+    pub(super) fields: Box<[Value]>,
+    /// The index in the object of each field declared by the class, in the
+    /// same order as [`Class::fields`]. Static fields have no index.
+    ///
+    /// [`Class::fields`]: super::class::Class::fields
+    pub(super) field_slots: Box<[Option<u32>]>,
+    /// The virtual method table, holding the method selected for each virtual
+    /// method of the class. A method keeps its index in the tables of all
+    /// subclasses, which replace it if they override it.
+    pub(super) vtable: Box<[MethodId]>,
+    /// The index in `vtable` of each method declared by the class, in the
+    /// same order as [`Class::methods`]. Methods which are not virtual have no
+    /// index.
+    ///
+    /// [`Class::methods`]: super::class::Class::methods
+    pub(super) vtable_indices: Box<[Option<u32>]>,
+    /// Every interface the class implements, directly or not. For an
+    /// interface, these are its superinterfaces.
+    pub(super) interfaces: Box<[u32]>,
+    /// The interface method table of each interface the class implements,
+    /// holding the method selected for each method of the interface in the
+    /// same order as [`Class::methods`]. A method has no selection if it is
+    /// static or private, or if several default methods are equally specific.
+    ///
+    /// [`Class::methods`]: super::class::Class::methods
+    pub(super) itables: HashMap<u32, Box<[Option<MethodId>]>>,
+    /// The kind of reference the objects of the class are, if the class
+    /// extends `java/lang/ref/SoftReference`, `WeakReference` or
+    /// `PhantomReference`.
+    pub(super) reference: Option<ReferenceType>,
+    /// The `finalize` method of the objects of the class, unless it is the
+    /// empty one of `java/lang/Object`.
+    pub(super) finalizer: Option<MethodId>,
+    /// The `enqueueFromPending` method of a reference class, which adds a
+    /// cleared reference to its queue.
+    pub(super) enqueue: Option<MethodId>,
+}
+
+impl ClassManager {
+    /// Links the class at `class` and its superclasses and superinterfaces,
+    /// building its object layout and method tables.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LinkageError`] if a superclass or superinterface is not
+    /// loaded or if a method overrides a final method.
+    pub(super) fn link(&self, class: usize) -> Result<&Linked, LinkageError> {
+        let loaded = &self.classes[class];
+        if let Some(linked) = loaded.linked.get() {
+            return Ok(linked);
+        }
+        self.verify(class)?;
+
+        let super_linked = match loaded.class.super_name() {
+            Some(name) => Some(self.link(self.find(name)?)?),
+            None => None,
+        };
+        let mut interfaces =
+            super_linked.map_or_else(Vec::new, |linked| linked.interfaces.to_vec());
+        for name in loaded.class.interfaces() {
+            let interface = self.find(name)?;
+            for &index in [interface as u32]
+                .iter()
+                .chain(&self.link(interface)?.interfaces)
+            {
+                if !interfaces.contains(&index) {
+                    interfaces.push(index);
+                }
+            }
+        }
+
+        let (fields, field_slots) = self.layout(class, super_linked);
+        let mut vtable = super_linked.map_or_else(Vec::new, |linked| linked.vtable.to_vec());
+        let vtable_indices = self.build_vtable(class, &mut vtable)?;
+
+        let mut itables = HashMap::new();
+        if !self.is_interface(class) {
+            for &interface in &interfaces {
+                let itable = self.build_itable(interface as usize, &vtable, &interfaces);
+                itables.insert(interface, itable);
+            }
+            self.add_default_methods(&mut vtable, &interfaces, &itables);
+        }
+
+        let reference = match loaded.class.name().as_bytes() {
+            b"java/lang/ref/SoftReference" => self.reference_type(ReferenceKind::Soft)?,
+            b"java/lang/ref/WeakReference" => self.reference_type(ReferenceKind::Weak)?,
+            b"java/lang/ref/PhantomReference" => self.reference_type(ReferenceKind::Phantom)?,
+            _ => super_linked.and_then(|linked| linked.reference),
+        };
+        let finalizer = self
+            .select(&vtable, java_str!("finalize"), java_str!("()V"))
+            .filter(|method| {
+                self.classes[method.class as usize].class.name() != java_str!("java/lang/Object")
+            });
+        let enqueue = reference
+            .and_then(|_| self.select(&vtable, java_str!("enqueueFromPending"), java_str!("()V")));
+
+        let linked = Linked {
+            fields,
+            field_slots,
+            vtable: vtable.into_boxed_slice(),
+            vtable_indices,
+            interfaces: interfaces.into_boxed_slice(),
+            itables,
+            reference,
+            finalizer,
+            enqueue,
+        };
+        Ok(loaded.linked.get_or_init(|| linked))
+    }
+
+    /// Returns the type of the references of a subclass of
+    /// `java/lang/ref/Reference`, whose `referent` field the collector clears.
+    fn reference_type(&self, kind: ReferenceKind) -> Result<Option<ReferenceType>, LinkageError> {
+        let class = self.find(java_str!("java/lang/ref/Reference"))?;
+        let Some(field) = self.classes[class]
+            .class
+            .field_index(java_str!("referent"), java_str!("Ljava/lang/Object;"))
+        else {
+            return Ok(None);
+        };
+        let referent = self.link(class)?.field_slots[field];
+        Ok(referent.map(|referent| ReferenceType { kind, referent }))
+    }
+
+    /// Returns the method selected by a virtual method table for a virtual
+    /// method with the given name and descriptor.
+    pub(super) fn select(
+        &self,
+        vtable: &[MethodId],
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Option<MethodId> {
+        vtable
+            .iter()
+            .copied()
+            .find(|&method| self.same_signature(method, name, descriptor))
+    }
+
+    /// Lays out the instance fields of the class after those of its
+    /// superclass.
+    fn layout(
+        &self,
+        class: usize,
+        super_linked: Option<&Linked>,
+    ) -> (Box<[Value]>, Box<[Option<u32>]>) {
+        let mut fields = super_linked.map_or_else(Vec::new, |linked| linked.fields.to_vec());
+        let field_slots = self.classes[class]
+            .class
+            .fields()
+            .iter()
+            .map(|field| {
+                if field.flags() & FieldFlags::STATIC == FieldFlags::STATIC {
+                    return None;
+                }
+                fields.push(default_value(field.parsed_descriptor()));
+                Some(fields.len() as u32 - 1)
+            })
+            .collect();
+        (fields.into_boxed_slice(), field_slots)
+    }
+
+    /// Builds the virtual method table of the class from `vtable`, that of its
+    /// superclass, replacing the methods which the class overrides and
+    /// appending the new ones. Returns the index of each method of the class.
+    fn build_vtable(
+        &self,
+        class: usize,
+        vtable: &mut Vec<MethodId>,
+    ) -> Result<Box<[Option<u32>]>, LinkageError> {
+        let loaded = &self.classes[class];
+        let constants = loaded.class.constants();
+        let package = package(loaded.class.name());
+        let mut vtable_indices = Vec::with_capacity(loaded.class.methods().len());
+        for (index, method) in loaded.class.methods().iter().enumerate() {
+            let name = method.name(constants);
+            let flags = method.flags();
+            if flags & MethodFlags::STATIC == MethodFlags::STATIC
+                || flags & MethodFlags::PRIVATE == MethodFlags::PRIVATE
+                || name.as_bytes().first() == Some(&b'<')
+            {
+                vtable_indices.push(None);
+                continue;
+            }
+
+            let id = MethodId {
+                class: class as u32,
+                method: index as u32,
+            };
+            let descriptor = method.descriptor(constants);
+            let mut vtable_index = None;
+            for (slot, entry) in vtable.iter_mut().enumerate() {
+                if !self.same_signature(*entry, name, descriptor)
+                    || !self.can_override(*entry, package)
+                {
+                    continue;
+                }
+                let overridden = self.method_info(*entry).flags();
+                if overridden & MethodFlags::FINAL == MethodFlags::FINAL {
+                    return Err(LinkageError::OverridesFinal {
+                        class: loaded.class.name().to_owned(),
+                        name: name.to_owned(),
+                        descriptor: descriptor.to_owned(),
+                    });
+                }
+                *entry = id;
+                vtable_index.get_or_insert(slot as u32);
+            }
+            if vtable_index.is_none() {
+                vtable.push(id);
+                vtable_index = Some(vtable.len() as u32 - 1);
+            }
+            vtable_indices.push(vtable_index);
+        }
+        Ok(vtable_indices.into_boxed_slice())
+    }
+
+    /// Returns whether a method declared in `package` can override `method`:
+    /// public and protected methods can always be overridden, while
+    /// package-private ones only from within their own package.
+    fn can_override(&self, method: MethodId, package: &[u8]) -> bool {
+        let flags = self.method_info(method).flags();
+        if flags & MethodFlags::PUBLIC == MethodFlags::PUBLIC
+            || flags & MethodFlags::PROTECTED == MethodFlags::PROTECTED
+        {
+            return true;
+        }
+        let owner = self.classes[method.class as usize].class.name();
+        self::package(owner) == package
+    }
+
+    /// Selects the method which implements each method of `interface` for a
+    /// class with the given virtual method table and interfaces.
+    fn build_itable(
+        &self,
+        interface: usize,
+        vtable: &[MethodId],
+        interfaces: &[u32],
+    ) -> Box<[Option<MethodId>]> {
+        let class = &self.classes[interface].class;
+        let constants = class.constants();
+        class
+            .methods()
+            .iter()
+            .map(|method| {
+                let flags = method.flags();
+                if flags & MethodFlags::STATIC == MethodFlags::STATIC
+                    || flags & MethodFlags::PRIVATE == MethodFlags::PRIVATE
+                {
+                    return None;
+                }
+                let name = method.name(constants);
+                let descriptor = method.descriptor(constants);
+
+                // A method of the class or of a superclass takes precedence
+                // over any default method.
+                let inherited = vtable.iter().copied().find(|&entry| {
+                    !self.is_interface(entry.class as usize)
+                        && self.same_signature(entry, name, descriptor)
+                });
+                inherited.or_else(|| self.maximally_specific(interfaces, name, descriptor))
+            })
+            .collect()
+    }
+
+    /// Returns the maximally-specific method with the given name and
+    /// descriptor among `interfaces`, that is, the one declared by an
+    /// interface which no other candidate's interface extends. A default
+    /// method is preferred over abstract ones, and there is no selection if
+    /// several default methods are maximally specific.
+    pub(super) fn maximally_specific(
+        &self,
+        interfaces: &[u32],
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Option<MethodId> {
+        let candidates: Vec<MethodId> = interfaces
+            .iter()
+            .filter_map(|&interface| {
+                let class = &self.classes[interface as usize].class;
+                let index = class.method_index(name, descriptor)?;
+                let flags = class.methods()[index].flags();
+                let hidden = MethodFlags::STATIC | MethodFlags::PRIVATE;
+                ((flags & hidden).bits() == 0).then_some(MethodId {
+                    class: interface,
+                    method: index as u32,
+                })
+            })
+            .collect();
+        let maximal: Vec<MethodId> = candidates
+            .iter()
+            .copied()
+            .filter(|candidate| {
+                !candidates.iter().any(|other| {
+                    self.classes[other.class as usize]
+                        .linked
+                        .get()
+                        .is_some_and(|linked| linked.interfaces.contains(&candidate.class))
+                })
+            })
+            .collect();
+
+        let mut defaults = maximal.iter().copied().filter(|&method| {
+            self.method_info(method).flags() & MethodFlags::ABSTRACT != MethodFlags::ABSTRACT
+        });
+        match (defaults.next(), defaults.next()) {
+            (Some(method), None) => Some(method),
+            (Some(_), Some(_)) => None,
+            (None, _) => maximal.first().copied(),
+        }
+    }
+
+    /// Adds the methods which the class inherits from its interfaces to its
+    /// virtual method table, so that `invokevirtual` can select default
+    /// methods. A default method selected for a superclass is replaced if a
+    /// more specific one is selected for the class.
+    fn add_default_methods(
+        &self,
+        vtable: &mut Vec<MethodId>,
+        interfaces: &[u32],
+        itables: &HashMap<u32, Box<[Option<MethodId>]>>,
+    ) {
+        let selections = interfaces.iter().flat_map(|interface| &itables[interface]);
+        for &selected in selections.flatten() {
+            if !self.is_interface(selected.class as usize) {
+                continue;
+            }
+            let method = self.method_info(selected);
+            let constants = self.classes[selected.class as usize].class.constants();
+            let name = method.name(constants);
+            let descriptor = method.descriptor(constants);
+            match vtable
+                .iter_mut()
+                .find(|entry| self.same_signature(**entry, name, descriptor))
+            {
+                Some(entry) if self.is_interface(entry.class as usize) => *entry = selected,
+                Some(_) => (),
+                None => vtable.push(selected),
+            }
+        }
+    }
+
+    fn same_signature(&self, method: MethodId, name: &JavaStr, descriptor: &JavaStr) -> bool {
+        let constants = self.classes[method.class as usize].class.constants();
+        let method = self.method_info(method);
+        method.name(constants) == name && method.descriptor(constants) == descriptor
+    }
+}
+
+/// Returns the package of a class from its internal binary name, such as
+/// `java/lang` for `java/lang/Object`.
+fn package(name: &JavaStr) -> &[u8] {
+    let bytes = name.as_bytes();
+    let end = bytes.iter().rposition(|&byte| byte == b'/').unwrap_or(0);
+    &bytes[..end]
+}
@@ -1,284 +1,2335 @@
+pub mod analysis;
 pub mod call_frame;
 pub mod class;
+pub mod convert;
+pub mod decode;
+mod gc;
+pub mod heap;
+pub mod host;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+#[cfg(feature = "jni")]
+pub mod jni;
+mod link;
+mod monitor;
+pub mod native;
+mod scheduler;
 pub mod value;
+mod verify;
 
-use call_frame::CallFrame;
-use class::{parse, Class, Entry, Instruction, ParseError};
+use std::cell::{Cell, OnceCell};
+use std::collections::HashMap;
+
+use call_frame::{CallFrame, Stack, ThreadStack};
+use class::{
+    parse, ArrayKind, Class, ClassBuilder, ClassFlags, ConstantIdx, Entry, Field, FieldFlags,
+    FieldType, Method, MethodDescriptor, MethodFlags, ParseError,
+};
+use convert::{ConvertError, FromJavaResult, JavaHeap, ToJavaArgs};
+use decode::{DecodedCode, FieldId, InlineCache, InterfaceCall, MethodId, Op, VirtualCall};
+use heap::{AllocError, Collector, Heap, ObjectRef, ReferenceClasses, ReferenceType};
+use host::{HostClass, HostMethod};
+use link::Linked;
+use monitor::{FrameMonitor, LockTarget, LockWord};
+use native::NativeMethod;
+use scheduler::{JavaThread, Request, Scheduler};
+use value::{Value, ValueType};
 
 use crate::java_str;
-use crate::string::JavaStr;
+use crate::string::{JavaStr, JavaString};
 
 #[derive(Debug, Default)]
 pub struct ClassManager {
-    classes: Vec<Class>,
+    classes: Vec<LoadedClass>,
+    /// The index of each class in `classes` by name.
+    names: HashMap<JavaString, usize>,
+    #[cfg(feature = "jni")]
+    natives: jni::Natives,
+}
+
+/// An error raised while linking a class or resolving and invoking a member,
+/// named after the Java error it corresponds to. Since exceptions are not
+/// supported yet, these abort execution instead of being thrown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkageError {
+    NoClassDefFound(JavaString),
+    NoSuchField {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+    },
+    NoSuchMethod {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+    },
+    /// A member reference resolved to a member of the wrong kind, such as an
+    /// `invokestatic` of a field, or no single method could be selected for an
+    /// interface call.
+    IncompatibleClassChange,
+    /// A `new` of an interface or abstract class.
+    Instantiation(JavaString),
+    /// A method which overrides a final method, reported as a `VerifyError`.
+    OverridesFinal {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+    },
+    /// A call which selected an abstract method.
+    AbstractMethod {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+    },
+    /// A call to a native method with no host or JNI implementation.
+    UnsatisfiedLink {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+    },
+    /// An instruction of a method which failed verification, at an offset
+    /// in its code.
+    Verify {
+        class: JavaString,
+        name: JavaString,
+        descriptor: JavaString,
+        offset: u32,
+        message: &'static str,
+    },
+}
+
+impl std::fmt::Display for LinkageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoClassDefFound(class) => write!(f, "java.lang.NoClassDefFoundError: {class}"),
+            Self::NoSuchField { class, name, .. } => {
+                write!(f, "java.lang.NoSuchFieldError: {class}.{name}")
+            }
+            Self::NoSuchMethod {
+                class,
+                name,
+                descriptor,
+            } => write!(f, "java.lang.NoSuchMethodError: {class}.{name}{descriptor}"),
+            Self::IncompatibleClassChange => write!(f, "java.lang.IncompatibleClassChangeError"),
+            Self::Instantiation(class) => write!(f, "java.lang.InstantiationError: {class}"),
+            Self::OverridesFinal {
+                class,
+                name,
+                descriptor,
+            } => write!(
+                f,
+                "java.lang.VerifyError: class {class} overrides final method {name}{descriptor}"
+            ),
+            Self::AbstractMethod {
+                class,
+                name,
+                descriptor,
+            } => write!(
+                f,
+                "java.lang.AbstractMethodError: {class}.{name}{descriptor}"
+            ),
+            Self::UnsatisfiedLink {
+                class,
+                name,
+                descriptor,
+            } => write!(
+                f,
+                "java.lang.UnsatisfiedLinkError: {class}.{name}{descriptor}"
+            ),
+            Self::Verify {
+                class,
+                name,
+                descriptor,
+                offset,
+                message,
+            } => write!(
+                f,
+                "java.lang.VerifyError: {message} in {class}.{name}{descriptor} at offset {offset}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkageError {}
+
+/// An error which aborts the execution of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecuteError {
+    Linkage(LinkageError),
+    /// An object did not fit in the heap even after it was collected.
+    OutOfMemory,
+    /// Every thread waits for another one, with no deadline.
+    Deadlock,
+    /// The program threw an exception, which ends it as exceptions cannot be
+    /// caught.
+    Exception(JavaException),
+    /// The arguments the host passed to a method do not match its descriptor.
+    IllegalArgument {
+        descriptor: JavaString,
+    },
+    /// The result of a method called by the host does not fit the Rust type
+    /// it asked for.
+    ResultMismatch {
+        descriptor: JavaString,
+    },
+    /// A method of a host class returned a value of another type than its
+    /// descriptor.
+    HostResultMismatch {
+        descriptor: JavaString,
+    },
+    /// A method of `sun.misc.Unsafe` accessed an offset at which the object
+    /// has no field of the type it accesses.
+    UnsafeAccess {
+        offset: i64,
+        value_type: ValueType,
+    },
+    /// The program used a feature the virtual machine does not implement,
+    /// such as an instruction or a `String` constant.
+    Unsupported(String),
+}
+
+impl From<LinkageError> for ExecuteError {
+    fn from(error: LinkageError) -> Self {
+        Self::Linkage(error)
+    }
+}
+
+impl From<JavaException> for ExecuteError {
+    fn from(exception: JavaException) -> Self {
+        Self::Exception(exception)
+    }
+}
+
+impl std::fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linkage(error) => error.fmt(f),
+            Self::OutOfMemory => write!(f, "java.lang.OutOfMemoryError: Java heap space"),
+            Self::Deadlock => write!(f, "deadlock: every thread is blocked"),
+            Self::Exception(exception) => exception.fmt(f),
+            Self::IllegalArgument { descriptor } => write!(
+                f,
+                "java.lang.IllegalArgumentException: arguments do not match {descriptor}"
+            ),
+            Self::ResultMismatch { descriptor } => {
+                write!(f, "the result of {descriptor} does not fit the host type")
+            }
+            Self::HostResultMismatch { descriptor } => {
+                write!(
+                    f,
+                    "the host returned a result which does not fit {descriptor}"
+                )
+            }
+            Self::UnsafeAccess { offset, value_type } => write!(
+                f,
+                "sun.misc.Unsafe accessed no {value_type:?} field at offset {offset}"
+            ),
+            Self::Unsupported(feature) => write!(f, "no support for {feature}"),
+        }
+    }
+}
+
+impl std::error::Error for ExecuteError {}
+
+impl ExecuteError {
+    /// Returns the error for loading a `String` constant, as there are no
+    /// strings in the heap.
+    fn string_constant() -> Self {
+        Self::Unsupported("String constants".to_owned())
+    }
+}
+
+/// An exception the virtual machine throws.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JavaException {
+    /// A thread was interrupted while it slept, joined another one or waited,
+    /// or before it did.
+    Interrupted,
+    /// A thread was started twice.
+    IllegalThreadState,
+    /// A thread was asked to sleep, join or wait for a negative time.
+    NegativeTimeout,
+    /// A thread exited, waited on or notified a monitor it does not own.
+    IllegalMonitorState,
+    /// `sun.misc.Unsafe` was asked for the object offset of a static field.
+    StaticFieldOffset,
+    /// A field or method was accessed through a null reference.
+    NullPointer,
+    /// An integer was divided by zero.
+    DivideByZero,
+    /// A `checkcast` of an object which is not an instance of the class.
+    ClassCast,
+    /// The frames of a thread did not fit in its stack.
+    StackOverflow,
+    /// An array was accessed at an index outside its bounds.
+    ArrayIndexOutOfBounds { index: i32, length: usize },
+    /// An array was created with a negative length.
+    NegativeArraySize(i32),
+    /// A reference was stored to an array of a class it is not an instance
+    /// of.
+    ArrayStore,
+    /// An exception a native library threw, with the name of its class and
+    /// its message, if any.
+    #[cfg(feature = "jni")]
+    Native {
+        class: JavaString,
+        message: Option<String>,
+    },
+}
+
+impl std::fmt::Display for JavaException {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interrupted => write!(f, "java.lang.InterruptedException"),
+            Self::IllegalThreadState => write!(f, "java.lang.IllegalThreadStateException"),
+            Self::NegativeTimeout => write!(
+                f,
+                "java.lang.IllegalArgumentException: timeout value is negative"
+            ),
+            Self::IllegalMonitorState => write!(
+                f,
+                "java.lang.IllegalMonitorStateException: current thread is not owner"
+            ),
+            Self::StaticFieldOffset => {
+                write!(f, "java.lang.IllegalArgumentException: the field is static")
+            }
+            Self::NullPointer => write!(f, "java.lang.NullPointerException"),
+            Self::DivideByZero => write!(f, "java.lang.ArithmeticException: / by zero"),
+            Self::ClassCast => write!(f, "java.lang.ClassCastException"),
+            Self::StackOverflow => write!(f, "java.lang.StackOverflowError"),
+            Self::ArrayIndexOutOfBounds { index, length } => write!(
+                f,
+                "java.lang.ArrayIndexOutOfBoundsException: Index {index} out of bounds for length {length}"
+            ),
+            Self::NegativeArraySize(length) => {
+                write!(f, "java.lang.NegativeArraySizeException: {length}")
+            }
+            Self::ArrayStore => write!(f, "java.lang.ArrayStoreException"),
+            #[cfg(feature = "jni")]
+            Self::Native { class, message } => {
+                let class = class.to_string().replace('/', ".");
+                match message {
+                    Some(message) => write!(f, "{class}: {message}"),
+                    None => f.write_str(&class),
+                }
+            }
+        }
+    }
+}
+
+/// A class along with the decoded code of its methods and its runtime state.
+#[derive(Debug)]
+struct LoadedClass {
+    class: Class,
+    /// The code of each method, in the same order as [`Class::methods`].
+    code: Box<[Option<DecodedCode>]>,
+    /// The value of each static field, in the same order as [`Class::fields`].
+    /// Instance fields and `String` constants have no value.
+    statics: Box<[Cell<Option<Value>>]>,
+    /// What each class, method and field reference in the constant pool
+    /// resolved to, indexed by the raw value of its [`ConstantIdx`].
+    resolved: Box<[Cell<Option<Resolved>>]>,
+    linked: OnceCell<Linked>,
+    /// Whether the code of the methods of the class has been verified.
+    verified: Cell<bool>,
+    initialized: Cell<bool>,
+    /// The monitor of the class, which its `static synchronized` methods
+    /// enter.
+    lock: Cell<LockWord>,
+    /// The `java.lang.Class` object of the class, once an `ldc` pushed it.
+    mirror: Cell<Option<ObjectRef>>,
+    /// The type of the components of an array class, whose elements are the
+    /// fields of its objects. Other classes have none.
+    component: Option<FieldType>,
+    /// The closure of each method of a class defined by the host, in the
+    /// same order as [`Class::methods`], which other classes have none of.
+    host: Box<[HostMethod]>,
+}
+
+/// A reference which has been resolved.
+#[derive(Debug, Clone, Copy)]
+enum Resolved {
+    Class(u32),
+    Method(MethodId),
+    Field(FieldId),
+}
+
+impl LoadedClass {
+    fn new(class: Class) -> Result<Self, ParseError> {
+        let code = class
+            .methods()
+            .iter()
+            .map(|method| {
+                let synchronized =
+                    method.flags() & MethodFlags::SYNCHRONIZED == MethodFlags::SYNCHRONIZED;
+                let code = method.bytecode().map(DecodedCode::new).transpose();
+                code.map(|code| {
+                    code.map(|mut code| {
+                        code.synchronized = synchronized;
+                        code
+                    })
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let statics = class
+            .fields()
+            .iter()
+            .map(|field| Cell::new(Self::initial_value(&class, field)))
+            .collect();
+        let resolved = vec![Cell::new(None); class.constants().len() + 1].into_boxed_slice();
+        Ok(Self {
+            class,
+            code,
+            statics,
+            resolved,
+            linked: OnceCell::new(),
+            verified: Cell::new(false),
+            initialized: Cell::new(false),
+            lock: Cell::new(LockWord::UNLOCKED),
+            mirror: Cell::new(None),
+            component: None,
+            host: Box::default(),
+        })
+    }
+
+    /// Returns the value a static field holds before the class is initialized,
+    /// which is its `ConstantValue` if it has one and its default value
+    /// otherwise.
+    fn initial_value(class: &Class, field: &Field) -> Option<Value> {
+        if field.flags() & FieldFlags::STATIC != FieldFlags::STATIC {
+            return None;
+        }
+        if let Some(idx) = field.constant_value() {
+            return match class.constants().get(idx) {
+                Entry::Integer(val) => Some(Value::Int(*val)),
+                Entry::Long(val) => Some(Value::Long(*val)),
+                Entry::Float(val) => Some(Value::Float(*val)),
+                Entry::Double(val) => Some(Value::Double(*val)),
+                _ => None,
+            };
+        }
+        Some(default_value(field.parsed_descriptor()))
+    }
+}
+
+/// Returns the value of a field of type `field_type` which has not been
+/// assigned to.
+fn default_value(field_type: &FieldType) -> Value {
+    match field_type {
+        FieldType::Long => Value::Long(0),
+        FieldType::Float => Value::Float(0.0),
+        FieldType::Double => Value::Double(0.0),
+        FieldType::Class(_) | FieldType::Array(_) => Value::Reference(None),
+        _ => Value::Int(0),
+    }
 }
 
 impl ClassManager {
     pub fn new() -> Self {
         Self {
             classes: Vec::new(),
+            names: HashMap::new(),
+            #[cfg(feature = "jni")]
+            natives: jni::Natives::default(),
         }
     }
 
+    /// Parses a class and decodes the code of its methods. If a class with
+    /// the same name is already loaded, lookups keep finding the first one.
     pub fn load(&mut self, slice: &[u8]) -> Result<(), ParseError> {
-        self.classes.push(parse(slice)?);
+        let class = parse(slice)?;
+        let index = self.classes.len();
+        self.names.entry(class.name().to_owned()).or_insert(index);
+        self.classes.push(LoadedClass::new(class)?);
+        self.add_array_classes(index);
         Ok(())
     }
 
+    /// Defines a class whose methods are implemented by the host. If a class
+    /// with the same name is already loaded, lookups keep finding the first
+    /// one.
+    pub fn define(&mut self, class: HostClass) {
+        let (class, methods) = class.build();
+        let index = self.classes.len();
+        self.names.entry(class.name().to_owned()).or_insert(index);
+        let mut loaded = LoadedClass::new(class).expect("host class has no code");
+        loaded.host = methods.into_boxed_slice();
+        self.classes.push(loaded);
+        self.add_array_classes(index);
+    }
+
+    /// Adds the array classes the class at `class` may create or name, which
+    /// are the arrays of primitive types, the arrays of the class itself, the
+    /// arrays its constant pool and descriptors name, the arrays of the
+    /// classes it names, and the components of all of them. Classes are not
+    /// added once the program runs, so they are added with the classes which
+    /// need them.
+    fn add_array_classes(&mut self, class: usize) {
+        let loaded = &self.classes[class].class;
+        let mut arrays = vec![
+            FieldType::Bool,
+            FieldType::Char,
+            FieldType::Float,
+            FieldType::Double,
+            FieldType::Byte,
+            FieldType::Short,
+            FieldType::Int,
+            FieldType::Long,
+            FieldType::class(loaded.name()),
+        ];
+        let constants = loaded.constants();
+        for (_, entry) in constants.iter() {
+            if let Entry::Class(name) = entry {
+                let name = constants.get(*name).into_utf8();
+                if name.as_bytes().first() == Some(&b'[') {
+                    arrays.extend(FieldType::parse(name));
+                } else {
+                    arrays.push(FieldType::class(name));
+                }
+            }
+        }
+        arrays = arrays.into_iter().map(FieldType::array).collect();
+        arrays.extend(
+            loaded
+                .fields()
+                .iter()
+                .map(|field| field.parsed_descriptor().clone()),
+        );
+        for method in loaded.methods() {
+            let descriptor = method.parsed_descriptor();
+            arrays.extend(descriptor.args().iter().chain(descriptor.result()).cloned());
+        }
+        for array in arrays {
+            self.add_array_class(array);
+        }
+    }
+
+    /// Adds the class of `array`, if it is an array type, and the classes of
+    /// its components unless they already are.
+    fn add_array_class(&mut self, array: FieldType) {
+        let FieldType::Array(component) = &array else {
+            return;
+        };
+        let name = crate::string::from_utf8(&array.to_string()).into_owned();
+        if self.names.contains_key(&name) {
+            return;
+        }
+        self.add_array_class((**component).clone());
+        let mut builder = ClassBuilder::new(&name);
+        builder.flags(ClassFlags::PUBLIC | ClassFlags::FINAL | ClassFlags::ABSTRACT);
+        let mut loaded = LoadedClass::new(builder.build()).expect("array class has no code");
+        loaded.component = Some((**component).clone());
+        self.names.insert(name, self.classes.len());
+        self.classes.push(loaded);
+    }
+
     pub fn get<'a>(&'a self, name: &JavaStr) -> Option<&'a Class> {
-        self.classes.iter().find(|class| class.name() == name)
+        self.position(name).map(|index| &self.classes[index].class)
+    }
+
+    fn position(&self, name: &JavaStr) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns the index of the class with the given name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::NoClassDefFound`] if it is not loaded.
+    fn find(&self, name: &JavaStr) -> Result<usize, LinkageError> {
+        self.position(name)
+            .ok_or_else(|| LinkageError::NoClassDefFound(name.to_owned()))
+    }
+
+    fn is_interface(&self, class: usize) -> bool {
+        self.classes[class].class.flags() & ClassFlags::INTERFACE == ClassFlags::INTERFACE
+    }
+
+    /// Returns whether the class at `class` is `target`, or a subclass or
+    /// implementation of it. Arrays are subclasses of `java.lang.Object`,
+    /// `java.lang.Cloneable` and `java.io.Serializable`, and of the arrays
+    /// of the classes their components are subclasses of.
+    fn is_subclass(&self, class: usize, target: usize) -> bool {
+        if let Some(component) = &self.classes[class].component {
+            let is_reference = |component: &FieldType| {
+                matches!(component, FieldType::Class(_) | FieldType::Array(_))
+            };
+            return match &self.classes[target].component {
+                Some(target_component)
+                    if is_reference(component) && is_reference(target_component) =>
+                {
+                    let classes = self
+                        .component_class(class)
+                        .zip(self.component_class(target));
+                    classes.is_some_and(|(class, target)| self.is_subclass(class, target))
+                }
+                Some(target_component) => component == target_component,
+                None => matches!(
+                    self.classes[target].class.name().as_bytes(),
+                    b"java/lang/Object" | b"java/lang/Cloneable" | b"java/io/Serializable"
+                ),
+            };
+        }
+        if self.is_interface(target) {
+            return class == target
+                || (self.link(class))
+                    .is_ok_and(|linked| linked.interfaces.contains(&(target as u32)));
+        }
+        let mut next = Some(class);
+        while let Some(index) = next {
+            if index == target {
+                return true;
+            }
+            let super_name = self.classes[index].class.super_name();
+            next = super_name.and_then(|name| self.position(name));
+        }
+        false
+    }
+
+    /// Returns the index of the class of the components of the array class
+    /// at `array`, if they are references to a class which is loaded.
+    fn component_class(&self, array: usize) -> Option<usize> {
+        match self.classes[array].component.as_ref()? {
+            FieldType::Class(name) => self.position(name),
+            component @ FieldType::Array(_) => {
+                self.position(&crate::string::from_utf8(&component.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the class of `array`, which must be an array
+    /// type.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::NoClassDefFound`] if no class loaded names
+    /// it.
+    fn array_class(&self, array: &FieldType) -> Result<usize, LinkageError> {
+        self.find(&crate::string::from_utf8(&array.to_string()))
+    }
+
+    fn is_final(&self, class: usize) -> bool {
+        self.classes[class].class.flags() & ClassFlags::FINAL == ClassFlags::FINAL
     }
+
+    fn method_info(&self, method: MethodId) -> &Method {
+        &self.classes[method.class as usize].class.methods()[method.method as usize]
+    }
+
+    /// Returns the class, name and descriptor of a method, as reported in
+    /// errors.
+    fn signature(&self, method: MethodId) -> (JavaString, JavaString, JavaString) {
+        let class = &self.classes[method.class as usize].class;
+        let info = self.method_info(method);
+        (
+            class.name().to_owned(),
+            info.name(class.constants()).to_owned(),
+            info.descriptor(class.constants()).to_owned(),
+        )
+    }
+
+    /// Returns the code of a method which is being invoked.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::AbstractMethod`] or
+    /// [`LinkageError::UnsatisfiedLink`] if the method has no code.
+    fn method_code(&self, method: MethodId) -> Result<&DecodedCode, LinkageError> {
+        match &self.classes[method.class as usize].code[method.method as usize] {
+            Some(code) => Ok(code),
+            None => Err(self.missing_code(method)),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn missing_code(&self, method: MethodId) -> LinkageError {
+        let (class, name, descriptor) = self.signature(method);
+        if self.method_info(method).flags() & MethodFlags::ABSTRACT == MethodFlags::ABSTRACT {
+            LinkageError::AbstractMethod {
+                class,
+                name,
+                descriptor,
+            }
+        } else {
+            LinkageError::UnsatisfiedLink {
+                class,
+                name,
+                descriptor,
+            }
+        }
+    }
+
+    /// Creates the frame of an invoked method, whose locals start at the top
+    /// `slots` slots of the operand stack of the caller, which hold the
+    /// arguments.
+    fn invoke<'a>(
+        &'a self,
+        caller: &mut Stack,
+        method: MethodId,
+        slots: usize,
+    ) -> Result<CallFrame<'a>, LinkageError> {
+        let code = self.method_code(method)?;
+        Ok(CallFrame::new(method, code, caller.pop_arguments(slots)))
+    }
+
+    /// Returns the type of the values held by a field.
+    fn field_type(&self, field: FieldId) -> ValueType {
+        let class = &self.classes[field.class as usize].class;
+        ValueType::from(class.fields()[field.field as usize].parsed_descriptor())
+    }
+
+    /// Returns the linked class at `class`, which must have been linked when
+    /// it was first instantiated.
+    fn linked(&self, class: u32) -> &Linked {
+        self.classes[class as usize]
+            .linked
+            .get()
+            .expect("classes are linked before they are instantiated")
+    }
+
+    /// Allocates an object of the linked class at `class`, registering it for
+    /// finalization if the class has a finalizer.
+    fn instantiate(&self, heap: &mut Heap, class: u32) -> Result<ObjectRef, AllocError> {
+        let linked = self.linked(class);
+        let object = heap.alloc(class, linked.fields.clone())?;
+        if linked.finalizer.is_some() {
+            heap.register_finalizer(object);
+        }
+        Ok(object)
+    }
+
+    /// Allocates an array of the linked array class at `class` with `length`
+    /// elements of their default value.
+    fn new_array(
+        &self,
+        heap: &mut Heap,
+        class: u32,
+        length: usize,
+    ) -> Result<ObjectRef, AllocError> {
+        let component =
+            (self.classes[class as usize].component.as_ref()).expect("class is an array class");
+        heap.alloc_array(class, length, default_value(component))
+    }
+
+    /// Returns the `java.lang.Class` object of the class at `class`,
+    /// allocating it the first time. `java.lang.Class` must have been linked.
+    fn mirror(&self, heap: &mut Heap, class: u32) -> Result<ObjectRef, AllocError> {
+        let loaded = &self.classes[class as usize];
+        if let Some(mirror) = loaded.mirror.get() {
+            return Ok(mirror);
+        }
+        let class_class = self.position(java_str!("java/lang/Class")).unwrap();
+        let mirror = self.instantiate(heap, class_class as u32)?;
+        loaded.mirror.set(Some(mirror));
+        Ok(mirror)
+    }
+
+    /// Returns the index of the class whose `java.lang.Class` object is
+    /// `mirror`, if it is one.
+    fn mirrored(&self, mirror: ObjectRef) -> Option<usize> {
+        self.classes
+            .iter()
+            .position(|class| class.mirror.get() == Some(mirror))
+    }
+
+    /// Resolves the class, method or field reference at `idx` in the constant
+    /// pool of the class at `class`. Resolutions are cached per class, so
+    /// that each reference is only looked up by name once.
+    fn resolve(&self, class: usize, idx: ConstantIdx) -> Result<Resolved, LinkageError> {
+        let cache = &self.classes[class].resolved[idx.get() as usize];
+        if let Some(resolved) = cache.get() {
+            return Ok(resolved);
+        }
+
+        let constants = self.classes[class].class.constants();
+        let entry = constants.get(idx);
+        if let Entry::Class(name) = entry {
+            let class = self.find(constants.get(*name).into_utf8())?;
+            let resolved = Resolved::Class(class as u32);
+            cache.set(Some(resolved));
+            return Ok(resolved);
+        }
+
+        let (owner, name_type) = entry.into_ref();
+        let owner = constants.get(constants.get(owner).into_class()).into_utf8();
+        let (name, descriptor) = constants.get(name_type).into_name_type();
+        let name = constants.get(name).into_utf8();
+        let descriptor = constants.get(descriptor).into_utf8();
+
+        let owner_index = self.find(owner)?;
+        let resolved = match entry {
+            Entry::FieldRef(..) => match self.resolve_field(owner_index, name, descriptor)? {
+                Some(field) => Resolved::Field(field),
+                None => {
+                    return Err(LinkageError::NoSuchField {
+                        class: owner.to_owned(),
+                        name: name.to_owned(),
+                        descriptor: descriptor.to_owned(),
+                    })
+                }
+            },
+            _ => {
+                let is_interface = matches!(entry, Entry::InterfaceMethodRef(..));
+                if self.is_interface(owner_index) != is_interface {
+                    return Err(LinkageError::IncompatibleClassChange);
+                }
+                let method = if is_interface {
+                    self.resolve_interface_method(owner_index, name, descriptor)?
+                } else {
+                    self.resolve_method(owner_index, name, descriptor)?
+                };
+                match method {
+                    Some(method) => Resolved::Method(method),
+                    None => {
+                        return Err(LinkageError::NoSuchMethod {
+                            class: owner.to_owned(),
+                            name: name.to_owned(),
+                            descriptor: descriptor.to_owned(),
+                        })
+                    }
+                }
+            }
+        };
+        cache.set(Some(resolved));
+        Ok(resolved)
+    }
+
+    /// Looks up a field in a class, then in its superinterfaces and then in
+    /// its superclasses.
+    fn resolve_field(
+        &self,
+        class: usize,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Result<Option<FieldId>, LinkageError> {
+        let loaded = &self.classes[class].class;
+        if let Some(field) = loaded.field_index(name, descriptor) {
+            return Ok(Some(FieldId {
+                class: class as u32,
+                field: field as u32,
+            }));
+        }
+        for interface in loaded.interfaces() {
+            if let Some(field) = self.resolve_field(self.find(interface)?, name, descriptor)? {
+                return Ok(Some(field));
+            }
+        }
+        match loaded.super_name() {
+            Some(super_name) => self.resolve_field(self.find(super_name)?, name, descriptor),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the index among the fields of the objects of the class at
+    /// `class` of the instance field with the given name and descriptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::NoSuchField`] if the class has no such instance
+    /// field.
+    fn field_slot(
+        &self,
+        class: usize,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Result<usize, LinkageError> {
+        let no_such_field = || LinkageError::NoSuchField {
+            class: self.classes[class].class.name().to_owned(),
+            name: name.to_owned(),
+            descriptor: descriptor.to_owned(),
+        };
+        let field = self
+            .resolve_field(class, name, descriptor)?
+            .ok_or_else(no_such_field)?;
+        let slot = self.link(field.class as usize)?.field_slots[field.field as usize];
+        slot.map(|slot| slot as usize).ok_or_else(no_such_field)
+    }
+
+    /// Looks up a method in a class and its superclasses, and then among the
+    /// methods of its superinterfaces.
+    fn resolve_method(
+        &self,
+        class: usize,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Result<Option<MethodId>, LinkageError> {
+        let mut next = Some(class);
+        while let Some(index) = next {
+            let loaded = &self.classes[index].class;
+            if let Some(method) = loaded.method_index(name, descriptor) {
+                return Ok(Some(MethodId {
+                    class: index as u32,
+                    method: method as u32,
+                }));
+            }
+            next = loaded
+                .super_name()
+                .map(|name| self.find(name))
+                .transpose()?;
+        }
+        let interfaces = &self.link(class)?.interfaces;
+        Ok(self.maximally_specific(interfaces, name, descriptor))
+    }
+
+    /// Looks up the first method with the given name in a class and then in
+    /// its superclasses which is static or not as requested and whose
+    /// descriptor `accepts` allows.
+    fn resolve_method_by(
+        &self,
+        class: usize,
+        name: &JavaStr,
+        is_static: bool,
+        accepts: impl Fn(&MethodDescriptor) -> bool,
+    ) -> Result<Option<MethodId>, LinkageError> {
+        let mut next = Some(class);
+        while let Some(index) = next {
+            let loaded = &self.classes[index].class;
+            let constants = loaded.constants();
+            let found = loaded.methods().iter().position(|method| {
+                let flags = method.flags();
+                method.name(constants) == name
+                    && (flags & MethodFlags::STATIC == MethodFlags::STATIC) == is_static
+                    && accepts(method.parsed_descriptor())
+            });
+            if let Some(method) = found {
+                return Ok(Some(MethodId {
+                    class: index as u32,
+                    method: method as u32,
+                }));
+            }
+            next = loaded
+                .super_name()
+                .map(|name| self.find(name))
+                .transpose()?;
+        }
+        Ok(None)
+    }
+
+    /// Looks up a method in an interface, then among the public methods of
+    /// `java/lang/Object` and then among the methods of its superinterfaces.
+    fn resolve_interface_method(
+        &self,
+        interface: usize,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+    ) -> Result<Option<MethodId>, LinkageError> {
+        if let Some(method) = self.classes[interface].class.method_index(name, descriptor) {
+            return Ok(Some(MethodId {
+                class: interface as u32,
+                method: method as u32,
+            }));
+        }
+
+        let object = self.find(java_str!("java/lang/Object"))?;
+        if let Some(method) = self.classes[object].class.method_index(name, descriptor) {
+            let method = MethodId {
+                class: object as u32,
+                method: method as u32,
+            };
+            let flags = self.method_info(method).flags();
+            if flags & MethodFlags::PUBLIC == MethodFlags::PUBLIC
+                && flags & MethodFlags::STATIC != MethodFlags::STATIC
+            {
+                return Ok(Some(method));
+            }
+        }
+
+        let interfaces = &self.link(interface)?.interfaces;
+        Ok(self.maximally_specific(interfaces, name, descriptor))
+    }
+
+    /// Returns the quickened form of `op`, the instruction at `index` in
+    /// `code` of the class at `class`, along with the class which must be
+    /// initialized before it executes, if any.
+    ///
+    /// This is kept out of line, as it only runs the first time an instruction
+    /// executes.
+    #[inline(never)]
+    fn quicken(
+        &self,
+        class: usize,
+        code: &DecodedCode,
+        index: usize,
+        op: Op,
+    ) -> Result<(Op, Option<u32>), ExecuteError> {
+        let constants = self.classes[class].class.constants();
+        let is_static = |flags: MethodFlags| flags & MethodFlags::STATIC == MethodFlags::STATIC;
+        let quick = match op {
+            Op::ldc(idx) => match constants.get(idx) {
+                Entry::Integer(val) => (Op::iconst(*val), None),
+                Entry::Long(val) => (Op::lconst(*val), None),
+                Entry::Float(val) => (Op::fconst(*val), None),
+                Entry::Double(val) => (Op::dconst(*val), None),
+                Entry::Class(_) => {
+                    let Resolved::Class(target) = self.resolve(class, idx)? else {
+                        return Err(LinkageError::IncompatibleClassChange.into());
+                    };
+                    self.link(self.find(java_str!("java/lang/Class"))?)?;
+                    (Op::ldc_class_quick(target), None)
+                }
+                Entry::String(_) => return Err(ExecuteError::string_constant()),
+                entry => return Err(ExecuteError::Unsupported(format!("the constant {entry:?}"))),
+            },
+            Op::getstatic(idx) | Op::putstatic(idx) | Op::getfield(idx) | Op::putfield(idx) => {
+                let Resolved::Field(field) = self.resolve(class, idx)? else {
+                    return Err(LinkageError::IncompatibleClassChange.into());
+                };
+                let owner = &self.classes[field.class as usize].class;
+                let flags = owner.fields()[field.field as usize].flags();
+                let is_static = flags & FieldFlags::STATIC == FieldFlags::STATIC;
+                match op {
+                    Op::getstatic(_) | Op::putstatic(_) if !is_static => {
+                        return Err(LinkageError::IncompatibleClassChange.into())
+                    }
+                    Op::getstatic(_) => (Op::getstatic_quick(field), Some(field.class)),
+                    Op::putstatic(_) => {
+                        let value_type = self.field_type(field);
+                        (Op::putstatic_quick(field, value_type), Some(field.class))
+                    }
+                    _ if is_static => return Err(LinkageError::IncompatibleClassChange.into()),
+                    _ => {
+                        let linked = self.link(field.class as usize)?;
+                        let slot = linked.field_slots[field.field as usize].unwrap();
+                        match op {
+                            Op::getfield(_) => (Op::getfield_quick(slot), None),
+                            _ => (Op::putfield_quick(slot, self.field_type(field)), None),
+                        }
+                    }
+                }
+            }
+            Op::invokevirtual(idx)
+            | Op::invokespecial(idx)
+            | Op::invokestatic(idx)
+            | Op::invokeinterface(idx, _) => {
+                let Resolved::Method(method) = self.resolve(class, idx)? else {
+                    return Err(LinkageError::IncompatibleClassChange.into());
+                };
+                let info = self.method_info(method);
+                let flags = info.flags();
+                if is_static(flags) != matches!(op, Op::invokestatic(_)) {
+                    return Err(LinkageError::IncompatibleClassChange.into());
+                }
+                let slots = info.parsed_descriptor().arg_slots(false) as u16;
+                match op {
+                    Op::invokestatic(_) => {
+                        let quick = self.select_native(method, Op::invokestatic_quick(method));
+                        (quick, Some(method.class))
+                    }
+                    Op::invokespecial(_) => {
+                        let selected = self.select_special(class, method)?;
+                        let quick = self.select_native(selected, Op::invokespecial_quick(selected));
+                        (quick, None)
+                    }
+                    _ if (flags & (MethodFlags::PRIVATE | MethodFlags::FINAL)).bits() != 0
+                        || self.is_final(method.class as usize) =>
+                    {
+                        let quick = self.select_native(method, Op::invokespecial_quick(method));
+                        (quick, None)
+                    }
+                    Op::invokeinterface(..) if self.is_interface(method.class as usize) => {
+                        let call = InterfaceCall {
+                            interface: method.class,
+                            method: method.method as u16,
+                            slots,
+                            cache: code.call_site(index),
+                        };
+                        (Op::invokeinterface_quick(call), None)
+                    }
+                    _ => {
+                        let call = VirtualCall {
+                            index: self.vtable_index(class, idx, method)?,
+                            slots,
+                            cache: code.call_site(index),
+                        };
+                        (Op::invokevirtual_quick(call), None)
+                    }
+                }
+            }
+            Op::new(idx) => {
+                let Resolved::Class(new_class) = self.resolve(class, idx)? else {
+                    return Err(LinkageError::IncompatibleClassChange.into());
+                };
+                let loaded = &self.classes[new_class as usize].class;
+                let abstract_flags = ClassFlags::INTERFACE | ClassFlags::ABSTRACT;
+                if (loaded.flags() & abstract_flags).bits() != 0 {
+                    let name = loaded.name().to_owned();
+                    return Err(LinkageError::Instantiation(name).into());
+                }
+                self.link(new_class as usize)?;
+                (Op::new_quick(new_class), Some(new_class))
+            }
+            Op::newarray(_) | Op::anewarray(_) => {
+                let component = match op {
+                    Op::newarray(kind) => match kind {
+                        ArrayKind::Bool => FieldType::Bool,
+                        ArrayKind::Char => FieldType::Char,
+                        ArrayKind::Float => FieldType::Float,
+                        ArrayKind::Double => FieldType::Double,
+                        ArrayKind::Byte => FieldType::Byte,
+                        ArrayKind::Short => FieldType::Short,
+                        ArrayKind::Int => FieldType::Int,
+                        ArrayKind::Long => FieldType::Long,
+                    },
+                    Op::anewarray(idx) => {
+                        let Resolved::Class(component) = self.resolve(class, idx)? else {
+                            return Err(LinkageError::IncompatibleClassChange.into());
+                        };
+                        let component = &self.classes[component as usize];
+                        match &component.component {
+                            Some(_) => FieldType::parse(component.class.name())
+                                .expect("array classes are named by their descriptor"),
+                            None => FieldType::class(component.class.name()),
+                        }
+                    }
+                    _ => unreachable!(),
+                };
+                let array = self.array_class(&FieldType::array(component))?;
+                self.link(array)?;
+                (Op::newarray_quick(array as u32), None)
+            }
+            Op::checkcast(idx) | Op::instanceof(idx) => {
+                let Resolved::Class(target) = self.resolve(class, idx)? else {
+                    return Err(LinkageError::IncompatibleClassChange.into());
+                };
+                match op {
+                    Op::checkcast(_) => (Op::checkcast_quick(target), None),
+                    _ => (Op::instanceof_quick(target), None),
+                }
+            }
+            _ => unreachable!("{op:?} cannot be quickened"),
+        };
+        Ok(quick)
+    }
+
+    /// Returns an `invokenative` for a call which always invokes `method` if
+    /// it is a native method the virtual machine, the host or a native
+    /// library implements, or `quick` otherwise.
+    fn select_native(&self, method: MethodId, quick: Op) -> Op {
+        if self.method_info(method).flags() & MethodFlags::NATIVE != MethodFlags::NATIVE {
+            return quick;
+        }
+        if !self.classes[method.class as usize].host.is_empty() {
+            return Op::invokenative(NativeMethod::Host(method));
+        }
+        let (class, name, descriptor) = self.signature(method);
+        let native = NativeMethod::find(&class, &name, &descriptor);
+        #[cfg(feature = "jni")]
+        let native = native.or(Some(NativeMethod::Jni(method)));
+        native.map_or(quick, Op::invokenative)
+    }
+
+    /// Runs a method of a class defined by the host, whose receiver and
+    /// arguments are on top of `stack`.
+    fn invoke_host(
+        &self,
+        method: MethodId,
+        stack: &mut Stack,
+        heap: &mut Heap,
+    ) -> Result<(), ExecuteError> {
+        let info = self.method_info(method);
+        let is_static = info.flags() & MethodFlags::STATIC == MethodFlags::STATIC;
+        let host = &self.classes[method.class as usize].host[method.method as usize];
+        host.invoke(info.parsed_descriptor(), is_static, stack, self, heap)
+    }
+
+    /// Selects the method invoked by an `invokespecial` in the class at
+    /// `class` which resolved to `method`. A call to a method of a superclass
+    /// other than a constructor selects the method that the superclass would,
+    /// as with `super.method()` in Java.
+    fn select_special(&self, class: usize, method: MethodId) -> Result<MethodId, LinkageError> {
+        let owner = method.class as usize;
+        let constants = self.classes[owner].class.constants();
+        let info = self.method_info(method);
+        let name = info.name(constants);
+        if name == java_str!("<init>") || self.is_interface(owner) || owner == class {
+            return Ok(method);
+        }
+
+        let super_class = match self.classes[class].class.super_name() {
+            Some(name) => self.find(name)?,
+            None => return Ok(method),
+        };
+        let mut next = Some(super_class);
+        while let Some(index) = next {
+            if index == owner {
+                let descriptor = info.descriptor(constants);
+                return Ok(self
+                    .resolve_method(super_class, name, descriptor)?
+                    .unwrap_or(method));
+            }
+            let super_name = self.classes[index].class.super_name();
+            next = super_name.map(|name| self.find(name)).transpose()?;
+        }
+        Ok(method)
+    }
+
+    /// Returns the index in the virtual method table of the method which an
+    /// `invokevirtual` of the reference at `idx` resolved to. A default method
+    /// has no index of its own, so it is looked up in the table of the class
+    /// the reference names.
+    fn vtable_index(
+        &self,
+        class: usize,
+        idx: ConstantIdx,
+        method: MethodId,
+    ) -> Result<u32, LinkageError> {
+        let owner = method.class as usize;
+        if !self.is_interface(owner) {
+            let index = self.link(owner)?.vtable_indices[method.method as usize];
+            return Ok(index.expect("virtual methods have a virtual method table index"));
+        }
+
+        let constants = self.classes[class].class.constants();
+        let (referenced, _) = constants.get(idx).into_ref();
+        let Resolved::Class(referenced) = self.resolve(class, referenced)? else {
+            unreachable!("classes resolve to classes");
+        };
+        let owner_constants = self.classes[owner].class.constants();
+        let info = self.method_info(method);
+        let name = info.name(owner_constants);
+        let descriptor = info.descriptor(owner_constants);
+        let vtable = &self.link(referenced as usize)?.vtable;
+        let index = vtable.iter().position(|&entry| {
+            let constants = self.classes[entry.class as usize].class.constants();
+            let entry = self.method_info(entry);
+            entry.name(constants) == name && entry.descriptor(constants) == descriptor
+        });
+        Ok(index.expect("inherited interface methods are in the virtual method table") as u32)
+    }
+
+    /// Marks the class at `class` and its loaded superclasses as initialized,
+    /// and pushes frames running their static initializers, such that the
+    /// initializer of a superclass runs before that of its subclasses.
+    fn initialize<'a>(
+        &'a self,
+        class: usize,
+        call_stack: &mut Vec<CallFrame<'a>>,
+    ) -> Result<(), LinkageError> {
+        const CLINIT_NAME: &JavaStr = java_str!("<clinit>");
+        const CLINIT_DESCRIPTOR: &JavaStr = java_str!("()V");
+
+        let mut next = Some(class);
+        while let Some(index) = next {
+            self.verify(index)?;
+            let loaded = &self.classes[index];
+            if loaded.initialized.replace(true) {
+                break;
+            }
+
+            if let Some(method) = loaded.class.method_index(CLINIT_NAME, CLINIT_DESCRIPTOR) {
+                let method = MethodId {
+                    class: index as u32,
+                    method: method as u32,
+                };
+                let base = call_stack.last().map_or(0, CallFrame::top);
+                call_stack.push(CallFrame::new(method, self.method_code(method)?, base));
+            }
+            next = loaded
+                .class
+                .super_name()
+                .and_then(|name| self.position(name));
+        }
+        Ok(())
+    }
+}
+
+impl ReferenceClasses for ClassManager {
+    fn reference_type(&self, class: u32) -> Option<ReferenceType> {
+        self.linked(class).reference
+    }
+}
+
+/// Options controlling how a program is executed.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecuteOptions {
+    /// Whether hot methods are compiled to the optimizing tier.
+    pub tiered: bool,
+    /// The number of invocations and backward jumps after which a method is
+    /// compiled.
+    pub compile_threshold: u32,
+    /// Whether hot methods are compiled to machine code rather than to the
+    /// optimizing tier.
+    #[cfg(feature = "jit")]
+    pub jit: bool,
+    /// The number of bytes objects may take up in the heap.
+    pub heap_size: usize,
+    pub collector: Collector,
+    /// Whether each collection of the heap is logged to the standard error,
+    /// along with statistics on all of them once the program ends.
+    pub verbose_gc: bool,
+}
+
+impl Default for ExecuteOptions {
+    fn default() -> Self {
+        Self {
+            tiered: true,
+            compile_threshold: 1000,
+            #[cfg(feature = "jit")]
+            jit: true,
+            heap_size: Heap::DEFAULT_CAPACITY,
+            collector: Collector::MarkSweep,
+            verbose_gc: false,
+        }
+    }
+}
+
+/// Runs the `main` method of `main_class`.
+///
+/// # Errors
+///
+/// Returns an [`ExecuteError`] if a class or member used by the program cannot
+/// be found, or if the heap runs out of memory.
+pub fn execute(classes: &ClassManager, main_class: &JavaStr) -> Result<(), ExecuteError> {
+    execute_with(classes, main_class, ExecuteOptions::default())
 }
 
-pub fn execute(classes: &ClassManager, main_class: &JavaStr) {
+/// Runs the `main` method of `main_class` with the given options.
+///
+/// # Errors
+///
+/// Returns an [`ExecuteError`] if a class or member used by the program cannot
+/// be found, or if the heap runs out of memory.
+pub fn execute_with(
+    classes: &ClassManager,
+    main_class: &JavaStr,
+    options: ExecuteOptions,
+) -> Result<(), ExecuteError> {
+    const MAIN_METHOD_NAME: &JavaStr = java_str!("main");
+    const METHOD_METHOD_DESCRIPTOR: &JavaStr = java_str!("([Ljava/lang/String;)V");
+
+    let mut vm = Vm::new(classes, options);
+    // There are no arrays yet, so `main` is passed `null` for its arguments.
+    let args = [Value::Reference(None)];
+    vm.invoke_static(
+        main_class,
+        MAIN_METHOD_NAME,
+        METHOD_METHOD_DESCRIPTOR,
+        &args,
+    )?;
+    vm.join()?;
+
+    if options.verbose_gc {
+        gc::log_stats(&vm.heap);
+    }
+    Ok(())
+}
+
+/// A virtual machine which runs methods of the loaded classes for the host
+/// application, keeping its heap and the threads started by Java code from
+/// one call to the next.
+///
+/// Each call runs on a thread of its own until the method returns, while the
+/// other threads run alongside it. The references returned to the host are
+/// pinned: the collector neither frees nor moves their objects until the host
+/// releases them, so that they can be passed to later calls.
+pub struct Vm<'a> {
+    classes: &'a ClassManager,
+    options: ExecuteOptions,
+    heap: Heap,
+    scheduler: Scheduler<'a>,
+    /// The references held by the host, once for each time they were
+    /// returned to it.
+    pinned: Vec<ObjectRef>,
+}
+
+impl<'a> Vm<'a> {
+    /// Creates a virtual machine running `classes`, which keep their static
+    /// fields and whether they are initialized from one virtual machine to
+    /// the next, so they should only be run by one.
+    pub fn new(classes: &'a ClassManager, options: ExecuteOptions) -> Self {
+        Self {
+            classes,
+            options,
+            heap: Heap::with_collector(options.heap_size, options.collector),
+            scheduler: Scheduler::new(),
+            pinned: Vec::new(),
+        }
+    }
+
+    /// Returns the heap, where the host can read the fields of the objects it
+    /// holds.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Runs the static method of `class` with the given name and descriptor,
+    /// initializing the class first, and returns its result, or `None` if it
+    /// returns `void`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the class or method cannot be found, if
+    /// `args` do not match the descriptor, or if the program fails.
+    pub fn invoke_static(
+        &mut self,
+        class: &JavaStr,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+        args: &[Value],
+    ) -> Result<Option<Value>, ExecuteError> {
+        let index = self.classes.find(class)?;
+        let Some(method) = self.classes.resolve_method(index, name, descriptor)? else {
+            return Err(LinkageError::NoSuchMethod {
+                class: class.to_owned(),
+                name: name.to_owned(),
+                descriptor: descriptor.to_owned(),
+            }
+            .into());
+        };
+        let flags = self.classes.method_info(method).flags();
+        if flags & MethodFlags::STATIC != MethodFlags::STATIC {
+            return Err(LinkageError::IncompatibleClassChange.into());
+        }
+        self.invoke(method, None, args)
+    }
+
+    /// Runs the method with the given name and descriptor which the class of
+    /// `object` selects, as `invokevirtual` does, and returns its result, or
+    /// `None` if it returns `void`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the method cannot be found, if `args` do
+    /// not match the descriptor, or if the program fails.
+    pub fn invoke_virtual(
+        &mut self,
+        object: ObjectRef,
+        name: &JavaStr,
+        descriptor: &JavaStr,
+        args: &[Value],
+    ) -> Result<Option<Value>, ExecuteError> {
+        let class = self.heap.get(object).class;
+        let vtable = &self.classes.linked(class).vtable;
+        let Some(method) = self.classes.select(vtable, name, descriptor) else {
+            return Err(LinkageError::NoSuchMethod {
+                class: self.classes.classes[class as usize].class.name().to_owned(),
+                name: name.to_owned(),
+                descriptor: descriptor.to_owned(),
+            }
+            .into());
+        };
+        self.invoke(method, Some(object), args)
+    }
+
+    /// Creates an object of `class` and runs its constructor with the given
+    /// descriptor, initializing the class first. The object is returned
+    /// pinned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the class or constructor cannot be
+    /// found, if the class cannot be instantiated, if `args` do not match the
+    /// descriptor, or if the program fails.
+    pub fn new_object(
+        &mut self,
+        class: &JavaStr,
+        descriptor: &JavaStr,
+        args: &[Value],
+    ) -> Result<ObjectRef, ExecuteError> {
+        const INIT_NAME: &JavaStr = java_str!("<init>");
+
+        let index = self.classes.find(class)?;
+        let loaded = &self.classes.classes[index].class;
+        let abstract_flags = ClassFlags::INTERFACE | ClassFlags::ABSTRACT;
+        if (loaded.flags() & abstract_flags).bits() != 0 {
+            return Err(LinkageError::Instantiation(class.to_owned()).into());
+        }
+        let Some(init) = loaded.method_index(INIT_NAME, descriptor) else {
+            return Err(LinkageError::NoSuchMethod {
+                class: class.to_owned(),
+                name: INIT_NAME.to_owned(),
+                descriptor: descriptor.to_owned(),
+            }
+            .into());
+        };
+        let init = MethodId {
+            class: index as u32,
+            method: init as u32,
+        };
+        self.classes.link(index)?;
+        let object = self.alloc(index as u32)?;
+        let object = self.pin(object)?;
+        if let Err(error) = self.invoke(init, Some(object), args) {
+            self.release(object);
+            return Err(error);
+        }
+        Ok(object)
+    }
+
+    /// Runs the static method of `class` or of a superclass with the given
+    /// name whose descriptor the Rust types of `args` and of the result
+    /// accept, initializing its class first, and converts its result, as in
+    /// `vm.call::<_, i64>(class, name, (1, "two"))`. Strings and arrays are
+    /// copied into the heap and out of it, and the references the result
+    /// holds are pinned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the class or method cannot be found, if
+    /// `args` cannot be represented in Java or do not fit in the heap, if the
+    /// result does not fit its Rust type, or if the program fails.
+    pub fn call<A: ToJavaArgs, R: FromJavaResult>(
+        &mut self,
+        class: &JavaStr,
+        name: &JavaStr,
+        args: A,
+    ) -> Result<R, ExecuteError> {
+        let index = self.classes.find(class)?;
+        self.call_by_types(index, name, None, args)
+    }
+
+    /// Runs the method of the class of `object` or of a superclass with the
+    /// given name whose descriptor the Rust types of `args` and of the result
+    /// accept, and converts its result as [`Vm::call`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the method cannot be found, if `args`
+    /// cannot be represented in Java or do not fit in the heap, if the result
+    /// does not fit its Rust type, or if the program fails.
+    pub fn call_method<A: ToJavaArgs, R: FromJavaResult>(
+        &mut self,
+        object: ObjectRef,
+        name: &JavaStr,
+        args: A,
+    ) -> Result<R, ExecuteError> {
+        let class = self.heap.get(object).class as usize;
+        self.call_by_types(class, name, Some(object), args)
+    }
+
+    /// Releases a reference returned to the host, once for each time it was
+    /// returned, after which the collector may free the object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the host does not hold the reference.
+    pub fn release(&mut self, object: ObjectRef) {
+        let index = self
+            .pinned
+            .iter()
+            .rposition(|&pinned| pinned == object)
+            .expect("reference is held by the host");
+        self.pinned.swap_remove(index);
+    }
+
+    /// Runs the threads started by Java code until only daemon threads are
+    /// left, as a program does once its `main` method returns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExecuteError`] if the program fails.
+    pub fn join(&mut self) -> Result<(), ExecuteError> {
+        if !self.scheduler.threads.is_empty() {
+            run(self, None)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `method` on a thread started for the host, with `receiver` and
+    /// `args` as its arguments, and returns its result, pinning a reference.
+    fn invoke(
+        &mut self,
+        method: MethodId,
+        receiver: Option<ObjectRef>,
+        args: &[Value],
+    ) -> Result<Option<Value>, ExecuteError> {
+        let classes = self.classes;
+        let descriptor = classes.method_info(method).parsed_descriptor();
+        let parameters = descriptor.args();
+        if parameters.len() != args.len()
+            || parameters
+                .iter()
+                .zip(args)
+                .any(|(parameter, arg)| !classes.accepts(&self.heap, parameter, *arg))
+        {
+            let (_, _, descriptor) = classes.signature(method);
+            return Err(ExecuteError::IllegalArgument { descriptor });
+        }
+        // The methods of the host and of native libraries run without a
+        // thread.
+        let host = &classes.classes[method.class as usize].host;
+        let result = match host.get(method.method as usize) {
+            Some(host) => Some(host.call(descriptor, classes, &mut self.heap, receiver, args)?),
+            #[cfg(feature = "jni")]
+            None if classes.is_jni(method) => Some(self.call_jni(method, receiver, args, None)?),
+            None => None,
+        };
+        if let Some(result) = result {
+            return match result {
+                Some(Value::Reference(Some(object))) => {
+                    Ok(Some(Value::Reference(Some(self.pin(object)?))))
+                }
+                value => Ok(value),
+            };
+        }
+        let code = classes.method_code(method)?;
+
+        // The references passed are pinned, so allocating the thread object
+        // does not move them.
+        let object = match classes.position(java_str!("java/lang/Thread")) {
+            Some(class) => {
+                classes.link(class)?;
+                Some(self.alloc(class as u32)?)
+            }
+            None => None,
+        };
+        let mut thread = ThreadStack::new();
+        let registers = thread.registers(0, code);
+        let receiver = receiver.map(|receiver| Value::Reference(Some(receiver)));
+        let mut slot = 0;
+        for &arg in receiver.iter().chain(args) {
+            call_frame::set(registers, slot, arg);
+            slot += arg.size();
+        }
+        let mut call_stack = vec![CallFrame::new(method, code, 0)];
+        classes.initialize(method.class as usize, &mut call_stack)?;
+        let id = self.scheduler.start(thread, call_stack, object);
+
+        let thread = run(self, Some(id))?.expect("the thread of the host terminates");
+        let Some(result) = descriptor.result() else {
+            return Ok(None);
+        };
+        match thread.result(ValueType::from(result)) {
+            Value::Reference(Some(object)) => Ok(Some(Value::Reference(Some(self.pin(object)?)))),
+            value => Ok(Some(value)),
+        }
+    }
+
+    /// Runs the method of the class at `class` with the given name which the
+    /// Rust types accept, as a static method unless it has a `receiver`.
+    fn call_by_types<A: ToJavaArgs, R: FromJavaResult>(
+        &mut self,
+        class: usize,
+        name: &JavaStr,
+        receiver: Option<ObjectRef>,
+        args: A,
+    ) -> Result<R, ExecuteError> {
+        let classes = self.classes;
+        let method = classes.resolve_method_by(class, name, receiver.is_none(), |descriptor| {
+            A::accepts(descriptor.args()) && R::accepts_result(descriptor.result())
+        })?;
+        let Some(method) = method else {
+            let descriptor = MethodDescriptor::new(A::parameter_types(), R::result_type());
+            return Err(LinkageError::NoSuchMethod {
+                class: classes.classes[class].class.name().to_owned(),
+                name: name.to_owned(),
+                descriptor: crate::string::from_utf8(&descriptor.to_string()).into_owned(),
+            }
+            .into());
+        };
+        let args = self.convert_args(method, &args)?;
+        let result = self.invoke(method, receiver, &args);
+        for arg in args {
+            if let Value::Reference(Some(object)) = arg {
+                self.release(object);
+            }
+        }
+        self.convert_result(method, result?)
+    }
+
+    /// Converts `args` to the parameter types of `method` and pins the
+    /// references among them until it returns. The heap is collected if the
+    /// objects they need do not fit, and fully collected if they still do
+    /// not, before converting them again. Running out of memory after that is
+    /// an [`ExecuteError::OutOfMemory`].
+    fn convert_args<A: ToJavaArgs>(
+        &mut self,
+        method: MethodId,
+        args: &A,
+    ) -> Result<Vec<Value>, ExecuteError> {
+        let classes = self.classes;
+        let parameters = classes.method_info(method).parsed_descriptor().args();
+        let mut causes = [gc::Cause::Allocation, gc::Cause::SystemGc].into_iter();
+        let mut args = loop {
+            match args.to_java(parameters, &mut JavaHeap::new(classes, &mut self.heap)) {
+                Ok(args) => break args,
+                Err(ConvertError::Alloc(AllocError::Full)) => match causes.next() {
+                    Some(cause) => self.collect(cause),
+                    None => return Err(ExecuteError::OutOfMemory),
+                },
+                Err(ConvertError::Alloc(AllocError::OutOfMemory)) => {
+                    return Err(ExecuteError::OutOfMemory)
+                }
+                Err(ConvertError::Linkage(error)) => return Err(error.into()),
+                Err(ConvertError::Unrepresentable) => {
+                    let (_, _, descriptor) = classes.signature(method);
+                    return Err(ExecuteError::IllegalArgument { descriptor });
+                }
+            }
+        };
+
+        self.pin_values(&mut args);
+        Ok(args)
+    }
+
+    /// Pins the references among `values`, moving them out of the nursery
+    /// first, until they are released.
+    fn pin_values(&mut self, values: &mut [Value]) {
+        let start = self.pinned.len();
+        self.pinned
+            .extend(values.iter().filter_map(|value| match value {
+                Value::Reference(object) => *object,
+                _ => None,
+            }));
+        if self.pinned[start..].iter().any(|object| object.is_young()) {
+            self.collect(gc::Cause::Pin);
+            let mut pinned = self.pinned[start..].iter();
+            for value in values {
+                if let Value::Reference(Some(object)) = value {
+                    *object = *pinned.next().expect("references are pinned");
+                }
+            }
+        }
+    }
+
+    /// Converts the `result` of `method`, which [`Vm::invoke`] pinned if it is
+    /// a reference, and pins the references the Rust value holds instead.
+    fn convert_result<R: FromJavaResult>(
+        &mut self,
+        method: MethodId,
+        result: Option<Value>,
+    ) -> Result<R, ExecuteError> {
+        let classes = self.classes;
+        let mut heap = JavaHeap::new(classes, &mut self.heap);
+        let mut converted = R::from_result(result, &mut heap);
+        let mut held = heap.into_held();
+        // The objects the result reaches are moved out of the nursery, where
+        // they are converted again.
+        if held.iter().any(|object| object.is_young()) {
+            self.collect(gc::Cause::Pin);
+            let mut heap = JavaHeap::new(classes, &mut self.heap);
+            converted = R::from_result(result, &mut heap);
+            held = heap.into_held();
+        }
+        if let Some(Value::Reference(Some(object))) = result {
+            self.release(object);
+        }
+        let Some(converted) = converted else {
+            let (_, _, descriptor) = classes.signature(method);
+            return Err(ExecuteError::ResultMismatch { descriptor });
+        };
+        self.pinned.extend(held);
+        Ok(converted)
+    }
+
+    /// Allocates an object of the linked class at `class`, collecting the
+    /// heap first if it is full.
+    fn alloc(&mut self, class: u32) -> Result<ObjectRef, ExecuteError> {
+        loop {
+            match self.classes.instantiate(&mut self.heap, class) {
+                Ok(object) => return Ok(object),
+                Err(AllocError::Full) => self.collect(gc::Cause::Allocation),
+                Err(AllocError::OutOfMemory) => return Err(ExecuteError::OutOfMemory),
+            }
+        }
+    }
+
+    /// Pins `object` for the host, moving it out of the nursery first, and
+    /// returns where it is.
+    fn pin(&mut self, object: ObjectRef) -> Result<ObjectRef, ExecuteError> {
+        self.pinned.push(object);
+        if object.is_young() {
+            self.collect(gc::Cause::Pin);
+        }
+        Ok(*self.pinned.last().expect("reference was pinned"))
+    }
+
+    fn collect(&mut self, cause: gc::Cause) {
+        gc::collect(
+            self.classes,
+            &mut self.heap,
+            &mut self.scheduler,
+            &mut self.pinned,
+            cause,
+            self.options.verbose_gc,
+        );
+    }
+}
+
+/// Runs the threads of `vm` until the thread with the id `host` is back to
+/// the frames it is waited for to return to, or until only daemon threads are
+/// left if `host` is `None`. Returns the stack holding the result of a thread
+/// the host started, which terminates once its first frame has returned.
+fn run<'a>(vm: &mut Vm<'a>, host: Option<u32>) -> Result<Option<ThreadStack>, ExecuteError> {
+    // The operations stay out of line, as inlining them all into the loop
+    // makes every instruction slower.
+    #[track_caller]
+    #[inline(never)]
+    fn bin_op_int<F: FnOnce(i32, i32) -> i32>(stack: &mut Stack, f: F) {
+        let rhs = stack.pop_int();
+        let lhs = stack.pop_int();
+        stack.push_int(f(lhs, rhs));
+    }
+    #[track_caller]
+    #[inline(never)]
+    fn bin_op_long<F: FnOnce(i64, i64) -> i64>(stack: &mut Stack, f: F) {
+        let rhs = stack.pop_long();
+        let lhs = stack.pop_long();
+        stack.push_long(f(lhs, rhs));
+    }
     #[track_caller]
-    fn bin_op_int<F: FnOnce(i32, i32) -> i32>(frame: &mut CallFrame, f: F) {
-        let rhs = frame.stack.pop_int();
-        let lhs = frame.stack.pop_int();
-        frame.stack.push_int(f(lhs, rhs));
+    #[inline(never)]
+    fn div_op_int(stack: &mut Stack, f: fn(i32, i32) -> i32) -> Result<(), ExecuteError> {
+        let rhs = stack.pop_int();
+        if rhs == 0 {
+            return Err(divide_by_zero());
+        }
+        let lhs = stack.pop_int();
+        stack.push_int(f(lhs, rhs));
+        Ok(())
+    }
+    #[track_caller]
+    #[inline(never)]
+    fn div_op_long(stack: &mut Stack, f: fn(i64, i64) -> i64) -> Result<(), ExecuteError> {
+        let rhs = stack.pop_long();
+        if rhs == 0 {
+            return Err(divide_by_zero());
+        }
+        let lhs = stack.pop_long();
+        stack.push_long(f(lhs, rhs));
+        Ok(())
     }
     #[track_caller]
-    fn bin_op_long<F: FnOnce(i64, i64) -> i64>(frame: &mut CallFrame, f: F) {
-        let rhs = frame.stack.pop_long();
-        let lhs = frame.stack.pop_long();
-        frame.stack.push_long(f(lhs, rhs));
+    #[inline(never)]
+    fn shift_op_long<F: FnOnce(i64, i32) -> i64>(stack: &mut Stack, f: F) {
+        let rhs = stack.pop_int();
+        let lhs = stack.pop_long();
+        stack.push_long(f(lhs, rhs));
     }
     #[track_caller]
-    fn bin_op_float<F: FnOnce(f32, f32) -> f32>(frame: &mut CallFrame, f: F) {
-        let rhs = frame.stack.pop_float();
-        let lhs = frame.stack.pop_float();
-        frame.stack.push_float(f(lhs, rhs));
+    #[inline(never)]
+    fn bin_op_float<F: FnOnce(f32, f32) -> f32>(stack: &mut Stack, f: F) {
+        let rhs = stack.pop_float();
+        let lhs = stack.pop_float();
+        stack.push_float(f(lhs, rhs));
     }
     #[track_caller]
-    fn bin_op_double<F: FnOnce(f64, f64) -> f64>(frame: &mut CallFrame, f: F) {
-        let rhs = frame.stack.pop_double();
-        let lhs = frame.stack.pop_double();
-        frame.stack.push_double(f(lhs, rhs));
+    #[inline(never)]
+    fn bin_op_double<F: FnOnce(f64, f64) -> f64>(stack: &mut Stack, f: F) {
+        let rhs = stack.pop_double();
+        let lhs = stack.pop_double();
+        stack.push_double(f(lhs, rhs));
     }
 
     #[track_caller]
-    fn un_op_int<F: FnOnce(i32) -> i32>(frame: &mut CallFrame, f: F) {
-        let val = frame.stack.pop_int();
-        frame.stack.push_int(f(val));
+    #[inline(never)]
+    fn un_op_int<F: FnOnce(i32) -> i32>(stack: &mut Stack, f: F) {
+        let val = stack.pop_int();
+        stack.push_int(f(val));
     }
     #[track_caller]
-    fn un_op_float<F: FnOnce(f32) -> f32>(frame: &mut CallFrame, f: F) {
-        let val = frame.stack.pop_float();
-        frame.stack.push_float(f(val));
+    #[inline(never)]
+    fn un_op_float<F: FnOnce(f32) -> f32>(stack: &mut Stack, f: F) {
+        let val = stack.pop_float();
+        stack.push_float(f(val));
     }
     #[track_caller]
-    fn un_op_long<F: FnOnce(i64) -> i64>(frame: &mut CallFrame, f: F) {
-        let val = frame.stack.pop_long();
-        frame.stack.push_long(f(val));
+    #[inline(never)]
+    fn un_op_long<F: FnOnce(i64) -> i64>(stack: &mut Stack, f: F) {
+        let val = stack.pop_long();
+        stack.push_long(f(val));
     }
     #[track_caller]
-    fn un_op_double<F: FnOnce(f64) -> f64>(frame: &mut CallFrame, f: F) {
-        let val = frame.stack.pop_double();
-        frame.stack.push_double(f(val));
+    #[inline(never)]
+    fn un_op_double<F: FnOnce(f64) -> f64>(stack: &mut Stack, f: F) {
+        let val = stack.pop_double();
+        stack.push_double(f(val));
     }
 
-    const MAIN_METHOD_NAME: &JavaStr = java_str!("main");
-    const METHOD_METHOD_DESCRIPTOR: &JavaStr = java_str!("([Ljava/lang/String;)V");
+    /// Jumps to `target`, and returns `true` if the jump went backward and
+    /// either used up the time slice `budget` or made the method hot.
+    #[inline(always)]
+    fn jump(
+        pc: &mut usize,
+        code: &DecodedCode,
+        target: u32,
+        threshold: u32,
+        budget: &mut i32,
+    ) -> bool {
+        let backward = (target as usize) < *pc;
+        *pc = target as usize;
+        if !backward {
+            return false;
+        }
+        *budget -= 1;
+        *budget <= 0 || code.profile.count(threshold)
+    }
 
-    let Some(main_class) = classes.get(main_class) else {
-        panic!("expected class \"{main_class}\" to be loaded");
+    // Formatting an instruction inline keeps it out of registers in the whole
+    // loop, which makes every instruction slower.
+    #[cold]
+    #[inline(never)]
+    fn unimplemented(op: Op) -> ExecuteError {
+        ExecuteError::Unsupported(format!("the instruction {op:?}"))
+    }
+
+    /// Pushes `value`, which the frame popped from `call_stack` returned, on
+    /// the operand stack of its caller, or leaves it as the result of the
+    /// thread if it has none.
+    #[inline(always)]
+    fn push_result(thread: &mut ThreadStack, call_stack: &mut [CallFrame], value: Value) {
+        match call_stack.last_mut() {
+            Some(invoker_frame) => thread.stack(invoker_frame).push(value),
+            None => thread.set_result(value),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn null_pointer() -> ExecuteError {
+        JavaException::NullPointer.into()
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn divide_by_zero() -> ExecuteError {
+        JavaException::DivideByZero.into()
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn class_cast() -> ExecuteError {
+        JavaException::ClassCast.into()
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn negative_array_size(length: i32) -> ExecuteError {
+        JavaException::NegativeArraySize(length).into()
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn index_out_of_bounds(index: i32, length: usize) -> ExecuteError {
+        JavaException::ArrayIndexOutOfBounds { index, length }.into()
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn array_store() -> ExecuteError {
+        JavaException::ArrayStore.into()
+    }
+
+    /// Returns `array` along with `index` if it is the index of one of its
+    /// elements.
+    #[inline(always)]
+    fn element(
+        heap: &Heap,
+        array: Option<ObjectRef>,
+        index: i32,
+    ) -> Result<(ObjectRef, usize), ExecuteError> {
+        let Some(array) = array else {
+            return Err(null_pointer());
+        };
+        let length = heap.get(array).fields.len();
+        match usize::try_from(index) {
+            Ok(index) if index < length => Ok((array, index)),
+            _ => Err(index_out_of_bounds(index, length)),
+        }
+    }
+
+    let classes = vm.classes;
+    let options = vm.options;
+
+    // A threshold of zero is never reached, as counting starts at one.
+    let threshold = if options.tiered {
+        options.compile_threshold.max(1)
+    } else {
+        0
     };
-    let main_method = main_class.get_method(MAIN_METHOD_NAME, METHOD_METHOD_DESCRIPTOR);
+    #[cfg(feature = "jit")]
+    let (jit_threshold, ir_threshold) = if options.jit {
+        (threshold, 0)
+    } else {
+        (0, threshold)
+    };
+    #[cfg(not(feature = "jit"))]
+    let ir_threshold = threshold;
+    // Set when the heap must be collected, which happens once the frame
+    // on top of the call stack has stopped before an instruction.
+    let mut collect = None;
+    // Set when the running thread needs the scheduler to call a native method
+    // or enter or exit a monitor, which it does once the frame has stopped.
+    let mut request = None;
+    let mut budget = scheduler::TIME_SLICE;
+    // Set once a native method has run, during which the thread the host
+    // waits for may have returned while another thread was running.
+    let mut switched = false;
+    loop {
+        if let Some(cause) = collect.take() {
+            vm.collect(cause);
+        }
+        if let Some(request) = request.take() {
+            let yields = match request {
+                #[cfg(feature = "jni")]
+                Request::Jni(method) => {
+                    vm.invoke_jni(method)?;
+                    switched = true;
+                    false
+                }
+                request => vm.scheduler.request(classes, &mut vm.heap, request)?,
+            };
+            if yields {
+                budget = 0;
+            }
+        }
+        let heap = &mut vm.heap;
+        let scheduler = &mut vm.scheduler;
+        // The thread the host started terminates once its first frame has
+        // returned, leaving the result in its stack, and a thread running a
+        // native method goes back to it once the methods it called returned.
+        if let Some(host) = host {
+            if scheduler.returned(host, std::mem::take(&mut switched)) {
+                if !scheduler.current().call_stack.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(scheduler.terminate().stack));
+            }
+        }
+        // Other threads terminate once their first frame has returned, unless
+        // they are waited for further down, while the finalizer thread makes
+        // the calls pending, or waits for a collection to find some.
+        if scheduler.is_awaited() {
+            budget = 0;
+        } else if scheduler.current().call_stack.is_empty() {
+            if !scheduler.is_finalizer() {
+                scheduler.terminate();
+                budget = 0;
+            } else if !gc::push_pending(classes, heap, scheduler.current())? {
+                scheduler.idle();
+                budget = 0;
+            }
+        }
+        // Every call and return counts down the time slice, as do backward
+        // jumps in every tier. Once switched, the thread is checked again.
+        budget -= 1;
+        if budget <= 0 {
+            budget = scheduler::TIME_SLICE;
+            if !scheduler.switch()? {
+                break;
+            }
+            continue;
+        }
+        let JavaThread {
+            stack: thread,
+            call_stack,
+            id: thread_id,
+            ..
+        } = scheduler.current();
+        let thread_id = *thread_id;
+        let frame = call_stack.last_mut().expect("thread has a frame");
+        if frame.overflows() {
+            return Err(JavaException::StackOverflow.into());
+        }
+        if frame.pc == 0 && frame.code.synchronized && frame.monitor == FrameMonitor::Unentered {
+            request = Some(Request::EnterMethod);
+            continue;
+        }
+        #[cfg(feature = "jit")]
+        if jit_threshold != 0 && jit::enter(classes, frame, jit_threshold) {
+            match jit::run(frame, thread, &mut budget) {
+                jit::Exit::Return(slots) => {
+                    call_stack.pop();
+                    if let Some(caller) = call_stack.last_mut() {
+                        caller.depth += slots;
+                    }
+                    continue;
+                }
+                jit::Exit::Call(method) => {
+                    let base = frame.top();
+                    call_stack.push(CallFrame::new(method, classes.method_code(method)?, base));
+                    continue;
+                }
+                // The interpreter runs the instruction the machine code
+                // stopped at, without entering the machine code again first,
+                // unless it stopped for the other threads to run.
+                jit::Exit::Fallback if budget <= 0 => continue,
+                jit::Exit::Fallback => (),
+            }
+        }
+        if ir_threshold != 0 && ir::enter(classes, frame, ir_threshold) {
+            match ir::run(classes, heap, thread, call_stack, &mut budget)? {
+                ir::Stop::Continue => (),
+                ir::Stop::Collect => collect = Some(gc::Cause::Allocation),
+                #[cfg(feature = "jni")]
+                ir::Stop::Jni(method) => request = Some(Request::Jni(method)),
+            }
+            continue;
+        }
 
-    let mut call_stack = vec![CallFrame::new(
-        main_class.constants(),
-        main_method.bytecode().unwrap(),
-    )];
+        let code = frame.code;
+        let (mut locals, mut stack) = thread.split(frame.base, code, &mut frame.depth);
+        'method: while let Some(op) = code.get(frame.pc) {
+            frame.pc += 1;
+            match op {
+                // The return runs again once the frame has exited its monitor.
+                Op::ireturn
+                | Op::lreturn
+                | Op::freturn
+                | Op::dreturn
+                | Op::areturn
+                | Op::ret_void
+                    if matches!(frame.monitor, FrameMonitor::Entered(_)) =>
+                {
+                    frame.pc -= 1;
+                    match frame.monitor {
+                        FrameMonitor::Entered(LockTarget::Object(object))
+                            if heap.get_mut(object).lock.try_exit(thread_id) =>
+                        {
+                            frame.monitor = FrameMonitor::Exited;
+                        }
+                        _ => {
+                            request = Some(Request::ExitMethod);
+                            break 'method;
+                        }
+                    }
+                }
 
-    while let Some(frame) = call_stack.last_mut() {
-        'method: while let Some((pc, inst)) = frame.bytecode.next() {
-            match inst {
                 // Constant
-                Instruction::nop => (),
-                Instruction::aconst_null => todo!("no support for objects"),
-                Instruction::iconst(val) => frame.stack.push_int(val),
-                Instruction::lconst(val) => frame.stack.push_long(val),
-                Instruction::fconst(val) => frame.stack.push_float(val),
-                Instruction::dconst(val) => frame.stack.push_double(val),
-                Instruction::bipush(val) => frame.stack.push_int(val as i32),
-                Instruction::sipush(val) => frame.stack.push_int(val as i32),
-                Instruction::ldc(idx) => match frame.constants.get(idx) {
-                    Entry::Integer(val) => frame.stack.push_int(*val),
-                    Entry::Long(val) => frame.stack.push_long(*val),
-                    Entry::Float(val) => frame.stack.push_float(*val),
-                    Entry::Double(val) => frame.stack.push_double(*val),
-                    entry => panic!("unexpected constant pool entry type: {entry:?}"),
-                },
+                Op::nop => (),
+                Op::aconst_null => stack.push_reference(None),
+                Op::iconst(val) => stack.push_int(val),
+                Op::lconst(val) => stack.push_long(val),
+                Op::fconst(val) => stack.push_float(val),
+                Op::dconst(val) => stack.push_double(val),
+                Op::ldc(_) => {
+                    let (quick, _) = classes.quicken(frame.class, code, frame.pc - 1, op)?;
+                    frame.pc -= 1;
+                    code.quicken(frame.pc, quick);
+                }
 
                 // Load
-                Instruction::iload(idx) => {
-                    let val = frame.locals.get_int(idx as usize);
-                    frame.stack.push_int(val);
+                Op::iload(idx) => {
+                    let val = locals.get_int(idx as usize);
+                    stack.push_int(val);
+                }
+                Op::lload(idx) => {
+                    let val = locals.get_long(idx as usize);
+                    stack.push_long(val);
+                }
+                Op::fload(idx) => {
+                    let val = locals.get_float(idx as usize);
+                    stack.push_float(val);
                 }
-                Instruction::lload(idx) => {
-                    let val = frame.locals.get_long(idx as usize);
-                    frame.stack.push_long(val);
+                Op::dload(idx) => {
+                    let val = locals.get_double(idx as usize);
+                    stack.push_double(val);
                 }
-                Instruction::fload(idx) => {
-                    let val = frame.locals.get_float(idx as usize);
-                    frame.stack.push_float(val);
+                Op::aload(idx) => {
+                    let val = locals.get_reference(idx as usize);
+                    stack.push_reference(val);
                 }
-                Instruction::dload(idx) => {
-                    let val = frame.locals.get_double(idx as usize);
-                    frame.stack.push_double(val);
+                Op::iaload
+                | Op::laload
+                | Op::faload
+                | Op::daload
+                | Op::aaload
+                | Op::baload
+                | Op::caload
+                | Op::saload => {
+                    let index = stack.pop_int();
+                    let (array, index) = element(heap, stack.pop_reference(), index)?;
+                    stack.push(heap.get(array).fields[index]);
                 }
 
                 // Store
-                Instruction::istore(idx) => {
-                    let val = frame.stack.pop_int();
-                    frame.locals.set_int(idx as usize, val);
+                Op::istore(idx) => {
+                    let val = stack.pop_int();
+                    locals.set_int(idx as usize, val);
                 }
-                Instruction::lstore(idx) => {
-                    let val = frame.stack.pop_long();
-                    frame.locals.set_long(idx as usize, val);
+                Op::lstore(idx) => {
+                    let val = stack.pop_long();
+                    locals.set_long(idx as usize, val);
                 }
-                Instruction::fstore(idx) => {
-                    let val = frame.stack.pop_float();
-                    frame.locals.set_float(idx as usize, val);
+                Op::fstore(idx) => {
+                    let val = stack.pop_float();
+                    locals.set_float(idx as usize, val);
                 }
-                Instruction::dstore(idx) => {
-                    let val = frame.stack.pop_double();
-                    frame.locals.set_double(idx as usize, val);
+                Op::dstore(idx) => {
+                    let val = stack.pop_double();
+                    locals.set_double(idx as usize, val);
+                }
+                Op::astore(idx) => {
+                    // `astore` also stores the return addresses pushed by `jsr`.
+                    let slot = stack.pop_slot();
+                    locals.set_slot(idx as usize, slot);
+                }
+                Op::iastore | Op::lastore | Op::fastore | Op::dastore => {
+                    let value = stack.pop(match op {
+                        Op::iastore => ValueType::Int,
+                        Op::lastore => ValueType::Long,
+                        Op::fastore => ValueType::Float,
+                        _ => ValueType::Double,
+                    });
+                    let index = stack.pop_int();
+                    let (array, index) = element(heap, stack.pop_reference(), index)?;
+                    heap.get_mut(array).fields[index] = value;
+                }
+                // The values stored to narrow elements are truncated to their type.
+                Op::bastore | Op::castore | Op::sastore => {
+                    let value = stack.pop_int();
+                    let index = stack.pop_int();
+                    let (array, index) = element(heap, stack.pop_reference(), index)?;
+                    let value = match op {
+                        Op::castore => value as u16 as i32,
+                        Op::sastore => value as i16 as i32,
+                        _ => {
+                            let class = &classes.classes[heap.get(array).class as usize];
+                            match class.component {
+                                Some(FieldType::Bool) => value & 1,
+                                _ => value as i8 as i32,
+                            }
+                        }
+                    };
+                    heap.get_mut(array).fields[index] = Value::Int(value);
+                }
+                Op::aastore => {
+                    let value = stack.pop_reference();
+                    let index = stack.pop_int();
+                    let (array, index) = element(heap, stack.pop_reference(), index)?;
+                    if let Some(value) = value {
+                        let class = heap.get(value).class as usize;
+                        let component = classes.component_class(heap.get(array).class as usize);
+                        if !component.is_some_and(|component| classes.is_subclass(class, component))
+                        {
+                            return Err(array_store());
+                        }
+                    }
+                    heap.set_field(array, index, Value::Reference(value));
                 }
 
                 // Stack
-                Instruction::pop => frame.stack.inst_pop(),
-                Instruction::pop2 => frame.stack.inst_pop2(),
-                Instruction::dup => frame.stack.inst_dup(),
-                Instruction::dup_x1 => frame.stack.inst_dup_x1(),
-                Instruction::dup_x2 => frame.stack.inst_dup_x2(),
-                Instruction::dup2 => frame.stack.inst_dup2(),
-                Instruction::dup2_x1 => frame.stack.inst_dup2_x1(),
-                Instruction::dup2_x2 => frame.stack.inst_dup2_x2(),
-                Instruction::swap => frame.stack.inst_swap(),
+                Op::pop => stack.inst_pop(),
+                Op::pop2 => stack.inst_pop2(),
+                Op::dup => stack.inst_dup(),
+                Op::dup_x1 => stack.inst_dup_x1(),
+                Op::dup_x2 => stack.inst_dup_x2(),
+                Op::dup2 => stack.inst_dup2(),
+                Op::dup2_x1 => stack.inst_dup2_x1(),
+                Op::dup2_x2 => stack.inst_dup2_x2(),
+                Op::swap => stack.inst_swap(),
 
                 // Math
-                Instruction::iadd => bin_op_int(frame, std::ops::Add::add),
-                Instruction::fadd => bin_op_float(frame, std::ops::Add::add),
-                Instruction::ladd => bin_op_long(frame, std::ops::Add::add),
-                Instruction::dadd => bin_op_double(frame, std::ops::Add::add),
-                Instruction::isub => bin_op_int(frame, std::ops::Sub::sub),
-                Instruction::fsub => bin_op_float(frame, std::ops::Sub::sub),
-                Instruction::lsub => bin_op_long(frame, std::ops::Sub::sub),
-                Instruction::dsub => bin_op_double(frame, std::ops::Sub::sub),
-                Instruction::imul => bin_op_int(frame, std::ops::Mul::mul),
-                Instruction::fmul => bin_op_float(frame, std::ops::Mul::mul),
-                Instruction::lmul => bin_op_long(frame, std::ops::Mul::mul),
-                Instruction::dmul => bin_op_double(frame, std::ops::Mul::mul),
-                Instruction::idiv => bin_op_int(frame, std::ops::Div::div),
-                Instruction::fdiv => bin_op_float(frame, std::ops::Div::div),
-                Instruction::ldiv => bin_op_long(frame, std::ops::Div::div),
-                Instruction::ddiv => bin_op_double(frame, std::ops::Div::div),
-                Instruction::irem => bin_op_int(frame, std::ops::Rem::rem),
-                Instruction::frem => bin_op_float(frame, std::ops::Rem::rem),
-                Instruction::lrem => bin_op_long(frame, std::ops::Rem::rem),
-                Instruction::drem => bin_op_double(frame, std::ops::Rem::rem),
-                Instruction::ineg => un_op_int(frame, std::ops::Neg::neg),
-                Instruction::fneg => un_op_float(frame, std::ops::Neg::neg),
-                Instruction::lneg => un_op_long(frame, std::ops::Neg::neg),
-                Instruction::dneg => un_op_double(frame, std::ops::Neg::neg),
-                Instruction::ishl => bin_op_int(frame, |val1, val2| val1.wrapping_shl(val2 as u32)),
-                Instruction::lshl => {
-                    bin_op_long(frame, |val1, val2| val1.wrapping_shl(val2 as u32));
-                }
-                Instruction::ishr => bin_op_int(frame, |val1, val2| val1.wrapping_shr(val2 as u32)),
-                Instruction::lshr => {
-                    bin_op_long(frame, |val1, val2| val1.wrapping_shr(val2 as u32));
-                }
-                Instruction::iushr => bin_op_int(frame, |val1, val2| {
+                Op::iadd => bin_op_int(&mut stack, i32::wrapping_add),
+                Op::fadd => bin_op_float(&mut stack, std::ops::Add::add),
+                Op::ladd => bin_op_long(&mut stack, i64::wrapping_add),
+                Op::dadd => bin_op_double(&mut stack, std::ops::Add::add),
+                Op::isub => bin_op_int(&mut stack, i32::wrapping_sub),
+                Op::fsub => bin_op_float(&mut stack, std::ops::Sub::sub),
+                Op::lsub => bin_op_long(&mut stack, i64::wrapping_sub),
+                Op::dsub => bin_op_double(&mut stack, std::ops::Sub::sub),
+                Op::imul => bin_op_int(&mut stack, i32::wrapping_mul),
+                Op::fmul => bin_op_float(&mut stack, std::ops::Mul::mul),
+                Op::lmul => bin_op_long(&mut stack, i64::wrapping_mul),
+                Op::dmul => bin_op_double(&mut stack, std::ops::Mul::mul),
+                Op::idiv => div_op_int(&mut stack, i32::wrapping_div)?,
+                Op::fdiv => bin_op_float(&mut stack, std::ops::Div::div),
+                Op::ldiv => div_op_long(&mut stack, i64::wrapping_div)?,
+                Op::ddiv => bin_op_double(&mut stack, std::ops::Div::div),
+                Op::irem => div_op_int(&mut stack, i32::wrapping_rem)?,
+                Op::frem => bin_op_float(&mut stack, std::ops::Rem::rem),
+                Op::lrem => div_op_long(&mut stack, i64::wrapping_rem)?,
+                Op::drem => bin_op_double(&mut stack, std::ops::Rem::rem),
+                Op::ineg => un_op_int(&mut stack, i32::wrapping_neg),
+                Op::fneg => un_op_float(&mut stack, std::ops::Neg::neg),
+                Op::lneg => un_op_long(&mut stack, i64::wrapping_neg),
+                Op::dneg => un_op_double(&mut stack, std::ops::Neg::neg),
+                Op::ishl => bin_op_int(&mut stack, |val1, val2| val1.wrapping_shl(val2 as u32)),
+                Op::lshl => shift_op_long(&mut stack, |val1, val2| val1.wrapping_shl(val2 as u32)),
+                Op::ishr => bin_op_int(&mut stack, |val1, val2| val1.wrapping_shr(val2 as u32)),
+                Op::lshr => shift_op_long(&mut stack, |val1, val2| val1.wrapping_shr(val2 as u32)),
+                Op::iushr => bin_op_int(&mut stack, |val1, val2| {
                     (val1 as u32).wrapping_shr(val2 as u32) as i32
                 }),
-                Instruction::lushr => bin_op_int(frame, |val1, val2| {
-                    (val1 as u32).wrapping_shr(val2 as u32) as i32
+                Op::lushr => shift_op_long(&mut stack, |val1, val2| {
+                    (val1 as u64).wrapping_shr(val2 as u32) as i64
                 }),
-                Instruction::iand => bin_op_int(frame, std::ops::BitAnd::bitand),
-                Instruction::land => bin_op_long(frame, std::ops::BitAnd::bitand),
-                Instruction::ior => bin_op_int(frame, std::ops::BitOr::bitor),
-                Instruction::lor => bin_op_long(frame, std::ops::BitOr::bitor),
-                Instruction::ixor => bin_op_int(frame, std::ops::BitXor::bitxor),
-                Instruction::lxor => bin_op_long(frame, std::ops::BitXor::bitxor),
+                Op::iand => bin_op_int(&mut stack, std::ops::BitAnd::bitand),
+                Op::land => bin_op_long(&mut stack, std::ops::BitAnd::bitand),
+                Op::ior => bin_op_int(&mut stack, std::ops::BitOr::bitor),
+                Op::lor => bin_op_long(&mut stack, std::ops::BitOr::bitor),
+                Op::ixor => bin_op_int(&mut stack, std::ops::BitXor::bitxor),
+                Op::lxor => bin_op_long(&mut stack, std::ops::BitXor::bitxor),
 
                 // Conversion
-                Instruction::i2l => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_long(val as i64);
+                Op::i2l => {
+                    let val = stack.pop_int();
+                    stack.push_long(val as i64);
                 }
-                Instruction::i2f => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_float(val as f32);
+                Op::i2f => {
+                    let val = stack.pop_int();
+                    stack.push_float(val as f32);
                 }
-                Instruction::i2d => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_double(val as f64);
+                Op::i2d => {
+                    let val = stack.pop_int();
+                    stack.push_double(val as f64);
                 }
-                Instruction::l2i => {
-                    let val = frame.stack.pop_long();
-                    frame.stack.push_int(val as i32);
+                Op::l2i => {
+                    let val = stack.pop_long();
+                    stack.push_int(val as i32);
                 }
-                Instruction::l2f => {
-                    let val = frame.stack.pop_long();
-                    frame.stack.push_float(val as f32);
+                Op::l2f => {
+                    let val = stack.pop_long();
+                    stack.push_float(val as f32);
                 }
-                Instruction::l2d => {
-                    let val = frame.stack.pop_long();
-                    frame.stack.push_double(val as f64);
+                Op::l2d => {
+                    let val = stack.pop_long();
+                    stack.push_double(val as f64);
                 }
-                Instruction::f2i => {
-                    let val = frame.stack.pop_float();
-                    frame.stack.push_int(val as i32);
+                Op::f2i => {
+                    let val = stack.pop_float();
+                    stack.push_int(val as i32);
                 }
-                Instruction::f2l => {
-                    let val = frame.stack.pop_float();
-                    frame.stack.push_long(val as i64);
+                Op::f2l => {
+                    let val = stack.pop_float();
+                    stack.push_long(val as i64);
                 }
-                Instruction::f2d => {
-                    let val = frame.stack.pop_float();
-                    frame.stack.push_double(val as f64);
+                Op::f2d => {
+                    let val = stack.pop_float();
+                    stack.push_double(val as f64);
                 }
-                Instruction::d2i => {
-                    let val = frame.stack.pop_double();
-                    frame.stack.push_int(val as i32);
+                Op::d2i => {
+                    let val = stack.pop_double();
+                    stack.push_int(val as i32);
                 }
-                Instruction::d2l => {
-                    let val = frame.stack.pop_double();
-                    frame.stack.push_long(val as i64);
+                Op::d2l => {
+                    let val = stack.pop_double();
+                    stack.push_long(val as i64);
                 }
-                Instruction::d2f => {
-                    let val = frame.stack.pop_double();
-                    frame.stack.push_float(val as f32);
+                Op::d2f => {
+                    let val = stack.pop_double();
+                    stack.push_float(val as f32);
                 }
-                Instruction::i2b => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_int((val as i8) as i32);
+                Op::i2b => {
+                    let val = stack.pop_int();
+                    stack.push_int((val as i8) as i32);
                 }
-                Instruction::i2c => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_int((val as u16) as i32);
+                Op::i2c => {
+                    let val = stack.pop_int();
+                    stack.push_int((val as u16) as i32);
                 }
-                Instruction::i2s => {
-                    let val = frame.stack.pop_int();
-                    frame.stack.push_int((val as i16) as i32);
+                Op::i2s => {
+                    let val = stack.pop_int();
+                    stack.push_int((val as i16) as i32);
                 }
 
                 // Comparison
-                Instruction::lcmp => {
-                    let val1 = frame.stack.pop_long();
-                    let val2 = frame.stack.pop_long();
+                Op::lcmp => {
+                    let val2 = stack.pop_long();
+                    let val1 = stack.pop_long();
                     let result = match val1.cmp(&val2) {
                         std::cmp::Ordering::Greater => 1,
                         std::cmp::Ordering::Equal => 0,
                         std::cmp::Ordering::Less => -1,
                     };
-                    frame.stack.push_int(result);
+                    stack.push_int(result);
                 }
-                Instruction::fcmp(greater_if_nan) => {
-                    let val1 = frame.stack.pop_float();
-                    let val2 = frame.stack.pop_float();
+                Op::fcmp(greater_if_nan) => {
+                    let val2 = stack.pop_float();
+                    let val1 = stack.pop_float();
                     let result = match val1.partial_cmp(&val2) {
                         Some(std::cmp::Ordering::Greater) => 1,
                         Some(std::cmp::Ordering::Equal) => 0,
@@ -291,11 +2342,11 @@ pub fn execute(classes: &ClassManager, main_class: &JavaStr) {
                             }
                         }
                     };
-                    frame.stack.push_int(result);
+                    stack.push_int(result);
                 }
-                Instruction::dcmp(less_if_nan) => {
-                    let val1 = frame.stack.pop_double();
-                    let val2 = frame.stack.pop_double();
+                Op::dcmp(less_if_nan) => {
+                    let val2 = stack.pop_double();
+                    let val1 = stack.pop_double();
                     let result = match val1.partial_cmp(&val2) {
                         Some(std::cmp::Ordering::Greater) => 1,
                         Some(std::cmp::Ordering::Equal) => 0,
@@ -308,189 +2359,431 @@ pub fn execute(classes: &ClassManager, main_class: &JavaStr) {
                             }
                         }
                     };
-                    frame.stack.push_int(result);
+                    stack.push_int(result);
+                }
+                Op::if_eq(target) => {
+                    let val = stack.pop_int();
+                    if val == 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
+                    }
+                }
+                Op::if_ne(target) => {
+                    let val = stack.pop_int();
+                    if val != 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
+                    }
                 }
-                Instruction::if_eq(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val == 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_lt(target) => {
+                    let val = stack.pop_int();
+                    if val < 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_ne(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val != 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_le(target) => {
+                    let val = stack.pop_int();
+                    if val <= 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_lt(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val < 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_gt(target) => {
+                    let val = stack.pop_int();
+                    if val > 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_le(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val <= 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_ge(target) => {
+                    let val = stack.pop_int();
+                    if val >= 0 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_gt(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val > 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_eq(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 == val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_ge(offset) => {
-                    let val = frame.stack.pop_int();
-                    if val >= 0 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_ne(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 != val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_eq(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 == val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_lt(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 < val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_ne(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 != val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_le(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 <= val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_lt(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 < val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_gt(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 > val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_le(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 <= val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_icmp_ge(target) => {
+                    let val2 = stack.pop_int();
+                    let val1 = stack.pop_int();
+                    if val1 >= val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_gt(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 > val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_acmp_eq(target) => {
+                    let val2 = stack.pop_reference();
+                    let val1 = stack.pop_reference();
+                    if val1 == val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
-                Instruction::if_icmp_ge(offset) => {
-                    let val2 = frame.stack.pop_int();
-                    let val1 = frame.stack.pop_int();
-                    if val1 >= val2 {
-                        frame.bytecode.set_pc(pc.wrapping_add_signed(offset as i32));
+                Op::if_acmp_ne(target) => {
+                    let val2 = stack.pop_reference();
+                    let val1 = stack.pop_reference();
+                    if val1 != val2 && jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
                     }
                 }
 
                 // Control
-                Instruction::goto(offset) => frame.bytecode.set_pc(pc.wrapping_add_signed(offset)),
-                Instruction::jsr(offset) => {
-                    frame.stack.push_ret_addr(frame.bytecode.pc());
-                    frame.bytecode.set_pc(pc.wrapping_add_signed(offset));
-                }
-                Instruction::ret(idx) => {
-                    let ret_addr = frame.locals.get_ret_addr(idx as usize);
-                    frame.bytecode.set_pc(ret_addr);
-                }
-                Instruction::tableswitch(switch) => {
-                    let idx = frame.stack.pop_int();
-                    let offset = switch.lookup(idx);
-                    frame.bytecode.set_pc(pc.wrapping_add_signed(offset));
-                }
-                Instruction::lookupswitch(switch) => {
-                    let key = frame.stack.pop_int();
-                    let offset = switch.lookup(key);
-                    frame.bytecode.set_pc(pc.wrapping_add_signed(offset));
-                }
-                Instruction::ireturn => {
-                    let ret_val = frame.stack.pop_int();
+                Op::goto(target) => {
+                    if jump(&mut frame.pc, code, target, threshold, &mut budget) {
+                        break 'method;
+                    }
+                }
+                Op::jsr(target) => {
+                    stack.push_ret_addr(frame.pc as u32);
+                    frame.pc = target as usize;
+                }
+                Op::ret(idx) => {
+                    let ret_addr = locals.get_ret_addr(idx as usize);
+                    frame.pc = ret_addr as usize;
+                }
+                Op::tableswitch(switch) => {
+                    let idx = stack.pop_int();
+                    frame.pc = code.table_switch(switch).target(idx) as usize;
+                }
+                Op::lookupswitch(switch) => {
+                    let key = stack.pop_int();
+                    frame.pc = code.lookup_switch(switch).target(key) as usize;
+                }
+                Op::ireturn => {
+                    let ret_val = stack.pop_int();
                     call_stack.pop();
-
-                    let invoker_frame = call_stack.last_mut().unwrap();
-                    invoker_frame.stack.push_int(ret_val);
+                    push_result(thread, call_stack, Value::Int(ret_val));
                     break 'method;
                 }
-                Instruction::lreturn => {
-                    let ret_val = frame.stack.pop_long();
+                Op::lreturn => {
+                    let ret_val = stack.pop_long();
                     call_stack.pop();
-
-                    let invoker_frame = call_stack.last_mut().unwrap();
-                    invoker_frame.stack.push_long(ret_val);
+                    push_result(thread, call_stack, Value::Long(ret_val));
                     break 'method;
                 }
-                Instruction::freturn => {
-                    let ret_val = frame.stack.pop_float();
+                Op::freturn => {
+                    let ret_val = stack.pop_float();
                     call_stack.pop();
-
-                    let invoker_frame = call_stack.last_mut().unwrap();
-                    invoker_frame.stack.push_float(ret_val);
+                    push_result(thread, call_stack, Value::Float(ret_val));
                     break 'method;
                 }
-                Instruction::dreturn => {
-                    let ret_val = frame.stack.pop_double();
+                Op::dreturn => {
+                    let ret_val = stack.pop_double();
                     call_stack.pop();
-
-                    let invoker_frame = call_stack.last_mut().unwrap();
-                    invoker_frame.stack.push_double(ret_val);
+                    push_result(thread, call_stack, Value::Double(ret_val));
                     break 'method;
                 }
-                Instruction::ret_void => {
+                Op::areturn => {
+                    let ret_val = stack.pop_reference();
+                    call_stack.pop();
+                    push_result(thread, call_stack, Value::Reference(ret_val));
+                    break 'method;
+                }
+                Op::ret_void => {
                     call_stack.pop();
                     break 'method;
                 }
 
                 // Reference
-                Instruction::invokestatic(idx) => {
-                    let (class, name_type) = frame.constants.get(idx).into_ref();
-                    let class = frame.constants.get(class).into_class();
+                Op::getstatic(_)
+                | Op::putstatic(_)
+                | Op::getfield(_)
+                | Op::putfield(_)
+                | Op::invokevirtual(_)
+                | Op::invokespecial(_)
+                | Op::invokestatic(_)
+                | Op::invokeinterface(..)
+                | Op::new(_)
+                | Op::newarray(_)
+                | Op::anewarray(_)
+                | Op::checkcast(_)
+                | Op::instanceof(_) => {
+                    frame.pc -= 1;
+                    let (quick, class) = classes.quicken(frame.class, code, frame.pc, op)?;
+                    code.quicken(frame.pc, quick);
 
-                    // We should be checking that the class is initialized, and if not, then
-                    // initializing it. Right now we assume it to be already initialized. This does
-                    // mean that static fields don't work.
-                    let class_name = frame.constants.get(class).into_utf8();
+                    // The class which declares the member, or which is
+                    // instantiated, is initialized before the quickened
+                    // instruction is executed.
+                    if let Some(class) = class {
+                        let class = class as usize;
+                        if !classes.classes[class].initialized.get() {
+                            classes.initialize(class, call_stack)?;
+                            break 'method;
+                        }
+                    }
+                }
 
-                    let (method_name, method_descriptor) =
-                        frame.constants.get(name_type).into_name_type();
-                    let method_name = frame.constants.get(method_name).into_utf8();
-                    let method_descriptor = frame.constants.get(method_descriptor).into_utf8();
+                // Extended
+                Op::iinc(idx, constant) => {
+                    let value = locals.get_int(idx as usize);
+                    locals.set_int(idx as usize, value.wrapping_add(constant as i32));
+                }
 
-                    let class = classes
-                        .get(class_name)
-                        .expect("All classes which are to be used should be already loaded");
-                    let method = class.get_method(method_name, method_descriptor);
+                Op::ifnonnull(target) => {
+                    if stack.pop_reference().is_some()
+                        && jump(&mut frame.pc, code, target, threshold, &mut budget)
+                    {
+                        break 'method;
+                    }
+                }
+                Op::ifnull(target) => {
+                    if stack.pop_reference().is_none()
+                        && jump(&mut frame.pc, code, target, threshold, &mut budget)
+                    {
+                        break 'method;
+                    }
+                }
 
-                    let mut invoked_frame =
-                        CallFrame::new(class.constants(), method.bytecode().expect("This instruction calls static methods and static methods must have a Code attribute"));
+                // Quick
+                Op::getstatic_quick(field) => {
+                    let statics = &classes.classes[field.class as usize].statics;
+                    let value = statics[field.field as usize].get();
+                    let Some(value) = value else {
+                        return Err(ExecuteError::string_constant());
+                    };
+                    stack.push(value);
+                }
+                Op::putstatic_quick(field, value_type) => {
+                    let statics = &classes.classes[field.class as usize].statics;
+                    let value = stack.pop(value_type);
+                    statics[field.field as usize].set(Some(value));
+                    heap.record_static(field.class, value);
+                }
+                Op::getfield_quick(slot) => {
+                    let Some(object) = stack.pop_reference() else {
+                        return Err(null_pointer());
+                    };
+                    stack.push(heap.get(object).fields[slot as usize]);
+                }
+                Op::putfield_quick(slot, value_type) => {
+                    let value = stack.pop(value_type);
+                    let Some(object) = stack.pop_reference() else {
+                        return Err(null_pointer());
+                    };
+                    heap.set_field(object, slot as usize, value);
+                }
+                Op::invokevirtual_quick(call) => {
+                    let Some(receiver) = stack.peek_reference(call.slots as usize - 1) else {
+                        return Err(null_pointer());
+                    };
+                    let class = heap.get(receiver).class;
+                    let cache = code.inline_cache(call.cache);
+                    let method = match cache.get() {
+                        Some(cached) if cached.class == class => cached.target,
+                        _ => {
+                            let target = classes.linked(class).vtable[call.index as usize];
+                            cache.set(Some(InlineCache { class, target }));
+                            target
+                        }
+                    };
 
-                    // Load method arguments into invoked method's frame
-                    let mut i = 0;
-                    for _ in method.parsed_descriptor().args() {
-                        let value = frame.stack.pop();
-                        invoked_frame.locals.set(i, value);
-                        i += value.size();
+                    // The native methods of libraries run without a frame.
+                    #[cfg(feature = "jni")]
+                    if classes.is_jni(method) {
+                        request = Some(Request::Jni(method));
+                        break 'method;
+                    }
+                    let invoked_frame = classes.invoke(&mut stack, method, call.slots as usize)?;
+                    call_stack.push(invoked_frame);
+                    break 'method;
+                }
+                Op::invokespecial_quick(method) => {
+                    let slots = classes
+                        .method_info(method)
+                        .parsed_descriptor()
+                        .arg_slots(false);
+                    if stack.peek_reference(slots - 1).is_none() {
+                        return Err(null_pointer());
                     }
 
+                    let invoked_frame = classes.invoke(&mut stack, method, slots)?;
                     call_stack.push(invoked_frame);
                     break 'method;
                 }
+                Op::invokestatic_quick(method) => {
+                    let slots = classes
+                        .method_info(method)
+                        .parsed_descriptor()
+                        .arg_slots(true);
+                    let invoked_frame = classes.invoke(&mut stack, method, slots)?;
+                    call_stack.push(invoked_frame);
+                    break 'method;
+                }
+                Op::invokeinterface_quick(call) => {
+                    let Some(receiver) = stack.peek_reference(call.slots as usize - 1) else {
+                        return Err(null_pointer());
+                    };
+                    let class = heap.get(receiver).class;
+                    let cache = code.inline_cache(call.cache);
+                    let method = match cache.get() {
+                        Some(cached) if cached.class == class => cached.target,
+                        _ => {
+                            // A class which does not implement the interface
+                            // has no table for it, and a method has no
+                            // selection if several defaults conflict.
+                            let itable = classes.linked(class).itables.get(&call.interface);
+                            let Some(target) =
+                                itable.and_then(|itable| itable[call.method as usize])
+                            else {
+                                return Err(LinkageError::IncompatibleClassChange.into());
+                            };
+                            cache.set(Some(InlineCache { class, target }));
+                            target
+                        }
+                    };
 
-                // Extended
-                Instruction::iinc(idx, constant) => {
-                    let value = frame.locals.get_int(idx as usize);
-                    frame
-                        .locals
-                        .set_int(idx as usize, value.wrapping_add(constant as i32));
+                    #[cfg(feature = "jni")]
+                    if classes.is_jni(method) {
+                        request = Some(Request::Jni(method));
+                        break 'method;
+                    }
+                    let invoked_frame = classes.invoke(&mut stack, method, call.slots as usize)?;
+                    call_stack.push(invoked_frame);
+                    break 'method;
+                }
+                Op::new_quick(class) => {
+                    match classes.instantiate(heap, class) {
+                        Ok(object) => stack.push_reference(Some(object)),
+                        // The instruction runs again once the heap has been
+                        // collected.
+                        Err(AllocError::Full) => {
+                            frame.pc -= 1;
+                            collect = Some(gc::Cause::Allocation);
+                            break 'method;
+                        }
+                        Err(AllocError::OutOfMemory) => return Err(ExecuteError::OutOfMemory),
+                    }
+                }
+                Op::newarray_quick(class) => {
+                    let length = stack.pop_int();
+                    let Ok(length) = usize::try_from(length) else {
+                        return Err(negative_array_size(length));
+                    };
+                    match classes.new_array(heap, class, length) {
+                        Ok(array) => stack.push_reference(Some(array)),
+                        Err(AllocError::Full) => {
+                            stack.push_int(length as i32);
+                            frame.pc -= 1;
+                            collect = Some(gc::Cause::Allocation);
+                            break 'method;
+                        }
+                        Err(AllocError::OutOfMemory) => return Err(ExecuteError::OutOfMemory),
+                    }
+                }
+                Op::arraylength => {
+                    let Some(array) = stack.pop_reference() else {
+                        return Err(null_pointer());
+                    };
+                    stack.push_int(heap.get(array).fields.len() as i32);
+                }
+                Op::ldc_class_quick(class) => match classes.mirror(heap, class) {
+                    Ok(mirror) => stack.push_reference(Some(mirror)),
+                    Err(AllocError::Full) => {
+                        frame.pc -= 1;
+                        collect = Some(gc::Cause::Allocation);
+                        break 'method;
+                    }
+                    Err(AllocError::OutOfMemory) => return Err(ExecuteError::OutOfMemory),
+                },
+                Op::checkcast_quick(target) => {
+                    if let Some(object) = stack.peek_reference(0) {
+                        let class = heap.get(object).class as usize;
+                        if !classes.is_subclass(class, target as usize) {
+                            return Err(class_cast());
+                        }
+                    }
+                }
+                Op::instanceof_quick(target) => {
+                    let object = stack.pop_reference();
+                    let is_instance = object.is_some_and(|object| {
+                        let class = heap.get(object).class as usize;
+                        classes.is_subclass(class, target as usize)
+                    });
+                    stack.push_int(is_instance as i32);
+                }
+                // The methods of the host and of `Unsafe`, and those loading
+                // libraries, run without leaving the frame, while those of
+                // native libraries run once it stopped, as they may call Java
+                // methods.
+                Op::invokenative(NativeMethod::Host(method)) => {
+                    classes.invoke_host(method, &mut stack, heap)?
+                }
+                #[cfg(feature = "jni")]
+                Op::invokenative(NativeMethod::Jni(method)) => {
+                    request = Some(Request::Jni(method));
+                    break 'method;
+                }
+                #[cfg(feature = "jni")]
+                Op::invokenative(
+                    native @ (NativeMethod::SystemLoad | NativeMethod::SystemLoadLibrary),
+                ) => classes.invoke_load(native, &mut stack, heap)?,
+                Op::invokenative(method) => {
+                    if !method.invoke_unsafe(classes, &mut stack, heap)? {
+                        match method {
+                            NativeMethod::SystemGc => collect = Some(gc::Cause::SystemGc),
+                            _ => request = Some(Request::Native(method)),
+                        }
+                        break 'method;
+                    }
                 }
 
-                inst => todo!("{inst:?} not implemented: no support for objects"),
+                // The scheduler enters and exits the monitors which are not
+                // thin locks of the running thread.
+                Op::monitorenter => {
+                    let Some(object) = stack.peek_reference(0) else {
+                        return Err(null_pointer());
+                    };
+                    if heap.get_mut(object).lock.try_enter(thread_id) {
+                        stack.pop_reference();
+                    } else {
+                        request = Some(Request::Enter);
+                        break 'method;
+                    }
+                }
+                Op::monitorexit => {
+                    let Some(object) = stack.peek_reference(0) else {
+                        return Err(null_pointer());
+                    };
+                    if heap.get_mut(object).lock.try_exit(thread_id) {
+                        stack.pop_reference();
+                    } else {
+                        request = Some(Request::Exit);
+                        break 'method;
+                    }
+                }
+
+                _ => return Err(unimplemented(op)),
             }
         }
     }
+
+    Ok(None)
 }
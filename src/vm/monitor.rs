@@ -0,0 +1,168 @@
+//! The monitors of objects and classes, which `synchronized` methods and
+//! blocks enter and `Object.wait` and `notify` wait on.
+//!
+//! A monitor only entered by one thread at a time is a thin lock: the lock
+//! word of its object holds the thread which owns it and how many times it
+//! entered it, which the bytecode interpreter updates on its own. Once
+//! another thread tries to enter it, a thread waits on it or the count
+//! overflows, it is inflated into a [`Monitor`] of the scheduler, which the
+//! lock word then holds the index of. It is deflated again once it is free
+//! and no thread is blocked on it.
+
+use std::collections::VecDeque;
+
+use super::heap::ObjectRef;
+
+/// The state of the monitor of an object or class, packed in 32 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct LockWord(u32);
+
+/// The state a [`LockWord`] holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Lock {
+    Unlocked,
+    /// Owned by a thread which entered it `count` times.
+    Thin {
+        owner: u32,
+        count: u32,
+    },
+    /// Held by the monitor at an index of the scheduler.
+    Inflated(u32),
+}
+
+impl LockWord {
+    pub(super) const UNLOCKED: Self = Self(0);
+    const INFLATED: u32 = 1 << 31;
+    const COUNT_BITS: u32 = 8;
+    const MAX_COUNT: u32 = (1 << Self::COUNT_BITS) - 1;
+    /// The largest id of a thread which can own a thin lock.
+    const MAX_OWNER: u32 = (Self::INFLATED >> Self::COUNT_BITS) - 1;
+
+    pub(super) fn inflated(monitor: u32) -> Self {
+        Self(Self::INFLATED | monitor)
+    }
+
+    fn thin(owner: u32, count: u32) -> Self {
+        match count {
+            0 => Self::UNLOCKED,
+            _ => Self(owner << Self::COUNT_BITS | count),
+        }
+    }
+
+    pub(super) fn get(self) -> Lock {
+        if self.0 & Self::INFLATED != 0 {
+            return Lock::Inflated(self.0 & !Self::INFLATED);
+        }
+        match self.0 & Self::MAX_COUNT {
+            0 => Lock::Unlocked,
+            count => Lock::Thin {
+                owner: self.0 >> Self::COUNT_BITS,
+                count,
+            },
+        }
+    }
+
+    /// Enters a thin lock for `thread`, and returns `false` if the lock must
+    /// be inflated first.
+    #[inline]
+    pub(super) fn try_enter(&mut self, thread: u32) -> bool {
+        match self.get() {
+            Lock::Unlocked if thread <= Self::MAX_OWNER => *self = Self::thin(thread, 1),
+            Lock::Thin { owner, count } if owner == thread && count < Self::MAX_COUNT => {
+                *self = Self::thin(owner, count + 1)
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Exits a thin lock `thread` owns, and returns `false` if the lock is
+    /// inflated or owned by another thread.
+    #[inline]
+    pub(super) fn try_exit(&mut self, thread: u32) -> bool {
+        match self.get() {
+            Lock::Thin { owner, count } if owner == thread => {
+                *self = Self::thin(owner, count - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What a monitor belongs to: an object, or a class for its `static
+/// synchronized` methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LockTarget {
+    Object(ObjectRef),
+    Class(u32),
+}
+
+/// Where a frame of a `synchronized` method is with its monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FrameMonitor {
+    /// The frame has not entered a monitor, which is the case of the frames
+    /// of the other methods.
+    Unentered,
+    Entered(LockTarget),
+    /// The frame exited its monitor, and returns.
+    Exited,
+}
+
+/// An inflated monitor.
+#[derive(Debug, Default)]
+pub(super) struct Monitor {
+    /// The thread which owns the monitor and the number of times it entered
+    /// it.
+    pub(super) owner: Option<(u32, u32)>,
+    /// The threads waiting on the monitor to be notified, in the order they
+    /// started waiting.
+    pub(super) waiters: VecDeque<u32>,
+}
+
+/// The inflated monitors, indexed by the lock words holding them.
+#[derive(Debug, Default)]
+pub(super) struct Monitors {
+    monitors: Vec<Monitor>,
+    /// The indices of the monitors which have been deflated.
+    free: Vec<u32>,
+}
+
+impl Monitors {
+    /// Inflates the lock word `lock` into a monitor with the same owner,
+    /// unless it is inflated already, and returns the index of the monitor.
+    pub(super) fn inflate(&mut self, lock: &mut LockWord) -> u32 {
+        let owner = match lock.get() {
+            Lock::Inflated(index) => return index,
+            Lock::Unlocked => None,
+            Lock::Thin { owner, count } => Some((owner, count)),
+        };
+        let monitor = Monitor {
+            owner,
+            waiters: VecDeque::new(),
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.monitors[index as usize] = monitor;
+                index
+            }
+            None => {
+                self.monitors.push(monitor);
+                self.monitors.len() as u32 - 1
+            }
+        };
+        *lock = LockWord::inflated(index);
+        index
+    }
+
+    /// Deflates the free monitor at `index`, which `lock` holds.
+    pub(super) fn deflate(&mut self, index: u32, lock: &mut LockWord) {
+        debug_assert!(self.monitors[index as usize].owner.is_none());
+        *lock = LockWord::UNLOCKED;
+        self.free.push(index);
+    }
+
+    pub(super) fn get_mut(&mut self, index: u32) -> &mut Monitor {
+        &mut self.monitors[index as usize]
+    }
+}
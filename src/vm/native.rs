@@ -0,0 +1,462 @@
+//! The native methods the virtual machine implements itself.
+//!
+//! The methods of `sun.misc.Unsafe` access the fields of objects at offsets
+//! which are the indices of their slots in [`Object::fields`]. Since threads
+//! never run at the same time, their volatile accesses and compare-and-swaps
+//! are plain accesses of the slots. `objectFieldOffset` finds the slot of a
+//! `java.lang.reflect.Field` from the `java.lang.Class` object in its `clazz`
+//! field and the index among the fields its class declares in its `slot`
+//! field.
+//!
+//! [`Object::fields`]: super::heap::Object::fields
+
+use super::call_frame::Stack;
+use super::convert::JavaHeap;
+use super::decode::MethodId;
+use super::heap::{Heap, ObjectRef};
+use super::value::{Value, ValueType};
+use super::{ClassManager, ExecuteError, JavaException, LinkageError};
+use crate::java_str;
+use crate::string::JavaStr;
+
+/// A native method implemented by the virtual machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NativeMethod {
+    /// `java.lang.System.gc()`, which runs the garbage collector.
+    SystemGc,
+    /// `java.lang.System.runFinalization()`, which waits for the finalizer
+    /// thread to run the finalizers of the objects found unreachable.
+    SystemRunFinalization,
+    /// `java.lang.System.load(String)`, which loads the native library at an
+    /// absolute path.
+    #[cfg(feature = "jni")]
+    SystemLoad,
+    /// `java.lang.System.loadLibrary(String)`, which loads a native library
+    /// found in the library path.
+    #[cfg(feature = "jni")]
+    SystemLoadLibrary,
+    /// `java.lang.Thread.start0()`, which starts a thread running the `run`
+    /// method of the receiver.
+    ThreadStart,
+    /// `java.lang.Thread.currentThread()`.
+    ThreadCurrentThread,
+    /// `java.lang.Thread.yield()`, which lets the other threads run first.
+    ThreadYield,
+    /// `java.lang.Thread.sleep(long)`.
+    ThreadSleep,
+    /// `java.lang.Thread.join(long)`, which waits for the receiver to
+    /// terminate, for at most a number of milliseconds unless it is zero.
+    ThreadJoin,
+    /// `java.lang.Thread.isAlive()`.
+    ThreadIsAlive,
+    /// `java.lang.Thread.interrupt0()`, which sets the interrupt status of the
+    /// receiver and wakes it if it sleeps or waits.
+    ThreadInterrupt,
+    /// `java.lang.Thread.isInterrupted(boolean)`, which returns the interrupt
+    /// status of the receiver and clears it if the argument is `true`.
+    ThreadIsInterrupted,
+    /// `java.lang.Object.wait(long)`, which exits the monitor of the receiver
+    /// until notified, for at most a number of milliseconds unless it is
+    /// zero, and enters it again.
+    ObjectWait,
+    /// `java.lang.Object.notify()`, which wakes up a thread waiting on the
+    /// monitor of the receiver.
+    ObjectNotify,
+    /// `java.lang.Object.notifyAll()`, which wakes up every thread waiting on
+    /// the monitor of the receiver.
+    ObjectNotifyAll,
+    /// `sun.misc.Unsafe.compareAndSwapInt(Object, long, int, int)`, which
+    /// sets an `int` field to the last argument if it holds the one before,
+    /// and returns whether it did.
+    UnsafeCompareAndSwapInt,
+    /// `sun.misc.Unsafe.compareAndSwapLong(Object, long, long, long)`.
+    UnsafeCompareAndSwapLong,
+    /// `sun.misc.Unsafe.compareAndSwapObject(Object, long, Object, Object)`,
+    /// which compares references by identity.
+    UnsafeCompareAndSwapObject,
+    /// `sun.misc.Unsafe.getIntVolatile(Object, long)`.
+    UnsafeGetIntVolatile,
+    /// `sun.misc.Unsafe.putIntVolatile(Object, long, int)`.
+    UnsafePutIntVolatile,
+    /// `sun.misc.Unsafe.getLongVolatile(Object, long)`.
+    UnsafeGetLongVolatile,
+    /// `sun.misc.Unsafe.putLongVolatile(Object, long, long)`.
+    UnsafePutLongVolatile,
+    /// `sun.misc.Unsafe.getObjectVolatile(Object, long)`.
+    UnsafeGetObjectVolatile,
+    /// `sun.misc.Unsafe.putObjectVolatile(Object, long, Object)`.
+    UnsafePutObjectVolatile,
+    /// `sun.misc.Unsafe.getUnsafe()`, which returns the instance in the
+    /// static field `theUnsafe`.
+    UnsafeGetUnsafe,
+    /// `sun.misc.Unsafe.objectFieldOffset(Field)`, which returns the index of
+    /// the slot of an instance field.
+    UnsafeObjectFieldOffset,
+    /// `sun.misc.Unsafe.arrayBaseOffset(Class)`, which is zero as elements
+    /// are indexed like slots.
+    UnsafeArrayBaseOffset,
+    /// `sun.misc.Unsafe.arrayIndexScale(Class)`, which is one for the same
+    /// reason.
+    UnsafeArrayIndexScale,
+    /// A method of a class defined by the host, which a closure implements.
+    Host(MethodId),
+    /// A method implemented by a native library.
+    #[cfg(feature = "jni")]
+    Jni(MethodId),
+}
+
+/// The class, name and descriptor of each native method.
+const NATIVES: &[(&JavaStr, &JavaStr, &JavaStr, NativeMethod)] = &[
+    (
+        java_str!("java/lang/System"),
+        java_str!("gc"),
+        java_str!("()V"),
+        NativeMethod::SystemGc,
+    ),
+    (
+        java_str!("java/lang/System"),
+        java_str!("runFinalization"),
+        java_str!("()V"),
+        NativeMethod::SystemRunFinalization,
+    ),
+    #[cfg(feature = "jni")]
+    (
+        java_str!("java/lang/System"),
+        java_str!("load"),
+        java_str!("(Ljava/lang/String;)V"),
+        NativeMethod::SystemLoad,
+    ),
+    #[cfg(feature = "jni")]
+    (
+        java_str!("java/lang/System"),
+        java_str!("loadLibrary"),
+        java_str!("(Ljava/lang/String;)V"),
+        NativeMethod::SystemLoadLibrary,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("start0"),
+        java_str!("()V"),
+        NativeMethod::ThreadStart,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("currentThread"),
+        java_str!("()Ljava/lang/Thread;"),
+        NativeMethod::ThreadCurrentThread,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("yield"),
+        java_str!("()V"),
+        NativeMethod::ThreadYield,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("sleep"),
+        java_str!("(J)V"),
+        NativeMethod::ThreadSleep,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("join"),
+        java_str!("(J)V"),
+        NativeMethod::ThreadJoin,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("isAlive"),
+        java_str!("()Z"),
+        NativeMethod::ThreadIsAlive,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("interrupt0"),
+        java_str!("()V"),
+        NativeMethod::ThreadInterrupt,
+    ),
+    (
+        java_str!("java/lang/Thread"),
+        java_str!("isInterrupted"),
+        java_str!("(Z)Z"),
+        NativeMethod::ThreadIsInterrupted,
+    ),
+    (
+        java_str!("java/lang/Object"),
+        java_str!("wait"),
+        java_str!("(J)V"),
+        NativeMethod::ObjectWait,
+    ),
+    (
+        java_str!("java/lang/Object"),
+        java_str!("notify"),
+        java_str!("()V"),
+        NativeMethod::ObjectNotify,
+    ),
+    (
+        java_str!("java/lang/Object"),
+        java_str!("notifyAll"),
+        java_str!("()V"),
+        NativeMethod::ObjectNotifyAll,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("compareAndSwapInt"),
+        java_str!("(Ljava/lang/Object;JII)Z"),
+        NativeMethod::UnsafeCompareAndSwapInt,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("compareAndSwapLong"),
+        java_str!("(Ljava/lang/Object;JJJ)Z"),
+        NativeMethod::UnsafeCompareAndSwapLong,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("compareAndSwapObject"),
+        java_str!("(Ljava/lang/Object;JLjava/lang/Object;Ljava/lang/Object;)Z"),
+        NativeMethod::UnsafeCompareAndSwapObject,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("getIntVolatile"),
+        java_str!("(Ljava/lang/Object;J)I"),
+        NativeMethod::UnsafeGetIntVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("putIntVolatile"),
+        java_str!("(Ljava/lang/Object;JI)V"),
+        NativeMethod::UnsafePutIntVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("getLongVolatile"),
+        java_str!("(Ljava/lang/Object;J)J"),
+        NativeMethod::UnsafeGetLongVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("putLongVolatile"),
+        java_str!("(Ljava/lang/Object;JJ)V"),
+        NativeMethod::UnsafePutLongVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("getObjectVolatile"),
+        java_str!("(Ljava/lang/Object;J)Ljava/lang/Object;"),
+        NativeMethod::UnsafeGetObjectVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("putObjectVolatile"),
+        java_str!("(Ljava/lang/Object;JLjava/lang/Object;)V"),
+        NativeMethod::UnsafePutObjectVolatile,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("getUnsafe"),
+        java_str!("()Lsun/misc/Unsafe;"),
+        NativeMethod::UnsafeGetUnsafe,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("objectFieldOffset"),
+        java_str!("(Ljava/lang/reflect/Field;)J"),
+        NativeMethod::UnsafeObjectFieldOffset,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("arrayBaseOffset"),
+        java_str!("(Ljava/lang/Class;)I"),
+        NativeMethod::UnsafeArrayBaseOffset,
+    ),
+    (
+        java_str!("sun/misc/Unsafe"),
+        java_str!("arrayIndexScale"),
+        java_str!("(Ljava/lang/Class;)I"),
+        NativeMethod::UnsafeArrayIndexScale,
+    ),
+];
+
+impl NativeMethod {
+    /// Returns the native method with the given class, name and descriptor,
+    /// if the virtual machine implements it.
+    pub(super) fn find(class: &JavaStr, name: &JavaStr, descriptor: &JavaStr) -> Option<Self> {
+        NATIVES
+            .iter()
+            .find(|native| native.0 == class && native.1 == name && native.2 == descriptor)
+            .map(|native| native.3)
+    }
+
+    /// Runs a method of `sun.misc.Unsafe` whose receiver and arguments are on
+    /// top of `stack`. Returns `false` if `self` is another native method,
+    /// and leaves `stack` untouched then.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an object passed is null, if the method accesses
+    /// an offset at which the object has no field of the type it accesses, or
+    /// if the offset of a static field is asked for.
+    pub(super) fn invoke_unsafe(
+        self,
+        classes: &ClassManager,
+        stack: &mut Stack,
+        heap: &mut Heap,
+    ) -> Result<bool, ExecuteError> {
+        let value_type = match self {
+            NativeMethod::UnsafeCompareAndSwapInt
+            | NativeMethod::UnsafeGetIntVolatile
+            | NativeMethod::UnsafePutIntVolatile => ValueType::Int,
+            NativeMethod::UnsafeCompareAndSwapLong
+            | NativeMethod::UnsafeGetLongVolatile
+            | NativeMethod::UnsafePutLongVolatile => ValueType::Long,
+            NativeMethod::UnsafeCompareAndSwapObject
+            | NativeMethod::UnsafeGetObjectVolatile
+            | NativeMethod::UnsafePutObjectVolatile => ValueType::Reference,
+            NativeMethod::UnsafeGetUnsafe => {
+                let class = classes.find(java_str!("sun/misc/Unsafe"))?;
+                let value = static_field(
+                    classes,
+                    class,
+                    java_str!("theUnsafe"),
+                    java_str!("Lsun/misc/Unsafe;"),
+                )?;
+                stack.push(value);
+                return Ok(true);
+            }
+            NativeMethod::UnsafeObjectFieldOffset => {
+                let field = stack.pop_reference().ok_or(JavaException::NullPointer)?;
+                stack.pop_reference();
+                let offset = object_field_offset(classes, heap, field)?;
+                stack.push_long(offset);
+                return Ok(true);
+            }
+            NativeMethod::UnsafeArrayBaseOffset | NativeMethod::UnsafeArrayIndexScale => {
+                stack.pop_reference();
+                stack.pop_reference();
+                stack.push_int((self == NativeMethod::UnsafeArrayIndexScale) as i32);
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+        let is_get = matches!(
+            self,
+            NativeMethod::UnsafeGetIntVolatile
+                | NativeMethod::UnsafeGetLongVolatile
+                | NativeMethod::UnsafeGetObjectVolatile
+        );
+        let is_swap = matches!(
+            self,
+            NativeMethod::UnsafeCompareAndSwapInt
+                | NativeMethod::UnsafeCompareAndSwapLong
+                | NativeMethod::UnsafeCompareAndSwapObject
+        );
+        let update = (!is_get).then(|| stack.pop(value_type));
+        let expected = is_swap.then(|| stack.pop(value_type));
+        let offset = stack.pop_long();
+        let object = stack.pop_reference().ok_or(JavaException::NullPointer)?;
+        // The receiver is the instance of `Unsafe`, which holds nothing.
+        stack.pop_reference();
+
+        let fields = &heap.get(object).fields;
+        let current = usize::try_from(offset)
+            .ok()
+            .and_then(|index| Some((index, *fields.get(index)?)))
+            .filter(|(_, current)| current.value_type() == value_type);
+        let Some((index, current)) = current else {
+            return Err(ExecuteError::UnsafeAccess { offset, value_type });
+        };
+        let matches = match (current, expected) {
+            (Value::Int(current), Some(Value::Int(expected))) => current == expected,
+            (Value::Long(current), Some(Value::Long(expected))) => current == expected,
+            (Value::Reference(current), Some(Value::Reference(expected))) => current == expected,
+            _ => true,
+        };
+
+        match update {
+            None => stack.push(current),
+            Some(update) => {
+                if matches {
+                    heap.set_field(object, index, update);
+                }
+                if is_swap {
+                    (stack.push_as(&matches, &mut JavaHeap::new(classes, heap)))
+                        .expect("a boolean converts");
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the index of the slot of the field which the
+/// `java.lang.reflect.Field` object `field` reflects.
+fn object_field_offset(
+    classes: &ClassManager,
+    heap: &Heap,
+    field: ObjectRef,
+) -> Result<i64, ExecuteError> {
+    let Value::Reference(mirror) = instance_field(
+        classes,
+        heap,
+        field,
+        java_str!("clazz"),
+        java_str!("Ljava/lang/Class;"),
+    )?
+    else {
+        unreachable!("a reference field holds a reference");
+    };
+    let Value::Int(slot) = instance_field(classes, heap, field, java_str!("slot"), java_str!("I"))?
+    else {
+        unreachable!("an int field holds an int");
+    };
+    let mirror = mirror.ok_or(JavaException::NullPointer)?;
+    let class = classes
+        .mirrored(mirror)
+        .expect("the class of a field has a java.lang.Class object");
+    let slots = &classes.link(class)?.field_slots;
+    let slot = usize::try_from(slot)
+        .ok()
+        .and_then(|slot| slots.get(slot).copied())
+        .flatten()
+        .ok_or(JavaException::StaticFieldOffset)?;
+    Ok(slot.into())
+}
+
+/// Returns the value of the instance field of `object` with the given name
+/// and descriptor.
+fn instance_field(
+    classes: &ClassManager,
+    heap: &Heap,
+    object: ObjectRef,
+    name: &JavaStr,
+    descriptor: &JavaStr,
+) -> Result<Value, LinkageError> {
+    let class = heap.get(object).class as usize;
+    let slot = classes.field_slot(class, name, descriptor)?;
+    Ok(heap.get(object).fields[slot])
+}
+
+/// Returns the value of the static field of the class at `class` with the
+/// given name and descriptor.
+fn static_field(
+    classes: &ClassManager,
+    class: usize,
+    name: &JavaStr,
+    descriptor: &JavaStr,
+) -> Result<Value, LinkageError> {
+    let no_such_field = || LinkageError::NoSuchField {
+        class: classes.classes[class].class.name().to_owned(),
+        name: name.to_owned(),
+        descriptor: descriptor.to_owned(),
+    };
+    let field = classes
+        .resolve_field(class, name, descriptor)?
+        .ok_or_else(no_such_field)?;
+    // Only `String` constants have no value, which `theUnsafe` is not.
+    let statics = &classes.classes[field.class as usize].statics;
+    Ok(statics[field.field as usize]
+        .get()
+        .unwrap_or(Value::Reference(None)))
+}
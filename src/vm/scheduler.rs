@@ -0,0 +1,784 @@
+//! Java threads, which run one at a time on the native thread executing the
+//! program.
+//!
+//! Each thread owns a [`ThreadStack`] and a call stack of its own. The running
+//! thread is preempted once it has used up its time slice, which every tier
+//! counts down at backward jumps and calls, so that a thread spinning in a
+//! loop cannot keep the others from running. Threads only switch where their
+//! frames are left as the bytecode interpreter would leave them, so that the
+//! garbage collector finds the references held by every thread.
+//!
+//! As threads only switch between instructions, every access to a field is
+//! sequentially consistent, which is stronger than the Java memory model asks
+//! of `volatile` fields: a write is seen by every read which runs after it,
+//! `long` and `double` fields are never torn, and the `final` fields set by a
+//! constructor are seen by any thread which finds the object.
+//!
+//! The scheduler also holds the inflated monitors, which threads block on
+//! until they can enter them.
+//!
+//! The host waits for the threads it starts to return from their first
+//! frame, and a native method for the frames of the Java methods it calls to
+//! return. As each waits on the native stack, a thread which has returned to
+//! one that is not the innermost does not run until the ones above it have
+//! returned in turn.
+//!
+//! The finalizer thread is a daemon thread without a `java.lang.Thread`
+//! object, which the first collection finding objects to finalize or
+//! references to enqueue starts. Once it has returned from the calls it
+//! made, it waits until a collection finds more.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::call_frame::{get_reference, set_reference, CallFrame, Stack, ThreadStack};
+use super::class::MethodFlags;
+use super::convert::JavaHeap;
+use super::decode::MethodId;
+use super::heap::{Heap, ObjectRef};
+use super::monitor::{FrameMonitor, Lock, LockTarget, LockWord, Monitors};
+use super::native::NativeMethod;
+use super::value::Value;
+use super::{ClassManager, ExecuteError, JavaException, LinkageError};
+use crate::java_str;
+
+/// The number of backward jumps and calls a thread runs before the next
+/// thread gets to run.
+pub(super) const TIME_SLICE: i32 = 10_000;
+
+/// A thread of the program, which has started and not terminated yet.
+#[derive(Debug)]
+pub(super) struct JavaThread<'a> {
+    pub(super) stack: ThreadStack,
+    pub(super) call_stack: Vec<CallFrame<'a>>,
+    /// The `java.lang.Thread` object of the thread, which the threads started
+    /// by the host have none of if the class is not loaded.
+    pub(super) object: Option<ObjectRef>,
+    /// Identifies the thread for those joining it, as its object may move,
+    /// and as the owner of monitors.
+    pub(super) id: u32,
+    daemon: bool,
+    interrupted: bool,
+    state: State,
+    /// The numbers of frames the thread had when the host, or a native method
+    /// it runs, started waiting for the frames pushed above them to return,
+    /// innermost last. Once back to that many, the thread does not run until
+    /// the one waiting for it takes over again.
+    awaited: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Runnable,
+    Sleeping(Instant),
+    /// Waits for the thread with an id to terminate, until a deadline if any.
+    Joining(u32, Option<Instant>),
+    /// Waits for the monitor at an index to be free, to enter it as many
+    /// times as it had before waiting on it, or once.
+    Entering {
+        monitor: u32,
+        count: u32,
+    },
+    /// Waits on the monitor at an index to be notified, until a deadline if
+    /// any, having exited it after entering it `count` times.
+    Waiting {
+        monitor: u32,
+        deadline: Option<Instant>,
+        count: u32,
+    },
+    /// Was interrupted while sleeping, joining or waiting.
+    Interrupted,
+    /// Waits for the finalizer thread to have nothing left to call.
+    AwaitingFinalization,
+    /// Is the finalizer thread, with no call to make.
+    Idle,
+}
+
+/// What the running thread asks of the scheduler once its frame has stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Request {
+    /// Calls a native method of `java.lang.Thread` or `java.lang.Object`.
+    Native(NativeMethod),
+    /// Enters the monitor of the object on top of the operand stack, which
+    /// the bytecode interpreter could not enter as a thin lock.
+    Enter,
+    /// Exits the monitor of the object on top of the operand stack, which the
+    /// bytecode interpreter could not exit as a thin lock.
+    Exit,
+    /// Enters the monitor of the `synchronized` method of the frame, before
+    /// its first instruction.
+    EnterMethod,
+    /// Exits the monitor the frame entered, before it returns.
+    ExitMethod,
+    /// Calls the native method of a library, whose receiver and arguments
+    /// are on top of the operand stack, which the virtual machine does as
+    /// it may call Java methods.
+    #[cfg(feature = "jni")]
+    Jni(MethodId),
+}
+
+impl<'a> JavaThread<'a> {
+    fn new(id: u32, call_stack: Vec<CallFrame<'a>>, stack: ThreadStack) -> Self {
+        Self {
+            stack,
+            call_stack,
+            object: None,
+            id,
+            daemon: false,
+            interrupted: false,
+            state: State::Runnable,
+            awaited: Vec::new(),
+        }
+    }
+
+    /// Returns whether the thread is back to the frames the host or a native
+    /// method waits for it to return to.
+    fn is_awaited(&self) -> bool {
+        self.awaited.last() == Some(&self.call_stack.len())
+    }
+}
+
+/// The threads of a program and the one running.
+#[derive(Debug)]
+pub(super) struct Scheduler<'a> {
+    pub(super) threads: Vec<JavaThread<'a>>,
+    /// The index in `threads` of the running thread.
+    pub(super) current: usize,
+    next_id: u32,
+    /// The id of the finalizer thread, once started.
+    finalizer: Option<u32>,
+    monitors: Monitors,
+}
+
+impl<'a> Scheduler<'a> {
+    pub(super) fn new() -> Self {
+        Self {
+            threads: Vec::new(),
+            current: 0,
+            next_id: 0,
+            finalizer: None,
+            monitors: Monitors::default(),
+        }
+    }
+
+    /// Starts a thread running `call_stack` for the host, with `object` as
+    /// its `java.lang.Thread` object, and switches to it. Returns the id of
+    /// the thread.
+    pub(super) fn start(
+        &mut self,
+        stack: ThreadStack,
+        call_stack: Vec<CallFrame<'a>>,
+        object: Option<ObjectRef>,
+    ) -> u32 {
+        let id = self.next_id;
+        let mut thread = JavaThread::new(id, call_stack, stack);
+        thread.object = object;
+        thread.awaited.push(0);
+        self.next_id += 1;
+        self.current = self.threads.len();
+        self.threads.push(thread);
+        id
+    }
+
+    pub(super) fn current(&mut self) -> &mut JavaThread<'a> {
+        &mut self.threads[self.current]
+    }
+
+    /// Leaves the running thread to wait for the frames pushed above its first
+    /// `frames` ones to return, and returns its id.
+    #[cfg(feature = "jni")]
+    pub(super) fn await_frames(&mut self, frames: usize) -> u32 {
+        let thread = self.current();
+        thread.awaited.push(frames);
+        thread.id
+    }
+
+    /// Stops the thread with the id `id` from waiting for frames to return.
+    #[cfg(feature = "jni")]
+    pub(super) fn stop_awaiting(&mut self, id: u32) {
+        if let Some(thread) = self.threads.iter_mut().find(|thread| thread.id == id) {
+            thread.awaited.pop();
+        }
+    }
+
+    /// Returns whether the thread with the id `id` can run and is back to the
+    /// frames it is waited for to return to, and switches to it if so. Only
+    /// the running thread is looked at unless `any` is set.
+    pub(super) fn returned(&mut self, id: u32, any: bool) -> bool {
+        let index = if self.threads[self.current].id == id {
+            self.current
+        } else if any {
+            match self.threads.iter().position(|thread| thread.id == id) {
+                Some(index) => index,
+                None => return false,
+            }
+        } else {
+            return false;
+        };
+        let thread = &self.threads[index];
+        if thread.state != State::Runnable || !thread.is_awaited() {
+            return false;
+        }
+        self.current = index;
+        true
+    }
+
+    /// Returns whether the running thread is back to the frames the host or a
+    /// native method waits for it to return to, and cannot run until it takes
+    /// over again.
+    pub(super) fn is_awaited(&self) -> bool {
+        self.threads[self.current].is_awaited()
+    }
+
+    /// Returns whether the running thread is the finalizer thread.
+    pub(super) fn is_finalizer(&self) -> bool {
+        self.finalizer == Some(self.threads[self.current].id)
+    }
+
+    /// Returns whether the finalizer thread has calls left to make.
+    fn is_finalizing(&self) -> bool {
+        self.threads
+            .iter()
+            .any(|thread| Some(thread.id) == self.finalizer && thread.state != State::Idle)
+    }
+
+    /// Lets the finalizer thread run, starting it first if needed, once a
+    /// collection has found objects to finalize or references to enqueue.
+    pub(super) fn wake_finalizer(&mut self) {
+        let finalizer = self.finalizer;
+        match self
+            .threads
+            .iter_mut()
+            .find(|thread| Some(thread.id) == finalizer)
+        {
+            Some(thread) if thread.state == State::Idle => thread.state = State::Runnable,
+            Some(_) => (),
+            None => {
+                let id = self.next_id;
+                let mut thread = JavaThread::new(id, Vec::new(), ThreadStack::new());
+                thread.daemon = true;
+                self.finalizer = Some(id);
+                self.next_id += 1;
+                self.threads.push(thread);
+            }
+        }
+    }
+
+    /// Leaves the running thread, which is the finalizer thread with no call
+    /// to make, waiting for a collection to find some.
+    pub(super) fn idle(&mut self) {
+        self.threads[self.current].state = State::Idle;
+    }
+
+    /// Returns the index of the thread whose object is `object`, if it is
+    /// alive.
+    fn position(&self, object: ObjectRef) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|thread| thread.object == Some(object))
+    }
+
+    /// Returns the operand stack of the frame of the running thread.
+    fn operands(&mut self) -> Stack<'_> {
+        let thread = &mut self.threads[self.current];
+        let frame = thread.call_stack.last_mut().expect("thread has a frame");
+        thread.stack.stack(frame)
+    }
+
+    /// Removes the running thread, whose call stack is empty, and returns it.
+    pub(super) fn terminate(&mut self) -> JavaThread<'a> {
+        let removed = self.current;
+        let thread = self.threads.remove(removed);
+        // The thread after the one removed runs next.
+        self.current = removed
+            .checked_sub(1)
+            .unwrap_or(self.threads.len().saturating_sub(1));
+        thread
+    }
+
+    /// Switches to the next thread which can run, in turn, waiting for a
+    /// sleeping thread to wake up if every thread is blocked. A thread
+    /// blocked on a monitor runs once it has entered it. Returns `false` once
+    /// only daemon threads are left, which ends the program.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::Deadlock`] if every thread is blocked with no
+    /// deadline, and [`JavaException::Interrupted`] if the next thread was
+    /// interrupted while it slept, joined or waited.
+    pub(super) fn switch(&mut self) -> Result<bool, ExecuteError> {
+        loop {
+            if self.threads.iter().all(|thread| thread.daemon) {
+                return Ok(false);
+            }
+
+            let now = Instant::now();
+            let mut wake_up: Option<Instant> = None;
+            for index in 0..self.threads.len() {
+                let deadline = match self.threads[index].state {
+                    State::Sleeping(until) => Some(until),
+                    State::Joining(id, deadline) => {
+                        if !self.threads.iter().any(|thread| thread.id == id) {
+                            self.threads[index].state = State::Runnable;
+                            continue;
+                        }
+                        deadline
+                    }
+                    State::Waiting { deadline, .. } => deadline,
+                    State::AwaitingFinalization => {
+                        if !self.is_finalizing() {
+                            self.threads[index].state = State::Runnable;
+                        }
+                        continue;
+                    }
+                    State::Runnable | State::Entering { .. } | State::Interrupted | State::Idle => {
+                        continue
+                    }
+                };
+                match deadline {
+                    Some(deadline) if deadline <= now => self.wake(index),
+                    Some(deadline) => {
+                        wake_up = Some(wake_up.map_or(deadline, |wake_up| wake_up.min(deadline)));
+                    }
+                    None => (),
+                }
+            }
+
+            let len = self.threads.len();
+            for offset in 1..=len {
+                let index = (self.current + offset) % len;
+                let thread = &mut self.threads[index];
+                match thread.state {
+                    State::Runnable if thread.is_awaited() => continue,
+                    State::Runnable => (),
+                    State::Entering { monitor, count } => {
+                        let monitor = self.monitors.get_mut(monitor);
+                        if monitor.owner.is_some() {
+                            continue;
+                        }
+                        monitor.owner = Some((thread.id, count));
+                        thread.state = State::Runnable;
+                    }
+                    State::Interrupted => {
+                        thread.interrupted = false;
+                        thread.state = State::Runnable;
+                        self.current = index;
+                        return Err(JavaException::Interrupted.into());
+                    }
+                    _ => continue,
+                }
+                self.current = index;
+                return Ok(true);
+            }
+            match wake_up {
+                Some(wake_up) => thread::sleep(wake_up - now),
+                None => return Err(ExecuteError::Deadlock),
+            }
+        }
+    }
+
+    /// Wakes up the thread at `index` from sleeping, joining or waiting,
+    /// leaving a thread which waited on a monitor to enter it again.
+    fn wake(&mut self, index: usize) {
+        let thread = &mut self.threads[index];
+        thread.state = match thread.state {
+            State::Waiting { monitor, count, .. } => {
+                let waiters = &mut self.monitors.get_mut(monitor).waiters;
+                waiters.retain(|&id| id != thread.id);
+                State::Entering { monitor, count }
+            }
+            _ => State::Runnable,
+        };
+    }
+
+    /// Does what the running thread asks for, and returns `true` if the thread
+    /// gives up the rest of its time slice.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LinkageError`] if a started thread has no `run` method, and
+    /// a [`JavaException`] if the request is not allowed.
+    pub(super) fn request(
+        &mut self,
+        classes: &'a ClassManager,
+        heap: &mut Heap,
+        request: Request,
+    ) -> Result<bool, ExecuteError> {
+        match request {
+            Request::Native(native) => self.invoke(classes, heap, native),
+            Request::Enter => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                Ok(self.enter(classes, heap, LockTarget::Object(object)))
+            }
+            Request::Exit => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                self.exit(classes, heap, LockTarget::Object(object))?;
+                Ok(false)
+            }
+            Request::EnterMethod => {
+                let thread = &mut self.threads[self.current];
+                let frame = thread.call_stack.last_mut().expect("thread has a frame");
+                let method = MethodId {
+                    class: frame.class as u32,
+                    method: frame.method,
+                };
+                let flags = classes.method_info(method).flags();
+                let target = if flags & MethodFlags::STATIC == MethodFlags::STATIC {
+                    LockTarget::Class(method.class)
+                } else {
+                    let receiver = get_reference(thread.stack.slots(frame), 0);
+                    LockTarget::Object(receiver.expect("receiver is not null"))
+                };
+                frame.monitor = FrameMonitor::Entered(target);
+                Ok(self.enter(classes, heap, target))
+            }
+            Request::ExitMethod => {
+                let thread = &mut self.threads[self.current];
+                let frame = thread.call_stack.last_mut().expect("thread has a frame");
+                let FrameMonitor::Entered(target) = frame.monitor else {
+                    unreachable!("frame entered a monitor");
+                };
+                frame.monitor = FrameMonitor::Exited;
+                self.exit(classes, heap, target)?;
+                Ok(false)
+            }
+            #[cfg(feature = "jni")]
+            Request::Jni(_) => unreachable!("native methods of libraries are called by the VM"),
+        }
+    }
+
+    /// Enters the monitor of `target` for the running thread, and returns
+    /// `true` if the thread blocks until another thread exits it.
+    pub(super) fn enter(
+        &mut self,
+        classes: &ClassManager,
+        heap: &mut Heap,
+        target: LockTarget,
+    ) -> bool {
+        let id = self.threads[self.current].id;
+        let mut lock = lock_word(classes, heap, target);
+        if lock.try_enter(id) {
+            set_lock_word(classes, heap, target, lock);
+            return false;
+        }
+        let index = self.monitors.inflate(&mut lock);
+        set_lock_word(classes, heap, target, lock);
+        let monitor = self.monitors.get_mut(index);
+        match &mut monitor.owner {
+            None => monitor.owner = Some((id, 1)),
+            Some((owner, count)) if *owner == id => *count += 1,
+            Some(_) => {
+                self.threads[self.current].state = State::Entering {
+                    monitor: index,
+                    count: 1,
+                };
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Exits the monitor of `target`, and deflates it once it is free and no
+    /// thread is blocked on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JavaException::IllegalMonitorState`] if the running thread
+    /// does not own the monitor.
+    pub(super) fn exit(
+        &mut self,
+        classes: &ClassManager,
+        heap: &mut Heap,
+        target: LockTarget,
+    ) -> Result<(), JavaException> {
+        let id = self.threads[self.current].id;
+        let mut lock = lock_word(classes, heap, target);
+        if lock.try_exit(id) {
+            set_lock_word(classes, heap, target, lock);
+            return Ok(());
+        }
+        let Lock::Inflated(index) = lock.get() else {
+            return Err(JavaException::IllegalMonitorState);
+        };
+        let monitor = self.monitors.get_mut(index);
+        match &mut monitor.owner {
+            Some((owner, count)) if *owner == id => *count -= 1,
+            _ => return Err(JavaException::IllegalMonitorState),
+        }
+        if monitor.owner.is_some_and(|(_, count)| count > 0) {
+            return Ok(());
+        }
+        monitor.owner = None;
+        let blocked = !monitor.waiters.is_empty()
+            || self.threads.iter().any(|thread| {
+                matches!(thread.state, State::Entering { monitor, .. } if monitor == index)
+            });
+        if !blocked {
+            self.monitors.deflate(index, &mut lock);
+            set_lock_word(classes, heap, target, lock);
+        }
+        Ok(())
+    }
+
+    /// Returns the inflated monitor of `object` if the running thread owns
+    /// it, or `None` if it owns it as a thin lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JavaException::IllegalMonitorState`] if the running thread
+    /// does not own the monitor.
+    fn owned(&mut self, heap: &Heap, object: ObjectRef) -> Result<Option<u32>, JavaException> {
+        let id = self.threads[self.current].id;
+        match heap.get(object).lock.get() {
+            Lock::Thin { owner, .. } if owner == id => Ok(None),
+            Lock::Inflated(index)
+                if self
+                    .monitors
+                    .get_mut(index)
+                    .owner
+                    .is_some_and(|(owner, _)| owner == id) =>
+            {
+                Ok(Some(index))
+            }
+            _ => Err(JavaException::IllegalMonitorState),
+        }
+    }
+
+    /// Runs a native method of `java.lang.Thread` or `java.lang.Object` for
+    /// the running thread, whose arguments are on top of its operand stack.
+    /// Returns `true` if the thread gives up the rest of its time slice.
+    fn invoke(
+        &mut self,
+        classes: &'a ClassManager,
+        heap: &mut Heap,
+        native: NativeMethod,
+    ) -> Result<bool, ExecuteError> {
+        match native {
+            NativeMethod::ThreadStart => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                if self.position(object).is_some() {
+                    return Err(JavaException::IllegalThreadState.into());
+                }
+                let class = heap.get(object).class;
+                let vtable = &classes.linked(class).vtable;
+                let Some(run) = classes.select(vtable, java_str!("run"), java_str!("()V")) else {
+                    return Err(LinkageError::NoSuchMethod {
+                        class: classes.classes[class as usize].class.name().to_owned(),
+                        name: java_str!("run").to_owned(),
+                        descriptor: java_str!("()V").to_owned(),
+                    }
+                    .into());
+                };
+                let code = classes.method_code(run)?;
+                let mut stack = ThreadStack::new();
+                set_reference(stack.registers(0, code), 0, Some(object));
+                let call_stack = vec![CallFrame::new(run, code, 0)];
+                let mut thread = JavaThread::new(self.next_id, call_stack, stack);
+                thread.object = Some(object);
+                thread.daemon = is_daemon(classes, heap, object)?;
+                self.next_id += 1;
+                self.threads.push(thread);
+                Ok(false)
+            }
+            NativeMethod::ThreadCurrentThread => {
+                let object = self.current().object;
+                self.operands().push_reference(object);
+                Ok(false)
+            }
+            NativeMethod::SystemRunFinalization => {
+                // The finalizer thread would wait for itself.
+                if self.is_finalizer() || !self.is_finalizing() {
+                    return Ok(false);
+                }
+                self.current().state = State::AwaitingFinalization;
+                Ok(true)
+            }
+            NativeMethod::ThreadYield => Ok(true),
+            NativeMethod::ThreadSleep => {
+                let millis = self.operands().pop_long();
+                if millis < 0 {
+                    return Err(JavaException::NegativeTimeout.into());
+                }
+                let thread = self.current();
+                if thread.interrupted {
+                    thread.interrupted = false;
+                    return Err(JavaException::Interrupted.into());
+                }
+                let duration = Duration::from_millis(millis as u64);
+                thread.state = State::Sleeping(Instant::now() + duration);
+                Ok(true)
+            }
+            NativeMethod::ThreadJoin => {
+                let mut operands = self.operands();
+                let millis = operands.pop_long();
+                let object = operands.pop_reference().ok_or(JavaException::NullPointer)?;
+                if millis < 0 {
+                    return Err(JavaException::NegativeTimeout.into());
+                }
+                let Some(target) = self.position(object) else {
+                    return Ok(false);
+                };
+                let id = self.threads[target].id;
+                let thread = self.current();
+                if thread.interrupted {
+                    thread.interrupted = false;
+                    return Err(JavaException::Interrupted.into());
+                }
+                thread.state = State::Joining(id, deadline(millis));
+                Ok(true)
+            }
+            NativeMethod::ThreadIsAlive => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                let alive = self.position(object).is_some();
+                (self.operands())
+                    .push_as(&alive, &mut JavaHeap::new(classes, heap))
+                    .expect("a boolean converts");
+                Ok(false)
+            }
+            NativeMethod::ThreadInterrupt => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                if let Some(index) = self.position(object) {
+                    if let State::Waiting { monitor, .. } = self.threads[index].state {
+                        let id = self.threads[index].id;
+                        self.monitors
+                            .get_mut(monitor)
+                            .waiters
+                            .retain(|&waiter| waiter != id);
+                    }
+                    let thread = &mut self.threads[index];
+                    thread.interrupted = true;
+                    if matches!(
+                        thread.state,
+                        State::Sleeping(_) | State::Joining(..) | State::Waiting { .. }
+                    ) {
+                        thread.state = State::Interrupted;
+                    }
+                }
+                Ok(false)
+            }
+            NativeMethod::ThreadIsInterrupted => {
+                let mut operands = self.operands();
+                let clear: bool = (operands.pop_as(&mut JavaHeap::new(classes, heap)))
+                    .expect("argument is a boolean");
+                let object = operands.pop_reference().ok_or(JavaException::NullPointer)?;
+                let interrupted = match self.position(object) {
+                    Some(index) => {
+                        let thread = &mut self.threads[index];
+                        let interrupted = thread.interrupted;
+                        thread.interrupted &= !clear;
+                        interrupted
+                    }
+                    None => false,
+                };
+                (self.operands())
+                    .push_as(&interrupted, &mut JavaHeap::new(classes, heap))
+                    .expect("a boolean converts");
+                Ok(false)
+            }
+            NativeMethod::ObjectWait => {
+                let mut operands = self.operands();
+                let millis = operands.pop_long();
+                let object = operands.pop_reference().ok_or(JavaException::NullPointer)?;
+                if millis < 0 {
+                    return Err(JavaException::NegativeTimeout.into());
+                }
+                self.owned(heap, object)?;
+                if self.current().interrupted {
+                    self.current().interrupted = false;
+                    return Err(JavaException::Interrupted.into());
+                }
+                // Waiting needs the monitor to be inflated, to hold the
+                // waiters, and it is exited however many times it was entered.
+                let mut lock = heap.get(object).lock;
+                let index = self.monitors.inflate(&mut lock);
+                heap.get_mut(object).lock = lock;
+                let thread = &mut self.threads[self.current];
+                let monitor = self.monitors.get_mut(index);
+                let (_, count) = monitor.owner.take().expect("thread owns the monitor");
+                monitor.waiters.push_back(thread.id);
+                thread.state = State::Waiting {
+                    monitor: index,
+                    deadline: deadline(millis),
+                    count,
+                };
+                Ok(true)
+            }
+            NativeMethod::ObjectNotify | NativeMethod::ObjectNotifyAll => {
+                let object = self
+                    .operands()
+                    .pop_reference()
+                    .ok_or(JavaException::NullPointer)?;
+                // A thin lock has no waiters.
+                let Some(index) = self.owned(heap, object)? else {
+                    return Ok(false);
+                };
+                let waiters = &mut self.monitors.get_mut(index).waiters;
+                let notified: Vec<u32> = match native {
+                    NativeMethod::ObjectNotify => waiters.pop_front().into_iter().collect(),
+                    _ => waiters.drain(..).collect(),
+                };
+                for id in notified {
+                    let thread = self
+                        .threads
+                        .iter_mut()
+                        .find(|thread| thread.id == id)
+                        .expect("waiting thread is alive");
+                    let State::Waiting { monitor, count, .. } = thread.state else {
+                        unreachable!("thread waits on the monitor");
+                    };
+                    thread.state = State::Entering { monitor, count };
+                }
+                Ok(false)
+            }
+            _ => unreachable!("{native:?} is not run by the scheduler"),
+        }
+    }
+}
+
+/// Returns the lock word of the monitor of `target`.
+fn lock_word(classes: &ClassManager, heap: &Heap, target: LockTarget) -> LockWord {
+    match target {
+        LockTarget::Object(object) => heap.get(object).lock,
+        LockTarget::Class(class) => classes.classes[class as usize].lock.get(),
+    }
+}
+
+fn set_lock_word(classes: &ClassManager, heap: &mut Heap, target: LockTarget, lock: LockWord) {
+    match target {
+        LockTarget::Object(object) => heap.get_mut(object).lock = lock,
+        LockTarget::Class(class) => classes.classes[class as usize].lock.set(lock),
+    }
+}
+
+/// Returns when a wait of `millis` milliseconds started now ends, or `None`
+/// if it never does, which zero means.
+fn deadline(millis: i64) -> Option<Instant> {
+    (millis > 0).then(|| Instant::now() + Duration::from_millis(millis as u64))
+}
+
+/// Returns whether the `daemon` field of a `java.lang.Thread` object is set.
+fn is_daemon(classes: &ClassManager, heap: &Heap, object: ObjectRef) -> Result<bool, LinkageError> {
+    let class = heap.get(object).class as usize;
+    let Some(field) = classes.resolve_field(class, java_str!("daemon"), java_str!("Z"))? else {
+        return Ok(false);
+    };
+    let slot = classes.link(field.class as usize)?.field_slots[field.field as usize];
+    Ok(slot.is_some_and(
+        |slot| matches!(heap.get(object).fields[slot as usize], Value::Int(daemon) if daemon != 0),
+    ))
+}
@@ -1,3 +1,6 @@
+use super::class::FieldType;
+use super::heap::ObjectRef;
+
 #[derive(Debug, Clone, Copy)]
 pub enum Value {
     Int(i32),
@@ -5,6 +8,8 @@ pub enum Value {
     Float(f32),
     Double(f64),
     RetAddr(u32),
+    /// A reference to an object, or `None` for `null`.
+    Reference(Option<ObjectRef>),
 }
 
 impl Value {
@@ -15,6 +20,54 @@ impl Value {
             Self::Float(_) => 1,
             Self::Double(_) => 2,
             Self::RetAddr(_) => 1,
+            Self::Reference(_) => 1,
+        }
+    }
+
+    pub(super) fn value_type(&self) -> ValueType {
+        match self {
+            Self::Int(_) => ValueType::Int,
+            Self::Long(_) => ValueType::Long,
+            Self::Float(_) => ValueType::Float,
+            Self::Double(_) => ValueType::Double,
+            Self::RetAddr(_) => ValueType::RetAddr,
+            Self::Reference(_) => ValueType::Reference,
+        }
+    }
+}
+
+/// The type of a [`Value`], which decides how it is stored in stack slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Int,
+    Long,
+    Float,
+    Double,
+    RetAddr,
+    Reference,
+}
+
+impl ValueType {
+    pub(super) fn size(self) -> usize {
+        match self {
+            Self::Long | Self::Double => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl From<&FieldType> for ValueType {
+    fn from(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Byte
+            | FieldType::Short
+            | FieldType::Int
+            | FieldType::Char
+            | FieldType::Bool => Self::Int,
+            FieldType::Long => Self::Long,
+            FieldType::Float => Self::Float,
+            FieldType::Double => Self::Double,
+            FieldType::Class(_) | FieldType::Array(_) => Self::Reference,
         }
     }
 }
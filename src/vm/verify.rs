@@ -0,0 +1,575 @@
+//! Verification of the bytecode of a class before any of its methods run.
+//!
+//! Slots do not record the type of their values in release builds, so every
+//! tier reads a slot as the type the instruction expects. A class is verified
+//! the first time it is linked or initialized: the types of the locals and
+//! operand stack are inferred before every instruction, and each instruction
+//! must find values of the types it takes there, references to instances of
+//! the class whose members it accesses, and stay within the `max_locals` and
+//! `max_stack` of its method.
+//!
+//! As in the verifier of the JVM, any reference is accepted where an
+//! interface is expected. A reference to a class which is not loaded is
+//! accepted as well, as it can only be `null`, and so is a reference where a
+//! class which is not loaded is expected, as resolving that class fails
+//! first.
+
+use crate::java_str;
+use crate::string::{JavaStr, JavaString};
+
+use super::analysis::{solve, ControlFlowGraph, StackTypes, TypeState};
+use super::class::{
+    ConstantIdx, ConstantPool, FieldType, Frame, Instruction, Method, MethodDescriptor,
+    MethodFlags, VerificationType,
+};
+use super::heap::Heap;
+use super::value::{Value, ValueType};
+use super::{ClassManager, LinkageError};
+
+const BAD_OPERAND: &str = "Bad type on operand stack";
+const BAD_LOCAL: &str = "Bad local variable type";
+const BAD_RETURN: &str = "Method does not return a value of its result type";
+const BAD_LOCAL_INDEX: &str = "Illegal local variable number";
+const STACK_OVERFLOW: &str = "Operand stack overflow";
+const INCONSISTENT: &str = "Inconsistent stack height or types";
+const BAD_CONTROL_FLOW: &str = "Control flow leaves the code";
+const BAD_DESCRIPTOR: &str = "Invalid descriptor";
+
+type Result<T> = std::result::Result<T, &'static str>;
+
+impl ClassManager {
+    /// Verifies the code of every method of the class at `class`, unless it
+    /// has already been verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LinkageError::Verify`] for the first instruction found to be
+    /// invalid.
+    pub(super) fn verify(&self, class: usize) -> std::result::Result<(), LinkageError> {
+        let loaded = &self.classes[class];
+        if loaded.verified.get() {
+            return Ok(());
+        }
+        for method in loaded.class.methods() {
+            if method.bytecode().is_some() {
+                self.verify_method(class, method)?;
+            }
+        }
+        loaded.verified.set(true);
+        Ok(())
+    }
+
+    fn verify_method(
+        &self,
+        class: usize,
+        method: &Method,
+    ) -> std::result::Result<(), LinkageError> {
+        let loaded = &self.classes[class].class;
+        let constants = loaded.constants();
+        let code = method.bytecode().expect("method has code");
+        let error = |offset: u32, message: &'static str| LinkageError::Verify {
+            class: loaded.name().to_owned(),
+            name: method.name(constants).to_owned(),
+            descriptor: method.descriptor(constants).to_owned(),
+            offset,
+            message,
+        };
+
+        let is_static = method.flags() & MethodFlags::STATIC == MethodFlags::STATIC;
+        let descriptor = method.parsed_descriptor();
+        if descriptor.arg_slots(is_static) > code.max_locals as usize {
+            return Err(error(0, BAD_LOCAL_INDEX));
+        }
+
+        let common_superclass = |a: &JavaStr, b: &JavaStr| self.common_superclass(a, b);
+        let mut analysis = StackTypes::new(loaded, method);
+        analysis.common_superclass(&common_superclass);
+        let graph = ControlFlowGraph::new(code).map_err(|_| error(0, BAD_CONTROL_FLOW))?;
+        let results = solve(&analysis, &graph, code);
+
+        let mut result = Ok(());
+        results.visit(
+            &analysis,
+            &graph,
+            code,
+            |offset, instruction, before, after| {
+                if result.is_err() {
+                    return;
+                }
+                let checked = match (before, after) {
+                    (TypeState::Unreached, _) => Ok(()),
+                    (TypeState::Frame(frame), TypeState::Frame(after)) => {
+                        let checker = Checker {
+                            classes: self,
+                            constants,
+                            descriptor,
+                            max_locals: code.max_locals as usize,
+                            frame,
+                            top: frame.stack.len(),
+                        };
+                        match checker.check(instruction) {
+                            Ok(()) if after.stack.len() > code.max_stack as usize => {
+                                Err(STACK_OVERFLOW)
+                            }
+                            checked => checked,
+                        }
+                    }
+                    _ => Err(INCONSISTENT),
+                };
+                if let Err(message) = checked {
+                    result = Err(error(offset, message));
+                }
+            },
+        );
+        result
+    }
+
+    /// Returns the closest common superclass of two classes, or
+    /// `java/lang/Object` if either is an interface or is not loaded.
+    fn common_superclass(&self, a: &JavaStr, b: &JavaStr) -> JavaString {
+        let object = java_str!("java/lang/Object").to_owned();
+        let (Some(a), Some(b)) = (self.position(a), self.position(b)) else {
+            return object;
+        };
+        if self.is_interface(a) || self.is_interface(b) {
+            return object;
+        }
+        let mut next = Some(a);
+        while let Some(index) = next {
+            if self.is_subclass(b, index) {
+                return self.classes[index].class.name().to_owned();
+            }
+            let super_name = self.classes[index].class.super_name();
+            next = super_name.and_then(|name| self.position(name));
+        }
+        object
+    }
+
+    /// Returns whether `value` may be passed where a value of `field_type` is
+    /// expected, which for a reference depends on the class of its object.
+    pub(super) fn accepts(&self, heap: &Heap, field_type: &FieldType, value: Value) -> bool {
+        match (VerificationType::from_field_type(field_type), value) {
+            (VerificationType::Object(target), Value::Reference(Some(object))) => {
+                let class = heap.get(object).class as usize;
+                self.is_assignable(self.classes[class].class.name(), &target)
+            }
+            _ => ValueType::from(field_type) == value.value_type(),
+        }
+    }
+
+    /// Returns whether a reference to an instance of the class or array type
+    /// `class` may be used where one of `target` is expected.
+    fn is_assignable(&self, class: &JavaStr, target: &JavaStr) -> bool {
+        if class == target || target == java_str!("java/lang/Object") {
+            return true;
+        }
+        match (component(class), component(target)) {
+            (Some(class), Some(target)) => match (class, target) {
+                (Some(class), Some(target)) => self.is_assignable(class, target),
+                _ => false,
+            },
+            (Some(_), None) => {
+                target == java_str!("java/lang/Cloneable")
+                    || target == java_str!("java/io/Serializable")
+            }
+            (None, Some(_)) => false,
+            (None, None) => match (self.position(class), self.position(target)) {
+                (Some(class), Some(target)) => {
+                    self.is_interface(target) || self.is_subclass(class, target)
+                }
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Returns `None` if `name` is not the name of an array type, or else the
+/// class or array type of its components, which is `None` for primitive
+/// types.
+fn component(name: &JavaStr) -> Option<Option<&JavaStr>> {
+    if name.as_bytes().first() != Some(&b'[') {
+        return None;
+    }
+    let component = name.get(1..)?;
+    Some(match component.as_bytes().first()? {
+        b'L' => component.get(1..component.len() - 1),
+        b'[' => Some(component),
+        _ => None,
+    })
+}
+
+/// Checks the operands of an instruction against the types inferred before
+/// it, popping them from the top of the stack as the instruction would.
+struct Checker<'a> {
+    classes: &'a ClassManager,
+    constants: &'a ConstantPool,
+    descriptor: &'a MethodDescriptor,
+    max_locals: usize,
+    frame: &'a Frame,
+    /// The height of the stack below the operands checked so far.
+    top: usize,
+}
+
+impl<'a> Checker<'a> {
+    /// Pops a value of type `expected`, which for a reference is any
+    /// reference to an instance of the class or array type it names.
+    fn pop(&mut self, expected: &VerificationType) -> Result<&'a VerificationType> {
+        let size = if expected.is_wide() { 2 } else { 1 };
+        let start = self.top.checked_sub(size).ok_or(BAD_OPERAND)?;
+        let stack = &self.frame.stack;
+        let actual = &stack[start];
+        let matches = match (actual, expected) {
+            (VerificationType::Null, VerificationType::Object(_)) => true,
+            (VerificationType::Object(class), VerificationType::Object(target)) => {
+                self.classes.is_assignable(class, target)
+            }
+            _ => actual == expected,
+        };
+        if !matches || (size == 2 && stack[start + 1] != VerificationType::Top) {
+            return Err(BAD_OPERAND);
+        }
+        self.top = start;
+        Ok(actual)
+    }
+
+    fn pop_all(&mut self, expected: &[VerificationType]) -> Result<()> {
+        for expected in expected.iter().rev() {
+            self.pop(expected)?;
+        }
+        Ok(())
+    }
+
+    /// Pops the receiver of an access to a member of `owner`, which may also
+    /// be an object whose constructor has not run if `uninitialized`.
+    fn pop_receiver(&mut self, owner: &JavaStr, uninitialized: bool) -> Result<()> {
+        let top = self.top.checked_sub(1).ok_or(BAD_OPERAND)?;
+        match &self.frame.stack[top] {
+            VerificationType::UninitializedThis | VerificationType::Uninitialized(_)
+                if uninitialized =>
+            {
+                self.top = top;
+                Ok(())
+            }
+            _ => self
+                .pop(&VerificationType::Object(owner.to_owned()))
+                .map(drop),
+        }
+    }
+
+    /// Checks that the slot `depth` slots below the top of the stack starts a
+    /// value rather than holding the second half of a `long` or `double`, so
+    /// that an instruction moving the slots above it keeps values whole.
+    fn boundary(&self, depth: usize) -> Result<()> {
+        let stack = &self.frame.stack;
+        let index = stack.len().checked_sub(depth).ok_or(BAD_OPERAND)?;
+        if index > 0 && stack[index - 1].is_wide() && stack[index] == VerificationType::Top {
+            return Err(BAD_OPERAND);
+        }
+        Ok(())
+    }
+
+    fn local(&self, index: u16, size: usize) -> Result<&'a VerificationType> {
+        let index = index as usize;
+        if index + size > self.max_locals {
+            return Err(BAD_LOCAL_INDEX);
+        }
+        Ok(&self.frame.locals[index])
+    }
+
+    fn load(&self, index: u16, expected: VerificationType) -> Result<()> {
+        let size = if expected.is_wide() { 2 } else { 1 };
+        if *self.local(index, size)? != expected {
+            return Err(BAD_LOCAL);
+        }
+        Ok(())
+    }
+
+    fn field_type(&self, index: ConstantIdx) -> Result<(&'a JavaStr, VerificationType)> {
+        let (owner, name_type) = self.constants.get(index).into_ref();
+        let owner = self
+            .constants
+            .get(self.constants.get(owner).into_class())
+            .into_utf8();
+        let (_, descriptor) = self.constants.get(name_type).into_name_type();
+        let field_type = FieldType::parse(self.constants.get(descriptor).into_utf8())
+            .map_err(|_| BAD_DESCRIPTOR)?;
+        Ok((owner, VerificationType::from_field_type(&field_type)))
+    }
+
+    /// Pops the arguments of a call of the method with the name and type at
+    /// `name_type`, and its receiver, which must be an instance of `owner`
+    /// unless it is `None`.
+    fn invoke(&mut self, name_type: ConstantIdx, owner: Option<&JavaStr>) -> Result<()> {
+        let (name, descriptor) = self.constants.get(name_type).into_name_type();
+        let descriptor = MethodDescriptor::parse(self.constants.get(descriptor).into_utf8())
+            .map_err(|_| BAD_DESCRIPTOR)?;
+        let args: Vec<_> = (descriptor.args().iter())
+            .map(VerificationType::from_field_type)
+            .collect();
+        self.pop_all(&args)?;
+        if let Some(owner) = owner {
+            let is_init = self.constants.get(name).into_utf8() == java_str!("<init>");
+            self.pop_receiver(owner, is_init)?;
+        }
+        Ok(())
+    }
+
+    fn ret(&mut self, expected: VerificationType) -> Result<()> {
+        let result = self.descriptor.result().ok_or(BAD_RETURN)?;
+        let result = VerificationType::from_field_type(result);
+        let matches = match (&result, &expected) {
+            (VerificationType::Object(_), VerificationType::Object(_)) => true,
+            _ => result == expected,
+        };
+        if !matches {
+            return Err(BAD_RETURN);
+        }
+        self.pop(&result).map(drop)
+    }
+
+    fn check(mut self, instruction: &Instruction) -> Result<()> {
+        use VerificationType::{Double, Float, Integer, Long};
+
+        let object = || VerificationType::Object(java_str!("java/lang/Object").to_owned());
+        let array = |name: &JavaStr| VerificationType::Object(name.to_owned());
+
+        match *instruction {
+            // Locals
+            Instruction::iload(index) => self.load(index, Integer)?,
+            Instruction::lload(index) => self.load(index, Long)?,
+            Instruction::fload(index) => self.load(index, Float)?,
+            Instruction::dload(index) => self.load(index, Double)?,
+            Instruction::aload(index) => match self.local(index, 1)? {
+                VerificationType::Top
+                | VerificationType::Integer
+                | VerificationType::Float
+                | VerificationType::Long
+                | VerificationType::Double => return Err(BAD_LOCAL),
+                _ => {}
+            },
+            Instruction::iinc(index, _) => self.load(index, Integer)?,
+            Instruction::ret(index) => {
+                if *self.local(index, 1)? != VerificationType::Top {
+                    return Err(BAD_LOCAL);
+                }
+            }
+            Instruction::istore(index) => {
+                self.local(index, 1)?;
+                self.pop(&Integer)?;
+            }
+            Instruction::lstore(index) => {
+                self.local(index, 2)?;
+                self.pop(&Long)?;
+            }
+            Instruction::fstore(index) => {
+                self.local(index, 1)?;
+                self.pop(&Float)?;
+            }
+            Instruction::dstore(index) => {
+                self.local(index, 2)?;
+                self.pop(&Double)?;
+            }
+            // A return address pushed by `jsr` has the type `Top`.
+            Instruction::astore(index) => {
+                self.local(index, 1)?;
+                self.boundary(1)?;
+                match self.frame.stack.last() {
+                    Some(Integer | Float | Long | Double) | None => return Err(BAD_OPERAND),
+                    Some(_) => {}
+                }
+            }
+
+            // Arrays
+            Instruction::iaload => self.pop_all(&[array(java_str!("[I")), Integer])?,
+            Instruction::laload => self.pop_all(&[array(java_str!("[J")), Integer])?,
+            Instruction::faload => self.pop_all(&[array(java_str!("[F")), Integer])?,
+            Instruction::daload => self.pop_all(&[array(java_str!("[D")), Integer])?,
+            Instruction::aaload => {
+                self.pop_all(&[array(java_str!("[Ljava/lang/Object;")), Integer])?
+            }
+            Instruction::baload => self.pop_all(&[object(), Integer])?,
+            Instruction::caload => self.pop_all(&[array(java_str!("[C")), Integer])?,
+            Instruction::saload => self.pop_all(&[array(java_str!("[S")), Integer])?,
+            Instruction::iastore => self.pop_all(&[array(java_str!("[I")), Integer, Integer])?,
+            Instruction::lastore => self.pop_all(&[array(java_str!("[J")), Integer, Long])?,
+            Instruction::fastore => self.pop_all(&[array(java_str!("[F")), Integer, Float])?,
+            Instruction::dastore => self.pop_all(&[array(java_str!("[D")), Integer, Double])?,
+            Instruction::aastore => {
+                self.pop_all(&[array(java_str!("[Ljava/lang/Object;")), Integer, object()])?
+            }
+            Instruction::bastore => self.pop_all(&[object(), Integer, Integer])?,
+            Instruction::castore => self.pop_all(&[array(java_str!("[C")), Integer, Integer])?,
+            Instruction::sastore => self.pop_all(&[array(java_str!("[S")), Integer, Integer])?,
+            Instruction::newarray(_) | Instruction::anewarray(_) => self.pop(&Integer).map(drop)?,
+            Instruction::arraylength => self.pop(&object()).map(drop)?,
+            Instruction::multianewarray(_, dimensions) => {
+                for _ in 0..dimensions {
+                    self.pop(&Integer)?;
+                }
+            }
+
+            // Stack
+            Instruction::pop | Instruction::dup => self.boundary(1)?,
+            Instruction::pop2 | Instruction::dup2 => self.boundary(2)?,
+            Instruction::dup_x1 | Instruction::swap => {
+                self.boundary(1)?;
+                self.boundary(2)?;
+            }
+            Instruction::dup_x2 => {
+                self.boundary(1)?;
+                self.boundary(3)?;
+            }
+            Instruction::dup2_x1 => {
+                self.boundary(2)?;
+                self.boundary(3)?;
+            }
+            Instruction::dup2_x2 => {
+                self.boundary(2)?;
+                self.boundary(4)?;
+            }
+
+            // Math
+            Instruction::iadd
+            | Instruction::isub
+            | Instruction::imul
+            | Instruction::idiv
+            | Instruction::irem
+            | Instruction::ishl
+            | Instruction::ishr
+            | Instruction::iushr
+            | Instruction::iand
+            | Instruction::ior
+            | Instruction::ixor
+            | Instruction::if_icmp_eq(_)
+            | Instruction::if_icmp_ne(_)
+            | Instruction::if_icmp_lt(_)
+            | Instruction::if_icmp_ge(_)
+            | Instruction::if_icmp_gt(_)
+            | Instruction::if_icmp_le(_) => self.pop_all(&[Integer, Integer])?,
+            Instruction::ladd
+            | Instruction::lsub
+            | Instruction::lmul
+            | Instruction::ldiv
+            | Instruction::lrem
+            | Instruction::land
+            | Instruction::lor
+            | Instruction::lxor
+            | Instruction::lcmp => self.pop_all(&[Long, Long])?,
+            Instruction::lshl | Instruction::lshr | Instruction::lushr => {
+                self.pop_all(&[Long, Integer])?
+            }
+            Instruction::fadd
+            | Instruction::fsub
+            | Instruction::fmul
+            | Instruction::fdiv
+            | Instruction::frem
+            | Instruction::fcmp(_) => self.pop_all(&[Float, Float])?,
+            Instruction::dadd
+            | Instruction::dsub
+            | Instruction::dmul
+            | Instruction::ddiv
+            | Instruction::drem
+            | Instruction::dcmp(_) => self.pop_all(&[Double, Double])?,
+            Instruction::ineg
+            | Instruction::i2l
+            | Instruction::i2f
+            | Instruction::i2d
+            | Instruction::i2b
+            | Instruction::i2c
+            | Instruction::i2s
+            | Instruction::if_eq(_)
+            | Instruction::if_ne(_)
+            | Instruction::if_lt(_)
+            | Instruction::if_ge(_)
+            | Instruction::if_gt(_)
+            | Instruction::if_le(_)
+            | Instruction::tableswitch(_)
+            | Instruction::lookupswitch(_) => self.pop(&Integer).map(drop)?,
+            Instruction::lneg | Instruction::l2i | Instruction::l2f | Instruction::l2d => {
+                self.pop(&Long).map(drop)?
+            }
+            Instruction::fneg | Instruction::f2i | Instruction::f2l | Instruction::f2d => {
+                self.pop(&Float).map(drop)?
+            }
+            Instruction::dneg | Instruction::d2i | Instruction::d2l | Instruction::d2f => {
+                self.pop(&Double).map(drop)?
+            }
+
+            // References
+            Instruction::if_acmp_eq(_) | Instruction::if_acmp_ne(_) => {
+                self.pop_all(&[object(), object()])?
+            }
+            Instruction::ifnull(_)
+            | Instruction::ifnonnull(_)
+            | Instruction::checkcast(_)
+            | Instruction::instanceof(_)
+            | Instruction::monitorenter
+            | Instruction::monitorexit => self.pop(&object()).map(drop)?,
+            Instruction::athrow => {
+                let throwable = java_str!("java/lang/Throwable");
+                self.pop(&VerificationType::Object(throwable.to_owned()))?;
+            }
+            Instruction::getstatic(_) => {}
+            Instruction::putstatic(index) => {
+                let (_, field_type) = self.field_type(index)?;
+                self.pop(&field_type)?;
+            }
+            Instruction::getfield(index) => {
+                let (owner, _) = self.field_type(index)?;
+                self.pop_receiver(owner, false)?;
+            }
+            // The constructor of a class may set its fields before it calls
+            // the constructor of its superclass.
+            Instruction::putfield(index) => {
+                let (owner, field_type) = self.field_type(index)?;
+                self.pop(&field_type)?;
+                let top = self.top.checked_sub(1).ok_or(BAD_OPERAND)?;
+                match self.frame.stack[top] {
+                    VerificationType::UninitializedThis => self.top = top,
+                    _ => self.pop_receiver(owner, false)?,
+                }
+            }
+            Instruction::invokevirtual(index)
+            | Instruction::invokespecial(index)
+            | Instruction::invokeinterface(index, _) => {
+                let (owner, name_type) = self.constants.get(index).into_ref();
+                let owner = self.constants.get(self.constants.get(owner).into_class());
+                self.invoke(name_type, Some(owner.into_utf8()))?;
+            }
+            Instruction::invokestatic(index) => {
+                let (_, name_type) = self.constants.get(index).into_ref();
+                self.invoke(name_type, None)?;
+            }
+            Instruction::invokedynamic(index) => {
+                let (_, name_type) = self.constants.get(index).into_invoke_dynamic();
+                self.invoke(name_type, None)?;
+            }
+
+            // Returns
+            Instruction::ireturn => self.ret(Integer)?,
+            Instruction::lreturn => self.ret(Long)?,
+            Instruction::freturn => self.ret(Float)?,
+            Instruction::dreturn => self.ret(Double)?,
+            Instruction::areturn => self.ret(object())?,
+            Instruction::ret_void => {
+                if self.descriptor.result().is_some() {
+                    return Err(BAD_RETURN);
+                }
+            }
+
+            Instruction::nop
+            | Instruction::aconst_null
+            | Instruction::iconst(_)
+            | Instruction::lconst(_)
+            | Instruction::fconst(_)
+            | Instruction::dconst(_)
+            | Instruction::bipush(_)
+            | Instruction::sipush(_)
+            | Instruction::ldc(_)
+            | Instruction::goto(_)
+            | Instruction::jsr(_)
+            | Instruction::new(_) => {}
+        }
+        Ok(())
+    }
+}
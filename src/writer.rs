@@ -0,0 +1,42 @@
+/// A buffer writer, used to marshall structured data into a byte array. This is
+/// the counterpart of [`Reader`].
+///
+/// [`Reader`]: crate::reader::Reader
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+    vec: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self { vec: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.vec
+    }
+
+    pub fn write_slice(&mut self, slice: &[u8]) {
+        self.vec.extend_from_slice(slice);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.vec.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write_slice(&value.to_be_bytes());
+    }
+}
@@ -0,0 +1,499 @@
+//! Builds the control-flow graphs of assembled methods and runs the data-flow
+//! analyses over them.
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::analysis::{
+    solve, BitSet, BlockId, Constant, ConstantPropagation, ControlFlowGraph, Definition, Edge,
+    EdgeKind, Liveness, ReachingDefinitions, StackTypes,
+};
+use graphene_jvm::vm::class::{assemble, Class, Code, Method, VerificationType};
+
+const BRANCHES: &str = "
+.class public Branches
+.super java/lang/Object
+
+.method public static sign (I)I
+    iload 0
+    if_ge Positive
+    iconst -1
+    istore 1
+    goto Done
+Positive:
+    iconst 1
+    istore 1
+Done:
+    iload 1
+    ireturn
+.end method
+
+.method public static nested (I)I
+    iconst 0
+    istore 1
+    iconst 0
+    istore 2
+Outer:
+    iload 2
+    iload 0
+    if_icmp_ge Done
+    iconst 0
+    istore 3
+Inner:
+    iload 3
+    iload 0
+    if_icmp_ge Next
+    iinc 1 1
+    iinc 3 1
+    goto Inner
+Next:
+    iinc 2 1
+    goto Outer
+Done:
+    iload 1
+    ireturn
+.end method
+
+.method public static table (I)I
+    iload 0
+    tableswitch 0
+        Zero
+        One
+        Zero
+        default: Other
+Zero:
+    bipush 10
+    ireturn
+One:
+    bipush 20
+    ireturn
+Other:
+    bipush 30
+    ireturn
+.end method
+
+.method public static lookup (I)I
+    iload 0
+    lookupswitch
+        -5: Small
+        1000: Large
+        default: Small
+Small:
+    iconst 0
+    ireturn
+Large:
+    iconst 1
+    ireturn
+.end method
+
+.method public static divide (II)I
+    .catch java/lang/ArithmeticException from Start to End using Handler
+    iload 1
+    if_eq Zero
+Start:
+    iload 0
+    iload 1
+    idiv
+    ireturn
+End:
+Zero:
+    iconst 0
+    ireturn
+Handler:
+    pop
+    iconst -1
+    ireturn
+.end method
+
+.method public static merge (II)I
+    iload 0
+    if_eq Other
+    iconst 7
+    istore 1
+    iconst 1
+    istore 2
+    ldc string \"text\"
+    astore 3
+    iconst 3
+    goto Join
+Other:
+    iconst 7
+    istore 1
+    fconst 1.0
+    fstore 2
+    aconst_null
+    astore 3
+    iconst 4
+Join:
+    istore 4
+    iload 1
+    iload 4
+    iadd
+    ireturn
+.end method
+";
+
+fn branches() -> Class {
+    assemble(BRANCHES).unwrap()
+}
+
+fn method<'a>(class: &'a Class, name: &str) -> &'a Method {
+    let constants = class.constants();
+    class
+        .methods()
+        .iter()
+        .find(|method| method.name(constants) == name)
+        .unwrap()
+}
+
+/// Returns the code of the method of `class` named `name`.
+fn code<'a>(class: &'a Class, name: &str) -> &'a Code {
+    method(class, name).bytecode().unwrap()
+}
+
+/// Returns the identifiers of the blocks of `graph` in order.
+fn ids(graph: &ControlFlowGraph) -> Vec<BlockId> {
+    graph
+        .blocks()
+        .iter()
+        .map(|block| graph.block_at(block.start()).unwrap())
+        .collect()
+}
+
+fn edge(target: BlockId, kind: EdgeKind) -> Edge {
+    Edge { target, kind }
+}
+
+#[test]
+fn if_else_branches_merge() {
+    let class = branches();
+    let graph = ControlFlowGraph::new(code(&class, "sign")).unwrap();
+    let [entry, negative, positive, done] = ids(&graph)[..] else {
+        panic!("four blocks: {graph:?}");
+    };
+    assert_eq!(entry, BlockId::ENTRY);
+    assert_eq!(
+        graph.block(entry).successors(),
+        [
+            edge(positive, EdgeKind::Branch),
+            edge(negative, EdgeKind::FallThrough)
+        ]
+    );
+    assert_eq!(
+        graph.block(negative).successors(),
+        [edge(done, EdgeKind::Goto)]
+    );
+    assert_eq!(
+        graph.block(positive).successors(),
+        [edge(done, EdgeKind::FallThrough)]
+    );
+    assert!(graph.block(done).successors().is_empty());
+    assert_eq!(graph.block(done).predecessors(), [negative, positive]);
+
+    let dominators = graph.dominators();
+    for block in [negative, positive, done] {
+        assert_eq!(dominators.immediate_dominator(block), Some(entry));
+    }
+    assert!(!dominators.dominates(negative, done));
+    assert!(!dominators.dominates(positive, done));
+    assert_eq!(dominators.children(entry), [negative, positive, done]);
+    assert!(graph.loops(&dominators).is_empty());
+}
+
+#[test]
+fn nested_loops_are_found() {
+    let class = branches();
+    let graph = ControlFlowGraph::new(code(&class, "nested")).unwrap();
+    let [entry, outer, body, inner, increment, next, done] = ids(&graph)[..] else {
+        panic!("seven blocks: {graph:?}");
+    };
+    assert_eq!(graph.block(outer).predecessors(), [entry, next]);
+    assert_eq!(graph.block(inner).predecessors(), [body, increment]);
+
+    let dominators = graph.dominators();
+    assert_eq!(dominators.immediate_dominator(outer), Some(entry));
+    assert_eq!(dominators.immediate_dominator(body), Some(outer));
+    assert_eq!(dominators.immediate_dominator(inner), Some(body));
+    assert_eq!(dominators.immediate_dominator(next), Some(inner));
+    assert_eq!(dominators.immediate_dominator(done), Some(outer));
+    assert!(dominators.dominates(outer, increment));
+    assert!(!dominators.dominates(inner, done));
+
+    let loops = graph.loops(&dominators);
+    let [outer_loop, inner_loop] = &loops[..] else {
+        panic!("two loops: {loops:?}");
+    };
+    assert_eq!(outer_loop.header(), outer);
+    assert_eq!(outer_loop.latches(), [next]);
+    assert_eq!(outer_loop.blocks(), [outer, body, inner, increment, next]);
+    assert_eq!(inner_loop.header(), inner);
+    assert_eq!(inner_loop.latches(), [increment]);
+    assert_eq!(inner_loop.blocks(), [inner, increment]);
+    assert!(outer_loop.contains(increment) && !inner_loop.contains(next));
+}
+
+#[test]
+fn switches_have_an_edge_per_target() {
+    let class = branches();
+    let graph = ControlFlowGraph::new(code(&class, "table")).unwrap();
+    let [entry, zero, one, other] = ids(&graph)[..] else {
+        panic!("four blocks: {graph:?}");
+    };
+    // The targets shared by several keys have a single edge.
+    assert_eq!(
+        graph.block(entry).successors(),
+        [
+            edge(zero, EdgeKind::Switch),
+            edge(one, EdgeKind::Switch),
+            edge(other, EdgeKind::Switch)
+        ]
+    );
+    let dominators = graph.dominators();
+    for block in [zero, one, other] {
+        assert_eq!(graph.block(block).predecessors(), [entry]);
+        assert_eq!(dominators.immediate_dominator(block), Some(entry));
+    }
+
+    let graph = ControlFlowGraph::new(code(&class, "lookup")).unwrap();
+    let [entry, small, large] = ids(&graph)[..] else {
+        panic!("three blocks: {graph:?}");
+    };
+    assert_eq!(
+        graph.block(entry).successors(),
+        [edge(small, EdgeKind::Switch), edge(large, EdgeKind::Switch)]
+    );
+}
+
+#[test]
+fn exception_ranges_split_blocks_and_add_edges() {
+    let class = branches();
+    let graph = ControlFlowGraph::new(code(&class, "divide")).unwrap();
+    let [entry, covered, zero, handler] = ids(&graph)[..] else {
+        panic!("four blocks: {graph:?}");
+    };
+    // The range starts a block of its own, and only that block is covered.
+    assert_eq!(graph.block(covered).start(), 4);
+    assert_eq!(graph.block(covered).end(), 8);
+    assert_eq!(
+        graph.block(entry).successors(),
+        [
+            edge(zero, EdgeKind::Branch),
+            edge(covered, EdgeKind::FallThrough)
+        ]
+    );
+    let [Edge {
+        target,
+        kind: EdgeKind::Exception(Some(catch_type)),
+    }] = graph.block(covered).successors()
+    else {
+        panic!("an exception edge: {graph:?}");
+    };
+    assert_eq!(*target, handler);
+    let constants = class.constants();
+    let name = constants.get(constants.get(*catch_type).into_class());
+    assert_eq!(
+        name.into_utf8().to_string(),
+        "java/lang/ArithmeticException"
+    );
+    assert!(graph.block(zero).successors().is_empty());
+    assert_eq!(graph.block(handler).predecessors(), [covered]);
+
+    let dominators = graph.dominators();
+    assert_eq!(dominators.immediate_dominator(handler), Some(covered));
+    assert_eq!(graph.block_at(6), Some(covered));
+    assert_eq!(graph.block_at(100), None);
+}
+
+#[test]
+fn graphs_are_exported_to_dot() {
+    let class = branches();
+    let constants = class.constants();
+    let code = self::code(&class, "sign");
+    let dot = ControlFlowGraph::new(code).unwrap().to_dot(code, constants);
+    assert!(dot.starts_with("digraph cfg {\n"), "{dot}");
+    assert!(
+        dot.contains("    b1 [label=\"4: iconst(-1)\\l5: istore(1)\\l6: goto(5)\\l\"];\n"),
+        "{dot}"
+    );
+    assert!(dot.contains("    b0 -> b2 [label=\"branch\"];\n"), "{dot}");
+    assert!(dot.contains("    b0 -> b1;\n"), "{dot}");
+    assert!(dot.contains("    b1 -> b3;\n"), "{dot}");
+    assert!(dot.ends_with("}\n"), "{dot}");
+
+    let code = self::code(&class, "divide");
+    let dot = ControlFlowGraph::new(code).unwrap().to_dot(code, constants);
+    assert!(
+        dot.contains("    b1 -> b3 [label=\"java/lang/ArithmeticException\", style=dashed];\n"),
+        "{dot}"
+    );
+}
+
+/// The blocks of `merge`: the test, the two arms of the branch and the
+/// join.
+fn merge_blocks(graph: &ControlFlowGraph) -> [BlockId; 4] {
+    let blocks = ids(graph);
+    blocks[..]
+        .try_into()
+        .unwrap_or_else(|_| panic!("four blocks: {graph:?}"))
+}
+
+#[test]
+fn liveness_flows_backward_from_reads() {
+    let class = branches();
+    let code = code(&class, "merge");
+    let graph = ControlFlowGraph::new(code).unwrap();
+    let [entry, then, other, join] = merge_blocks(&graph);
+    let liveness = Liveness::new(code);
+    let results = solve(&liveness, &graph, code);
+
+    let live = |state: &BitSet| {
+        (0..5)
+            .filter(|&local| state.contains(local))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(live(results.before(entry)), [0]);
+    assert_eq!(live(results.after(entry)), []);
+    for block in [then, other] {
+        assert_eq!(live(results.before(block)), []);
+        assert_eq!(live(results.after(block)), [1]);
+    }
+    assert_eq!(live(results.before(join)), [1]);
+    assert_eq!(live(results.after(join)), []);
+
+    // Locals 2 and 3 are never read, and parameter 1 is overwritten first.
+    assert_eq!(
+        liveness.dead_stores(&graph, code, &results),
+        [8, 11, 20, 22]
+    );
+    let method = method(&class, "merge");
+    assert_eq!(
+        liveness.unused_parameters(&results, method.parsed_descriptor(), true),
+        [1]
+    );
+}
+
+#[test]
+fn definitions_from_both_branches_reach_the_join() {
+    let class = branches();
+    let method = method(&class, "merge");
+    let code = method.bytecode().unwrap();
+    let graph = ControlFlowGraph::new(code).unwrap();
+    let [entry, _, _, join] = merge_blocks(&graph);
+    let analysis = ReachingDefinitions::new(code, method.parsed_descriptor(), true);
+    let results = solve(&analysis, &graph, code);
+
+    let reaching = |block, local| {
+        analysis
+            .reaching(results.before(block), local)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(reaching(entry, 1), [Definition::Parameter(1)]);
+    assert_eq!(reaching(join, 0), [Definition::Parameter(0)]);
+    assert_eq!(
+        reaching(join, 1),
+        [
+            Definition::Store { pc: 6, local: 1 },
+            Definition::Store { pc: 18, local: 1 }
+        ]
+    );
+    assert_eq!(reaching(join, 4), []);
+
+    let mut loads = Vec::new();
+    results.visit(&analysis, &graph, code, |pc, _, before, _| {
+        if pc == 27 {
+            loads.extend(analysis.reaching(before, 4));
+        }
+    });
+    assert_eq!(loads, [Definition::Store { pc: 24, local: 4 }]);
+}
+
+#[test]
+fn stack_types_are_merged_at_the_join() {
+    let class = branches();
+    let method = method(&class, "merge");
+    let code = method.bytecode().unwrap();
+    let graph = ControlFlowGraph::new(code).unwrap();
+    let [entry, then, other, join] = merge_blocks(&graph);
+    let analysis = StackTypes::new(&class, method);
+    let results = solve(&analysis, &graph, code);
+
+    let initial = results.before(entry).frame().unwrap();
+    assert_eq!(
+        initial.locals(),
+        [
+            VerificationType::Integer,
+            VerificationType::Integer,
+            VerificationType::Top,
+            VerificationType::Top,
+            VerificationType::Top
+        ]
+    );
+    assert_eq!(
+        results.after(other).frame().unwrap().locals()[2..4],
+        [VerificationType::Float, VerificationType::Null]
+    );
+    let string = VerificationType::Object(java_str!("java/lang/String").to_owned());
+    assert_eq!(results.after(then).frame().unwrap().locals()[3], string);
+
+    // An int and a float merge into an unusable local, while null merges
+    // into the string.
+    let join = results.before(join).frame().unwrap();
+    assert_eq!(
+        join.locals(),
+        [
+            VerificationType::Integer,
+            VerificationType::Integer,
+            VerificationType::Top,
+            string,
+            VerificationType::Top
+        ]
+    );
+    assert_eq!(join.stack(), [VerificationType::Integer]);
+}
+
+#[test]
+fn constants_agreeing_on_both_branches_survive_the_join() {
+    let class = branches();
+    let constants = class.constants();
+    let code = code(&class, "merge");
+    let graph = ControlFlowGraph::new(code).unwrap();
+    let [_, then, other, join] = merge_blocks(&graph);
+    let analysis = ConstantPropagation::new(code, constants);
+    let results = solve(&analysis, &graph, code);
+
+    let then = results.after(then).as_ref().unwrap();
+    let Constant::String(text) = then.locals()[3] else {
+        panic!("a string: {then:?}");
+    };
+    assert_eq!(
+        constants.get(constants.get(text).into_string()).into_utf8(),
+        java_str!("text")
+    );
+    assert_eq!(
+        then.locals()[..3],
+        [Constant::Unknown, Constant::Int(7), Constant::Int(1)]
+    );
+    assert_eq!(then.stack(), [Constant::Int(3)]);
+    let other = results.after(other).as_ref().unwrap();
+    assert_eq!(
+        other.locals()[..4],
+        [
+            Constant::Unknown,
+            Constant::Int(7),
+            Constant::Float(1.0),
+            Constant::Null
+        ]
+    );
+    assert_eq!(other.stack(), [Constant::Int(4)]);
+
+    let join = results.before(join).as_ref().unwrap();
+    assert_eq!(
+        join.locals(),
+        [
+            Constant::Unknown,
+            Constant::Int(7),
+            Constant::Unknown,
+            Constant::Unknown,
+            Constant::Unknown
+        ]
+    );
+    assert_eq!(join.stack(), [Constant::Unknown]);
+}
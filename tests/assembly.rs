@@ -0,0 +1,315 @@
+//! Assembles classes from text, disassembles them and assembles them again,
+//! and runs them.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::string::JavaStr;
+use graphene_jvm::vm::class::{
+    assemble, assemble_with, disassemble, write, AssembleErrorKind, BuildError,
+};
+use graphene_jvm::vm::{ExecuteOptions, Vm};
+
+const SHAPES: &str = "
+.class public Shape
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public area ()I
+    iconst 0
+    ireturn
+.end method
+
+.method public static pick (Z)I
+    iload 0
+    if_eq Circle
+    new Square
+    dup
+    invokespecial Square <init> ()V
+    goto Measure
+Circle:
+    new Circle
+    dup
+    invokespecial Circle <init> ()V
+Measure:
+    invokevirtual Shape area ()I
+    ireturn
+.end method
+";
+
+const SQUARE: &str = "
+.class public Square
+.super Shape
+
+.method public <init> ()V
+    aload 0
+    invokespecial Shape <init> ()V
+    ret_void
+.end method
+
+.method public area ()I
+    iconst 4
+    ireturn
+.end method
+";
+
+const CIRCLE: &str = "
+.class public Circle
+.super Shape
+
+.method public <init> ()V
+    aload 0
+    invokespecial Shape <init> ()V
+    ret_void
+.end method
+
+.method public area ()I
+    iconst 3
+    ireturn
+.end method
+";
+
+fn shape_hierarchy(a: &JavaStr, b: &JavaStr) -> graphene_jvm::string::JavaString {
+    let is_shape = |name: &JavaStr| ["Shape", "Square", "Circle"].iter().any(|s| name == *s);
+    if is_shape(a) && is_shape(b) {
+        java_str!("Shape").to_owned()
+    } else {
+        java_str!("java/lang/Object").to_owned()
+    }
+}
+
+/// Returns the `.stack` directives of the method named `name` in `text`.
+fn frames<'a>(text: &'a str, name: &str) -> Vec<&'a str> {
+    text.split(".method ")
+        .find(|method| method.split_whitespace().any(|word| word == name))
+        .unwrap()
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with(".stack"))
+        .collect()
+}
+
+#[test]
+fn frames_use_the_hierarchy() {
+    let class = assemble_with(SHAPES, shape_hierarchy).unwrap();
+    assert_eq!(
+        frames(&disassemble(&class), "pick"),
+        [".stack locals int", ".stack locals int stack Shape"]
+    );
+
+    // Without the hierarchy, the merged type is widened.
+    let class = assemble(SHAPES).unwrap();
+    assert_eq!(
+        frames(&disassemble(&class), "pick"),
+        [
+            ".stack locals int",
+            ".stack locals int stack java/lang/Object"
+        ]
+    );
+}
+
+#[test]
+fn assembled_classes_run() {
+    let classes = common::load_assembly(&[SQUARE, CIRCLE]);
+    let mut classes = classes;
+    let shapes = assemble_with(SHAPES, shape_hierarchy).unwrap();
+    classes.load(&write(&shapes).unwrap()).unwrap();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let square: i32 = vm
+        .call(java_str!("Shape"), java_str!("pick"), (true,))
+        .unwrap();
+    let circle: i32 = vm
+        .call(java_str!("Shape"), java_str!("pick"), (false,))
+        .unwrap();
+    assert_eq!((square, circle), (4, 3));
+}
+
+#[test]
+fn disassembly_round_trips() {
+    let source = "
+.class public Boxes
+.super java/lang/Object
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public static make (Z)LBoxes;
+    new Boxes
+    dup
+    iload 0
+    if_eq Zero
+    iconst 1
+    goto Call
+Zero:
+    iconst 0
+Call:
+    invokespecial Boxes <init> (I)V
+    areturn
+.end method
+
+.method public static sum (JI)J
+    lconst 0
+    lstore 3
+Loop:
+    iload 2
+    if_le Done
+    lload 3
+    lload 0
+    ladd
+    lstore 3
+    iinc 2 -1
+    goto Loop
+Done:
+    lload 3
+    lreturn
+.end method
+";
+    let class = assemble(source).unwrap();
+    let text = disassemble(&class);
+    assert!(text.contains(".stack locals int stack uninitialized L0 uninitialized L0\n"));
+    assert!(text.contains(".stack locals long int long\n"));
+
+    let again = assemble(&text).unwrap();
+    assert_eq!(disassemble(&again), text);
+    assert_eq!(write(&again).unwrap(), write(&class).unwrap());
+}
+
+#[test]
+fn frames_are_written_with_limits() {
+    // The second frame is wrong, but frames given by hand are kept as they
+    // are, which is what fixtures for the verifier need.
+    let source = "
+.class public Fixture
+.super java/lang/Object
+
+.method public static choose (I)I
+    .limit stack 1
+    .limit locals 1
+    iload 0
+    if_eq Zero
+    iconst 1
+    ireturn
+Zero:
+    .stack locals float
+    iconst 0
+    ireturn
+.end method
+";
+    let class = assemble(source).unwrap();
+    let text = disassemble(&class);
+    assert_eq!(frames(&text, "choose"), [".stack locals float"]);
+    assert!(text.contains(".limit stack 1\n"));
+    assert_eq!(disassemble(&assemble(&text).unwrap()), text);
+}
+
+#[test]
+fn misplaced_frames_are_rejected() {
+    let source = "
+.class public Fixture
+.super java/lang/Object
+
+.method public static f ()V
+    ret_void
+    .stack
+.end method
+";
+    let error = assemble(source).unwrap_err();
+    assert_eq!(error.line, 5);
+    assert_eq!(
+        error.kind,
+        AssembleErrorKind::Build(BuildError::InvalidFrame(1))
+    );
+}
+
+#[test]
+fn metadata_and_dynamic_call_sites_round_trip() {
+    let source = r#"
+.class public Boxes
+.super java/lang/Object
+.signature <T:Ljava/lang/Object;>Ljava/lang/Object;
+.bootstrap methodhandle invokestatic Boxes bootstrap (Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite;
+.bootstrap methodhandle invokestatic Boxes constant (Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;I)I int 7 string "seven"
+
+.field private items Ljava/util/List; signature Ljava/util/List<TT;>;
+.field public static final "signature" I = int 3
+
+.method public abstract first ()Ljava/lang/Object;
+    .signature ()TT;
+.end method
+
+.method public static apply (Ljava/util/List;)I
+    .signature (Ljava/util/List<TT;>;)I
+    .var 0 is items Ljava/util/List; from Start to End
+    .var 0 is items signature Ljava/util/List<TT;>; from Start to End
+Start:
+    aload 0
+    invokedynamic 0 size (Ljava/util/List;)I
+    ldc dynamic 1 seven I
+    iadd
+End:
+    ireturn
+.end method
+"#;
+    let class = assemble(source).unwrap();
+    assert_eq!(
+        class.signature().unwrap(),
+        "<T:Ljava/lang/Object;>Ljava/lang/Object;"
+    );
+    let constants = class.constants();
+    let [items, _] = class.fields() else {
+        panic!("two fields: {class:?}");
+    };
+    assert_eq!(items.signature(constants).unwrap(), "Ljava/util/List<TT;>;");
+    let [first, apply] = class.methods() else {
+        panic!("two methods: {class:?}");
+    };
+    assert_eq!(first.signature(constants).unwrap(), "()TT;");
+    let code = apply.bytecode().unwrap();
+    let [variable] = code.local_variables() else {
+        panic!("one local variable: {code:?}");
+    };
+    assert_eq!((variable.start_pc, variable.length), (0, 9));
+    assert_eq!(
+        constants.get(variable.descriptor).into_utf8(),
+        "Ljava/util/List;"
+    );
+    let [variable] = code.local_variable_types() else {
+        panic!("one local variable type: {code:?}");
+    };
+    assert_eq!(
+        constants.get(variable.descriptor).into_utf8(),
+        "Ljava/util/List<TT;>;"
+    );
+
+    let text = disassemble(&class);
+    assert!(text.contains("int 7 string \"seven\"\n"), "{text}");
+    assert!(text.contains("    invokedynamic 0 size (Ljava/util/List;)I\n"));
+    assert!(text.contains("    .var 0 is items signature Ljava/util/List<TT;>; from L0 to L1\n"));
+    let again = assemble(&text).unwrap();
+    assert_eq!(disassemble(&again), text);
+    assert_eq!(write(&again).unwrap(), write(&class).unwrap());
+}
+
+#[test]
+fn undeclared_bootstrap_methods_are_rejected() {
+    let source = "
+.class public Fixture
+.super java/lang/Object
+
+.method public static f ()I
+    invokedynamic 0 f ()I
+    ireturn
+.end method
+";
+    let error = assemble(source).unwrap_err();
+    assert_eq!(error.line, 6);
+    assert_eq!(error.kind, AssembleErrorKind::UndefinedBootstrapMethod);
+}
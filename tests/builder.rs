@@ -0,0 +1,90 @@
+//! Builds classes, writes them to class files and parses them back, then runs
+//! them.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{
+    parse, write, Class, ClassBuilder, CodeBuilder, Instruction, MethodDescriptor, MethodFlags,
+};
+use graphene_jvm::vm::{ExecuteOptions, Vm};
+
+fn descriptor(descriptor: &str) -> MethodDescriptor {
+    MethodDescriptor::parse(&graphene_jvm::string::from_utf8(descriptor)).unwrap()
+}
+
+/// Builds a class `Sum` with `static int sum(int n)`, which adds up the
+/// numbers below `n` in a loop, with the loop body padded by `padding` `nop`s.
+fn sum_class(padding: usize) -> Class {
+    let mut code = CodeBuilder::new();
+    let head = code.label();
+    let exit = code.label();
+    code.emit(Instruction::iconst(0))
+        .emit(Instruction::istore(1))
+        .emit(Instruction::iconst(0))
+        .emit(Instruction::istore(2))
+        .bind(head)
+        .emit(Instruction::iload(2))
+        .emit(Instruction::iload(0))
+        .branch(Instruction::if_icmp_ge, exit);
+    for _ in 0..padding {
+        code.emit(Instruction::nop);
+    }
+    code.emit(Instruction::iload(1))
+        .emit(Instruction::iload(2))
+        .emit(Instruction::iadd)
+        .emit(Instruction::istore(1))
+        .emit(Instruction::iinc(2, 1))
+        .goto(head)
+        .bind(exit)
+        .emit(Instruction::iload(1))
+        .emit(Instruction::ireturn);
+
+    let mut builder = ClassBuilder::new(java_str!("Sum"));
+    builder
+        .method(
+            MethodFlags::PUBLIC | MethodFlags::STATIC,
+            java_str!("sum"),
+            &descriptor("(I)I"),
+            code,
+        )
+        .unwrap();
+    builder.build()
+}
+
+fn run_sum(class: Class, n: i32) -> i32 {
+    let classes = common::load(&[class]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    vm.call(java_str!("Sum"), java_str!("sum"), (n,)).unwrap()
+}
+
+#[test]
+fn write_round_trips() {
+    let class = sum_class(0);
+    let bytes = write(&class).unwrap();
+    let parsed = parse(&bytes).unwrap();
+    assert_eq!(write(&parsed).unwrap(), bytes);
+
+    let code = parsed.methods()[0].bytecode().unwrap();
+    assert_eq!((code.max_stack(), code.max_locals()), (2, 3));
+    let constants = parsed.constants();
+    assert!(code
+        .attributes
+        .iter()
+        .any(|attribute| constants.get(attribute.name).into_utf8() == "StackMapTable"));
+}
+
+#[test]
+fn built_method_runs() {
+    assert_eq!(run_sum(sum_class(0), 0), 0);
+    assert_eq!(run_sum(sum_class(0), 10), 45);
+}
+
+#[test]
+fn long_jumps_are_widened() {
+    let class = sum_class(40_000);
+    let bytes = write(&class).unwrap();
+    let parsed = parse(&bytes).unwrap();
+    assert_eq!(write(&parsed).unwrap(), bytes);
+    assert_eq!(run_sum(parsed, 100), 4950);
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by the integration tests, which build or assemble their
+//! classes instead of loading checked-in class files.
+
+#![allow(dead_code)]
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{
+    assemble, write, Class, ClassBuilder, CodeBuilder, Instruction, MethodDescriptor, MethodFlags,
+};
+use graphene_jvm::vm::ClassManager;
+
+/// Returns a `java/lang/Object` with only a constructor, which is all the
+/// classes of the tests need from it.
+pub fn object_class() -> Class {
+    let mut builder = ClassBuilder::new(java_str!("java/lang/Object"));
+    builder.super_class(None);
+    let mut code = CodeBuilder::new();
+    code.emit(Instruction::ret_void);
+    builder
+        .method(
+            MethodFlags::PUBLIC,
+            java_str!("<init>"),
+            &MethodDescriptor::parse(java_str!("()V")).unwrap(),
+            code,
+        )
+        .unwrap();
+    builder.build()
+}
+
+/// Loads `classes` into a class manager along with [`object_class`], going
+/// through their class files.
+pub fn load(classes: &[Class]) -> ClassManager {
+    let mut manager = ClassManager::new();
+    manager.load(&write(&object_class()).unwrap()).unwrap();
+    for class in classes {
+        manager.load(&write(class).unwrap()).unwrap();
+    }
+    manager
+}
+
+/// Assembles each of `sources` and loads them with [`load`].
+pub fn load_assembly(sources: &[&str]) -> ClassManager {
+    let classes = sources
+        .iter()
+        .map(|source| assemble(source).unwrap())
+        .collect::<Vec<_>>();
+    load(&classes)
+}
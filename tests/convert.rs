@@ -0,0 +1,455 @@
+//! Calls methods with strings and arrays converted from Rust values, and
+//! converts the strings and arrays they return back.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{FieldType, MethodDescriptor, MethodFlags};
+use graphene_jvm::vm::heap::{Collector, ObjectRef};
+use graphene_jvm::vm::host::HostClass;
+use graphene_jvm::vm::value::Value;
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, Vm};
+
+/// A `java.lang.String` with the fields of JDK 9 and later, whose length is
+/// the number of bytes of its value shifted right by its coder.
+const STRING: &str = "
+.class public final java/lang/String
+.super java/lang/Object
+
+.field private final value [B
+.field private final coder B
+
+.method public <init> ([BB)V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    aload 0
+    aload 1
+    putfield java/lang/String value [B
+    aload 0
+    iload 2
+    putfield java/lang/String coder B
+    ret_void
+.end method
+
+.method public length ()I
+    aload 0
+    getfield java/lang/String value [B
+    arraylength
+    aload 0
+    getfield java/lang/String coder B
+    ishr
+    ireturn
+.end method
+";
+
+const SYSTEM: &str = "
+.class public final java/lang/System
+.super java/lang/Object
+
+.method public static native gc ()V
+.end method
+";
+
+const NODE: &str = "
+.class public Node
+.super java/lang/Object
+
+.field public value I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+const TEST: &str = "
+.class public Test
+.super java/lang/Object
+
+.method public static length (Ljava/lang/String;)I
+    aload 0
+    invokevirtual java/lang/String length ()I
+    ireturn
+.end method
+
+.method public static identity (Ljava/lang/String;)Ljava/lang/String;
+    aload 0
+    areturn
+.end method
+
+.method public static isNull (Ljava/lang/String;)Z
+    aload 0
+    ifnull Null
+    iconst 0
+    ireturn
+Null:
+    iconst 1
+    ireturn
+.end method
+
+.method public static string ([BB)Ljava/lang/String;
+    new java/lang/String
+    dup
+    aload 0
+    iload 1
+    invokespecial java/lang/String <init> ([BB)V
+    areturn
+.end method
+
+.method public static sum ([I)I
+    iconst 0
+    istore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    aload 0
+    arraylength
+    if_icmp_ge Done
+    iload 1
+    aload 0
+    iload 2
+    iaload
+    iadd
+    istore 1
+    iinc 2 1
+    goto Loop
+Done:
+    iload 1
+    ireturn
+.end method
+
+.method public static rows ([[I)I
+    iconst 0
+    istore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    aload 0
+    arraylength
+    if_icmp_ge Done
+    iload 1
+    aload 0
+    iload 2
+    aaload
+    invokestatic Test sum ([I)I
+    iadd
+    istore 1
+    iinc 2 1
+    goto Loop
+Done:
+    iload 1
+    ireturn
+.end method
+
+.method public static reverse ([I)[I
+    aload 0
+    arraylength
+    newarray int
+    astore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    aload 0
+    arraylength
+    if_icmp_ge Done
+    aload 1
+    aload 0
+    arraylength
+    iconst 1
+    isub
+    iload 2
+    isub
+    aload 0
+    iload 2
+    iaload
+    iastore
+    iinc 2 1
+    goto Loop
+Done:
+    aload 1
+    areturn
+.end method
+
+.method public static swap ([Ljava/lang/String;)[Ljava/lang/String;
+    iconst 2
+    anewarray java/lang/String
+    dup
+    iconst 0
+    aload 0
+    iconst 1
+    aaload
+    aastore
+    dup
+    iconst 1
+    aload 0
+    iconst 0
+    aaload
+    aastore
+    areturn
+.end method
+
+.method public static nodes (I)[LNode;
+    iload 0
+    anewarray Node
+    astore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    iload 0
+    if_icmp_ge Done
+    new Node
+    dup
+    invokespecial Node <init> ()V
+    astore 3
+    aload 3
+    iload 2
+    putfield Node value I
+    aload 1
+    iload 2
+    aload 3
+    aastore
+    iinc 2 1
+    goto Loop
+Done:
+    aload 1
+    areturn
+.end method
+
+.method public static value (LNode;)I
+    aload 0
+    getfield Node value I
+    ireturn
+.end method
+
+.method public static first ([LNode;)I
+    aload 0
+    iconst 0
+    aaload
+    getfield Node value I
+    ireturn
+.end method
+
+.method public static garbage (I)V
+Loop:
+    iload 0
+    if_le Done
+    bipush 64
+    newarray int
+    pop
+    iinc 0 -1
+    goto Loop
+Done:
+    invokestatic java/lang/System gc ()V
+    ret_void
+.end method
+";
+
+fn classes() -> ClassManager {
+    common::load_assembly(&[STRING, SYSTEM, NODE, TEST])
+}
+
+/// Returns the options of a heap of `heap_size` bytes with a nursery of a
+/// sixteenth of it.
+fn generational(heap_size: usize) -> ExecuteOptions {
+    ExecuteOptions {
+        heap_size,
+        collector: Collector::Generational {
+            nursery_size: heap_size / 16,
+        },
+        ..ExecuteOptions::default()
+    }
+}
+
+#[test]
+fn strings_convert_to_java_strings_and_back() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    // Latin-1, the Basic Multilingual Plane and a surrogate pair.
+    for string in ["", "hello", "déjà vu", "snow ☃", "clef 𝄞"] {
+        let length: i32 = vm
+            .call(java_str!("Test"), java_str!("length"), (string,))
+            .unwrap();
+        assert_eq!(length as usize, string.encode_utf16().count());
+        let copy: String = vm
+            .call(
+                java_str!("Test"),
+                java_str!("identity"),
+                (string.to_owned(),),
+            )
+            .unwrap();
+        assert_eq!(copy, string);
+    }
+}
+
+#[test]
+fn null_strings_convert_to_none() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let is_null: bool = vm
+        .call(java_str!("Test"), java_str!("isNull"), (None::<&str>,))
+        .unwrap();
+    assert!(is_null);
+    let string: Option<String> = vm
+        .call(java_str!("Test"), java_str!("identity"), (Some("set"),))
+        .unwrap();
+    assert_eq!(string.as_deref(), Some("set"));
+    let string: Option<String> = vm
+        .call(java_str!("Test"), java_str!("identity"), (None::<String>,))
+        .unwrap();
+    assert_eq!(string, None);
+}
+
+#[test]
+fn strings_with_unpaired_surrogates_do_not_convert() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let bytes: Vec<i8> = 0xD800u16.to_ne_bytes().map(|byte| byte as i8).to_vec();
+    let result: Result<String, _> =
+        vm.call(java_str!("Test"), java_str!("string"), (bytes.clone(), 1i8));
+    assert!(matches!(result, Err(ExecuteError::ResultMismatch { .. })));
+    // A `byte[]` is not a `String`, so no method takes it.
+    let result: Result<i32, _> =
+        vm.call(java_str!("Test"), java_str!("length"), (bytes.as_slice(),));
+    assert!(matches!(result, Err(ExecuteError::Linkage(_))));
+}
+
+#[test]
+fn vectors_and_slices_convert_to_arrays_and_back() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let sum: i32 = vm
+        .call(java_str!("Test"), java_str!("sum"), (&[1, 2, 3, 4][..],))
+        .unwrap();
+    assert_eq!(sum, 10);
+    let reversed: Vec<i32> = vm
+        .call(java_str!("Test"), java_str!("reverse"), (vec![1, 2, 3],))
+        .unwrap();
+    assert_eq!(reversed, [3, 2, 1]);
+    let rows: i32 = vm
+        .call(
+            java_str!("Test"),
+            java_str!("rows"),
+            (vec![vec![1, 2], vec![], vec![3]],),
+        )
+        .unwrap();
+    assert_eq!(rows, 6);
+    let swapped: Vec<String> = vm
+        .call(
+            java_str!("Test"),
+            java_str!("swap"),
+            (["one", "two"].as_slice(),),
+        )
+        .unwrap();
+    assert_eq!(swapped, ["two", "one"]);
+}
+
+#[test]
+fn arrays_only_convert_to_vectors_of_their_component_type() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("sum"), (vec![1i64],));
+    assert!(matches!(result, Err(ExecuteError::Linkage(_))));
+    let result: Result<Vec<i64>, _> = vm.call(java_str!("Test"), java_str!("reverse"), (vec![1],));
+    assert!(matches!(result, Err(ExecuteError::Linkage(_))));
+}
+
+#[test]
+fn elements_of_other_classes_are_not_stored_to_arrays() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let object = vm
+        .new_object(java_str!("java/lang/Object"), java_str!("()V"), &[])
+        .unwrap();
+    let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("first"), (vec![object],));
+    assert!(matches!(result, Err(ExecuteError::IllegalArgument { .. })));
+}
+
+#[test]
+fn references_in_arrays_returned_are_pinned() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, generational(1 << 20));
+    let nodes: Vec<ObjectRef> = vm
+        .call(java_str!("Test"), java_str!("nodes"), (100,))
+        .unwrap();
+    vm.call::<_, ()>(java_str!("Test"), java_str!("garbage"), (20_000,))
+        .unwrap();
+    let stats = vm.heap().stats();
+    assert!(stats.young_collections > 0 && stats.full_collections > 0);
+    for (index, &node) in nodes.iter().enumerate() {
+        let value: i32 = vm
+            .call(java_str!("Test"), java_str!("value"), (node,))
+            .unwrap();
+        assert_eq!(value as usize, index);
+    }
+    let first: i32 = vm
+        .call(java_str!("Test"), java_str!("first"), (nodes.clone(),))
+        .unwrap();
+    assert_eq!(first, 0);
+    for node in nodes {
+        vm.release(node);
+    }
+}
+
+#[test]
+fn arguments_which_do_not_fit_are_converted_after_a_collection() {
+    let classes = classes();
+    for options in [
+        ExecuteOptions {
+            heap_size: 1 << 18,
+            ..ExecuteOptions::default()
+        },
+        generational(1 << 18),
+    ] {
+        let mut vm = Vm::new(&classes, options);
+        let string = "x".repeat(1000);
+        for _ in 0..100 {
+            let length: i32 = vm
+                .call(java_str!("Test"), java_str!("length"), (string.as_str(),))
+                .unwrap();
+            assert_eq!(length, 1000);
+        }
+        let stats = vm.heap().stats();
+        assert!(stats.young_collections + stats.full_collections > 0);
+    }
+}
+
+#[test]
+fn arguments_larger_than_the_heap_run_out_of_memory() {
+    let classes = classes();
+    let options = ExecuteOptions {
+        heap_size: 1 << 16,
+        ..ExecuteOptions::default()
+    };
+    let mut vm = Vm::new(&classes, options);
+    let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("sum"), (vec![0; 1 << 16],));
+    assert_eq!(result, Err(ExecuteError::OutOfMemory));
+}
+
+#[test]
+fn host_methods_read_strings() {
+    let mut classes = classes();
+    let mut class = HostClass::new(java_str!("Chars"));
+    class.method(
+        MethodFlags::PUBLIC | MethodFlags::STATIC,
+        java_str!("count"),
+        &MethodDescriptor::new(
+            vec![FieldType::class(java_str!("java/lang/String"))],
+            Some(FieldType::Int),
+        ),
+        |call| {
+            let string: String = call.arg(0)?;
+            Some(Value::Int(string.chars().count() as i32))
+        },
+    );
+    classes.define(class);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let count: i32 = vm
+        .call(java_str!("Chars"), java_str!("count"), ("clef 𝄞",))
+        .unwrap();
+    assert_eq!(count, 6);
+}
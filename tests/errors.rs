@@ -0,0 +1,166 @@
+//! Runs classes which fail the way Java reports with an exception, or which use
+//! what the virtual machine does not implement, which end them with an error.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::{ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const POINT: &str = "
+.class public Point
+.super java/lang/Object
+
+.field public x I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public getX ()I
+    aload 0
+    getfield Point x I
+    ireturn
+.end method
+";
+
+/// Assembles `methods` into a class `Test` and calls its static method `run`
+/// with no arguments.
+fn run(methods: &str) -> Result<i32, ExecuteError> {
+    let test = format!(".class public Test\n.super java/lang/Object\n{methods}");
+    let classes = common::load_assembly(&[POINT, &test]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    vm.call(java_str!("Test"), java_str!("run"), ())
+}
+
+#[test]
+fn fields_of_null_throw() {
+    let methods = "
+.method public static run ()I
+    aconst_null
+    getfield Point x I
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::NullPointer))
+    );
+}
+
+#[test]
+fn methods_of_null_throw() {
+    let methods = "
+.method public static run ()I
+    aconst_null
+    invokevirtual Point getX ()I
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::NullPointer))
+    );
+}
+
+#[test]
+fn casts_to_unrelated_classes_throw() {
+    let methods = "
+.method public static run ()I
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    checkcast Point
+    getfield Point x I
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::ClassCast))
+    );
+}
+
+#[test]
+fn string_constants_are_unsupported() {
+    let methods = "
+.method public static run ()I
+    ldc string \"hello\"
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert!(matches!(run(methods), Err(ExecuteError::Unsupported(_))));
+}
+
+#[test]
+fn arrays_of_negative_length_throw() {
+    let methods = "
+.method public static run ()I
+    iconst -1
+    newarray int
+    arraylength
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::NegativeArraySize(
+            -1
+        )))
+    );
+}
+
+#[test]
+fn elements_of_null_throw() {
+    let methods = "
+.method public static run ()I
+    aconst_null
+    iconst 0
+    iaload
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::NullPointer))
+    );
+}
+
+#[test]
+fn stores_of_other_classes_to_arrays_throw() {
+    let methods = "
+.method public static run ()I
+    iconst 1
+    anewarray Point
+    iconst 0
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    aastore
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::ArrayStore))
+    );
+}
+
+#[test]
+fn multidimensional_arrays_are_unsupported() {
+    let methods = "
+.method public static run ()I
+    iconst 1
+    iconst 1
+    multianewarray [[I 2
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert!(matches!(run(methods), Err(ExecuteError::Unsupported(_))));
+}
@@ -0,0 +1,435 @@
+//! Runs classes whose objects have finalizers, which run on a thread of their
+//! own, and classes which hold objects through `java.lang.ref` references.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::string::JavaStr;
+use graphene_jvm::vm::convert::ToJavaArgs;
+use graphene_jvm::vm::{ExecuteError, ExecuteOptions, Vm};
+
+const REFERENCE: &str = "
+.class public abstract java/lang/ref/Reference
+.super java/lang/Object
+
+.field private referent Ljava/lang/Object;
+.field final queue Ljava/lang/ref/ReferenceQueue;
+.field next Ljava/lang/ref/Reference;
+
+.method <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    aload 0
+    aload 1
+    putfield java/lang/ref/Reference referent Ljava/lang/Object;
+    aload 0
+    aload 2
+    putfield java/lang/ref/Reference queue Ljava/lang/ref/ReferenceQueue;
+    ret_void
+.end method
+
+.method public get ()Ljava/lang/Object;
+    aload 0
+    getfield java/lang/ref/Reference referent Ljava/lang/Object;
+    areturn
+.end method
+
+; Called on the finalizer thread once the reference has been cleared.
+.method enqueueFromPending ()V
+    aload 0
+    getfield java/lang/ref/Reference queue Ljava/lang/ref/ReferenceQueue;
+    ifnull Done
+    aload 0
+    getfield java/lang/ref/Reference queue Ljava/lang/ref/ReferenceQueue;
+    aload 0
+    invokevirtual java/lang/ref/ReferenceQueue enqueue (Ljava/lang/ref/Reference;)V
+Done:
+    ret_void
+.end method
+";
+
+const SOFT_REFERENCE: &str = "
+.class public java/lang/ref/SoftReference
+.super java/lang/ref/Reference
+
+.method public <init> (Ljava/lang/Object;)V
+    aload 0
+    aload 1
+    aconst_null
+    invokespecial java/lang/ref/Reference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    ret_void
+.end method
+";
+
+const WEAK_REFERENCE: &str = "
+.class public java/lang/ref/WeakReference
+.super java/lang/ref/Reference
+
+.method public <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    aload 0
+    aload 1
+    aload 2
+    invokespecial java/lang/ref/Reference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    ret_void
+.end method
+";
+
+const PHANTOM_REFERENCE: &str = "
+.class public java/lang/ref/PhantomReference
+.super java/lang/ref/Reference
+
+.method public <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    aload 0
+    aload 1
+    aload 2
+    invokespecial java/lang/ref/Reference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    ret_void
+.end method
+";
+
+/// A queue which returns the last reference enqueued first.
+const REFERENCE_QUEUE: &str = "
+.class public java/lang/ref/ReferenceQueue
+.super java/lang/Object
+
+.field private head Ljava/lang/ref/Reference;
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method enqueue (Ljava/lang/ref/Reference;)V
+    aload 1
+    aload 0
+    getfield java/lang/ref/ReferenceQueue head Ljava/lang/ref/Reference;
+    putfield java/lang/ref/Reference next Ljava/lang/ref/Reference;
+    aload 0
+    aload 1
+    putfield java/lang/ref/ReferenceQueue head Ljava/lang/ref/Reference;
+    ret_void
+.end method
+
+.method public poll ()Ljava/lang/ref/Reference;
+    aload 0
+    getfield java/lang/ref/ReferenceQueue head Ljava/lang/ref/Reference;
+    astore 1
+    aload 1
+    ifnull Done
+    aload 0
+    aload 1
+    getfield java/lang/ref/Reference next Ljava/lang/ref/Reference;
+    putfield java/lang/ref/ReferenceQueue head Ljava/lang/ref/Reference;
+Done:
+    aload 1
+    areturn
+.end method
+";
+
+const SYSTEM: &str = "
+.class public final java/lang/System
+.super java/lang/Object
+
+.method public static native gc ()V
+.end method
+
+.method public static native runFinalization ()V
+.end method
+";
+
+const COUNTED: &str = "
+.class public Counted
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method protected finalize ()V
+    getstatic Test lock Ljava/lang/Object;
+    monitorenter
+    getstatic Test finalized I
+    iconst 1
+    iadd
+    putstatic Test finalized I
+    getstatic Test lock Ljava/lang/Object;
+    monitorexit
+    ret_void
+.end method
+";
+
+const TEST: &str = "
+.class public Test
+.super java/lang/Object
+
+.field public static lock Ljava/lang/Object;
+.field public static finalized I
+
+.method public static allocate (I)V
+Loop:
+    iload 0
+    if_le Done
+    new Counted
+    dup
+    invokespecial Counted <init> ()V
+    pop
+    iinc 0 -1
+    goto Loop
+Done:
+    ret_void
+.end method
+
+; Runs long enough for the other threads to get their turn.
+.method public static spin ()V
+    iconst 100000
+    istore 0
+Loop:
+    iinc 0 -1
+    iload 0
+    if_gt Loop
+    ret_void
+.end method
+
+; Returns the number of objects finalized while the lock is held, times a
+; thousand, plus the number finalized once it is released.
+.method public static run (I)I
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    putstatic Test lock Ljava/lang/Object;
+    getstatic Test lock Ljava/lang/Object;
+    monitorenter
+    iload 0
+    invokestatic Test allocate (I)V
+    invokestatic java/lang/System gc ()V
+    invokestatic Test spin ()V
+    getstatic Test finalized I
+    sipush 1000
+    imul
+    getstatic Test lock Ljava/lang/Object;
+    monitorexit
+    invokestatic java/lang/System runFinalization ()V
+    getstatic Test finalized I
+    iadd
+    ireturn
+.end method
+";
+
+#[test]
+fn finalizers_run_on_their_own_thread() {
+    let classes = common::load_assembly(&[SYSTEM, COUNTED, TEST]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: i32 = vm.call(java_str!("Test"), java_str!("run"), (3,)).unwrap();
+    assert_eq!(result, 3);
+}
+
+#[test]
+fn waiting_for_a_finalizer_blocked_on_a_held_monitor_deadlocks() {
+    let wait = "
+.class public Wait
+.super java/lang/Object
+
+.method public static run ()V
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    putstatic Test lock Ljava/lang/Object;
+    getstatic Test lock Ljava/lang/Object;
+    monitorenter
+    iconst 1
+    invokestatic Test allocate (I)V
+    invokestatic java/lang/System gc ()V
+    invokestatic java/lang/System runFinalization ()V
+    getstatic Test lock Ljava/lang/Object;
+    monitorexit
+    ret_void
+.end method
+";
+    let classes = common::load_assembly(&[SYSTEM, COUNTED, TEST, wait]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result = vm.call::<_, ()>(java_str!("Wait"), java_str!("run"), ());
+    assert!(matches!(result, Err(ExecuteError::Deadlock)));
+}
+
+/// Methods which hold objects through references, and return a bit for each
+/// condition they check, the first condition in the highest bit.
+const REFS: &str = "
+.class public Refs
+.super java/lang/Object
+
+; Returns `bits * 2 + 1` if `a` and `b` are the same object, and `bits * 2`
+; otherwise.
+.method public static bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    iload 0
+    iconst 1
+    ishl
+    aload 1
+    aload 2
+    if_acmp_ne Different
+    iconst 1
+    ior
+Different:
+    ireturn
+.end method
+
+.method public static newObject ()Ljava/lang/Object;
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    areturn
+.end method
+
+.method public static collect ()V
+    invokestatic java/lang/System gc ()V
+    invokestatic java/lang/System runFinalization ()V
+    ret_void
+.end method
+
+; Checks that a weak reference to an object nothing else holds is cleared and
+; enqueued, while one to an object held by a local is kept.
+.method public static weak ()I
+    new java/lang/ref/ReferenceQueue
+    dup
+    invokespecial java/lang/ref/ReferenceQueue <init> ()V
+    astore 0
+    new java/lang/ref/WeakReference
+    dup
+    invokestatic Refs newObject ()Ljava/lang/Object;
+    aload 0
+    invokespecial java/lang/ref/WeakReference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    astore 1
+    invokestatic Refs newObject ()Ljava/lang/Object;
+    astore 2
+    new java/lang/ref/WeakReference
+    dup
+    aload 2
+    aload 0
+    invokespecial java/lang/ref/WeakReference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    astore 3
+    invokestatic Refs collect ()V
+    iconst 0
+    aload 1
+    invokevirtual java/lang/ref/Reference get ()Ljava/lang/Object;
+    aconst_null
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    aload 3
+    invokevirtual java/lang/ref/Reference get ()Ljava/lang/Object;
+    aload 2
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    aload 0
+    invokevirtual java/lang/ref/ReferenceQueue poll ()Ljava/lang/ref/Reference;
+    aload 1
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    aload 0
+    invokevirtual java/lang/ref/ReferenceQueue poll ()Ljava/lang/ref/Reference;
+    aconst_null
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    ireturn
+.end method
+
+; Checks that a soft reference to an array of `length` ints is kept by a
+; collection, and whether it is kept once an array of `pressure` ints is
+; allocated.
+.method public static soft (II)I
+    new java/lang/ref/SoftReference
+    dup
+    iload 0
+    newarray int
+    invokespecial java/lang/ref/SoftReference <init> (Ljava/lang/Object;)V
+    astore 2
+    invokestatic Refs collect ()V
+    iconst 0
+    aload 2
+    invokevirtual java/lang/ref/Reference get ()Ljava/lang/Object;
+    aconst_null
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    iload 1
+    newarray int
+    astore 3
+    aload 2
+    invokevirtual java/lang/ref/Reference get ()Ljava/lang/Object;
+    aconst_null
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    ireturn
+.end method
+
+; Checks that a phantom reference to a finalizable object is not enqueued by
+; the collection which finds the object unreachable but once its finalizer
+; has run, and returns the bits after the number of objects finalized.
+.method public static phantom ()I
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    putstatic Test lock Ljava/lang/Object;
+    new java/lang/ref/ReferenceQueue
+    dup
+    invokespecial java/lang/ref/ReferenceQueue <init> ()V
+    astore 0
+    new java/lang/ref/PhantomReference
+    dup
+    new Counted
+    dup
+    invokespecial Counted <init> ()V
+    aload 0
+    invokespecial java/lang/ref/PhantomReference <init> (Ljava/lang/Object;Ljava/lang/ref/ReferenceQueue;)V
+    astore 1
+    invokestatic Refs collect ()V
+    getstatic Test finalized I
+    aload 0
+    invokevirtual java/lang/ref/ReferenceQueue poll ()Ljava/lang/ref/Reference;
+    aconst_null
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    invokestatic Refs collect ()V
+    aload 0
+    invokevirtual java/lang/ref/ReferenceQueue poll ()Ljava/lang/ref/Reference;
+    aload 1
+    invokestatic Refs bit (ILjava/lang/Object;Ljava/lang/Object;)I
+    ireturn
+.end method
+";
+
+/// Loads [`REFS`] with the reference classes and calls its static method
+/// `name` with `args`.
+fn call_refs<A: ToJavaArgs>(name: &str, args: A, options: ExecuteOptions) -> i32 {
+    let classes = common::load_assembly(&[
+        SYSTEM,
+        COUNTED,
+        TEST,
+        REFERENCE,
+        SOFT_REFERENCE,
+        WEAK_REFERENCE,
+        PHANTOM_REFERENCE,
+        REFERENCE_QUEUE,
+        REFS,
+    ]);
+    let mut vm = Vm::new(&classes, options);
+    let name = JavaStr::from_java(name.as_bytes()).unwrap();
+    vm.call(java_str!("Refs"), name, args).unwrap()
+}
+
+#[test]
+fn weak_references_are_cleared_and_enqueued() {
+    let result = call_refs("weak", (), ExecuteOptions::default());
+    assert_eq!(result, 0b1111);
+}
+
+#[test]
+fn soft_references_are_cleared_only_under_pressure() {
+    let options = || ExecuteOptions {
+        heap_size: 1 << 16,
+        ..ExecuteOptions::default()
+    };
+    // The referent and the new array fit in the heap together.
+    assert_eq!(call_refs("soft", (1000, 1000), options()), 0b00);
+    // The new array only fits once the referent is freed.
+    assert_eq!(call_refs("soft", (2000, 2500), options()), 0b01);
+}
+
+#[test]
+fn phantom_references_are_enqueued_after_finalization() {
+    let result = call_refs("phantom", (), ExecuteOptions::default());
+    assert_eq!(result, 0b111);
+}
@@ -0,0 +1,189 @@
+//! Runs classes which create and call the objects of classes defined by the
+//! host.
+
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{FieldType, MethodDescriptor, MethodFlags};
+use graphene_jvm::vm::host::HostClass;
+use graphene_jvm::vm::value::Value;
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const SYSTEM: &str = "
+.class public final java/lang/System
+.super java/lang/Object
+
+.method public static native gc ()V
+.end method
+";
+
+const TEST: &str = "
+.class public Test
+.super java/lang/Object
+
+; Adds to a tally created with `start`, and returns its total.
+.method public static total (I)I
+    new Tally
+    dup
+    iload 0
+    invokespecial Tally <init> (I)V
+    astore 1
+    aload 1
+    iconst 2
+    invokevirtual Tally add (I)V
+    aload 1
+    iconst 3
+    invokevirtual Tally add (I)V
+    aload 1
+    invokevirtual Tally total ()I
+    ireturn
+.end method
+
+; Creates a tally which is dropped and one which is kept across a collection,
+; and returns the number of payloads dropped.
+.method public static collect ()I
+    new Tally
+    dup
+    iconst 0
+    invokespecial Tally <init> (I)V
+    pop
+    new Tally
+    dup
+    iconst 0
+    invokespecial Tally <init> (I)V
+    astore 0
+    invokestatic java/lang/System gc ()V
+    invokestatic Tally dropped ()I
+    aload 0
+    invokevirtual Tally total ()I
+    iadd
+    ireturn
+.end method
+
+.method public static missing ()I
+    invokestatic Tally missing ()I
+    ireturn
+.end method
+
+.method public static broken ()I
+    invokestatic Tally broken ()I
+    ireturn
+.end method
+";
+
+/// The payload of a `Tally`, which counts its drops in `drops`.
+struct Payload {
+    total: i32,
+    drops: Rc<Cell<i32>>,
+}
+
+impl Drop for Payload {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
+
+fn descriptor(args: Vec<FieldType>, result: Option<FieldType>) -> MethodDescriptor {
+    MethodDescriptor::new(args, result)
+}
+
+/// Defines `Tally`, whose objects hold a running total as their payload.
+fn tally() -> HostClass {
+    let drops = Rc::new(Cell::new(0));
+    let mut class = HostClass::new(java_str!("Tally"));
+    let dropped = drops.clone();
+    class
+        .constructor(&descriptor(vec![FieldType::Int], None), move |call| {
+            let total = call.arg(0).unwrap();
+            let drops = drops.clone();
+            call.set_payload(Payload { total, drops });
+        })
+        .method(
+            MethodFlags::PUBLIC,
+            java_str!("add"),
+            &descriptor(vec![FieldType::Int], None),
+            |call| {
+                let value: i32 = call.arg(0)?;
+                call.payload::<Payload>()?.total += value;
+                None
+            },
+        )
+        .method(
+            MethodFlags::PUBLIC,
+            java_str!("total"),
+            &descriptor(vec![], Some(FieldType::Int)),
+            |call| Some(Value::Int(call.payload::<Payload>()?.total)),
+        )
+        .method(
+            MethodFlags::PUBLIC | MethodFlags::STATIC,
+            java_str!("dropped"),
+            &descriptor(vec![], Some(FieldType::Int)),
+            move |_| Some(Value::Int(dropped.get())),
+        )
+        .method(
+            MethodFlags::PUBLIC | MethodFlags::STATIC,
+            java_str!("missing"),
+            &descriptor(vec![], Some(FieldType::Int)),
+            |_| None,
+        )
+        .method(
+            MethodFlags::PUBLIC | MethodFlags::STATIC,
+            java_str!("broken"),
+            &descriptor(vec![], Some(FieldType::Int)),
+            |_| Some(Value::Long(0)),
+        );
+    class
+}
+
+fn classes() -> ClassManager {
+    let mut classes = common::load_assembly(&[SYSTEM, TEST]);
+    classes.define(tally());
+    classes
+}
+
+#[test]
+fn java_creates_and_calls_host_objects() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let total: i32 = vm
+        .call(java_str!("Test"), java_str!("total"), (10,))
+        .unwrap();
+    assert_eq!(total, 15);
+}
+
+#[test]
+fn payloads_of_collected_objects_are_dropped() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let dropped: i32 = vm
+        .call(java_str!("Test"), java_str!("collect"), ())
+        .unwrap();
+    assert_eq!(dropped, 1);
+}
+
+#[test]
+fn missing_results_throw_null_pointer_exceptions() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("missing"), ());
+    assert_eq!(
+        result,
+        Err(ExecuteError::Exception(JavaException::NullPointer))
+    );
+}
+
+#[test]
+fn results_of_the_wrong_type_are_rejected() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("broken"), ());
+    assert_eq!(
+        result,
+        Err(ExecuteError::HostResultMismatch {
+            descriptor: java_str!("()I").to_owned()
+        })
+    );
+}
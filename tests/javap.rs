@@ -0,0 +1,73 @@
+//! Runs `graphene-javap` on an assembled class file.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use graphene_jvm::vm::class::{assemble, write};
+
+const POINT: &str = "
+.class public Point
+.super java/lang/Object
+
+.field private x I
+
+.method public static origin ()I
+    iconst 0
+    ireturn
+.end method
+";
+
+/// Writes the class file of `Point` to `name` and returns its path. Each test
+/// writes its own file, as the tests run in parallel.
+fn point(name: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, write(&assemble(POINT).unwrap()).unwrap()).unwrap();
+    path
+}
+
+fn javap() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_graphene-javap"))
+}
+
+#[test]
+fn classes_are_disassembled() {
+    let output = javap()
+        .args(["-c", "-p"])
+        .arg(point("Point.class"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("public class Point {\n"), "{stdout}");
+    assert!(stdout.contains("  private int x;\n"), "{stdout}");
+    assert!(
+        stdout.contains("  public static int origin();\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("       0: iconst_0\n"), "{stdout}");
+    assert!(stdout.contains("       1: ireturn\n"), "{stdout}");
+    assert!(stdout.ends_with("}\n"), "{stdout}");
+}
+
+#[test]
+fn closed_pipes_end_the_output_quietly() {
+    let path = point("Piped.class");
+    let mut child = javap()
+        .arg("-v")
+        .args(std::iter::repeat_n(&path, 1000))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(stderr, "");
+}
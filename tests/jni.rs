@@ -0,0 +1,507 @@
+//! Calls the native methods of a library built from `tests/jni/natives.c`
+//! with the C compiler and the JNI headers of the JDK `JAVA_HOME` names, or
+//! else of the one whose `javac` is on the `PATH`.
+
+#![cfg(feature = "jni")]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::heap::ObjectRef;
+use graphene_jvm::vm::jni::{map_library_name, LoadError};
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const STRING: &str = "
+.class public final java/lang/String
+.super java/lang/Object
+
+.field private final value [B
+.field private final coder B
+";
+
+const SYSTEM: &str = "
+.class public final java/lang/System
+.super java/lang/Object
+
+.method public static native load (Ljava/lang/String;)V
+.end method
+
+.method public static native loadLibrary (Ljava/lang/String;)V
+.end method
+";
+
+const ILLEGAL_STATE: &str = "
+.class public java/lang/IllegalStateException
+.super java/lang/Object
+";
+
+const THREAD: &str = "
+.class public java/lang/Thread
+.super java/lang/Object
+
+.field public daemon Z
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public run ()V
+    ret_void
+.end method
+
+.method public final native start0 ()V
+.end method
+
+.method public final native join (J)V
+.end method
+";
+
+/// A thread which sums the numbers up to `count` in Java called by a native
+/// method.
+const LOOPER: &str = "
+.class public Looper
+.super java/lang/Thread
+
+.field public count I
+.field public sum I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    aload 0
+    iload 1
+    putfield Looper count I
+    ret_void
+.end method
+
+.method public run ()V
+    aload 0
+    aload 0
+    getfield Looper count I
+    invokestatic Native callLoop (I)I
+    putfield Looper sum I
+    ret_void
+.end method
+";
+
+const NATIVE: &str = "
+.class public Native
+.super java/lang/Object
+
+.field public x I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    aload 0
+    iload 1
+    putfield Native x I
+    ret_void
+.end method
+
+.method public static twice (I)I
+    iload 0
+    iconst 2
+    imul
+    ireturn
+.end method
+
+.method public plus (I)I
+    aload 0
+    getfield Native x I
+    iload 1
+    iadd
+    ireturn
+.end method
+
+; Sums the numbers up to the argument.
+.method public static loop (I)I
+    iconst 0
+    istore 1
+Loop:
+    iload 0
+    if_le Done
+    iload 1
+    iload 0
+    iadd
+    istore 1
+    iinc 0 -1
+    goto Loop
+Done:
+    iload 1
+    ireturn
+.end method
+
+.method public synchronized locked ()I
+    aload 0
+    getfield Native x I
+    ireturn
+.end method
+
+.method public static native add (II)I
+.end method
+
+.method public static native mix (IDJF)D
+.end method
+
+.method public static native over (I)I
+.end method
+
+.method public static native over (J)I
+.end method
+
+.method public native getX ()I
+.end method
+
+.method public static native make (I)LNative;
+.end method
+
+.method public static native registered (I)I
+.end method
+
+.method public static native fail ()V
+.end method
+
+.method public static native greeting ()Ljava/lang/String;
+.end method
+
+.method public static native back (I)I
+.end method
+
+.method public static native callLoop (I)I
+.end method
+
+.method public static native describe (Ljava/lang/String;)Ljava/lang/String;
+.end method
+
+.method public static native squares (I)[I
+.end method
+
+.method public static native sum ([I)I
+.end method
+
+.method public static native overrun ([I)I
+.end method
+
+.method public native synchronizedX ()I
+.end method
+
+.method public static native direct ()Ljava/lang/Object;
+.end method
+
+.method public static native missing ()V
+.end method
+
+.method public static load (Ljava/lang/String;)I
+    aload 0
+    invokestatic java/lang/System load (Ljava/lang/String;)V
+    iconst 1
+    iconst 2
+    invokestatic Native add (II)I
+    ireturn
+.end method
+
+.method public static loadLibrary (Ljava/lang/String;)I
+    aload 0
+    invokestatic java/lang/System loadLibrary (Ljava/lang/String;)V
+    iconst 4
+    invokestatic Native registered (I)I
+    ireturn
+.end method
+
+.method public static x (I)I
+    iload 0
+    invokestatic Native make (I)LNative;
+    invokevirtual Native getX ()I
+    ireturn
+.end method
+
+.method public static callBack (I)I
+    iload 0
+    invokestatic Native back (I)I
+    ireturn
+.end method
+
+; Sums the numbers up to the argument on this thread and on a `Looper`, both
+; in Java called by a native method.
+.method public static together (I)I
+    new Looper
+    dup
+    iload 0
+    invokespecial Looper <init> (I)V
+    astore 1
+    aload 1
+    invokevirtual java/lang/Thread start0 ()V
+    iload 0
+    invokestatic Native callLoop (I)I
+    aload 1
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    aload 1
+    getfield Looper sum I
+    iadd
+    ireturn
+.end method
+
+.method public static sumOfSquares (I)I
+    iload 0
+    invokestatic Native squares (I)[I
+    invokestatic Native sum ([I)I
+    ireturn
+.end method
+
+.method public static lockedX (I)I
+    iload 0
+    invokestatic Native make (I)LNative;
+    invokevirtual Native synchronizedX ()I
+    ireturn
+.end method
+";
+
+/// Returns the path of the library, which is built the first time.
+fn library() -> &'static Path {
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let java_home = std::env::var_os("JAVA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                let path = std::env::var_os("PATH")?;
+                let javac = std::env::split_paths(&path)
+                    .map(|directory| directory.join("javac"))
+                    .find(|javac| javac.is_file())?;
+                let javac = javac.canonicalize().ok()?;
+                Some(javac.parent()?.parent()?.to_owned())
+            })
+            .expect("JAVA_HOME is set or javac is on the PATH");
+        let include = java_home.join("include");
+        let library = Path::new(env!("CARGO_TARGET_TMPDIR")).join(map_library_name("natives"));
+        let status = Command::new(std::env::var_os("CC").unwrap_or("cc".into()))
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&library)
+            .arg("-I")
+            .arg(&include)
+            .arg("-I")
+            .arg(include.join("linux"))
+            .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/jni/natives.c"))
+            .status()
+            .expect("the C compiler runs");
+        assert!(status.success(), "tests/jni/natives.c compiles");
+        library
+    })
+}
+
+fn classes() -> ClassManager {
+    common::load_assembly(&[STRING, SYSTEM, ILLEGAL_STATE, THREAD, LOOPER, NATIVE])
+}
+
+/// Returns the classes with the library loaded by the host.
+fn loaded() -> ClassManager {
+    let classes = classes();
+    // SAFETY: The library follows the JNI.
+    unsafe { classes.load_library(library()) }.unwrap();
+    classes
+}
+
+#[test]
+fn native_methods_take_and_return_primitives() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("add"), (2, 40))
+        .unwrap();
+    assert_eq!(sum, 42);
+    let mix: f64 = vm
+        .call(
+            java_str!("Native"),
+            java_str!("mix"),
+            (3, 0.5, 10i64, 0.25f32),
+        )
+        .unwrap();
+    assert_eq!(mix, 13.75);
+    let int: i32 = vm
+        .call(java_str!("Native"), java_str!("over"), (0,))
+        .unwrap();
+    let long: i32 = vm
+        .call(java_str!("Native"), java_str!("over"), (0i64,))
+        .unwrap();
+    assert_eq!((int, long), (1, 2));
+}
+
+#[test]
+fn native_methods_create_objects_and_access_their_fields() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let x: i32 = vm.call(java_str!("Native"), java_str!("x"), (9,)).unwrap();
+    assert_eq!(x, 9);
+}
+
+#[test]
+fn native_methods_are_registered_by_jni_on_load() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let value: i32 = vm
+        .call(java_str!("Native"), java_str!("registered"), (5,))
+        .unwrap();
+    assert_eq!(value, 50);
+}
+
+#[test]
+fn programs_load_libraries_by_path() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let path = library().to_str().unwrap();
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("load"), (path,))
+        .unwrap();
+    assert_eq!(sum, 3);
+    // A library is loaded once.
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("load"), (path,))
+        .unwrap();
+    assert_eq!(sum, 3);
+
+    let result: Result<i32, _> = vm.call(java_str!("Native"), java_str!("load"), ("natives",));
+    let Err(ExecuteError::Exception(JavaException::Native { class, .. })) = result else {
+        panic!("a relative path is not loaded: {result:?}");
+    };
+    assert_eq!(class, java_str!("java/lang/UnsatisfiedLinkError"));
+}
+
+#[test]
+fn programs_load_libraries_from_the_library_path() {
+    let mut classes = classes();
+    let directory = library().parent().unwrap().to_owned();
+    classes.set_library_path(vec![PathBuf::from("/nonexistent"), directory]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let value: i32 = vm
+        .call(java_str!("Native"), java_str!("loadLibrary"), ("natives",))
+        .unwrap();
+    assert_eq!(value, 40);
+
+    let result: Result<i32, _> =
+        vm.call(java_str!("Native"), java_str!("loadLibrary"), ("missing",));
+    assert_eq!(
+        result,
+        Err(LoadError::NotFound("missing".to_owned()).into())
+    );
+}
+
+#[test]
+fn exceptions_left_pending_are_errors() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<(), _> = vm.call(java_str!("Native"), java_str!("fail"), ());
+    assert_eq!(
+        result,
+        Err(ExecuteError::Exception(JavaException::Native {
+            class: java_str!("java/lang/IllegalStateException").to_owned(),
+            message: Some("failed".to_owned()),
+        }))
+    );
+}
+
+#[test]
+fn native_methods_call_java_methods() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    // Called by the host and by Java.
+    let value: i32 = vm
+        .call(java_str!("Native"), java_str!("back"), (5,))
+        .unwrap();
+    assert_eq!(value, 34);
+    let value: i32 = vm
+        .call(java_str!("Native"), java_str!("callBack"), (5,))
+        .unwrap();
+    assert_eq!(value, 34);
+}
+
+#[test]
+fn threads_run_java_called_by_native_methods_together() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("together"), (30000,))
+        .unwrap();
+    assert_eq!(sum, 900_030_000);
+}
+
+#[test]
+fn native_methods_create_and_read_strings() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let greeting: String = vm
+        .call(java_str!("Native"), java_str!("greeting"), ())
+        .unwrap();
+    assert_eq!(greeting, "hello");
+    let description: String = vm
+        .call(java_str!("Native"), java_str!("describe"), ("h\u{e9}llo",))
+        .unwrap();
+    assert_eq!(description, "h\u{e9}llo has 5 characters");
+}
+
+#[test]
+fn native_methods_create_fill_and_read_arrays() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("sumOfSquares"), (4,))
+        .unwrap();
+    assert_eq!(sum, 30);
+    let sum: i32 = vm
+        .call(java_str!("Native"), java_str!("sum"), (vec![1, 2, 3],))
+        .unwrap();
+    assert_eq!(sum, 6);
+
+    let result: Result<i32, _> = vm.call(
+        java_str!("Native"),
+        java_str!("overrun"),
+        (vec![1, 2, 3, 4],),
+    );
+    assert_eq!(
+        result,
+        Err(ExecuteError::Exception(
+            JavaException::ArrayIndexOutOfBounds {
+                index: 4,
+                length: 4
+            }
+        ))
+    );
+}
+
+#[test]
+fn native_methods_enter_and_exit_monitors() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let x: i32 = vm
+        .call(java_str!("Native"), java_str!("lockedX"), (7,))
+        .unwrap();
+    assert_eq!(x, 7);
+}
+
+#[test]
+fn unsupported_functions_are_errors() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<Option<ObjectRef>, _> =
+        vm.call(java_str!("Native"), java_str!("direct"), ());
+    assert_eq!(
+        result,
+        Err(ExecuteError::Unsupported(
+            "the JNI function NewDirectByteBuffer".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn methods_no_library_implements_are_unsatisfied_links() {
+    let classes = loaded();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let result: Result<(), _> = vm.call(java_str!("Native"), java_str!("missing"), ());
+    assert!(matches!(result, Err(ExecuteError::Linkage(_))));
+}
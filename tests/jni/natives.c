@@ -0,0 +1,150 @@
+/* The native methods of the class `Native` in tests/jni.rs. */
+
+#include <jni.h>
+#include <stdio.h>
+
+JNIEXPORT jint JNICALL Java_Native_add(JNIEnv *env, jclass class, jint a, jint b) {
+    return a + b;
+}
+
+JNIEXPORT jdouble JNICALL Java_Native_mix(JNIEnv *env, jclass class, jint a, jdouble b, jlong c,
+                                          jfloat d) {
+    return a + b + c + d;
+}
+
+JNIEXPORT jint JNICALL Java_Native_over__I(JNIEnv *env, jclass class, jint value) {
+    return 1;
+}
+
+JNIEXPORT jint JNICALL Java_Native_over__J(JNIEnv *env, jclass class, jlong value) {
+    return 2;
+}
+
+JNIEXPORT jint JNICALL Java_Native_getX(JNIEnv *env, jobject this) {
+    jclass class = (*env)->GetObjectClass(env, this);
+    return (*env)->GetIntField(env, this, (*env)->GetFieldID(env, class, "x", "I"));
+}
+
+JNIEXPORT jobject JNICALL Java_Native_make(JNIEnv *env, jclass class, jint x) {
+    jobject object = (*env)->AllocObject(env, class);
+    (*env)->SetIntField(env, object, (*env)->GetFieldID(env, class, "x", "I"), x);
+    return object;
+}
+
+JNIEXPORT void JNICALL Java_Native_fail(JNIEnv *env, jclass class) {
+    jclass exception = (*env)->FindClass(env, "java/lang/IllegalStateException");
+    (*env)->ThrowNew(env, exception, "failed");
+}
+
+JNIEXPORT jobject JNICALL Java_Native_greeting(JNIEnv *env, jclass class) {
+    jobject greeting = (*env)->NewStringUTF(env, "hello");
+    if ((*env)->ExceptionCheck(env)) {
+        return NULL;
+    }
+    return greeting;
+}
+
+static jint call_plus(JNIEnv *env, jobject object, jmethodID plus, ...) {
+    va_list args;
+    va_start(args, plus);
+    jint result = (*env)->CallIntMethodV(env, object, plus, args);
+    va_end(args);
+    return result;
+}
+
+/* Calls the static method `twice`, then the constructor and the method `plus`, passing the
+ * arguments of the last as variadic arguments, a `va_list` and an array of `jvalue`s. */
+JNIEXPORT jint JNICALL Java_Native_back(JNIEnv *env, jclass class, jint value) {
+    jmethodID twice = (*env)->GetStaticMethodID(env, class, "twice", "(I)I");
+    jint doubled = (*env)->CallStaticIntMethod(env, class, twice, value);
+    jmethodID init = (*env)->GetMethodID(env, class, "<init>", "(I)V");
+    jobject object = (*env)->NewObject(env, class, init, doubled);
+    jmethodID plus = (*env)->GetMethodID(env, class, "plus", "(I)I");
+    jvalue one = {.i = 1};
+    return (*env)->CallIntMethod(env, object, plus, 1) + call_plus(env, object, plus, 2)
+           + (*env)->CallIntMethodA(env, object, plus, &one);
+}
+
+JNIEXPORT jint JNICALL Java_Native_callLoop(JNIEnv *env, jclass class, jint count) {
+    jmethodID loop = (*env)->GetStaticMethodID(env, class, "loop", "(I)I");
+    return (*env)->CallStaticIntMethod(env, class, loop, count);
+}
+
+JNIEXPORT jstring JNICALL Java_Native_describe(JNIEnv *env, jclass class, jstring name) {
+    const char *chars = (*env)->GetStringUTFChars(env, name, NULL);
+    if (chars == NULL) {
+        return NULL;
+    }
+    char description[64];
+    snprintf(description, sizeof description, "%s has %d characters", chars,
+             (int) (*env)->GetStringLength(env, name));
+    (*env)->ReleaseStringUTFChars(env, name, chars);
+    return (*env)->NewStringUTF(env, description);
+}
+
+JNIEXPORT jintArray JNICALL Java_Native_squares(JNIEnv *env, jclass class, jint count) {
+    jintArray array = (*env)->NewIntArray(env, count);
+    if (array == NULL) {
+        return NULL;
+    }
+    jint *elements = (*env)->GetIntArrayElements(env, array, NULL);
+    for (jint i = 0; i < count; i++) {
+        elements[i] = (i + 1) * (i + 1);
+    }
+    (*env)->ReleaseIntArrayElements(env, array, elements, 0);
+    return array;
+}
+
+JNIEXPORT jint JNICALL Java_Native_sum(JNIEnv *env, jclass class, jintArray array) {
+    jint total = 0;
+    for (jsize i = 0; i < (*env)->GetArrayLength(env, array); i++) {
+        jint element;
+        (*env)->GetIntArrayRegion(env, array, i, 1, &element);
+        total += element;
+    }
+    return total;
+}
+
+/* Reads past the end of the array, which leaves an exception pending. */
+JNIEXPORT jint JNICALL Java_Native_overrun(JNIEnv *env, jclass class, jintArray array) {
+    jint elements[8];
+    (*env)->GetIntArrayRegion(env, array, 2, 5, elements);
+    return 0;
+}
+
+/* Calls the synchronized method `locked` with the monitor of the object entered, and then exits
+ * it once too often, which throws an exception it clears. */
+JNIEXPORT jint JNICALL Java_Native_synchronizedX(JNIEnv *env, jobject this) {
+    if ((*env)->MonitorEnter(env, this) != JNI_OK) {
+        return -1;
+    }
+    jclass class = (*env)->GetObjectClass(env, this);
+    jint x = (*env)->CallIntMethod(env, this, (*env)->GetMethodID(env, class, "locked", "()I"));
+    (*env)->MonitorExit(env, this);
+    if ((*env)->MonitorExit(env, this) != JNI_ERR || !(*env)->ExceptionCheck(env)) {
+        return -1;
+    }
+    (*env)->ExceptionClear(env);
+    return x;
+}
+
+JNIEXPORT jobject JNICALL Java_Native_direct(JNIEnv *env, jclass class) {
+    return (*env)->NewDirectByteBuffer(env, NULL, 0);
+}
+
+static jint times_ten(JNIEnv *env, jclass class, jint value) {
+    return value * 10;
+}
+
+JNIEXPORT jint JNICALL JNI_OnLoad(JavaVM *vm, void *reserved) {
+    JNIEnv *env;
+    if ((*vm)->GetEnv(vm, (void **) &env, JNI_VERSION_1_8) != JNI_OK) {
+        return JNI_ERR;
+    }
+    jclass class = (*env)->FindClass(env, "Native");
+    JNINativeMethod methods[] = {{"registered", "(I)I", (void *) times_ten}};
+    if ((*env)->RegisterNatives(env, class, methods, 1) != JNI_OK) {
+        return JNI_ERR;
+    }
+    return JNI_VERSION_1_8;
+}
@@ -0,0 +1,520 @@
+//! Runs classes whose references to other classes and their members fail to
+//! resolve or link, which ends them with a linkage error, and classes whose
+//! calls select methods by the overriding rules.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::{ExecuteError, ExecuteOptions, LinkageError, Vm};
+
+const ANIMAL: &str = "
+.class public Animal
+.super java/lang/Object
+
+.field public legs I
+.field public static count I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public legs ()I
+    iconst 4
+    ireturn
+.end method
+
+.method public static create ()LAnimal;
+    new Animal
+    dup
+    invokespecial Animal <init> ()V
+    areturn
+.end method
+";
+
+const SHAPE: &str = "
+.class public abstract Shape
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public abstract area ()I
+.end method
+";
+
+/// A subclass of `Shape` which does not implement `area`, as if it had been
+/// compiled against an older version of `Shape`.
+const SQUARE: &str = "
+.class public Square
+.super Shape
+
+.method public <init> ()V
+    aload 0
+    invokespecial Shape <init> ()V
+    ret_void
+.end method
+";
+
+const NAMED: &str = "
+.class public interface abstract Named
+.super java/lang/Object
+";
+
+/// A class whose superclass is not loaded.
+const ORPHAN: &str = "
+.class public Orphan
+.super Missing
+
+.method public <init> ()V
+    aload 0
+    invokespecial Missing <init> ()V
+    ret_void
+.end method
+";
+
+/// A package-private method, and a method calling it, in the package `zoo`.
+const ZOO_ANIMAL: &str = "
+.class public zoo/Animal
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method sound ()I
+    iconst 1
+    ireturn
+.end method
+
+.method public speak ()I
+    aload 0
+    invokevirtual zoo/Animal sound ()I
+    ireturn
+.end method
+";
+
+/// A subclass in the same package, which overrides `sound`.
+const ZOO_CAT: &str = "
+.class public zoo/Cat
+.super zoo/Animal
+
+.method public <init> ()V
+    aload 0
+    invokespecial zoo/Animal <init> ()V
+    ret_void
+.end method
+
+.method sound ()I
+    iconst 2
+    ireturn
+.end method
+";
+
+/// A subclass in another package, whose `sound` is a new method.
+const FARM_COW: &str = "
+.class public farm/Cow
+.super zoo/Animal
+
+.method public <init> ()V
+    aload 0
+    invokespecial zoo/Animal <init> ()V
+    ret_void
+.end method
+
+.method public sound ()I
+    iconst 3
+    ireturn
+.end method
+";
+
+const SEALED: &str = "
+.class public Sealed
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public final id ()I
+    iconst 1
+    ireturn
+.end method
+";
+
+const UNSEALED: &str = "
+.class public Unsealed
+.super Sealed
+
+.method public <init> ()V
+    aload 0
+    invokespecial Sealed <init> ()V
+    ret_void
+.end method
+
+.method public id ()I
+    iconst 2
+    ireturn
+.end method
+";
+
+const GREETER: &str = "
+.class public interface abstract Greeter
+.super java/lang/Object
+
+.method public greet ()I
+    iconst 1
+    ireturn
+.end method
+";
+
+/// A subinterface of `Greeter`, whose default method is more specific.
+const POLITE: &str = "
+.class public interface abstract Polite
+.super java/lang/Object
+.implements Greeter
+
+.method public greet ()I
+    iconst 2
+    ireturn
+.end method
+";
+
+/// An interface unrelated to `Greeter` with a default method of the same
+/// signature.
+const LOUD: &str = "
+.class public interface abstract Loud
+.super java/lang/Object
+
+.method public greet ()I
+    iconst 3
+    ireturn
+.end method
+";
+
+const HOST: &str = "
+.class public Host
+.super java/lang/Object
+.implements Greeter
+.implements Polite
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+const CLASH: &str = "
+.class public Clash
+.super java/lang/Object
+.implements Greeter
+.implements Loud
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+/// Assembles `methods` into a class `Test` and calls its static method `run`
+/// with no arguments.
+fn run(methods: &str) -> Result<i32, ExecuteError> {
+    let test = format!(".class public Test\n.super java/lang/Object\n{methods}");
+    let classes = common::load_assembly(&[
+        ANIMAL, SHAPE, SQUARE, NAMED, ORPHAN, ZOO_ANIMAL, ZOO_CAT, FARM_COW, SEALED, UNSEALED,
+        GREETER, POLITE, LOUD, HOST, CLASH, &test,
+    ]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    vm.call(java_str!("Test"), java_str!("run"), ())
+}
+
+/// Runs `methods` with [`run`] and returns the linkage error it ends with.
+fn linkage_error(methods: &str) -> LinkageError {
+    match run(methods) {
+        Err(ExecuteError::Linkage(error)) => error,
+        result => panic!("unexpected result {result:?}"),
+    }
+}
+
+#[test]
+fn missing_classes_are_not_found() {
+    let methods = "
+.method public static run ()I
+    new Missing
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoClassDefFound(java_str!("Missing").to_owned())
+    );
+
+    let methods = "
+.method public static run ()I
+    new Orphan
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoClassDefFound(java_str!("Missing").to_owned())
+    );
+}
+
+#[test]
+fn missing_fields_are_not_found() {
+    let methods = "
+.method public static run ()I
+    invokestatic Animal create ()LAnimal;
+    getfield Animal weight I
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoSuchField {
+            class: java_str!("Animal").to_owned(),
+            name: java_str!("weight").to_owned(),
+            descriptor: java_str!("I").to_owned(),
+        }
+    );
+
+    // Fields are looked up by their descriptor as well as their name.
+    let methods = "
+.method public static run ()I
+    getstatic Animal count J
+    l2i
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoSuchField {
+            class: java_str!("Animal").to_owned(),
+            name: java_str!("count").to_owned(),
+            descriptor: java_str!("J").to_owned(),
+        }
+    );
+}
+
+#[test]
+fn missing_methods_are_not_found() {
+    let methods = "
+.method public static run ()I
+    invokestatic Animal create ()LAnimal;
+    invokevirtual Animal wings ()I
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoSuchMethod {
+            class: java_str!("Animal").to_owned(),
+            name: java_str!("wings").to_owned(),
+            descriptor: java_str!("()I").to_owned(),
+        }
+    );
+
+    let methods = "
+.method public static run ()I
+    invokestatic Animal create ()I
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::NoSuchMethod {
+            class: java_str!("Animal").to_owned(),
+            name: java_str!("create").to_owned(),
+            descriptor: java_str!("()I").to_owned(),
+        }
+    );
+}
+
+#[test]
+fn members_accessed_as_the_wrong_kind_are_rejected() {
+    let accesses = [
+        // A static access of an instance field.
+        "getstatic Animal legs I",
+        // An instance access of a static field.
+        "invokestatic Animal create ()LAnimal;
+    getfield Animal count I",
+        // A static call of an instance method.
+        "invokestatic Animal legs ()I",
+        // An instance call of a static method.
+        "aconst_null
+    invokevirtual Animal create ()LAnimal;
+    pop
+    iconst 0",
+        // An interface call of a method of a class.
+        "invokestatic Animal create ()LAnimal;
+    invokeinterface Animal legs ()I",
+    ];
+    for access in accesses {
+        let methods = format!(
+            "
+.method public static run ()I
+    {access}
+    ireturn
+.end method
+"
+        );
+        assert_eq!(
+            linkage_error(&methods),
+            LinkageError::IncompatibleClassChange,
+            "{access}"
+        );
+    }
+}
+
+#[test]
+fn abstract_classes_and_interfaces_are_not_instantiated() {
+    for class in ["Shape", "Named"] {
+        let methods = format!(
+            "
+.method public static run ()I
+    new {class}
+    pop
+    iconst 0
+    ireturn
+.end method
+"
+        );
+        let LinkageError::Instantiation(name) = linkage_error(&methods) else {
+            panic!("expected an instantiation error for {class}");
+        };
+        assert_eq!(name.to_string(), class);
+    }
+}
+
+#[test]
+fn calls_selecting_abstract_methods_are_rejected() {
+    let methods = "
+.method public static run ()I
+    new Square
+    dup
+    invokespecial Square <init> ()V
+    invokevirtual Shape area ()I
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::AbstractMethod {
+            class: java_str!("Shape").to_owned(),
+            name: java_str!("area").to_owned(),
+            descriptor: java_str!("()I").to_owned(),
+        }
+    );
+}
+
+#[test]
+fn final_methods_are_not_overridden() {
+    let methods = "
+.method public static run ()I
+    new Unsealed
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(
+        linkage_error(methods),
+        LinkageError::OverridesFinal {
+            class: java_str!("Unsealed").to_owned(),
+            name: java_str!("id").to_owned(),
+            descriptor: java_str!("()I").to_owned(),
+        }
+    );
+}
+
+#[test]
+fn package_private_methods_are_overridden_only_within_their_package() {
+    let speak = |class: &str| {
+        let methods = format!(
+            "
+.method public static run ()I
+    new {class}
+    dup
+    invokespecial {class} <init> ()V
+    invokevirtual zoo/Animal speak ()I
+    ireturn
+.end method
+"
+        );
+        run(&methods).unwrap()
+    };
+    assert_eq!(speak("zoo/Animal"), 1);
+    assert_eq!(speak("zoo/Cat"), 2);
+    assert_eq!(speak("farm/Cow"), 1);
+
+    // The method of the other package is still selected by its own calls.
+    let methods = "
+.method public static run ()I
+    new farm/Cow
+    dup
+    invokespecial farm/Cow <init> ()V
+    invokevirtual farm/Cow sound ()I
+    ireturn
+.end method
+";
+    assert_eq!(run(methods).unwrap(), 3);
+}
+
+#[test]
+fn the_maximally_specific_default_method_is_selected() {
+    for call in [
+        "invokeinterface Greeter greet ()I",
+        "invokeinterface Polite greet ()I",
+        "invokevirtual Host greet ()I",
+    ] {
+        let methods = format!(
+            "
+.method public static run ()I
+    new Host
+    dup
+    invokespecial Host <init> ()V
+    {call}
+    ireturn
+.end method
+"
+        );
+        assert_eq!(run(&methods).unwrap(), 2, "{call}");
+    }
+}
+
+#[test]
+fn ambiguous_default_methods_are_rejected() {
+    for interface in ["Greeter", "Loud"] {
+        let methods = format!(
+            "
+.method public static run ()I
+    new Clash
+    dup
+    invokespecial Clash <init> ()V
+    invokeinterface {interface} greet ()I
+    ireturn
+.end method
+"
+        );
+        assert_eq!(
+            linkage_error(&methods),
+            LinkageError::IncompatibleClassChange,
+            "{interface}"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Runs classes which cast objects and test their classes, in each tier.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::{ClassManager, ExecuteOptions, Vm};
+
+const NAMED: &str = "
+.class public interface abstract Named
+.super java/lang/Object
+";
+
+const ANIMAL: &str = "
+.class public Animal
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public legs ()I
+    iconst 0
+    ireturn
+.end method
+";
+
+const DOG: &str = "
+.class public Dog
+.super Animal
+.implements Named
+
+.method public <init> ()V
+    aload 0
+    invokespecial Animal <init> ()V
+    ret_void
+.end method
+
+.method public legs ()I
+    iconst 4
+    ireturn
+.end method
+";
+
+const ROCK: &str = "
+.class public Rock
+.super java/lang/Object
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+const TEST: &str = "
+.class public Test
+.super java/lang/Object
+
+.method public static make (I)Ljava/lang/Object;
+    iload 0
+    if_ne NotDog
+    new Dog
+    dup
+    invokespecial Dog <init> ()V
+    areturn
+NotDog:
+    iload 0
+    iconst 1
+    if_icmp_ne NotAnimal
+    new Animal
+    dup
+    invokespecial Animal <init> ()V
+    areturn
+NotAnimal:
+    iload 0
+    iconst 2
+    if_icmp_ne Null
+    new Rock
+    dup
+    invokespecial Rock <init> ()V
+    areturn
+Null:
+    aconst_null
+    areturn
+.end method
+
+.method public static kind (I)I
+    iload 0
+    invokestatic Test make (I)Ljava/lang/Object;
+    astore 1
+    aload 1
+    instanceof Animal
+    aload 1
+    instanceof Dog
+    iconst 1
+    ishl
+    ior
+    aload 1
+    instanceof Named
+    iconst 2
+    ishl
+    ior
+    aload 1
+    instanceof Rock
+    iconst 3
+    ishl
+    ior
+    ireturn
+.end method
+
+.method public static legs (I)I
+    iload 0
+    invokestatic Test make (I)Ljava/lang/Object;
+    checkcast Animal
+    invokevirtual Animal legs ()I
+    ireturn
+.end method
+
+.method public static castNull ()Z
+    aconst_null
+    checkcast Rock
+    ifnull Null
+    iconst 0
+    ireturn
+Null:
+    iconst 1
+    ireturn
+.end method
+
+.method public static total (I)I
+    iconst 0
+    istore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    iload 0
+    if_icmp_ge Done
+    iload 1
+    iload 2
+    iconst 3
+    iand
+    invokestatic Test kind (I)I
+    iadd
+    istore 1
+    iload 2
+    iconst 1
+    iadd
+    istore 2
+    goto Loop
+Done:
+    iload 1
+    ireturn
+.end method
+";
+
+fn classes() -> ClassManager {
+    common::load_assembly(&[NAMED, ANIMAL, DOG, ROCK, TEST])
+}
+
+fn total(options: ExecuteOptions) -> i32 {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, options);
+    vm.call(java_str!("Test"), java_str!("total"), (4000,))
+        .unwrap()
+}
+
+#[test]
+fn instanceof_tests_superclasses_and_interfaces() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let mut kind = |i: i32| -> i32 { vm.call(java_str!("Test"), java_str!("kind"), (i,)).unwrap() };
+    assert_eq!(kind(0), 0b0111);
+    assert_eq!(kind(1), 0b0001);
+    assert_eq!(kind(2), 0b1000);
+    assert_eq!(kind(3), 0);
+}
+
+#[test]
+fn checkcast_passes_instances_and_null() {
+    let classes = classes();
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let legs: i32 = vm.call(java_str!("Test"), java_str!("legs"), (0,)).unwrap();
+    assert_eq!(legs, 4);
+    let legs: i32 = vm.call(java_str!("Test"), java_str!("legs"), (1,)).unwrap();
+    assert_eq!(legs, 0);
+    let cast: bool = vm
+        .call(java_str!("Test"), java_str!("castNull"), ())
+        .unwrap();
+    assert!(cast);
+}
+
+#[test]
+fn tiers_agree_on_instanceof() {
+    let interpreted = total(ExecuteOptions {
+        tiered: false,
+        ..ExecuteOptions::default()
+    });
+    assert_eq!(interpreted, 1000 * (7 + 1 + 8));
+    let compiled = total(ExecuteOptions {
+        compile_threshold: 10,
+        #[cfg(feature = "jit")]
+        jit: false,
+        ..ExecuteOptions::default()
+    });
+    assert_eq!(compiled, interpreted);
+    #[cfg(feature = "jit")]
+    {
+        let jit = total(ExecuteOptions {
+            compile_threshold: 10,
+            ..ExecuteOptions::default()
+        });
+        assert_eq!(jit, interpreted);
+    }
+}
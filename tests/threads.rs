@@ -0,0 +1,684 @@
+//! Runs classes which start threads and share data between them, and
+//! classes which misuse threads and monitors, which ends them with the
+//! exception Java would throw.
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{assemble, write};
+use graphene_jvm::vm::convert::FromJavaResult;
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const OBJECT: &str = "
+.class public java/lang/Object
+
+.method public <init> ()V
+    ret_void
+.end method
+
+.method public final native wait (J)V
+.end method
+
+.method public final native notify ()V
+.end method
+";
+
+const THREAD: &str = "
+.class public java/lang/Thread
+.super java/lang/Object
+
+.field public daemon Z
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public run ()V
+    ret_void
+.end method
+
+.method public final native start0 ()V
+.end method
+
+.method public static native sleep (J)V
+.end method
+
+.method public final native join (J)V
+.end method
+
+.method public final native interrupt0 ()V
+.end method
+";
+
+const SLEEPER: &str = "
+.class public Sleeper
+.super java/lang/Thread
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    ret_void
+.end method
+
+.method public run ()V
+    lconst 10000
+    invokestatic java/lang/Thread sleep (J)V
+    ret_void
+.end method
+";
+
+/// Loads the classes above and `sources`, and calls the static method `run`
+/// of the class `Test`.
+fn run<R: FromJavaResult>(sources: &[&str]) -> Result<R, ExecuteError> {
+    let mut classes = ClassManager::new();
+    for source in [OBJECT, THREAD, SLEEPER].iter().chain(sources) {
+        classes
+            .load(&write(&assemble(source).unwrap()).unwrap())
+            .unwrap();
+    }
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    vm.call(java_str!("Test"), java_str!("run"), ())
+}
+
+fn exception(test: &str) -> JavaException {
+    match run::<()>(&[test]) {
+        Err(ExecuteError::Exception(exception)) => exception,
+        result => panic!("unexpected result {result:?}"),
+    }
+}
+
+/// A thread which sums the numbers up to `count`, counting it down to zero.
+const SUMMER: &str = "
+.class public Summer
+.super java/lang/Thread
+
+.field public count I
+.field public sum I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    aload 0
+    iload 1
+    putfield Summer count I
+    ret_void
+.end method
+
+.method public run ()V
+    iconst 0
+    istore 1
+Loop:
+    aload 0
+    getfield Summer count I
+    dup
+    if_le Done
+    iload 1
+    iadd
+    istore 1
+    aload 0
+    dup
+    getfield Summer count I
+    iconst -1
+    iadd
+    putfield Summer count I
+    goto Loop
+Done:
+    pop
+    aload 0
+    iload 1
+    putfield Summer sum I
+    ret_void
+.end method
+";
+
+#[test]
+fn joining_a_thread_waits_for_its_result() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()I
+    new Summer
+    dup
+    sipush 30000
+    invokespecial Summer <init> (I)V
+    astore 0
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    aload 0
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    aload 0
+    getfield Summer sum I
+    ireturn
+.end method
+";
+    assert_eq!(run::<i32>(&[SUMMER, test]).unwrap(), 30000 * 30001 / 2);
+}
+
+#[test]
+fn sleeping_threads_wake_in_order_of_their_deadlines() {
+    // Appends its digit to `Test.order` once it has slept.
+    let napper = "
+.class public Napper
+.super java/lang/Thread
+
+.field public digit I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    aload 0
+    iload 1
+    putfield Napper digit I
+    ret_void
+.end method
+
+.method public run ()V
+    aload 0
+    getfield Napper digit I
+    i2l
+    lconst 20
+    lmul
+    invokestatic java/lang/Thread sleep (J)V
+    getstatic Test order I
+    bipush 10
+    imul
+    aload 0
+    getfield Napper digit I
+    iadd
+    putstatic Test order I
+    ret_void
+.end method
+";
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.field public static order I
+
+.method public static start (I)LNapper;
+    new Napper
+    dup
+    iload 0
+    invokespecial Napper <init> (I)V
+    dup
+    invokevirtual java/lang/Thread start0 ()V
+    areturn
+.end method
+
+.method public static run ()I
+    iconst 3
+    invokestatic Test start (I)LNapper;
+    iconst 1
+    invokestatic Test start (I)LNapper;
+    iconst 2
+    invokestatic Test start (I)LNapper;
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    getstatic Test order I
+    ireturn
+.end method
+";
+    assert_eq!(run::<i32>(&[napper, test]).unwrap(), 123);
+}
+
+#[test]
+fn running_threads_are_interleaved() {
+    // Each summer runs for several time slices. Once the first is done, the
+    // second, which counts from further, has started but not finished.
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()I
+    new Summer
+    dup
+    iconst 100000
+    invokespecial Summer <init> (I)V
+    astore 0
+    new Summer
+    dup
+    iconst 300000
+    invokespecial Summer <init> (I)V
+    astore 1
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    aload 1
+    invokevirtual java/lang/Thread start0 ()V
+Wait:
+    aload 0
+    getfield Summer sum I
+    if_eq Wait
+    aload 1
+    getfield Summer count I
+    ireturn
+.end method
+";
+    let remaining = run::<i32>(&[SUMMER, test]).unwrap();
+    assert!(0 < remaining && remaining < 300000, "{remaining}");
+}
+
+/// A thread which adds one to `Test.count` `times` times through a
+/// synchronized method.
+const INCREMENTER: &str = "
+.class public Incrementer
+.super java/lang/Thread
+
+.field public times I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    aload 0
+    iload 1
+    putfield Incrementer times I
+    ret_void
+.end method
+
+.method public run ()V
+    aload 0
+    getfield Incrementer times I
+    istore 1
+Loop:
+    iload 1
+    if_le Done
+    invokestatic Incrementer increment ()V
+    iinc 1 -1
+    goto Loop
+Done:
+    ret_void
+.end method
+
+; The call between reading and writing the count lets the thread be preempted
+; there, which would lose updates if the method were not synchronized.
+.method public static synchronized increment ()V
+    getstatic Test count I
+    invokestatic Incrementer identity (I)I
+    iconst 1
+    iadd
+    putstatic Test count I
+    ret_void
+.end method
+
+.method public static identity (I)I
+    iload 0
+    ireturn
+.end method
+";
+
+#[test]
+fn synchronized_increments_are_not_lost() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.field public static count I
+
+.method public static start ()LIncrementer;
+    new Incrementer
+    dup
+    sipush 20000
+    invokespecial Incrementer <init> (I)V
+    dup
+    invokevirtual java/lang/Thread start0 ()V
+    areturn
+.end method
+
+.method public static run ()I
+    invokestatic Test start ()LIncrementer;
+    invokestatic Test start ()LIncrementer;
+    invokestatic Test start ()LIncrementer;
+    invokestatic Test start ()LIncrementer;
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    getstatic Test count I
+    ireturn
+.end method
+";
+    assert_eq!(run::<i32>(&[INCREMENTER, test]).unwrap(), 4 * 20000);
+}
+
+#[test]
+fn waiting_consumers_receive_every_item() {
+    // Takes `count` items from the one-item buffer of `Test`, waiting until
+    // each is put, and sums them.
+    let consumer = "
+.class public Consumer
+.super java/lang/Thread
+
+.field public count I
+.field public sum I
+
+.method public <init> (I)V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    aload 0
+    iload 1
+    putfield Consumer count I
+    ret_void
+.end method
+
+.method public run ()V
+    getstatic Test lock Ljava/lang/Object;
+    monitorenter
+Next:
+    aload 0
+    getfield Consumer count I
+    if_le Done
+Wait:
+    getstatic Test full I
+    if_ne Take
+    getstatic Test lock Ljava/lang/Object;
+    lconst 0
+    invokevirtual java/lang/Object wait (J)V
+    goto Wait
+Take:
+    aload 0
+    dup
+    getfield Consumer sum I
+    getstatic Test item I
+    iadd
+    putfield Consumer sum I
+    iconst 0
+    putstatic Test full I
+    getstatic Test lock Ljava/lang/Object;
+    invokevirtual java/lang/Object notify ()V
+    aload 0
+    dup
+    getfield Consumer count I
+    iconst -1
+    iadd
+    putfield Consumer count I
+    goto Next
+Done:
+    getstatic Test lock Ljava/lang/Object;
+    monitorexit
+    ret_void
+.end method
+";
+    // Puts the items from 1 to 1000 into the buffer, waiting until each has
+    // been taken.
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.field public static lock Ljava/lang/Object;
+.field public static item I
+.field public static full I
+
+.method public static run ()I
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    putstatic Test lock Ljava/lang/Object;
+    new Consumer
+    dup
+    sipush 1000
+    invokespecial Consumer <init> (I)V
+    astore 0
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    iconst 1
+    istore 1
+    getstatic Test lock Ljava/lang/Object;
+    monitorenter
+Next:
+    iload 1
+    sipush 1000
+    if_icmp_gt Done
+Wait:
+    getstatic Test full I
+    if_eq Put
+    getstatic Test lock Ljava/lang/Object;
+    lconst 0
+    invokevirtual java/lang/Object wait (J)V
+    goto Wait
+Put:
+    iload 1
+    putstatic Test item I
+    iconst 1
+    putstatic Test full I
+    getstatic Test lock Ljava/lang/Object;
+    invokevirtual java/lang/Object notify ()V
+    iinc 1 1
+    goto Next
+Done:
+    getstatic Test lock Ljava/lang/Object;
+    monitorexit
+    aload 0
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    aload 0
+    getfield Consumer sum I
+    ireturn
+.end method
+";
+    assert_eq!(run::<i32>(&[consumer, test]).unwrap(), 1000 * 1001 / 2);
+}
+
+#[test]
+fn monitors_are_reentered_by_their_owner() {
+    // Enters the monitor of `Test.lock` and sets `Test.entered`.
+    let locker = "
+.class public Locker
+.super java/lang/Thread
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Thread <init> ()V
+    ret_void
+.end method
+
+.method public run ()V
+    getstatic Test lock LTest;
+    monitorenter
+    iconst 1
+    putstatic Test entered I
+    getstatic Test lock LTest;
+    monitorexit
+    ret_void
+.end method
+";
+    // Enters the monitor twice, then once for each call of the synchronized
+    // `nest`, and checks that the other thread can enter it once all have
+    // been exited.
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.field public static lock LTest;
+.field public static entered I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public synchronized nest (I)I
+    iload 1
+    if_le Done
+    aload 0
+    iload 1
+    iconst -1
+    iadd
+    invokevirtual Test nest (I)I
+    iconst 1
+    iadd
+    ireturn
+Done:
+    iconst 0
+    ireturn
+.end method
+
+.method public static run ()I
+    new Test
+    dup
+    invokespecial Test <init> ()V
+    putstatic Test lock LTest;
+    getstatic Test lock LTest;
+    monitorenter
+    getstatic Test lock LTest;
+    monitorenter
+    getstatic Test lock LTest;
+    iconst 5
+    invokevirtual Test nest (I)I
+    istore 0
+    getstatic Test lock LTest;
+    monitorexit
+    ; The other thread cannot enter the monitor while it is still held once.
+    new Locker
+    dup
+    invokespecial Locker <init> ()V
+    astore 1
+    aload 1
+    invokevirtual java/lang/Thread start0 ()V
+    lconst 1
+    invokestatic java/lang/Thread sleep (J)V
+    getstatic Test entered I
+    istore 2
+    getstatic Test lock LTest;
+    monitorexit
+    aload 1
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    iload 0
+    bipush 100
+    imul
+    iload 2
+    bipush 10
+    imul
+    iadd
+    getstatic Test entered I
+    iadd
+    ireturn
+.end method
+";
+    assert_eq!(run::<i32>(&[locker, test]).unwrap(), 501);
+}
+
+#[test]
+fn interrupting_a_sleeping_thread_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    new Sleeper
+    dup
+    invokespecial Sleeper <init> ()V
+    astore 0
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    ; Lets the sleeper start sleeping.
+    lconst 1
+    invokestatic java/lang/Thread sleep (J)V
+    aload 0
+    invokevirtual java/lang/Thread interrupt0 ()V
+    aload 0
+    lconst 0
+    invokevirtual java/lang/Thread join (J)V
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::Interrupted);
+}
+
+#[test]
+fn starting_a_thread_twice_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    new Sleeper
+    dup
+    invokespecial Sleeper <init> ()V
+    astore 0
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    aload 0
+    invokevirtual java/lang/Thread start0 ()V
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::IllegalThreadState);
+}
+
+#[test]
+fn sleeping_for_a_negative_time_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    lconst -1
+    invokestatic java/lang/Thread sleep (J)V
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::NegativeTimeout);
+}
+
+#[test]
+fn notifying_without_the_monitor_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    invokevirtual java/lang/Object notify ()V
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::IllegalMonitorState);
+}
+
+#[test]
+fn waiting_without_the_monitor_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    lconst 0
+    invokevirtual java/lang/Object wait (J)V
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::IllegalMonitorState);
+}
+
+#[test]
+fn exiting_a_monitor_not_entered_throws() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()V
+    new java/lang/Object
+    dup
+    invokespecial java/lang/Object <init> ()V
+    monitorexit
+    ret_void
+.end method
+";
+    assert_eq!(exception(test), JavaException::IllegalMonitorState);
+}
@@ -0,0 +1,622 @@
+//! Runs the same methods in the interpreter, the optimizing tier and the
+//! machine code, checking that every tier computes the same results.
+
+mod common;
+
+use std::fmt::Debug;
+
+use graphene_jvm::java_str;
+use graphene_jvm::string::JavaStr;
+use graphene_jvm::vm::convert::{FromJavaResult, ToJavaArgs};
+use graphene_jvm::vm::heap::Collector;
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const NODE: &str = "
+.class public Node
+.super java/lang/Object
+
+.field public value I
+.field public next LNode;
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+const TEST: &str = "
+.class public Test
+.super java/lang/Object
+
+.method public static div (II)I
+    iload 0
+    iload 1
+    idiv
+    ireturn
+.end method
+
+.method public static rem (II)I
+    iload 0
+    iload 1
+    irem
+    ireturn
+.end method
+
+.method public static ldiv (JJ)J
+    lload 0
+    lload 2
+    ldiv
+    lreturn
+.end method
+
+.method public static lrem (JJ)J
+    lload 0
+    lload 2
+    lrem
+    lreturn
+.end method
+
+.method public static shl (II)I
+    iload 0
+    iload 1
+    ishl
+    ireturn
+.end method
+
+.method public static shr (II)I
+    iload 0
+    iload 1
+    ishr
+    ireturn
+.end method
+
+.method public static ushr (II)I
+    iload 0
+    iload 1
+    iushr
+    ireturn
+.end method
+
+.method public static lshl (JI)J
+    lload 0
+    iload 2
+    lshl
+    lreturn
+.end method
+
+.method public static lshr (JI)J
+    lload 0
+    iload 2
+    lshr
+    lreturn
+.end method
+
+.method public static lushr (JI)J
+    lload 0
+    iload 2
+    lushr
+    lreturn
+.end method
+
+.method public static lcmp (JJ)I
+    lload 0
+    lload 2
+    lcmp
+    ireturn
+.end method
+
+.method public static fcmpl (FF)I
+    fload 0
+    fload 1
+    fcmp l
+    ireturn
+.end method
+
+.method public static fcmpg (FF)I
+    fload 0
+    fload 1
+    fcmp g
+    ireturn
+.end method
+
+.method public static dcmpl (DD)I
+    dload 0
+    dload 2
+    dcmp l
+    ireturn
+.end method
+
+.method public static dcmpg (DD)I
+    dload 0
+    dload 2
+    dcmp g
+    ireturn
+.end method
+
+.method public static fib (I)I
+    iload 0
+    iconst 2
+    if_icmp_ge Recurse
+    iload 0
+    ireturn
+Recurse:
+    iload 0
+    iconst 1
+    isub
+    invokestatic Test fib (I)I
+    iload 0
+    iconst 2
+    isub
+    invokestatic Test fib (I)I
+    iadd
+    ireturn
+.end method
+
+.method public static recurse (I)I
+    iload 0
+    iconst 1
+    iadd
+    invokestatic Test recurse (I)I
+    ireturn
+.end method
+
+.method public static allocate (I)I
+    aconst_null
+    astore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    iload 0
+    if_icmp_ge Sum
+    new Node
+    dup
+    invokespecial Node <init> ()V
+    astore 3
+    aload 3
+    iload 2
+    putfield Node value I
+    iload 2
+    bipush 15
+    iand
+    if_ne Next
+    aload 3
+    aload 1
+    putfield Node next LNode;
+    aload 3
+    astore 1
+Next:
+    iinc 2 1
+    goto Loop
+Sum:
+    iconst 0
+    istore 2
+SumLoop:
+    aload 1
+    ifnull Done
+    iload 2
+    aload 1
+    getfield Node value I
+    iadd
+    istore 2
+    aload 1
+    getfield Node next LNode;
+    astore 1
+    goto SumLoop
+Done:
+    iload 2
+    ireturn
+.end method
+
+.method public static bytes (I)I
+    iconst 1
+    newarray byte
+    dup
+    iconst 0
+    iload 0
+    bastore
+    iconst 0
+    baload
+    ireturn
+.end method
+
+.method public static booleans (I)I
+    iconst 1
+    newarray boolean
+    dup
+    iconst 0
+    iload 0
+    bastore
+    iconst 0
+    baload
+    ireturn
+.end method
+
+.method public static chars (I)I
+    iconst 1
+    newarray char
+    dup
+    iconst 0
+    iload 0
+    castore
+    iconst 0
+    caload
+    ireturn
+.end method
+
+.method public static shorts (I)I
+    iconst 1
+    newarray short
+    dup
+    iconst 0
+    iload 0
+    sastore
+    iconst 0
+    saload
+    ireturn
+.end method
+
+.method public static element (I)I
+    iconst 3
+    newarray int
+    iload 0
+    iaload
+    ireturn
+.end method
+
+.method public static chunks (I)I
+    iload 0
+    bipush 15
+    iadd
+    iconst 4
+    ishr
+    anewarray [I
+    astore 1
+    iconst 0
+    istore 2
+Loop:
+    iload 2
+    iload 0
+    if_icmp_ge Sum
+    bipush 8
+    newarray int
+    astore 3
+    aload 3
+    iconst 7
+    iload 2
+    iastore
+    iload 2
+    bipush 15
+    iand
+    if_ne Next
+    aload 1
+    iload 2
+    iconst 4
+    ishr
+    aload 3
+    aastore
+Next:
+    iinc 2 1
+    goto Loop
+Sum:
+    iconst 0
+    istore 2
+    iconst 0
+    istore 4
+SumLoop:
+    iload 4
+    aload 1
+    arraylength
+    if_icmp_ge Done
+    iload 2
+    aload 1
+    iload 4
+    aaload
+    iconst 7
+    iaload
+    iadd
+    istore 2
+    iinc 4 1
+    goto SumLoop
+Done:
+    iload 2
+    ireturn
+.end method
+";
+
+fn classes() -> ClassManager {
+    common::load_assembly(&[NODE, TEST])
+}
+
+/// Returns the options of each tier: the interpreter, the optimizing tier and,
+/// with the `jit` feature, the machine code. Methods are compiled as soon as
+/// they are called.
+fn tiers(heap_size: usize, collector: Collector) -> Vec<(&'static str, ExecuteOptions)> {
+    let options = ExecuteOptions {
+        compile_threshold: 1,
+        heap_size,
+        collector,
+        ..ExecuteOptions::default()
+    };
+    vec![
+        (
+            "interpreter",
+            ExecuteOptions {
+                tiered: false,
+                ..options
+            },
+        ),
+        (
+            "optimizing",
+            ExecuteOptions {
+                #[cfg(feature = "jit")]
+                jit: false,
+                ..options
+            },
+        ),
+        #[cfg(feature = "jit")]
+        ("jit", options),
+    ]
+}
+
+/// Calls `name` with each of `cases` in every tier, and checks that every tier
+/// returns what the interpreter returns, which is returned.
+fn agree<A, R>(name: &JavaStr, cases: &[A]) -> Vec<R>
+where
+    A: ToJavaArgs + Copy,
+    R: FromJavaResult + PartialEq + Debug,
+{
+    let classes = classes();
+    let mut expected = None;
+    for (tier, options) in tiers(ExecuteOptions::default().heap_size, Collector::MarkSweep) {
+        let mut vm = Vm::new(&classes, options);
+        // Every case runs twice, so that the second call runs compiled code.
+        let results = cases
+            .iter()
+            .chain(cases)
+            .map(|&args| vm.call(java_str!("Test"), name, args).unwrap())
+            .collect::<Vec<R>>();
+        let (first, second) = results.split_at(cases.len());
+        assert_eq!(first, second, "{name} differs between calls in the {tier}");
+        match &expected {
+            None => expected = Some(results),
+            Some(expected) => assert_eq!(&results, expected, "{name} differs in the {tier}"),
+        }
+    }
+    let mut results = expected.unwrap();
+    results.truncate(cases.len());
+    results
+}
+
+#[test]
+fn tiers_agree_on_division() {
+    let cases = [
+        (7, 2),
+        (-7, 2),
+        (7, -2),
+        (i32::MIN, -1),
+        (i32::MIN, 1),
+        (0, 5),
+    ];
+    let div: Vec<i32> = agree(java_str!("div"), &cases);
+    assert_eq!(div, [3, -3, -3, i32::MIN, i32::MIN, 0]);
+    let rem: Vec<i32> = agree(java_str!("rem"), &cases);
+    assert_eq!(rem, [1, -1, 1, 0, 0, 0]);
+
+    let cases = [(7i64, 2i64), (-7, 2), (i64::MIN, -1), (i64::MIN, 1 << 40)];
+    let ldiv: Vec<i64> = agree(java_str!("ldiv"), &cases);
+    assert_eq!(ldiv, [3, -3, i64::MIN, -(1 << 23)]);
+    let lrem: Vec<i64> = agree(java_str!("lrem"), &cases);
+    assert_eq!(lrem, [1, -1, 0, 0]);
+}
+
+#[test]
+fn division_by_zero_throws_in_every_tier() {
+    let classes = classes();
+    let thrown = ExecuteError::Exception(JavaException::DivideByZero);
+    for (tier, options) in tiers(ExecuteOptions::default().heap_size, Collector::MarkSweep) {
+        let mut vm = Vm::new(&classes, options);
+        // The first calls compile the methods, whose code the second ones run.
+        for name in [java_str!("div"), java_str!("rem")] {
+            let result = vm.call::<_, i32>(java_str!("Test"), name, (7, 7));
+            assert!(result.is_ok(), "{name} in the {tier}");
+            let result = vm.call::<_, i32>(java_str!("Test"), name, (7, 0));
+            assert_eq!(result, Err(thrown.clone()), "{name} in the {tier}");
+        }
+        for name in [java_str!("ldiv"), java_str!("lrem")] {
+            let result = vm.call::<_, i64>(java_str!("Test"), name, (7i64, 7i64));
+            assert!(result.is_ok(), "{name} in the {tier}");
+            let result = vm.call::<_, i64>(java_str!("Test"), name, (7i64, 0i64));
+            assert_eq!(result, Err(thrown.clone()), "{name} in the {tier}");
+        }
+    }
+}
+
+#[test]
+fn tiers_agree_on_shifts() {
+    let cases = [(1, 31), (1, 32), (1, 33), (-8, 1), (-8, -1), (i32::MIN, 63)];
+    let shl: Vec<i32> = agree(java_str!("shl"), &cases);
+    assert_eq!(shl, [i32::MIN, 1, 2, -16, 0, 0]);
+    let shr: Vec<i32> = agree(java_str!("shr"), &cases);
+    assert_eq!(shr, [0, 1, 0, -4, -1, -1]);
+    let ushr: Vec<i32> = agree(java_str!("ushr"), &cases);
+    assert_eq!(ushr, [0, 1, 0, 0x7fff_fffc, 1, 1]);
+
+    let cases = [
+        (1i64, 63),
+        (1, 64),
+        (1, 65),
+        (-8, 1),
+        (-8, -1),
+        (i64::MIN, 127),
+    ];
+    let lshl: Vec<i64> = agree(java_str!("lshl"), &cases);
+    assert_eq!(lshl, [i64::MIN, 1, 2, -16, 0, 0]);
+    let lshr: Vec<i64> = agree(java_str!("lshr"), &cases);
+    assert_eq!(lshr, [0, 1, 0, -4, -1, -1]);
+    let lushr: Vec<i64> = agree(java_str!("lushr"), &cases);
+    assert_eq!(lushr, [0, 1, 0, 0x7fff_ffff_ffff_fffc, 1, 1]);
+}
+
+#[test]
+fn tiers_agree_on_comparisons() {
+    let cases = [(1i64, 2i64), (2, 2), (3, 2), (i64::MIN, i64::MAX)];
+    let lcmp: Vec<i32> = agree(java_str!("lcmp"), &cases);
+    assert_eq!(lcmp, [-1, 0, 1, -1]);
+
+    let nan = f32::NAN;
+    let cases = [
+        (1.0f32, 2.0f32),
+        (2.0, 2.0),
+        (3.0, 2.0),
+        (nan, 1.0),
+        (1.0, nan),
+        (-0.0, 0.0),
+    ];
+    let fcmpl: Vec<i32> = agree(java_str!("fcmpl"), &cases);
+    assert_eq!(fcmpl, [-1, 0, 1, -1, -1, 0]);
+    let fcmpg: Vec<i32> = agree(java_str!("fcmpg"), &cases);
+    assert_eq!(fcmpg, [-1, 0, 1, 1, 1, 0]);
+
+    let nan = f64::NAN;
+    let cases = [
+        (1.0f64, 2.0f64),
+        (2.0, 2.0),
+        (3.0, 2.0),
+        (nan, 1.0),
+        (1.0, nan),
+        (-0.0, 0.0),
+    ];
+    let dcmpl: Vec<i32> = agree(java_str!("dcmpl"), &cases);
+    assert_eq!(dcmpl, [-1, 0, 1, -1, -1, 0]);
+    let dcmpg: Vec<i32> = agree(java_str!("dcmpg"), &cases);
+    assert_eq!(dcmpg, [-1, 0, 1, 1, 1, 0]);
+}
+
+#[test]
+fn tiers_agree_on_calls() {
+    let fib: Vec<i32> = agree(java_str!("fib"), &[(0,), (1,), (10,), (20,)]);
+    assert_eq!(fib, [0, 1, 55, 6765]);
+}
+
+#[test]
+fn objects_survive_collections_during_compiled_code() {
+    let classes = classes();
+    let collectors = [
+        Collector::MarkSweep,
+        Collector::Generational {
+            nursery_size: 16 << 10,
+        },
+    ];
+    // Every sixteenth node is kept in a list, while the others become garbage.
+    let count = 100_000;
+    let expected = (0..count).step_by(16).sum::<i32>();
+    for collector in collectors {
+        for (tier, options) in tiers(1 << 20, collector) {
+            let mut vm = Vm::new(&classes, options);
+            let sum: i32 = vm
+                .call(java_str!("Test"), java_str!("allocate"), (count,))
+                .unwrap();
+            assert_eq!(sum, expected, "{collector:?} in the {tier}");
+            let stats = vm.heap().stats();
+            assert!(
+                stats.young_collections + stats.full_collections > 0,
+                "no collection with {collector:?} in the {tier}"
+            );
+        }
+    }
+}
+
+#[test]
+fn tiers_agree_on_narrow_elements() {
+    let cases = [(0,), (1,), (200,), (-129,), (70_000,)];
+    let bytes: Vec<i32> = agree(java_str!("bytes"), &cases);
+    assert_eq!(bytes, [0, 1, -56, 127, 112]);
+    let booleans: Vec<i32> = agree(java_str!("booleans"), &cases);
+    assert_eq!(booleans, [0, 1, 0, 1, 0]);
+    let chars: Vec<i32> = agree(java_str!("chars"), &cases);
+    assert_eq!(chars, [0, 1, 200, 65_407, 4_464]);
+    let shorts: Vec<i32> = agree(java_str!("shorts"), &cases);
+    assert_eq!(shorts, [0, 1, 200, -129, 4_464]);
+}
+
+#[test]
+fn indices_out_of_bounds_throw_in_every_tier() {
+    let classes = classes();
+    for (tier, options) in tiers(ExecuteOptions::default().heap_size, Collector::MarkSweep) {
+        let mut vm = Vm::new(&classes, options);
+        for index in [-1, 3] {
+            let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("element"), (index,));
+            let exception = JavaException::ArrayIndexOutOfBounds { index, length: 3 };
+            assert_eq!(result, Err(exception.into()), "in the {tier}");
+        }
+        let result: Result<i32, _> = vm.call(java_str!("Test"), java_str!("element"), (2,));
+        assert_eq!(result, Ok(0), "in the {tier}");
+    }
+}
+
+#[test]
+fn arrays_survive_collections_during_compiled_code() {
+    let classes = classes();
+    let collectors = [
+        Collector::MarkSweep,
+        Collector::Generational {
+            nursery_size: 16 << 10,
+        },
+    ];
+    // Every sixteenth array is kept in an array of arrays.
+    let count = 50_000;
+    let expected = (0..count).step_by(16).sum::<i32>();
+    for collector in collectors {
+        for (tier, options) in tiers(1 << 20, collector) {
+            let mut vm = Vm::new(&classes, options);
+            let sum: i32 = vm
+                .call(java_str!("Test"), java_str!("chunks"), (count,))
+                .unwrap();
+            assert_eq!(sum, expected, "{collector:?} in the {tier}");
+            let stats = vm.heap().stats();
+            assert!(
+                stats.young_collections + stats.full_collections > 0,
+                "no collection with {collector:?} in the {tier}"
+            );
+        }
+    }
+}
+
+#[test]
+fn code_accessing_locals_beyond_the_frame_is_not_run() {
+    let test = "
+.class public Test
+.super java/lang/Object
+
+.method public static run ()I
+    .limit stack 1
+    .limit locals 1
+    iconst 5
+    istore 60000
+    iconst 0
+    ireturn
+.end method
+";
+    let classes = common::load_assembly(&[test]);
+    for (tier, options) in tiers(ExecuteOptions::default().heap_size, Collector::MarkSweep) {
+        let mut vm = Vm::new(&classes, options);
+        let result = vm.call::<_, i32>(java_str!("Test"), java_str!("run"), ());
+        assert!(result.is_err(), "ran in the {tier}");
+    }
+}
+
+#[test]
+fn unbounded_recursion_overflows_the_stack_in_every_tier() {
+    let classes = classes();
+    for (tier, options) in tiers(ExecuteOptions::default().heap_size, Collector::MarkSweep) {
+        let mut vm = Vm::new(&classes, options);
+        let result = vm.call::<_, i32>(java_str!("Test"), java_str!("recurse"), (0,));
+        assert_eq!(
+            result,
+            Err(ExecuteError::Exception(JavaException::StackOverflow)),
+            "in the {tier}"
+        );
+    }
+}
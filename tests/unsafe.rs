@@ -0,0 +1,240 @@
+//! Runs classes which access fields through `sun.misc.Unsafe`, at the offsets
+//! it finds for their `java.lang.reflect.Field` objects.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::value::ValueType;
+use graphene_jvm::vm::{ExecuteError, ExecuteOptions, JavaException, Vm};
+
+const CLASS: &str = "
+.class public final java/lang/Class
+.super java/lang/Object
+";
+
+const FIELD: &str = "
+.class public final java/lang/reflect/Field
+.super java/lang/Object
+
+.field private clazz Ljava/lang/Class;
+.field private slot I
+
+.method public <init> (Ljava/lang/Class;I)V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    aload 0
+    aload 1
+    putfield java/lang/reflect/Field clazz Ljava/lang/Class;
+    aload 0
+    iload 2
+    putfield java/lang/reflect/Field slot I
+    ret_void
+.end method
+";
+
+const UNSAFE: &str = "
+.class public final sun/misc/Unsafe
+.super java/lang/Object
+
+.field private static theUnsafe Lsun/misc/Unsafe;
+
+.method private <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method static <clinit> ()V
+    new sun/misc/Unsafe
+    dup
+    invokespecial sun/misc/Unsafe <init> ()V
+    putstatic sun/misc/Unsafe theUnsafe Lsun/misc/Unsafe;
+    ret_void
+.end method
+
+.method public static native getUnsafe ()Lsun/misc/Unsafe;
+.end method
+
+.method public native objectFieldOffset (Ljava/lang/reflect/Field;)J
+.end method
+
+.method public native arrayBaseOffset (Ljava/lang/Class;)I
+.end method
+
+.method public native arrayIndexScale (Ljava/lang/Class;)I
+.end method
+
+.method public native compareAndSwapInt (Ljava/lang/Object;JII)Z
+.end method
+";
+
+const SYSTEM: &str = "
+.class public final java/lang/System
+.super java/lang/Object
+
+.method public static native gc ()V
+.end method
+";
+
+/// `count` is the second field `Counter` declares, and its only instance field,
+/// so it has offset zero.
+const COUNTER: &str = "
+.class public Counter
+.super java/lang/Object
+
+.field public static total I
+.field public count I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+/// Returns the offset `Unsafe` finds for the field of `Counter` declared at
+/// the index it is passed.
+const OFFSET: &str = "
+.method public static offset (I)J
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    new java/lang/reflect/Field
+    dup
+    ldc class Counter
+    iload 0
+    invokespecial java/lang/reflect/Field <init> (Ljava/lang/Class;I)V
+    invokevirtual sun/misc/Unsafe objectFieldOffset (Ljava/lang/reflect/Field;)J
+    lreturn
+.end method
+";
+
+/// Assembles `methods` into a class `Test` and calls its static method `run`
+/// with no arguments.
+fn run(methods: &str) -> Result<i64, ExecuteError> {
+    let test = format!(".class public Test\n.super java/lang/Object\n{OFFSET}{methods}");
+    let classes = common::load_assembly(&[CLASS, FIELD, UNSAFE, SYSTEM, COUNTER, &test]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    vm.call(java_str!("Test"), java_str!("run"), ())
+}
+
+#[test]
+fn fields_are_swapped_at_the_offsets_of_their_field_objects() {
+    // Returns the offset, whether the second and the first swap succeeded and
+    // the count, as digits.
+    let methods = "
+.method public static run ()J
+    iconst 1
+    invokestatic Test offset (I)J
+    lstore 0
+    new Counter
+    dup
+    invokespecial Counter <init> ()V
+    astore 2
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    aload 2
+    lload 0
+    iconst 0
+    iconst 5
+    invokevirtual sun/misc/Unsafe compareAndSwapInt (Ljava/lang/Object;JII)Z
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    aload 2
+    lload 0
+    iconst 0
+    iconst 7
+    invokevirtual sun/misc/Unsafe compareAndSwapInt (Ljava/lang/Object;JII)Z
+    iconst 10
+    imul
+    iadd
+    iconst 10
+    imul
+    aload 2
+    getfield Counter count I
+    iadd
+    i2l
+    lload 0
+    sipush 1000
+    i2l
+    lmul
+    ladd
+    lreturn
+.end method
+";
+    assert_eq!(run(methods), Ok(15));
+}
+
+#[test]
+fn class_objects_are_unique_and_survive_collections() {
+    let methods = "
+.method public static run ()J
+    ldc class Counter
+    invokestatic java/lang/System gc ()V
+    ldc class Counter
+    if_acmp_eq Same
+    lconst 0
+    lreturn
+Same:
+    lconst 1
+    lreturn
+.end method
+";
+    assert_eq!(run(methods), Ok(1));
+}
+
+#[test]
+fn arrays_start_at_offset_zero_with_one_slot_per_element() {
+    let methods = "
+.method public static run ()J
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    ldc class Counter
+    invokevirtual sun/misc/Unsafe arrayBaseOffset (Ljava/lang/Class;)I
+    iconst 10
+    imul
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    ldc class Counter
+    invokevirtual sun/misc/Unsafe arrayIndexScale (Ljava/lang/Class;)I
+    iadd
+    i2l
+    lreturn
+.end method
+";
+    assert_eq!(run(methods), Ok(1));
+}
+
+#[test]
+fn offsets_of_static_fields_are_rejected() {
+    let methods = "
+.method public static run ()J
+    iconst 0
+    invokestatic Test offset (I)J
+    lreturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::Exception(JavaException::StaticFieldOffset))
+    );
+}
+
+#[test]
+fn accesses_beyond_the_fields_are_errors() {
+    let methods = "
+.method public static run ()J
+    invokestatic sun/misc/Unsafe getUnsafe ()Lsun/misc/Unsafe;
+    new Counter
+    dup
+    invokespecial Counter <init> ()V
+    lconst 7
+    iconst 0
+    iconst 1
+    invokevirtual sun/misc/Unsafe compareAndSwapInt (Ljava/lang/Object;JII)Z
+    i2l
+    lreturn
+.end method
+";
+    assert_eq!(
+        run(methods),
+        Err(ExecuteError::UnsafeAccess {
+            offset: 7,
+            value_type: ValueType::Int,
+        })
+    );
+}
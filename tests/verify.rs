@@ -0,0 +1,225 @@
+//! Checks that classes whose code would read values as the wrong type are
+//! rejected before they run.
+
+mod common;
+
+use graphene_jvm::java_str;
+use graphene_jvm::vm::class::{assemble, write, ParseError};
+use graphene_jvm::vm::value::Value;
+use graphene_jvm::vm::{ClassManager, ExecuteError, ExecuteOptions, LinkageError, Vm};
+
+const ANIMAL: &str = "
+.class public Animal
+.super java/lang/Object
+
+.field public legs I
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+
+.method public static legs (LAnimal;)I
+    aload 0
+    getfield Animal legs I
+    ireturn
+.end method
+";
+
+const ROCK: &str = "
+.class public Rock
+.super java/lang/Object
+
+.field public weight Ljava/lang/Object;
+
+.method public <init> ()V
+    aload 0
+    invokespecial java/lang/Object <init> ()V
+    ret_void
+.end method
+";
+
+/// Assembles `methods` into a class `Test` and calls its static method `run`
+/// with no arguments, returning the offset and message of the error.
+fn verify_error(methods: &str) -> (u32, &'static str) {
+    let test = format!(".class public Test\n.super java/lang/Object\n{methods}");
+    let classes = common::load_assembly(&[ANIMAL, ROCK, &test]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    match vm.call::<_, i32>(java_str!("Test"), java_str!("run"), ()) {
+        Err(ExecuteError::Linkage(LinkageError::Verify {
+            offset, message, ..
+        })) => (offset, message),
+        result => panic!("unexpected result {result:?}"),
+    }
+}
+
+/// Assembles `methods` into a class `Test`, replaces the bytes `from` in its
+/// class file by `to` and returns the error of loading it.
+fn load_error(methods: &str, from: &[u8], to: &[u8]) -> ParseError {
+    let test = format!(".class public Test\n.super java/lang/Object\n{methods}");
+    let mut bytes = write(&assemble(&test).unwrap()).unwrap();
+    let start = bytes
+        .windows(from.len())
+        .position(|window| window == from)
+        .unwrap();
+    bytes[start..start + from.len()].copy_from_slice(to);
+    ClassManager::new().load(&bytes).unwrap_err()
+}
+
+#[test]
+fn operands_of_the_wrong_type_are_rejected() {
+    let methods = "
+.method public static run ()I
+    aconst_null
+    iconst 1
+    iadd
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (2, "Bad type on operand stack"));
+}
+
+#[test]
+fn locals_of_the_wrong_type_are_rejected() {
+    let methods = "
+.method public static run ()I
+    .limit stack 1
+    .limit locals 1
+    iconst 5
+    istore 0
+    aload 0
+    pop
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (2, "Bad local variable type"));
+}
+
+#[test]
+fn locals_beyond_max_locals_are_rejected() {
+    let methods = "
+.method public static run ()I
+    .limit stack 1
+    .limit locals 1
+    iconst 5
+    istore 60000
+    iconst 0
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (1, "Illegal local variable number"));
+}
+
+#[test]
+fn stacks_beyond_max_stack_are_rejected() {
+    let methods = "
+.method public static run ()I
+    .limit stack 1
+    .limit locals 0
+    iconst 1
+    iconst 2
+    iadd
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (1, "Operand stack overflow"));
+}
+
+#[test]
+fn receivers_of_the_wrong_class_are_rejected() {
+    let methods = "
+.method public static run ()I
+    new Rock
+    dup
+    invokespecial Rock <init> ()V
+    getfield Animal legs I
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (7, "Bad type on operand stack"));
+}
+
+#[test]
+fn arguments_of_the_wrong_class_are_rejected() {
+    let methods = "
+.method public static run ()I
+    new Rock
+    dup
+    invokespecial Rock <init> ()V
+    invokestatic Animal legs (LAnimal;)I
+    ireturn
+.end method
+";
+    assert_eq!(verify_error(methods), (7, "Bad type on operand stack"));
+}
+
+#[test]
+fn host_arguments_of_the_wrong_class_are_rejected() {
+    let classes = common::load_assembly(&[ANIMAL, ROCK]);
+    let mut vm = Vm::new(&classes, ExecuteOptions::default());
+    let rock = vm
+        .new_object(java_str!("Rock"), java_str!("()V"), &[])
+        .unwrap();
+    let result = vm.invoke_static(
+        java_str!("Animal"),
+        java_str!("legs"),
+        java_str!("(LAnimal;)I"),
+        &[Value::Reference(Some(rock))],
+    );
+    assert!(matches!(result, Err(ExecuteError::IllegalArgument { .. })));
+
+    let animal = vm
+        .new_object(java_str!("Animal"), java_str!("()V"), &[])
+        .unwrap();
+    let result = vm.invoke_static(
+        java_str!("Animal"),
+        java_str!("legs"),
+        java_str!("(LAnimal;)I"),
+        &[Value::Reference(Some(animal))],
+    );
+    assert!(matches!(result, Ok(Some(Value::Int(0)))));
+}
+
+#[test]
+fn malformed_instructions_are_rejected_when_loaded() {
+    let methods = "
+.method public static run ()I
+    sipush 4660
+    ireturn
+.end method
+";
+    // `sipush 0x1234` becomes an unknown opcode followed by two bytes.
+    assert_eq!(
+        load_error(methods, &[0x11, 0x12, 0x34], &[0xFF, 0x12, 0x34]),
+        ParseError::InvalidInstruction
+    );
+    // A `sipush` whose operand is cut off by the end of the code.
+    assert_eq!(
+        load_error(
+            methods,
+            &[0x11, 0x12, 0x34, 0xAC],
+            &[0xAC, 0xAC, 0xAC, 0x11]
+        ),
+        ParseError::UnexpectedEndOfFile
+    );
+
+    let methods = "
+.method public static run (Ljava/lang/Runnable;)I
+    aload 0
+    invokeinterface java/lang/Runnable run ()V
+    iconst 0
+    ireturn
+.end method
+";
+    // The byte after the count of `invokeinterface` must be zero.
+    assert_eq!(
+        load_error(
+            methods,
+            &[0x01, 0x00, 0x03, 0xAC],
+            &[0x01, 0x07, 0x03, 0xAC]
+        ),
+        ParseError::InvalidInstruction
+    );
+}